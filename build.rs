@@ -0,0 +1,89 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Passes description of build returned by `version()` to compiler: crate version, git commit
+/// and enabled Cargo features.
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // Optional dependencies enable implicit features of the same name, which aren't listed.
+    let manifest = std::fs::read_to_string("Cargo.toml").unwrap_or_default();
+    let declared: Vec<_> = manifest
+        .lines()
+        .skip_while(|line| line.trim() != "[features]")
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| {
+            line.split_once('=')
+                .map(|(name, _)| name.trim().to_string())
+        })
+        .collect();
+    let mut features: Vec<_> = std::env::vars()
+        .filter_map(|(name, _)| name.strip_prefix("CARGO_FEATURE_").map(str::to_string))
+        .map(|feature| feature.to_lowercase().replace('_', "-"))
+        .filter(|feature| declared.contains(feature))
+        .collect();
+    features.sort();
+    let features = match features.is_empty() {
+        true => "none".to_string(),
+        false => features.join(", "),
+    };
+
+    let version = std::env::var("CARGO_PKG_VERSION").unwrap_or_default();
+    let build = format!("{} (git {}, features: {})", version, hash, features);
+    println!("cargo:rustc-env=IMAGE_SL_BUILD={}", build);
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    // WebAssembly hosts call functions of `functions_ex()` table through indirect function table
+    // of module, which isn't exported by default.
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("wasm32") {
+        println!("cargo:rustc-cdylib-link-arg=--export-table");
+    }
+
+    if std::env::var_os("CARGO_FEATURE_C_HEADER").is_some() {
+        write_c_header();
+    }
+}
+
+/// Generates `image_sl.h` with `cbindgen` next to built library, e.g. `target/release`, so C and
+/// C++ hosts include declarations matching the sources instead of maintaining their own. Without
+/// `cbindgen` executable the header is skipped with warning, so library still builds.
+fn write_c_header() {
+    // Output directory of build script is `<profile dir>/build/<package>-<hash>/out`.
+    let out_dir = std::env::var("OUT_DIR").unwrap_or_default();
+    let profile_dir = Path::new(&out_dir)
+        .ancestors()
+        .nth(3)
+        .expect("OUT_DIR is inside profile directory");
+    let header = profile_dir.join("image_sl.h");
+
+    let status = Command::new("cbindgen")
+        .args(["--config", "cbindgen.toml", "--crate", "image_sl"])
+        .arg("--output")
+        .arg(&header)
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => panic!("cbindgen failed to generate C header: {}", status),
+        Err(e) => {
+            println!(
+                "cargo:warning=C header isn't generated: `c-header` feature requires cbindgen \
+                 (`cargo install cbindgen`): {}",
+                e
+            );
+            return;
+        }
+    }
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=src");
+}
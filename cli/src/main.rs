@@ -0,0 +1,277 @@
+//! Command-line front end of image_sl. Every subcommand calls library functions through
+//! `image_sl_client`, so running it is also a quick check of library build.
+
+use image_sl_client::{Batch, ColorType, ImageFactory};
+use std::error::Error;
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Usage: image_sl_cli [--lib PATH] COMMAND ARGS...
+
+Commands:
+    open INPUT                          print size and color type of image
+    blur INPUT OUTPUT SIGMA             blur image by gaussian of SIGMA pixels
+    resize INPUT OUTPUT WIDTH HEIGHT    resize image by Lanczos filter
+    convert INPUT OUTPUT [COLOR_TYPE]   save image in format of OUTPUT extension, converted to
+                                        COLOR_TYPE if given, e.g. rgba8
+    batch [--pipeline FILE] [--ext EXT] OUT_DIR INPUT...
+                                        apply edit stack of FILE to inputs and save them to
+                                        OUT_DIR, in format of EXT if given
+
+Options:
+    --lib PATH    load shared library at PATH instead of searching for it";
+
+/// Names of color types accepted by `convert`.
+const COLOR_TYPES: [(&str, ColorType); 10] = [
+    ("l8", ColorType::L8),
+    ("la8", ColorType::La8),
+    ("rgb8", ColorType::Rgb8),
+    ("rgba8", ColorType::Rgba8),
+    ("l16", ColorType::L16),
+    ("la16", ColorType::La16),
+    ("rgb16", ColorType::Rgb16),
+    ("rgba16", ColorType::Rgba16),
+    ("bgr8", ColorType::Bgr8),
+    ("bgra8", ColorType::Bgra8),
+];
+
+/// Subcommand with its arguments.
+enum Command {
+    Open(PathBuf),
+    Blur {
+        input: PathBuf,
+        output: PathBuf,
+        sigma: f32,
+    },
+    Resize {
+        input: PathBuf,
+        output: PathBuf,
+        width: u32,
+        height: u32,
+    },
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        color_type: Option<ColorType>,
+    },
+    Batch {
+        pipeline: Option<PathBuf>,
+        extension: Option<String>,
+        out_dir: PathBuf,
+        inputs: Vec<PathBuf>,
+    },
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (lib, command) = match parse(&args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let result = factory(lib).and_then(|factory| run(&factory, command));
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Parses command-line `args` without executable name into path of shared library given by
+/// `--lib` and subcommand.
+fn parse(args: &[String]) -> Result<(Option<PathBuf>, Command), String> {
+    let mut args = args.iter().map(String::as_str).peekable();
+    let mut lib = None;
+    if args.peek() == Some(&"--lib") {
+        args.next();
+        lib = Some(PathBuf::from(args.next().ok_or("Missing path of --lib")?));
+    }
+    let name = args.next().ok_or("Missing command")?;
+    let mut args: Vec<&str> = args.collect();
+
+    let command = match (name, args.as_slice()) {
+        ("open", [input]) => Command::Open(input.into()),
+        ("blur", [input, output, sigma]) => Command::Blur {
+            input: input.into(),
+            output: output.into(),
+            sigma: number(sigma, "sigma")?,
+        },
+        ("resize", [input, output, width, height]) => Command::Resize {
+            input: input.into(),
+            output: output.into(),
+            width: number(width, "width")?,
+            height: number(height, "height")?,
+        },
+        ("convert", [input, output, rest @ ..]) if rest.len() <= 1 => Command::Convert {
+            input: input.into(),
+            output: output.into(),
+            color_type: rest.first().map(|name| color_type(name)).transpose()?,
+        },
+        ("batch", _) => {
+            let pipeline = option(&mut args, "--pipeline")?.map(PathBuf::from);
+            let extension = option(&mut args, "--ext")?.map(str::to_string);
+            match args.split_first() {
+                Some((out_dir, inputs)) if !inputs.is_empty() => Command::Batch {
+                    pipeline,
+                    extension,
+                    out_dir: out_dir.into(),
+                    inputs: inputs.iter().map(PathBuf::from).collect(),
+                },
+                _ => return Err("Missing output directory or inputs of batch".into()),
+            }
+        }
+        ("open" | "blur" | "resize" | "convert", _) => {
+            return Err(format!("Wrong number of arguments of {}", name))
+        }
+        _ => return Err(format!("Unknown command: {}", name)),
+    };
+    Ok((lib, command))
+}
+
+/// Removes option `name` with its value from `args` and returns the value, `None` if there is no
+/// such option.
+fn option<'a>(args: &mut Vec<&'a str>, name: &str) -> Result<Option<&'a str>, String> {
+    let index = match args.iter().position(|&arg| arg == name) {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+    if index + 1 >= args.len() {
+        return Err(format!("Missing value of {}", name));
+    }
+    let value = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(value))
+}
+
+/// Parses `value` of argument `name`.
+fn number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {}: {}", name, value))
+}
+
+/// Parses name of color type, e.g. `rgba8`.
+fn color_type(name: &str) -> Result<ColorType, String> {
+    let name = name.to_ascii_lowercase();
+    COLOR_TYPES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|&(_, color_type)| color_type)
+        .ok_or_else(|| format!("Unknown color type: {}", name))
+}
+
+/// Loads shared library at `lib` or the first one found at `lib_search_paths`.
+#[cfg(not(feature = "static-link"))]
+fn factory(lib: Option<PathBuf>) -> Result<ImageFactory, Box<dyn Error>> {
+    let factory = match lib {
+        Some(path) => ImageFactory::with_path(path)?,
+        None => ImageFactory::new()?,
+    };
+    Ok(factory)
+}
+
+/// Uses library linked into executable, which can't load another one from `lib`.
+#[cfg(feature = "static-link")]
+fn factory(lib: Option<PathBuf>) -> Result<ImageFactory, Box<dyn Error>> {
+    use image_sl_client::{Exports, FunctionsV2, ImageError, InitConfig, Version};
+    use std::os::raw::c_char;
+
+    // Links library statically, so its exported functions are resolved by linker.
+    use image_sl as _;
+
+    extern "C" {
+        fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+        fn version() -> Version;
+        fn image_sl_init(config: *const InitConfig) -> ImageError;
+        fn image_sl_shutdown();
+        fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+    }
+
+    if lib.is_some() {
+        return Err("--lib isn't supported by statically linked build".into());
+    }
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    Ok(unsafe { ImageFactory::from_exports(exports)? })
+}
+
+/// Runs `command` with library of `factory`.
+fn run(factory: &ImageFactory, command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Open(input) => {
+            let info = factory.open_image(&input)?.info()?;
+            let color_type = match info.color_type {
+                Some(color_type) => format!("{:?}", color_type),
+                None => "unknown color type".to_string(),
+            };
+            println!(
+                "{}: {}x{} {}, {} bits per channel",
+                input.display(),
+                info.width,
+                info.height,
+                color_type,
+                info.bit_depth
+            );
+        }
+        Command::Blur {
+            input,
+            output,
+            sigma,
+        } => factory.open_image(input)?.blur(sigma)?.save(output)?,
+        Command::Resize {
+            input,
+            output,
+            width,
+            height,
+        } => {
+            let image = factory.open_image(input)?;
+            image
+                .resize_with_progress(width, height, |_| true)?
+                .save(output)?
+        }
+        Command::Convert {
+            input,
+            output,
+            color_type,
+        } => {
+            let image = factory.open_image(input)?;
+            match color_type {
+                Some(color_type) => image.convert(color_type)?.save(output)?,
+                None => image.save(output)?,
+            }
+        }
+        Command::Batch {
+            pipeline,
+            extension,
+            out_dir,
+            inputs,
+        } => {
+            let pipeline = pipeline.map(std::fs::read_to_string).transpose()?;
+            let batch = Batch {
+                pipeline: pipeline.as_deref(),
+                extension: extension.as_deref(),
+                ..Default::default()
+            };
+            let results = factory.batch(&inputs, &out_dir, &batch, |_| true)?;
+            let failed: Vec<_> = inputs
+                .iter()
+                .zip(&results)
+                .filter_map(|(input, result)| Some((input, result.as_ref().err()?)))
+                .collect();
+            for (input, err) in &failed {
+                eprintln!("{}: {:?} error", input.display(), err);
+            }
+            if !failed.is_empty() {
+                let message = format!("{} of {} files failed", failed.len(), inputs.len());
+                return Err(message.into());
+            }
+        }
+    }
+    Ok(())
+}
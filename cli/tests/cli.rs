@@ -0,0 +1,95 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Runs executable with shared library Cargo builds next to tests and `args`. Statically linked
+/// executable uses library linked into it.
+fn cli(args: &[&Path]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_image_sl_cli"));
+    if !cfg!(feature = "static-link") {
+        // Cargo builds shared library of `image_sl` dev-dependency next to tests.
+        let exe = std::env::current_exe().unwrap();
+        let file_name = match std::env::consts::OS {
+            "windows" => "image_sl.dll",
+            "macos" => "libimage_sl.dylib",
+            _ => "libimage_sl.so",
+        };
+        command.arg("--lib").arg(exe.with_file_name(file_name));
+    }
+    command.args(args).output().unwrap()
+}
+
+fn logo() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../data/logo.jpg")
+}
+
+#[test]
+fn commands_process_image() {
+    let dir = std::env::temp_dir().join(format!("image_sl_cli_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let logo = logo();
+    let path = |name: &str| dir.join(name);
+
+    let output = cli(&[Path::new("open"), &logo]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("1200x1200 Rgb8"), "{}", stdout);
+
+    let resized = path("resized.png");
+    let args = [
+        "resize".as_ref(),
+        logo.as_path(),
+        &resized,
+        "64".as_ref(),
+        "32".as_ref(),
+    ];
+    assert!(cli(&args).status.success());
+    let output = cli(&[Path::new("open"), &resized]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("64x32 Rgb8"));
+
+    let blurred = path("blurred.png");
+    let args = ["blur".as_ref(), resized.as_path(), &blurred, "2".as_ref()];
+    assert!(cli(&args).status.success());
+
+    let converted = path("converted.png");
+    let args = [
+        "convert".as_ref(),
+        blurred.as_path(),
+        &converted,
+        "l16".as_ref(),
+    ];
+    assert!(cli(&args).status.success());
+    let output = cli(&[Path::new("open"), &converted]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("64x32 L16"));
+
+    let pipeline = path("mirror.json");
+    let edits = r#"{"version": 1, "edits": [{"op": "mirror"}]}"#;
+    std::fs::write(&pipeline, edits).unwrap();
+    let out_dir = path("batch");
+    let args = [
+        "batch".as_ref(),
+        "--pipeline".as_ref(),
+        pipeline.as_path(),
+        "--ext".as_ref(),
+        "bmp".as_ref(),
+        &out_dir,
+        &resized,
+        &blurred,
+    ];
+    assert!(cli(&args).status.success());
+    assert!(out_dir.join("resized.bmp").exists());
+    assert!(out_dir.join("blurred.bmp").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failures_are_reported() {
+    let missing = Path::new("missing.png");
+    let output = cli(&[Path::new("open"), missing]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error:"));
+
+    let output = cli(&[Path::new("blur"), &logo()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage:"));
+}
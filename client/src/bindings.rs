@@ -0,0 +1,1892 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::ffi::c_void;
+use std::fmt::{Display, Formatter};
+use std::os::raw::c_char;
+
+/// Declares `#[repr(u32)]` enum of library and implements `TryFrom<u32>` converting raw values
+/// passed by library. Values added by newer library fail with `UnknownValue`.
+macro_rules! raw_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident $(= $value:literal)?,)+
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(u32)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant $(= $value)?,)+
+        }
+
+        impl TryFrom<u32> for $name {
+            type Error = UnknownValue;
+
+            fn try_from(value: u32) -> Result<Self, UnknownValue> {
+                $(if value == Self::$variant as u32 {
+                    return Ok(Self::$variant);
+                })+
+                Err(UnknownValue(value))
+            }
+        }
+    };
+}
+
+/// Raw value of enum unknown to these bindings, e.g. one added by newer library.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnknownValue(pub u32);
+
+impl Display for UnknownValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown raw value {} of enum", self.0)
+    }
+}
+
+impl Error for UnknownValue {}
+
+/// File name of shared library.
+#[cfg(target_os = "linux")]
+pub const LIB_FILE_NAME: &str = "libimage_sl.so";
+
+/// File name of shared library.
+#[cfg(target_os = "macos")]
+pub const LIB_FILE_NAME: &str = "libimage_sl.dylib";
+
+/// File name of shared library.
+#[cfg(target_os = "windows")]
+pub const LIB_FILE_NAME: &str = "image_sl.dll";
+
+/// Incapsulate raw pointer to image.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct ImageHandle(*const c_void);
+
+impl ImageHandle {
+    /// Creates new null pointer.
+    /// # Safety
+    /// Null handle may only be passed to functions writing handle to it.
+    pub unsafe fn new_null() -> Self {
+        Self(std::ptr::null())
+    }
+
+    /// Returns address of image, e.g. to identify leaked image.
+    pub fn address(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Incapsulate raw pointer to layered document.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct DocumentHandle(*const c_void);
+
+impl DocumentHandle {
+    /// Creates new null pointer.
+    /// # Safety
+    /// Null handle may only be passed to functions writing handle to it.
+    pub unsafe fn new_null() -> Self {
+        Self(std::ptr::null())
+    }
+}
+
+/// Incapsulate raw pointer to export profile.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct ExportProfileHandle(*const c_void);
+
+impl ExportProfileHandle {
+    /// Creates new null pointer.
+    /// # Safety
+    /// Null handle may only be passed to functions writing handle to it.
+    pub unsafe fn new_null() -> Self {
+        Self(std::ptr::null())
+    }
+}
+
+/// Incapsulate raw pointer to job running on worker thread of library.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct JobHandle(*const c_void);
+
+impl JobHandle {
+    /// Creates new null pointer.
+    /// # Safety
+    /// Null handle may only be passed to functions writing handle to it.
+    pub unsafe fn new_null() -> Self {
+        Self(std::ptr::null())
+    }
+}
+
+/// Incapsulate raw pointer to job queue limiting its running jobs.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct QueueHandle(*const c_void);
+
+impl QueueHandle {
+    /// Creates new null pointer.
+    /// # Safety
+    /// Null handle may only be passed to functions writing handle to it.
+    pub unsafe fn new_null() -> Self {
+        Self(std::ptr::null())
+    }
+}
+
+/// Incapsulate raw pointer to cancellation token.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct TokenHandle(*const c_void);
+
+impl TokenHandle {
+    /// Creates new null pointer.
+    /// # Safety
+    /// Null handle may only be passed to functions writing handle to it.
+    pub unsafe fn new_null() -> Self {
+        Self(std::ptr::null())
+    }
+}
+
+/// Incapsulate raw pointer to operation pipeline.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct PipelineHandle(*const c_void);
+
+impl PipelineHandle {
+    /// Creates new null pointer.
+    /// # Safety
+    /// Null handle may only be passed to functions writing handle to it.
+    pub unsafe fn new_null() -> Self {
+        Self(std::ptr::null())
+    }
+}
+
+/// Contain pointer to null-terminated UTF-8 path.
+#[repr(transparent)]
+pub struct RawPath(pub *const c_char);
+
+/// Contain pointer to null-terminated UTF-8 string.
+#[repr(transparent)]
+pub struct RawStr(pub *const c_char);
+
+raw_enum! {
+    /// Error codes for image oprerations.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    #[allow(dead_code)]
+    pub enum ImageError {
+        NoError = 0,
+        Io,
+        Decoding,
+        Encoding,
+        Parameter,
+        Unsupported,
+        BufferTooSmall,
+        LimitExceeded,
+        NotFound,
+        Internal,
+        OutOfMemory,
+        InvalidHandle,
+        BudgetExceeded,
+        Cancelled,
+    }
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::NoError => write!(f, "image no error"),
+            ImageError::Io => write!(f, "image i/o error"),
+            ImageError::Decoding => write!(f, "image decoding error"),
+            ImageError::Encoding => write!(f, "image encoding error"),
+            ImageError::Parameter => write!(f, "image parameter error"),
+            ImageError::Unsupported => write!(f, "image unsupported error"),
+            ImageError::BufferTooSmall => write!(f, "image buffer too small error"),
+            ImageError::LimitExceeded => write!(f, "image limit exceeded error"),
+            ImageError::NotFound => write!(f, "image data not found error"),
+            ImageError::Internal => write!(f, "image internal error"),
+            ImageError::OutOfMemory => write!(f, "image out of memory error"),
+            ImageError::InvalidHandle => write!(f, "image invalid handle error"),
+            ImageError::BudgetExceeded => write!(f, "image operation budget exceeded error"),
+            ImageError::Cancelled => write!(f, "image operation cancelled error"),
+        }
+    }
+}
+
+raw_enum! {
+    /// Image formats available for encoding.
+    #[derive(Debug, Copy, Clone)]
+    pub enum ImageFormat {
+        Png = 0,
+        Jpeg,
+        Gif,
+        Ico,
+        Bmp,
+        Farbfeld,
+        Tga,
+        Pnm,
+        Tiff,
+        WebP,
+        Hdr,
+        Dds,
+    }
+}
+
+raw_enum! {
+    /// Refinements of error codes in `ErrorDetails`.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ErrorSubCode {
+        None = 0,
+        FileNotFound,
+        PermissionDenied,
+        UnexpectedEof,
+        UnsupportedFormat,
+        UnsupportedColor,
+        UnsupportedFeature,
+        DimensionMismatch,
+        DimensionTooLarge,
+        InsufficientMemory,
+        WrongThread,
+    }
+}
+
+impl ErrorSubCode {
+    /// Converts value written by library. Values unknown to this version are `None`.
+    pub fn from_raw(value: u32) -> Self {
+        Self::try_from(value).unwrap_or(Self::None)
+    }
+}
+
+/// Details of the last error. `size` field contain size of this struct.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ErrorDetails {
+    pub size: usize,
+    /// `ImageError` code of error.
+    pub code: u32,
+    /// `ErrorSubCode` refining `code`.
+    pub sub_code: u32,
+    /// `ImageFormat` value of format that caused error, or `u32::MAX` if unknown.
+    pub format: u32,
+    /// OS error number of I/O errors, or zero.
+    pub os_error: i32,
+    /// Null-terminated UTF-8 message. Valid until the next error on the same thread.
+    pub message: *const c_char,
+}
+
+impl Default for ErrorDetails {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            code: 0,
+            sub_code: 0,
+            format: u32::MAX,
+            os_error: 0,
+            message: std::ptr::null(),
+        }
+    }
+}
+
+raw_enum! {
+    /// Color types of images.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ColorType {
+        L8 = 0,
+        La8,
+        Rgb8,
+        Rgba8,
+        L16,
+        La16,
+        Rgb16,
+        Rgba16,
+        Bgr8,
+        Bgra8,
+        /// RGBA with `float` channels, only for pixels copied out of images.
+        Rgba32F,
+    }
+}
+
+impl ColorType {
+    /// Converts raw value of `ImageInfo`, `None` for color types unknown to these bindings.
+    pub fn from_raw(value: u32) -> Option<Self> {
+        Self::try_from(value).ok()
+    }
+}
+
+/// Size, color type and bit depth of image. `size` field contain size of this struct.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ImageInfo {
+    pub size: usize,
+    pub width: u32,
+    pub height: u32,
+    /// `ColorType` value.
+    pub color_type: u32,
+    /// Bits per channel.
+    pub bit_depth: u32,
+    /// Number of channels including alpha.
+    pub channels: u32,
+    pub has_alpha: bool,
+}
+
+impl Default for ImageInfo {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            width: 0,
+            height: 0,
+            color_type: 0,
+            bit_depth: 0,
+            channels: 0,
+            has_alpha: false,
+        }
+    }
+}
+
+/// Statistics of image in `[0, 1]` units of channel range. `size` field contain size of this
+/// struct.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Stats {
+    pub size: usize,
+    /// Number of channels including alpha, entries of per-channel arrays past it are zero.
+    pub channels: u32,
+    /// Per-channel values in order of color type of image.
+    pub min: [f64; 4],
+    pub max: [f64; 4],
+    pub mean: [f64; 4],
+    pub std_dev: [f64; 4],
+    /// Mean Rec. 709 luma of pixels, ignoring alpha.
+    pub luminance: f64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            channels: 0,
+            min: [0.0; 4],
+            max: [0.0; 4],
+            mean: [0.0; 4],
+            std_dev: [0.0; 4],
+            luminance: 0.0,
+        }
+    }
+}
+
+/// Pixels of image locked by `LockPixelsFn`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct PixelLock {
+    /// The first byte of the top row, 16-bit channels are in native byte order.
+    pub pixels: *mut u8,
+    /// Distance between starts of adjacent rows in bytes.
+    pub stride: usize,
+    pub width: u32,
+    pub height: u32,
+    /// `ColorType` value.
+    pub format: u32,
+}
+
+/// Required for `Error` wrapping `ImageError` as its source.
+impl Error for ImageError {}
+
+/// Load functions block
+pub type FunctionsFn = unsafe extern "C" fn() -> Functions;
+
+/// ABI version of `FunctionsV2` these bindings are written for.
+pub const ABI_VERSION: u32 = 59;
+
+/// Table of functions of negotiated ABI version.
+#[repr(C)]
+pub struct FunctionsV2 {
+    pub abi_version: u32,
+    pub size: usize,
+    pub functions: Functions,
+}
+
+/// Load functions table of requested ABI version
+pub type FunctionsExFn = unsafe extern "C" fn(u32, *mut FunctionsV2) -> ImageError;
+
+/// Version of library.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Static null-terminated description of build with git commit and enabled features.
+    pub build: *const c_char,
+}
+
+/// Load library version
+pub type VersionFn = unsafe extern "C" fn() -> Version;
+
+/// Global settings of library. `size` field contain size of this struct.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct InitConfig {
+    pub size: usize,
+    /// Maximum number of worker threads of blur and resize, `0` means number of CPUs, `1` runs
+    /// them on calling thread.
+    pub thread_count: u32,
+    /// Limits of decoded images. Zero means no limit.
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_memory: u64,
+    pub log_callback: Option<LogFn>,
+    pub log_user_data: *mut c_void,
+    pub debug_handles: bool,
+    /// File trace of the last operations is dumped to when library catches panic. Null means no
+    /// dumps.
+    pub trace_path: *const c_char,
+}
+
+impl Default for InitConfig {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            thread_count: 0,
+            max_width: 0,
+            max_height: 0,
+            max_memory: 0,
+            log_callback: None,
+            log_user_data: std::ptr::null_mut(),
+            debug_handles: false,
+            trace_path: std::ptr::null(),
+        }
+    }
+}
+
+/// Applies global settings of library
+pub type InitFn = unsafe extern "C" fn(*const InitConfig) -> ImageError;
+/// Releases global resources of library before unloading
+pub type ShutdownFn = unsafe extern "C" fn();
+/// Writes trace of the last operations of library to file
+pub type DumpTraceFn = unsafe extern "C" fn(*const c_char) -> ImageError;
+
+/// Exported functions of library, e.g. resolved by linker when library is linked statically.
+#[derive(Copy, Clone)]
+pub struct Exports {
+    pub functions_ex: FunctionsExFn,
+    pub version: VersionFn,
+    pub init: InitFn,
+    pub shutdown: ShutdownFn,
+    pub dump_trace: DumpTraceFn,
+}
+
+raw_enum! {
+    /// Layouts of raw pixel data. Rows are tightly packed, 16-bit channels are in native byte order.
+    #[derive(Debug, Copy, Clone)]
+    pub enum RawPixelFormat {
+        Rgba8 = 0,
+        Rgb8,
+        L8,
+        Rgba16,
+    }
+}
+
+impl RawPixelFormat {
+    /// Size of one pixel in bytes.
+    pub fn pixel_size(self) -> usize {
+        match self {
+            Self::Rgba8 => 4,
+            Self::Rgb8 => 3,
+            Self::L8 => 1,
+            Self::Rgba16 => 8,
+        }
+    }
+}
+
+raw_enum! {
+    /// Types of EXIF tag values.
+    #[derive(Debug, Copy, Clone)]
+    pub enum ExifType {
+        Ascii = 0,
+        Byte,
+        Short,
+        Long,
+        Rational,
+        SRational,
+        Undefined,
+    }
+}
+
+raw_enum! {
+    /// Packed framebuffer formats of e-paper displays.
+    #[derive(Debug, Copy, Clone)]
+    pub enum EinkFormat {
+        /// 1 bit per pixel, set bit is white.
+        Mono = 0,
+        /// 4 bits per pixel gray, `0` is black.
+        Gray4,
+        /// 4 bits per pixel indices of 7-color ACeP panel palette.
+        Acep7,
+    }
+}
+
+raw_enum! {
+    /// Pixel layouts of packed display framebuffers.
+    #[derive(Debug, Copy, Clone)]
+    pub enum PixelLayout {
+        /// 16-bit little-endian `RRRRRGGGGGGBBBBB` pixels.
+        Rgb565 = 0,
+        /// 8-bit `RRRGGGBB` pixels.
+        Rgb332,
+        /// 24-bit pixels with blue byte first.
+        Bgr888,
+    }
+}
+
+raw_enum! {
+    /// Order of rows in packed framebuffer.
+    #[derive(Debug, Copy, Clone)]
+    pub enum RowOrder {
+        TopDown = 0,
+        BottomUp,
+    }
+}
+
+raw_enum! {
+    /// Platforms of icon sets.
+    #[derive(Debug, Copy, Clone)]
+    pub enum IconSetKind {
+        /// `favicon.ico` with PNG favicons and Apple touch icon.
+        Favicon = 0,
+        /// App icon set of iOS asset catalog with its `Contents.json`.
+        Ios,
+        /// Launcher icons of Android density buckets and Play Store icon.
+        Android,
+        /// Icons of web app manifest with `manifest.json` listing them.
+        Pwa,
+    }
+}
+
+raw_enum! {
+    /// Arrangements of social media card.
+    #[derive(Debug, Copy, Clone)]
+    pub enum CardLayout {
+        /// Background covers card with gradient scrim at the bottom, title at bottom left next to
+        /// avatar.
+        Bottom = 0,
+        /// Background covers card under uniform scrim, avatar and title centered.
+        Center,
+        /// Left half is solid panel of opaque scrim color with avatar and title, background covers
+        /// right half.
+        Split,
+    }
+}
+
+raw_enum! {
+    /// Kinds of transforms estimated by alignment.
+    #[derive(Debug, Copy, Clone)]
+    pub enum Motion {
+        /// Shift estimated by phase correlation.
+        Translation = 0,
+        /// Affine transform estimated by enhanced correlation coefficient maximization.
+        Affine,
+    }
+}
+
+raw_enum! {
+    /// Keypoint detectors.
+    #[derive(Debug, Copy, Clone)]
+    pub enum Detector {
+        /// FAST-9 corners without orientation and descriptors.
+        Fast = 0,
+        /// FAST-9 corners with orientation and rotated BRIEF descriptors.
+        Orb,
+    }
+}
+
+raw_enum! {
+    /// Ways to search template in image.
+    #[derive(Debug, Copy, Clone)]
+    pub enum MatchMethod {
+        /// Template as is.
+        Ncc = 0,
+        /// Template scaled in `[0.5, 2]`.
+        MultiScale,
+        /// Template rotated in 15 degree steps.
+        Rotated,
+        /// Template both scaled and rotated.
+        MultiScaleRotated,
+    }
+}
+
+raw_enum! {
+    /// Kinds of image operations.
+    #[derive(Debug, Copy, Clone)]
+    pub enum OperationKind {
+        Blur = 0,
+        Mirror,
+        Resize,
+    }
+}
+
+raw_enum! {
+    /// Color bands of HSL adjustment.
+    #[derive(Debug, Copy, Clone)]
+    pub enum ColorBand {
+        Red = 0,
+        Orange,
+        Yellow,
+        Green,
+        Aqua,
+        Blue,
+        Purple,
+        Magenta,
+    }
+}
+
+raw_enum! {
+    /// Film stocks emulated by `film_emulation`.
+    #[derive(Debug, Copy, Clone)]
+    pub enum FilmStock {
+        Portra400 = 0,
+        Ektar100,
+        Velvia50,
+        Gold200,
+        Superia400,
+        CineStill800T,
+        TriX400,
+        Hp5Plus,
+    }
+}
+
+raw_enum! {
+    /// Blend modes of document layers.
+    #[derive(Debug, Copy, Clone)]
+    pub enum BlendMode {
+        Normal = 0,
+        Multiply,
+        Screen,
+        Overlay,
+        Darken,
+        Lighten,
+        Add,
+        Difference,
+    }
+}
+
+raw_enum! {
+    /// Algorithms of perceptual hashes.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum HashAlgorithm {
+        /// Average hash, bits of 8x8 thumbnail brighter than its mean.
+        Average = 0,
+        /// Difference hash, gradients between neighbours of 9x8 thumbnail.
+        Difference,
+        /// Perceptual hash of low DCT frequencies, the most robust to scaling and compression.
+        Perceptual,
+    }
+}
+
+raw_enum! {
+    /// Kinds of gradients.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum GradientKind {
+        /// Gradient along direction of angle, clockwise from left to right.
+        Linear = 0,
+        /// Gradient from center of image to its corners.
+        Radial,
+    }
+}
+
+raw_enum! {
+    /// Kinds of generated noise.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum NoiseKind {
+        /// Random values at lattice points interpolated between them.
+        Value = 0,
+        /// Random gradients at lattice points.
+        Perlin,
+        /// Random gradients at corners of triangular lattice, with fewer axis-aligned artifacts.
+        Simplex,
+    }
+}
+
+raw_enum! {
+    /// Positions of watermark on image.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Anchor {
+        TopLeft = 0,
+        Top,
+        TopRight,
+        Left,
+        Center,
+        Right,
+        BottomLeft,
+        Bottom,
+        BottomRight,
+    }
+}
+
+raw_enum! {
+    /// Metrics of similarity of images.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum CompareMetric {
+        /// Peak signal-to-noise ratio of RGB channels in decibels, infinite for identical images.
+        Psnr = 0,
+        /// Mean structural similarity index of luma, 1 for identical images.
+        Ssim,
+    }
+}
+
+/// Placement and compositing properties of document layer. `size` field contain size of this
+/// struct.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LayerProps {
+    pub size: usize,
+    /// Offset of top left corner of layer image on canvas.
+    pub x: i32,
+    pub y: i32,
+    /// Layer opacity in `[0, 1]`.
+    pub opacity: f32,
+    /// One of `BlendMode` values.
+    pub blend_mode: u32,
+    pub visible: bool,
+}
+
+impl Default for LayerProps {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            x: 0,
+            y: 0,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal as u32,
+            visible: true,
+        }
+    }
+}
+
+raw_enum! {
+    /// Progress of job.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum JobStatus {
+        Running = 0,
+        Finished,
+        Failed,
+    }
+}
+
+raw_enum! {
+    /// Severity levels of log messages.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum LogLevel {
+        Error = 0,
+        Warning,
+        Info,
+        Debug,
+    }
+}
+
+impl LogLevel {
+    /// Converts value passed by library. Values unknown to this version are `Debug`.
+    pub fn from_raw(value: u32) -> Self {
+        Self::try_from(value).unwrap_or(Self::Debug)
+    }
+}
+
+/// Color stop of gradient.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GradientStop {
+    /// Position of stop along gradient in `[0, 1]`.
+    pub position: f32,
+    /// Color at stop as `0xRRGGBBAA`.
+    pub color: u32,
+}
+
+impl GradientStop {
+    /// Creates stop of `rgba` color at `position`.
+    pub fn new(position: f32, rgba: [u8; 4]) -> Self {
+        Self {
+            position,
+            color: u32::from_be_bytes(rgba),
+        }
+    }
+}
+
+/// Hue, saturation and luminance adjustment of one color band.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct HslBand {
+    /// One of `ColorBand` values.
+    pub band: u32,
+    /// Hue shift in degrees in `[-180, 180]`.
+    pub hue: f32,
+    /// Relative saturation change in `[-1, 1]`.
+    pub saturation: f32,
+    /// Luminance change in `[-1, 1]`.
+    pub luminance: f32,
+}
+
+/// Point in image pixel coordinates, where `(0, 0)` is top-left corner of image and
+/// `(width, height)` is bottom-right one.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Rectangle in image pixel coordinates.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Placement of template found in image. Rectangle bounds template scaled by `scale` and rotated
+/// clockwise by `angle` degrees.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MatchResult {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    pub angle: f32,
+    /// Normalized cross-correlation in `(0, 1]`.
+    pub score: f32,
+}
+
+/// Affine transform mapping point `(x, y)` of reference image to point
+/// `(m[0] * x + m[1] * y + m[2], m[3] * x + m[4] * y + m[5])` of moving image.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Transform {
+    pub matrix: [f32; 6],
+    /// Quality of alignment, in `[0, 1]` for aligned images.
+    pub score: f32,
+}
+
+/// Corner detected in image.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Keypoint {
+    pub x: f32,
+    pub y: f32,
+    /// Strength of corner.
+    pub response: f32,
+    /// Orientation in radians of ORB keypoint.
+    pub angle: f32,
+    /// Binary descriptor of ORB keypoint.
+    pub descriptor: [u8; 32],
+}
+
+/// Keypoint of the first image matched to keypoint of the second one.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct KeypointMatch {
+    pub ax: f32,
+    pub ay: f32,
+    pub bx: f32,
+    pub by: f32,
+    /// Hamming distance between descriptors.
+    pub distance: u32,
+    /// Whether match agrees with estimated offset.
+    pub inlier: bool,
+}
+
+/// Keypoint matches owned by library with estimated offset between images.
+#[repr(C)]
+pub struct Matches {
+    pub matches: *mut KeypointMatch,
+    pub count: usize,
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub inliers: usize,
+}
+
+/// Description of image operation. Fields unused by operation `kind` are ignored.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Operation {
+    pub kind: u32,
+    pub sigma: f32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Operation {
+    /// Gaussian blur with `sigma` radius.
+    pub fn blur(sigma: f32) -> Self {
+        Self {
+            kind: OperationKind::Blur as u32,
+            sigma,
+            width: 0,
+            height: 0,
+        }
+    }
+
+    /// Horizontal flip.
+    pub fn mirror() -> Self {
+        Self {
+            kind: OperationKind::Mirror as u32,
+            sigma: 0.,
+            width: 0,
+            height: 0,
+        }
+    }
+
+    /// Resize to exactly `width` x `height`.
+    pub fn resize(width: u32, height: u32) -> Self {
+        Self {
+            kind: OperationKind::Resize as u32,
+            sigma: 0.,
+            width,
+            height,
+        }
+    }
+}
+
+/// Reads up to `len` bytes from host stream
+pub type ReadFn = unsafe extern "C" fn(*mut c_void, *mut u8, usize) -> isize;
+/// Seeks in host stream
+pub type SeekFn = unsafe extern "C" fn(*mut c_void, i64, i32) -> i64;
+
+/// Writes `len` bytes to host stream
+pub type WriteFn = unsafe extern "C" fn(*mut c_void, *const u8, usize) -> isize;
+
+/// Receives percent of work done, returns `false` to abort operation
+pub type ProgressFn = unsafe extern "C" fn(f32, *mut c_void) -> bool;
+
+/// Receives log message of `len` bytes with its level
+pub type LogFn = unsafe extern "C" fn(u32, *const c_char, usize, *mut c_void);
+
+/// Receives live image handle and backtrace of its creation of `len` bytes
+pub type LeakFn = unsafe extern "C" fn(ImageHandle, *const c_char, usize, *mut c_void);
+
+/// Checks whether leading bytes of stream are in format of codec plugin
+pub type ProbeFn = unsafe extern "C" fn(*const u8, usize, *mut c_void) -> bool;
+
+/// Decodes image of codec plugin format into new image handle
+pub type DecodeFn =
+    unsafe extern "C" fn(*const u8, usize, *mut c_void, *mut ImageHandle) -> ImageError;
+
+/// Encodes RGBA8 pixels in codec plugin format passing encoded bytes to write callback
+pub type EncodeFn =
+    unsafe extern "C" fn(*const u8, u32, u32, WriteFn, *mut c_void, *mut c_void) -> ImageError;
+
+/// Receives coordinates and `0xRRGGBBAA` color of pixel, which it may change
+pub type PixelFn = unsafe extern "C" fn(u32, u32, *mut u32, *mut c_void);
+
+/// Receives index and bytes of row, returns `false` to stop
+pub type RowFn = unsafe extern "C" fn(u32, *const u8, usize, *mut c_void) -> bool;
+
+/// Encoding options. `size` field contain size of this struct.
+#[repr(C)]
+pub struct SaveOptions {
+    pub size: usize,
+    /// JPEG quality in `1..=100` range.
+    pub jpeg_quality: u8,
+    /// Write metadata of source image (EXIF, PNG text) if output format supports it (JPEG, PNG).
+    pub keep_metadata: bool,
+    /// Embed EXIF thumbnail into JPEG output.
+    pub embed_thumbnail: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            jpeg_quality: 75,
+            keep_metadata: false,
+            embed_thumbnail: false,
+        }
+    }
+}
+
+/// Description of compositional guides drawn over image. `size` field contain size of this
+/// struct.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct GuideSpec {
+    pub size: usize,
+    /// Set of `GuideSpec::THIRDS`, `GuideSpec::CENTER_CROSS` and `GuideSpec::SAFE_AREA` flags.
+    pub guides: u32,
+    /// RGBA color of guides, blended over image.
+    pub color: [u8; 4],
+    /// Width of guide lines in pixels.
+    pub line_width: u32,
+    /// Margin of safe area as fraction of image width and height.
+    pub safe_margin: f32,
+}
+
+impl Default for GuideSpec {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            guides: Self::THIRDS,
+            color: [255, 255, 255, 160],
+            line_width: 1,
+            safe_margin: 0.1,
+        }
+    }
+}
+
+impl GuideSpec {
+    /// Rule-of-thirds grid.
+    pub const THIRDS: u32 = 1;
+    /// Cross at the center of image.
+    pub const CENTER_CROSS: u32 = 1 << 1;
+    /// Title-safe area frame.
+    pub const SAFE_AREA: u32 = 1 << 2;
+}
+
+/// Description of social media card. `size` field contain size of this struct.
+#[repr(C)]
+pub struct CardSpec {
+    pub size: usize,
+    /// Size of card in pixels. Zero width and height mean 1200 x 630.
+    pub width: u32,
+    pub height: u32,
+    /// One of `CardLayout` values.
+    pub layout: u32,
+    /// Title drawn with built-in font of printable ASCII characters. May be null.
+    pub title: RawStr,
+    /// Image drawn cropped to circle. May be null.
+    pub avatar: ImageHandle,
+    /// Image drawn at top right corner keeping its aspect ratio. May be null.
+    pub logo: ImageHandle,
+    /// RGBA color of title.
+    pub text_color: [u8; 4],
+    /// RGBA color of overlay keeping title readable over background.
+    pub scrim_color: [u8; 4],
+}
+
+impl Default for CardSpec {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            width: 0,
+            height: 0,
+            layout: CardLayout::Bottom as u32,
+            title: RawStr(std::ptr::null()),
+            avatar: unsafe { ImageHandle::new_null() },
+            logo: unsafe { ImageHandle::new_null() },
+            text_color: [255; 4],
+            scrim_color: [0, 0, 0, 180],
+        }
+    }
+}
+
+/// Decoding options. `size` field contain size of this struct.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct OpenOptions {
+    pub size: usize,
+    /// Maximum width of decoded image. Zero means no limit.
+    pub max_width: u32,
+    /// Maximum height of decoded image. Zero means no limit.
+    pub max_height: u32,
+    /// Maximum size of decoded pixels in bytes. Zero means no limit.
+    pub max_memory: u64,
+    /// Decode image as `format_hint` instead of detecting its format.
+    pub use_format_hint: bool,
+    pub format_hint: u32,
+    /// Apply EXIF orientation to decoded pixels.
+    pub auto_orient: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            max_width: 0,
+            max_height: 0,
+            max_memory: 0,
+            use_format_hint: false,
+            format_hint: 0,
+            auto_orient: false,
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Sets format hint.
+    pub fn with_format_hint(mut self, format: ImageFormat) -> Self {
+        self.use_format_hint = true;
+        self.format_hint = format as u32;
+        self
+    }
+}
+
+/// Limits of one call. `size` field contain size of this struct. Zero means no limit.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct OperationBudget {
+    pub size: usize,
+    /// Maximum run time of call in milliseconds.
+    pub max_millis: u64,
+    /// Maximum total size in bytes of pixel buffers allocated by call.
+    pub max_bytes: u64,
+}
+
+impl Default for OperationBudget {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            max_millis: 0,
+            max_bytes: 0,
+        }
+    }
+}
+
+/// Options of batch conversion. `size` field contain size of this struct.
+#[repr(C)]
+pub struct BatchOptions {
+    pub size: usize,
+    /// Extension of output files without dot, e.g. `"webp"`, selecting their format. Null keeps
+    /// extension of each source.
+    pub extension: RawStr,
+    /// JPEG quality of outputs in `1..=100` range.
+    pub jpeg_quality: u8,
+    /// Write metadata of sources (EXIF, PNG text) if output format supports it (JPEG, PNG).
+    pub keep_metadata: bool,
+    /// Callback receiving percent of processed files with its user data, may be called from
+    /// worker threads of library.
+    pub progress_fn: Option<ProgressFn>,
+    pub user_data: *mut c_void,
+    /// Cancellation token aborting batch, or null.
+    pub token: TokenHandle,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            extension: RawStr(std::ptr::null()),
+            jpeg_quality: 75,
+            keep_metadata: false,
+            progress_fn: None,
+            user_data: std::ptr::null_mut(),
+            token: unsafe { TokenHandle::new_null() },
+        }
+    }
+}
+
+/// Operating system file handle: file descriptor on Unix, `HANDLE` on Windows.
+#[cfg(unix)]
+pub type OsHandle = std::os::raw::c_int;
+/// Operating system file handle: file descriptor on Unix, `HANDLE` on Windows.
+#[cfg(windows)]
+pub type OsHandle = *mut c_void;
+
+/// Loads image from file
+pub type OpenImageFn = unsafe extern "C" fn(RawPath, *mut ImageHandle) -> ImageError;
+/// Loads image from file with options
+pub type OpenImageWithOptionsFn =
+    unsafe extern "C" fn(RawPath, *const OpenOptions, *mut ImageHandle) -> ImageError;
+/// Loads image from host-provided reader
+pub type OpenImageWithReaderFn =
+    unsafe extern "C" fn(ReadFn, Option<SeekFn>, *mut c_void, *mut ImageHandle) -> ImageError;
+/// Saves image to file
+pub type SaveImageFn = unsafe extern "C" fn(RawPath, ImageHandle) -> ImageError;
+/// Saves image to host-provided writer
+pub type SaveImageWithWriterFn =
+    unsafe extern "C" fn(WriteFn, *mut c_void, ImageHandle, u32, *const SaveOptions) -> ImageError;
+/// Loads image from OS file handle
+pub type OpenImageFdFn = unsafe extern "C" fn(OsHandle, *mut ImageHandle) -> ImageError;
+/// Saves image to OS file handle
+pub type SaveImageFdFn =
+    unsafe extern "C" fn(OsHandle, ImageHandle, u32, *const SaveOptions) -> ImageError;
+/// Loads image from base64 data URI
+pub type OpenImageDataUriFn = unsafe extern "C" fn(RawStr, *mut ImageHandle) -> ImageError;
+/// Encodes image into base64 data URI
+pub type EncodeImageDataUriFn =
+    unsafe extern "C" fn(ImageHandle, u32, *mut c_char, *mut usize) -> ImageError;
+/// Loads image from HTTP(S) URL
+pub type OpenImageUrlFn = unsafe extern "C" fn(RawStr, u32, usize, *mut ImageHandle) -> ImageError;
+/// Sets limits applied to every decoded image
+pub type SetLimitsFn = unsafe extern "C" fn(u32, u32, u64);
+/// Destroys image
+pub type DestroyImageFn = unsafe extern "C" fn(ImageHandle);
+
+/// Encodes image into caller-provided buffer
+pub type EncodeImageFn = unsafe extern "C" fn(ImageHandle, u32, *mut u8, *mut usize) -> ImageError;
+
+/// Decodes, transforms and encodes image from reader to writer. Deprecated: use
+/// `TranscodeStream2Fn`
+pub type TranscodeStreamFn =
+    unsafe extern "C" fn(ReadFn, WriteFn, *const Operation, usize, *mut c_void) -> ImageError;
+
+/// Creates image from raw pixel data
+pub type ImageFromRawFn =
+    unsafe extern "C" fn(*const u8, u32, u32, u32, *mut ImageHandle) -> ImageError;
+/// Packs image into e-paper framebuffer
+pub type PackEinkFn = unsafe extern "C" fn(ImageHandle, u32, *mut u8, *mut usize) -> ImageError;
+/// Packs image into display framebuffer
+pub type PackFramebufferFn =
+    unsafe extern "C" fn(ImageHandle, u32, u32, *mut u8, *mut usize) -> ImageError;
+
+/// Suggests crops of image
+pub type SuggestCropsFn =
+    unsafe extern "C" fn(ImageHandle, u32, u32, usize, *mut Rect, *mut usize) -> ImageError;
+
+/// Reads EXIF tag of image
+pub type ExifGetFn = unsafe extern "C" fn(ImageHandle, u16, *mut c_char, *mut usize) -> ImageError;
+
+/// Draws compositional guides over image
+pub type DrawGuidesFn = unsafe extern "C" fn(ImageHandle, *const GuideSpec) -> ImageError;
+
+/// Estimates noise of image channels
+pub type EstimateNoiseFn = unsafe extern "C" fn(ImageHandle, *mut f64) -> ImageError;
+
+/// Writes EXIF tag of image
+pub type ExifSetFn = unsafe extern "C" fn(ImageHandle, u16, u32, RawStr) -> ImageError;
+
+/// Applies EXIF orientation to image
+pub type AutoOrientFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+
+/// Denoises image with automatically picked strength
+pub type AutoDenoiseFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Sharpens image with automatically picked strength
+pub type AutoSharpenFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+
+/// Corrects lateral chromatic aberration
+pub type CorrectCaFn = unsafe extern "C" fn(ImageHandle, f32, f32) -> ImageError;
+/// Reads ICC profile of image
+pub type IccGetFn = unsafe extern "C" fn(ImageHandle, *mut u8, *mut usize) -> ImageError;
+/// Replaces ICC profile of image
+pub type IccSetFn = unsafe extern "C" fn(ImageHandle, *const u8, usize) -> ImageError;
+/// Converts image from its ICC profile to sRGB
+pub type ConvertToSrgbFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Removes purple fringes at high-contrast edges
+pub type DefringeFn = unsafe extern "C" fn(ImageHandle, f32, f32) -> ImageError;
+/// Reads physical resolution of image
+pub type GetDpiFn = unsafe extern "C" fn(ImageHandle, *mut f64, *mut f64) -> ImageError;
+/// Sets physical resolution of image
+pub type SetDpiFn = unsafe extern "C" fn(ImageHandle, f64, f64) -> ImageError;
+/// Reconstructs clipped highlights
+pub type RecoverHighlightsFn = unsafe extern "C" fn(ImageHandle, f32) -> ImageError;
+/// Lists keywords of PNG text of image
+pub type PngTextKeysFn = unsafe extern "C" fn(ImageHandle, *mut c_char, *mut usize) -> ImageError;
+/// Reads PNG text of image
+pub type PngTextGetFn =
+    unsafe extern "C" fn(ImageHandle, RawStr, *mut c_char, *mut usize) -> ImageError;
+/// Writes PNG text of image
+pub type PngTextSetFn = unsafe extern "C" fn(ImageHandle, RawStr, RawStr) -> ImageError;
+/// Adjusts shadows and highlights of image
+pub type ShadowsHighlightsFn = unsafe extern "C" fn(ImageHandle, f32, f32, f32) -> ImageError;
+/// Adjusts hue, saturation and luminance of color bands of image
+pub type HslAdjustFn = unsafe extern "C" fn(ImageHandle, *const HslBand, usize) -> ImageError;
+/// Reads XMP packet of image
+pub type XmpGetFn = unsafe extern "C" fn(ImageHandle, *mut c_char, *mut usize) -> ImageError;
+/// Reads IPTC dataset of image
+pub type IptcGetFn = unsafe extern "C" fn(ImageHandle, u32, *mut c_char, *mut usize) -> ImageError;
+/// Replaces color of image
+pub type ReplaceColorFn = unsafe extern "C" fn(ImageHandle, u32, u32, f32, bool) -> ImageError;
+/// Removes all metadata of image
+pub type StripMetadataFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Loads EXIF thumbnail of image file
+pub type ExifThumbnailFn = unsafe extern "C" fn(RawPath, *mut ImageHandle) -> ImageError;
+/// Grades shadows, midtones and highlights of image
+pub type SplitToneFn = unsafe extern "C" fn(ImageHandle, u32, u32, u32, f32) -> ImageError;
+/// Applies film stock look to image
+pub type FilmEmulationFn = unsafe extern "C" fn(ImageHandle, u32, f32) -> ImageError;
+/// Reads message of the last error
+pub type LastErrorMessageFn = unsafe extern "C" fn(*mut c_char, usize) -> usize;
+/// Reads details of the last error
+pub type LastErrorDetailsFn = unsafe extern "C" fn(*mut ErrorDetails) -> ImageError;
+/// Sets depth of edit journal of image
+pub type SetJournalDepthFn = unsafe extern "C" fn(ImageHandle, u32) -> ImageError;
+/// Reverts the last edit of image
+pub type UndoFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Reapplies the last undone edit of image
+pub type RedoFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Reads numbers of undo and redo steps of image
+pub type HistoryLenFn = unsafe extern "C" fn(ImageHandle, *mut usize, *mut usize) -> ImageError;
+/// Exports edit stack of image
+pub type ExportEditStackFn = unsafe extern "C" fn(ImageHandle, *mut *mut c_char) -> ImageError;
+/// Applies edit stack to image
+pub type ApplyEditStackFn = unsafe extern "C" fn(ImageHandle, RawStr) -> ImageError;
+/// Frees string allocated by library
+pub type FreeStringFn = unsafe extern "C" fn(*mut c_char);
+/// Sets log callback
+pub type SetLogCallbackFn = unsafe extern "C" fn(Option<LogFn>, *mut c_void);
+/// Attaches annotation to region of image
+pub type AddAnnotationFn = unsafe extern "C" fn(ImageHandle, Rect, RawStr) -> ImageError;
+/// Reads number of annotations of image
+pub type AnnotationCountFn = unsafe extern "C" fn(ImageHandle, *mut usize) -> ImageError;
+/// Reads annotation of image
+pub type GetAnnotationFn =
+    unsafe extern "C" fn(ImageHandle, usize, *mut Rect, *mut c_char, *mut usize) -> ImageError;
+/// Removes annotation of image
+pub type RemoveAnnotationFn = unsafe extern "C" fn(ImageHandle, usize) -> ImageError;
+/// Draws annotated regions over image
+pub type BurnAnnotationsFn = unsafe extern "C" fn(ImageHandle, u32, u32) -> ImageError;
+/// Creates empty layered document
+pub type DocNewFn = unsafe extern "C" fn(u32, u32, *mut DocumentHandle) -> ImageError;
+/// Destroys document
+pub type DocDestroyFn = unsafe extern "C" fn(DocumentHandle);
+/// Adds layer on top of document
+pub type DocAddLayerFn =
+    unsafe extern "C" fn(DocumentHandle, ImageHandle, *const LayerProps) -> ImageError;
+/// Reads number of layers of document
+pub type DocLayerCountFn = unsafe extern "C" fn(DocumentHandle, *mut usize) -> ImageError;
+/// Reads properties of document layer
+pub type DocGetLayerFn = unsafe extern "C" fn(DocumentHandle, usize, *mut LayerProps) -> ImageError;
+/// Sets properties of document layer
+pub type DocSetLayerFn =
+    unsafe extern "C" fn(DocumentHandle, usize, *const LayerProps) -> ImageError;
+/// Reads image of document layer
+pub type DocLayerImageFn =
+    unsafe extern "C" fn(DocumentHandle, usize, *mut ImageHandle) -> ImageError;
+/// Removes layer of document
+pub type DocRemoveLayerFn =
+    unsafe extern "C" fn(DocumentHandle, usize, *mut ImageHandle) -> ImageError;
+/// Moves layer of document
+pub type DocMoveLayerFn = unsafe extern "C" fn(DocumentHandle, usize, usize) -> ImageError;
+/// Composites layers of document into image
+pub type DocFlattenFn = unsafe extern "C" fn(DocumentHandle, *mut ImageHandle) -> ImageError;
+/// Checks image handle
+pub type IsValidFn = unsafe extern "C" fn(ImageHandle) -> bool;
+/// Opens OpenRaster document
+pub type DocOpenOraFn = unsafe extern "C" fn(RawPath, *mut DocumentHandle) -> ImageError;
+/// Saves document as OpenRaster
+pub type DocSaveOraFn = unsafe extern "C" fn(DocumentHandle, RawPath) -> ImageError;
+/// Opens layers of PSD file as document
+pub type DocOpenPsdFn = unsafe extern "C" fn(RawPath, *mut DocumentHandle) -> ImageError;
+/// Enables debug registry of image handles
+pub type SetDebugHandlesFn = unsafe extern "C" fn(bool);
+/// Counts live images
+pub type LiveHandleCountFn = unsafe extern "C" fn() -> usize;
+/// Lists live images
+pub type DumpLeaksFn = unsafe extern "C" fn(LeakFn, *mut c_void) -> usize;
+
+/// Blurs image into new image
+pub type BlurImage2Fn = unsafe extern "C" fn(ImageHandle, f32, *mut ImageHandle) -> ImageError;
+/// Flips image horizontally reporting errors
+pub type MirrorImage2Fn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Finds template in image
+pub type MatchTemplateFn = unsafe extern "C" fn(
+    ImageHandle,
+    ImageHandle,
+    u32,
+    usize,
+    *mut MatchResult,
+    *mut usize,
+) -> ImageError;
+/// Detects keypoints of image
+pub type DetectKeypointsFn =
+    unsafe extern "C" fn(ImageHandle, u32, *mut *mut Keypoint, *mut usize) -> ImageError;
+/// Frees keypoints
+pub type FreeKeypointsFn = unsafe extern "C" fn(*mut Keypoint, usize);
+/// Matches keypoints of two images
+pub type MatchKeypointsFn =
+    unsafe extern "C" fn(ImageHandle, ImageHandle, *mut Matches) -> ImageError;
+/// Frees keypoint matches
+pub type FreeMatchesFn = unsafe extern "C" fn(*mut Matches);
+/// Aligns two images
+pub type AlignFn = unsafe extern "C" fn(
+    ImageHandle,
+    ImageHandle,
+    u32,
+    *mut Transform,
+    *mut ImageHandle,
+) -> ImageError;
+/// Warps image by control grid
+pub type MeshWarpFn =
+    unsafe extern "C" fn(ImageHandle, *const Point, *const Point, u32, u32) -> ImageError;
+/// Creates export profile from JSON
+pub type ExportProfileCreateFn =
+    unsafe extern "C" fn(RawStr, *mut ExportProfileHandle) -> ImageError;
+/// Destroys export profile
+pub type ExportProfileDestroyFn = unsafe extern "C" fn(ExportProfileHandle);
+/// Writes image to every output of export profile
+pub type ExportFn = unsafe extern "C" fn(ImageHandle, ExportProfileHandle, RawPath) -> ImageError;
+/// Returns set of capability flags of optional features compiled into library
+pub type CapabilitiesFn = unsafe extern "C" fn() -> u32;
+/// Checks whether function with field name is available in library
+pub type HasFnFn = unsafe extern "C" fn(RawStr) -> bool;
+/// Writes icon set of platform rendered from image to directory
+pub type GenerateIconSetFn = unsafe extern "C" fn(ImageHandle, u32, RawPath) -> ImageError;
+/// Registers codec plugin decoding format
+pub type RegisterDecoderFn =
+    unsafe extern "C" fn(RawStr, Option<ProbeFn>, Option<DecodeFn>, *mut c_void) -> ImageError;
+/// Registers codec plugin encoding format
+pub type RegisterEncoderFn =
+    unsafe extern "C" fn(RawStr, Option<EncodeFn>, *mut c_void) -> ImageError;
+/// Composes social media card over background image
+pub type ComposeCardFn =
+    unsafe extern "C" fn(ImageHandle, *const CardSpec, *mut ImageHandle) -> ImageError;
+/// Loads image from file with options within budget
+pub type OpenWithBudgetFn = unsafe extern "C" fn(
+    RawPath,
+    *const OpenOptions,
+    *const OperationBudget,
+    *mut ImageHandle,
+) -> ImageError;
+/// Saves image to file within budget
+pub type SaveWithBudgetFn =
+    unsafe extern "C" fn(RawPath, ImageHandle, *const OperationBudget) -> ImageError;
+/// Transcodes image stream with operations within budget
+pub type TranscodeStreamWithBudgetFn = unsafe extern "C" fn(
+    ReadFn,
+    WriteFn,
+    *const Operation,
+    usize,
+    *const OperationBudget,
+    *mut c_void,
+) -> ImageError;
+/// Detaches image from current thread
+pub type DetachThreadFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Attaches image to current thread
+pub type AttachThreadFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Applies operation by name with parameters to copy of image
+pub type ApplyFn =
+    unsafe extern "C" fn(ImageHandle, RawStr, RawStr, *mut ImageHandle) -> ImageError;
+/// Loads image from memory buffer with options
+pub type OpenImageMemoryFn =
+    unsafe extern "C" fn(*const u8, usize, *const OpenOptions, *mut ImageHandle) -> ImageError;
+/// Sets maximum number of worker threads
+pub type SetThreadCountFn = unsafe extern "C" fn(u32);
+/// Blurs image into new image reporting progress
+pub type BlurWithProgressFn = unsafe extern "C" fn(
+    ImageHandle,
+    f32,
+    Option<ProgressFn>,
+    *mut c_void,
+    *mut ImageHandle,
+) -> ImageError;
+/// Resizes image into new image reporting progress
+pub type ResizeWithProgressFn = unsafe extern "C" fn(
+    ImageHandle,
+    u32,
+    u32,
+    Option<ProgressFn>,
+    *mut c_void,
+    *mut ImageHandle,
+) -> ImageError;
+/// Encodes image into caller-provided buffer reporting progress
+pub type EncodeWithProgressFn = unsafe extern "C" fn(
+    ImageHandle,
+    u32,
+    *mut u8,
+    *mut usize,
+    Option<ProgressFn>,
+    *mut c_void,
+) -> ImageError;
+/// Creates cancellation token
+pub type TokenCreateFn = unsafe extern "C" fn(*mut TokenHandle) -> ImageError;
+/// Cancels operations token was passed to
+pub type TokenCancelFn = unsafe extern "C" fn(TokenHandle);
+/// Destroys cancellation token
+pub type TokenDestroyFn = unsafe extern "C" fn(TokenHandle);
+/// Starts operation on worker thread cancelled by token
+pub type SubmitWithTokenFn =
+    unsafe extern "C" fn(ImageHandle, RawStr, RawStr, TokenHandle, *mut JobHandle) -> ImageError;
+/// Converts many files with edit stack on worker threads of library
+pub type BatchFn = unsafe extern "C" fn(
+    *const RawPath,
+    usize,
+    RawStr,
+    RawPath,
+    *const BatchOptions,
+    *mut ImageError,
+) -> ImageError;
+/// Creates empty operation pipeline
+pub type PipelineCreateFn = unsafe extern "C" fn(*mut PipelineHandle) -> ImageError;
+/// Destroys operation pipeline
+pub type PipelineDestroyFn = unsafe extern "C" fn(PipelineHandle);
+/// Appends crop to pipeline
+pub type PipelineAddCropFn = unsafe extern "C" fn(PipelineHandle, Rect) -> ImageError;
+/// Appends resize to pipeline
+pub type PipelineAddResizeFn = unsafe extern "C" fn(PipelineHandle, u32, u32) -> ImageError;
+/// Appends blur to pipeline
+pub type PipelineAddBlurFn = unsafe extern "C" fn(PipelineHandle, f32) -> ImageError;
+/// Appends horizontal flip to pipeline
+pub type PipelineAddMirrorFn = unsafe extern "C" fn(PipelineHandle) -> ImageError;
+/// Applies operations of pipeline to image in place
+pub type PipelineExecuteFn = unsafe extern "C" fn(PipelineHandle, ImageHandle) -> ImageError;
+/// Blurs image into pixels of existing image
+pub type BlurIntoFn = unsafe extern "C" fn(ImageHandle, ImageHandle, f32) -> ImageError;
+/// Resizes image to size of existing image into its pixels
+pub type ResizeIntoFn = unsafe extern "C" fn(ImageHandle, ImageHandle) -> ImageError;
+/// Sets size limit of pool of temporary buffers
+pub type SetPoolLimitFn = unsafe extern "C" fn(u64);
+/// Frees idle buffers of pool, returning their size
+pub type PoolTrimFn = unsafe extern "C" fn() -> u64;
+/// Creates clone of image sharing its pixels until one of them is changed
+pub type CloneCowFn = unsafe extern "C" fn(ImageHandle, *mut ImageHandle) -> ImageError;
+/// Creates frozen clone of image readable by many threads at once
+pub type FreezeFn = unsafe extern "C" fn(ImageHandle, *mut ImageHandle) -> ImageError;
+/// Applies operations of pipeline to TIFF file streaming its strips into TIFF file
+pub type PipelineExecuteTiledFn = unsafe extern "C" fn(
+    PipelineHandle,
+    RawPath,
+    RawPath,
+    Option<ProgressFn>,
+    *mut c_void,
+) -> ImageError;
+/// Applies operation by name with parameters to downscaled copy of image
+pub type ApplyPreviewFn =
+    unsafe extern "C" fn(ImageHandle, RawStr, RawStr, u32, *mut ImageHandle) -> ImageError;
+/// Writes size, color type and bit depth of image
+pub type InfoFn = unsafe extern "C" fn(ImageHandle, *mut ImageInfo) -> ImageError;
+/// Locks pixels of image for direct access
+pub type LockPixelsFn = unsafe extern "C" fn(ImageHandle, *mut PixelLock) -> ImageError;
+/// Unlocks pixels of image
+pub type UnlockPixelsFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Reads color of pixel as `0xRRGGBBAA`
+pub type GetPixelFn = unsafe extern "C" fn(ImageHandle, u32, u32, *mut u32) -> ImageError;
+/// Sets color of pixel given as `0xRRGGBBAA`
+pub type SetPixelFn = unsafe extern "C" fn(ImageHandle, u32, u32, u32) -> ImageError;
+/// Creates read-only view of region of image sharing its pixels
+pub type ViewFn = unsafe extern "C" fn(ImageHandle, Rect, *mut ImageHandle) -> ImageError;
+/// Converts image to color type
+pub type ConvertFn = unsafe extern "C" fn(ImageHandle, u32, *mut ImageHandle) -> ImageError;
+/// Locks pixels of image converted to color type with rows aligned to power of two
+pub type LockPixelsAsFn = unsafe extern "C" fn(ImageHandle, u32, u32, *mut PixelLock) -> ImageError;
+/// Copies pixels of image converted to color type into buffer with stride
+pub type ReadPixelsFn = unsafe extern "C" fn(ImageHandle, *mut u8, usize, u32, usize) -> ImageError;
+/// Passes every pixel of image to callback which may change it, in parallel if asked
+pub type MapPixelsFn =
+    unsafe extern "C" fn(ImageHandle, Option<PixelFn>, *mut c_void, bool) -> ImageError;
+/// Passes rows of image to callback
+pub type ForEachRowFn = unsafe extern "C" fn(ImageHandle, Option<RowFn>, *mut c_void) -> ImageError;
+/// Creates copy of image with its own pixels
+pub type CloneFn = unsafe extern "C" fn(ImageHandle, *mut ImageHandle) -> ImageError;
+/// Creates image filled with color
+pub type NewFn = unsafe extern "C" fn(u32, u32, u32, u32, *mut ImageHandle) -> ImageError;
+/// Reorders channels of image
+pub type SwapChannelsFn = unsafe extern "C" fn(ImageHandle, *const [u8; 4]) -> ImageError;
+/// Counts values of channel of image in 256 bins
+pub type HistogramFn = unsafe extern "C" fn(ImageHandle, u32, *mut u32) -> ImageError;
+/// Counts values of channel of image in 65536 bins
+pub type Histogram16Fn = unsafe extern "C" fn(ImageHandle, u32, *mut u32) -> ImageError;
+/// Writes statistics of channels and luma of image to `Stats`
+pub type StatsFn = unsafe extern "C" fn(ImageHandle, *mut Stats) -> ImageError;
+/// Writes `k` dominant colors of image with their weights
+pub type DominantColorsFn =
+    unsafe extern "C" fn(ImageHandle, u32, *mut u32, *mut f32) -> ImageError;
+/// Writes average color of image or its region as `0xRRGGBBAA`
+pub type AverageColorFn = unsafe extern "C" fn(ImageHandle, *const Rect, *mut u32) -> ImageError;
+/// Writes 64-bit perceptual hash of image computed by `HashAlgorithm`
+pub type PhashFn = unsafe extern "C" fn(ImageHandle, u32, *mut u64) -> ImageError;
+/// Returns number of bits differing between two hashes
+pub type HashDistanceFn = unsafe extern "C" fn(u64, u64) -> u32;
+/// Writes BlurHash of image as null-terminated string
+pub type BlurhashFn =
+    unsafe extern "C" fn(ImageHandle, u32, u32, *mut c_char, *mut usize) -> ImageError;
+/// Writes ThumbHash of image
+pub type ThumbhashFn = unsafe extern "C" fn(ImageHandle, *mut u8, *mut usize) -> ImageError;
+/// Decodes ThumbHash into new preview image
+pub type ThumbhashDecodeFn = unsafe extern "C" fn(*const u8, usize, *mut ImageHandle) -> ImageError;
+/// Compares two images of the same size by metric
+pub type CompareFn = unsafe extern "C" fn(ImageHandle, ImageHandle, u32, *mut f64) -> ImageError;
+/// Counts pixels differing between two images and highlights them
+pub type DiffFn =
+    unsafe extern "C" fn(ImageHandle, ImageHandle, u8, *mut ImageHandle, *mut u64) -> ImageError;
+/// Crops away uniform borders of image
+pub type TrimFn = unsafe extern "C" fn(ImageHandle, u8, *mut ImageHandle) -> ImageError;
+/// Tells whether image is uniform
+pub type IsBlankFn = unsafe extern "C" fn(ImageHandle, u8, *mut bool) -> ImageError;
+/// Crops image to aspect ratio keeping its most interesting part and resizes it
+pub type SmartCropFn = unsafe extern "C" fn(ImageHandle, u32, u32, *mut ImageHandle) -> ImageError;
+/// Estimates sharpness of image
+pub type SharpnessFn = unsafe extern "C" fn(ImageHandle, *mut f64) -> ImageError;
+/// Draws antialiased line over image
+pub type DrawLineFn = unsafe extern "C" fn(ImageHandle, f32, f32, f32, f32, f32, u32) -> ImageError;
+/// Draws antialiased filled or stroked rectangle over image
+pub type DrawRectFn =
+    unsafe extern "C" fn(ImageHandle, f32, f32, f32, f32, f32, u32, bool) -> ImageError;
+/// Draws antialiased filled or stroked circle over image
+pub type DrawCircleFn =
+    unsafe extern "C" fn(ImageHandle, f32, f32, f32, f32, u32, bool) -> ImageError;
+/// Draws antialiased filled or stroked polygon over image
+pub type DrawPolygonFn =
+    unsafe extern "C" fn(ImageHandle, *const Point, usize, f32, u32, bool) -> ImageError;
+/// Creates image filled with linear or radial gradient
+pub type GradientFn = unsafe extern "C" fn(
+    u32,
+    u32,
+    *const GradientStop,
+    usize,
+    u32,
+    f32,
+    *mut ImageHandle,
+) -> ImageError;
+/// Creates checkerboard image of two colors
+pub type CheckerboardFn =
+    unsafe extern "C" fn(u32, u32, u32, u32, u32, *mut ImageHandle) -> ImageError;
+/// Creates image of SMPTE color bars
+pub type ColorBarsFn = unsafe extern "C" fn(u32, u32, *mut ImageHandle) -> ImageError;
+/// Watermarking
+pub type WatermarkFn =
+    unsafe extern "C" fn(ImageHandle, ImageHandle, u32, u32, f32, f32) -> ImageError;
+/// Sprite packing
+pub type PackSpritesFn = unsafe extern "C" fn(
+    *const ImageHandle,
+    usize,
+    u32,
+    u32,
+    *mut ImageHandle,
+    *mut Rect,
+) -> ImageError;
+/// Noise generation
+pub type NoiseFn =
+    unsafe extern "C" fn(u32, u32, u32, f32, u32, u32, *mut ImageHandle) -> ImageError;
+/// Corner rounding
+pub type RoundCornersFn = unsafe extern "C" fn(ImageHandle, f32) -> ImageError;
+/// Circle masking
+pub type CircleMaskFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Alpha masking
+pub type ApplyMaskFn = unsafe extern "C" fn(ImageHandle, ImageHandle, bool) -> ImageError;
+/// CMYK TIFF saving
+pub type SaveCmykTiffFn =
+    unsafe extern "C" fn(RawPath, ImageHandle, *const u8, usize) -> ImageError;
+/// Pipeline execution within budget
+pub type PipelineExecuteWithBudgetFn =
+    unsafe extern "C" fn(PipelineHandle, ImageHandle, *const OperationBudget) -> ImageError;
+/// Tiled pipeline execution within budget
+pub type PipelineExecuteTiledWithBudgetFn = unsafe extern "C" fn(
+    PipelineHandle,
+    RawPath,
+    RawPath,
+    *const OperationBudget,
+    Option<ProgressFn>,
+    *mut c_void,
+) -> ImageError;
+/// Decodes, transforms and encodes image in given format from reader to writer
+pub type TranscodeStream2Fn = unsafe extern "C" fn(
+    ReadFn,
+    WriteFn,
+    *const Operation,
+    usize,
+    u32,
+    u64,
+    *mut c_void,
+) -> ImageError;
+/// Enables cache of pipeline transcoding results
+pub type EnableCacheFn = unsafe extern "C" fn(*const c_char, u64) -> ImageError;
+/// Transcodes stream applying pipeline
+pub type PipelineTranscodeFn =
+    unsafe extern "C" fn(PipelineHandle, ReadFn, WriteFn, u32, u64, *mut c_void) -> ImageError;
+/// Creates job queue limiting its running jobs
+pub type QueueCreateFn = unsafe extern "C" fn(u32, *mut QueueHandle) -> ImageError;
+/// Destroys job queue
+pub type QueueDestroyFn = unsafe extern "C" fn(QueueHandle);
+/// Starts operation as job of queue with priority
+pub type SubmitOnFn = unsafe extern "C" fn(
+    QueueHandle,
+    i32,
+    ImageHandle,
+    RawStr,
+    RawStr,
+    TokenHandle,
+    *mut JobHandle,
+) -> ImageError;
+/// Starts operation by name with parameters on worker thread
+pub type SubmitFn = unsafe extern "C" fn(ImageHandle, RawStr, RawStr, *mut JobHandle) -> ImageError;
+/// Returns progress of job as raw `JobStatus` value, which newer library may extend
+pub type JobStatusFn = unsafe extern "C" fn(JobHandle) -> u32;
+/// Waits for job and takes its resulting image
+pub type JobResultFn = unsafe extern "C" fn(JobHandle, *mut ImageHandle) -> ImageError;
+/// Destroys job
+pub type JobDestroyFn = unsafe extern "C" fn(JobHandle);
+
+/// Performs a Gaussian blur on the supplied image. Deprecated: use `BlurImage2Fn`
+pub type BlurImageFn = unsafe extern "C" fn(ImageHandle, f32) -> ImageHandle;
+/// Flips image horizontally. Deprecated: use `MirrorImage2Fn`
+pub type MirrorImageFn = unsafe extern "C" fn(ImageHandle);
+
+/// Contains functions provided by library, `functions` of `FunctionsV2` written by `functions_ex`.
+/// `size` field contain size of this struct. It helps to avoid versioning and some other errors.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Functions {
+    pub size: usize,
+    pub open_image: OpenImageFn,
+    pub save_image: SaveImageFn,
+    pub destroy_image: DestroyImageFn,
+    #[deprecated(note = "use `blur_image2`, which reports errors")]
+    pub blur_image: BlurImageFn,
+    #[deprecated(note = "use `mirror_image2`, which reports errors")]
+    pub mirror_image: MirrorImageFn,
+    pub encode_image: EncodeImageFn,
+    pub open_image_with_reader: OpenImageWithReaderFn,
+    pub save_image_with_writer: SaveImageWithWriterFn,
+    pub transcode_stream: TranscodeStreamFn,
+    pub open_image_fd: OpenImageFdFn,
+    pub save_image_fd: SaveImageFdFn,
+    pub open_image_data_uri: OpenImageDataUriFn,
+    pub encode_image_data_uri: EncodeImageDataUriFn,
+    pub open_image_url: OpenImageUrlFn,
+    pub open_image_with_options: OpenImageWithOptionsFn,
+    pub set_limits: SetLimitsFn,
+    pub pack_eink: PackEinkFn,
+    pub pack_framebuffer: PackFramebufferFn,
+    pub image_from_raw: ImageFromRawFn,
+    pub suggest_crops: SuggestCropsFn,
+    pub exif_get: ExifGetFn,
+    pub draw_guides: DrawGuidesFn,
+    pub estimate_noise: EstimateNoiseFn,
+    pub exif_set: ExifSetFn,
+    pub auto_orient: AutoOrientFn,
+    pub auto_denoise: AutoDenoiseFn,
+    pub auto_sharpen: AutoSharpenFn,
+    pub correct_ca: CorrectCaFn,
+    pub icc_get: IccGetFn,
+    pub icc_set: IccSetFn,
+    pub convert_to_srgb: ConvertToSrgbFn,
+    pub defringe: DefringeFn,
+    pub get_dpi: GetDpiFn,
+    pub set_dpi: SetDpiFn,
+    pub recover_highlights: RecoverHighlightsFn,
+    pub png_text_keys: PngTextKeysFn,
+    pub png_text_get: PngTextGetFn,
+    pub png_text_set: PngTextSetFn,
+    pub shadows_highlights: ShadowsHighlightsFn,
+    pub hsl_adjust: HslAdjustFn,
+    pub xmp_get: XmpGetFn,
+    pub iptc_get: IptcGetFn,
+    pub replace_color: ReplaceColorFn,
+    pub strip_metadata: StripMetadataFn,
+    pub exif_thumbnail: ExifThumbnailFn,
+    pub split_tone: SplitToneFn,
+    pub film_emulation: FilmEmulationFn,
+    pub last_error_message: LastErrorMessageFn,
+    pub set_journal_depth: SetJournalDepthFn,
+    pub undo: UndoFn,
+    pub redo: RedoFn,
+    pub history_len: HistoryLenFn,
+    pub last_error_details: LastErrorDetailsFn,
+    pub export_edit_stack: ExportEditStackFn,
+    pub apply_edit_stack: ApplyEditStackFn,
+    pub free_string: FreeStringFn,
+    pub set_log_callback: SetLogCallbackFn,
+    pub add_annotation: AddAnnotationFn,
+    pub annotation_count: AnnotationCountFn,
+    pub get_annotation: GetAnnotationFn,
+    pub remove_annotation: RemoveAnnotationFn,
+    pub burn_annotations: BurnAnnotationsFn,
+    pub doc_new: DocNewFn,
+    pub doc_destroy: DocDestroyFn,
+    pub doc_add_layer: DocAddLayerFn,
+    pub doc_layer_count: DocLayerCountFn,
+    pub doc_get_layer: DocGetLayerFn,
+    pub doc_set_layer: DocSetLayerFn,
+    pub doc_layer_image: DocLayerImageFn,
+    pub doc_remove_layer: DocRemoveLayerFn,
+    pub doc_move_layer: DocMoveLayerFn,
+    pub doc_flatten: DocFlattenFn,
+    pub is_valid: IsValidFn,
+    pub doc_open_ora: DocOpenOraFn,
+    pub doc_save_ora: DocSaveOraFn,
+    pub doc_open_psd: DocOpenPsdFn,
+    pub set_debug_handles: SetDebugHandlesFn,
+    pub live_handle_count: LiveHandleCountFn,
+    pub dump_leaks: DumpLeaksFn,
+    pub blur_image2: BlurImage2Fn,
+    pub mirror_image2: MirrorImage2Fn,
+    pub match_template: MatchTemplateFn,
+    pub detect_keypoints: DetectKeypointsFn,
+    pub free_keypoints: FreeKeypointsFn,
+    pub match_keypoints: MatchKeypointsFn,
+    pub free_matches: FreeMatchesFn,
+    pub align: AlignFn,
+    pub mesh_warp: MeshWarpFn,
+    pub export_profile_create: ExportProfileCreateFn,
+    pub export_profile_destroy: ExportProfileDestroyFn,
+    pub export: ExportFn,
+    pub capabilities: CapabilitiesFn,
+    pub has_fn: HasFnFn,
+    pub generate_icon_set: GenerateIconSetFn,
+    pub register_decoder: RegisterDecoderFn,
+    pub register_encoder: RegisterEncoderFn,
+    pub compose_card: ComposeCardFn,
+    pub open_with_budget: OpenWithBudgetFn,
+    pub save_with_budget: SaveWithBudgetFn,
+    pub transcode_stream_with_budget: TranscodeStreamWithBudgetFn,
+    pub detach_thread: DetachThreadFn,
+    pub attach_thread: AttachThreadFn,
+    pub apply: ApplyFn,
+    pub open_image_memory: OpenImageMemoryFn,
+    pub set_thread_count: SetThreadCountFn,
+    pub submit: SubmitFn,
+    pub job_status: JobStatusFn,
+    pub job_result: JobResultFn,
+    pub job_destroy: JobDestroyFn,
+    pub blur_with_progress: BlurWithProgressFn,
+    pub resize_with_progress: ResizeWithProgressFn,
+    pub encode_with_progress: EncodeWithProgressFn,
+    pub token_create: TokenCreateFn,
+    pub token_cancel: TokenCancelFn,
+    pub token_destroy: TokenDestroyFn,
+    pub submit_with_token: SubmitWithTokenFn,
+    pub batch: BatchFn,
+    pub pipeline_create: PipelineCreateFn,
+    pub pipeline_destroy: PipelineDestroyFn,
+    pub pipeline_add_crop: PipelineAddCropFn,
+    pub pipeline_add_resize: PipelineAddResizeFn,
+    pub pipeline_add_blur: PipelineAddBlurFn,
+    pub pipeline_add_mirror: PipelineAddMirrorFn,
+    pub pipeline_execute: PipelineExecuteFn,
+    pub blur_into: BlurIntoFn,
+    pub resize_into: ResizeIntoFn,
+    pub set_pool_limit: SetPoolLimitFn,
+    pub pool_trim: PoolTrimFn,
+    pub clone_cow: CloneCowFn,
+    pub freeze: FreezeFn,
+    pub pipeline_execute_tiled: PipelineExecuteTiledFn,
+    pub apply_preview: ApplyPreviewFn,
+    pub info: InfoFn,
+    pub lock_pixels: LockPixelsFn,
+    pub unlock_pixels: UnlockPixelsFn,
+    pub get_pixel: GetPixelFn,
+    pub set_pixel: SetPixelFn,
+    pub view: ViewFn,
+    pub convert: ConvertFn,
+    pub lock_pixels_as: LockPixelsAsFn,
+    pub read_pixels: ReadPixelsFn,
+    pub map_pixels: MapPixelsFn,
+    pub for_each_row: ForEachRowFn,
+    pub clone: CloneFn,
+    pub new: NewFn,
+    pub swap_channels: SwapChannelsFn,
+    pub histogram: HistogramFn,
+    pub histogram16: Histogram16Fn,
+    pub stats: StatsFn,
+    pub dominant_colors: DominantColorsFn,
+    pub average_color: AverageColorFn,
+    pub phash: PhashFn,
+    pub hash_distance: HashDistanceFn,
+    pub blurhash: BlurhashFn,
+    pub thumbhash: ThumbhashFn,
+    pub thumbhash_decode: ThumbhashDecodeFn,
+    pub compare: CompareFn,
+    pub diff: DiffFn,
+    pub trim: TrimFn,
+    pub is_blank: IsBlankFn,
+    pub smart_crop: SmartCropFn,
+    pub sharpness: SharpnessFn,
+    pub draw_line: DrawLineFn,
+    pub draw_rect: DrawRectFn,
+    pub draw_circle: DrawCircleFn,
+    pub draw_polygon: DrawPolygonFn,
+    pub gradient: GradientFn,
+    pub checkerboard: CheckerboardFn,
+    pub color_bars: ColorBarsFn,
+    pub watermark: WatermarkFn,
+    pub pack_sprites: PackSpritesFn,
+    pub noise: NoiseFn,
+    pub round_corners: RoundCornersFn,
+    pub circle_mask: CircleMaskFn,
+    pub apply_mask: ApplyMaskFn,
+    pub save_cmyk_tiff: SaveCmykTiffFn,
+    pub pipeline_execute_with_budget: PipelineExecuteWithBudgetFn,
+    pub pipeline_execute_tiled_with_budget: PipelineExecuteTiledWithBudgetFn,
+    pub transcode_stream2: TranscodeStream2Fn,
+    pub enable_cache: EnableCacheFn,
+    pub pipeline_transcode: PipelineTranscodeFn,
+    pub queue_create: QueueCreateFn,
+    pub queue_destroy: QueueDestroyFn,
+    pub submit_on: SubmitOnFn,
+}
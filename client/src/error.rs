@@ -0,0 +1,122 @@
+use crate::bindings::ImageError;
+use crate::ErrorInfo;
+use std::ffi::{IntoStringError, NulError};
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+/// Error of plugin closures, passed to library as failure of decoding or encoding.
+pub type PluginError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Result of client functions.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Error of client functions.
+#[derive(Debug)]
+pub enum Error {
+    /// Library function failed with error `code`. `details` are details of the last error of
+    /// library if they describe this failure, e.g. message of decoder.
+    Image {
+        code: ImageError,
+        details: Option<ErrorInfo>,
+    },
+    /// Shared library isn't found at any of searched paths.
+    NotFound(Vec<PathBuf>),
+    /// Shared library can't be loaded or doesn't export function.
+    Load(libloading::Error),
+    /// Library doesn't provide functions table of these bindings, e.g. it is older than them.
+    Incompatible(&'static str),
+    /// Library is linked statically, so it can't be reloaded.
+    NotReloadable,
+    /// Objects of different loads of library are passed together, e.g. image created before
+    /// `ImageFactory::reload` and pipeline created after it.
+    ForeignObject,
+    /// Argument can't be passed to library, e.g. path with null byte.
+    InvalidArgument(String),
+    /// Library returned malformed data, e.g. string which isn't UTF-8.
+    InvalidData(String),
+    Io(std::io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Image { code, details } => {
+                code.fmt(f)?;
+                match details {
+                    Some(details) if !details.message.is_empty() => {
+                        write!(f, ": {}", details.message)
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Error::NotFound(paths) => {
+                write!(f, "image_sl library isn't found, searched:")?;
+                for path in paths {
+                    write!(f, " {}", path.display())?;
+                }
+                Ok(())
+            }
+            Error::Load(e) => write!(f, "can't load image_sl library: {}", e),
+            Error::Incompatible(reason) => write!(f, "incompatible image_sl library: {}", reason),
+            Error::NotReloadable => write!(f, "statically linked image_sl can't be reloaded"),
+            Error::ForeignObject => write!(f, "objects come from different loads of image_sl"),
+            Error::InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            Error::InvalidData(reason) => write!(f, "invalid data of library: {}", reason),
+            Error::Io(e) => write!(f, "i/o error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Image { code, .. } => Some(code),
+            Error::Load(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// Returns error code of library if function of library failed.
+    pub fn code(&self) -> Option<ImageError> {
+        match self {
+            Error::Image { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(code: ImageError) -> Self {
+        Error::Image {
+            code,
+            details: None,
+        }
+    }
+}
+
+impl From<libloading::Error> for Error {
+    fn from(e: libloading::Error) -> Self {
+        Error::Load(e)
+    }
+}
+
+impl From<NulError> for Error {
+    fn from(e: NulError) -> Self {
+        Error::InvalidArgument(e.to_string())
+    }
+}
+
+impl From<IntoStringError> for Error {
+    fn from(e: IntoStringError) -> Self {
+        Error::InvalidData(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
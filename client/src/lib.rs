@@ -0,0 +1,5337 @@
+use crate::bindings::{
+    BatchOptions, CardSpec, DocumentHandle, ExportProfileHandle, ImageHandle, JobHandle, Matches,
+    OsHandle, PipelineHandle, QueueHandle, RawPath, RawStr, TokenHandle,
+};
+use bindings::{
+    DumpTraceFn, Functions, FunctionsExFn, InitFn, PixelFn, ProgressFn, RowFn, ShutdownFn,
+    VersionFn,
+};
+use libloading::Library;
+use std::convert::TryFrom;
+use std::ffi::{c_void, CStr, CString};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+pub use bindings::{
+    Anchor, BlendMode, CardLayout, ColorBand, ColorType, CompareMetric, Detector, EinkFormat,
+    ErrorSubCode, ExifType, Exports, FilmStock, FunctionsV2, GradientKind, GradientStop, GuideSpec,
+    HashAlgorithm, HslBand, IconSetKind, ImageError, ImageFormat, InitConfig, JobStatus, Keypoint,
+    KeypointMatch, LayerProps, LogLevel, MatchMethod, MatchResult, Motion, NoiseKind, OpenOptions,
+    Operation, OperationBudget, PixelLayout, Point, RawPixelFormat, Rect, RowOrder, SaveOptions,
+    Stats, Transform, UnknownValue, Version, LIB_FILE_NAME,
+};
+
+pub use error::{Error, PluginError, Result};
+
+pub mod bindings;
+mod error;
+
+/// Ids of commonly used EXIF tags.
+pub mod exif_tag {
+    pub const MAKE: u16 = 0x010f;
+    pub const MODEL: u16 = 0x0110;
+    pub const ORIENTATION: u16 = 0x0112;
+    pub const DATE_TIME: u16 = 0x0132;
+    pub const DATE_TIME_ORIGINAL: u16 = 0x9003;
+}
+
+/// Numbers of commonly used IPTC application record datasets.
+pub mod iptc_dataset {
+    pub const OBJECT_NAME: u32 = 5;
+    pub const KEYWORDS: u32 = 25;
+    pub const BY_LINE: u32 = 80;
+    pub const CITY: u32 = 90;
+    pub const HEADLINE: u32 = 105;
+    pub const COPYRIGHT_NOTICE: u32 = 116;
+    pub const CAPTION: u32 = 120;
+}
+
+/// Flags of optional features returned by `ImageFactory::capabilities`.
+pub mod capability {
+    /// Images are downloaded by `ImageFactory::open_image_url`.
+    pub const HTTP: u32 = 1;
+    /// ICC profiles are applied by `Image::convert_to_srgb`.
+    pub const ICC: u32 = 1 << 1;
+    /// Files are decoded through memory mapping.
+    pub const MMAP: u32 = 1 << 2;
+    /// Trace spans are reported to log callback.
+    pub const TRACING: u32 = 1 << 3;
+    /// Leak reports have backtraces.
+    pub const BACKTRACE: u32 = 1 << 4;
+    /// Blur and resize run on worker threads.
+    pub const PARALLEL: u32 = 1 << 5;
+}
+
+/// Operations of library which may be missing in loaded build, either because it is older than
+/// these bindings or because Cargo feature of the operation isn't compiled in.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// `ImageFactory::open_image_url`.
+    OpenUrl,
+    /// `Image::convert_to_srgb`.
+    ConvertToSrgb,
+    /// `Image::export` with export profiles.
+    ExportProfiles,
+    /// `Image::generate_icon_set`.
+    IconSets,
+    /// Decoder and encoder plugins of `ImageFactory`.
+    Plugins,
+    /// `Image::compose_card`.
+    Cards,
+    /// Opening, saving and transcoding with `OperationBudget`.
+    Budgets,
+    /// `ImageFactory::transcode_as`.
+    TranscodeAs,
+    /// `Pipeline::execute_with_budget` and `Pipeline::execute_tiled_with_budget`.
+    PipelineBudgets,
+    /// `Image::detach_thread` and `Image::attach_thread`.
+    ThreadTransfer,
+    /// Jobs run on worker threads of library.
+    Jobs,
+    /// Blurring, resizing and encoding with progress callback.
+    Progress,
+    /// Jobs cancelled by `CancelToken`.
+    Cancellation,
+    /// `ImageFactory::batch`.
+    Batch,
+    /// `Pipeline` and `Image::pipeline`.
+    Pipelines,
+    /// `Image::blur_into` and `Image::resize_into`.
+    ReuseImages,
+    /// Buffer pool limited by `ImageFactory::set_pool_limit`.
+    BufferPool,
+    /// `Image::clone_cow`.
+    CopyOnWrite,
+    /// `Image::info`.
+    Info,
+    /// `Image::lock_pixels`.
+    LockPixels,
+    /// `Image::pixel` and `Image::set_pixel`.
+    PixelAccess,
+    /// `Image::view`.
+    Views,
+    /// `Image::convert`.
+    Convert,
+    /// `Image::read_pixels`.
+    ReadPixels,
+    /// `Image::map_pixels`.
+    MapPixels,
+    /// `Image::for_each_row`.
+    ForEachRow,
+    /// Deep copies by `Image::try_clone`.
+    Clone,
+    /// `ImageFactory::new_image`.
+    NewImage,
+    /// `Image::swap_channels`.
+    SwapChannels,
+    /// `Image::histogram` and `Image::histogram16`.
+    Histogram,
+    /// `Image::stats`.
+    Stats,
+    /// `Image::dominant_colors`.
+    DominantColors,
+    /// `Image::average_color`.
+    AverageColor,
+    /// `Image::perceptual_hash`.
+    PerceptualHash,
+    /// `Image::blurhash`.
+    Blurhash,
+    /// `Image::thumbhash` and `ImageFactory::image_from_thumbhash`.
+    Thumbhash,
+    /// `Image::compare`.
+    Compare,
+    /// `Image::diff` and `Image::differing_pixels`.
+    Diff,
+    /// `Image::trim` and `Image::is_blank`.
+    Trim,
+    /// `Image::smart_crop`.
+    SmartCrop,
+    /// `Image::sharpness`.
+    Sharpness,
+    /// `Image::draw_line`, `Image::draw_rect`, `Image::draw_circle` and `Image::draw_polygon`.
+    Draw,
+    /// `ImageFactory::gradient`.
+    Gradient,
+    /// `ImageFactory::checkerboard` and `ImageFactory::color_bars`.
+    TestPattern,
+    /// `Image::watermark`.
+    Watermark,
+    /// `ImageFactory::pack_sprites`.
+    PackSprites,
+    /// `ImageFactory::noise`.
+    Noise,
+    /// `Image::round_corners` and `Image::circle_mask`.
+    RoundCorners,
+    /// `Image::apply_mask`.
+    ApplyMask,
+    /// `Image::save_cmyk_tiff`.
+    CmykTiff,
+    /// `Pipeline::transcode` with cache of `ImageFactory::enable_cache`.
+    ResultCache,
+    /// `ImageFactory::job_queue`.
+    JobQueues,
+}
+
+impl HslBand {
+    /// Creates adjustment of `band`.
+    pub fn new(band: ColorBand, hue: f32, saturation: f32, luminance: f32) -> Self {
+        Self {
+            band: band as u32,
+            hue,
+            saturation,
+            luminance,
+        }
+    }
+}
+
+/// Details of the last decoding, encoding or I/O error of library.
+#[derive(Debug, Clone)]
+pub struct ErrorInfo {
+    /// `ImageError` code of error.
+    pub code: u32,
+    pub sub_code: ErrorSubCode,
+    /// `ImageFormat` value of format that caused error.
+    pub format: Option<u32>,
+    /// OS error number of I/O errors.
+    pub os_error: Option<i32>,
+    pub message: String,
+}
+
+impl ErrorInfo {
+    /// Returns error code of error, `None` for codes unknown to these bindings.
+    pub fn error_code(&self) -> Option<ImageError> {
+        ImageError::try_from(self.code).ok()
+    }
+
+    /// Returns format that caused error, `None` if it's unknown or unknown to these bindings.
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        ImageFormat::try_from(self.format?).ok()
+    }
+}
+
+/// Size, color type and bit depth of image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    /// `None` for color types unknown to these bindings.
+    pub color_type: Option<ColorType>,
+    /// Bits per channel.
+    pub bit_depth: u32,
+    /// Number of channels including alpha.
+    pub channels: u32,
+    pub has_alpha: bool,
+}
+
+/// Version of loaded library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Description of build, e.g. `"0.1.0 (git 1a2b3c4, features: http, icc)"`.
+    pub build: String,
+}
+
+/// Keypoint matches of two images with offset which moves points of the first image to the same
+/// points of the second one.
+#[derive(Debug)]
+pub struct Alignment {
+    pub matches: Vec<KeypointMatch>,
+    pub offset_x: f32,
+    pub offset_y: f32,
+    /// Number of matches offset is estimated from, zero if images don't overlap.
+    pub inliers: usize,
+}
+
+/// Receives log messages of library.
+type Logger = Box<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+/// Logger of library. Library has one log callback per process, so logger is global too.
+static LOGGER: RwLock<Option<Logger>> = RwLock::new(None);
+
+/// Passes log message of library to `LOGGER`.
+unsafe extern "C" fn log_callback(
+    level: u32,
+    message: *const c_char,
+    len: usize,
+    _user_data: *mut c_void,
+) {
+    let message = std::slice::from_raw_parts(message as *const u8, len);
+    let message = String::from_utf8_lossy(message);
+    if let Some(logger) = &*LOGGER.read().unwrap_or_else(|e| e.into_inner()) {
+        logger(LogLevel::from_raw(level), &message);
+    }
+}
+
+/// Returns Hamming distance between perceptual hashes `a` and `b` of `Image::perceptual_hash`,
+/// number of bits they differ in, as `hash_distance` of library does. Hashes of the same
+/// algorithm within distance of about 10 usually belong to the same picture.
+pub fn hash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Environment variable with path to shared library or directory containing it, which overrides
+/// search of `ImageFactory::new`.
+pub const LIB_PATH_VAR: &str = "IMAGE_SL_PATH";
+
+/// Returns paths `ImageFactory::new` looks for shared library at, in order. Path of
+/// `LIB_PATH_VAR` variable is the only one if it is set. Otherwise library is looked for next to
+/// executable, in `Frameworks` directory of macOS app bundle and in parent directory of
+/// executable, which covers examples and tests of Cargo, then in `target/release` and
+/// `target/debug` of current directory.
+pub fn lib_search_paths() -> Vec<PathBuf> {
+    if let Some(path) = std::env::var_os(LIB_PATH_VAR) {
+        let path = PathBuf::from(path);
+        return match path.is_dir() {
+            true => vec![path.join(LIB_FILE_NAME)],
+            false => vec![path],
+        };
+    }
+
+    let mut dirs = Vec::new();
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|e| Some(e.parent()?.to_owned()))
+    {
+        let parent = exe_dir.parent().map(Path::to_owned);
+        dirs.push(exe_dir);
+        // Executables of app bundles are in `Contents/MacOS`, libraries in `Contents/Frameworks`.
+        #[cfg(target_os = "macos")]
+        dirs.extend(parent.as_ref().map(|p| p.join("Frameworks")));
+        dirs.extend(parent);
+    }
+    dirs.push(PathBuf::from("target/release"));
+    dirs.push(PathBuf::from("target/debug"));
+    dirs.into_iter().map(|d| d.join(LIB_FILE_NAME)).collect()
+}
+
+/// Copies library at `path` to file `name` in new directory of random name in temporary
+/// directory and returns path of directory. Directory is accessible only by current user and
+/// file is created anew, so neither can be planted or replaced by another user beforehand.
+fn copy_to_private_dir(path: &Path, name: &str) -> std::io::Result<PathBuf> {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    let dir = loop {
+        // Keys of `RandomState` are random and differ for every instance.
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let dir = std::env::temp_dir().join(format!("image_sl-{:016x}", random));
+        match builder.create(&dir) {
+            Ok(()) => break dir,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    };
+
+    let copy = || {
+        let mut src = File::open(path)?;
+        let mut dst = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(name))?;
+        std::io::copy(&mut src, &mut dst)
+    };
+    if let Err(e) = copy() {
+        let _ = std::fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    Ok(dir)
+}
+
+/// Shared library loaded at runtime.
+struct LoadedLibrary {
+    library: Option<Library>,
+    /// Directory of copy loaded by `ImageFactory::reload`, removed once library is unloaded.
+    copy_dir: Option<PathBuf>,
+}
+
+impl Drop for LoadedLibrary {
+    fn drop(&mut self) {
+        drop(self.library.take());
+        if let Some(dir) = self.copy_dir.take() {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Safe interface for image opening.
+pub struct ImageFactory {
+    lib: Lib,
+    /// Path shared library is loaded from, `None` if library is linked statically.
+    path: Option<PathBuf>,
+}
+
+impl ImageFactory {
+    /// Creates new instance of `ImagesFactory` loading the first shared library found at
+    /// `lib_search_paths`. Fails with `Error::NotFound` listing searched paths if there is none.
+    /// If library loading well-tested on general platfoms, this function can be safe.
+    pub fn new() -> Result<Self> {
+        let paths = lib_search_paths();
+        match paths.iter().find(|p| p.is_file()) {
+            Some(path) => Self::with_path(path),
+            None => Err(Error::NotFound(paths)),
+        }
+    }
+
+    /// Creates new instance of `ImagesFactory` loading shared library at `path`.
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let lib = unsafe {
+            let lib = libloading::Library::new(path.as_ref())?;
+            Lib::new(lib)?
+        };
+
+        Ok(Self {
+            lib,
+            path: Some(path.as_ref().to_owned()),
+        })
+    }
+
+    /// Loads shared library again from path it was loaded from, e.g. after rebuilding it, so
+    /// objects created by factory after it use new code. Library is loaded from temporary copy,
+    /// as systems return already loaded library for the same path. Copy is written to new
+    /// directory of random name only current user can access, so other users can't replace it
+    /// before it is loaded, and it is removed once loaded on Unix systems, or once library is
+    /// unloaded on others. Fails with `Error::NotReloadable` if library is linked statically,
+    /// and keeps current library if new one can't be loaded.
+    ///
+    /// Objects created before reloading keep working with library they came from, which stays
+    /// loaded until all of them are dropped. Passing them together with objects of reloaded
+    /// library, e.g. to `Image::blur_into`, fails with `Error::ForeignObject`; `migrate` moves
+    /// images to reloaded library. Logger set by `set_logger` is passed to reloaded library,
+    /// while other global settings, e.g. limits, and plugins must be applied again.
+    pub fn reload(&mut self) -> Result<()> {
+        let path = self.path.as_ref().ok_or(Error::NotReloadable)?;
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => LIB_FILE_NAME.to_string(),
+        };
+        let dir = copy_to_private_dir(path, &name)?;
+        let loaded = unsafe { Library::new(dir.join(&name)) };
+        let loaded = loaded
+            .map_err(Error::from)
+            .and_then(|l| unsafe { Lib::new(l) });
+        // Loaded library stays mapped after its file is removed on Unix systems, elsewhere copy
+        // is removed once library is unloaded.
+        if cfg!(unix) || loaded.is_err() {
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+        let mut lib = loaded?;
+        if !cfg!(unix) {
+            if let Some(loaded) = lib.lib.as_mut().and_then(Arc::get_mut) {
+                loaded.copy_dir = Some(dir);
+            }
+        }
+        if LOGGER.read().unwrap_or_else(|e| e.into_inner()).is_some() {
+            unsafe { lib.set_log_callback(Some(log_callback)) }
+        }
+        self.lib = lib;
+        Ok(())
+    }
+
+    /// Returns copy of `image` belonging to library of factory, e.g. created before `reload`.
+    /// Copy has pixels of image, but not its metadata, annotations and edit history. Image
+    /// already belonging to library is cloned.
+    pub fn migrate(&self, image: &Image) -> Result<Image> {
+        if image.lib.is_same(&self.lib) {
+            return image.try_clone();
+        }
+        let info = image.info()?;
+        let color_type = info
+            .color_type
+            .ok_or(Error::from(ImageError::Unsupported))?;
+        let mut copy = self.new_image(info.width, info.height, color_type, [0; 4])?;
+        let mut lock = copy.lock_pixels()?;
+        let stride = lock.stride();
+        image.read_pixels(color_type, lock.bytes_mut(), stride)?;
+        drop(lock);
+        Ok(copy)
+    }
+
+    /// Creates new instance of `ImagesFactory` using library `exports` linked into executable,
+    /// without loading shared library at runtime.
+    /// # Safety
+    /// `exports` are functions of the same build of library.
+    pub unsafe fn from_exports(exports: Exports) -> Result<Self> {
+        let lib = Lib::from_exports(exports, None)?;
+        Ok(Self { lib, path: None })
+    }
+
+    /// Loads library and applies global settings of `config` at once. Log callback of `config`
+    /// must be usable from any thread; `set_logger` forwards messages to Rust closure instead.
+    pub fn with_config(config: &InitConfig) -> Result<Self> {
+        let factory = Self::new()?;
+        unsafe { factory.lib.init(config)? };
+        Ok(factory)
+    }
+
+    /// Releases global resources of library: removes logger, limits and debug registry of
+    /// handles. Live images are reported to log as leaks before logger is removed.
+    pub fn shutdown(&self) {
+        unsafe { self.lib.shutdown() }
+        *LOGGER.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Writes the last operations of library on every thread to file at `path`, e.g. to attach
+    /// it to crash report. Unfinished operations are marked so.
+    pub fn dump_trace<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path_cstring = path_to_cstring(path)?;
+        unsafe { self.lib.dump_trace(&path_cstring) }
+    }
+
+    /// Returns version of loaded library.
+    pub fn version(&self) -> LibraryVersion {
+        unsafe { self.lib.version() }
+    }
+
+    /// Returns ABI version negotiated with loaded library, older than `bindings::ABI_VERSION`
+    /// if library is older than these bindings.
+    pub fn abi_version(&self) -> u32 {
+        self.lib.abi_version()
+    }
+
+    /// Returns set of `capability` flags of optional features compiled into loaded library.
+    pub fn capabilities(&self) -> u32 {
+        unsafe { self.lib.capabilities() }
+    }
+
+    /// Returns `true` if loaded library supports operation `op`, so it can be skipped when
+    /// library is older than bindings or lacks Cargo feature of it.
+    pub fn supports(&self, op: Op) -> bool {
+        unsafe { self.lib.supports(op) }
+    }
+
+    /// Returns `true` if loaded library provides working function `name`, e.g.
+    /// `"open_image_url"`, so optional operations can be skipped when they are missing.
+    pub fn has_fn(&self, name: &str) -> bool {
+        match CString::new(name) {
+            Ok(name) => unsafe { self.lib.has_fn(&name) },
+            Err(_) => false,
+        }
+    }
+
+    /// Sets limits applied to every image opened by library. Zero means no limit.
+    pub fn set_limits(&self, max_width: u32, max_height: u32, max_memory: u64) {
+        unsafe { self.lib.set_limits(max_width, max_height, max_memory) }
+    }
+
+    /// Sets maximum number of worker threads running blur and resize. `0` means number of CPUs
+    /// and `1` runs them on calling thread.
+    pub fn set_thread_count(&self, count: u32) {
+        unsafe { self.lib.set_thread_count(count) }
+    }
+
+    /// Enables pool keeping up to `max_bytes` of temporary buffers of blur and resize for reuse.
+    /// `0` disables it.
+    pub fn set_pool_limit(&self, max_bytes: u64) {
+        unsafe { self.lib.set_pool_limit(max_bytes) }
+    }
+
+    /// Frees idle buffers of pool, returning their size in bytes.
+    pub fn pool_trim(&self) -> u64 {
+        unsafe { self.lib.pool_trim() }
+    }
+
+    /// Enables cache of results of `Pipeline::transcode` in `dir` keeping up to `max_bytes` of
+    /// encoded images, so repeated identical requests are answered without decoding their input.
+    /// `0` disables it.
+    pub fn enable_cache<P: AsRef<Path>>(&self, dir: P, max_bytes: u64) -> Result<()> {
+        let dir = path_to_cstring(dir)?;
+        unsafe { self.lib.enable_cache(Some(&dir), max_bytes) }
+    }
+
+    /// Disables cache of results of `Pipeline::transcode`, leaving its files in place.
+    pub fn disable_cache(&self) -> Result<()> {
+        unsafe { self.lib.enable_cache(None, 0) }
+    }
+
+    /// Opens new image at specified path.
+    pub fn open_image<P: AsRef<Path>>(&self, path: P) -> Result<Image> {
+        Image::open(self.lib.clone(), path)
+    }
+
+    /// Opens new image at specified path respecting `options`.
+    pub fn open_image_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &OpenOptions,
+    ) -> Result<Image> {
+        Image::open_with_options(self.lib.clone(), path, options)
+    }
+
+    /// Opens new image at specified path respecting `options`. Fails with
+    /// `ImageError::BudgetExceeded` if decoding takes longer or allocates more than `budget`.
+    pub fn open_image_with_budget<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &OpenOptions,
+        budget: &OperationBudget,
+    ) -> Result<Image> {
+        let path_cstring = path_to_cstring(path)?;
+        let handle = unsafe { self.lib.open_with_budget(&path_cstring, options, budget)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Opens new image from encoded image `data` respecting `options`.
+    pub fn open_image_from_memory(&self, data: &[u8], options: &OpenOptions) -> Result<Image> {
+        let handle = unsafe { self.lib.open_image_memory(data, options)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates cancellation token for operations, e.g. `Image::submit_with_token`.
+    pub fn cancel_token(&self) -> Result<CancelToken> {
+        let handle = unsafe { self.lib.token_create()? };
+        Ok(CancelToken {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates queue running at most `max_parallel` of its jobs at once, starting waiting ones
+    /// from the highest priority one, e.g. to keep background jobs from starving interactive
+    /// ones.
+    pub fn job_queue(&self, max_parallel: u32) -> Result<JobQueue> {
+        let handle = unsafe { self.lib.queue_create(max_parallel)? };
+        Ok(JobQueue {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Starts decoding image at specified path on worker thread of library.
+    pub fn submit_open<P: AsRef<Path>>(&self, path: P) -> Result<Job> {
+        let params = path_params(path)?;
+        let op = CString::new("open")?;
+        let handle = unsafe { self.lib.submit(ImageHandle::new_null(), &op, &params)? };
+        Ok(Job {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Decodes images at `paths`, applies edits of `batch` to each one and encodes results into
+    /// `out_dir` on worker threads of library, passing percent of processed files to `progress`,
+    /// which may be called from worker threads. Returns outcome of every file in order of
+    /// `paths`. Fails with `ImageError::Cancelled` once `progress` returns `false` or token of
+    /// `batch` is cancelled.
+    pub fn batch<P, Q, F>(
+        &self,
+        paths: &[P],
+        out_dir: Q,
+        batch: &Batch,
+        mut progress: F,
+    ) -> Result<Vec<Result<(), ImageError>>>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(f32) -> bool + Send,
+    {
+        let paths = paths
+            .iter()
+            .map(path_to_cstring)
+            .collect::<Result<Vec<_>, _>>()?;
+        let out_dir = path_to_cstring(out_dir)?;
+        let pipeline = batch.pipeline.map(CString::new).transpose()?;
+        let extension = batch.extension.map(CString::new).transpose()?;
+        let options = BatchOptions {
+            extension: RawStr(extension.as_ref().map_or(std::ptr::null(), |e| e.as_ptr())),
+            jpeg_quality: batch.jpeg_quality,
+            keep_metadata: batch.keep_metadata,
+            progress_fn: Some(progress_callback::<F> as ProgressFn),
+            user_data: &mut progress as *mut F as *mut c_void,
+            token: match batch.token {
+                Some(t) => {
+                    t.lib.check_same(&self.lib)?;
+                    t.handle
+                }
+                None => unsafe { TokenHandle::new_null() },
+            },
+            ..Default::default()
+        };
+        let results = unsafe {
+            self.lib
+                .batch(&paths, pipeline.as_deref(), &out_dir, &options)?
+        };
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                ImageError::NoError => Ok(()),
+                err => Err(err),
+            })
+            .collect())
+    }
+
+    /// Loads JPEG thumbnail embedded in EXIF of image at specified path without decoding the
+    /// image itself. Returns `None` if there is no thumbnail.
+    pub fn open_exif_thumbnail<P: AsRef<Path>>(&self, path: P) -> Result<Option<Image>> {
+        Image::exif_thumbnail(self.lib.clone(), path)
+    }
+
+    /// Forwards errors, warnings and debug messages of library to `logger`, which may be called
+    /// from any thread using library. Replaces previous logger.
+    pub fn set_logger<F>(&self, logger: F)
+    where
+        F: Fn(LogLevel, &str) + Send + Sync + 'static,
+    {
+        *LOGGER.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(logger));
+        unsafe { self.lib.set_log_callback(Some(log_callback)) }
+    }
+
+    /// Stops forwarding log messages of library.
+    pub fn remove_logger(&self) {
+        unsafe { self.lib.set_log_callback(None) }
+        *LOGGER.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Enables or disables debug registry of image handles, reporting use of destroyed images
+    /// to log.
+    pub fn set_debug_handles(&self, enabled: bool) {
+        unsafe { self.lib.set_debug_handles(enabled) }
+    }
+
+    /// Returns number of live images, including layers of documents.
+    pub fn live_handle_count(&self) -> usize {
+        unsafe { self.lib.live_handle_count() }
+    }
+
+    /// Returns addresses of live images with backtraces of their creation, empty if library is
+    /// built without `backtrace` feature.
+    pub fn leaks(&self) -> Vec<(usize, String)> {
+        unsafe { self.lib.dump_leaks() }
+    }
+
+    /// Creates empty layered document with `width` x `height` transparent canvas.
+    pub fn new_document(&self, width: u32, height: u32) -> Result<Document> {
+        let handle = unsafe { self.lib.doc_new(width, height)? };
+        Ok(Document {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates export profile from `json` description of files written by `Image::export`.
+    pub fn export_profile(&self, json: &str) -> Result<ExportProfile> {
+        let json = CString::new(json)?;
+        let handle = unsafe { self.lib.export_profile_create(&json)? };
+        Ok(ExportProfile {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates empty pipeline of operations applied to images at once by `Pipeline::execute`.
+    pub fn pipeline(&self) -> Result<Pipeline> {
+        let handle = unsafe { self.lib.pipeline_create()? };
+        Ok(Pipeline {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Opens OpenRaster (`.ora`) document at specified path.
+    pub fn open_ora<P: AsRef<Path>>(&self, path: P) -> Result<Document> {
+        let path_cstring = path_to_cstring(path)?;
+        let handle = unsafe { self.lib.doc_open_ora(&path_cstring)? };
+        Ok(Document {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Opens raster layers of PSD file at specified path as document.
+    pub fn open_psd<P: AsRef<Path>>(&self, path: P) -> Result<Document> {
+        let path_cstring = path_to_cstring(path)?;
+        let handle = unsafe { self.lib.doc_open_psd(&path_cstring)? };
+        Ok(Document {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Returns detailed message of the last decoding, encoding or I/O error on this thread.
+    pub fn last_error_message(&self) -> Option<String> {
+        unsafe { self.lib.last_error_message() }
+    }
+
+    /// Returns details of the last decoding, encoding or I/O error on this thread.
+    pub fn last_error_details(&self) -> Option<ErrorInfo> {
+        unsafe { self.lib.last_error_details() }.ok()
+    }
+
+    /// Opens new image from `reader`.
+    pub fn open_image_from_reader<R: Read + Seek>(&self, reader: R) -> Result<Image> {
+        Image::from_reader(self.lib.clone(), reader)
+    }
+
+    /// Opens new image from `data:image/...;base64,` URI.
+    pub fn open_image_data_uri(&self, uri: &str) -> Result<Image> {
+        Image::from_data_uri(self.lib.clone(), uri)
+    }
+
+    /// Downloads and opens new image from HTTP(S) `url`. Fails if request takes longer than
+    /// `timeout` or response is larger than `max_bytes`.
+    pub fn open_image_url(&self, url: &str, timeout: Duration, max_bytes: usize) -> Result<Image> {
+        Image::from_url(self.lib.clone(), url, timeout, max_bytes)
+    }
+
+    /// Plugs decoder of format `name` into library, so files with extension `name` and streams
+    /// recognized by `probe` (receiving leading bytes of stream) are decoded by `decode`.
+    /// Decoder returns width, height and RGBA pixels with 8 bits per channel. Plugins stay alive
+    /// until library is unloaded, even if they are replaced.
+    pub fn register_decoder<P, D>(&self, name: &str, probe: P, decode: D) -> Result<()>
+    where
+        P: Fn(&[u8]) -> bool + Send + Sync + 'static,
+        D: Fn(&[u8]) -> Result<(u32, u32, Vec<u8>), PluginError> + Send + Sync + 'static,
+    {
+        let name = CString::new(name)?;
+        let plugin = Box::new(DecoderPlugin {
+            probe: Box::new(probe),
+            decode: Box::new(decode),
+            image_from_raw: self.lib.functions.image_from_raw,
+        });
+        let user_data = Box::into_raw(plugin) as *mut c_void;
+        unsafe { self.lib.register_decoder(&name, user_data) }
+    }
+
+    /// Plugs encoder of format `name` into library, so images saved to files with extension
+    /// `name` are encoded by `encode`, which receives RGBA pixels with 8 bits per channel with
+    /// width and height of image. Plugins stay alive until library is unloaded, even if they are
+    /// replaced.
+    pub fn register_encoder<E>(&self, name: &str, encode: E) -> Result<()>
+    where
+        E: Fn(&[u8], u32, u32, &mut dyn Write) -> Result<(), PluginError> + Send + Sync + 'static,
+    {
+        let name = CString::new(name)?;
+        let plugin: Box<EncoderPlugin> = Box::new(Box::new(encode));
+        let user_data = Box::into_raw(plugin) as *mut c_void;
+        unsafe { self.lib.register_encoder(&name, user_data) }
+    }
+
+    /// Creates new image of `width` x `height` pixels by copying raw pixel `data`.
+    pub fn image_from_raw(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: RawPixelFormat,
+    ) -> Result<Image> {
+        Image::from_raw(self.lib.clone(), data, width, height, format)
+    }
+
+    /// Creates new image of `width` x `height` pixels of `color_type` filled with `fill` RGBA
+    /// color, e.g. canvas to composite images onto.
+    pub fn new_image(
+        &self,
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        fill: [u8; 4],
+    ) -> Result<Image> {
+        let handle = unsafe { self.lib.new_image(width, height, color_type as u32, fill)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates RGBA8 preview image of about 32 pixels on its longer side decoded from ThumbHash
+    /// `hash` of `Image::thumbhash`.
+    pub fn image_from_thumbhash(&self, hash: &[u8]) -> Result<Image> {
+        let handle = unsafe { self.lib.thumbhash_decode(hash)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates RGBA8 image of `width` x `height` pixels filled with gradient of color `stops` of
+    /// `kind`, e.g. for backgrounds and masks. Linear gradient goes along direction of `angle` in
+    /// degrees, clockwise from left to right; radial one ignores it.
+    pub fn gradient(
+        &self,
+        width: u32,
+        height: u32,
+        stops: &[GradientStop],
+        kind: GradientKind,
+        angle: f32,
+    ) -> Result<Image> {
+        let handle = unsafe { self.lib.gradient(width, height, stops, kind, angle)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates RGBA8 checkerboard of `width` x `height` pixels of squares of `cell_size` pixels
+    /// alternating `a` and `b` RGBA colors, e.g. test image or backdrop of transparent images.
+    pub fn checkerboard(
+        &self,
+        width: u32,
+        height: u32,
+        cell_size: u32,
+        a: [u8; 4],
+        b: [u8; 4],
+    ) -> Result<Image> {
+        let handle = unsafe { self.lib.checkerboard(width, height, cell_size, a, b)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates RGBA8 image of SMPTE color bars of `width` x `height` pixels.
+    pub fn color_bars(&self, width: u32, height: u32) -> Result<Image> {
+        let handle = unsafe { self.lib.color_bars(width, height)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Creates L8 image of `width` x `height` pixels of fractal noise of `kind`, e.g. mask or
+    /// texture. Noise is sum of `octaves` layers from 1 to 16, each one of double frequency and
+    /// half amplitude of previous one. `frequency` of the first layer is in cycles per pixel.
+    /// The same `seed` gives the same noise.
+    pub fn noise(
+        &self,
+        width: u32,
+        height: u32,
+        kind: NoiseKind,
+        frequency: f32,
+        octaves: u32,
+        seed: u32,
+    ) -> Result<Image> {
+        let handle = unsafe {
+            self.lib
+                .noise(width, height, kind, frequency, octaves, seed)?
+        };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Packs `images` into RGBA8 sprite sheet at most `max_width` pixels wide with `padding`
+    /// transparent pixels between them. Returns sheet and regions of images in it in order of
+    /// `images`.
+    pub fn pack_sprites(
+        &self,
+        images: &[&Image],
+        max_width: u32,
+        padding: u32,
+    ) -> Result<(Image, Vec<Rect>)> {
+        let handles: Vec<_> = images.iter().map(|image| image.handle).collect();
+        let (handle, placements) = unsafe { self.lib.pack_sprites(&handles, max_width, padding)? };
+        let sheet = Image {
+            lib: self.lib.clone(),
+            handle,
+        };
+        Ok((sheet, placements))
+    }
+
+    /// Opens new image from already opened `file`.
+    pub fn open_image_file(&self, file: &File) -> Result<Image> {
+        Image::from_file(self.lib.clone(), file)
+    }
+
+    /// Decodes image from `reader`, applies `ops` and writes image encoded in source format to
+    /// `writer`.
+    pub fn transcode<R: Read, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+        ops: &[Operation],
+    ) -> Result<()> {
+        let mut stream = Stream { reader, writer };
+        unsafe { self.lib.transcode_stream(&mut stream, ops) }
+    }
+
+    /// Decodes image from `reader`, applies `ops` and writes image encoded in `format` to
+    /// `writer`. Fails with `ImageError::LimitExceeded` once more than `max_input` bytes are read,
+    /// or more than memory limit of `set_limits` if it is zero.
+    pub fn transcode_as<R: Read, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+        ops: &[Operation],
+        format: ImageFormat,
+        max_input: u64,
+    ) -> Result<()> {
+        let mut stream = Stream { reader, writer };
+        unsafe {
+            self.lib
+                .transcode_stream2(&mut stream, ops, format, max_input)
+        }
+    }
+
+    /// Transcodes as `transcode` does, failing with `ImageError::BudgetExceeded` if it takes
+    /// longer or allocates more than `budget`.
+    pub fn transcode_with_budget<R: Read, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+        ops: &[Operation],
+        budget: &OperationBudget,
+    ) -> Result<()> {
+        let mut stream = Stream { reader, writer };
+        unsafe {
+            self.lib
+                .transcode_stream_with_budget(&mut stream, ops, budget)
+        }
+    }
+}
+
+/// Pair of streams passed to library as single `user_data`.
+struct Stream<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W> Read for Stream<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R, W: Write> Write for Stream<R, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// RAII unique image. `clone` copies its pixels, `clone_cow` shares them until either image is
+/// changed.
+///
+/// Image may be moved to another thread, e.g. worker one, since library lets one thread at a
+/// time use image, which Rust guarantees for `Send` types which aren't `Sync`. Only debug registry
+/// of handles, enabled by `ImageFactory::set_debug_handles`, checks that images are used by thread
+/// owning them: with it image is detached by `detach_thread` before it is moved and attached by
+/// `attach_thread` after. `FrozenImage` is read by threads at once instead.
+pub struct Image {
+    lib: Lib,
+    handle: ImageHandle,
+}
+
+impl Image {
+    /// Opens new image at specified path.
+    fn open<P: AsRef<Path>>(lib: Lib, path: P) -> Result<Self> {
+        let path_cstring = path_to_cstring(path)?;
+        let handle = unsafe { lib.open_image(&path_cstring) }?;
+        Ok(Self { lib, handle })
+    }
+
+    /// Opens new image at specified path respecting `options`.
+    fn open_with_options<P: AsRef<Path>>(lib: Lib, path: P, options: &OpenOptions) -> Result<Self> {
+        let path_cstring = path_to_cstring(path)?;
+        let handle = unsafe { lib.open_image_with_options(&path_cstring, options) }?;
+        Ok(Self { lib, handle })
+    }
+
+    /// Loads EXIF thumbnail of image at specified path.
+    fn exif_thumbnail<P: AsRef<Path>>(lib: Lib, path: P) -> Result<Option<Self>> {
+        let path_cstring = path_to_cstring(path)?;
+        match unsafe { lib.exif_thumbnail(&path_cstring) } {
+            Ok(handle) => Ok(Some(Self { lib, handle })),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Opens new image from `reader`.
+    fn from_reader<R: Read + Seek>(lib: Lib, mut reader: R) -> Result<Self> {
+        let handle = unsafe { lib.open_image_with_reader(&mut reader) }?;
+        Ok(Self { lib, handle })
+    }
+
+    /// Creates new image by copying raw pixel `data`.
+    fn from_raw(
+        lib: Lib,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: RawPixelFormat,
+    ) -> Result<Self> {
+        let len = width as usize * height as usize * format.pixel_size();
+        if data.len() != len {
+            let message = format!("raw pixel data size {} doesn't match {}", data.len(), len);
+            return Err(Error::InvalidArgument(message));
+        }
+        let handle = unsafe { lib.image_from_raw(data, width, height, format) }?;
+        Ok(Self { lib, handle })
+    }
+
+    /// Opens new image from `data:image/...;base64,` URI.
+    fn from_data_uri(lib: Lib, uri: &str) -> Result<Self> {
+        let uri = CString::new(uri)?;
+        let handle = unsafe { lib.open_image_data_uri(&uri) }?;
+        Ok(Self { lib, handle })
+    }
+
+    /// Downloads and opens new image from HTTP(S) `url`.
+    fn from_url(lib: Lib, url: &str, timeout: Duration, max_bytes: usize) -> Result<Self> {
+        let url = CString::new(url)?;
+        let timeout_ms = timeout.as_millis().min(u32::MAX as u128) as u32;
+        let handle = unsafe { lib.open_image_url(&url, timeout_ms, max_bytes) }?;
+        Ok(Self { lib, handle })
+    }
+
+    /// Opens new image from already opened `file`.
+    fn from_file(lib: Lib, file: &File) -> Result<Self> {
+        let handle = unsafe { lib.open_image_fd(os_handle(file)) }?;
+        Ok(Self { lib, handle })
+    }
+
+    /// Saves image at specified path.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path_cstring = path_to_cstring(path)?;
+        unsafe { self.lib.save_image(self.handle, &path_cstring) }
+    }
+
+    /// Saves image at specified path, failing with `ImageError::BudgetExceeded` if it takes
+    /// longer or allocates more than `budget`.
+    pub fn save_with_budget<P: AsRef<Path>>(
+        &self,
+        path: P,
+        budget: &OperationBudget,
+    ) -> Result<()> {
+        let path_cstring = path_to_cstring(path)?;
+        unsafe {
+            self.lib
+                .save_with_budget(self.handle, &path_cstring, budget)
+        }
+    }
+
+    /// Saves image at specified path as uncompressed CMYK TIFF file for print, embedding `icc`
+    /// profile if given. Colors are separated naively: black is the complement of the
+    /// brightest channel and alpha is composited over white paper.
+    pub fn save_cmyk_tiff<P: AsRef<Path>>(&self, path: P, icc: Option<&[u8]>) -> Result<()> {
+        let path_cstring = path_to_cstring(path)?;
+        unsafe { self.lib.save_cmyk_tiff(self.handle, &path_cstring, icc) }
+    }
+
+    /// Reorders channels of image: channel `i` becomes channel `mapping[i]`, e.g. `[2, 1, 0, 3]`
+    /// swaps red and blue. Channels are numbered in order of color type of image.
+    pub fn swap_channels(&mut self, mapping: [u8; 4]) -> Result<()> {
+        unsafe { self.lib.swap_channels(self.handle, mapping) }
+    }
+
+    /// Returns number of pixels having each value of `channel` in 256 bins, e.g. to draw
+    /// histogram. Channels are numbered in order of color type of image, and 16-bit values are
+    /// counted by their high bytes.
+    pub fn histogram(&self, channel: u32) -> Result<[u32; 256]> {
+        unsafe { self.lib.histogram(self.handle, channel) }
+    }
+
+    /// Returns number of pixels having each value of `channel` in 65536 bins, keeping full
+    /// precision of 16-bit images. 8-bit value `v` is counted in bin `v * 257`.
+    pub fn histogram16(&self, channel: u32) -> Result<Vec<u32>> {
+        unsafe { self.lib.histogram16(self.handle, channel) }
+    }
+
+    /// Returns minimum, maximum, mean and standard deviation of every channel and mean luma of
+    /// image in `[0, 1]` units of channel range, e.g. to detect under- or over-exposed frames.
+    pub fn stats(&self) -> Result<Stats> {
+        unsafe { self.lib.stats(self.handle) }
+    }
+
+    /// Returns at most `k` dominant colors of image as RGBA with fractions of pixels they
+    /// represent, most common first, e.g. for accent colors of UI. Fully transparent pixels are
+    /// ignored. `k` is in `[1, 256]`.
+    pub fn dominant_colors(&self, k: u32) -> Result<Vec<([u8; 4], f32)>> {
+        unsafe { self.lib.dominant_colors(self.handle, k) }
+    }
+
+    /// Returns average RGBA color of `rect` of image, or of the whole image for `None`. Colors
+    /// are weighted by alpha, so transparent pixels don't affect color.
+    pub fn average_color(&self, rect: Option<Rect>) -> Result<[u8; 4]> {
+        unsafe { Ok(self.lib.average_color(self.handle, rect)?.to_be_bytes()) }
+    }
+
+    /// Returns 64-bit perceptual hash of image computed by `algorithm`, e.g. to find duplicates.
+    /// Hashes of similar images differ in few bits, see `hash_distance`.
+    pub fn perceptual_hash(&self, algorithm: HashAlgorithm) -> Result<u64> {
+        unsafe { self.lib.phash(self.handle, algorithm) }
+    }
+
+    /// Returns similarity of image to `other` image of the same size by `metric`, e.g. PSNR of
+    /// encoded copy against its source.
+    pub fn compare(&self, other: &Image, metric: CompareMetric) -> Result<f64> {
+        unsafe { self.lib.compare(self.handle, other.handle, metric) }
+    }
+
+    /// Returns image highlighting pixels differing from `other` image of the same size in red
+    /// and number of such pixels. Pixels differ if any of their RGBA channels differ by more
+    /// than `tolerance`.
+    pub fn diff(&self, other: &Image, tolerance: u8) -> Result<(Image, u64)> {
+        let (handle, count) = unsafe { self.lib.diff(self.handle, other.handle, tolerance, true)? };
+        let image = Image {
+            lib: self.lib.clone(),
+            handle: handle.expect("diff image is created"),
+        };
+        Ok((image, count))
+    }
+
+    /// Returns number of pixels differing from `other` image of the same size as `diff` does,
+    /// without creating diff image.
+    pub fn differing_pixels(&self, other: &Image, tolerance: u8) -> Result<u64> {
+        let (_, count) = unsafe { self.lib.diff(self.handle, other.handle, tolerance, false)? };
+        Ok(count)
+    }
+
+    /// Returns copy of image with uniform borders cropped away, e.g. white margins of scans.
+    /// Pixels differing from top left pixel by at most `tolerance` in every RGBA channel belong
+    /// to borders. Fails with `ImageError::NotFound` if the whole image is uniform.
+    pub fn trim(&self, tolerance: u8) -> Result<Image> {
+        let handle = unsafe { self.lib.trim(self.handle, tolerance)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Returns whether all pixels of image differ from its top left pixel by at most `tolerance`
+    /// in every RGBA channel, e.g. for blank pages of scans.
+    pub fn is_blank(&self, tolerance: u8) -> Result<bool> {
+        unsafe { self.lib.is_blank(self.handle, tolerance) }
+    }
+
+    /// Returns BlurHash of image with `x_components` x `y_components` components, both in
+    /// `[1, 9]`, for blurred placeholders of web pages.
+    pub fn blurhash(&self, x_components: u32, y_components: u32) -> Result<String> {
+        let hash = unsafe { self.lib.blurhash(self.handle, x_components, y_components) }?;
+        Ok(hash.into_string()?)
+    }
+
+    /// Returns ThumbHash of image, placeholder of at most 25 bytes which keeps alpha and aspect
+    /// ratio, decoded by `ImageFactory::image_from_thumbhash`.
+    pub fn thumbhash(&self) -> Result<Vec<u8>> {
+        unsafe { self.lib.thumbhash(self.handle) }
+    }
+
+    /// Returns builder of operations applied to image at once, e.g.
+    /// `image.pipeline().crop(rect).resize(800, 600, Filter::Lanczos3).run()?`.
+    pub fn pipeline(&mut self) -> PipelineBuilder<'_> {
+        PipelineBuilder {
+            image: self,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Returns copy of image with its own pixels. Unlike `clone`, reports failure, e.g. when
+    /// pixels can't be allocated.
+    pub fn try_clone(&self) -> Result<Self> {
+        let handle = unsafe { self.lib.clone_image(self.handle)? };
+        Ok(Self {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Returns clone of image sharing its pixels, which are copied only once one of images is
+    /// changed, e.g. to keep versions of image for undo cheaply.
+    pub fn clone_cow(&self) -> Result<Self> {
+        let handle = unsafe { self.lib.clone_cow(self.handle)? };
+        Ok(Self {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Returns read-only view of `rect` of image sharing its pixels, e.g. to read image by tiles.
+    /// View shows pixels image has now, later changes of image don't show in it.
+    pub fn view(&self, rect: Rect) -> Result<ImageView> {
+        let handle = unsafe { self.lib.view(self.handle, rect)? };
+        Ok(ImageView(Image {
+            lib: self.lib.clone(),
+            handle,
+        }))
+    }
+
+    /// Returns copy of image converted to `color_type`, e.g. for GPU texture of known layout.
+    /// `ColorType::Rgba32F` isn't supported for images.
+    pub fn convert(&self, color_type: ColorType) -> Result<Self> {
+        let handle = unsafe { self.lib.convert(self.handle, color_type as u32)? };
+        Ok(Self {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Returns frozen clone of image, which may be shared by threads reading it at once.
+    pub fn freeze(&self) -> Result<FrozenImage> {
+        let handle = unsafe { self.lib.freeze(self.handle)? };
+        Ok(FrozenImage(Image {
+            lib: self.lib.clone(),
+            handle,
+        }))
+    }
+
+    /// Blurs image. `sigma` is a measure of how much to blur by. Returns new image.
+    pub fn blur(&self, sigma: f32) -> Result<Self> {
+        let handle = unsafe { self.lib.blur_image(self.handle, sigma)? };
+        Ok(Self {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Blurs image as `blur` does, passing percent of work done to `progress`, which may be
+    /// called from worker threads of library. Blurring fails with `ImageError::Cancelled` once
+    /// `progress` returns `false`.
+    pub fn blur_with_progress<F>(&self, sigma: f32, mut progress: F) -> Result<Self>
+    where
+        F: FnMut(f32) -> bool + Send,
+    {
+        let handle = unsafe {
+            self.lib
+                .blur_with_progress(self.handle, sigma, &mut progress)?
+        };
+        Ok(Self {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Resizes image to `width` x `height` pixels by Lanczos filter, passing percent of work
+    /// done to `progress`, which may be called from worker threads of library. Resizing fails
+    /// with `ImageError::Cancelled` once `progress` returns `false`.
+    pub fn resize_with_progress<F>(&self, width: u32, height: u32, mut progress: F) -> Result<Self>
+    where
+        F: FnMut(f32) -> bool + Send,
+    {
+        let handle = unsafe {
+            self.lib
+                .resize_with_progress(self.handle, width, height, &mut progress)?
+        };
+        Ok(Self {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Blurs image as `blur` does into `dst` instead of new image, e.g. to reuse one image for
+    /// every frame of video. `dst` must be RGBA image of the same size, e.g. returned by `blur`.
+    pub fn blur_into(&self, dst: &mut Image, sigma: f32) -> Result<()> {
+        self.lib.check_same(&dst.lib)?;
+        unsafe { self.lib.blur_into(self.handle, dst.handle, sigma) }
+    }
+
+    /// Resizes image to size of `dst` into it instead of new image, e.g. to reuse one image for
+    /// every frame of video. `dst` must have the same color type.
+    pub fn resize_into(&self, dst: &mut Image) -> Result<()> {
+        self.lib.check_same(&dst.lib)?;
+        unsafe { self.lib.resize_into(self.handle, dst.handle) }
+    }
+
+    /// Flip image horizontally in place.
+    pub fn mirror(&mut self) -> Result<()> {
+        unsafe { self.lib.mirror_image(self.handle) }
+    }
+
+    /// Encodes image in specified format and writes it to already opened `file`.
+    pub fn save_to_file(
+        &self,
+        file: &File,
+        format: ImageFormat,
+        options: &SaveOptions,
+    ) -> Result<()> {
+        unsafe {
+            self.lib
+                .save_image_fd(self.handle, os_handle(file), format, options)
+        }
+    }
+
+    /// Encodes image in specified format and writes it to `writer`.
+    pub fn write_to<W: Write>(
+        &self,
+        mut writer: W,
+        format: ImageFormat,
+        options: &SaveOptions,
+    ) -> Result<()> {
+        unsafe {
+            self.lib
+                .save_image_with_writer(self.handle, &mut writer, format, options)
+        }
+    }
+
+    /// Encodes image in specified format into base64 data URI.
+    pub fn encode_data_uri(&self, format: ImageFormat) -> Result<String> {
+        let uri = unsafe { self.lib.encode_image_data_uri(self.handle, format) }?;
+        Ok(uri.into_string()?)
+    }
+
+    /// Packs image into framebuffer bytes of e-paper display.
+    pub fn pack_eink(&self, format: EinkFormat) -> Result<Vec<u8>> {
+        unsafe { self.lib.pack_eink(self.handle, format) }
+    }
+
+    /// Packs image into display framebuffer bytes.
+    pub fn pack_framebuffer(&self, layout: PixelLayout, row_order: RowOrder) -> Result<Vec<u8>> {
+        unsafe { self.lib.pack_framebuffer(self.handle, layout, row_order) }
+    }
+
+    /// Suggests up to `max` crops of `aspect_w:aspect_h` aspect ratio ranked from best to worst.
+    pub fn suggest_crops(&self, aspect_w: u32, aspect_h: u32, max: usize) -> Result<Vec<Rect>> {
+        unsafe { self.lib.suggest_crops(self.handle, aspect_w, aspect_h, max) }
+    }
+
+    /// Returns thumbnail of `target_w` x `target_h` pixels cropped to their aspect ratio where
+    /// the most interesting part of image is, as crops of `suggest_crops` are ranked.
+    pub fn smart_crop(&self, target_w: u32, target_h: u32) -> Result<Image> {
+        let handle = unsafe { self.lib.smart_crop(self.handle, target_w, target_h)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Finds up to `max` non-overlapping placements of `needle` in image ordered from the best
+    /// one. Empty if needle isn't found.
+    pub fn match_template(
+        &self,
+        needle: &Image,
+        method: MatchMethod,
+        max: usize,
+    ) -> Result<Vec<MatchResult>> {
+        self.lib.check_same(&needle.lib)?;
+        unsafe {
+            self.lib
+                .match_template(self.handle, needle.handle, method, max)
+        }
+    }
+
+    /// Detects up to 1000 strongest corners of image.
+    pub fn detect_keypoints(&self, detector: Detector) -> Result<Vec<Keypoint>> {
+        unsafe { self.lib.detect_keypoints(self.handle, detector) }
+    }
+
+    /// Matches keypoints of image and `other` and estimates offset between them.
+    pub fn match_keypoints(&self, other: &Image) -> Result<Alignment> {
+        self.lib.check_same(&other.lib)?;
+        unsafe { self.lib.match_keypoints(self.handle, other.handle) }
+    }
+
+    /// Estimates transform of `motion` kind mapping points of image to the same points of
+    /// `moving` and returns it with `moving` resampled into frame of image.
+    pub fn align(&self, moving: &Image, motion: Motion) -> Result<(Transform, Image)> {
+        self.lib.check_same(&moving.lib)?;
+        let (transform, warped) = unsafe { self.lib.align(self.handle, moving.handle, motion)? };
+        let warped = Self {
+            lib: self.lib.clone(),
+            handle: warped,
+        };
+        Ok((transform, warped))
+    }
+
+    /// Warps image so that content at points of `src_grid` moves to the same points of
+    /// `dst_grid`. Grids have `cols` x `rows` points stored row by row.
+    pub fn mesh_warp(
+        &mut self,
+        src_grid: &[Point],
+        dst_grid: &[Point],
+        cols: u32,
+        rows: u32,
+    ) -> Result<()> {
+        let count = cols as usize * rows as usize;
+        if src_grid.len() != count || dst_grid.len() != count {
+            return Err(Error::InvalidArgument("grid size != cols * rows".into()));
+        }
+        unsafe {
+            self.lib
+                .mesh_warp(self.handle, src_grid, dst_grid, cols, rows)
+        }
+    }
+
+    /// Writes image to every output of `profile` next to `base_name` path, e.g. `icons/app`
+    /// for `icons/app@2x.png`.
+    pub fn export<P: AsRef<Path>>(&self, profile: &ExportProfile, base_name: P) -> Result<()> {
+        let path_cstring = path_to_cstring(base_name)?;
+        unsafe { self.lib.export(self.handle, profile.handle, &path_cstring) }
+    }
+
+    /// Writes icon set of `kind` rendered from image to `out_dir`, e.g. favicons or iOS app
+    /// icons with their manifest.
+    pub fn generate_icon_set<P: AsRef<Path>>(&self, kind: IconSetKind, out_dir: P) -> Result<()> {
+        let path_cstring = path_to_cstring(out_dir)?;
+        unsafe { self.lib.generate_icon_set(self.handle, kind, &path_cstring) }
+    }
+
+    /// Composes Open Graph or Twitter card over image used as background. Title is wrapped to at
+    /// most 3 lines with the largest font fitting layout.
+    pub fn compose_card(&self, card: &Card) -> Result<Image> {
+        let title = CString::new(card.title)?;
+        for image in card.avatar.iter().chain(&card.logo) {
+            self.lib.check_same(&image.lib)?;
+        }
+        let spec = CardSpec {
+            width: card.width,
+            height: card.height,
+            layout: card.layout as u32,
+            title: RawStr(title.as_ptr()),
+            avatar: card
+                .avatar
+                .map_or(unsafe { ImageHandle::new_null() }, |i| i.handle),
+            logo: card
+                .logo
+                .map_or(unsafe { ImageHandle::new_null() }, |i| i.handle),
+            text_color: card.text_color,
+            scrim_color: card.scrim_color,
+            ..Default::default()
+        };
+        let handle = unsafe { self.lib.compose_card(self.handle, &spec)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Detaches image from current thread before it is passed to another thread, which attaches
+    /// it by `attach_thread`. Ownership is checked only with debug registry of handles enabled.
+    pub fn detach_thread(&self) -> Result<()> {
+        unsafe { self.lib.detach_thread(self.handle) }
+    }
+
+    /// Attaches image detached by `detach_thread` to current thread.
+    pub fn attach_thread(&self) -> Result<()> {
+        unsafe { self.lib.attach_thread(self.handle) }
+    }
+
+    /// Applies operation `op` of edit stacks, e.g. `"blur"`, to copy of image and returns it.
+    /// `params` are JSON object of parameters, e.g. `{"sigma": 2.5}`, or `key=value` pairs,
+    /// e.g. `sigma=2.5`.
+    pub fn apply(&self, op: &str, params: &str) -> Result<Self> {
+        let op = CString::new(op)?;
+        let params = CString::new(params)?;
+        let handle = unsafe { self.lib.apply(self.handle, &op, &params)? };
+        Ok(Self {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Applies operation `op` with `params`, as `apply` does, to copy of image downscaled to fit
+    /// `max_size` x `max_size` pixels, scaling parameters measured in pixels with it. Returns
+    /// small preview of result quickly, e.g. while slider of editor is dragged.
+    pub fn apply_preview(&self, op: &str, params: &str, max_size: u32) -> Result<Self> {
+        let op = CString::new(op)?;
+        let params = CString::new(params)?;
+        let handle = unsafe {
+            self.lib
+                .apply_preview(self.handle, &op, &params, max_size)?
+        };
+        Ok(Self {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Starts applying operation `op` with `params`, as `apply` takes them, to copy of image on
+    /// worker thread of library.
+    pub fn submit(&self, op: &str, params: &str) -> Result<Job> {
+        let op = CString::new(op)?;
+        let params = CString::new(params)?;
+        let handle = unsafe { self.lib.submit(self.handle, &op, &params)? };
+        Ok(Job {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Starts job as `submit` does, failing it with `ImageError::Cancelled` once `token` is
+    /// cancelled.
+    pub fn submit_with_token(&self, op: &str, params: &str, token: &CancelToken) -> Result<Job> {
+        let op = CString::new(op)?;
+        let params = CString::new(params)?;
+        self.lib.check_same(&token.lib)?;
+        let handle = unsafe {
+            self.lib
+                .submit_with_token(self.handle, &op, &params, token.handle)?
+        };
+        Ok(Job {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Starts saving copy of image at specified path on worker thread of library. Result of job
+    /// is `None`.
+    pub fn submit_save<P: AsRef<Path>>(&self, path: P) -> Result<Job> {
+        let params = path_params(path)?;
+        let op = CString::new("save")?;
+        let handle = unsafe { self.lib.submit(self.handle, &op, &params)? };
+        Ok(Job {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+
+    /// Returns value of EXIF `tag` (see `exif_tag`) formatted as string, or `None` if image has
+    /// no such tag.
+    pub fn exif(&self, tag: u16) -> Result<Option<String>> {
+        let value = unsafe { self.lib.exif_get(self.handle, tag) };
+        match value {
+            Ok(v) => Ok(Some(v.into_string()?)),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Draws antialiased line from `from` to `to` of `thickness` pixels with round caps in `rgba`
+    /// color blended by its alpha. Centers of pixels are at half-integer coordinates.
+    pub fn draw_line(
+        &mut self,
+        from: Point,
+        to: Point,
+        thickness: f32,
+        rgba: [u8; 4],
+    ) -> Result<()> {
+        unsafe { self.lib.draw_line(self.handle, from, to, thickness, rgba) }
+    }
+
+    /// Draws antialiased rectangle at `x`, `y` of `width` x `height` pixels, e.g. bounding box.
+    pub fn draw_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        style: DrawStyle,
+    ) -> Result<()> {
+        unsafe { self.lib.draw_rect(self.handle, x, y, width, height, style) }
+    }
+
+    /// Draws antialiased circle of `radius` pixels centered at `center`.
+    pub fn draw_circle(&mut self, center: Point, radius: f32, style: DrawStyle) -> Result<()> {
+        unsafe { self.lib.draw_circle(self.handle, center, radius, style) }
+    }
+
+    /// Draws antialiased closed polygon of at least 3 `points`. Inside of self-intersecting
+    /// polygon is given by non-zero winding rule.
+    pub fn draw_polygon(&mut self, points: &[Point], style: DrawStyle) -> Result<()> {
+        unsafe { self.lib.draw_polygon(self.handle, points, style) }
+    }
+
+    /// Makes corners of image transparent outside arcs of `radius` pixels with antialiased edges,
+    /// e.g. for avatars. Image is converted to RGBA of its bit depth.
+    pub fn round_corners(&mut self, radius: f32) -> Result<()> {
+        unsafe { self.lib.round_corners(self.handle, radius) }
+    }
+
+    /// Makes image transparent outside of circle of diameter of its shorter side in its center
+    /// with antialiased edge. Image is converted to RGBA of its bit depth.
+    pub fn circle_mask(&mut self) -> Result<()> {
+        unsafe { self.lib.circle_mask(self.handle) }
+    }
+
+    /// Multiplies alpha of image by luminance of `mask` image of the same size, or by its
+    /// complement if `invert` is set: white parts of mask keep image and black ones make it
+    /// transparent. Image is converted to RGBA of its bit depth.
+    pub fn apply_mask(&mut self, mask: &Image, invert: bool) -> Result<()> {
+        unsafe { self.lib.apply_mask(self.handle, mask.handle, invert) }
+    }
+
+    /// Blends `mark` image, e.g. logo, over image at `anchor`, `margin` pixels away from edges.
+    /// Alpha of mark is multiplied by `opacity` in `[0, 1]`. Positive `scale` resizes mark to
+    /// `scale` times width of image keeping its aspect ratio, zero keeps its size.
+    pub fn watermark(
+        &mut self,
+        mark: &Image,
+        anchor: Anchor,
+        margin: u32,
+        opacity: f32,
+        scale: f32,
+    ) -> Result<()> {
+        unsafe {
+            self.lib
+                .watermark(self.handle, mark.handle, anchor, margin, opacity, scale)
+        }
+    }
+
+    /// Draws compositional guides described by `spec` over image.
+    pub fn draw_guides(&mut self, spec: &GuideSpec) -> Result<()> {
+        unsafe { self.lib.draw_guides(self.handle, spec) }
+    }
+
+    /// Estimates standard deviation of noise of RGBA channels in `[0, 1]` units of channel range.
+    pub fn estimate_noise(&self) -> Result<[f64; 4]> {
+        unsafe { self.lib.estimate_noise(self.handle) }
+    }
+
+    /// Estimates sharpness of image as standard deviation of Laplacian of its luma in `[0, 1]`
+    /// units of channel range. Blurry images have lower values.
+    pub fn sharpness(&self) -> Result<f64> {
+        unsafe { self.lib.sharpness(self.handle) }
+    }
+
+    /// Sets EXIF `tag` (see `exif_tag`) to `value` of type `ty`. Values are written the same way
+    /// as returned by `exif`. Tags are saved with image if `SaveOptions::keep_metadata` is set.
+    pub fn set_exif(&mut self, tag: u16, ty: ExifType, value: &str) -> Result<()> {
+        let value = CString::new(value)?;
+        unsafe { self.lib.exif_set(self.handle, tag, ty, &value) }
+    }
+
+    /// Rotates and flips image as its EXIF orientation prescribes.
+    pub fn auto_orient(&mut self) -> Result<()> {
+        unsafe { self.lib.auto_orient(self.handle) }
+    }
+
+    /// Denoises image with automatically picked strength.
+    pub fn auto_denoise(&mut self) -> Result<()> {
+        unsafe { self.lib.auto_denoise(self.handle) }
+    }
+
+    /// Sharpens image with automatically picked strength.
+    pub fn auto_sharpen(&mut self) -> Result<()> {
+        unsafe { self.lib.auto_sharpen(self.handle) }
+    }
+
+    /// Corrects lateral chromatic aberration by radially rescaling red and blue channels. Scale
+    /// `0` means it is estimated automatically.
+    pub fn correct_ca(&mut self, red_scale: f32, blue_scale: f32) -> Result<()> {
+        unsafe { self.lib.correct_ca(self.handle, red_scale, blue_scale) }
+    }
+
+    /// Returns ICC profile embedded in source file, or `None` if there is no profile.
+    pub fn icc(&self) -> Result<Option<Vec<u8>>> {
+        match unsafe { self.lib.icc_get(self.handle) } {
+            Ok(icc) => Ok(Some(icc)),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces ICC profile of image without converting pixels. Empty `icc` removes profile.
+    pub fn set_icc(&mut self, icc: &[u8]) -> Result<()> {
+        unsafe { self.lib.icc_set(self.handle, icc) }
+    }
+
+    /// Converts image from its ICC profile to sRGB and removes profile. Requires library built
+    /// with `icc` feature.
+    pub fn convert_to_srgb(&mut self) -> Result<()> {
+        unsafe { self.lib.convert_to_srgb(self.handle) }
+    }
+
+    /// Removes purple fringes at high-contrast edges by desaturating pixels with hue within
+    /// `hue_range` degrees around purple by up to `strength` in `[0, 1]`.
+    pub fn defringe(&mut self, strength: f32, hue_range: f32) -> Result<()> {
+        unsafe { self.lib.defringe(self.handle, strength, hue_range) }
+    }
+
+    /// Returns width, height, color type and bit depth of image.
+    pub fn info(&self) -> Result<ImageInfo> {
+        unsafe { self.lib.info(self.handle) }
+    }
+
+    /// Returns RGBA color of pixel at `x`, `y`, e.g. for eyedropper. 16-bit channels are reduced
+    /// to 8 bits.
+    pub fn pixel(&self, x: u32, y: u32) -> Result<[u8; 4]> {
+        unsafe { Ok(self.lib.get_pixel(self.handle, x, y)?.to_be_bytes()) }
+    }
+
+    /// Sets pixel at `x`, `y` to RGBA `color`.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) -> Result<()> {
+        let rgba = u32::from_be_bytes(color);
+        unsafe { self.lib.set_pixel(self.handle, x, y, rgba) }
+    }
+
+    /// Copies pixels converted to `color_type` into `buffer` with rows `stride` bytes apart, `0`
+    /// for rows without padding, e.g. into texture staging buffer.
+    pub fn read_pixels(
+        &self,
+        color_type: ColorType,
+        buffer: &mut [u8],
+        stride: usize,
+    ) -> Result<()> {
+        unsafe {
+            self.lib
+                .read_pixels(self.handle, buffer, color_type as u32, stride)
+        }
+    }
+
+    /// Replaces every pixel of image with RGBA color `f` returns for its coordinates and color,
+    /// e.g. for one-off effects. With `parallel`, rows are mapped on worker threads of library.
+    pub fn map_pixels<F>(&mut self, parallel: bool, f: F) -> Result<()>
+    where
+        F: Fn(u32, u32, [u8; 4]) -> [u8; 4] + Sync,
+    {
+        unsafe { self.lib.map_pixels(self.handle, parallel, &f) }
+    }
+
+    /// Passes index and bytes of every row in color type of image to `f`, e.g. to stream image
+    /// to printer. Stops with `ImageError::Cancelled` once `f` returns `false`.
+    pub fn for_each_row<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(u32, &[u8]) -> bool,
+    {
+        unsafe { self.lib.for_each_row(self.handle, &mut f) }
+    }
+
+    /// Locks pixels of image for reading and writing them directly, e.g. to blit image to
+    /// framebuffer. Image is unlocked once returned guard is dropped.
+    pub fn lock_pixels(&mut self) -> Result<LockedPixels<'_>> {
+        let lock = unsafe { self.lib.lock_pixels(self.handle)? };
+        Ok(LockedPixels { image: self, lock })
+    }
+
+    /// Converts image to `color_type` and locks its pixels with stride rounded up to multiple of
+    /// `row_alignment`, e.g. `ColorType::Bgra8` for Win32 bitmaps. Conversion is kept after
+    /// unlocking.
+    pub fn lock_pixels_as(
+        &mut self,
+        color_type: ColorType,
+        row_alignment: u32,
+    ) -> Result<LockedPixels<'_>> {
+        let lock = unsafe {
+            self.lib
+                .lock_pixels_as(self.handle, color_type as u32, row_alignment)?
+        };
+        Ok(LockedPixels { image: self, lock })
+    }
+
+    /// Returns horizontal and vertical resolution in dots per inch, or `None` if source file
+    /// doesn't specify it.
+    pub fn dpi(&self) -> Result<Option<(f64, f64)>> {
+        match unsafe { self.lib.get_dpi(self.handle) } {
+            Ok(dpi) => Ok(Some(dpi)),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets horizontal and vertical resolution in dots per inch written to JPEG and PNG.
+    pub fn set_dpi(&mut self, x: f64, y: f64) -> Result<()> {
+        unsafe { self.lib.set_dpi(self.handle, x, y) }
+    }
+
+    /// Reconstructs channels clipped in highlights from unclipped ones with `strength` in
+    /// `[0, 1]`.
+    pub fn recover_highlights(&mut self, strength: f32) -> Result<()> {
+        unsafe { self.lib.recover_highlights(self.handle, strength) }
+    }
+
+    /// Returns keywords of PNG text chunks.
+    pub fn png_text_keys(&self) -> Result<Vec<String>> {
+        let keys = unsafe { self.lib.png_text_keys(self.handle)? };
+        Ok(keys
+            .into_iter()
+            .map(|k| k.into_string())
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Returns value of PNG text with `keyword`, or `None` if there is no such text.
+    pub fn png_text(&self, keyword: &str) -> Result<Option<String>> {
+        let keyword = CString::new(keyword)?;
+        match unsafe { self.lib.png_text_get(self.handle, &keyword) } {
+            Ok(v) => Ok(Some(v.into_string()?)),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets PNG text with `keyword` to `value`, or removes it if `value` is `None`. Text is saved
+    /// with PNG images if `SaveOptions::keep_metadata` is set.
+    pub fn set_png_text(&mut self, keyword: &str, value: Option<&str>) -> Result<()> {
+        let keyword = CString::new(keyword)?;
+        let value = value.map(CString::new).transpose()?;
+        unsafe {
+            self.lib
+                .png_text_set(self.handle, &keyword, value.as_deref())
+        }
+    }
+
+    /// Brightens (positive amounts) or darkens (negative amounts) shadows and highlights by
+    /// `shadows` and `highlights` in `[-1, 1]`. Tones are masked by luma blurred with `radius`.
+    pub fn shadows_highlights(&mut self, shadows: f32, highlights: f32, radius: f32) -> Result<()> {
+        unsafe {
+            self.lib
+                .shadows_highlights(self.handle, shadows, highlights, radius)
+        }
+    }
+
+    /// Adjusts hue, saturation and luminance of color bands by `bands`.
+    pub fn hsl_adjust(&mut self, bands: &[HslBand]) -> Result<()> {
+        unsafe { self.lib.hsl_adjust(self.handle, bands) }
+    }
+
+    /// Returns XMP packet of JPEG or TIFF source file, or `None` if there is none.
+    pub fn xmp(&self) -> Result<Option<String>> {
+        match unsafe { self.lib.xmp_get(self.handle) } {
+            Ok(xmp) => Ok(Some(xmp.into_string()?)),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns values of IPTC `dataset` (see `iptc_dataset`) of JPEG or TIFF source file.
+    pub fn iptc(&self, dataset: u32) -> Result<Vec<String>> {
+        match unsafe { self.lib.iptc_get(self.handle, dataset) } {
+            Ok(values) => Ok(values
+                .into_iter()
+                .map(|v| v.into_string())
+                .collect::<Result<_, _>>()?),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces colors close to `from` with `to` within `tolerance` in `[0, 1]`, keeping
+    /// shading. If `preserve_luminosity` is set, only hue and saturation are changed.
+    pub fn replace_color(
+        &mut self,
+        from: [u8; 4],
+        to: [u8; 4],
+        tolerance: f32,
+        preserve_luminosity: bool,
+    ) -> Result<()> {
+        let (from, to) = (u32::from_be_bytes(from), u32::from_be_bytes(to));
+        unsafe {
+            self.lib
+                .replace_color(self.handle, from, to, tolerance, preserve_luminosity)
+        }
+    }
+
+    /// Removes EXIF (including GPS), XMP, IPTC, ICC profile, DPI and PNG text, so only pixels
+    /// are written on save. Image is auto-oriented first.
+    pub fn strip_metadata(&mut self) -> Result<()> {
+        unsafe { self.lib.strip_metadata(self.handle) }
+    }
+
+    /// Grades image with lift of `shadows`, gamma of `midtones` and gain of `highlights` colors.
+    /// Offset of color channels from `0x80` gray sets direction of adjustment and alpha sets
+    /// its strength. Positive `balance` in `[-1, 1]` favours highlights.
+    pub fn split_tone(
+        &mut self,
+        shadows: [u8; 4],
+        midtones: [u8; 4],
+        highlights: [u8; 4],
+        balance: f32,
+    ) -> Result<()> {
+        let [shadows, midtones, highlights] =
+            [shadows, midtones, highlights].map(u32::from_be_bytes);
+        unsafe {
+            self.lib
+                .split_tone(self.handle, shadows, midtones, highlights, balance)
+        }
+    }
+
+    /// Applies curves, colors and grain of film `stock` blended with original by `strength` in
+    /// `[0, 1]`.
+    pub fn film_emulation(&mut self, stock: FilmStock, strength: f32) -> Result<()> {
+        unsafe { self.lib.film_emulation(self.handle, stock, strength) }
+    }
+
+    /// Enables journal keeping up to `depth` states of image before edits for `undo` and
+    /// `redo`. Zero `depth` disables journal.
+    pub fn set_journal_depth(&mut self, depth: u32) -> Result<()> {
+        unsafe { self.lib.set_journal_depth(self.handle, depth) }
+    }
+
+    /// Reverts the last edit. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self) -> Result<bool> {
+        match unsafe { self.lib.undo(self.handle) } {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reapplies the last undone edit. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self) -> Result<bool> {
+        match unsafe { self.lib.redo(self.handle) } {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns numbers of edits available for undo and redo.
+    pub fn history_len(&self) -> Result<(usize, usize)> {
+        unsafe { self.lib.history_len(self.handle) }
+    }
+
+    /// Returns JSON edit stack listing edits applied to image, to store it as recipe.
+    pub fn edit_stack(&self) -> Result<String> {
+        unsafe { self.lib.export_edit_stack(self.handle) }
+    }
+
+    /// Applies edits of JSON edit stack returned by `edit_stack`.
+    pub fn apply_edit_stack(&mut self, json: &str) -> Result<()> {
+        unsafe { self.lib.apply_edit_stack(self.handle, json) }
+    }
+
+    /// Attaches annotation with JSON markup `json` to region `rect` of image.
+    pub fn add_annotation(&mut self, rect: Rect, json: &str) -> Result<()> {
+        let json = CString::new(json)?;
+        unsafe { self.lib.add_annotation(self.handle, rect, &json) }
+    }
+
+    /// Returns annotated regions of image with their JSON markup in order of addition.
+    pub fn annotations(&self) -> Result<Vec<(Rect, String)>> {
+        let annotations = unsafe { self.lib.annotations(self.handle)? };
+        let annotations = annotations
+            .into_iter()
+            .map(|(rect, json)| Ok((rect, json.into_string()?)));
+        annotations.collect()
+    }
+
+    /// Removes annotation at `index`. Returns `false` if there is no such annotation.
+    pub fn remove_annotation(&mut self, index: usize) -> Result<bool> {
+        match unsafe { self.lib.remove_annotation(self.handle, index) } {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Draws outlines of annotated regions over image with `rgba` color.
+    pub fn burn_annotations(&mut self, rgba: [u8; 4], line_width: u32) -> Result<()> {
+        let color = u32::from_be_bytes(rgba);
+        unsafe { self.lib.burn_annotations(self.handle, color, line_width) }
+    }
+
+    /// Returns `true` if library recognizes image handle as live.
+    pub fn is_valid(&self) -> bool {
+        unsafe { self.lib.is_valid(self.handle) }
+    }
+
+    /// Encodes image in specified format.
+    pub fn encode(&self, format: ImageFormat) -> Result<Vec<u8>> {
+        unsafe { self.lib.encode_image(self.handle, format) }
+    }
+
+    /// Encodes image as `encode` does, passing percent of work done to `progress`. Encoding
+    /// fails with `ImageError::Cancelled` if `progress` returns `false` before it finishes.
+    pub fn encode_with_progress<F>(&self, format: ImageFormat, mut progress: F) -> Result<Vec<u8>>
+    where
+        F: FnMut(f32) -> bool + Send,
+    {
+        // Library encodes image twice, to measure and to write it, each time from 0 to 100.
+        let (mut pass, mut last) = (0.0, 0.0);
+        let mut halves = |percent: f32| {
+            if percent < last {
+                pass = 1.0;
+            }
+            last = percent;
+            progress((pass * 100.0 + percent) / 2.0)
+        };
+        unsafe {
+            self.lib
+                .encode_with_progress(self.handle, format, &mut halves)
+        }
+    }
+}
+
+impl Clone for Image {
+    fn clone(&self) -> Self {
+        self.try_clone().expect("image is cloned")
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            self.lib.destroy_image(self.handle);
+        }
+    }
+}
+
+/// Image which is never changed, created by `Image::freeze`. Unlike `Image`, it may be shared by
+/// threads, e.g. in `Arc`, and read by them at once. Methods of `Image` which don't change it,
+/// e.g. `save`, `encode` and `blur`, are available through `Deref`; changing ones fail.
+pub struct FrozenImage(Image);
+
+// Library allows image to be used by one thread at a time.
+unsafe impl Send for Image {}
+
+// Library allows frozen images to be read by any thread at once.
+unsafe impl Send for FrozenImage {}
+unsafe impl Sync for FrozenImage {}
+
+/// Read-only view of region of image created by `Image::view`, sharing its pixels. Methods of
+/// `Image` which don't change it, e.g. `pixel`, `read_pixels` and `blur`, are available through
+/// `Deref`; `try_clone` and `clone_cow` give changeable copy of its region.
+pub struct ImageView(Image);
+
+impl std::ops::Deref for ImageView {
+    type Target = Image;
+
+    fn deref(&self) -> &Image {
+        &self.0
+    }
+}
+
+impl FrozenImage {
+    /// Returns copy-on-write clone of image which can be changed.
+    pub fn thaw(&self) -> Result<Image> {
+        self.0.clone_cow()
+    }
+}
+
+impl std::ops::Deref for FrozenImage {
+    type Target = Image;
+
+    fn deref(&self) -> &Image {
+        &self.0
+    }
+}
+
+impl LayerProps {
+    /// Creates properties of visible layer at `(x, y)`.
+    pub fn new(x: i32, y: i32, opacity: f32, blend_mode: BlendMode) -> Self {
+        Self {
+            x,
+            y,
+            opacity,
+            blend_mode: blend_mode as u32,
+            ..Self::default()
+        }
+    }
+}
+
+/// Safe interface of layered document. Layers are stacked bottom to top.
+pub struct Document {
+    lib: Lib,
+    handle: DocumentHandle,
+}
+
+impl Document {
+    /// Adds `image` as layer on top of document. Document takes ownership of image.
+    pub fn add_layer(&mut self, image: Image, props: &LayerProps) -> Result<()> {
+        self.lib.check_same(&image.lib)?;
+        unsafe { self.lib.doc_add_layer(self.handle, image.handle, props)? };
+        // Image is owned by document now, so it must not be destroyed.
+        let image = std::mem::ManuallyDrop::new(image);
+        drop(unsafe { std::ptr::read(&image.lib) });
+        Ok(())
+    }
+
+    /// Returns number of layers.
+    pub fn layer_count(&self) -> Result<usize> {
+        unsafe { self.lib.doc_layer_count(self.handle) }
+    }
+
+    /// Returns properties of layer at `index`, or `None` if there is no such layer.
+    pub fn layer(&self, index: usize) -> Result<Option<LayerProps>> {
+        match unsafe { self.lib.doc_get_layer(self.handle, index) } {
+            Ok(props) => Ok(Some(props)),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets properties of layer at `index`. Returns `false` if there is no such layer.
+    pub fn set_layer(&mut self, index: usize, props: &LayerProps) -> Result<bool> {
+        match unsafe { self.lib.doc_set_layer(self.handle, index, props) } {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns image of layer at `index` for editing, or `None` if there is no such layer.
+    pub fn layer_image(&mut self, index: usize) -> Result<Option<LayerImage<'_>>> {
+        let handle = match unsafe { self.lib.doc_layer_image(self.handle, index) } {
+            Ok(handle) => handle,
+            Err(e) if e.code() == Some(ImageError::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let image = Image {
+            lib: self.lib.clone(),
+            handle,
+        };
+        Ok(Some(LayerImage {
+            image: std::mem::ManuallyDrop::new(image),
+            _document: std::marker::PhantomData,
+        }))
+    }
+
+    /// Removes layer at `index` and returns its image, or `None` if there is no such layer.
+    pub fn remove_layer(&mut self, index: usize) -> Result<Option<Image>> {
+        match unsafe { self.lib.doc_remove_layer(self.handle, index) } {
+            Ok(handle) => Ok(Some(Image {
+                lib: self.lib.clone(),
+                handle,
+            })),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Moves layer at index `from` to index `to`. Returns `false` if there is no such layer.
+    pub fn move_layer(&mut self, from: usize, to: usize) -> Result<bool> {
+        match unsafe { self.lib.doc_move_layer(self.handle, from, to) } {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == Some(ImageError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Saves document at specified path as OpenRaster archive.
+    pub fn save_ora<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path_cstring = path_to_cstring(path)?;
+        unsafe { self.lib.doc_save_ora(self.handle, &path_cstring) }
+    }
+
+    /// Composites visible layers into new image.
+    pub fn flatten(&self) -> Result<Image> {
+        let handle = unsafe { self.lib.doc_flatten(self.handle)? };
+        Ok(Image {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+}
+
+impl Drop for Document {
+    fn drop(&mut self) {
+        unsafe {
+            self.lib.doc_destroy(self.handle);
+        }
+    }
+}
+
+/// Pixels of image locked by `Image::lock_pixels`, unlocked once dropped.
+pub struct LockedPixels<'a> {
+    image: &'a mut Image,
+    lock: bindings::PixelLock,
+}
+
+impl LockedPixels<'_> {
+    pub fn width(&self) -> u32 {
+        self.lock.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.lock.height
+    }
+
+    /// Distance between starts of adjacent rows in bytes.
+    pub fn stride(&self) -> usize {
+        self.lock.stride
+    }
+
+    /// `None` for color types unknown to these bindings.
+    pub fn color_type(&self) -> Option<ColorType> {
+        ColorType::from_raw(self.lock.format)
+    }
+
+    /// Bytes of rows from top to bottom, 16-bit channels are in native byte order.
+    pub fn bytes(&self) -> &[u8] {
+        let len = self.lock.stride * self.lock.height as usize;
+        match len {
+            0 => &[],
+            _ => unsafe { std::slice::from_raw_parts(self.lock.pixels, len) },
+        }
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.lock.stride * self.lock.height as usize;
+        match len {
+            0 => &mut [],
+            _ => unsafe { std::slice::from_raw_parts_mut(self.lock.pixels, len) },
+        }
+    }
+}
+
+impl Drop for LockedPixels<'_> {
+    fn drop(&mut self) {
+        let _ = unsafe { self.image.lib.unlock_pixels(self.image.handle) };
+    }
+}
+
+/// Image of document layer, owned by document.
+pub struct LayerImage<'a> {
+    image: std::mem::ManuallyDrop<Image>,
+    _document: std::marker::PhantomData<&'a mut Document>,
+}
+
+impl std::ops::Deref for LayerImage<'_> {
+    type Target = Image;
+
+    fn deref(&self) -> &Image {
+        &self.image
+    }
+}
+
+impl std::ops::DerefMut for LayerImage<'_> {
+    fn deref_mut(&mut self) -> &mut Image {
+        &mut self.image
+    }
+}
+
+impl Drop for LayerImage<'_> {
+    fn drop(&mut self) {
+        // Image is destroyed by document, only library reference is released.
+        unsafe { std::ptr::drop_in_place(&mut self.image.lib) }
+    }
+}
+
+/// Set of files written for image by `Image::export`, e.g. `@1x`, `@2x` and `@3x` icons.
+pub struct ExportProfile {
+    lib: Lib,
+    handle: ExportProfileHandle,
+}
+
+impl Drop for ExportProfile {
+    fn drop(&mut self) {
+        unsafe {
+            self.lib.export_profile_destroy(self.handle);
+        }
+    }
+}
+
+/// Operations applied to image at once reusing buffers, created by `ImageFactory::pipeline`.
+/// Reuse pipeline for many images to reuse its buffers too.
+pub struct Pipeline {
+    lib: Lib,
+    handle: PipelineHandle,
+}
+
+impl Pipeline {
+    /// Appends crop keeping `rect` of image given by previous operations.
+    pub fn crop(&mut self, rect: Rect) -> Result<&mut Self> {
+        unsafe { self.lib.pipeline_add_crop(self.handle, rect)? };
+        Ok(self)
+    }
+
+    /// Appends resize to `width` x `height`, as `Image::resize_with_progress` does.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<&mut Self> {
+        unsafe { self.lib.pipeline_add_resize(self.handle, width, height)? };
+        Ok(self)
+    }
+
+    /// Appends gaussian blur, as `Image::blur` does.
+    pub fn blur(&mut self, sigma: f32) -> Result<&mut Self> {
+        unsafe { self.lib.pipeline_add_blur(self.handle, sigma)? };
+        Ok(self)
+    }
+
+    /// Appends horizontal flip.
+    pub fn mirror(&mut self) -> Result<&mut Self> {
+        unsafe { self.lib.pipeline_add_mirror(self.handle)? };
+        Ok(self)
+    }
+
+    /// Applies operations to `image` in place, in order they were appended.
+    pub fn execute(&mut self, image: &mut Image) -> Result<()> {
+        self.lib.check_same(&image.lib)?;
+        unsafe { self.lib.pipeline_execute(self.handle, image.handle) }
+    }
+
+    /// Decodes image from `reader`, applies operations and writes image encoded in `format` to
+    /// `writer`. Fails with `ImageError::LimitExceeded` once more than `max_input` bytes are read,
+    /// or more than memory limit of `ImageFactory::set_limits` if it is zero. With cache enabled
+    /// by `ImageFactory::enable_cache`, result of the same input is written from cache.
+    pub fn transcode<R: Read, W: Write>(
+        &mut self,
+        reader: R,
+        writer: W,
+        format: ImageFormat,
+        max_input: u64,
+    ) -> Result<()> {
+        let mut stream = Stream { reader, writer };
+        unsafe {
+            self.lib
+                .pipeline_transcode(self.handle, &mut stream, format, max_input)
+        }
+    }
+
+    /// Applies operations to TIFF file at `src` too large to be loaded, writing result to TIFF
+    /// file at `dst` as it's computed. Passes percent of work done to `progress`, and fails with
+    /// `ImageError::Cancelled` once it returns `false`.
+    pub fn execute_tiled<P, Q, F>(&mut self, src: P, dst: Q, mut progress: F) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(f32) -> bool + Send,
+    {
+        let src = path_to_cstring(src)?;
+        let dst = path_to_cstring(dst)?;
+        unsafe {
+            self.lib
+                .pipeline_execute_tiled(self.handle, &src, &dst, &mut progress)
+        }
+    }
+
+    /// Applies operations to `image` in place, failing with `ImageError::BudgetExceeded` if
+    /// images they allocate exceed `budget`, leaving image unchanged, or if they take longer.
+    pub fn execute_with_budget(
+        &mut self,
+        image: &mut Image,
+        budget: &OperationBudget,
+    ) -> Result<()> {
+        self.lib.check_same(&image.lib)?;
+        unsafe {
+            self.lib
+                .pipeline_execute_with_budget(self.handle, image.handle, budget)
+        }
+    }
+
+    /// Applies operations to TIFF file as `execute_tiled` does, failing with
+    /// `ImageError::BudgetExceeded` if it takes longer or keeps more rows than `budget`.
+    pub fn execute_tiled_with_budget<P, Q, F>(
+        &mut self,
+        src: P,
+        dst: Q,
+        budget: &OperationBudget,
+        mut progress: F,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(f32) -> bool + Send,
+    {
+        let src = path_to_cstring(src)?;
+        let dst = path_to_cstring(dst)?;
+        unsafe {
+            self.lib.pipeline_execute_tiled_with_budget(
+                self.handle,
+                &src,
+                &dst,
+                budget,
+                &mut progress,
+            )
+        }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.lib.pipeline_destroy(self.handle);
+        }
+    }
+}
+
+/// Paint of shapes of `Image::draw_rect`, `Image::draw_circle` and `Image::draw_polygon`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DrawStyle {
+    /// Fills inside of shape with RGBA color.
+    Fill([u8; 4]),
+    /// Strokes outline of shape with line of RGBA color and thickness in pixels centered on it.
+    Stroke([u8; 4], f32),
+}
+
+impl DrawStyle {
+    /// Returns thickness, color as `0xRRGGBBAA` and fill flag drawing functions take.
+    fn to_raw(self) -> (f32, u32, bool) {
+        match self {
+            DrawStyle::Fill(rgba) => (0.0, u32::from_be_bytes(rgba), true),
+            DrawStyle::Stroke(rgba, thickness) => (thickness, u32::from_be_bytes(rgba), false),
+        }
+    }
+}
+
+/// Resampling filter of resize.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Filter {
+    /// Lanczos filter with window of 3, the one library resizes by.
+    Lanczos3,
+}
+
+/// Operation of `PipelineBuilder`.
+#[derive(Copy, Clone)]
+enum Step {
+    Crop(Rect),
+    Resize(u32, u32),
+    Blur(f32),
+    Mirror,
+}
+
+/// Operations collected by `Image::pipeline`, e.g.
+/// `image.pipeline().blur(2.0).resize(800, 600, Filter::Lanczos3).run()?`, and applied to image
+/// at once by `run` through `Pipeline`. Operations are checked by library, so invalid ones, e.g.
+/// crop outside of image, fail `run` without changing image.
+pub struct PipelineBuilder<'a> {
+    image: &'a mut Image,
+    steps: Vec<Step>,
+}
+
+impl PipelineBuilder<'_> {
+    /// Appends crop keeping `rect` of image given by previous operations.
+    pub fn crop(mut self, rect: Rect) -> Self {
+        self.steps.push(Step::Crop(rect));
+        self
+    }
+
+    /// Appends resize to `width` x `height` by `filter`.
+    pub fn resize(mut self, width: u32, height: u32, filter: Filter) -> Self {
+        match filter {
+            Filter::Lanczos3 => self.steps.push(Step::Resize(width, height)),
+        }
+        self
+    }
+
+    /// Appends gaussian blur, as `Image::blur` does.
+    pub fn blur(mut self, sigma: f32) -> Self {
+        self.steps.push(Step::Blur(sigma));
+        self
+    }
+
+    /// Appends horizontal flip.
+    pub fn mirror(mut self) -> Self {
+        self.steps.push(Step::Mirror);
+        self
+    }
+
+    /// Applies operations to image in place, in order they were appended.
+    pub fn run(self) -> Result<()> {
+        let lib = self.image.lib.clone();
+        let handle = unsafe { lib.pipeline_create()? };
+        let mut pipeline = Pipeline { lib, handle };
+        for step in self.steps {
+            match step {
+                Step::Crop(rect) => pipeline.crop(rect)?,
+                Step::Resize(width, height) => pipeline.resize(width, height)?,
+                Step::Blur(sigma) => pipeline.blur(sigma)?,
+                Step::Mirror => pipeline.mirror()?,
+            };
+        }
+        pipeline.execute(self.image)
+    }
+}
+
+/// Operation running on worker thread of library, e.g. started by `Image::submit`. Dropping
+/// running job lets it finish in background and destroys its result.
+pub struct Job {
+    lib: Lib,
+    handle: JobHandle,
+}
+
+impl Job {
+    /// Returns progress of job without waiting.
+    pub fn status(&self) -> JobStatus {
+        unsafe { self.lib.job_status(self.handle) }
+    }
+
+    /// Waits until job finishes and returns its resulting image, `None` for saving jobs. Image
+    /// can be taken once, later calls fail.
+    pub fn result(&self) -> Result<Option<Image>> {
+        let handle = unsafe { self.lib.job_result(self.handle)? };
+        Ok(handle.map(|handle| Image {
+            lib: self.lib.clone(),
+            handle,
+        }))
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        unsafe {
+            self.lib.job_destroy(self.handle);
+        }
+    }
+}
+
+/// Jobs limited to some running at once, created by `ImageFactory::job_queue`. Jobs with higher
+/// `priority` start first, jobs of equal one in order they were submitted. Dropping queue lets
+/// its jobs run to the end.
+pub struct JobQueue {
+    lib: Lib,
+    handle: QueueHandle,
+}
+
+impl JobQueue {
+    /// Starts decoding image at specified path once job of `priority` gets its turn in queue.
+    pub fn submit_open<P: AsRef<Path>>(&self, path: P, priority: i32) -> Result<Job> {
+        let params = path_params(path)?;
+        let op = CString::new("open")?;
+        let image = unsafe { ImageHandle::new_null() };
+        self.start(image, &op, &params, priority, None)
+    }
+
+    /// Starts applying operation to copy of `image`, as `Image::submit` does, once job of
+    /// `priority` gets its turn in queue.
+    pub fn submit(&self, image: &Image, op: &str, params: &str, priority: i32) -> Result<Job> {
+        self.lib.check_same(&image.lib)?;
+        let (op, params) = (CString::new(op)?, CString::new(params)?);
+        self.start(image.handle, &op, &params, priority, None)
+    }
+
+    /// Starts job as `submit` does, failing it with `ImageError::Cancelled` once `token` is
+    /// cancelled, also while it waits in queue.
+    pub fn submit_with_token(
+        &self,
+        image: &Image,
+        op: &str,
+        params: &str,
+        priority: i32,
+        token: &CancelToken,
+    ) -> Result<Job> {
+        self.lib.check_same(&image.lib)?;
+        self.lib.check_same(&token.lib)?;
+        let (op, params) = (CString::new(op)?, CString::new(params)?);
+        self.start(image.handle, &op, &params, priority, Some(token))
+    }
+
+    fn start(
+        &self,
+        image: ImageHandle,
+        op: &CStr,
+        params: &CStr,
+        priority: i32,
+        token: Option<&CancelToken>,
+    ) -> Result<Job> {
+        let token = token.map_or(unsafe { TokenHandle::new_null() }, |t| t.handle);
+        let handle = unsafe {
+            self.lib
+                .submit_on(self.handle, priority, image, op, params, token)?
+        };
+        Ok(Job {
+            lib: self.lib.clone(),
+            handle,
+        })
+    }
+}
+
+impl Drop for JobQueue {
+    fn drop(&mut self) {
+        unsafe {
+            self.lib.queue_destroy(self.handle);
+        }
+    }
+}
+
+/// Cancels operations it was passed to at once, e.g. when user presses "Cancel".
+pub struct CancelToken {
+    lib: Lib,
+    handle: TokenHandle,
+}
+
+impl CancelToken {
+    /// Cancels operations token was passed to, including ones started with it later.
+    pub fn cancel(&self) {
+        unsafe { self.lib.token_cancel(self.handle) }
+    }
+}
+
+impl Drop for CancelToken {
+    fn drop(&mut self) {
+        unsafe {
+            self.lib.token_destroy(self.handle);
+        }
+    }
+}
+
+/// Edits and output settings of `ImageFactory::batch`.
+pub struct Batch<'a> {
+    /// Edit stack applied to every image, as returned by `Image::edit_stack`. `None`
+    /// only converts images.
+    pub pipeline: Option<&'a str>,
+    /// Extension of output files without dot, e.g. `"webp"`, selecting their format. `None`
+    /// keeps extension of each source.
+    pub extension: Option<&'a str>,
+    /// JPEG quality of outputs in `1..=100` range.
+    pub jpeg_quality: u8,
+    /// Write metadata of sources if output format supports it.
+    pub keep_metadata: bool,
+    pub token: Option<&'a CancelToken>,
+}
+
+impl Default for Batch<'_> {
+    fn default() -> Self {
+        Self {
+            pipeline: None,
+            extension: None,
+            jpeg_quality: SaveOptions::default().jpeg_quality,
+            keep_metadata: false,
+            token: None,
+        }
+    }
+}
+
+/// Social media card composed by `Image::compose_card`.
+pub struct Card<'a> {
+    /// Size of card in pixels. Zero width and height mean 1200 x 630.
+    pub width: u32,
+    pub height: u32,
+    pub layout: CardLayout,
+    /// Title drawn with built-in font of printable ASCII characters.
+    pub title: &'a str,
+    /// Image drawn cropped to circle, e.g. author photo.
+    pub avatar: Option<&'a Image>,
+    /// Image drawn at top right corner keeping its aspect ratio.
+    pub logo: Option<&'a Image>,
+    /// RGBA color of title.
+    pub text_color: [u8; 4],
+    /// RGBA color of overlay keeping title readable over background.
+    pub scrim_color: [u8; 4],
+}
+
+impl Default for Card<'_> {
+    fn default() -> Self {
+        let spec = CardSpec::default();
+        Self {
+            width: spec.width,
+            height: spec.height,
+            layout: CardLayout::Bottom,
+            title: "",
+            avatar: None,
+            logo: None,
+            text_color: spec.text_color,
+            scrim_color: spec.scrim_color,
+        }
+    }
+}
+
+/// Placeholder of `image_sl_init` missing in older builds of library.
+unsafe extern "C" fn init_unsupported(_config: *const InitConfig) -> ImageError {
+    ImageError::Unsupported
+}
+
+/// Placeholder of `image_sl_shutdown` missing in older builds of library.
+unsafe extern "C" fn shutdown_unsupported() {}
+
+/// Placeholder of `image_sl_dump_trace` missing in older builds of library.
+unsafe extern "C" fn dump_trace_unsupported(_path: *const c_char) -> ImageError {
+    ImageError::Unsupported
+}
+
+/// Placeholder filling functions table past functions of negotiated ABI version. It is never
+/// called, as `function!` checks size of table first.
+unsafe extern "C" fn missing_function() {}
+
+/// Splits list of consecutive null-terminated strings ended by empty string.
+fn string_list(list: &[u8]) -> Vec<CString> {
+    let strings = list.split(|&b| b == 0).take_while(|s| !s.is_empty());
+    strings.map(|s| CString::new(s).unwrap()).collect()
+}
+
+/// Returns OS handle of `file`.
+#[cfg(unix)]
+fn os_handle(file: &File) -> OsHandle {
+    use std::os::unix::io::AsRawFd;
+    file.as_raw_fd()
+}
+
+/// Returns OS handle of `file`.
+#[cfg(windows)]
+fn os_handle(file: &File) -> OsHandle {
+    use std::os::windows::io::AsRawHandle;
+    file.as_raw_handle()
+}
+
+/// Converts `path` to CString.
+fn path_to_cstring<P: AsRef<Path>>(path: P) -> Result<CString> {
+    let path_str = path
+        .as_ref()
+        .to_str()
+        .ok_or_else(|| Error::InvalidArgument("Only UTF-8 file path accepted".into()))?;
+
+    let path_bytes = path_str.as_bytes();
+    Ok(CString::new(path_bytes)?)
+}
+
+/// Converts `path` to JSON parameters of jobs, e.g. `{"path": "out.png"}`.
+fn path_params<P: AsRef<Path>>(path: P) -> Result<CString> {
+    let path = path_to_cstring(path)?.into_string()?;
+    let mut json = String::from("{\"path\": \"");
+    for c in path.chars() {
+        match c {
+            '"' | '\\' => json.extend(['\\', c]),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push_str("\"}");
+    Ok(CString::new(json)?)
+}
+
+/// Probe closure of decoder plugin.
+type Probe = Box<dyn Fn(&[u8]) -> bool + Send + Sync>;
+/// Decode closure of decoder plugin.
+type Decode = Box<dyn Fn(&[u8]) -> Result<(u32, u32, Vec<u8>), PluginError> + Send + Sync>;
+
+/// Closures of decoder plugin passed to library as `user_data`.
+struct DecoderPlugin {
+    probe: Probe,
+    decode: Decode,
+    image_from_raw: bindings::ImageFromRawFn,
+}
+
+/// Closure of encoder plugin passed to library as `user_data`.
+type EncoderPlugin =
+    Box<dyn Fn(&[u8], u32, u32, &mut dyn Write) -> Result<(), PluginError> + Send + Sync>;
+
+/// Passes percent of work done to `FnMut(f32) -> bool` closure passed as `user_data`.
+unsafe extern "C" fn progress_callback<F: FnMut(f32) -> bool>(
+    percent: f32,
+    user_data: *mut c_void,
+) -> bool {
+    let progress = &mut *(user_data as *mut F);
+    progress(percent)
+}
+
+/// Maps color of pixel by `Fn(u32, u32, [u8; 4]) -> [u8; 4]` closure passed as `user_data`.
+unsafe extern "C" fn pixel_callback<F: Fn(u32, u32, [u8; 4]) -> [u8; 4]>(
+    x: u32,
+    y: u32,
+    rgba: *mut u32,
+    user_data: *mut c_void,
+) {
+    let f = &*(user_data as *const F);
+    *rgba = u32::from_be_bytes(f(x, y, (*rgba).to_be_bytes()));
+}
+
+/// Passes row to `FnMut(u32, &[u8]) -> bool` closure passed as `user_data`.
+unsafe extern "C" fn row_callback<F: FnMut(u32, &[u8]) -> bool>(
+    index: u32,
+    row: *const u8,
+    len: usize,
+    user_data: *mut c_void,
+) -> bool {
+    let f = &mut *(user_data as *mut F);
+    match len {
+        0 => f(index, &[]),
+        _ => f(index, std::slice::from_raw_parts(row, len)),
+    }
+}
+
+/// Probes stream header by `DecoderPlugin` passed as `user_data`.
+unsafe extern "C" fn probe_callback(data: *const u8, len: usize, user_data: *mut c_void) -> bool {
+    let plugin = &*(user_data as *const DecoderPlugin);
+    (plugin.probe)(std::slice::from_raw_parts(data, len))
+}
+
+/// Decodes image by `DecoderPlugin` passed as `user_data` into new image handle.
+unsafe extern "C" fn decode_callback(
+    data: *const u8,
+    len: usize,
+    user_data: *mut c_void,
+    handle: *mut ImageHandle,
+) -> ImageError {
+    let plugin = &*(user_data as *const DecoderPlugin);
+    match (plugin.decode)(std::slice::from_raw_parts(data, len)) {
+        Ok((width, height, pixels)) => (plugin.image_from_raw)(
+            pixels.as_ptr(),
+            width,
+            height,
+            RawPixelFormat::Rgba8 as u32,
+            handle,
+        ),
+        Err(_) => ImageError::Decoding,
+    }
+}
+
+/// Encodes pixels by `EncoderPlugin` passed as `user_data` writing to library callback.
+unsafe extern "C" fn encode_callback(
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    write_fn: bindings::WriteFn,
+    writer: *mut c_void,
+    user_data: *mut c_void,
+) -> ImageError {
+    let plugin = &*(user_data as *const EncoderPlugin);
+    let pixels = std::slice::from_raw_parts(pixels, width as usize * height as usize * 4);
+    let mut writer = PluginWriter { write_fn, writer };
+    match plugin(pixels, width, height, &mut writer) {
+        Ok(_) => ImageError::NoError,
+        Err(_) => ImageError::Encoding,
+    }
+}
+
+/// `Write` adapter over write callback passed to encoder plugin.
+struct PluginWriter {
+    write_fn: bindings::WriteFn,
+    writer: *mut c_void,
+}
+
+impl Write for PluginWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match unsafe { (self.write_fn)(self.writer, buf.as_ptr(), buf.len()) } {
+            written if written >= 0 => Ok(written as usize),
+            _ => Err(std::io::Error::other("library write callback failed")),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Pushes leaked image to `Vec<(usize, String)>` passed as `user_data`.
+unsafe extern "C" fn leak_callback(
+    handle: ImageHandle,
+    backtrace: *const c_char,
+    len: usize,
+    user_data: *mut c_void,
+) {
+    let leaks = &mut *(user_data as *mut Vec<(usize, String)>);
+    let backtrace = std::slice::from_raw_parts(backtrace as *const u8, len);
+    leaks.push((
+        handle.address(),
+        String::from_utf8_lossy(backtrace).into_owned(),
+    ));
+}
+
+/// Reads from `R` passed as `user_data`.
+unsafe extern "C" fn read_callback<R: Read>(
+    user_data: *mut c_void,
+    buffer: *mut u8,
+    len: usize,
+) -> isize {
+    let reader = &mut *(user_data as *mut R);
+    let buffer = std::slice::from_raw_parts_mut(buffer, len);
+    match reader.read(buffer) {
+        Ok(read) => read as isize,
+        Err(_) => -1,
+    }
+}
+
+/// Seeks in `R` passed as `user_data`.
+unsafe extern "C" fn seek_callback<R: Seek>(
+    user_data: *mut c_void,
+    offset: i64,
+    whence: i32,
+) -> i64 {
+    let reader = &mut *(user_data as *mut R);
+    let pos = match whence {
+        0 => SeekFrom::Start(offset as u64),
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => return -1,
+    };
+
+    match reader.seek(pos) {
+        Ok(position) => position as i64,
+        Err(_) => -1,
+    }
+}
+
+/// Writes to `W` passed as `user_data`.
+unsafe extern "C" fn write_callback<W: Write>(
+    user_data: *mut c_void,
+    data: *const u8,
+    len: usize,
+) -> isize {
+    let writer = &mut *(user_data as *mut W);
+    let data = std::slice::from_raw_parts(data, len);
+    match writer.write(data) {
+        Ok(written) => written as isize,
+        Err(_) => -1,
+    }
+}
+
+/// Incapsulation of intercommunication with dynamic or statically linked library. Its methods
+/// call library functions directly, so safe types above are preferred.
+/// # Safety
+/// Methods have the same requirements as library functions they call: handles are valid and
+/// used by threads allowed to, pointers point to buffers of passed sizes.
+#[derive(Clone)]
+pub struct Lib {
+    /// Keeps dynamic library loaded. `None` if library is linked statically.
+    lib: Option<Arc<LoadedLibrary>>,
+    /// Negotiated ABI version of `functions`.
+    abi_version: u32,
+    functions: Functions,
+    version: VersionFn,
+    init: InitFn,
+    shutdown: ShutdownFn,
+    dump_trace: DumpTraceFn,
+}
+
+/// Returns function `name` of functions table of `lib`, or `ImageError::Unsupported` if library
+/// is older than ABI version the function is added in.
+macro_rules! function {
+    ($lib:expr, $name:ident) => {
+        if std::mem::offset_of!(Functions, $name) < $lib.functions.size {
+            Ok($lib.functions.$name)
+        } else {
+            Err(Error::from(ImageError::Unsupported))
+        }
+    };
+}
+
+// Safety requirements are listed once in docs of `Lib`.
+#[allow(clippy::missing_safety_doc)]
+impl Lib {
+    /// Creates new instance of `Lib`. Loads functons from shared library.
+    pub unsafe fn new(lib: Library) -> Result<Self> {
+        let exports = Exports {
+            functions_ex: *lib.get::<FunctionsExFn>(b"functions_ex")?,
+            version: *lib.get::<VersionFn>(b"version")?,
+            // Older builds don't export them, calls fail with `ImageError::Unsupported` then.
+            init: lib
+                .get::<InitFn>(b"image_sl_init")
+                .map_or(init_unsupported, |f| *f),
+            shutdown: lib
+                .get::<ShutdownFn>(b"image_sl_shutdown")
+                .map_or(shutdown_unsupported, |f| *f),
+            dump_trace: lib
+                .get::<DumpTraceFn>(b"image_sl_dump_trace")
+                .map_or(dump_trace_unsupported, |f| *f),
+        };
+        Self::from_exports(exports, Some(lib))
+    }
+
+    /// Creates new instance of `Lib` from exported functions of library. `lib` is dynamic
+    /// library they are loaded from, if any.
+    pub unsafe fn from_exports(exports: Exports, lib: Option<Library>) -> Result<Self> {
+        let mut table = std::mem::MaybeUninit::<FunctionsV2>::uninit();
+        // Older library writes only functions of its version, so the rest of table must hold
+        // valid function pointers too.
+        type MissingFn = unsafe extern "C" fn();
+        let words = table.as_mut_ptr() as *mut MissingFn;
+        for i in 0..std::mem::size_of::<FunctionsV2>() / std::mem::size_of::<MissingFn>() {
+            words.add(i).write(missing_function);
+        }
+
+        // Library older than bindings provides table of its own version, functions added
+        // after it fail with `ImageError::Unsupported`.
+        let mut abi_version = bindings::ABI_VERSION;
+        loop {
+            match (exports.functions_ex)(abi_version, table.as_mut_ptr()) {
+                ImageError::NoError => break,
+                ImageError::Unsupported if abi_version > 1 => abi_version -= 1,
+                ImageError::Unsupported => {
+                    return Err(Error::Incompatible(
+                        "Lib supports no ABI version of app bindings",
+                    ));
+                }
+                err => return Err(err.into()),
+            }
+        }
+
+        let table = table.assume_init();
+        let functions_offset = std::mem::offset_of!(FunctionsV2, functions);
+        if table.abi_version != abi_version
+            || table.functions.size > std::mem::size_of::<Functions>()
+            || table.size != functions_offset + table.functions.size
+        {
+            return Err(Error::Incompatible(
+                "Lib FunctionsV2 size doesn't match its ABI version",
+            ));
+        }
+        Ok(Self {
+            lib: lib.map(|library| {
+                Arc::new(LoadedLibrary {
+                    library: Some(library),
+                    copy_dir: None,
+                })
+            }),
+            abi_version,
+            functions: table.functions,
+            version: exports.version,
+            init: exports.init,
+            shutdown: exports.shutdown,
+            dump_trace: exports.dump_trace,
+        })
+    }
+
+    /// Returns whether `other` calls the same loaded library, so their objects can be passed to
+    /// each other.
+    pub fn is_same(&self, other: &Lib) -> bool {
+        self.functions.destroy_image as usize == other.functions.destroy_image as usize
+    }
+
+    /// Fails with `Error::ForeignObject` unless `other` calls the same loaded library.
+    fn check_same(&self, other: &Lib) -> Result<()> {
+        match self.is_same(other) {
+            true => Ok(()),
+            false => Err(Error::ForeignObject),
+        }
+    }
+
+    /// Returns version of library.
+    pub unsafe fn version(&self) -> LibraryVersion {
+        let version: Version = (self.version)();
+        LibraryVersion {
+            major: version.major,
+            minor: version.minor,
+            patch: version.patch,
+            build: CStr::from_ptr(version.build).to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Applies global settings of library.
+    pub unsafe fn init(&self, config: &InitConfig) -> Result<()> {
+        match (self.init)(config) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Releases global resources of library.
+    pub unsafe fn shutdown(&self) {
+        (self.shutdown)()
+    }
+
+    /// Writes trace of the last operations to file.
+    pub unsafe fn dump_trace(&self, path: &CStr) -> Result<()> {
+        match (self.dump_trace)(path.as_ptr()) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens image at specified path.
+    pub unsafe fn open_image(&self, path: &CStr) -> Result<ImageHandle> {
+        let raw_path = path.as_ptr();
+        let mut handle = ImageHandle::new_null();
+        let err = (function!(self, open_image)?)(RawPath(raw_path), &mut handle);
+        match err {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens image at specified path respecting `options`.
+    pub unsafe fn open_image_with_options(
+        &self,
+        path: &CStr,
+        options: &OpenOptions,
+    ) -> Result<ImageHandle> {
+        let raw_path = path.as_ptr();
+        let mut handle = ImageHandle::new_null();
+        let err =
+            (function!(self, open_image_with_options)?)(RawPath(raw_path), options, &mut handle);
+        match err {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens image from memory buffer respecting `options`.
+    pub unsafe fn open_image_memory(
+        &self,
+        data: &[u8],
+        options: &OpenOptions,
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let err =
+            (function!(self, open_image_memory)?)(data.as_ptr(), data.len(), options, &mut handle);
+        match err {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens image from `reader`.
+    pub unsafe fn open_image_with_reader<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+    ) -> Result<ImageHandle> {
+        let user_data = reader as *mut R as *mut c_void;
+        let mut handle = ImageHandle::new_null();
+        let err = (function!(self, open_image_with_reader)?)(
+            read_callback::<R>,
+            Some(seek_callback::<R>),
+            user_data,
+            &mut handle,
+        );
+        match err {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Registers decoder plugin with `DecoderPlugin` user data.
+    pub unsafe fn register_decoder(&self, name: &CStr, user_data: *mut c_void) -> Result<()> {
+        let (probe, decode) = (Some(probe_callback as _), Some(decode_callback as _));
+        match (function!(self, register_decoder)?)(RawStr(name.as_ptr()), probe, decode, user_data)
+        {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Registers encoder plugin with `EncoderPlugin` user data.
+    pub unsafe fn register_encoder(&self, name: &CStr, user_data: *mut c_void) -> Result<()> {
+        let encode = Some(encode_callback as _);
+        match (function!(self, register_encoder)?)(RawStr(name.as_ptr()), encode, user_data) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates image by copying raw pixel data. `data` must contain all pixels of image.
+    pub unsafe fn image_from_raw(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: RawPixelFormat,
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let err = (function!(self, image_from_raw)?)(
+            data.as_ptr(),
+            width,
+            height,
+            format as u32,
+            &mut handle,
+        );
+        match err {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates image filled with RGBA color.
+    pub unsafe fn new_image(
+        &self,
+        width: u32,
+        height: u32,
+        color_type: u32,
+        fill: [u8; 4],
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let fill = u32::from_be_bytes(fill);
+        match (function!(self, new)?)(width, height, color_type, fill, &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates image filled with gradient.
+    pub unsafe fn gradient(
+        &self,
+        width: u32,
+        height: u32,
+        stops: &[GradientStop],
+        kind: GradientKind,
+        angle: f32,
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let gradient = function!(self, gradient)?;
+        let (count, kind) = (stops.len(), kind as u32);
+        match gradient(
+            width,
+            height,
+            stops.as_ptr(),
+            count,
+            kind,
+            angle,
+            &mut handle,
+        ) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates checkerboard image of two colors.
+    pub unsafe fn checkerboard(
+        &self,
+        width: u32,
+        height: u32,
+        cell_size: u32,
+        a: [u8; 4],
+        b: [u8; 4],
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let (a, b) = (u32::from_be_bytes(a), u32::from_be_bytes(b));
+        match (function!(self, checkerboard)?)(width, height, cell_size, a, b, &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates image of fractal noise.
+    pub unsafe fn noise(
+        &self,
+        width: u32,
+        height: u32,
+        kind: NoiseKind,
+        frequency: f32,
+        octaves: u32,
+        seed: u32,
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let kind = kind as u32;
+        match (function!(self, noise)?)(width, height, kind, frequency, octaves, seed, &mut handle)
+        {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Packs images into sprite sheet.
+    pub unsafe fn pack_sprites(
+        &self,
+        handles: &[ImageHandle],
+        max_width: u32,
+        padding: u32,
+    ) -> Result<(ImageHandle, Vec<Rect>)> {
+        let mut sheet = ImageHandle::new_null();
+        let mut placements = vec![Rect::default(); handles.len()];
+        let (ptr, count) = (handles.as_ptr(), handles.len());
+        let placements_ptr = placements.as_mut_ptr();
+        match (function!(self, pack_sprites)?)(
+            ptr,
+            count,
+            max_width,
+            padding,
+            &mut sheet,
+            placements_ptr,
+        ) {
+            ImageError::NoError => Ok((sheet, placements)),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates image of SMPTE color bars.
+    pub unsafe fn color_bars(&self, width: u32, height: u32) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        match (function!(self, color_bars)?)(width, height, &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens image from base64 data URI.
+    pub unsafe fn open_image_data_uri(&self, uri: &CStr) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let err = (function!(self, open_image_data_uri)?)(RawStr(uri.as_ptr()), &mut handle);
+        match err {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Downloads and opens image from HTTP(S) URL.
+    pub unsafe fn open_image_url(
+        &self,
+        url: &CStr,
+        timeout_ms: u32,
+        max_bytes: usize,
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let err = (function!(self, open_image_url)?)(
+            RawStr(url.as_ptr()),
+            timeout_ms,
+            max_bytes,
+            &mut handle,
+        );
+        match err {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens image from OS file handle.
+    pub unsafe fn open_image_fd(&self, fd: OsHandle) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let err = (function!(self, open_image_fd)?)(fd, &mut handle);
+        match err {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Saves image at specified path.
+    pub unsafe fn save_image(&self, handle: ImageHandle, path: &CStr) -> Result<()> {
+        let raw_path = path.as_ptr();
+
+        let err = (function!(self, save_image)?)(RawPath(raw_path), handle);
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Saves image as CMYK TIFF file.
+    pub unsafe fn save_cmyk_tiff(
+        &self,
+        handle: ImageHandle,
+        path: &CStr,
+        icc: Option<&[u8]>,
+    ) -> Result<()> {
+        let (icc, icc_len) = match icc {
+            Some(icc) => (icc.as_ptr(), icc.len()),
+            None => (std::ptr::null(), 0),
+        };
+        let err = (function!(self, save_cmyk_tiff)?)(RawPath(path.as_ptr()), handle, icc, icc_len);
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Encodes image in specified format into base64 data URI.
+    pub unsafe fn encode_image_data_uri(
+        &self,
+        handle: ImageHandle,
+        format: ImageFormat,
+    ) -> Result<CString> {
+        let encode_image_data_uri = function!(self, encode_image_data_uri)?;
+        let encode = |buffer: *mut u8, len: &mut usize| {
+            encode_image_data_uri(handle, format as u32, buffer as *mut c_char, len)
+        };
+        let uri = self.read_buffer(encode)?;
+        let uri = CStr::from_bytes_with_nul(&uri).map_err(|_| ImageError::Encoding)?;
+        Ok(uri.to_owned())
+    }
+
+    /// Encodes image and writes it to OS file handle.
+    pub unsafe fn save_image_fd(
+        &self,
+        handle: ImageHandle,
+        fd: OsHandle,
+        format: ImageFormat,
+        options: &SaveOptions,
+    ) -> Result<()> {
+        let err = (function!(self, save_image_fd)?)(fd, handle, format as u32, options);
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Encodes image and writes it to `writer`.
+    pub unsafe fn save_image_with_writer<W: Write>(
+        &self,
+        handle: ImageHandle,
+        writer: &mut W,
+        format: ImageFormat,
+        options: &SaveOptions,
+    ) -> Result<()> {
+        let user_data = writer as *mut W as *mut c_void;
+        let err = (function!(self, save_image_with_writer)?)(
+            write_callback::<W>,
+            user_data,
+            handle,
+            format as u32,
+            options,
+        );
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Decodes, transforms and encodes image from `stream` reader to `stream` writer.
+    pub unsafe fn transcode_stream<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        ops: &[Operation],
+    ) -> Result<()> {
+        let user_data = stream as *mut S as *mut c_void;
+        let err = (function!(self, transcode_stream)?)(
+            read_callback::<S>,
+            write_callback::<S>,
+            ops.as_ptr(),
+            ops.len(),
+            user_data,
+        );
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Decodes, transforms and encodes image in `format` from `stream` reader to `stream` writer,
+    /// reading at most `max_input` bytes.
+    pub unsafe fn transcode_stream2<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        ops: &[Operation],
+        format: ImageFormat,
+        max_input: u64,
+    ) -> Result<()> {
+        let user_data = stream as *mut S as *mut c_void;
+        let err = (function!(self, transcode_stream2)?)(
+            read_callback::<S>,
+            write_callback::<S>,
+            ops.as_ptr(),
+            ops.len(),
+            format as u32,
+            max_input,
+            user_data,
+        );
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Transcodes stream applying operations of pipeline.
+    pub unsafe fn pipeline_transcode<S: Read + Write>(
+        &self,
+        pipeline: PipelineHandle,
+        stream: &mut S,
+        format: ImageFormat,
+        max_input: u64,
+    ) -> Result<()> {
+        let user_data = stream as *mut S as *mut c_void;
+        let err = (function!(self, pipeline_transcode)?)(
+            pipeline,
+            read_callback::<S>,
+            write_callback::<S>,
+            format as u32,
+            max_input,
+            user_data,
+        );
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Enables cache of pipeline transcoding results in `dir`, disables it if `dir` is `None`.
+    pub unsafe fn enable_cache(&self, dir: Option<&CStr>, max_bytes: u64) -> Result<()> {
+        let dir = dir.map_or(std::ptr::null(), CStr::as_ptr);
+        match (function!(self, enable_cache)?)(dir, max_bytes) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Sets limits applied to every decoded image.
+    pub unsafe fn set_limits(&self, max_width: u32, max_height: u32, max_memory: u64) {
+        (self.functions.set_limits)(max_width, max_height, max_memory)
+    }
+
+    /// Sets maximum number of worker threads. Does nothing if library doesn't have the setting.
+    pub unsafe fn set_thread_count(&self, count: u32) {
+        if let Ok(set_thread_count) = function!(self, set_thread_count) {
+            set_thread_count(count)
+        }
+    }
+
+    /// Sets size limit of pool of temporary buffers. Does nothing if library has no pool.
+    pub unsafe fn set_pool_limit(&self, max_bytes: u64) {
+        if let Ok(set_pool_limit) = function!(self, set_pool_limit) {
+            set_pool_limit(max_bytes)
+        }
+    }
+
+    /// Frees idle buffers of pool.
+    pub unsafe fn pool_trim(&self) -> u64 {
+        function!(self, pool_trim).map_or(0, |pool_trim| pool_trim())
+    }
+
+    /// Destroy image.
+    pub unsafe fn destroy_image(&self, handle: ImageHandle) {
+        (self.functions.destroy_image)(handle)
+    }
+
+    /// Blurs image. `sigma` is a measure of how much to blur by. Returns new image.
+    pub unsafe fn blur_image(&self, handle: ImageHandle, sigma: f32) -> Result<ImageHandle> {
+        let mut blurred = ImageHandle::new_null();
+        match (function!(self, blur_image2)?)(handle, sigma, &mut blurred) {
+            ImageError::NoError => Ok(blurred),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Blurs image reporting progress to closure. Returns new image.
+    pub unsafe fn blur_with_progress<F: FnMut(f32) -> bool + Send>(
+        &self,
+        handle: ImageHandle,
+        sigma: f32,
+        progress: &mut F,
+    ) -> Result<ImageHandle> {
+        let mut blurred = ImageHandle::new_null();
+        let user_data = progress as *mut F as *mut c_void;
+        let callback = Some(progress_callback::<F> as ProgressFn);
+        match (function!(self, blur_with_progress)?)(
+            handle,
+            sigma,
+            callback,
+            user_data,
+            &mut blurred,
+        ) {
+            ImageError::NoError => Ok(blurred),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Resizes image reporting progress to closure. Returns new image.
+    pub unsafe fn resize_with_progress<F: FnMut(f32) -> bool + Send>(
+        &self,
+        handle: ImageHandle,
+        width: u32,
+        height: u32,
+        progress: &mut F,
+    ) -> Result<ImageHandle> {
+        let mut resized = ImageHandle::new_null();
+        let user_data = progress as *mut F as *mut c_void;
+        let callback = Some(progress_callback::<F> as ProgressFn);
+        let resize = function!(self, resize_with_progress)?;
+        match resize(handle, width, height, callback, user_data, &mut resized) {
+            ImageError::NoError => Ok(resized),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates frozen clone of image.
+    pub unsafe fn freeze(&self, handle: ImageHandle) -> Result<ImageHandle> {
+        let mut frozen = ImageHandle::new_null();
+        match (function!(self, freeze)?)(handle, &mut frozen) {
+            ImageError::NoError => Ok(frozen),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reorders channels of image.
+    pub unsafe fn swap_channels(&self, handle: ImageHandle, mapping: [u8; 4]) -> Result<()> {
+        match (function!(self, swap_channels)?)(handle, &mapping) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Counts values of channel of image in 256 bins.
+    pub unsafe fn histogram(&self, handle: ImageHandle, channel: u32) -> Result<[u32; 256]> {
+        let mut bins = [0; 256];
+        match (function!(self, histogram)?)(handle, channel, bins.as_mut_ptr()) {
+            ImageError::NoError => Ok(bins),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Counts values of channel of image in 65536 bins.
+    pub unsafe fn histogram16(&self, handle: ImageHandle, channel: u32) -> Result<Vec<u32>> {
+        let mut bins = vec![0; 1 << 16];
+        match (function!(self, histogram16)?)(handle, channel, bins.as_mut_ptr()) {
+            ImageError::NoError => Ok(bins),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Computes statistics of channels of image.
+    pub unsafe fn stats(&self, handle: ImageHandle) -> Result<Stats> {
+        let mut stats = Stats::default();
+        match (function!(self, stats)?)(handle, &mut stats) {
+            ImageError::NoError => Ok(stats),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Finds dominant colors of image, skipping entries of zero weight.
+    pub unsafe fn dominant_colors(
+        &self,
+        handle: ImageHandle,
+        k: u32,
+    ) -> Result<Vec<([u8; 4], f32)>> {
+        let mut colors = vec![0; k as usize];
+        let mut weights = vec![0.0; k as usize];
+        let dominant_colors = function!(self, dominant_colors)?;
+        match dominant_colors(handle, k, colors.as_mut_ptr(), weights.as_mut_ptr()) {
+            ImageError::NoError => Ok(colors
+                .into_iter()
+                .zip(weights)
+                .filter(|&(_, weight)| weight > 0.0)
+                .map(|(rgba, weight)| (rgba.to_be_bytes(), weight))
+                .collect()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Computes average color of image or its region.
+    pub unsafe fn average_color(&self, handle: ImageHandle, rect: Option<Rect>) -> Result<u32> {
+        let rect = rect.as_ref().map_or(std::ptr::null(), |r| r as *const Rect);
+        let mut rgba = 0;
+        match (function!(self, average_color)?)(handle, rect, &mut rgba) {
+            ImageError::NoError => Ok(rgba),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Computes perceptual hash of image.
+    pub unsafe fn phash(&self, handle: ImageHandle, algorithm: HashAlgorithm) -> Result<u64> {
+        let mut hash = 0;
+        match (function!(self, phash)?)(handle, algorithm as u32, &mut hash) {
+            ImageError::NoError => Ok(hash),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Compares two images of the same size by metric.
+    pub unsafe fn compare(
+        &self,
+        a: ImageHandle,
+        b: ImageHandle,
+        metric: CompareMetric,
+    ) -> Result<f64> {
+        let mut result = 0.0;
+        match (function!(self, compare)?)(a, b, metric as u32, &mut result) {
+            ImageError::NoError => Ok(result),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Counts pixels differing between two images and creates image highlighting them if `diff`
+    /// is true.
+    pub unsafe fn diff(
+        &self,
+        a: ImageHandle,
+        b: ImageHandle,
+        tolerance: u8,
+        diff: bool,
+    ) -> Result<(Option<ImageHandle>, u64)> {
+        let mut handle = ImageHandle::new_null();
+        let out = match diff {
+            true => &mut handle as *mut ImageHandle,
+            false => std::ptr::null_mut(),
+        };
+        let mut count = 0;
+        match (function!(self, diff)?)(a, b, tolerance, out, &mut count) {
+            ImageError::NoError => Ok((Some(handle).filter(|_| diff), count)),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Crops away uniform borders of image.
+    pub unsafe fn trim(&self, handle: ImageHandle, tolerance: u8) -> Result<ImageHandle> {
+        let mut trimmed = ImageHandle::new_null();
+        match (function!(self, trim)?)(handle, tolerance, &mut trimmed) {
+            ImageError::NoError => Ok(trimmed),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Tells whether image is uniform.
+    pub unsafe fn is_blank(&self, handle: ImageHandle, tolerance: u8) -> Result<bool> {
+        let mut blank = false;
+        match (function!(self, is_blank)?)(handle, tolerance, &mut blank) {
+            ImageError::NoError => Ok(blank),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Computes BlurHash of image.
+    pub unsafe fn blurhash(
+        &self,
+        handle: ImageHandle,
+        x_components: u32,
+        y_components: u32,
+    ) -> Result<CString> {
+        let blurhash = function!(self, blurhash)?;
+        let encode = |buffer: *mut u8, len: &mut usize| {
+            blurhash(
+                handle,
+                x_components,
+                y_components,
+                buffer as *mut c_char,
+                len,
+            )
+        };
+        let hash = self.read_buffer(encode)?;
+        let hash = CStr::from_bytes_with_nul(&hash).map_err(|_| ImageError::Encoding)?;
+        Ok(hash.to_owned())
+    }
+
+    /// Computes ThumbHash of image.
+    pub unsafe fn thumbhash(&self, handle: ImageHandle) -> Result<Vec<u8>> {
+        let thumbhash = function!(self, thumbhash)?;
+        self.read_buffer(|buffer, len| thumbhash(handle, buffer, len))
+    }
+
+    /// Decodes ThumbHash into new preview image.
+    pub unsafe fn thumbhash_decode(&self, hash: &[u8]) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        match (function!(self, thumbhash_decode)?)(hash.as_ptr(), hash.len(), &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates copy of image with its own pixels.
+    pub unsafe fn clone_image(&self, handle: ImageHandle) -> Result<ImageHandle> {
+        let mut clone = ImageHandle::new_null();
+        match (function!(self, clone)?)(handle, &mut clone) {
+            ImageError::NoError => Ok(clone),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates copy-on-write clone of image.
+    pub unsafe fn clone_cow(&self, handle: ImageHandle) -> Result<ImageHandle> {
+        let mut clone = ImageHandle::new_null();
+        match (function!(self, clone_cow)?)(handle, &mut clone) {
+            ImageError::NoError => Ok(clone),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates view of region of image.
+    pub unsafe fn view(&self, handle: ImageHandle, rect: Rect) -> Result<ImageHandle> {
+        let mut view = ImageHandle::new_null();
+        match (function!(self, view)?)(handle, rect, &mut view) {
+            ImageError::NoError => Ok(view),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Converts image to color type.
+    pub unsafe fn convert(&self, handle: ImageHandle, color_type: u32) -> Result<ImageHandle> {
+        let mut converted = ImageHandle::new_null();
+        match (function!(self, convert)?)(handle, color_type, &mut converted) {
+            ImageError::NoError => Ok(converted),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Blurs image into existing image.
+    pub unsafe fn blur_into(&self, src: ImageHandle, dst: ImageHandle, sigma: f32) -> Result<()> {
+        match (function!(self, blur_into)?)(src, dst, sigma) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Resizes image to size of existing image into it.
+    pub unsafe fn resize_into(&self, src: ImageHandle, dst: ImageHandle) -> Result<()> {
+        match (function!(self, resize_into)?)(src, dst) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Encodes image reporting progress to closure.
+    pub unsafe fn encode_with_progress<F: FnMut(f32) -> bool + Send>(
+        &self,
+        handle: ImageHandle,
+        format: ImageFormat,
+        progress: &mut F,
+    ) -> Result<Vec<u8>> {
+        let user_data = progress as *mut F as *mut c_void;
+        let callback = Some(progress_callback::<F> as ProgressFn);
+        let encode = function!(self, encode_with_progress)?;
+        let encode = |buffer: *mut u8, len: &mut usize| {
+            encode(handle, format as u32, buffer, len, callback, user_data)
+        };
+        self.read_buffer(encode)
+    }
+
+    /// Flip image horizontally in place.
+    pub unsafe fn mirror_image(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, mirror_image2)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Packs image into framebuffer bytes of e-paper display.
+    pub unsafe fn pack_eink(&self, handle: ImageHandle, format: EinkFormat) -> Result<Vec<u8>> {
+        let pack_eink = function!(self, pack_eink)?;
+        let pack = |out: *mut u8, len: &mut usize| pack_eink(handle, format as u32, out, len);
+        self.read_buffer(pack)
+    }
+
+    /// Packs image into display framebuffer bytes.
+    pub unsafe fn pack_framebuffer(
+        &self,
+        handle: ImageHandle,
+        layout: PixelLayout,
+        row_order: RowOrder,
+    ) -> Result<Vec<u8>> {
+        let pack_framebuffer = function!(self, pack_framebuffer)?;
+        let pack = |out: *mut u8, len: &mut usize| {
+            pack_framebuffer(handle, layout as u32, row_order as u32, out, len)
+        };
+        self.read_buffer(pack)
+    }
+
+    /// Crops image to aspect ratio keeping its most interesting part and resizes it.
+    pub unsafe fn smart_crop(
+        &self,
+        handle: ImageHandle,
+        target_w: u32,
+        target_h: u32,
+    ) -> Result<ImageHandle> {
+        let mut cropped = ImageHandle::new_null();
+        match (function!(self, smart_crop)?)(handle, target_w, target_h, &mut cropped) {
+            ImageError::NoError => Ok(cropped),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Suggests ranked crops of image.
+    pub unsafe fn suggest_crops(
+        &self,
+        handle: ImageHandle,
+        aspect_w: u32,
+        aspect_h: u32,
+        max: usize,
+    ) -> Result<Vec<Rect>> {
+        let mut crops = vec![Rect::default(); max];
+        let mut count = 0;
+        let err = (function!(self, suggest_crops)?)(
+            handle,
+            aspect_w,
+            aspect_h,
+            max,
+            crops.as_mut_ptr(),
+            &mut count,
+        );
+        match err {
+            ImageError::NoError => {
+                crops.truncate(count);
+                Ok(crops)
+            }
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Finds template in image.
+    pub unsafe fn match_template(
+        &self,
+        haystack: ImageHandle,
+        needle: ImageHandle,
+        method: MatchMethod,
+        max: usize,
+    ) -> Result<Vec<MatchResult>> {
+        let mut matches = vec![MatchResult::default(); max];
+        let mut count = 0;
+        let err = (function!(self, match_template)?)(
+            haystack,
+            needle,
+            method as u32,
+            max,
+            matches.as_mut_ptr(),
+            &mut count,
+        );
+        match err {
+            ImageError::NoError => {
+                matches.truncate(count);
+                Ok(matches)
+            }
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Detects keypoints of image.
+    pub unsafe fn detect_keypoints(
+        &self,
+        handle: ImageHandle,
+        detector: Detector,
+    ) -> Result<Vec<Keypoint>> {
+        let mut keypoints = std::ptr::null_mut();
+        let mut count = 0;
+        let err = (function!(self, detect_keypoints)?)(
+            handle,
+            detector as u32,
+            &mut keypoints,
+            &mut count,
+        );
+        match err {
+            ImageError::NoError => {}
+            err => return Err(self.error(err)),
+        }
+
+        let result = std::slice::from_raw_parts(keypoints, count).to_vec();
+        (function!(self, free_keypoints)?)(keypoints, count);
+        Ok(result)
+    }
+
+    /// Matches keypoints of two images.
+    pub unsafe fn match_keypoints(&self, a: ImageHandle, b: ImageHandle) -> Result<Alignment> {
+        let mut matches = Matches {
+            matches: std::ptr::null_mut(),
+            count: 0,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            inliers: 0,
+        };
+        match (function!(self, match_keypoints)?)(a, b, &mut matches) {
+            ImageError::NoError => {}
+            err => return Err(self.error(err)),
+        }
+
+        let alignment = Alignment {
+            matches: std::slice::from_raw_parts(matches.matches, matches.count).to_vec(),
+            offset_x: matches.offset_x,
+            offset_y: matches.offset_y,
+            inliers: matches.inliers,
+        };
+        (function!(self, free_matches)?)(&mut matches);
+        Ok(alignment)
+    }
+
+    /// Aligns two images and warps the moving one.
+    pub unsafe fn align(
+        &self,
+        reference: ImageHandle,
+        moving: ImageHandle,
+        motion: Motion,
+    ) -> Result<(Transform, ImageHandle)> {
+        let mut transform = Transform::default();
+        let mut warped = ImageHandle::new_null();
+        let err = (function!(self, align)?)(
+            reference,
+            moving,
+            motion as u32,
+            &mut transform,
+            &mut warped,
+        );
+        match err {
+            ImageError::NoError => Ok((transform, warped)),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Warps image by control grid.
+    pub unsafe fn mesh_warp(
+        &self,
+        handle: ImageHandle,
+        src_grid: &[Point],
+        dst_grid: &[Point],
+        cols: u32,
+        rows: u32,
+    ) -> Result<()> {
+        let src = src_grid.as_ptr();
+        match (function!(self, mesh_warp)?)(handle, src, dst_grid.as_ptr(), cols, rows) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates export profile from JSON.
+    pub unsafe fn export_profile_create(&self, json: &CStr) -> Result<ExportProfileHandle> {
+        let mut handle = ExportProfileHandle::new_null();
+        match (function!(self, export_profile_create)?)(RawStr(json.as_ptr()), &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Destroys export profile.
+    pub unsafe fn export_profile_destroy(&self, handle: ExportProfileHandle) {
+        (self.functions.export_profile_destroy)(handle)
+    }
+
+    /// Writes image to every output of export profile.
+    pub unsafe fn export(
+        &self,
+        handle: ImageHandle,
+        profile: ExportProfileHandle,
+        base_name: &CStr,
+    ) -> Result<()> {
+        match (function!(self, export)?)(handle, profile, RawPath(base_name.as_ptr())) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Writes icon set of image to directory.
+    pub unsafe fn generate_icon_set(
+        &self,
+        handle: ImageHandle,
+        kind: IconSetKind,
+        out_dir: &CStr,
+    ) -> Result<()> {
+        match (function!(self, generate_icon_set)?)(handle, kind as u32, RawPath(out_dir.as_ptr()))
+        {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Composes social media card over background image.
+    pub unsafe fn compose_card(
+        &self,
+        background: ImageHandle,
+        spec: &CardSpec,
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        match (function!(self, compose_card)?)(background, spec, &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens image with options within budget.
+    pub unsafe fn open_with_budget(
+        &self,
+        path: &CStr,
+        options: &OpenOptions,
+        budget: &OperationBudget,
+    ) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        let path = RawPath(path.as_ptr());
+        match (function!(self, open_with_budget)?)(path, options, budget, &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Saves image within budget.
+    pub unsafe fn save_with_budget(
+        &self,
+        handle: ImageHandle,
+        path: &CStr,
+        budget: &OperationBudget,
+    ) -> Result<()> {
+        match (function!(self, save_with_budget)?)(RawPath(path.as_ptr()), handle, budget) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Transcodes stream with operations within budget.
+    pub unsafe fn transcode_stream_with_budget<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        ops: &[Operation],
+        budget: &OperationBudget,
+    ) -> Result<()> {
+        let user_data = stream as *mut S as *mut c_void;
+        let err = (function!(self, transcode_stream_with_budget)?)(
+            read_callback::<S>,
+            write_callback::<S>,
+            ops.as_ptr(),
+            ops.len(),
+            budget,
+            user_data,
+        );
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Detaches image from current thread.
+    pub unsafe fn detach_thread(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, detach_thread)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Attaches image to current thread.
+    pub unsafe fn attach_thread(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, attach_thread)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Applies operation by name to copy of image.
+    pub unsafe fn apply(
+        &self,
+        handle: ImageHandle,
+        op: &CStr,
+        params: &CStr,
+    ) -> Result<ImageHandle> {
+        let mut result = ImageHandle::new_null();
+        let op = RawStr(op.as_ptr());
+        match (function!(self, apply)?)(handle, op, RawStr(params.as_ptr()), &mut result) {
+            ImageError::NoError => Ok(result),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Applies operation by name to downscaled copy of image.
+    pub unsafe fn apply_preview(
+        &self,
+        handle: ImageHandle,
+        op: &CStr,
+        params: &CStr,
+        max_size: u32,
+    ) -> Result<ImageHandle> {
+        let mut result = ImageHandle::new_null();
+        let (op, params) = (RawStr(op.as_ptr()), RawStr(params.as_ptr()));
+        match (function!(self, apply_preview)?)(handle, op, params, max_size, &mut result) {
+            ImageError::NoError => Ok(result),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Starts operation by name on worker thread.
+    pub unsafe fn submit(
+        &self,
+        handle: ImageHandle,
+        op: &CStr,
+        params: &CStr,
+    ) -> Result<JobHandle> {
+        let mut job = JobHandle::new_null();
+        let op = RawStr(op.as_ptr());
+        match (function!(self, submit)?)(handle, op, RawStr(params.as_ptr()), &mut job) {
+            ImageError::NoError => Ok(job),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Starts operation by name on worker thread cancelled by token.
+    pub unsafe fn submit_with_token(
+        &self,
+        handle: ImageHandle,
+        op: &CStr,
+        params: &CStr,
+        token: TokenHandle,
+    ) -> Result<JobHandle> {
+        let mut job = JobHandle::new_null();
+        let (op, params) = (RawStr(op.as_ptr()), RawStr(params.as_ptr()));
+        match (function!(self, submit_with_token)?)(handle, op, params, token, &mut job) {
+            ImageError::NoError => Ok(job),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates cancellation token.
+    pub unsafe fn token_create(&self) -> Result<TokenHandle> {
+        let mut token = TokenHandle::new_null();
+        match (function!(self, token_create)?)(&mut token) {
+            ImageError::NoError => Ok(token),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Cancels operations of token.
+    pub unsafe fn token_cancel(&self, token: TokenHandle) {
+        (self.functions.token_cancel)(token)
+    }
+
+    /// Destroys cancellation token.
+    pub unsafe fn token_destroy(&self, token: TokenHandle) {
+        (self.functions.token_destroy)(token)
+    }
+
+    /// Converts files with edit stack on worker threads, returning outcome of each file.
+    pub unsafe fn batch(
+        &self,
+        paths: &[CString],
+        pipeline: Option<&CStr>,
+        out_dir: &CStr,
+        options: &BatchOptions,
+    ) -> Result<Vec<ImageError>> {
+        let raw_paths: Vec<_> = paths.iter().map(|p| RawPath(p.as_ptr())).collect();
+        let pipeline = RawStr(pipeline.map_or(std::ptr::null(), |p| p.as_ptr()));
+        let mut results: Vec<_> = paths.iter().map(|_| ImageError::NoError).collect();
+        let err = (function!(self, batch)?)(
+            raw_paths.as_ptr(),
+            raw_paths.len(),
+            pipeline,
+            RawPath(out_dir.as_ptr()),
+            options,
+            results.as_mut_ptr(),
+        );
+        match err {
+            ImageError::NoError => Ok(results),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates empty operation pipeline.
+    pub unsafe fn pipeline_create(&self) -> Result<PipelineHandle> {
+        let mut handle = PipelineHandle::new_null();
+        match (function!(self, pipeline_create)?)(&mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Destroys operation pipeline.
+    pub unsafe fn pipeline_destroy(&self, pipeline: PipelineHandle) {
+        (self.functions.pipeline_destroy)(pipeline)
+    }
+
+    /// Appends crop to pipeline.
+    pub unsafe fn pipeline_add_crop(&self, pipeline: PipelineHandle, rect: Rect) -> Result<()> {
+        match (function!(self, pipeline_add_crop)?)(pipeline, rect) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Appends resize to pipeline.
+    pub unsafe fn pipeline_add_resize(
+        &self,
+        pipeline: PipelineHandle,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        match (function!(self, pipeline_add_resize)?)(pipeline, width, height) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Appends blur to pipeline.
+    pub unsafe fn pipeline_add_blur(&self, pipeline: PipelineHandle, sigma: f32) -> Result<()> {
+        match (function!(self, pipeline_add_blur)?)(pipeline, sigma) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Appends horizontal flip to pipeline.
+    pub unsafe fn pipeline_add_mirror(&self, pipeline: PipelineHandle) -> Result<()> {
+        match (function!(self, pipeline_add_mirror)?)(pipeline) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Applies operations of pipeline to image.
+    pub unsafe fn pipeline_execute(
+        &self,
+        pipeline: PipelineHandle,
+        handle: ImageHandle,
+    ) -> Result<()> {
+        match (function!(self, pipeline_execute)?)(pipeline, handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Applies operations of pipeline to TIFF file streaming it into TIFF file, reporting
+    /// progress to closure.
+    pub unsafe fn pipeline_execute_tiled<F: FnMut(f32) -> bool + Send>(
+        &self,
+        pipeline: PipelineHandle,
+        src: &CStr,
+        dst: &CStr,
+        progress: &mut F,
+    ) -> Result<()> {
+        let user_data = progress as *mut F as *mut c_void;
+        let callback = Some(progress_callback::<F> as ProgressFn);
+        let (src, dst) = (RawPath(src.as_ptr()), RawPath(dst.as_ptr()));
+        match (function!(self, pipeline_execute_tiled)?)(pipeline, src, dst, callback, user_data) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Applies operations of pipeline to image within budget.
+    pub unsafe fn pipeline_execute_with_budget(
+        &self,
+        pipeline: PipelineHandle,
+        handle: ImageHandle,
+        budget: &OperationBudget,
+    ) -> Result<()> {
+        match (function!(self, pipeline_execute_with_budget)?)(pipeline, handle, budget) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Applies operations of pipeline to TIFF file within budget, reporting progress to closure.
+    pub unsafe fn pipeline_execute_tiled_with_budget<F: FnMut(f32) -> bool + Send>(
+        &self,
+        pipeline: PipelineHandle,
+        src: &CStr,
+        dst: &CStr,
+        budget: &OperationBudget,
+        progress: &mut F,
+    ) -> Result<()> {
+        let user_data = progress as *mut F as *mut c_void;
+        let callback = Some(progress_callback::<F> as ProgressFn);
+        let (src, dst) = (RawPath(src.as_ptr()), RawPath(dst.as_ptr()));
+        let err = (function!(self, pipeline_execute_tiled_with_budget)?)(
+            pipeline, src, dst, budget, callback, user_data,
+        );
+        match err {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates job queue limiting its running jobs.
+    pub unsafe fn queue_create(&self, max_parallel: u32) -> Result<QueueHandle> {
+        let mut queue = QueueHandle::new_null();
+        match (function!(self, queue_create)?)(max_parallel, &mut queue) {
+            ImageError::NoError => Ok(queue),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Destroys job queue.
+    pub unsafe fn queue_destroy(&self, queue: QueueHandle) {
+        (self.functions.queue_destroy)(queue)
+    }
+
+    /// Starts operation by name as job of queue with priority.
+    pub unsafe fn submit_on(
+        &self,
+        queue: QueueHandle,
+        priority: i32,
+        handle: ImageHandle,
+        op: &CStr,
+        params: &CStr,
+        token: TokenHandle,
+    ) -> Result<JobHandle> {
+        let mut job = JobHandle::new_null();
+        let (op, params) = (RawStr(op.as_ptr()), RawStr(params.as_ptr()));
+        let submit_on = function!(self, submit_on)?;
+        match submit_on(queue, priority, handle, op, params, token, &mut job) {
+            ImageError::NoError => Ok(job),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Returns progress of job. Statuses of newer library unknown to these bindings are `Failed`,
+    /// so waiting for job ends and `job_result` reports its outcome.
+    pub unsafe fn job_status(&self, job: JobHandle) -> JobStatus {
+        let status = (self.functions.job_status)(job);
+        JobStatus::try_from(status).unwrap_or(JobStatus::Failed)
+    }
+
+    /// Waits for job and takes its resulting image, if any.
+    pub unsafe fn job_result(&self, job: JobHandle) -> Result<Option<ImageHandle>> {
+        let mut result = ImageHandle::new_null();
+        match (function!(self, job_result)?)(job, &mut result) {
+            ImageError::NoError if result.address() == 0 => Ok(None),
+            ImageError::NoError => Ok(Some(result)),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Destroys job.
+    pub unsafe fn job_destroy(&self, job: JobHandle) {
+        (self.functions.job_destroy)(job)
+    }
+
+    /// Reads EXIF tag of image.
+    pub unsafe fn exif_get(&self, handle: ImageHandle, tag: u16) -> Result<CString> {
+        let exif_get = function!(self, exif_get)?;
+        let get =
+            |buffer: *mut u8, len: &mut usize| exif_get(handle, tag, buffer as *mut c_char, len);
+        let value = self.read_buffer(get)?;
+        let value = CStr::from_bytes_with_nul(&value).map_err(|_| ImageError::Decoding)?;
+        Ok(value.to_owned())
+    }
+
+    /// Draws antialiased line over image.
+    pub unsafe fn draw_line(
+        &self,
+        handle: ImageHandle,
+        from: Point,
+        to: Point,
+        thickness: f32,
+        rgba: [u8; 4],
+    ) -> Result<()> {
+        let color = u32::from_be_bytes(rgba);
+        match (function!(self, draw_line)?)(handle, from.x, from.y, to.x, to.y, thickness, color) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Draws filled or stroked rectangle over image.
+    pub unsafe fn draw_rect(
+        &self,
+        handle: ImageHandle,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        style: DrawStyle,
+    ) -> Result<()> {
+        let (thickness, color, fill) = style.to_raw();
+        let draw_rect = function!(self, draw_rect)?;
+        match draw_rect(handle, x, y, width, height, thickness, color, fill) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Draws filled or stroked circle over image.
+    pub unsafe fn draw_circle(
+        &self,
+        handle: ImageHandle,
+        center: Point,
+        radius: f32,
+        style: DrawStyle,
+    ) -> Result<()> {
+        let (thickness, color, fill) = style.to_raw();
+        let draw_circle = function!(self, draw_circle)?;
+        match draw_circle(handle, center.x, center.y, radius, thickness, color, fill) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Draws filled or stroked polygon over image.
+    pub unsafe fn draw_polygon(
+        &self,
+        handle: ImageHandle,
+        points: &[Point],
+        style: DrawStyle,
+    ) -> Result<()> {
+        let (thickness, color, fill) = style.to_raw();
+        let draw_polygon = function!(self, draw_polygon)?;
+        match draw_polygon(
+            handle,
+            points.as_ptr(),
+            points.len(),
+            thickness,
+            color,
+            fill,
+        ) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Rounds corners of image.
+    pub unsafe fn round_corners(&self, handle: ImageHandle, radius: f32) -> Result<()> {
+        match (function!(self, round_corners)?)(handle, radius) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Masks image with circle.
+    pub unsafe fn circle_mask(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, circle_mask)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Multiplies alpha of image by luminance of mask.
+    pub unsafe fn apply_mask(
+        &self,
+        handle: ImageHandle,
+        mask: ImageHandle,
+        invert: bool,
+    ) -> Result<()> {
+        match (function!(self, apply_mask)?)(handle, mask, invert) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Blends watermark over image.
+    pub unsafe fn watermark(
+        &self,
+        handle: ImageHandle,
+        mark: ImageHandle,
+        anchor: Anchor,
+        margin: u32,
+        opacity: f32,
+        scale: f32,
+    ) -> Result<()> {
+        let anchor = anchor as u32;
+        match (function!(self, watermark)?)(handle, mark, anchor, margin, opacity, scale) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Draws compositional guides over image.
+    pub unsafe fn draw_guides(&self, handle: ImageHandle, spec: &GuideSpec) -> Result<()> {
+        match (function!(self, draw_guides)?)(handle, spec) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Estimates noise of image channels.
+    pub unsafe fn estimate_noise(&self, handle: ImageHandle) -> Result<[f64; 4]> {
+        let mut sigmas = [0.0; 4];
+        match (function!(self, estimate_noise)?)(handle, sigmas.as_mut_ptr()) {
+            ImageError::NoError => Ok(sigmas),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Estimates sharpness of image.
+    pub unsafe fn sharpness(&self, handle: ImageHandle) -> Result<f64> {
+        let mut sharpness = 0.0;
+        match (function!(self, sharpness)?)(handle, &mut sharpness) {
+            ImageError::NoError => Ok(sharpness),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Writes EXIF tag of image.
+    pub unsafe fn exif_set(
+        &self,
+        handle: ImageHandle,
+        tag: u16,
+        ty: ExifType,
+        value: &CStr,
+    ) -> Result<()> {
+        match (function!(self, exif_set)?)(handle, tag, ty as u32, RawStr(value.as_ptr())) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Applies EXIF orientation to image.
+    pub unsafe fn auto_orient(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, auto_orient)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Denoises image with automatically picked strength.
+    pub unsafe fn auto_denoise(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, auto_denoise)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Sharpens image with automatically picked strength.
+    pub unsafe fn auto_sharpen(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, auto_sharpen)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Corrects lateral chromatic aberration.
+    pub unsafe fn correct_ca(
+        &self,
+        handle: ImageHandle,
+        red_scale: f32,
+        blue_scale: f32,
+    ) -> Result<()> {
+        match (function!(self, correct_ca)?)(handle, red_scale, blue_scale) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads ICC profile of image.
+    pub unsafe fn icc_get(&self, handle: ImageHandle) -> Result<Vec<u8>> {
+        let icc_get = function!(self, icc_get)?;
+        self.read_buffer(|buffer, len| icc_get(handle, buffer, len))
+    }
+
+    /// Replaces ICC profile of image. Empty `icc` removes profile.
+    pub unsafe fn icc_set(&self, handle: ImageHandle, icc: &[u8]) -> Result<()> {
+        match (function!(self, icc_set)?)(handle, icc.as_ptr(), icc.len()) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Converts image from its ICC profile to sRGB.
+    pub unsafe fn convert_to_srgb(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, convert_to_srgb)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Removes purple fringes at high-contrast edges.
+    pub unsafe fn defringe(
+        &self,
+        handle: ImageHandle,
+        strength: f32,
+        hue_range: f32,
+    ) -> Result<()> {
+        match (function!(self, defringe)?)(handle, strength, hue_range) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads size, color type and bit depth of image.
+    pub unsafe fn info(&self, handle: ImageHandle) -> Result<ImageInfo> {
+        let mut info = bindings::ImageInfo::default();
+        match (function!(self, info)?)(handle, &mut info) {
+            ImageError::NoError => Ok(ImageInfo {
+                width: info.width,
+                height: info.height,
+                color_type: ColorType::from_raw(info.color_type),
+                bit_depth: info.bit_depth,
+                channels: info.channels,
+                has_alpha: info.has_alpha,
+            }),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Locks pixels of image for direct access.
+    pub unsafe fn lock_pixels(&self, handle: ImageHandle) -> Result<bindings::PixelLock> {
+        let mut lock = bindings::PixelLock {
+            pixels: std::ptr::null_mut(),
+            stride: 0,
+            width: 0,
+            height: 0,
+            format: 0,
+        };
+        match (function!(self, lock_pixels)?)(handle, &mut lock) {
+            ImageError::NoError => Ok(lock),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Locks pixels of image converted to color type with aligned rows.
+    pub unsafe fn lock_pixels_as(
+        &self,
+        handle: ImageHandle,
+        color_type: u32,
+        row_alignment: u32,
+    ) -> Result<bindings::PixelLock> {
+        let mut lock = bindings::PixelLock {
+            pixels: std::ptr::null_mut(),
+            stride: 0,
+            width: 0,
+            height: 0,
+            format: 0,
+        };
+        match (function!(self, lock_pixels_as)?)(handle, color_type, row_alignment, &mut lock) {
+            ImageError::NoError => Ok(lock),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Copies pixels of image converted to color type into buffer.
+    pub unsafe fn read_pixels(
+        &self,
+        handle: ImageHandle,
+        buffer: &mut [u8],
+        color_type: u32,
+        stride: usize,
+    ) -> Result<()> {
+        let (ptr, len) = (buffer.as_mut_ptr(), buffer.len());
+        match (function!(self, read_pixels)?)(handle, ptr, len, color_type, stride) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Passes every pixel of image to closure which may change it.
+    pub unsafe fn map_pixels<F>(&self, handle: ImageHandle, parallel: bool, f: &F) -> Result<()>
+    where
+        F: Fn(u32, u32, [u8; 4]) -> [u8; 4] + Sync,
+    {
+        let user_data = f as *const F as *mut c_void;
+        let callback = Some(pixel_callback::<F> as PixelFn);
+        match (function!(self, map_pixels)?)(handle, callback, user_data, parallel) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Passes rows of image to closure.
+    pub unsafe fn for_each_row<F>(&self, handle: ImageHandle, f: &mut F) -> Result<()>
+    where
+        F: FnMut(u32, &[u8]) -> bool,
+    {
+        let user_data = f as *mut F as *mut c_void;
+        let callback = Some(row_callback::<F> as RowFn);
+        match (function!(self, for_each_row)?)(handle, callback, user_data) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Unlocks pixels of image.
+    pub unsafe fn unlock_pixels(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, unlock_pixels)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads color of pixel.
+    pub unsafe fn get_pixel(&self, handle: ImageHandle, x: u32, y: u32) -> Result<u32> {
+        let mut rgba = 0;
+        match (function!(self, get_pixel)?)(handle, x, y, &mut rgba) {
+            ImageError::NoError => Ok(rgba),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Sets color of pixel.
+    pub unsafe fn set_pixel(&self, handle: ImageHandle, x: u32, y: u32, rgba: u32) -> Result<()> {
+        match (function!(self, set_pixel)?)(handle, x, y, rgba) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads physical resolution of image.
+    pub unsafe fn get_dpi(&self, handle: ImageHandle) -> Result<(f64, f64)> {
+        let (mut x, mut y) = (0.0, 0.0);
+        match (function!(self, get_dpi)?)(handle, &mut x, &mut y) {
+            ImageError::NoError => Ok((x, y)),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Sets physical resolution of image.
+    pub unsafe fn set_dpi(&self, handle: ImageHandle, x: f64, y: f64) -> Result<()> {
+        match (function!(self, set_dpi)?)(handle, x, y) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reconstructs clipped highlights.
+    pub unsafe fn recover_highlights(&self, handle: ImageHandle, strength: f32) -> Result<()> {
+        match (function!(self, recover_highlights)?)(handle, strength) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Lists keywords of PNG text of image.
+    pub unsafe fn png_text_keys(&self, handle: ImageHandle) -> Result<Vec<CString>> {
+        let png_text_keys = function!(self, png_text_keys)?;
+        let get =
+            |buffer: *mut u8, len: &mut usize| png_text_keys(handle, buffer as *mut c_char, len);
+        Ok(string_list(&self.read_buffer(get)?))
+    }
+
+    /// Reads PNG text of image.
+    pub unsafe fn png_text_get(&self, handle: ImageHandle, keyword: &CStr) -> Result<CString> {
+        let png_text_get = function!(self, png_text_get)?;
+        let get = |buffer: *mut u8, len: &mut usize| {
+            let keyword = RawStr(keyword.as_ptr());
+            png_text_get(handle, keyword, buffer as *mut c_char, len)
+        };
+        let value = self.read_buffer(get)?;
+        let value = CStr::from_bytes_with_nul(&value).map_err(|_| ImageError::Decoding)?;
+        Ok(value.to_owned())
+    }
+
+    /// Writes PNG text of image. `None` value removes text.
+    pub unsafe fn png_text_set(
+        &self,
+        handle: ImageHandle,
+        keyword: &CStr,
+        value: Option<&CStr>,
+    ) -> Result<()> {
+        let value = RawStr(value.map_or(std::ptr::null(), |v| v.as_ptr()));
+        match (function!(self, png_text_set)?)(handle, RawStr(keyword.as_ptr()), value) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Adjusts shadows and highlights of image.
+    pub unsafe fn shadows_highlights(
+        &self,
+        handle: ImageHandle,
+        shadows: f32,
+        highlights: f32,
+        radius: f32,
+    ) -> Result<()> {
+        match (function!(self, shadows_highlights)?)(handle, shadows, highlights, radius) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Adjusts hue, saturation and luminance of color bands of image.
+    pub unsafe fn hsl_adjust(&self, handle: ImageHandle, bands: &[HslBand]) -> Result<()> {
+        match (function!(self, hsl_adjust)?)(handle, bands.as_ptr(), bands.len()) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads XMP packet of image.
+    pub unsafe fn xmp_get(&self, handle: ImageHandle) -> Result<CString> {
+        let xmp_get = function!(self, xmp_get)?;
+        let get = |buffer: *mut u8, len: &mut usize| xmp_get(handle, buffer as *mut c_char, len);
+        let xmp = self.read_buffer(get)?;
+        let xmp = CStr::from_bytes_with_nul(&xmp).map_err(|_| ImageError::Decoding)?;
+        Ok(xmp.to_owned())
+    }
+
+    /// Reads IPTC dataset of image.
+    pub unsafe fn iptc_get(&self, handle: ImageHandle, dataset: u32) -> Result<Vec<CString>> {
+        let iptc_get = function!(self, iptc_get)?;
+        let get = |buffer: *mut u8, len: &mut usize| {
+            iptc_get(handle, dataset, buffer as *mut c_char, len)
+        };
+        Ok(string_list(&self.read_buffer(get)?))
+    }
+
+    /// Replaces color of image.
+    pub unsafe fn replace_color(
+        &self,
+        handle: ImageHandle,
+        from_rgba: u32,
+        to_rgba: u32,
+        tolerance: f32,
+        preserve_luminosity: bool,
+    ) -> Result<()> {
+        let replace = function!(self, replace_color)?;
+        match replace(handle, from_rgba, to_rgba, tolerance, preserve_luminosity) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Removes all metadata of image.
+    pub unsafe fn strip_metadata(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, strip_metadata)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Loads EXIF thumbnail of image at specified path.
+    pub unsafe fn exif_thumbnail(&self, path: &CStr) -> Result<ImageHandle> {
+        let mut handle = ImageHandle::new_null();
+        match (function!(self, exif_thumbnail)?)(RawPath(path.as_ptr()), &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Grades shadows, midtones and highlights of image.
+    pub unsafe fn split_tone(
+        &self,
+        handle: ImageHandle,
+        shadows_rgba: u32,
+        midtones_rgba: u32,
+        highlights_rgba: u32,
+        balance: f32,
+    ) -> Result<()> {
+        let split_tone = function!(self, split_tone)?;
+        match split_tone(
+            handle,
+            shadows_rgba,
+            midtones_rgba,
+            highlights_rgba,
+            balance,
+        ) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Applies film stock look to image.
+    pub unsafe fn film_emulation(
+        &self,
+        handle: ImageHandle,
+        stock: FilmStock,
+        strength: f32,
+    ) -> Result<()> {
+        match (function!(self, film_emulation)?)(handle, stock as u32, strength) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads message of the last error on this thread.
+    pub unsafe fn last_error_message(&self) -> Option<String> {
+        let size = (self.functions.last_error_message)(std::ptr::null_mut(), 0);
+        if size == 0 {
+            return None;
+        }
+
+        let mut buffer = vec![0u8; size];
+        (self.functions.last_error_message)(buffer.as_mut_ptr() as *mut c_char, size);
+        let message = CStr::from_bytes_with_nul(&buffer).ok()?;
+        Some(message.to_string_lossy().into_owned())
+    }
+
+    /// Sets depth of edit journal of image.
+    pub unsafe fn set_journal_depth(&self, handle: ImageHandle, depth: u32) -> Result<()> {
+        match (function!(self, set_journal_depth)?)(handle, depth) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reverts the last edit of image.
+    pub unsafe fn undo(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, undo)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reapplies the last undone edit of image.
+    pub unsafe fn redo(&self, handle: ImageHandle) -> Result<()> {
+        match (function!(self, redo)?)(handle) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads numbers of undo and redo steps of image.
+    pub unsafe fn history_len(&self, handle: ImageHandle) -> Result<(usize, usize)> {
+        let (mut undo, mut redo) = (0, 0);
+        match (function!(self, history_len)?)(handle, &mut undo, &mut redo) {
+            ImageError::NoError => Ok((undo, redo)),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Returns error of library function failed with `code`, with details of the last error on
+    /// this thread if they describe it.
+    unsafe fn error(&self, code: ImageError) -> Error {
+        let details = self
+            .last_error_details()
+            .ok()
+            .filter(|details| details.code == code as u32);
+        Error::Image { code, details }
+    }
+
+    /// Reads details of the last error on this thread.
+    pub unsafe fn last_error_details(&self) -> Result<ErrorInfo> {
+        let mut details = bindings::ErrorDetails::default();
+        match (self.functions.last_error_details)(&mut details) {
+            ImageError::NoError => {}
+            err => return Err(err.into()),
+        }
+
+        Ok(ErrorInfo {
+            code: details.code,
+            sub_code: ErrorSubCode::from_raw(details.sub_code),
+            format: Some(details.format).filter(|&f| f != u32::MAX),
+            os_error: Some(details.os_error).filter(|&e| e != 0),
+            message: CStr::from_ptr(details.message)
+                .to_string_lossy()
+                .into_owned(),
+        })
+    }
+
+    /// Exports edit stack of image as JSON.
+    pub unsafe fn export_edit_stack(&self, handle: ImageHandle) -> Result<String> {
+        let mut json = std::ptr::null_mut();
+        match (function!(self, export_edit_stack)?)(handle, &mut json) {
+            ImageError::NoError => {}
+            err => return Err(self.error(err)),
+        }
+
+        let stack = CStr::from_ptr(json).to_string_lossy().into_owned();
+        (function!(self, free_string)?)(json);
+        Ok(stack)
+    }
+
+    /// Applies edit stack `json` to image.
+    pub unsafe fn apply_edit_stack(&self, handle: ImageHandle, json: &str) -> Result<()> {
+        let json = CString::new(json).map_err(|_| ImageError::Parameter)?;
+        match (function!(self, apply_edit_stack)?)(handle, RawStr(json.as_ptr())) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Sets log `callback` of library.
+    pub unsafe fn set_log_callback(&self, callback: Option<bindings::LogFn>) {
+        (self.functions.set_log_callback)(callback, std::ptr::null_mut())
+    }
+
+    /// Enables or disables debug registry of image handles.
+    pub unsafe fn set_debug_handles(&self, enabled: bool) {
+        (self.functions.set_debug_handles)(enabled)
+    }
+
+    /// Returns capability flags of library, none if library is older than them.
+    pub unsafe fn capabilities(&self) -> u32 {
+        function!(self, capabilities).map_or(0, |capabilities| capabilities())
+    }
+
+    /// Checks whether library provides working function.
+    pub unsafe fn has_fn(&self, name: &CStr) -> bool {
+        function!(self, has_fn).map_or(false, |has_fn| has_fn(RawStr(name.as_ptr())))
+    }
+
+    /// Checks whether library supports operation `op`.
+    pub unsafe fn supports(&self, op: Op) -> bool {
+        use std::mem::offset_of;
+        let (function, capability) = match op {
+            Op::OpenUrl => (offset_of!(Functions, open_image_url), capability::HTTP),
+            Op::ConvertToSrgb => (offset_of!(Functions, convert_to_srgb), capability::ICC),
+            Op::ExportProfiles => (offset_of!(Functions, export), 0),
+            Op::IconSets => (offset_of!(Functions, generate_icon_set), 0),
+            Op::Plugins => (offset_of!(Functions, register_encoder), 0),
+            Op::Cards => (offset_of!(Functions, compose_card), 0),
+            Op::Budgets => (offset_of!(Functions, transcode_stream_with_budget), 0),
+            Op::ThreadTransfer => (offset_of!(Functions, attach_thread), 0),
+            Op::Jobs => (offset_of!(Functions, job_destroy), 0),
+            Op::Progress => (offset_of!(Functions, encode_with_progress), 0),
+            Op::Cancellation => (offset_of!(Functions, submit_with_token), 0),
+            Op::Batch => (offset_of!(Functions, batch), 0),
+            Op::Pipelines => (offset_of!(Functions, pipeline_execute), 0),
+            Op::ReuseImages => (offset_of!(Functions, resize_into), 0),
+            Op::BufferPool => (offset_of!(Functions, pool_trim), 0),
+            Op::CopyOnWrite => (offset_of!(Functions, clone_cow), 0),
+            Op::Info => (offset_of!(Functions, info), 0),
+            Op::LockPixels => (offset_of!(Functions, unlock_pixels), 0),
+            Op::PixelAccess => (offset_of!(Functions, set_pixel), 0),
+            Op::Views => (offset_of!(Functions, view), 0),
+            Op::Convert => (offset_of!(Functions, convert), 0),
+            Op::ReadPixels => (offset_of!(Functions, read_pixels), 0),
+            Op::MapPixels => (offset_of!(Functions, map_pixels), 0),
+            Op::ForEachRow => (offset_of!(Functions, for_each_row), 0),
+            Op::Clone => (offset_of!(Functions, clone), 0),
+            Op::NewImage => (offset_of!(Functions, new), 0),
+            Op::SwapChannels => (offset_of!(Functions, swap_channels), 0),
+            Op::Histogram => (offset_of!(Functions, histogram16), 0),
+            Op::Stats => (offset_of!(Functions, stats), 0),
+            Op::DominantColors => (offset_of!(Functions, dominant_colors), 0),
+            Op::AverageColor => (offset_of!(Functions, average_color), 0),
+            Op::PerceptualHash => (offset_of!(Functions, phash), 0),
+            Op::Blurhash => (offset_of!(Functions, blurhash), 0),
+            Op::Thumbhash => (offset_of!(Functions, thumbhash_decode), 0),
+            Op::Compare => (offset_of!(Functions, compare), 0),
+            Op::Diff => (offset_of!(Functions, diff), 0),
+            Op::Trim => (offset_of!(Functions, is_blank), 0),
+            Op::SmartCrop => (offset_of!(Functions, smart_crop), 0),
+            Op::Sharpness => (offset_of!(Functions, sharpness), 0),
+            Op::Draw => (offset_of!(Functions, draw_polygon), 0),
+            Op::Gradient => (offset_of!(Functions, gradient), 0),
+            Op::TestPattern => (offset_of!(Functions, color_bars), 0),
+            Op::Watermark => (offset_of!(Functions, watermark), 0),
+            Op::PackSprites => (offset_of!(Functions, pack_sprites), 0),
+            Op::Noise => (offset_of!(Functions, noise), 0),
+            Op::RoundCorners => (offset_of!(Functions, circle_mask), 0),
+            Op::ApplyMask => (offset_of!(Functions, apply_mask), 0),
+            Op::CmykTiff => (offset_of!(Functions, save_cmyk_tiff), 0),
+            Op::PipelineBudgets => (offset_of!(Functions, pipeline_execute_tiled_with_budget), 0),
+            Op::TranscodeAs => (offset_of!(Functions, transcode_stream2), 0),
+            Op::ResultCache => (offset_of!(Functions, pipeline_transcode), 0),
+            Op::JobQueues => (offset_of!(Functions, submit_on), 0),
+        };
+        function < self.functions.size && self.capabilities() & capability == capability
+    }
+
+    /// Returns negotiated ABI version of functions table. It is older than
+    /// `bindings::ABI_VERSION` if library is older than bindings.
+    pub fn abi_version(&self) -> u32 {
+        self.abi_version
+    }
+
+    /// Returns number of live images.
+    pub unsafe fn live_handle_count(&self) -> usize {
+        (self.functions.live_handle_count)()
+    }
+
+    /// Lists live images with backtraces of their creation.
+    pub unsafe fn dump_leaks(&self) -> Vec<(usize, String)> {
+        let mut leaks = Vec::<(usize, String)>::new();
+        let user_data = &mut leaks as *mut Vec<(usize, String)> as *mut c_void;
+        (self.functions.dump_leaks)(leak_callback, user_data);
+        leaks
+    }
+
+    /// Attaches annotation with JSON markup to region of image.
+    pub unsafe fn add_annotation(
+        &self,
+        handle: ImageHandle,
+        rect: Rect,
+        json: &CStr,
+    ) -> Result<()> {
+        match (function!(self, add_annotation)?)(handle, rect, RawStr(json.as_ptr())) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads annotations of image with their JSON markup.
+    pub unsafe fn annotations(&self, handle: ImageHandle) -> Result<Vec<(Rect, CString)>> {
+        let mut count = 0;
+        match (function!(self, annotation_count)?)(handle, &mut count) {
+            ImageError::NoError => {}
+            err => return Err(self.error(err)),
+        }
+
+        let mut annotations = Vec::with_capacity(count);
+        for index in 0..count {
+            let rect = std::cell::Cell::new(Rect::default());
+            let get_annotation = function!(self, get_annotation)?;
+            let get = |buffer: *mut u8, len: &mut usize| {
+                let buffer = buffer as *mut c_char;
+                get_annotation(handle, index, rect.as_ptr(), buffer, len)
+            };
+            let json = self.read_buffer(get)?;
+            let json = CStr::from_bytes_with_nul(&json).map_err(|_| ImageError::Decoding)?;
+            annotations.push((rect.get(), json.to_owned()));
+        }
+        Ok(annotations)
+    }
+
+    /// Removes annotation of image.
+    pub unsafe fn remove_annotation(&self, handle: ImageHandle, index: usize) -> Result<()> {
+        match (function!(self, remove_annotation)?)(handle, index) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Draws annotated regions over image.
+    pub unsafe fn burn_annotations(
+        &self,
+        handle: ImageHandle,
+        color: u32,
+        line_width: u32,
+    ) -> Result<()> {
+        match (function!(self, burn_annotations)?)(handle, color, line_width) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Creates empty layered document.
+    pub unsafe fn doc_new(&self, width: u32, height: u32) -> Result<DocumentHandle> {
+        let mut handle = DocumentHandle::new_null();
+        match (function!(self, doc_new)?)(width, height, &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Destroys document.
+    pub unsafe fn doc_destroy(&self, handle: DocumentHandle) {
+        (self.functions.doc_destroy)(handle)
+    }
+
+    /// Adds layer on top of document.
+    pub unsafe fn doc_add_layer(
+        &self,
+        handle: DocumentHandle,
+        image: ImageHandle,
+        props: &LayerProps,
+    ) -> Result<()> {
+        match (function!(self, doc_add_layer)?)(handle, image, props) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads number of layers of document.
+    pub unsafe fn doc_layer_count(&self, handle: DocumentHandle) -> Result<usize> {
+        let mut count = 0;
+        match (function!(self, doc_layer_count)?)(handle, &mut count) {
+            ImageError::NoError => Ok(count),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads properties of document layer.
+    pub unsafe fn doc_get_layer(&self, handle: DocumentHandle, index: usize) -> Result<LayerProps> {
+        let mut props = LayerProps::default();
+        match (function!(self, doc_get_layer)?)(handle, index, &mut props) {
+            ImageError::NoError => Ok(props),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Sets properties of document layer.
+    pub unsafe fn doc_set_layer(
+        &self,
+        handle: DocumentHandle,
+        index: usize,
+        props: &LayerProps,
+    ) -> Result<()> {
+        match (function!(self, doc_set_layer)?)(handle, index, props) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Reads image of document layer. Image is owned by document.
+    pub unsafe fn doc_layer_image(
+        &self,
+        handle: DocumentHandle,
+        index: usize,
+    ) -> Result<ImageHandle> {
+        let mut image = ImageHandle::new_null();
+        match (function!(self, doc_layer_image)?)(handle, index, &mut image) {
+            ImageError::NoError => Ok(image),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Removes layer of document and returns its image.
+    pub unsafe fn doc_remove_layer(
+        &self,
+        handle: DocumentHandle,
+        index: usize,
+    ) -> Result<ImageHandle> {
+        let mut image = ImageHandle::new_null();
+        match (function!(self, doc_remove_layer)?)(handle, index, &mut image) {
+            ImageError::NoError => Ok(image),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Moves layer of document.
+    pub unsafe fn doc_move_layer(
+        &self,
+        handle: DocumentHandle,
+        from: usize,
+        to: usize,
+    ) -> Result<()> {
+        match (function!(self, doc_move_layer)?)(handle, from, to) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens OpenRaster document at specified path.
+    pub unsafe fn doc_open_ora(&self, path: &CStr) -> Result<DocumentHandle> {
+        let mut handle = DocumentHandle::new_null();
+        match (function!(self, doc_open_ora)?)(RawPath(path.as_ptr()), &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Saves document as OpenRaster at specified path.
+    pub unsafe fn doc_save_ora(&self, handle: DocumentHandle, path: &CStr) -> Result<()> {
+        match (function!(self, doc_save_ora)?)(handle, RawPath(path.as_ptr())) {
+            ImageError::NoError => Ok(()),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Opens layers of PSD file at specified path as document.
+    pub unsafe fn doc_open_psd(&self, path: &CStr) -> Result<DocumentHandle> {
+        let mut handle = DocumentHandle::new_null();
+        match (function!(self, doc_open_psd)?)(RawPath(path.as_ptr()), &mut handle) {
+            ImageError::NoError => Ok(handle),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Composites layers of document into new image.
+    pub unsafe fn doc_flatten(&self, handle: DocumentHandle) -> Result<ImageHandle> {
+        let mut image = ImageHandle::new_null();
+        match (function!(self, doc_flatten)?)(handle, &mut image) {
+            ImageError::NoError => Ok(image),
+            err => Err(self.error(err)),
+        }
+    }
+
+    /// Checks that image handle is live.
+    pub unsafe fn is_valid(&self, handle: ImageHandle) -> bool {
+        (self.functions.is_valid)(handle)
+    }
+
+    /// Encodes image in specified format.
+    pub unsafe fn encode_image(&self, handle: ImageHandle, format: ImageFormat) -> Result<Vec<u8>> {
+        let encode_image = function!(self, encode_image)?;
+        let encode =
+            |buffer: *mut u8, len: &mut usize| encode_image(handle, format as u32, buffer, len);
+        self.read_buffer(encode)
+    }
+
+    /// Reads data from library function using size query with null buffer.
+    unsafe fn read_buffer<F>(&self, function: F) -> Result<Vec<u8>>
+    where
+        F: Fn(*mut u8, &mut usize) -> ImageError,
+    {
+        let mut len = 0;
+        let err = function(std::ptr::null_mut(), &mut len);
+        if err != ImageError::NoError {
+            return Err(self.error(err));
+        }
+
+        let mut buffer = vec![0; len];
+        match function(buffer.as_mut_ptr(), &mut len) {
+            ImageError::NoError => Ok(buffer),
+            err => Err(self.error(err)),
+        }
+    }
+}
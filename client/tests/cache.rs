@@ -0,0 +1,107 @@
+use image_sl_client::{
+    ColorType, Exports, FunctionsV2, ImageError, ImageFactory, ImageFormat, InitConfig,
+    OpenOptions, Version,
+};
+use std::os::raw::c_char;
+use std::path::Path;
+
+// Links library statically, so tests don't depend on location of shared library.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn factory() -> ImageFactory {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    unsafe { ImageFactory::from_exports(exports) }.expect("library is linked")
+}
+
+fn entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+// The only test of this file, as it changes result cache of the whole process.
+#[test]
+fn repeated_transcoding_is_answered_from_cache() {
+    let factory = factory();
+    let dir = std::env::temp_dir().join(format!("image_sl_cache_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    factory.enable_cache(&dir, 1 << 20).unwrap();
+
+    let mut image = factory.new_image(8, 8, ColorType::Rgb8, [0; 4]).unwrap();
+    image.set_pixel(3, 3, [255, 0, 0, 255]).unwrap();
+    let input = image.encode(ImageFormat::Png).unwrap();
+    let mut pipeline = factory.pipeline().unwrap();
+    pipeline.resize(4, 4).unwrap().mirror().unwrap();
+
+    let mut first = Vec::new();
+    pipeline
+        .transcode(&input[..], &mut first, ImageFormat::Png, 0)
+        .unwrap();
+    let cached = entries(&dir);
+    assert_eq!(cached.len(), 1);
+    assert_eq!(cached[0].len(), 64);
+    let decoded = factory
+        .open_image_from_memory(&first, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(decoded.info().unwrap().width, 4);
+
+    // Cached result is written even though its input couldn't be decoded again.
+    std::fs::write(dir.join(&cached[0]), b"cached").unwrap();
+    let mut second = Vec::new();
+    pipeline
+        .transcode(&input[..], &mut second, ImageFormat::Png, 0)
+        .unwrap();
+    assert_eq!(second, b"cached");
+    std::fs::write(dir.join(&cached[0]), &first).unwrap();
+
+    // Other format and other operations are different requests.
+    let mut bmp = Vec::new();
+    pipeline
+        .transcode(&input[..], &mut bmp, ImageFormat::Bmp, 0)
+        .unwrap();
+    assert_ne!(bmp, first);
+    pipeline.blur(1.0).unwrap();
+    let mut blurred = Vec::new();
+    pipeline
+        .transcode(&input[..], &mut blurred, ImageFormat::Png, 0)
+        .unwrap();
+    assert_eq!(entries(&dir).len(), 3);
+
+    // Limit keeps the most recently used entries, also for cache enabled again.
+    let limit = (bmp.len() + blurred.len()) as u64;
+    factory.enable_cache(&dir, limit).unwrap();
+    assert_eq!(entries(&dir).len(), 2);
+    assert!(!entries(&dir).contains(&cached[0]));
+    let mut again = Vec::new();
+    pipeline
+        .transcode(&input[..], &mut again, ImageFormat::Png, 0)
+        .unwrap();
+    assert_eq!(again, blurred);
+    assert_eq!(entries(&dir).len(), 2);
+
+    // Disabled cache leaves its files and doesn't add new ones.
+    factory.disable_cache().unwrap();
+    pipeline.mirror().unwrap();
+    pipeline
+        .transcode(&input[..], &mut Vec::new(), ImageFormat::Png, 0)
+        .unwrap();
+    assert_eq!(entries(&dir).len(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
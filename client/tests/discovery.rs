@@ -0,0 +1,37 @@
+use image_sl_client::{lib_search_paths, Error, ImageFactory, LIB_FILE_NAME, LIB_PATH_VAR};
+use std::path::PathBuf;
+
+// The only test of this file, as it changes environment of the whole process.
+#[test]
+fn library_is_discovered() {
+    std::env::remove_var(LIB_PATH_VAR);
+    // Cargo builds shared library of `image_sl` dev-dependency next to tests.
+    let exe_dir = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_owned();
+    assert_eq!(lib_search_paths()[0], exe_dir.join(LIB_FILE_NAME));
+    ImageFactory::new().unwrap();
+
+    std::env::set_var(LIB_PATH_VAR, &exe_dir);
+    assert_eq!(lib_search_paths(), [exe_dir.join(LIB_FILE_NAME)]);
+    ImageFactory::new().unwrap();
+
+    let missing = PathBuf::from("missing").join(LIB_FILE_NAME);
+    std::env::set_var(LIB_PATH_VAR, &missing);
+    match ImageFactory::new() {
+        Err(Error::NotFound(paths)) => assert_eq!(paths, std::slice::from_ref(&missing)),
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("missing library is loaded"),
+    }
+    std::env::remove_var(LIB_PATH_VAR);
+
+    match ImageFactory::with_path(&missing) {
+        Err(Error::Load(_)) => {}
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("missing library is loaded"),
+    }
+    let factory = ImageFactory::with_path(exe_dir.join(LIB_FILE_NAME)).unwrap();
+    assert!(factory.version().build.starts_with("0.1.0"));
+}
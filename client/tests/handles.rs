@@ -0,0 +1,90 @@
+use image_sl_client::{
+    bindings, ColorType, Exports, FunctionsV2, ImageError, ImageFactory, InitConfig, Version,
+};
+use std::os::raw::{c_char, c_void};
+
+// Links library statically, so tests don't depend on location of shared library.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn factory() -> ImageFactory {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    unsafe { ImageFactory::from_exports(exports) }.expect("library is linked")
+}
+
+/// Returns table of library functions, for passing handles client never passes.
+fn table() -> FunctionsV2 {
+    let mut table = std::mem::MaybeUninit::<FunctionsV2>::uninit();
+    unsafe {
+        assert_eq!(
+            functions_ex(bindings::ABI_VERSION, table.as_mut_ptr()),
+            ImageError::NoError
+        );
+        table.assume_init()
+    }
+}
+
+// The only test of this file, as it changes handle registry of the whole process.
+#[test]
+fn stale_and_foreign_handles_are_rejected() {
+    let factory = factory();
+    factory.set_debug_handles(true);
+    let f = table().functions;
+    unsafe {
+        let mut handle = bindings::ImageHandle::new_null();
+        let color_type = ColorType::Rgba8 as u32;
+        assert_eq!(
+            (f.new)(2, 2, color_type, 0x0102_03ff, &mut handle),
+            ImageError::NoError
+        );
+        assert!((f.is_valid)(handle));
+        (f.destroy_image)(handle);
+        assert!(!(f.is_valid)(handle));
+        let mut pixel = 0;
+        assert_eq!(
+            (f.get_pixel)(handle, 0, 0, &mut pixel),
+            ImageError::InvalidHandle
+        );
+        assert_eq!(
+            (f.set_pixel)(handle, 0, 0, 0x0909_09ff),
+            ImageError::InvalidHandle
+        );
+        // Destroying image twice is reported instead of freeing its memory again.
+        (f.destroy_image)(handle);
+
+        // Registry rejects handles it didn't hand out without reading memory behind them.
+        let local = [0x5a5a_5a5a_usize; 32];
+        let foreign: bindings::ImageHandle = std::mem::transmute(local.as_ptr() as *const c_void);
+        assert!(!(f.is_valid)(foreign));
+        assert_eq!(
+            (f.get_pixel)(foreign, 0, 0, &mut pixel),
+            ImageError::InvalidHandle
+        );
+        assert_eq!(
+            (f.set_pixel)(foreign, 0, 0, 0x0909_09ff),
+            ImageError::InvalidHandle
+        );
+        assert_eq!(local, [0x5a5a_5a5a; 32]);
+    }
+
+    let image = factory
+        .new_image(2, 2, ColorType::Rgba8, [1, 2, 3, 255])
+        .unwrap();
+    assert!(image.is_valid());
+    assert_eq!(image.pixel(1, 1).unwrap(), [1, 2, 3, 255]);
+    drop(image);
+    factory.set_debug_handles(false);
+}
@@ -0,0 +1,3239 @@
+use image_sl_client::{
+    bindings, capability, exif_tag, hash_distance, iptc_dataset, Anchor, BlendMode, ColorBand,
+    ColorType, CompareMetric, DrawStyle, EinkFormat, Error, ExifType, Exports, FilmStock, Filter,
+    FunctionsV2, GradientKind, GradientStop, GuideSpec, HashAlgorithm, HslBand, IconSetKind, Image,
+    ImageError, ImageFactory, ImageFormat, InitConfig, JobStatus, LayerProps, LogLevel,
+    MatchMethod, Motion, NoiseKind, Op, OpenOptions, Operation, OperationBudget, PixelLayout,
+    Point, RawPixelFormat, Rect, RowOrder, SaveOptions, UnknownValue, Version,
+};
+use std::convert::TryFrom;
+use std::os::raw::{c_char, c_void};
+
+// Links library statically, so tests don't depend on location of shared library.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn factory() -> ImageFactory {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    unsafe { ImageFactory::from_exports(exports) }.expect("library is linked")
+}
+
+// Library of ABI version 20, which has pipelines but no pixel access and blank images yet.
+unsafe extern "C" fn functions_v20(requested_abi: u32, out: *mut FunctionsV2) -> ImageError {
+    if requested_abi > 20 {
+        return ImageError::Unsupported;
+    }
+    functions_ex(requested_abi, out)
+}
+
+/// Returns table of library functions, for tests passing arguments client doesn't pass.
+fn table() -> FunctionsV2 {
+    let mut table = std::mem::MaybeUninit::<FunctionsV2>::uninit();
+    unsafe {
+        assert_eq!(
+            functions_ex(bindings::ABI_VERSION, table.as_mut_ptr()),
+            ImageError::NoError
+        );
+        table.assume_init()
+    }
+}
+
+/// Returns code of error of failed `result`, `None` if it succeeded.
+fn error_code<T>(result: Result<T, Error>) -> Option<ImageError> {
+    result.err().and_then(|e| e.code())
+}
+
+/// Returns `jpeg` with EXIF segment of camera `make` and `orientation` inserted after its start
+/// marker.
+fn with_exif(jpeg: &[u8], make: &str, orientation: u16) -> Vec<u8> {
+    let make = [make.as_bytes(), b"\0"].concat();
+    // Little-endian TIFF header and IFD of two entries, followed by value of make.
+    let mut tiff = b"II*\0\x08\0\0\0\x02\0".to_vec();
+    tiff.extend_from_slice(&[0x0f, 0x01, 2, 0]);
+    tiff.extend_from_slice(&(make.len() as u32).to_le_bytes());
+    tiff.extend_from_slice(&38u32.to_le_bytes());
+    tiff.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0]);
+    tiff.extend_from_slice(&[orientation as u8, (orientation >> 8) as u8, 0, 0]);
+    tiff.extend_from_slice(&[0; 4]);
+    tiff.extend_from_slice(&make);
+
+    with_segment(jpeg, 0xe1, &[&b"Exif\0\0"[..], &tiff].concat())
+}
+
+/// Returns `jpeg` with segment of `marker` holding `payload` inserted after its start marker.
+fn with_segment(jpeg: &[u8], marker: u8, payload: &[u8]) -> Vec<u8> {
+    let len = (2 + payload.len()) as u16;
+    let mut segment = vec![0xff, marker];
+    segment.extend_from_slice(&len.to_be_bytes());
+    segment.extend_from_slice(payload);
+    [&jpeg[..2], &segment, &jpeg[2..]].concat()
+}
+
+#[test]
+fn version_matches_library() {
+    let version = factory().version();
+    assert_eq!(version.major, 0);
+    assert!(version.build.starts_with("0.1.0"), "{}", version.build);
+}
+
+#[test]
+fn older_library_is_degraded() {
+    assert_eq!(factory().abi_version(), bindings::ABI_VERSION);
+    let exports = Exports {
+        functions_ex: functions_v20,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    let factory = unsafe { ImageFactory::from_exports(exports) }.unwrap();
+    assert_eq!(factory.abi_version(), 20);
+    assert!(factory.supports(Op::Pipelines));
+    assert!(!factory.supports(Op::PixelAccess));
+    assert!(!factory.supports(Op::NewImage));
+
+    let pixels = [1, 2, 3, 255];
+    let image = factory
+        .image_from_raw(&pixels, 1, 1, RawPixelFormat::Rgba8)
+        .unwrap();
+    assert!(image.encode(ImageFormat::Png).is_ok());
+    match image.pixel(0, 0) {
+        Err(e) if e.code() == Some(ImageError::Unsupported) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+    match factory.new_image(1, 1, ColorType::L8, [0; 4]) {
+        Err(e) if e.code() == Some(ImageError::Unsupported) => {}
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("image is created by older library"),
+    }
+}
+
+#[test]
+fn new_image_is_filled() {
+    let image = factory()
+        .new_image(3, 2, ColorType::Rgba8, [10, 20, 30, 40])
+        .unwrap();
+    let info = image.info().unwrap();
+    assert_eq!((info.width, info.height), (3, 2));
+    assert_eq!(info.color_type, Some(ColorType::Rgba8));
+    assert_eq!(image.pixel(2, 1).unwrap(), [10, 20, 30, 40]);
+}
+
+#[test]
+fn clone_has_own_pixels() {
+    let image = factory()
+        .new_image(2, 2, ColorType::Rgb8, [1, 2, 3, 255])
+        .unwrap();
+    let mut clone = image.clone();
+    clone.set_pixel(0, 0, [9, 9, 9, 255]).unwrap();
+    assert_eq!(image.pixel(0, 0).unwrap(), [1, 2, 3, 255]);
+    assert_eq!(clone.pixel(0, 0).unwrap(), [9, 9, 9, 255]);
+}
+
+#[test]
+fn image_moves_to_thread() {
+    let image = factory()
+        .new_image(2, 2, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    let mut images = vec![image.clone(), image];
+    let workers: Vec<_> = images
+        .drain(..)
+        .enumerate()
+        .map(|(i, mut image)| {
+            std::thread::spawn(move || {
+                image.set_pixel(0, 0, [i as u8, 0, 0, 255]).unwrap();
+                image
+            })
+        })
+        .collect();
+    for (i, worker) in workers.into_iter().enumerate() {
+        let image = worker.join().unwrap();
+        assert_eq!(image.pixel(0, 0).unwrap(), [i as u8, 0, 0, 255]);
+    }
+}
+
+#[test]
+fn pipeline_applies_operations() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(8, 6, ColorType::Rgb8, [0, 0, 0, 255])
+        .unwrap();
+    image.set_pixel(0, 1, [255, 255, 255, 255]).unwrap();
+    let rect = Rect {
+        x: 0,
+        y: 1,
+        width: 4,
+        height: 4,
+    };
+    image
+        .pipeline()
+        .crop(rect)
+        .resize(2, 2, Filter::Lanczos3)
+        .mirror()
+        .run()
+        .unwrap();
+    let info = image.info().unwrap();
+    assert_eq!((info.width, info.height), (2, 2));
+    assert_eq!(info.color_type, Some(ColorType::Rgb8));
+    assert!(image.pixel(1, 0).unwrap()[0] > image.pixel(0, 0).unwrap()[0]);
+
+    match image.pipeline().mirror().blur(-1.0).run() {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert_eq!(image.info().unwrap().width, 2);
+}
+
+#[test]
+fn swap_channels_reorders_pixels() {
+    let mut image = factory()
+        .new_image(1, 1, ColorType::Rgba8, [1, 2, 3, 4])
+        .unwrap();
+    image.swap_channels([2, 1, 0, 3]).unwrap();
+    assert_eq!(image.pixel(0, 0).unwrap(), [3, 2, 1, 4]);
+}
+
+#[test]
+fn histogram_counts_channel_values() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(4, 2, ColorType::Rgb16, [10, 20, 30, 255])
+        .unwrap();
+    image.set_pixel(3, 1, [200, 20, 30, 255]).unwrap();
+    let bins = image.histogram(0).unwrap();
+    assert_eq!((bins[10], bins[200]), (7, 1));
+    assert_eq!(bins.iter().sum::<u32>(), 8);
+    let bins = image.histogram16(2).unwrap();
+    assert_eq!(bins[30 * 257], 8);
+
+    let view = image
+        .view(Rect {
+            x: 2,
+            y: 1,
+            width: 2,
+            height: 1,
+        })
+        .unwrap();
+    assert_eq!(view.histogram(0).unwrap()[200], 1);
+    match image.histogram(3) {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn stats_describe_channels() {
+    let mut image = factory()
+        .new_image(2, 1, ColorType::Rgba8, [0, 255, 51, 255])
+        .unwrap();
+    image.set_pixel(1, 0, [255, 255, 153, 255]).unwrap();
+    let stats = image.stats().unwrap();
+    assert_eq!(stats.channels, 4);
+    assert_eq!((stats.min[0], stats.max[0], stats.mean[0]), (0.0, 1.0, 0.5));
+    assert!((stats.std_dev[0] - 0.5).abs() < 1e-9);
+    assert!((stats.mean[2] - 0.4).abs() < 1e-9);
+    assert_eq!((stats.std_dev[1], stats.mean[3]), (0.0, 1.0));
+    let luminance = 0.2126 * 0.5 + 0.7152 + 0.0722 * 0.4;
+    assert!((stats.luminance - luminance).abs() < 1e-9);
+}
+
+#[test]
+fn dominant_colors_are_found() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(4, 4, ColorType::Rgba8, [200, 10, 10, 255])
+        .unwrap();
+    for x in 0..4 {
+        image.set_pixel(x, 0, [10, 10, 200, 255]).unwrap();
+        image.set_pixel(x, 1, [0, 0, 0, 0]).unwrap();
+    }
+    let colors = image.dominant_colors(4).unwrap();
+    assert_eq!(colors.len(), 2);
+    assert_eq!(colors[0], ([200, 10, 10, 255], 2.0 / 3.0));
+    assert_eq!(colors[1].0, [10, 10, 200, 255]);
+    assert_eq!(image.dominant_colors(1).unwrap()[0].1, 1.0);
+    match image.dominant_colors(0) {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn average_color_weights_alpha() {
+    let mut image = factory()
+        .new_image(2, 2, ColorType::Rgba8, [100, 0, 200, 255])
+        .unwrap();
+    image.set_pixel(1, 0, [200, 100, 0, 255]).unwrap();
+    image.set_pixel(0, 1, [255, 255, 255, 0]).unwrap();
+    image.set_pixel(1, 1, [255, 255, 255, 0]).unwrap();
+    assert_eq!(image.average_color(None).unwrap(), [150, 50, 100, 128]);
+    let rect = Rect {
+        x: 1,
+        y: 0,
+        width: 1,
+        height: 2,
+    };
+    assert_eq!(image.average_color(Some(rect)).unwrap(), [200, 100, 0, 128]);
+    let rect = Rect { x: 2, ..rect };
+    match image.average_color(Some(rect)) {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn perceptual_hashes_match_similar_images() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(64, 48, ColorType::Rgb8, [20, 20, 20, 255])
+        .unwrap();
+    for y in 0..48 {
+        for x in 0..24 {
+            image.set_pixel(x, y, [230, 230, 230, 255]).unwrap();
+        }
+    }
+    let mut other = image.blur(1.0).unwrap();
+    for y in 0..48 {
+        other.set_pixel(63, y, [200, 0, 0, 255]).unwrap();
+    }
+    let mut mirrored = image.clone();
+    mirrored.mirror().unwrap();
+
+    let algorithms = [
+        HashAlgorithm::Average,
+        HashAlgorithm::Difference,
+        HashAlgorithm::Perceptual,
+    ];
+    for algorithm in algorithms {
+        let hash = image.perceptual_hash(algorithm).unwrap();
+        let similar = other.perceptual_hash(algorithm).unwrap();
+        let different = mirrored.perceptual_hash(algorithm).unwrap();
+        assert!(hash_distance(hash, similar) <= 10, "{:?}", algorithm);
+        assert!(hash_distance(hash, different) > 10, "{:?}", algorithm);
+    }
+}
+
+#[test]
+fn blurhash_encodes_components() {
+    let factory = factory();
+    let image = factory
+        .new_image(8, 6, ColorType::Rgb8, [255, 0, 0, 255])
+        .unwrap();
+    // Size flag of 4x3 components, maximum of AC components, red DC and 11 AC components.
+    let hash = image.blurhash(4, 3).unwrap();
+    assert_eq!((&hash[..1], &hash[2..6], hash.len()), ("L", "TI:j", 28));
+    assert_eq!(image.blurhash(1, 1).unwrap(), "00TI:j");
+
+    let mut image = factory
+        .new_image(96, 64, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    for y in 0..64 {
+        for x in 48..96 {
+            image.set_pixel(x, y, [255, 255, 255, 255]).unwrap();
+        }
+    }
+    let hash = image.blurhash(9, 9).unwrap();
+    assert_eq!(hash.len(), 4 + 2 * 81);
+    assert_ne!(&hash[1..2], "0");
+    match image.blurhash(0, 3) {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn thumbhash_decodes_to_preview() {
+    let factory = factory();
+    let image = factory
+        .new_image(120, 60, ColorType::Rgb8, [200, 40, 40, 255])
+        .unwrap();
+    let hash = image.thumbhash().unwrap();
+    assert!(hash.len() <= 25);
+    let preview = factory.image_from_thumbhash(&hash).unwrap();
+    // Aspect ratio is kept approximately.
+    let info = preview.info().unwrap();
+    assert_eq!(info.width, 32);
+    assert!((14..=20).contains(&info.height));
+    let [r, g, b, a] = preview.pixel(16, 8).unwrap();
+    assert!(r.abs_diff(200) < 12 && g.abs_diff(40) < 12 && b.abs_diff(40) < 12);
+    assert_eq!(a, 255);
+
+    let image = factory
+        .new_image(40, 80, ColorType::Rgba8, [0, 0, 255, 0])
+        .unwrap();
+    let preview = factory
+        .image_from_thumbhash(&image.thumbhash().unwrap())
+        .unwrap();
+    let info = preview.info().unwrap();
+    assert_eq!(info.height, 32);
+    assert!((14..=20).contains(&info.width));
+    assert!(preview.pixel(8, 16).unwrap()[3] < 12);
+    match factory.image_from_thumbhash(&hash[..3]) {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn compare_measures_similarity() {
+    let factory = factory();
+    let image = factory
+        .new_image(32, 24, ColorType::Rgb8, [100, 100, 100, 255])
+        .unwrap();
+    let mut noisy = image.clone();
+    for y in 0..24 {
+        for x in 0..32 {
+            let v = if (x + y) % 2 == 0 { 110 } else { 90 };
+            noisy.set_pixel(x, y, [v, v, v, 255]).unwrap();
+        }
+    }
+
+    let psnr = image.compare(&image, CompareMetric::Psnr).unwrap();
+    assert!(psnr.is_infinite());
+    // Error of 10 of 255 everywhere.
+    let psnr = image.compare(&noisy, CompareMetric::Psnr).unwrap();
+    assert!((psnr - 28.13).abs() < 0.01, "{}", psnr);
+    assert!((image.compare(&image, CompareMetric::Ssim).unwrap() - 1.0).abs() < 1e-9);
+    let ssim = image.compare(&noisy, CompareMetric::Ssim).unwrap();
+    assert!(ssim < 0.9 && ssim > 0.0, "{}", ssim);
+
+    let smaller = factory
+        .new_image(16, 24, ColorType::Rgb8, [100, 100, 100, 255])
+        .unwrap();
+    match image.compare(&smaller, CompareMetric::Ssim) {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn diff_highlights_differing_pixels() {
+    let factory = factory();
+    let image = factory
+        .new_image(8, 4, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    let mut changed = image.clone();
+    changed.set_pixel(1, 1, [5, 0, 0, 255]).unwrap();
+    changed.set_pixel(6, 2, [0, 200, 0, 255]).unwrap();
+
+    assert_eq!(image.differing_pixels(&changed, 0).unwrap(), 2);
+    let (diff, count) = image.diff(&changed, 10).unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(diff.pixel(6, 2).unwrap(), [255, 0, 0, 255]);
+    assert_ne!(diff.pixel(1, 1).unwrap(), [255, 0, 0, 255]);
+    assert_eq!(image.differing_pixels(&image, 0).unwrap(), 0);
+
+    let smaller = factory
+        .new_image(4, 4, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    match image.differing_pixels(&smaller, 0) {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn trim_crops_uniform_borders() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(10, 8, ColorType::Rgb8, [255, 255, 255, 255])
+        .unwrap();
+    assert!(image.is_blank(0).unwrap());
+    match image.trim(0) {
+        Err(e) if e.code() == Some(ImageError::NotFound) => {}
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+
+    image.set_pixel(2, 3, [0, 0, 0, 255]).unwrap();
+    image.set_pixel(6, 4, [0, 0, 0, 255]).unwrap();
+    image.set_pixel(8, 1, [250, 250, 250, 255]).unwrap();
+    assert!(!image.is_blank(0).unwrap());
+    assert!(!image.is_blank(10).unwrap());
+    let trimmed = image.trim(10).unwrap();
+    let info = trimmed.info().unwrap();
+    assert_eq!((info.width, info.height), (5, 2));
+    assert_eq!(trimmed.pixel(0, 0).unwrap(), [0, 0, 0, 255]);
+    let info = image.trim(0).unwrap().info().unwrap();
+    assert_eq!((info.width, info.height), (7, 4));
+}
+
+#[test]
+fn smart_crop_keeps_detailed_part() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(120, 40, ColorType::Rgb8, [128, 128, 128, 255])
+        .unwrap();
+    // Checkerboard near the right edge is the only detail of image.
+    for y in 0..40 {
+        for x in 90..110 {
+            let v = if (x / 2 + y / 2) % 2 == 0 { 0 } else { 255 };
+            image.set_pixel(x, y, [v, v, v, 255]).unwrap();
+        }
+    }
+
+    let cropped = image.smart_crop(40, 40).unwrap();
+    let info = cropped.info().unwrap();
+    assert_eq!((info.width, info.height), (40, 40));
+    let detailed = (0..40).any(|x| cropped.pixel(x, 0).unwrap()[0] < 64);
+    assert!(detailed);
+
+    let thumbnail = image.smart_crop(24, 12).unwrap().info().unwrap();
+    assert_eq!((thumbnail.width, thumbnail.height), (24, 12));
+}
+
+#[test]
+fn blurred_image_is_less_sharp() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(32, 32, ColorType::Rgb8, [0, 0, 0, 255])
+        .unwrap();
+    for y in 0..32 {
+        for x in 0..32 {
+            if (x / 4 + y / 4) % 2 == 0 {
+                image.set_pixel(x, y, [255, 255, 255, 255]).unwrap();
+            }
+        }
+    }
+    let sharp = image.sharpness().unwrap();
+    let blurry = image.blur(2.0).unwrap().sharpness().unwrap();
+    assert!(blurry < sharp / 2.0, "{} {}", blurry, sharp);
+
+    let flat = factory
+        .new_image(8, 8, ColorType::Rgb8, [90, 90, 90, 255])
+        .unwrap();
+    assert_eq!(flat.sharpness().unwrap(), 0.0);
+    let tiny = factory
+        .new_image(2, 2, ColorType::Rgb8, [0, 0, 0, 255])
+        .unwrap();
+    assert!(tiny.sharpness().is_err());
+}
+
+#[test]
+fn shapes_are_drawn_antialiased() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(40, 40, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    let white = [255, 255, 255, 255];
+    image
+        .draw_rect(4.0, 4.0, 10.0, 10.0, DrawStyle::Fill(white))
+        .unwrap();
+    assert_eq!(image.pixel(8, 8).unwrap(), white);
+    assert_eq!(image.pixel(14, 8).unwrap(), [0, 0, 0, 255]);
+
+    image
+        .draw_circle(
+            Point { x: 28.0, y: 28.0 },
+            8.0,
+            DrawStyle::Stroke([255, 0, 0, 255], 2.0),
+        )
+        .unwrap();
+    assert_eq!(image.pixel(28, 28).unwrap(), [0, 0, 0, 255]);
+    assert_eq!(image.pixel(35, 27).unwrap()[0], 255);
+    // Line 2 pixels thick through centers of row covers neighbouring rows by half.
+    image
+        .draw_line(
+            Point { x: 2.0, y: 36.5 },
+            Point { x: 12.0, y: 36.5 },
+            2.0,
+            white,
+        )
+        .unwrap();
+    assert_eq!(image.pixel(6, 36).unwrap(), white);
+    assert!((120..136).contains(&image.pixel(6, 37).unwrap()[0]));
+
+    let triangle = [
+        Point { x: 20.0, y: 2.0 },
+        Point { x: 38.0, y: 2.0 },
+        Point { x: 29.0, y: 14.0 },
+    ];
+    image
+        .draw_polygon(&triangle, DrawStyle::Fill([0, 255, 0, 255]))
+        .unwrap();
+    assert_eq!(image.pixel(29, 5).unwrap(), [0, 255, 0, 255]);
+    assert!(image
+        .draw_polygon(&triangle[..2], DrawStyle::Fill(white))
+        .is_err());
+    assert!(image
+        .draw_line(triangle[0], triangle[1], 0.0, white)
+        .is_err());
+
+    // Shapes are replayed from edit stack.
+    let mut replayed = factory
+        .new_image(40, 40, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    replayed
+        .apply_edit_stack(&image.edit_stack().unwrap())
+        .unwrap();
+    assert_eq!(replayed.differing_pixels(&image, 0).unwrap(), 0);
+}
+
+#[test]
+fn gradients_interpolate_stops() {
+    let factory = factory();
+    let stops = [
+        GradientStop::new(1.0, [255, 255, 255, 255]),
+        GradientStop::new(0.0, [0, 0, 0, 255]),
+    ];
+    let image = factory
+        .gradient(256, 4, &stops, GradientKind::Linear, 0.0)
+        .unwrap();
+    assert_eq!(image.pixel(0, 0).unwrap(), [0, 0, 0, 255]);
+    assert_eq!(image.pixel(128, 2).unwrap(), [128, 128, 128, 255]);
+    assert_eq!(image.pixel(255, 3).unwrap(), [255, 255, 255, 255]);
+
+    // Vertical gradient from top to bottom.
+    let image = factory
+        .gradient(4, 100, &stops, GradientKind::Linear, 90.0)
+        .unwrap();
+    assert!(image.pixel(2, 10).unwrap()[0] < image.pixel(2, 90).unwrap()[0]);
+    assert_eq!(image.pixel(0, 50).unwrap(), image.pixel(3, 50).unwrap());
+
+    // Transparent stop doesn't darken radial gradient.
+    let stops = [
+        GradientStop::new(0.0, [255, 0, 0, 255]),
+        GradientStop::new(1.0, [0, 0, 0, 0]),
+    ];
+    let image = factory
+        .gradient(64, 64, &stops, GradientKind::Radial, 0.0)
+        .unwrap();
+    assert_eq!(image.pixel(32, 32).unwrap()[..3], [255, 0, 0]);
+    let [r, _, _, a] = image.pixel(48, 48).unwrap();
+    assert!(r == 255 && a > 0 && a < 255);
+    assert!(factory
+        .gradient(4, 4, &[], GradientKind::Linear, 0.0)
+        .is_err());
+}
+
+#[test]
+fn test_patterns_are_generated() {
+    let factory = factory();
+    let (white, black) = ([255, 255, 255, 255], [0, 0, 0, 255]);
+    let board = factory.checkerboard(10, 6, 3, white, black).unwrap();
+    assert_eq!(board.pixel(0, 0).unwrap(), white);
+    assert_eq!(board.pixel(3, 0).unwrap(), black);
+    assert_eq!(board.pixel(3, 3).unwrap(), white);
+    assert_eq!(board.pixel(6, 3).unwrap(), black);
+    assert!(factory.checkerboard(10, 6, 0, white, black).is_err());
+
+    let bars = factory.color_bars(700, 120).unwrap();
+    let info = bars.info().unwrap();
+    assert_eq!((info.width, info.height), (700, 120));
+    assert_eq!(bars.pixel(50, 10).unwrap(), [192, 192, 192, 255]);
+    assert_eq!(bars.pixel(150, 10).unwrap(), [192, 192, 0, 255]);
+    assert_eq!(bars.pixel(650, 10).unwrap(), [0, 0, 192, 255]);
+    assert_eq!(bars.pixel(50, 85).unwrap(), [0, 0, 192, 255]);
+    assert_eq!(bars.pixel(200, 110).unwrap(), [255, 255, 255, 255]);
+}
+
+#[test]
+fn watermark_is_stamped_at_anchor() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(100, 50, ColorType::Rgb8, [0, 0, 0, 255])
+        .unwrap();
+    let mark = factory
+        .new_image(10, 5, ColorType::Rgba8, [255, 255, 255, 255])
+        .unwrap();
+    image
+        .watermark(&mark, Anchor::BottomRight, 4, 0.4, 0.2)
+        .unwrap();
+    // Mark is resized to 20 x 10 pixels at bottom right corner 4 pixels away from edges.
+    assert_eq!(image.pixel(76, 36).unwrap(), [102, 102, 102, 255]);
+    assert_eq!(image.pixel(95, 45).unwrap(), [102, 102, 102, 255]);
+    assert_eq!(image.pixel(75, 36).unwrap(), [0, 0, 0, 255]);
+    assert_eq!(image.pixel(96, 45).unwrap(), [0, 0, 0, 255]);
+    assert_eq!(image.pixel(76, 46).unwrap(), [0, 0, 0, 255]);
+
+    let mut image = factory
+        .new_image(30, 30, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    image.watermark(&mark, Anchor::Center, 4, 1.0, 0.0).unwrap();
+    assert_eq!(image.pixel(10, 12).unwrap(), [255, 255, 255, 255]);
+    assert_eq!(image.pixel(9, 12).unwrap(), [0, 0, 0, 255]);
+    assert!(image.watermark(&mark, Anchor::Top, 0, 1.5, 0.0).is_err());
+}
+
+#[test]
+fn sprites_are_packed() {
+    let factory = factory();
+    let red = factory
+        .new_image(30, 20, ColorType::Rgb8, [255, 0, 0, 255])
+        .unwrap();
+    let green = factory
+        .new_image(40, 10, ColorType::Rgba8, [0, 255, 0, 255])
+        .unwrap();
+    let blue = factory
+        .new_image(20, 10, ColorType::L8, [0, 0, 255, 255])
+        .unwrap();
+    let (sheet, placements) = factory.pack_sprites(&[&green, &red, &blue], 64, 2).unwrap();
+    let rect = |x, y, width, height| Rect {
+        x,
+        y,
+        width,
+        height,
+    };
+    // Red is the tallest one, green doesn't fit next to it, blue does.
+    assert_eq!(placements[1], rect(0, 0, 30, 20));
+    assert_eq!(placements[2], rect(32, 0, 20, 10));
+    assert_eq!(placements[0], rect(0, 22, 40, 10));
+    let info = sheet.info().unwrap();
+    assert_eq!((info.width, info.height), (52, 32));
+    assert_eq!(sheet.pixel(29, 19).unwrap(), [255, 0, 0, 255]);
+    assert_eq!(sheet.pixel(31, 0).unwrap(), [0, 0, 0, 0]);
+    assert_eq!(sheet.pixel(39, 31).unwrap(), [0, 255, 0, 255]);
+
+    assert!(factory.pack_sprites(&[&green], 39, 0).is_err());
+}
+
+#[test]
+fn noise_depends_on_seed() {
+    let factory = factory();
+    for &kind in &[NoiseKind::Value, NoiseKind::Perlin, NoiseKind::Simplex] {
+        let noise = factory.noise(64, 48, kind, 0.05, 4, 7).unwrap();
+        let info = noise.info().unwrap();
+        assert_eq!(info.color_type, Some(ColorType::L8));
+        let stats = noise.stats().unwrap();
+        assert!(
+            stats.max[0] - stats.min[0] > 0.3,
+            "{:?} noise is flat",
+            kind
+        );
+        assert!(
+            (stats.mean[0] - 0.5).abs() < 0.15,
+            "{:?} noise is biased",
+            kind
+        );
+
+        let same = factory.noise(64, 48, kind, 0.05, 4, 7).unwrap();
+        assert_eq!(
+            noise.compare(&same, CompareMetric::Psnr).unwrap(),
+            f64::INFINITY
+        );
+        let other = factory.noise(64, 48, kind, 0.05, 4, 8).unwrap();
+        assert!(noise.differing_pixels(&other, 0).unwrap() > 0);
+    }
+    assert!(factory.noise(8, 8, NoiseKind::Perlin, 0.0, 1, 0).is_err());
+    assert!(factory.noise(8, 8, NoiseKind::Perlin, 0.1, 0, 0).is_err());
+}
+
+#[test]
+fn corners_are_masked() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(40, 20, ColorType::Rgb8, [0, 0, 255, 255])
+        .unwrap();
+    let source = image.clone();
+    image.round_corners(8.0).unwrap();
+    assert_eq!(image.info().unwrap().color_type, Some(ColorType::Rgba8));
+    assert_eq!(image.pixel(0, 0).unwrap(), [0, 0, 255, 0]);
+    assert_eq!(image.pixel(39, 19).unwrap(), [0, 0, 255, 0]);
+    assert_eq!(image.pixel(8, 0).unwrap(), [0, 0, 255, 255]);
+    assert_eq!(image.pixel(20, 10).unwrap(), [0, 0, 255, 255]);
+    let corner = image.pixel(2, 2).unwrap()[3];
+    assert!(
+        corner > 0 && corner < 255,
+        "edge is antialiased: {}",
+        corner
+    );
+
+    // Corners are replayed from edit stack.
+    let mut replayed = source.clone();
+    replayed
+        .apply_edit_stack(&image.edit_stack().unwrap())
+        .unwrap();
+    assert_eq!(replayed.differing_pixels(&image, 0).unwrap(), 0);
+    assert!(image.round_corners(-1.0).is_err());
+
+    let mut image = factory
+        .new_image(40, 20, ColorType::Rgb16, [255; 4])
+        .unwrap();
+    image.circle_mask().unwrap();
+    assert_eq!(image.info().unwrap().color_type, Some(ColorType::Rgba16));
+    assert_eq!(image.pixel(20, 10).unwrap(), [255; 4]);
+    assert_eq!(image.pixel(8, 10).unwrap()[3], 0);
+    assert_eq!(image.pixel(20, 1).unwrap()[3], 255);
+    assert_eq!(image.pixel(28, 10).unwrap()[3], 255);
+    assert_eq!(image.pixel(31, 10).unwrap()[3], 0);
+}
+
+#[test]
+fn mask_cuts_out_image() {
+    let factory = factory();
+    let (white, black) = ([255, 255, 255, 255], [0, 0, 0, 255]);
+    let mask = factory.checkerboard(8, 8, 4, white, black).unwrap();
+    let red = factory
+        .new_image(8, 8, ColorType::Rgb8, [255, 0, 0, 255])
+        .unwrap();
+
+    let mut image = red.clone();
+    image.apply_mask(&mask, false).unwrap();
+    assert_eq!(image.info().unwrap().color_type, Some(ColorType::Rgba8));
+    assert_eq!(image.pixel(0, 0).unwrap(), [255, 0, 0, 255]);
+    assert_eq!(image.pixel(4, 0).unwrap(), [255, 0, 0, 0]);
+    let mut inverted = red.clone();
+    inverted.apply_mask(&mask, true).unwrap();
+    assert_eq!(inverted.pixel(0, 0).unwrap(), [255, 0, 0, 0]);
+    assert_eq!(inverted.pixel(4, 0).unwrap(), [255, 0, 0, 255]);
+
+    let gray = factory
+        .new_image(8, 8, ColorType::L8, [128, 128, 128, 255])
+        .unwrap();
+    image.apply_mask(&gray, false).unwrap();
+    assert_eq!(image.pixel(0, 0).unwrap(), [255, 0, 0, 128]);
+
+    let small = factory.new_image(4, 4, ColorType::L8, white).unwrap();
+    let err = image.apply_mask(&small, false).unwrap_err();
+    assert_eq!(err.code(), Some(ImageError::Parameter));
+}
+
+#[test]
+fn cmyk_tiff_separates_colors() {
+    let factory = factory();
+    let mut image = factory.new_image(4, 1, ColorType::Rgba8, [0; 4]).unwrap();
+    image.set_pixel(0, 0, [255, 255, 255, 255]).unwrap();
+    image.set_pixel(1, 0, [255, 0, 0, 255]).unwrap();
+    image.set_pixel(2, 0, [0, 0, 0, 255]).unwrap();
+    image.set_pixel(3, 0, [0, 0, 0, 0]).unwrap();
+    let path = std::env::temp_dir().join(format!("image_sl_cmyk_{}.tiff", std::process::id()));
+    let icc = b"not really a profile";
+    image.save_cmyk_tiff(&path, Some(icc)).unwrap();
+
+    let file = std::fs::File::open(&path).unwrap();
+    let mut decoder = tiff::decoder::Decoder::new(file).unwrap();
+    assert_eq!(decoder.colortype().unwrap(), tiff::ColorType::CMYK(8));
+    let tag = tiff::tags::Tag::Unknown(0x8773);
+    // Decoder reads BYTE values as wider integers.
+    let profile: Vec<u64> = decoder.get_tag_u64_vec(tag).unwrap();
+    assert!(profile.iter().map(|&b| b as u8).eq(icc.iter().copied()));
+    let pixels = match decoder.read_image().unwrap() {
+        tiff::decoder::DecodingResult::U8(pixels) => pixels,
+        _ => panic!("CMYK TIFF isn't 8-bit"),
+    };
+    let expected = [0, 0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 255, 0, 0, 0, 0];
+    assert_eq!(pixels, expected);
+    std::fs::remove_file(&path).unwrap();
+
+    let missing = std::env::temp_dir().join("image_sl_missing_dir/cmyk.tiff");
+    let err = image.save_cmyk_tiff(&missing, None).unwrap_err();
+    assert_eq!(err.code(), Some(ImageError::Io));
+}
+
+#[test]
+fn frozen_images_are_processed_into_destination() {
+    let factory = factory();
+    let mut image = factory.new_image(4, 4, ColorType::Rgba8, [0; 4]).unwrap();
+    image.set_pixel(1, 1, [255, 255, 255, 255]).unwrap();
+    let frozen = image.freeze().unwrap();
+
+    let mut blurred = factory.new_image(4, 4, ColorType::Rgba8, [0; 4]).unwrap();
+    frozen.blur_into(&mut blurred, 1.0).unwrap();
+    assert_ne!(blurred.pixel(2, 2).unwrap(), [0; 4]);
+    let mut resized = factory.new_image(2, 2, ColorType::Rgba8, [0; 4]).unwrap();
+    frozen.resize_into(&mut resized).unwrap();
+    assert_ne!(resized.pixel(0, 0).unwrap(), [0; 4]);
+}
+
+#[test]
+fn keypoints_of_frozen_images_are_matched() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/logo.jpg");
+    let logo = factory().open_image(path).unwrap();
+    let small = logo.resize_with_progress(240, 240, |_| true).unwrap();
+    let frozen = small.freeze().unwrap();
+    let alignment = frozen.match_keypoints(&frozen).unwrap();
+    assert!(alignment.inliers > 0);
+    assert!(alignment.offset_x.abs() < 0.5 && alignment.offset_y.abs() < 0.5);
+}
+
+#[test]
+fn jobs_run_on_frozen_images() {
+    let factory = factory();
+    let mut image = factory.new_image(2, 1, ColorType::Rgba8, [0; 4]).unwrap();
+    image.set_pixel(0, 0, [255, 0, 0, 255]).unwrap();
+    let frozen = image.freeze().unwrap();
+
+    let mirrored = frozen
+        .submit("mirror", "")
+        .unwrap()
+        .result()
+        .unwrap()
+        .unwrap();
+    assert_eq!(mirrored.pixel(1, 0).unwrap(), [255, 0, 0, 255]);
+
+    let path = std::env::temp_dir().join(format!("image_sl_job_{}.png", std::process::id()));
+    let params = format!(r#"{{"path": {:?}}}"#, path.to_str().unwrap());
+    let job = frozen.submit("save", &params).unwrap();
+    assert!(job.result().unwrap().is_none());
+    assert_eq!(job.status(), JobStatus::Finished);
+    assert!(factory.open_image(&path).is_ok());
+    std::fs::remove_file(&path).unwrap();
+
+    let token = factory.cancel_token().unwrap();
+    token.cancel();
+    let job = frozen.submit_with_token("mirror", "", &token).unwrap();
+    match job.result() {
+        Err(e) => assert_eq!(e.code(), Some(ImageError::Cancelled)),
+        Ok(_) => panic!("cancelled job is finished"),
+    }
+    assert_eq!(job.status(), JobStatus::Failed);
+}
+
+#[test]
+fn views_share_pixels_of_image() {
+    let factory = factory();
+    let mut image = factory.new_image(4, 4, ColorType::Rgba8, [0; 4]).unwrap();
+    image.set_pixel(1, 1, [10, 20, 30, 255]).unwrap();
+    image.set_pixel(2, 2, [40, 50, 60, 255]).unwrap();
+    let rect = Rect {
+        x: 1,
+        y: 1,
+        width: 2,
+        height: 2,
+    };
+    let view = image.view(rect).unwrap();
+
+    // Reading view as whole doesn't turn it into ordinary image.
+    let png = view.encode(ImageFormat::Png).unwrap();
+    let decoded = factory
+        .open_image_from_memory(&png, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(decoded.pixel(1, 1).unwrap(), [40, 50, 60, 255]);
+    assert!(view.blurhash(1, 1).is_ok());
+    let frozen = view.freeze().unwrap();
+    let hashes = std::thread::scope(|s| {
+        let threads: Vec<_> = (0..2)
+            .map(|_| s.spawn(|| frozen.blurhash(1, 1).unwrap()))
+            .collect();
+        threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(hashes[0], hashes[1]);
+    let cow = frozen.thaw().unwrap();
+    assert_eq!(
+        (cow.info().unwrap().width, cow.info().unwrap().height),
+        (2, 2)
+    );
+    assert_eq!(cow.pixel(0, 0).unwrap(), [10, 20, 30, 255]);
+
+    // Copy of view is ordinary image, and neither its changes nor changes of image show in view.
+    let mut copy = view.clone_cow().unwrap();
+    copy.set_pixel(0, 0, [1, 2, 3, 255]).unwrap();
+    image.set_pixel(2, 2, [7, 8, 9, 255]).unwrap();
+    assert_eq!(view.pixel(0, 0).unwrap(), [10, 20, 30, 255]);
+    assert_eq!(view.pixel(1, 1).unwrap(), [40, 50, 60, 255]);
+    assert_eq!(image.pixel(1, 1).unwrap(), [10, 20, 30, 255]);
+    assert_eq!(cow.pixel(0, 0).unwrap(), [10, 20, 30, 255]);
+}
+
+#[test]
+fn views_reject_changes() {
+    let f = table().functions;
+    unsafe {
+        let mut handle = bindings::ImageHandle::new_null();
+        let color_type = ColorType::Rgba8 as u32;
+        assert_eq!(
+            (f.new)(4, 4, color_type, 0x0102_03ff, &mut handle),
+            ImageError::NoError
+        );
+        let image = handle;
+        let rect = Rect {
+            x: 2,
+            y: 2,
+            width: 2,
+            height: 2,
+        };
+        assert_eq!((f.view)(image, rect, &mut handle), ImageError::NoError);
+        let view = handle;
+        let err = (f.set_pixel)(view, 0, 0, 0x0909_09ff);
+        assert_eq!(err, ImageError::InvalidHandle);
+        let mut lock = std::mem::MaybeUninit::uninit();
+        let err = (f.lock_pixels)(view, lock.as_mut_ptr());
+        assert_eq!(err, ImageError::InvalidHandle);
+
+        // Pixels of destroyed image stay alive for view.
+        (f.destroy_image)(image);
+        let mut pixel = 0;
+        assert_eq!((f.get_pixel)(view, 1, 1, &mut pixel), ImageError::NoError);
+        assert_eq!(pixel, 0x0102_03ff);
+        (f.destroy_image)(view);
+    }
+}
+
+#[test]
+fn encoded_image_opens_back() {
+    let factory = factory();
+    let pixels = [255, 0, 0, 255, 0, 255, 0, 255];
+    let image = factory
+        .image_from_raw(&pixels, 2, 1, RawPixelFormat::Rgba8)
+        .unwrap();
+    let png = image.encode(ImageFormat::Png).unwrap();
+    let opened = factory
+        .open_image_from_memory(&png, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(opened.pixel(1, 0).unwrap(), [0, 255, 0, 255]);
+}
+
+#[test]
+fn errors_of_library_are_reported() {
+    let factory = factory();
+    let image = factory.new_image(2, 2, ColorType::L8, [0; 4]).unwrap();
+    match image.pixel(2, 0) {
+        Err(e) if e.code() == Some(ImageError::Parameter) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+    match factory.new_image(1, 1, ColorType::Rgba32F, [0; 4]) {
+        Err(e) if e.code() == Some(ImageError::Unsupported) => {}
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("float image is created"),
+    }
+    match factory.new_image(u32::MAX, u32::MAX, ColorType::Rgba16, [0; 4]) {
+        Err(e) if e.code() == Some(ImageError::LimitExceeded) => {}
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("image larger than address space is created"),
+    }
+    match factory.open_image_from_memory(b"\x89PNG\r\n\x1a\n", &OpenOptions::default()) {
+        Err(e) if e.code() == Some(ImageError::Decoding) => {
+            let message = e.to_string();
+            assert!(message.starts_with("image decoding error: "), "{}", message);
+            assert!(
+                message.len() > "image decoding error: ".len(),
+                "{}",
+                message
+            );
+        }
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("truncated PNG is opened"),
+    }
+}
+
+#[test]
+fn budget_stops_opening() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/logo.jpg");
+    let budget = OperationBudget {
+        max_bytes: 1024,
+        ..Default::default()
+    };
+    match factory().open_image_with_budget(path, &OpenOptions::default(), &budget) {
+        Err(e) if e.code() == Some(ImageError::BudgetExceeded) => {}
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("image is opened beyond budget"),
+    }
+
+    // Handle passed in by caller is overwritten, not destroyed.
+    let table = table();
+    let f = &table.functions;
+    let path = std::ffi::CString::new(path).unwrap();
+    unsafe {
+        let mut handle = bindings::ImageHandle::new_null();
+        let color_type = ColorType::L8 as u32;
+        assert_eq!(
+            (f.new)(1, 1, color_type, 0, &mut handle),
+            ImageError::NoError
+        );
+        let image = handle;
+        let err = (f.open_with_budget)(
+            bindings::RawPath(path.as_ptr()),
+            &OpenOptions::default(),
+            &budget,
+            &mut handle,
+        );
+        assert_eq!(err, ImageError::BudgetExceeded);
+        assert_eq!(handle.address(), 0);
+        let mut pixel = 0;
+        assert_eq!((f.get_pixel)(image, 0, 0, &mut pixel), ImageError::NoError);
+        (f.destroy_image)(image);
+    }
+}
+
+#[test]
+fn budget_stops_pipelines() {
+    let factory = factory();
+    let budget = OperationBudget {
+        max_bytes: 1024,
+        ..Default::default()
+    };
+    let mut pipeline = factory.pipeline().unwrap();
+    pipeline.resize(64, 64).unwrap().blur(1.0).unwrap();
+    let mut image = factory.new_image(32, 32, ColorType::Rgb8, [0; 4]).unwrap();
+    match pipeline.execute_with_budget(&mut image, &budget) {
+        Err(e) => assert_eq!(e.code(), Some(ImageError::BudgetExceeded)),
+        Ok(_) => panic!("pipeline is executed beyond budget"),
+    }
+    assert_eq!(image.info().unwrap().width, 32);
+    let unlimited = OperationBudget::default();
+    pipeline
+        .execute_with_budget(&mut image, &unlimited)
+        .unwrap();
+    assert_eq!(image.info().unwrap().width, 64);
+
+    let dir = std::env::temp_dir();
+    let src = dir.join(format!("image_sl_budget_{}.tiff", std::process::id()));
+    let dst = dir.join(format!("image_sl_budget_{}_out.tiff", std::process::id()));
+    let mut file = std::fs::File::create(&src).unwrap();
+    let mut encoder = tiff::encoder::TiffEncoder::new(&mut file).unwrap();
+    let pixels = vec![128; 32 * 32 * 3];
+    encoder
+        .write_image::<tiff::encoder::colortype::RGB8>(32, 32, &pixels)
+        .unwrap();
+    match pipeline.execute_tiled_with_budget(&src, &dst, &budget, |_| true) {
+        Err(e) => assert_eq!(e.code(), Some(ImageError::BudgetExceeded)),
+        Ok(_) => panic!("tiled pipeline is executed beyond budget"),
+    }
+    assert!(!dst.exists());
+    pipeline
+        .execute_tiled_with_budget(&src, &dst, &unlimited, |_| true)
+        .unwrap();
+    assert!(dst.exists());
+    std::fs::remove_file(&src).unwrap();
+    std::fs::remove_file(&dst).unwrap();
+}
+
+#[test]
+fn callbacks_returning_wrong_length_are_rejected() {
+    unsafe extern "C" fn read(_: *mut c_void, _: *mut u8, len: usize) -> isize {
+        len as isize + 1
+    }
+    unsafe extern "C" fn write(_: *mut c_void, _: *const u8, len: usize) -> isize {
+        len as isize + 1
+    }
+
+    let f = table().functions;
+    unsafe {
+        let mut handle = bindings::ImageHandle::new_null();
+        let err = (f.open_image_with_reader)(read, None, std::ptr::null_mut(), &mut handle);
+        assert_eq!(err, ImageError::Io);
+        assert_eq!(handle.address(), 0);
+
+        let color_type = ColorType::L8 as u32;
+        assert_eq!(
+            (f.new)(1, 1, color_type, 0, &mut handle),
+            ImageError::NoError
+        );
+        let png = ImageFormat::Png as u32;
+        let err =
+            (f.save_image_with_writer)(write, std::ptr::null_mut(), handle, png, std::ptr::null());
+        assert_eq!(err, ImageError::Io);
+        (f.destroy_image)(handle);
+    }
+}
+
+#[test]
+fn layer_props_of_newer_host_are_filled() {
+    /// `LayerProps` with field added by newer version of host.
+    #[repr(C)]
+    struct NewerProps {
+        props: bindings::LayerProps,
+        extra: u64,
+    }
+
+    let f = table().functions;
+    unsafe {
+        let mut document = bindings::DocumentHandle::new_null();
+        assert_eq!((f.doc_new)(2, 2, &mut document), ImageError::NoError);
+        let mut image = bindings::ImageHandle::new_null();
+        let color_type = ColorType::Rgba8 as u32;
+        assert_eq!(
+            (f.new)(2, 2, color_type, 0, &mut image),
+            ImageError::NoError
+        );
+        let props = bindings::LayerProps {
+            x: 3,
+            opacity: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            (f.doc_add_layer)(document, image, &props),
+            ImageError::NoError
+        );
+
+        let mut newer = NewerProps {
+            props: bindings::LayerProps {
+                size: std::mem::size_of::<NewerProps>(),
+                ..Default::default()
+            },
+            extra: 7,
+        };
+        let err = (f.doc_get_layer)(document, 0, &mut newer.props);
+        assert_eq!(err, ImageError::NoError);
+        assert_eq!(newer.props.size, std::mem::size_of::<NewerProps>());
+        assert_eq!((newer.props.x, newer.props.opacity), (3, 0.5));
+        assert_eq!(newer.extra, 7);
+
+        let mut older = bindings::LayerProps {
+            size: 8,
+            ..Default::default()
+        };
+        let err = (f.doc_get_layer)(document, 0, &mut older);
+        assert_eq!(err, ImageError::Parameter);
+        (f.doc_destroy)(document);
+    }
+}
+
+#[test]
+fn card_spec_of_older_host_gets_defaults() {
+    let f = table().functions;
+    unsafe {
+        let mut background = bindings::ImageHandle::new_null();
+        let color_type = ColorType::Rgba8 as u32;
+        let err = (f.new)(8, 8, color_type, 0xffff_ffff, &mut background);
+        assert_eq!(err, ImageError::NoError);
+
+        // Spec of host built before colors of card were added is accepted.
+        let spec = bindings::CardSpec {
+            size: std::mem::offset_of!(bindings::CardSpec, text_color),
+            width: 4,
+            height: 2,
+            text_color: [0; 4],
+            scrim_color: [0; 4],
+            ..Default::default()
+        };
+        let mut card = bindings::ImageHandle::new_null();
+        let err = (f.compose_card)(background, &spec, &mut card);
+        assert_eq!(err, ImageError::NoError);
+        let mut info = bindings::ImageInfo {
+            size: std::mem::size_of::<bindings::ImageInfo>(),
+            ..std::mem::zeroed()
+        };
+        assert_eq!((f.info)(card, &mut info), ImageError::NoError);
+        assert_eq!((info.width, info.height), (4, 2));
+        (f.destroy_image)(card);
+
+        let spec = bindings::CardSpec {
+            size: std::mem::size_of::<bindings::CardSpec>() + 8,
+            ..Default::default()
+        };
+        let err = (f.compose_card)(background, &spec, &mut card);
+        assert_eq!(err, ImageError::Parameter);
+        (f.destroy_image)(background);
+    }
+}
+
+#[test]
+fn raw_values_convert_to_enums() {
+    assert!(matches!(ImageFormat::try_from(11), Ok(ImageFormat::Dds)));
+    assert_eq!(
+        ColorType::try_from(ColorType::Bgra8 as u32),
+        Ok(ColorType::Bgra8)
+    );
+    assert_eq!(JobStatus::try_from(1000), Err(UnknownValue(1000)));
+    assert_eq!(ColorType::from_raw(1000), None);
+
+    let factory = factory();
+    match factory.open_image_from_memory(b"\x89PNG\r\n\x1a\n", &OpenOptions::default()) {
+        Err(Error::Image {
+            details: Some(details),
+            ..
+        }) => {
+            assert_eq!(details.error_code(), Some(ImageError::Decoding));
+            assert!(matches!(details.image_format(), Some(ImageFormat::Png)));
+        }
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("truncated PNG is opened"),
+    }
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    let factory = factory();
+    match factory.image_from_raw(&[0; 3], 2, 1, RawPixelFormat::Rgba8) {
+        Err(Error::InvalidArgument(reason)) => assert!(reason.contains("doesn't match")),
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("image is created from short data"),
+    }
+    match factory.open_image("image\0.png") {
+        Err(e @ Error::InvalidArgument(_)) => assert!(e.to_string().starts_with("invalid")),
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("path with null byte is opened"),
+    }
+}
+
+#[test]
+fn encoding_queries_buffer_size() {
+    let table = table();
+    let f = &table.functions;
+    let png = ImageFormat::Png as u32;
+    unsafe {
+        let mut handle = bindings::ImageHandle::new_null();
+        let color_type = ColorType::Rgb8 as u32;
+        assert_eq!(
+            (f.new)(4, 4, color_type, 0, &mut handle),
+            ImageError::NoError
+        );
+        let mut len = 0;
+        let err = (f.encode_image)(handle, png, std::ptr::null_mut(), &mut len);
+        assert_eq!(err, ImageError::NoError);
+        assert!(len > 0);
+        let mut buffer = vec![0; len - 1];
+        let mut short = buffer.len();
+        let err = (f.encode_image)(handle, png, buffer.as_mut_ptr(), &mut short);
+        assert_eq!(err, ImageError::BufferTooSmall);
+        assert_eq!(short, len);
+        buffer.push(0);
+        let err = (f.encode_image)(handle, png, buffer.as_mut_ptr(), &mut len);
+        assert_eq!(err, ImageError::NoError);
+        assert_eq!(len, buffer.len());
+        (f.destroy_image)(handle);
+
+        let opened = factory()
+            .open_image_from_memory(&buffer, &OpenOptions::default())
+            .unwrap();
+        assert_eq!(opened.info().unwrap().width, 4);
+    }
+}
+
+#[test]
+fn stream_is_transcoded_with_operations() {
+    let factory = factory();
+    let mut image = factory.new_image(4, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    image.set_pixel(0, 0, [255, 0, 0, 255]).unwrap();
+    let png = image.encode(ImageFormat::Png).unwrap();
+
+    let mut output = Vec::new();
+    let ops = [Operation::mirror(), Operation::resize(2, 1)];
+    factory.transcode(&png[..], &mut output, &ops).unwrap();
+    // Result is encoded in format of source.
+    assert_eq!(&output[..8], b"\x89PNG\r\n\x1a\n");
+    let transcoded = factory
+        .open_image_from_memory(&output, &OpenOptions::default())
+        .unwrap();
+    let info = transcoded.info().unwrap();
+    assert_eq!((info.width, info.height), (2, 1));
+    assert!(transcoded.pixel(1, 0).unwrap()[0] > transcoded.pixel(0, 0).unwrap()[0]);
+
+    let err = factory
+        .transcode(&b"not an image"[..], &mut Vec::new(), &[])
+        .unwrap_err();
+    // Format of stream isn't recognized.
+    assert_eq!(err.code(), Some(ImageError::Unsupported));
+}
+
+#[test]
+fn open_options_limit_decoding() {
+    let factory = factory();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/logo.jpg");
+    let jpeg = std::fs::read(path).unwrap();
+    let limits = [
+        OpenOptions {
+            max_width: 1000,
+            ..Default::default()
+        },
+        OpenOptions {
+            max_height: 1000,
+            ..Default::default()
+        },
+        OpenOptions {
+            max_memory: 1024,
+            ..Default::default()
+        },
+    ];
+    for options in &limits {
+        let opened = factory.open_image_with_options(path, options);
+        assert_eq!(error_code(opened), Some(ImageError::LimitExceeded));
+        let opened = factory.open_image_from_memory(&jpeg, options);
+        assert_eq!(error_code(opened), Some(ImageError::LimitExceeded));
+    }
+    let options = OpenOptions {
+        max_width: 1200,
+        max_height: 1200,
+        ..Default::default()
+    };
+    assert!(factory.open_image_with_options(path, &options).is_ok());
+
+    let jpeg_hint = OpenOptions::default().with_format_hint(ImageFormat::Jpeg);
+    assert!(factory.open_image_from_memory(&jpeg, &jpeg_hint).is_ok());
+    let png_hint = OpenOptions::default().with_format_hint(ImageFormat::Png);
+    let opened = factory.open_image_from_memory(&jpeg, &png_hint);
+    assert_eq!(error_code(opened), Some(ImageError::Decoding));
+}
+
+#[test]
+fn global_limits_apply_to_every_decoding() {
+    let factory = factory();
+    // Wider than images of other tests, which may decode meanwhile.
+    let wide = factory.new_image(5000, 1, ColorType::L8, [0; 4]).unwrap();
+    let png = wide.encode(ImageFormat::Png).unwrap();
+    factory.set_limits(4096, 0, 0);
+    let limited = factory.open_image_from_memory(&png, &OpenOptions::default());
+    factory.set_limits(0, 0, 0);
+    assert_eq!(error_code(limited), Some(ImageError::LimitExceeded));
+    assert!(factory
+        .open_image_from_memory(&png, &OpenOptions::default())
+        .is_ok());
+}
+
+#[test]
+fn exif_of_source_is_read() {
+    let factory = factory();
+    let image = factory.new_image(4, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    let jpeg = with_exif(&image.encode(ImageFormat::Jpeg).unwrap(), "Camera", 1);
+    let opened = factory
+        .open_image_from_memory(&jpeg, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(
+        opened.exif(exif_tag::MAKE).unwrap().as_deref(),
+        Some("Camera")
+    );
+    assert_eq!(
+        opened.exif(exif_tag::ORIENTATION).unwrap().as_deref(),
+        Some("1")
+    );
+    assert_eq!(opened.exif(exif_tag::MODEL).unwrap(), None);
+    assert_eq!(image.exif(exif_tag::MAKE).unwrap(), None);
+}
+
+#[test]
+fn exif_is_kept_on_save() {
+    let factory = factory();
+    let mut image = factory.new_image(4, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    image
+        .set_exif(exif_tag::MODEL, ExifType::Ascii, "Model X")
+        .unwrap();
+    image
+        .set_exif(exif_tag::ORIENTATION, ExifType::Short, "1")
+        .unwrap();
+    assert_eq!(
+        image.exif(exif_tag::MODEL).unwrap().as_deref(),
+        Some("Model X")
+    );
+
+    let reopen = |options: &SaveOptions, format| {
+        let mut encoded = Vec::new();
+        image.write_to(&mut encoded, format, options).unwrap();
+        factory
+            .open_image_from_memory(&encoded, &OpenOptions::default())
+            .unwrap()
+    };
+    let keep = SaveOptions {
+        keep_metadata: true,
+        ..Default::default()
+    };
+    for &format in &[ImageFormat::Jpeg, ImageFormat::Png] {
+        let kept = reopen(&keep, format);
+        assert_eq!(
+            kept.exif(exif_tag::MODEL).unwrap().as_deref(),
+            Some("Model X")
+        );
+        let dropped = reopen(&SaveOptions::default(), format);
+        assert_eq!(dropped.exif(exif_tag::MODEL).unwrap(), None);
+    }
+
+    let invalid = image.set_exif(exif_tag::ORIENTATION, ExifType::Short, "up");
+    assert_eq!(error_code(invalid), Some(ImageError::Parameter));
+}
+
+#[test]
+fn images_are_oriented_by_exif() {
+    let factory = factory();
+    // Left half is red and right half is blue.
+    let mut image = factory
+        .new_image(32, 16, ColorType::Rgb8, [0, 0, 255, 255])
+        .unwrap();
+    let red = DrawStyle::Fill([255, 0, 0, 255]);
+    image.draw_rect(0.0, 0.0, 16.0, 16.0, red).unwrap();
+    // Orientation 6 is stored rotated 90 degrees counterclockwise.
+    let jpeg = with_exif(&image.encode(ImageFormat::Jpeg).unwrap(), "Camera", 6);
+    let is_red = |pixel: [u8; 4]| pixel[0] > 200 && pixel[2] < 50;
+
+    let mut opened = factory
+        .open_image_from_memory(&jpeg, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(opened.info().unwrap().width, 32);
+    opened.auto_orient().unwrap();
+    let info = opened.info().unwrap();
+    assert_eq!((info.width, info.height), (16, 32));
+    assert!(is_red(opened.pixel(8, 4).unwrap()));
+    assert!(!is_red(opened.pixel(8, 28).unwrap()));
+
+    let options = OpenOptions {
+        auto_orient: true,
+        ..Default::default()
+    };
+    let oriented = factory.open_image_from_memory(&jpeg, &options).unwrap();
+    assert_eq!(oriented.info().unwrap().width, 16);
+    assert!(is_red(oriented.pixel(8, 4).unwrap()));
+    // Orientation is reset, so image isn't rotated again.
+    let mut again = oriented.clone();
+    again.auto_orient().unwrap();
+    assert_eq!(again.info().unwrap().width, 16);
+}
+
+#[test]
+fn icc_profile_is_preserved_on_save() {
+    let factory = factory();
+    let mut image = factory.new_image(4, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    assert_eq!(image.icc().unwrap(), None);
+    let invalid: Vec<u8> = (0..=255).collect();
+    assert_eq!(
+        error_code(image.set_icc(&invalid)),
+        Some(ImageError::Parameter)
+    );
+    // Header of ICC profile is its size and `acsp` signature.
+    let mut profile = vec![0; 256];
+    profile[..4].copy_from_slice(&256u32.to_be_bytes());
+    profile[36..40].copy_from_slice(b"acsp");
+    image.set_icc(&profile).unwrap();
+    assert_eq!(image.icc().unwrap().as_deref(), Some(&profile[..]));
+
+    for &format in &[ImageFormat::Jpeg, ImageFormat::Png] {
+        let encoded = image.encode(format).unwrap();
+        let opened = factory
+            .open_image_from_memory(&encoded, &OpenOptions::default())
+            .unwrap();
+        assert_eq!(opened.icc().unwrap().as_deref(), Some(&profile[..]));
+    }
+
+    image.set_icc(&[]).unwrap();
+    assert_eq!(image.icc().unwrap(), None);
+    let encoded = image.encode(ImageFormat::Png).unwrap();
+    let opened = factory
+        .open_image_from_memory(&encoded, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(opened.icc().unwrap(), None);
+}
+
+#[test]
+fn conversion_to_srgb_follows_icc_capability() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(2, 2, ColorType::Rgb8, [10, 20, 30, 255])
+        .unwrap();
+    // Image without profile is treated as sRGB.
+    image.convert_to_srgb().unwrap();
+    assert_eq!(image.pixel(0, 0).unwrap(), [10, 20, 30, 255]);
+
+    // Header of profile without any tags.
+    let mut profile = vec![0; 128];
+    profile[..4].copy_from_slice(&128u32.to_be_bytes());
+    profile[36..40].copy_from_slice(b"acsp");
+    image.set_icc(&profile).unwrap();
+    let converted = image.convert_to_srgb();
+    if factory.supports(Op::ConvertToSrgb) {
+        assert!(converted.is_err());
+    } else {
+        assert_eq!(error_code(converted), Some(ImageError::Unsupported));
+    }
+    assert_eq!(image.icc().unwrap().as_deref(), Some(&profile[..]));
+    assert_eq!(image.pixel(0, 0).unwrap(), [10, 20, 30, 255]);
+}
+
+#[test]
+fn dpi_is_written_to_jpeg_and_png() {
+    let factory = factory();
+    let mut image = factory.new_image(4, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    assert_eq!(image.dpi().unwrap(), None);
+    image.set_dpi(300.0, 150.0).unwrap();
+    assert_eq!(image.dpi().unwrap(), Some((300.0, 150.0)));
+
+    for &format in &[ImageFormat::Jpeg, ImageFormat::Png] {
+        let encoded = image.encode(format).unwrap();
+        let opened = factory
+            .open_image_from_memory(&encoded, &OpenOptions::default())
+            .unwrap();
+        let (x, y) = opened.dpi().unwrap().unwrap();
+        // PNG stores pixels per meter.
+        assert!(
+            (x - 300.0).abs() < 0.1 && (y - 150.0).abs() < 0.1,
+            "{} {}",
+            x,
+            y
+        );
+    }
+
+    assert_eq!(
+        error_code(image.set_dpi(0.0, 72.0)),
+        Some(ImageError::Parameter)
+    );
+}
+
+#[test]
+fn png_text_is_read_and_written() {
+    let factory = factory();
+    let mut image = factory.new_image(4, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    image.set_png_text("Title", Some("Logo")).unwrap();
+    image.set_png_text("Comment", Some("Zürich")).unwrap();
+    assert_eq!(image.png_text("Title").unwrap().as_deref(), Some("Logo"));
+
+    let mut png = Vec::new();
+    let keep = SaveOptions {
+        keep_metadata: true,
+        ..Default::default()
+    };
+    image.write_to(&mut png, ImageFormat::Png, &keep).unwrap();
+    let mut opened = factory
+        .open_image_from_memory(&png, &OpenOptions::default())
+        .unwrap();
+    let mut keys = opened.png_text_keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, ["Comment", "Title"]);
+    assert_eq!(
+        opened.png_text("Comment").unwrap().as_deref(),
+        Some("Zürich")
+    );
+
+    opened.set_png_text("Title", None).unwrap();
+    assert_eq!(opened.png_text("Title").unwrap(), None);
+    assert_eq!(opened.png_text_keys().unwrap(), ["Comment"]);
+}
+
+#[test]
+fn xmp_and_iptc_of_source_are_read() {
+    let factory = factory();
+    let image = factory.new_image(4, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    let jpeg = image.encode(ImageFormat::Jpeg).unwrap();
+    let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF/></x:xmpmeta>"#;
+    let xmp = [&b"http://ns.adobe.com/xap/1.0/\0"[..], packet.as_bytes()].concat();
+    let jpeg = with_segment(&jpeg, 0xe1, &xmp);
+
+    // Two keywords and headline in IPTC resource of Photoshop segment.
+    let mut iptc = Vec::new();
+    for &(dataset, value) in &[(25u8, "sea"), (25, "sky"), (105, "Coast")] {
+        iptc.extend_from_slice(&[0x1c, 2, dataset, 0, value.len() as u8]);
+        iptc.extend_from_slice(value.as_bytes());
+    }
+    if iptc.len() % 2 == 1 {
+        iptc.push(0);
+    }
+    let mut resource = b"Photoshop 3.0\08BIM\x04\x04\0\0".to_vec();
+    resource.extend_from_slice(&(iptc.len() as u32).to_be_bytes());
+    resource.extend_from_slice(&iptc);
+    let jpeg = with_segment(&jpeg, 0xed, &resource);
+
+    let opened = factory
+        .open_image_from_memory(&jpeg, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(opened.xmp().unwrap().as_deref(), Some(packet));
+    assert_eq!(opened.iptc(iptc_dataset::KEYWORDS).unwrap(), ["sea", "sky"]);
+    assert_eq!(opened.iptc(iptc_dataset::HEADLINE).unwrap(), ["Coast"]);
+    assert!(opened.iptc(iptc_dataset::CAPTION).unwrap().is_empty());
+    assert_eq!(image.xmp().unwrap(), None);
+}
+
+#[test]
+fn metadata_is_stripped() {
+    let factory = factory();
+    let image = factory.new_image(4, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    let jpeg = with_exif(&image.encode(ImageFormat::Jpeg).unwrap(), "Camera", 6);
+    let mut image = factory
+        .open_image_from_memory(&jpeg, &OpenOptions::default())
+        .unwrap();
+    image.set_dpi(300.0, 300.0).unwrap();
+    image.set_png_text("Author", Some("Someone")).unwrap();
+
+    image.strip_metadata().unwrap();
+    // Image is oriented before its orientation is dropped.
+    assert_eq!(image.info().unwrap().width, 2);
+    assert_eq!(image.exif(exif_tag::MAKE).unwrap(), None);
+    assert_eq!(image.dpi().unwrap(), None);
+    assert!(image.png_text_keys().unwrap().is_empty());
+
+    let mut png = Vec::new();
+    let keep = SaveOptions {
+        keep_metadata: true,
+        ..Default::default()
+    };
+    image.write_to(&mut png, ImageFormat::Png, &keep).unwrap();
+    let opened = factory
+        .open_image_from_memory(&png, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(opened.exif(exif_tag::ORIENTATION).unwrap(), None);
+    assert_eq!(opened.dpi().unwrap(), None);
+}
+
+#[test]
+fn exif_thumbnail_is_extracted() {
+    let factory = factory();
+    let image = factory.new_image(64, 32, ColorType::Rgb8, [0; 4]).unwrap();
+    let thumbnail = factory.new_image(8, 4, ColorType::Rgb8, [0; 4]).unwrap();
+    let thumbnail = thumbnail.encode(ImageFormat::Jpeg).unwrap();
+    // Empty IFD of primary image followed by IFD of thumbnail pointing to its JPEG data.
+    let mut tiff = b"II*\0\x08\0\0\0\0\0\x0e\0\0\0\x02\0".to_vec();
+    tiff.extend_from_slice(&[0x01, 0x02, 4, 0, 1, 0, 0, 0, 44, 0, 0, 0]);
+    tiff.extend_from_slice(&[0x02, 0x02, 4, 0, 1, 0, 0, 0]);
+    tiff.extend_from_slice(&(thumbnail.len() as u32).to_le_bytes());
+    tiff.extend_from_slice(&[0; 4]);
+    tiff.extend_from_slice(&thumbnail);
+    let exif = [&b"Exif\0\0"[..], &tiff].concat();
+    let jpeg = with_segment(&image.encode(ImageFormat::Jpeg).unwrap(), 0xe1, &exif);
+
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("image_sl_thumbnail_{}.jpg", std::process::id()));
+    std::fs::write(&path, jpeg).unwrap();
+    let extracted = factory.open_exif_thumbnail(&path).unwrap().unwrap();
+    let info = extracted.info().unwrap();
+    assert_eq!((info.width, info.height), (8, 4));
+    std::fs::remove_file(&path).unwrap();
+
+    let logo = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/logo.jpg");
+    assert!(factory.open_exif_thumbnail(logo).unwrap().is_none());
+}
+
+#[test]
+fn ora_document_is_saved_and_opened_back() {
+    let factory = factory();
+    let mut document = factory.new_document(4, 4).unwrap();
+    let base = factory
+        .new_image(4, 4, ColorType::Rgba8, [255, 0, 0, 255])
+        .unwrap();
+    document.add_layer(base, &LayerProps::default()).unwrap();
+    let top = factory
+        .new_image(2, 2, ColorType::Rgba8, [0, 0, 255, 255])
+        .unwrap();
+    let props = LayerProps {
+        x: 2,
+        y: 1,
+        opacity: 0.5,
+        blend_mode: BlendMode::Multiply as u32,
+        ..Default::default()
+    };
+    document.add_layer(top, &props).unwrap();
+    let hidden = factory
+        .new_image(4, 4, ColorType::Rgba8, [0, 255, 0, 255])
+        .unwrap();
+    let invisible = LayerProps {
+        visible: false,
+        ..Default::default()
+    };
+    document.add_layer(hidden, &invisible).unwrap();
+
+    let path = std::env::temp_dir().join(format!("image_sl_{}.ora", std::process::id()));
+    document.save_ora(&path).unwrap();
+    let opened = factory.open_ora(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(opened.layer_count().unwrap(), 3);
+    let layer = opened.layer(1).unwrap().unwrap();
+    assert_eq!((layer.x, layer.y), (2, 1));
+    assert!((layer.opacity - 0.5).abs() < 1e-3);
+    assert_eq!(layer.blend_mode, BlendMode::Multiply as u32);
+    assert!(!opened.layer(2).unwrap().unwrap().visible);
+    let (flat, opened_flat) = (document.flatten().unwrap(), opened.flatten().unwrap());
+    assert_eq!(flat.differing_pixels(&opened_flat, 0).unwrap(), 0);
+    assert_eq!(opened_flat.pixel(0, 0).unwrap(), [255, 0, 0, 255]);
+    assert_ne!(opened_flat.pixel(2, 1).unwrap(), [255, 0, 0, 255]);
+
+    std::fs::write(&path, b"PK\x03\x04 not an archive").unwrap();
+    let broken = factory.open_ora(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(error_code(broken), Some(ImageError::Decoding));
+}
+
+/// Returns PSD file of 3 x 2 RGB composite image without layers, whose planes are stored raw or,
+/// if `rle` is set, packed.
+fn psd(rle: bool) -> Vec<u8> {
+    let mut file = b"8BPS\0\x01\0\0\0\0\0\0\0\x03".to_vec();
+    file.extend_from_slice(&2u32.to_be_bytes());
+    file.extend_from_slice(&3u32.to_be_bytes());
+    // 8 bits per channel RGB, empty color mode, resource and layer sections.
+    file.extend_from_slice(&[0, 8, 0, 3]);
+    file.extend_from_slice(&[0; 12]);
+    let planes: [[u8; 6]; 3] = [[255, 0, 0, 255, 0, 0], [0, 255, 0, 0, 255, 0], [0; 6]];
+    if rle {
+        file.extend_from_slice(&[0, 1]);
+        // Every row is one literal run of 3 bytes.
+        file.extend_from_slice(&[0, 4].repeat(6));
+        for row in planes.iter().flat_map(|plane| plane.chunks(3)) {
+            file.push(2);
+            file.extend_from_slice(row);
+        }
+    } else {
+        file.extend_from_slice(&[0, 0]);
+        file.extend(planes.iter().flatten());
+    }
+    file
+}
+
+#[test]
+fn psd_composite_is_read() {
+    let factory = factory();
+    for &rle in &[false, true] {
+        let file = psd(rle);
+        let image = factory
+            .open_image_from_memory(&file, &OpenOptions::default())
+            .unwrap();
+        let info = image.info().unwrap();
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(image.pixel(0, 0).unwrap(), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(1, 0).unwrap(), [0, 255, 0, 255]);
+        assert_eq!(image.pixel(2, 1).unwrap(), [0, 0, 0, 255]);
+
+        let options = OpenOptions {
+            max_width: 2,
+            ..Default::default()
+        };
+        let limited = factory.open_image_from_memory(&file, &options);
+        assert_eq!(error_code(limited), Some(ImageError::LimitExceeded));
+        let truncated = factory.open_image_from_memory(&file[..file.len() - 2], &options);
+        assert!(truncated.is_err());
+    }
+
+    let path = std::env::temp_dir().join(format!("image_sl_{}.psd", std::process::id()));
+    std::fs::write(&path, psd(true)).unwrap();
+    let document = factory.open_psd(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // File without layers gives document of its composite.
+    assert_eq!(document.layer_count().unwrap(), 1);
+    let flat = document.flatten().unwrap();
+    assert_eq!(flat.pixel(1, 1).unwrap(), [0, 255, 0, 255]);
+}
+
+#[test]
+fn thumbnail_is_embedded_into_jpeg() {
+    let factory = factory();
+    let image = factory
+        .new_image(640, 320, ColorType::Rgb8, [0, 128, 255, 255])
+        .unwrap();
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("image_sl_embedded_{}.jpg", std::process::id()));
+    let options = SaveOptions {
+        embed_thumbnail: true,
+        ..Default::default()
+    };
+    let mut jpeg = Vec::new();
+    image
+        .write_to(&mut jpeg, ImageFormat::Jpeg, &options)
+        .unwrap();
+    std::fs::write(&path, &jpeg).unwrap();
+    let thumbnail = factory.open_exif_thumbnail(&path).unwrap().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let info = thumbnail.info().unwrap();
+    // Thumbnail keeps aspect ratio of image.
+    assert!(
+        info.width < 640 && info.width == info.height * 2,
+        "{:?}",
+        info
+    );
+
+    let opened = factory
+        .open_image_from_memory(&jpeg, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(opened.info().unwrap().width, 640);
+}
+
+#[test]
+fn codec_plugins_encode_and_decode() {
+    let factory = factory();
+    // Format of 8 byte header with size followed by RGBA pixels.
+    factory
+        .register_encoder("rawx", |pixels, width, height, out| {
+            out.write_all(b"RAWX")?;
+            out.write_all(&(width as u16).to_le_bytes())?;
+            out.write_all(&(height as u16).to_le_bytes())?;
+            Ok(out.write_all(pixels)?)
+        })
+        .unwrap();
+    let probe = |data: &[u8]| data.starts_with(b"RAWX");
+    let decode = |data: &[u8]| {
+        let width = u16::from_le_bytes([data[4], data[5]]) as u32;
+        let height = u16::from_le_bytes([data[6], data[7]]) as u32;
+        if data.len() != 8 + (width * height * 4) as usize {
+            return Err("truncated RAWX file".into());
+        }
+        Ok((width, height, data[8..].to_vec()))
+    };
+    factory.register_decoder("rawx", probe, decode).unwrap();
+
+    let mut image = factory.new_image(3, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    image.set_pixel(2, 1, [10, 20, 30, 255]).unwrap();
+    let path = std::env::temp_dir().join(format!("image_sl_{}.rawx", std::process::id()));
+    image.save(&path).unwrap();
+    let file = std::fs::read(&path).unwrap();
+    assert_eq!(&file[..8], b"RAWX\x03\0\x02\0");
+    let opened = factory.open_image(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(opened.pixel(2, 1).unwrap(), [10, 20, 30, 255]);
+
+    // Streams are recognized by probe of plugin.
+    let opened = factory
+        .open_image_from_memory(&file, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(opened.info().unwrap().width, 3);
+    let truncated = factory.open_image_from_memory(&file[..12], &OpenOptions::default());
+    assert_eq!(error_code(truncated), Some(ImageError::Decoding));
+}
+
+#[test]
+fn token_cancels_running_jobs() {
+    let factory = factory();
+    let image = factory
+        .new_image(1500, 1500, ColorType::Rgba8, [0; 4])
+        .unwrap();
+    let token = factory.cancel_token().unwrap();
+    let jobs: Vec<_> = (0..2)
+        .map(|_| {
+            image
+                .submit_with_token("blur", r#"{"sigma": 30}"#, &token)
+                .unwrap()
+        })
+        .collect();
+    token.cancel();
+    for job in &jobs {
+        assert_eq!(error_code(job.result()), Some(ImageError::Cancelled));
+        assert_eq!(job.status(), JobStatus::Failed);
+    }
+
+    let token = factory.cancel_token().unwrap();
+    let small = factory.new_image(8, 8, ColorType::Rgba8, [0; 4]).unwrap();
+    let job = small
+        .submit_with_token("blur", r#"{"sigma": 1}"#, &token)
+        .unwrap();
+    assert!(job.result().unwrap().is_some());
+    assert_eq!(job.status(), JobStatus::Finished);
+}
+
+#[test]
+fn tiled_pipeline_matches_in_memory_one() {
+    let factory = factory();
+    let dir = std::env::temp_dir();
+    let src = dir.join(format!("image_sl_tiled_{}.tiff", std::process::id()));
+    let dst = dir.join(format!("image_sl_tiled_{}_out.tiff", std::process::id()));
+    let (width, height) = (40, 300);
+    let pixels: Vec<u8> = (0..width * height * 3)
+        .map(|i| (i * 7 % 251) as u8)
+        .collect();
+    let mut file = std::fs::File::create(&src).unwrap();
+    tiff::encoder::TiffEncoder::new(&mut file)
+        .unwrap()
+        .write_image::<tiff::encoder::colortype::RGB8>(width, height, &pixels)
+        .unwrap();
+
+    let mut pipeline = factory.pipeline().unwrap();
+    let rect = Rect {
+        x: 3,
+        y: 10,
+        width: 30,
+        height: 250,
+    };
+    pipeline.crop(rect).unwrap().resize(20, 120).unwrap();
+    pipeline.mirror().unwrap().blur(1.5).unwrap();
+    let mut done = Vec::new();
+    pipeline
+        .execute_tiled(&src, &dst, |percent| {
+            done.push(percent);
+            true
+        })
+        .unwrap();
+    assert!(done.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(done.last().copied(), Some(100.0));
+
+    let tiled = factory.open_image(&dst).unwrap();
+    let mut image = factory.open_image(&src).unwrap();
+    pipeline.execute(&mut image).unwrap();
+    assert_eq!(tiled.info().unwrap().width, 20);
+    assert_eq!(tiled.differing_pixels(&image, 0).unwrap(), 0);
+
+    // Output of pipeline cancelled halfway is removed.
+    let cancelled = pipeline.execute_tiled(&src, &dst, |percent| percent < 50.0);
+    assert_eq!(error_code(cancelled), Some(ImageError::Cancelled));
+    assert!(!dst.exists());
+    let overwriting = pipeline.execute_tiled(&src, &src, |_| true);
+    assert_eq!(error_code(overwriting), Some(ImageError::Parameter));
+    assert!(src.exists());
+    std::fs::remove_file(&src).unwrap();
+}
+
+#[test]
+fn cow_clone_is_copied_on_write() {
+    let mut image = factory()
+        .new_image(2, 2, ColorType::Rgba8, [1, 2, 3, 255])
+        .unwrap();
+    let mut clone = image.clone_cow().unwrap();
+    assert_eq!(clone.pixel(1, 1).unwrap(), [1, 2, 3, 255]);
+
+    // Change of either image doesn't show in another one.
+    clone.set_pixel(0, 0, [9, 9, 9, 255]).unwrap();
+    assert_eq!(image.pixel(0, 0).unwrap(), [1, 2, 3, 255]);
+    image.set_pixel(1, 1, [7, 7, 7, 255]).unwrap();
+    assert_eq!(clone.pixel(1, 1).unwrap(), [1, 2, 3, 255]);
+
+    // Destroyed clone leaves pixels shared with image alone.
+    let shared = image.clone_cow().unwrap();
+    drop(shared);
+    assert_eq!(image.pixel(1, 1).unwrap(), [7, 7, 7, 255]);
+}
+
+#[test]
+fn frozen_image_rejects_changes() {
+    let f = table().functions;
+    unsafe {
+        let mut handle = bindings::ImageHandle::new_null();
+        let color_type = ColorType::Rgba8 as u32;
+        assert_eq!(
+            (f.new)(2, 2, color_type, 0x0102_03ff, &mut handle),
+            ImageError::NoError
+        );
+        let image = handle;
+        assert_eq!((f.freeze)(image, &mut handle), ImageError::NoError);
+        let frozen = handle;
+        let err = (f.set_pixel)(frozen, 0, 0, 0x0909_09ff);
+        assert_eq!(err, ImageError::InvalidHandle);
+        let mut pixel = 0;
+        assert_eq!((f.get_pixel)(frozen, 0, 0, &mut pixel), ImageError::NoError);
+        assert_eq!(pixel, 0x0102_03ff);
+
+        // Image frozen clone was made of may still be changed, frozen one keeps its pixels.
+        assert_eq!((f.set_pixel)(image, 0, 0, 0x0909_09ff), ImageError::NoError);
+        assert_eq!((f.get_pixel)(frozen, 0, 0, &mut pixel), ImageError::NoError);
+        assert_eq!(pixel, 0x0102_03ff);
+        (f.destroy_image)(image);
+        (f.destroy_image)(frozen);
+    }
+
+    let image = factory()
+        .new_image(2, 2, ColorType::Rgba8, [1, 2, 3, 255])
+        .unwrap();
+    let frozen = image.freeze().unwrap();
+    let mut thawed = frozen.thaw().unwrap();
+    thawed.set_pixel(1, 1, [9, 9, 9, 255]).unwrap();
+    assert_eq!(frozen.pixel(1, 1).unwrap(), [1, 2, 3, 255]);
+    // Frozen image may be destroyed by another thread.
+    std::thread::spawn(move || drop(frozen)).join().unwrap();
+    assert_eq!(thawed.pixel(1, 1).unwrap(), [9, 9, 9, 255]);
+}
+
+#[test]
+fn locked_pixels_are_written_directly() {
+    let mut image = factory()
+        .new_image(3, 2, ColorType::Rgb8, [1, 2, 3, 255])
+        .unwrap();
+    {
+        let mut lock = image.lock_pixels().unwrap();
+        assert_eq!((lock.width(), lock.height()), (3, 2));
+        assert_eq!(lock.stride(), 9);
+        assert_eq!(lock.color_type(), Some(ColorType::Rgb8));
+        assert_eq!(&lock.bytes()[..3], [1, 2, 3]);
+        let stride = lock.stride();
+        lock.bytes_mut()[stride + 3..stride + 6].copy_from_slice(&[7, 8, 9]);
+    }
+    assert_eq!(image.pixel(1, 1).unwrap(), [7, 8, 9, 255]);
+    image.set_pixel(0, 0, [4, 5, 6, 255]).unwrap();
+
+    let f = table().functions;
+    unsafe {
+        let mut handle = bindings::ImageHandle::new_null();
+        let color_type = ColorType::L8 as u32;
+        assert_eq!(
+            (f.new)(1, 1, color_type, 0, &mut handle),
+            ImageError::NoError
+        );
+        let mut lock = std::mem::MaybeUninit::uninit();
+        assert_eq!(
+            (f.lock_pixels)(handle, lock.as_mut_ptr()),
+            ImageError::NoError
+        );
+        let err = (f.set_pixel)(handle, 0, 0, 0xffff_ffff);
+        assert_eq!(err, ImageError::InvalidHandle);
+        assert_eq!((f.unlock_pixels)(handle), ImageError::NoError);
+        assert_eq!(
+            (f.set_pixel)(handle, 0, 0, 0xffff_ffff),
+            ImageError::NoError
+        );
+        (f.destroy_image)(handle);
+    }
+}
+
+#[test]
+fn image_is_converted_to_color_type() {
+    let image = factory()
+        .new_image(2, 1, ColorType::Rgba8, [100, 100, 100, 51])
+        .unwrap();
+    let gray = image.convert(ColorType::L8).unwrap();
+    assert_eq!(gray.info().unwrap().color_type, Some(ColorType::L8));
+    assert_eq!(gray.pixel(1, 0).unwrap(), [100, 100, 100, 255]);
+
+    // 8-bit channels are scaled to full range of 16 bits.
+    let mut wide = image.convert(ColorType::Rgba16).unwrap();
+    assert_eq!(wide.info().unwrap().bit_depth, 16);
+    let lock = wide.lock_pixels().unwrap();
+    let alpha = u16::from_ne_bytes([lock.bytes()[6], lock.bytes()[7]]);
+    assert_eq!(alpha, 51 * 257);
+    drop(lock);
+    let narrow = wide.convert(ColorType::Rgb8).unwrap();
+    assert_eq!(narrow.pixel(0, 0).unwrap(), [100, 100, 100, 255]);
+    assert_eq!(image.pixel(0, 0).unwrap(), [100, 100, 100, 51]);
+
+    let float = image.convert(ColorType::Rgba32F);
+    assert_eq!(error_code(float), Some(ImageError::Unsupported));
+}
+
+#[test]
+fn pixels_are_locked_as_aligned_bgr() {
+    let mut image = factory()
+        .new_image(3, 2, ColorType::Rgb8, [1, 2, 3, 255])
+        .unwrap();
+    let misaligned = image.lock_pixels_as(ColorType::Bgr8, 3);
+    assert_eq!(error_code(misaligned), Some(ImageError::Parameter));
+    {
+        let mut lock = image.lock_pixels_as(ColorType::Bgr8, 4).unwrap();
+        assert_eq!(lock.color_type(), Some(ColorType::Bgr8));
+        assert_eq!(lock.stride(), 12);
+        assert_eq!(lock.bytes().len(), 24);
+        assert_eq!(&lock.bytes()[..3], [3, 2, 1]);
+        lock.bytes_mut()[12..15].copy_from_slice(&[9, 8, 7]);
+    }
+    assert_eq!(image.info().unwrap().color_type, Some(ColorType::Bgr8));
+    assert_eq!(image.pixel(0, 1).unwrap(), [7, 8, 9, 255]);
+    assert_eq!(image.pixel(2, 1).unwrap(), [1, 2, 3, 255]);
+
+    let lock = image.lock_pixels_as(ColorType::Bgra8, 0).unwrap();
+    assert_eq!(lock.stride(), 12);
+    assert_eq!(&lock.bytes()[..4], [3, 2, 1, 255]);
+}
+
+#[test]
+fn pixels_are_read_into_buffer() {
+    let mut image = factory()
+        .new_image(2, 2, ColorType::Rgb8, [0, 51, 255, 255])
+        .unwrap();
+    image.set_pixel(1, 1, [10, 20, 30, 255]).unwrap();
+
+    // Rows are written `stride` bytes apart, padding is left alone.
+    let mut buffer = [0xaa; 20];
+    image
+        .read_pixels(ColorType::Rgba8, &mut buffer, 10)
+        .unwrap();
+    assert_eq!(&buffer[..8], [0, 51, 255, 255, 0, 51, 255, 255]);
+    assert_eq!(&buffer[8..10], [0xaa, 0xaa]);
+    assert_eq!(&buffer[14..18], [10, 20, 30, 255]);
+
+    let mut floats = [0; 64];
+    image
+        .read_pixels(ColorType::Rgba32F, &mut floats, 0)
+        .unwrap();
+    let green = f32::from_ne_bytes([floats[4], floats[5], floats[6], floats[7]]);
+    assert!((green - 0.2).abs() < 1e-6);
+
+    let small = image.read_pixels(ColorType::Rgba8, &mut buffer[..15], 0);
+    assert_eq!(error_code(small), Some(ImageError::BufferTooSmall));
+    let narrow = image.read_pixels(ColorType::Rgba8, &mut buffer, 7);
+    assert_eq!(error_code(narrow), Some(ImageError::Parameter));
+}
+
+#[test]
+fn pixels_are_mapped_by_callback() {
+    for &parallel in &[false, true] {
+        let mut image = factory()
+            .new_image(5, 7, ColorType::Rgba8, [10, 20, 30, 255])
+            .unwrap();
+        image
+            .map_pixels(parallel, |x, y, [r, g, b, a]| {
+                [x as u8, y as u8, r + g + b, a]
+            })
+            .unwrap();
+        assert_eq!(image.pixel(0, 0).unwrap(), [0, 0, 60, 255]);
+        assert_eq!(image.pixel(4, 6).unwrap(), [4, 6, 60, 255]);
+    }
+
+    // Colors are converted back to color type of image.
+    let mut image = factory().new_image(2, 2, ColorType::L8, [0; 4]).unwrap();
+    image
+        .map_pixels(false, |_, _, _| [90, 90, 90, 255])
+        .unwrap();
+    assert_eq!(image.info().unwrap().color_type, Some(ColorType::L8));
+    assert_eq!(image.pixel(1, 1).unwrap(), [90, 90, 90, 255]);
+}
+
+#[test]
+fn rows_are_passed_to_callback() {
+    let mut image = factory()
+        .new_image(2, 3, ColorType::Rgb8, [1, 2, 3, 255])
+        .unwrap();
+    image.set_pixel(1, 2, [7, 8, 9, 255]).unwrap();
+    let mut rows = Vec::new();
+    image
+        .for_each_row(|y, row| {
+            rows.push((y, row.to_vec()));
+            true
+        })
+        .unwrap();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0], (0, vec![1, 2, 3, 1, 2, 3]));
+    assert_eq!(rows[2], (2, vec![1, 2, 3, 7, 8, 9]));
+
+    let mut count = 0;
+    let stopped = image.for_each_row(|_, _| {
+        count += 1;
+        false
+    });
+    assert_eq!(error_code(stopped), Some(ImageError::Cancelled));
+    assert_eq!(count, 1);
+}
+
+/// Table returned by `functions()` of the first release.
+#[repr(C)]
+struct LegacyFunctions {
+    size: usize,
+    open_image: bindings::OpenImageFn,
+    save_image: bindings::SaveImageFn,
+    destroy_image: bindings::DestroyImageFn,
+    blur_image: bindings::BlurImageFn,
+    mirror_image: bindings::MirrorImageFn,
+}
+
+extern "C" {
+    fn functions() -> LegacyFunctions;
+}
+
+#[test]
+fn legacy_functions_keep_first_layout() {
+    let f = unsafe { functions() };
+    assert_eq!(f.size, std::mem::size_of::<LegacyFunctions>());
+    let path = std::ffi::CString::new("../data/logo.jpg").unwrap();
+    unsafe {
+        let mut handle = bindings::ImageHandle::new_null();
+        let err = (f.open_image)(bindings::RawPath(path.as_ptr()), &mut handle);
+        assert_eq!(err, ImageError::NoError);
+        (f.mirror_image)(handle);
+        let blurred = (f.blur_image)(handle, 1.0);
+        assert_ne!(blurred.address(), 0);
+        (f.destroy_image)(blurred);
+        (f.destroy_image)(handle);
+    }
+}
+
+#[test]
+fn stream_is_transcoded_to_format() {
+    let factory = factory();
+    let image = factory
+        .new_image(4, 2, ColorType::Rgb8, [9, 8, 7, 255])
+        .unwrap();
+    let png = image.encode(ImageFormat::Png).unwrap();
+
+    let mut output = Vec::new();
+    let ops = [Operation::resize(2, 1)];
+    let len = png.len() as u64;
+    factory
+        .transcode_as(&png[..], &mut output, &ops, ImageFormat::Bmp, len)
+        .unwrap();
+    assert_eq!(&output[..2], b"BM");
+    let transcoded = factory
+        .open_image_from_memory(&output, &OpenOptions::default())
+        .unwrap();
+    assert_eq!(transcoded.pixel(1, 0).unwrap(), [9, 8, 7, 255]);
+
+    // Input is read only up to limit, so endless stream fails too.
+    let short = factory.transcode_as(&png[..], Vec::new(), &[], ImageFormat::Bmp, len - 1);
+    assert_eq!(error_code(short), Some(ImageError::LimitExceeded));
+    let endless = std::io::repeat(0);
+    let endless = factory.transcode_as(endless, Vec::new(), &[], ImageFormat::Png, 1 << 20);
+    assert_eq!(error_code(endless), Some(ImageError::LimitExceeded));
+}
+
+#[test]
+fn edits_are_undone_and_redone() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(2, 1, ColorType::Rgba8, [1, 2, 3, 255])
+        .unwrap();
+    image.set_pixel(1, 0, [4, 5, 6, 255]).unwrap();
+    assert!(!image.undo().unwrap());
+
+    image.set_journal_depth(2).unwrap();
+    image.mirror().unwrap();
+    image.set_pixel(1, 0, [7, 7, 7, 255]).unwrap();
+    image.mirror().unwrap();
+    // The oldest of three edits is forgotten.
+    assert_eq!(image.history_len().unwrap(), (2, 0));
+    assert_eq!(image.pixel(0, 0).unwrap(), [7, 7, 7, 255]);
+
+    assert!(image.undo().unwrap());
+    assert_eq!(image.pixel(1, 0).unwrap(), [7, 7, 7, 255]);
+    assert!(image.undo().unwrap());
+    assert_eq!(image.pixel(0, 0).unwrap(), [4, 5, 6, 255]);
+    assert!(!image.undo().unwrap());
+    assert_eq!(image.history_len().unwrap(), (0, 2));
+
+    // Redone edits are limited by depth as new ones are.
+    image.set_journal_depth(1).unwrap();
+    assert!(image.redo().unwrap());
+    assert!(image.redo().unwrap());
+    assert!(!image.redo().unwrap());
+    assert_eq!(image.pixel(0, 0).unwrap(), [7, 7, 7, 255]);
+    assert_eq!(image.history_len().unwrap(), (1, 0));
+
+    image.set_journal_depth(0).unwrap();
+    assert_eq!(image.history_len().unwrap(), (0, 0));
+    assert!(!image.undo().unwrap());
+}
+
+#[test]
+fn jobs_wait_for_worker_threads() {
+    let factory = factory();
+    let mut image = factory.new_image(2, 1, ColorType::Rgba8, [0; 4]).unwrap();
+    image.set_pixel(0, 0, [255, 0, 0, 255]).unwrap();
+
+    // More jobs than threads are queued, including with the single background thread.
+    for threads in [2, 1] {
+        factory.set_thread_count(threads);
+        let jobs: Vec<_> = (0..8)
+            .map(|_| image.submit("mirror", "").unwrap())
+            .collect();
+        for job in jobs {
+            let mirrored = job.result().unwrap().unwrap();
+            assert_eq!(mirrored.pixel(1, 0).unwrap(), [255, 0, 0, 255]);
+        }
+    }
+    factory.set_thread_count(0);
+}
+
+#[test]
+fn edit_stack_renders_edited_image() {
+    let factory = factory();
+    let original = factory.open_image("../data/logo.jpg").unwrap();
+
+    let mut edited = original.try_clone().unwrap();
+    edited.mirror().unwrap();
+    edited.swap_channels([2, 1, 0, 3]).unwrap();
+    edited
+        .draw_rect(4.0, 4.0, 20.0, 10.0, DrawStyle::Fill([0, 255, 0, 255]))
+        .unwrap();
+    let mut edited = edited.blur(1.5).unwrap();
+    edited.set_pixel(0, 0, [1, 2, 3, 255]).unwrap();
+    let rect = Rect {
+        x: 2,
+        y: 3,
+        width: 40,
+        height: 30,
+    };
+    edited
+        .pipeline()
+        .crop(rect)
+        .resize(20, 15, Filter::Lanczos3)
+        .run()
+        .unwrap();
+    let stack = edited.edit_stack().unwrap();
+
+    // Recipe stored next to original renders the same image from it.
+    let mut rendered = original.try_clone().unwrap();
+    rendered.apply_edit_stack(&stack).unwrap();
+    assert_eq!(rendered.info().unwrap(), edited.info().unwrap());
+    assert_eq!(rendered.differing_pixels(&edited, 0).unwrap(), 0);
+    assert_eq!(rendered.edit_stack().unwrap(), stack);
+}
+
+#[test]
+fn suggested_crops_fit_image_with_aspect_ratio() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(90, 60, ColorType::Rgb8, [0, 0, 0, 255])
+        .unwrap();
+    for y in 20..30 {
+        for x in 70..80 {
+            image.set_pixel(x, y, [255; 4]).unwrap();
+        }
+    }
+
+    for (aspect_w, aspect_h) in [(1, 1), (16, 9), (2, 3)] {
+        let crops = image.suggest_crops(aspect_w, aspect_h, 3).unwrap();
+        assert!(!crops.is_empty() && crops.len() <= 3);
+        for r in &crops {
+            assert!(r.width > 0 && r.x + r.width <= 90, "{:?}", r);
+            assert!(r.height > 0 && r.y + r.height <= 60, "{:?}", r);
+            // Sides are rounded to whole pixels.
+            let error = (r.width * aspect_h) as i64 - (r.height * aspect_w) as i64;
+            assert!(
+                error.unsigned_abs() <= aspect_w.max(aspect_h) as u64,
+                "{:?}",
+                r
+            );
+        }
+        // The best crop covers the only detail of image.
+        let best = crops[0];
+        assert!(best.x <= 70 && best.x + best.width >= 80, "{:?}", best);
+        assert!(best.y <= 20 && best.y + best.height >= 30, "{:?}", best);
+    }
+    assert!(image.suggest_crops(0, 1, 3).is_err());
+}
+
+#[test]
+fn guides_are_drawn_at_thirds_and_safe_area() {
+    let factory = factory();
+    let black = [0, 0, 0, 255];
+    let red = [255, 0, 0, 255];
+    let spec = GuideSpec {
+        color: red,
+        ..GuideSpec::default()
+    };
+    let mut image = factory.new_image(30, 20, ColorType::Rgba8, black).unwrap();
+    image.draw_guides(&spec).unwrap();
+    for y in 0..20 {
+        for x in 0..30 {
+            let guide = x == 10 || x == 20 || y == 6 || y == 13;
+            let expected = if guide { red } else { black };
+            assert_eq!(image.pixel(x, y).unwrap(), expected, "{}, {}", x, y);
+        }
+    }
+
+    let mut image = factory.new_image(30, 20, ColorType::Rgba8, black).unwrap();
+    let spec = GuideSpec {
+        guides: GuideSpec::SAFE_AREA | GuideSpec::CENTER_CROSS,
+        line_width: 2,
+        ..spec
+    };
+    image.draw_guides(&spec).unwrap();
+    // Frame is drawn inside margins of 3 x 2 pixels.
+    for (x, y) in [(3, 2), (4, 3), (26, 17), (25, 16), (15, 2), (3, 10)] {
+        assert_eq!(image.pixel(x, y).unwrap(), red, "{}, {}", x, y);
+    }
+    for (x, y) in [(2, 2), (3, 1), (27, 17), (5, 4), (24, 15)] {
+        assert_eq!(image.pixel(x, y).unwrap(), black, "{}, {}", x, y);
+    }
+    // Cross arms are 2 pixels long around the center.
+    for (x, y) in [(14, 10), (16, 10), (15, 8), (13, 9)] {
+        assert_eq!(image.pixel(x, y).unwrap(), red, "{}, {}", x, y);
+    }
+    for (x, y) in [(15, 12), (12, 10), (17, 10)] {
+        assert_eq!(image.pixel(x, y).unwrap(), black, "{}, {}", x, y);
+    }
+
+    let unknown = GuideSpec { guides: 8, ..spec };
+    assert!(image.draw_guides(&unknown).is_err());
+}
+
+/// Returns `width` x `height` gray image with pseudo-random noise of standard deviation of about
+/// `sigma` channel values added to its RGB channels.
+fn noisy_image(factory: &ImageFactory, width: u32, height: u32, sigma: f32) -> Image {
+    let mut image = factory
+        .new_image(width, height, ColorType::Rgba8, [128, 128, 128, 255])
+        .unwrap();
+    let mut state = 7u32;
+    let mut uniform = || {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+    };
+    // Sum of 4 uniform values in [-1, 1] has standard deviation of sqrt(4 / 3).
+    let scale = sigma / (4.0f32 / 3.0).sqrt();
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = [0, 0, 0, 255];
+            for channel in &mut pixel[..3] {
+                let noise: f32 = (0..4).map(|_| uniform()).sum();
+                *channel = (128.0 + noise * scale).round() as u8;
+            }
+            image.set_pixel(x, y, pixel).unwrap();
+        }
+    }
+    image
+}
+
+#[test]
+fn noise_of_noisy_image_is_estimated() {
+    let factory = factory();
+    let flat = factory
+        .new_image(48, 48, ColorType::Rgb8, [90, 120, 200, 255])
+        .unwrap();
+    assert_eq!(flat.estimate_noise().unwrap(), [0.0; 4]);
+
+    let noisy = noisy_image(&factory, 48, 48, 12.0);
+    let sigmas = noisy.estimate_noise().unwrap();
+    for &sigma in &sigmas[..3] {
+        let expected = 12.0 / 255.0;
+        assert!(
+            sigma > expected * 0.6 && sigma < expected * 1.5,
+            "{:?}",
+            sigmas
+        );
+    }
+    assert_eq!(sigmas[3], 0.0);
+
+    let tiny = factory.new_image(2, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    assert!(tiny.estimate_noise().is_err());
+}
+
+#[test]
+fn auto_denoise_and_sharpen_pick_strength() {
+    let factory = factory();
+    let mut noisy = noisy_image(&factory, 48, 48, 12.0);
+    let before = noisy.estimate_noise().unwrap();
+    noisy.auto_denoise().unwrap();
+    let after = noisy.estimate_noise().unwrap();
+    for c in 0..3 {
+        assert!(after[c] < before[c] * 0.7, "{:?} -> {:?}", before, after);
+    }
+
+    // Images without noise are left as they are.
+    let flat = factory
+        .new_image(16, 16, ColorType::Rgb8, [90, 120, 200, 255])
+        .unwrap();
+    let mut denoised = flat.try_clone().unwrap();
+    denoised.auto_denoise().unwrap();
+    assert_eq!(denoised.differing_pixels(&flat, 0).unwrap(), 0);
+
+    // Blurred edge gets sharper.
+    let mut edge = factory
+        .new_image(32, 32, ColorType::Rgb8, [40, 40, 40, 255])
+        .unwrap();
+    for y in 0..32 {
+        for x in 16..32 {
+            edge.set_pixel(x, y, [220, 220, 220, 255]).unwrap();
+        }
+    }
+    let mut blurred = edge.blur(2.0).unwrap();
+    let before = blurred.sharpness().unwrap();
+    blurred.auto_sharpen().unwrap();
+    assert!(blurred.sharpness().unwrap() > before);
+}
+
+#[test]
+fn chromatic_aberration_is_corrected() {
+    let factory = factory();
+    // Red channel is image of green one magnified by 1 / 1.002 about the center.
+    let size = 160;
+    let center = (size - 1) as f32 / 2.0;
+    let wave = |x: f32, y: f32| 128.0 + 100.0 * (x * 0.5).sin() * (y * 0.5).sin();
+    let mut image = factory
+        .new_image(size, size, ColorType::Rgb8, [0; 4])
+        .unwrap();
+    for y in 0..size {
+        for x in 0..size {
+            let (fx, fy) = (x as f32, y as f32);
+            let green = wave(fx, fy).round() as u8;
+            let scaled = |v: f32| center + (v - center) / 1.002;
+            let red = wave(scaled(fx), scaled(fy)).round() as u8;
+            image.set_pixel(x, y, [red, green, green, 255]).unwrap();
+        }
+    }
+    let misalignment = |image: &Image| {
+        let mut sum = 0;
+        for y in 0..size {
+            for x in 0..size {
+                let [r, g, _, _] = image.pixel(x, y).unwrap();
+                sum += (r as i64 - g as i64).abs();
+            }
+        }
+        sum
+    };
+    let before = misalignment(&image);
+
+    for red_scale in [1.002, 0.0] {
+        let mut corrected = image.try_clone().unwrap();
+        corrected.correct_ca(red_scale, 0.0).unwrap();
+        let after = misalignment(&corrected);
+        assert!(after * 2 < before, "{} -> {}", before, after);
+        // Blue channel is aligned with green one already.
+        assert_eq!(
+            corrected.pixel(3, 5).unwrap()[2],
+            image.pixel(3, 5).unwrap()[2]
+        );
+    }
+
+    let mut unchanged = image.try_clone().unwrap();
+    unchanged.correct_ca(1.0, 1.0).unwrap();
+    assert_eq!(unchanged.differing_pixels(&image, 0).unwrap(), 0);
+}
+
+#[test]
+fn purple_fringes_at_edges_are_removed() {
+    let factory = factory();
+    let purple = [160, 60, 200, 255];
+    let green = [60, 200, 60, 255];
+    // Black and white halves with purple fringe along the edge, green pixel on it and purple
+    // patch away from it.
+    let mut image = factory
+        .new_image(40, 14, ColorType::Rgb8, [0, 0, 0, 255])
+        .unwrap();
+    for y in 0..14 {
+        for x in 21..40 {
+            image.set_pixel(x, y, [255; 4]).unwrap();
+        }
+        let fringe = if y == 1 { green } else { purple };
+        image.set_pixel(20, y, fringe).unwrap();
+    }
+    for y in 3..11 {
+        for x in 2..10 {
+            image.set_pixel(x, y, purple).unwrap();
+        }
+    }
+
+    let mut defringed = image.try_clone().unwrap();
+    defringed.defringe(1.0, 60.0).unwrap();
+    let spread = |p: [u8; 4]| p[..3].iter().max().unwrap() - p[..3].iter().min().unwrap();
+    let fringe = defringed.pixel(20, 7).unwrap();
+    assert!(spread(fringe) < spread(purple) / 4, "{:?}", fringe);
+    assert_eq!(defringed.pixel(20, 1).unwrap(), green);
+    assert_eq!(defringed.pixel(6, 7).unwrap(), purple);
+    assert_eq!(defringed.pixel(30, 7).unwrap(), [255; 4]);
+
+    let mut unchanged = image.try_clone().unwrap();
+    unchanged.defringe(0.0, 60.0).unwrap();
+    assert_eq!(unchanged.differing_pixels(&image, 0).unwrap(), 0);
+}
+
+#[test]
+fn clipped_highlights_get_hue_of_neighbours() {
+    let factory = factory();
+    let skin = [200, 120, 100, 255];
+    let mut image = factory.new_image(9, 9, ColorType::Rgb8, skin).unwrap();
+    image.set_pixel(4, 4, [255, 180, 150, 255]).unwrap();
+    image.set_pixel(0, 0, [255; 4]).unwrap();
+
+    let mut recovered = image.try_clone().unwrap();
+    recovered.recover_highlights(1.0).unwrap();
+    let [r, g, b, _] = recovered.pixel(4, 4).unwrap();
+    // Red is predicted from green and blue by their ratio to red of unclipped pixels around and
+    // then the pixel is scaled down to fit channel range.
+    assert_eq!(r, 255);
+    let ratio = |a: u8, b: u8| a as f32 / b as f32;
+    assert!(
+        (ratio(r, g) - ratio(skin[0], skin[1])).abs() < 0.05,
+        "{:?}",
+        [r, g, b]
+    );
+    assert!(
+        (ratio(r, b) - ratio(skin[0], skin[2])).abs() < 0.05,
+        "{:?}",
+        [r, g, b]
+    );
+    // Pixels with all or none of channels clipped are kept.
+    assert_eq!(recovered.pixel(0, 0).unwrap(), [255; 4]);
+    assert_eq!(recovered.pixel(8, 8).unwrap(), skin);
+
+    let mut unchanged = image.try_clone().unwrap();
+    unchanged.recover_highlights(0.0).unwrap();
+    assert_eq!(unchanged.differing_pixels(&image, 0).unwrap(), 0);
+}
+
+#[test]
+fn shadows_and_highlights_are_adjusted_separately() {
+    let factory = factory();
+    let dark = [60, 30, 20, 255];
+    let bright = [220, 220, 220, 255];
+    let mut image = factory.new_image(40, 10, ColorType::Rgb8, dark).unwrap();
+    for y in 0..10 {
+        for x in 20..40 {
+            image.set_pixel(x, y, bright).unwrap();
+        }
+    }
+
+    let mut lifted = image.try_clone().unwrap();
+    lifted.shadows_highlights(0.5, 0.0, 2.0).unwrap();
+    let shadow = lifted.pixel(2, 5).unwrap();
+    assert!(shadow[0] > dark[0] + 10, "{:?}", shadow);
+    // Color is kept by scaling channels together.
+    let ratio = |p: [u8; 4]| p[0] as f32 / p[1] as f32;
+    assert!((ratio(shadow) - ratio(dark)).abs() < 0.1, "{:?}", shadow);
+    let highlight = lifted.pixel(37, 5).unwrap();
+    assert!(highlight[0].abs_diff(bright[0]) <= 1, "{:?}", highlight);
+
+    let mut darkened = image.try_clone().unwrap();
+    darkened.shadows_highlights(0.0, -0.5, 2.0).unwrap();
+    let highlight = darkened.pixel(37, 5).unwrap();
+    assert!(highlight[0] + 5 < bright[0], "{:?}", highlight);
+    let shadow = darkened.pixel(2, 5).unwrap();
+    assert!(shadow[0].abs_diff(dark[0]) <= 1, "{:?}", shadow);
+
+    let mut unchanged = image.try_clone().unwrap();
+    unchanged.shadows_highlights(0.0, 0.0, 2.0).unwrap();
+    assert_eq!(unchanged.differing_pixels(&image, 0).unwrap(), 0);
+    assert!(unchanged.shadows_highlights(2.0, 0.0, 2.0).is_err());
+}
+
+#[test]
+fn hsl_bands_shift_solid_colors() {
+    let factory = factory();
+    let red = [255, 0, 0, 255];
+    let blue = [0, 0, 255, 255];
+    let gray = [100, 100, 100, 255];
+    let mut image = factory.new_image(3, 1, ColorType::Rgb8, red).unwrap();
+    image.set_pixel(1, 0, blue).unwrap();
+    image.set_pixel(2, 0, gray).unwrap();
+    let adjusted = |band: HslBand| {
+        let mut adjusted = image.try_clone().unwrap();
+        adjusted.hsl_adjust(&[band]).unwrap();
+        adjusted
+    };
+
+    // Hue of red band is rotated to green, colors of other bands and grays are kept.
+    let shifted = adjusted(HslBand::new(ColorBand::Red, 120.0, 0.0, 0.0));
+    assert_eq!(shifted.pixel(0, 0).unwrap(), [0, 255, 0, 255]);
+    assert_eq!(shifted.pixel(1, 0).unwrap(), blue);
+    assert_eq!(shifted.pixel(2, 0).unwrap(), gray);
+
+    let desaturated = adjusted(HslBand::new(ColorBand::Red, 0.0, -1.0, 0.0));
+    assert_eq!(desaturated.pixel(0, 0).unwrap(), [127, 127, 127, 255]);
+    let lightened = adjusted(HslBand::new(ColorBand::Blue, 0.0, 0.0, 0.5));
+    assert_eq!(lightened.pixel(1, 0).unwrap(), [127, 127, 255, 255]);
+    assert_eq!(lightened.pixel(0, 0).unwrap(), red);
+}
+
+#[test]
+fn colors_within_tolerance_are_replaced() {
+    let factory = factory();
+    let red = [200, 0, 0, 255];
+    let shaded = [170, 0, 0, 255];
+    let blue = [0, 0, 200, 255];
+    let green = [0, 180, 0, 255];
+    let mut image = factory.new_image(4, 1, ColorType::Rgb8, red).unwrap();
+    for (x, color) in [(1, shaded), (2, blue), (3, green)] {
+        image.set_pixel(x, 0, color).unwrap();
+    }
+
+    for preserve_luminosity in [false, true] {
+        let mut replaced = image.try_clone().unwrap();
+        replaced
+            .replace_color(red, blue, 0.1, preserve_luminosity)
+            .unwrap();
+        assert_eq!(replaced.pixel(0, 0).unwrap(), blue);
+        let [r, g, b, a] = replaced.pixel(1, 0).unwrap();
+        assert!(r < 20 && g < 20 && b > 120, "{:?}", [r, g, b]);
+        assert_eq!(a, 255);
+        // Lightness of shaded pixel is kept.
+        if preserve_luminosity {
+            assert_eq!(b, shaded[0]);
+        }
+        assert_eq!(replaced.pixel(2, 0).unwrap(), blue);
+        assert_eq!(replaced.pixel(3, 0).unwrap(), green);
+    }
+
+    // Shades are out of small tolerance.
+    let mut exact = image.try_clone().unwrap();
+    exact.replace_color(red, blue, 0.01, false).unwrap();
+    assert_eq!(exact.pixel(0, 0).unwrap(), blue);
+    assert_eq!(exact.pixel(1, 0).unwrap(), shaded);
+}
+
+#[test]
+fn split_tone_tints_shadows_and_highlights() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(3, 1, ColorType::Rgba8, [30, 30, 30, 200])
+        .unwrap();
+    image.set_pixel(1, 0, [128, 128, 128, 200]).unwrap();
+    image.set_pixel(2, 0, [230, 230, 230, 200]).unwrap();
+    let teal = [0x40, 0x80, 0xc0, 0xff];
+    let orange = [0xc0, 0x80, 0x40, 0xff];
+    let neutral = [0x80, 0x80, 0x80, 0];
+
+    let graded = |shadows, highlights, balance| {
+        let mut graded = image.try_clone().unwrap();
+        graded
+            .split_tone(shadows, neutral, highlights, balance)
+            .unwrap();
+        graded
+    };
+    let toned = graded(teal, orange, 0.0);
+    let [r, _, b, a] = toned.pixel(0, 0).unwrap();
+    assert!(b > r + 10, "shadows are teal: {:?}", [r, b]);
+    let [r, _, b, _] = toned.pixel(2, 0).unwrap();
+    assert!(r > b + 10, "highlights are orange: {:?}", [r, b]);
+    assert_eq!(a, 200);
+
+    // Balance moves tone splitting shadows and highlights.
+    let [low, _, _, _] = graded(neutral, orange, -1.0).pixel(1, 0).unwrap();
+    let [high, _, _, _] = graded(neutral, orange, 1.0).pixel(1, 0).unwrap();
+    assert_ne!(low, high);
+
+    let unchanged = graded(neutral, neutral, 0.0);
+    assert_eq!(unchanged.differing_pixels(&image, 0).unwrap(), 0);
+    assert!(image.split_tone(teal, neutral, orange, 1.5).is_err());
+}
+
+#[test]
+fn film_look_is_applied_deterministically() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(16, 16, ColorType::Rgb8, [0, 0, 0, 255])
+        .unwrap();
+    for y in 0..16 {
+        for x in 0..16 {
+            let color = [x as u8 * 16, y as u8 * 16, 128, 255];
+            image.set_pixel(x, y, color).unwrap();
+        }
+    }
+    let emulated = |stock, strength| {
+        let mut emulated = image.try_clone().unwrap();
+        emulated.film_emulation(stock, strength).unwrap();
+        emulated
+    };
+
+    let portra = emulated(FilmStock::Portra400, 1.0);
+    assert!(portra.differing_pixels(&image, 2).unwrap() > 128);
+    let again = emulated(FilmStock::Portra400, 1.0);
+    assert_eq!(again.differing_pixels(&portra, 0).unwrap(), 0);
+
+    // Black and white stock gives gray pixels with grain equal in every channel.
+    let tri_x = emulated(FilmStock::TriX400, 1.0);
+    for (x, y) in [(0, 0), (5, 9), (15, 15)] {
+        let [r, g, b, _] = tri_x.pixel(x, y).unwrap();
+        assert!(r.abs_diff(g) <= 1 && g.abs_diff(b) <= 1, "{:?}", [r, g, b]);
+    }
+    let midtones = factory
+        .new_image(32, 32, ColorType::Rgb8, [128, 128, 128, 255])
+        .unwrap();
+    let mut grainy = midtones.try_clone().unwrap();
+    grainy.film_emulation(FilmStock::TriX400, 1.0).unwrap();
+    assert!(grainy.estimate_noise().unwrap()[1] > 0.005);
+
+    let unchanged = emulated(FilmStock::Velvia50, 0.0);
+    assert_eq!(unchanged.differing_pixels(&image, 0).unwrap(), 0);
+    assert!(image.film_emulation(FilmStock::Portra400, 2.0).is_err());
+}
+
+#[test]
+fn logger_receives_warnings() {
+    let factory = factory();
+    let jpeg = std::fs::read("../data/logo.jpg").unwrap();
+    let malformed = with_segment(&jpeg, 0xe1, b"Exif\0\0II*\0\xff\xff\xff\xff");
+
+    let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let received = messages.clone();
+    factory.set_logger(move |level, message| {
+        received.lock().unwrap().push((level, message.to_string()));
+    });
+    let image = factory.open_image_from_memory(&malformed, &OpenOptions::default());
+    factory.remove_logger();
+    assert!(image.is_ok());
+
+    // Logger is global, so messages of other tests may be received too.
+    let messages = messages.lock().unwrap();
+    assert!(
+        messages
+            .iter()
+            .any(|(level, m)| *level == LogLevel::Warning && m.starts_with("Malformed EXIF")),
+        "{:?}",
+        messages
+    );
+}
+
+#[test]
+fn annotations_are_added_listed_and_removed() {
+    let factory = factory();
+    let mut image = factory.new_image(20, 10, ColorType::Rgb8, [0; 4]).unwrap();
+    assert!(image.annotations().unwrap().is_empty());
+    let rect = |x, y, width, height| Rect {
+        x,
+        y,
+        width,
+        height,
+    };
+    let face = (rect(1, 1, 4, 4), r#"{"label": "face", "score": 0.93}"#);
+    let tag = (rect(10, 2, 8, 6), r#"{"label": "tag"}"#);
+    image.add_annotation(face.0, face.1).unwrap();
+    image.add_annotation(tag.0, tag.1).unwrap();
+    let listed = image.annotations().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0].0, face.0);
+    assert!(listed[0].1.contains("face") && listed[0].1.contains("0.93"));
+    assert_eq!(listed[1].0, tag.0);
+
+    // Annotations travel with copies of image.
+    let blurred = image.blur(1.0).unwrap();
+    assert_eq!(blurred.annotations().unwrap().len(), 2);
+
+    assert!(image.remove_annotation(0).unwrap());
+    let listed = image.annotations().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].0, tag.0);
+    assert!(!image.remove_annotation(1).unwrap());
+
+    assert!(image.add_annotation(face.0, "{not json").is_err());
+    assert!(image.add_annotation(rect(15, 0, 10, 4), tag.1).is_err());
+    assert!(image.add_annotation(rect(0, 0, 0, 4), tag.1).is_err());
+}
+
+#[test]
+fn template_is_found_where_it_was_cut() {
+    let factory = factory();
+    let haystack = noisy_image(&factory, 64, 48, 40.0);
+    let rect = Rect {
+        x: 37,
+        y: 11,
+        width: 12,
+        height: 10,
+    };
+    let needle = haystack.view(rect).unwrap();
+    let found = haystack
+        .match_template(&needle, MatchMethod::Ncc, 3)
+        .unwrap();
+    assert!(!found.is_empty());
+    let best = found[0];
+    assert_eq!((best.x, best.y), (37, 11));
+    assert_eq!((best.width, best.height), (12, 10));
+    assert!(best.score > 0.99, "score {}", best.score);
+    assert!(found[1..].iter().all(|m| m.score <= best.score));
+}
+
+#[test]
+fn known_offset_is_recovered_by_alignment() {
+    let factory = factory();
+    let scene = noisy_image(&factory, 64, 48, 40.0);
+    let frame = |x, y| {
+        let rect = Rect {
+            x,
+            y,
+            width: 48,
+            height: 32,
+        };
+        scene.view(rect).unwrap().try_clone().unwrap()
+    };
+    let reference = frame(8, 8);
+    // Point of reference is 3 pixels to the left and 2 pixels lower in moving image.
+    let moving = frame(11, 6);
+    let (transform, warped) = reference.align(&moving, Motion::Translation).unwrap();
+    let m = transform.matrix;
+    assert!((m[2] + 3.0).abs() < 0.25, "{:?}", m);
+    assert!((m[5] - 2.0).abs() < 0.25, "{:?}", m);
+    assert!((m[0] - 1.0).abs() < 1e-3 && m[1].abs() < 1e-3, "{:?}", m);
+    assert!((m[4] - 1.0).abs() < 1e-3 && m[3].abs() < 1e-3, "{:?}", m);
+    assert!(transform.score > 0.5, "score {}", transform.score);
+    // Warped moving image shows the same scene as reference, resampled at fractional shift.
+    let (warped, reference) = (
+        warped.pixel(20, 10).unwrap(),
+        reference.pixel(20, 10).unwrap(),
+    );
+    let close = warped
+        .iter()
+        .zip(&reference)
+        .all(|(&a, &b)| (a as i32 - b as i32).abs() <= 2);
+    assert!(close, "{:?} != {:?}", warped, reference);
+}
+
+#[test]
+fn identity_mesh_leaves_image_unchanged() {
+    let factory = factory();
+    let image = noisy_image(&factory, 40, 30, 40.0);
+    let grid: Vec<_> = (0..3)
+        .flat_map(|row| (0..4).map(move |col| (col, row)))
+        .map(|(col, row)| Point {
+            x: col as f32 * 40.0 / 3.0,
+            y: row as f32 * 30.0 / 2.0,
+        })
+        .collect();
+    let mut warped = image.try_clone().unwrap();
+    warped.mesh_warp(&grid, &grid, 4, 3).unwrap();
+    assert_eq!(warped.differing_pixels(&image, 0).unwrap(), 0);
+
+    match warped.mesh_warp(&grid, &grid[1..], 4, 3) {
+        Err(Error::InvalidArgument(_)) => (),
+        r => panic!("mismatched grids are warped: {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn export_profile_writes_every_output() {
+    let factory = factory();
+    let image = factory
+        .new_image(60, 30, ColorType::Rgb8, [200, 100, 50, 255])
+        .unwrap();
+    let profile = factory
+        .export_profile(
+            r#"{"source_scale": 3, "outputs": [
+                {"name": "{name}@1x.png", "scale": 1},
+                {"name": "{name}@3x.png", "scale": 3},
+                {"name": "{name}-{width}.jpg", "width": 24, "jpeg_quality": 85}
+            ]}"#,
+        )
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("image_sl_export_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    image.export(&profile, dir.join("app")).unwrap();
+    let outputs = [
+        ("app@1x.png", 20, 10),
+        ("app@3x.png", 60, 30),
+        ("app-24.jpg", 24, 12),
+    ];
+    for &(name, width, height) in &outputs {
+        let info = factory.open_image(dir.join(name)).unwrap().info().unwrap();
+        assert_eq!((info.width, info.height), (width, height), "{}", name);
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), outputs.len());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let invalid = factory.export_profile(r#"{"outputs": [{"name": "{name}.png", "scale": 0}]}"#);
+    assert_eq!(error_code(invalid), Some(ImageError::Parameter));
+    let missing = factory.export_profile(r#"{"source_scale": 2}"#);
+    assert_eq!(error_code(missing), Some(ImageError::Parameter));
+}
+
+#[test]
+fn capabilities_match_functions() {
+    let factory = factory();
+    let capabilities = factory.capabilities();
+    let features = [
+        ("open_image_url", Op::OpenUrl, capability::HTTP),
+        ("convert_to_srgb", Op::ConvertToSrgb, capability::ICC),
+    ];
+    for &(name, op, flag) in &features {
+        let enabled = capabilities & flag != 0;
+        assert_eq!(factory.has_fn(name), enabled, "{}", name);
+        assert_eq!(factory.supports(op), enabled, "{:?}", op);
+    }
+    // Library of tests is built with default features.
+    assert_ne!(capabilities & capability::PARALLEL, 0);
+    assert!(factory.has_fn("blur_image"));
+    assert!(factory.has_fn("generate_icon_set"));
+    assert!(factory.supports(Op::IconSets));
+    assert!(!factory.has_fn("no_such_function"));
+    assert!(!factory.has_fn("blur_image\0"));
+    assert!(!factory.has_fn(""));
+}
+
+#[test]
+fn icon_sets_have_every_size() {
+    let factory = factory();
+    let image = factory
+        .new_image(64, 64, ColorType::Rgba8, [30, 60, 90, 255])
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("image_sl_icons_{}", std::process::id()));
+    let side = |name: &str| {
+        let info = factory.open_image(dir.join(name)).unwrap().info().unwrap();
+        assert_eq!(info.width, info.height, "{}", name);
+        info.width
+    };
+    let count = |dir: &std::path::Path| std::fs::read_dir(dir).unwrap().count();
+
+    image.generate_icon_set(IconSetKind::Favicon, &dir).unwrap();
+    assert_eq!(count(&dir), 4);
+    let ico = std::fs::read(dir.join("favicon.ico")).unwrap();
+    // ICO header of icon resource with 16, 32 and 48 pixel images.
+    assert_eq!(ico[..6], [0, 0, 1, 0, 3, 0]);
+    assert_eq!(side("favicon-16x16.png"), 16);
+    assert_eq!(side("favicon-32x32.png"), 32);
+    assert_eq!(side("apple-touch-icon.png"), 180);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    image.generate_icon_set(IconSetKind::Android, &dir).unwrap();
+    assert_eq!(count(&dir), 6);
+    let densities = [
+        ("mdpi", 48),
+        ("hdpi", 72),
+        ("xhdpi", 96),
+        ("xxhdpi", 144),
+        ("xxxhdpi", 192),
+    ];
+    for &(density, size) in &densities {
+        assert_eq!(side(&format!("mipmap-{}/ic_launcher.png", density)), size);
+    }
+    assert_eq!(side("playstore-icon.png"), 512);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    image.generate_icon_set(IconSetKind::Ios, &dir).unwrap();
+    // 15 icons of 18 iPhone, iPad and App Store slots with `Contents.json` listing them.
+    assert_eq!(count(&dir), 16);
+    assert_eq!(side("Icon-60@3x.png"), 180);
+    assert_eq!(side("Icon-83.5@2x.png"), 167);
+    assert_eq!(side("Icon-1024@1x.png"), 1024);
+    let contents = std::fs::read_to_string(dir.join("Contents.json")).unwrap();
+    assert_eq!(contents.matches("\"filename\"").count(), 18, "{}", contents);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    image.generate_icon_set(IconSetKind::Pwa, &dir).unwrap();
+    assert_eq!(count(&dir), 4);
+    assert_eq!(side("icon-192x192.png"), 192);
+    assert_eq!(side("icon-512x512.png"), 512);
+    let manifest = std::fs::read_to_string(dir.join("manifest.json")).unwrap();
+    assert!(manifest.contains("\"512x512\""), "{}", manifest);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn progress_is_reported_and_cancels_operations() {
+    let factory = factory();
+    let image = noisy_image(&factory, 64, 200, 40.0);
+    let mut done = Vec::new();
+    let blurred = image
+        .blur_with_progress(2.0, |percent| {
+            done.push(percent);
+            true
+        })
+        .unwrap();
+    assert!(done.len() > 1);
+    assert!(done.windows(2).all(|w| w[0] <= w[1]), "{:?}", done);
+    assert_eq!(done.last().copied(), Some(100.0));
+    let expected = image.blur(2.0).unwrap();
+    assert_eq!(blurred.differing_pixels(&expected, 0).unwrap(), 0);
+
+    done.clear();
+    let resized = image
+        .resize_with_progress(32, 100, |percent| {
+            done.push(percent);
+            true
+        })
+        .unwrap();
+    assert_eq!(resized.info().unwrap().width, 32);
+    assert_eq!(done.last().copied(), Some(100.0));
+
+    done.clear();
+    let encoded = image
+        .encode_with_progress(ImageFormat::Png, |percent| {
+            done.push(percent);
+            true
+        })
+        .unwrap();
+    assert_eq!(encoded, image.encode(ImageFormat::Png).unwrap());
+    assert_eq!(done.last().copied(), Some(100.0));
+
+    // Operations stop once callback returns `false`.
+    let mut calls = 0;
+    let cancelled = image.blur_with_progress(2.0, |_| {
+        calls += 1;
+        false
+    });
+    assert_eq!(error_code(cancelled), Some(ImageError::Cancelled));
+    assert!(calls >= 1);
+    let cancelled = image.resize_with_progress(32, 100, |percent| percent < 50.0);
+    assert_eq!(error_code(cancelled), Some(ImageError::Cancelled));
+    let cancelled = image.encode_with_progress(ImageFormat::Png, |_| false);
+    assert_eq!(error_code(cancelled), Some(ImageError::Cancelled));
+}
+
+#[test]
+fn preview_scales_image_and_parameters() {
+    let factory = factory();
+    let image = factory
+        .new_image(200, 100, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    let rect = r#"{"x": 40, "y": 20, "width": 80, "height": 40, "thickness": 1,
+        "color": 4278190335, "fill": true}"#;
+    let preview = image.apply_preview("draw_rect", rect, 50).unwrap();
+    let info = preview.info().unwrap();
+    assert_eq!((info.width, info.height), (50, 25));
+    // Rectangle is scaled by a quarter with image.
+    assert_eq!(preview.pixel(20, 10).unwrap(), [255, 0, 0, 255]);
+    assert_eq!(preview.pixel(8, 10).unwrap(), [0, 0, 0, 255]);
+    assert_eq!(preview.pixel(20, 17).unwrap(), [0, 0, 0, 255]);
+    assert_eq!(image.pixel(100, 40).unwrap(), [0, 0, 0, 255]);
+    let stack = preview.edit_stack().unwrap();
+    assert!(stack.contains(r#""width":20"#), "{}", stack);
+
+    // Images fitting preview size are edited as they are.
+    let preview = image.apply_preview("draw_rect", rect, 200).unwrap();
+    let applied = image.apply("draw_rect", rect).unwrap();
+    assert_eq!(preview.differing_pixels(&applied, 0).unwrap(), 0);
+    let unknown = image.apply_preview("no_such_op", "", 50);
+    assert!(unknown.is_err());
+}
+
+#[test]
+fn data_uri_round_trips_image() {
+    let factory = factory();
+    let image = noisy_image(&factory, 9, 7, 40.0);
+    let uri = image.encode_data_uri(ImageFormat::Png).unwrap();
+    assert!(uri.starts_with("data:image/png;base64,"), "{}", uri);
+    let decoded = factory.open_image_data_uri(&uri).unwrap();
+    assert_eq!(decoded.info().unwrap().width, 9);
+    assert_eq!(decoded.differing_pixels(&image, 0).unwrap(), 0);
+
+    let not_base64 = factory.open_image_data_uri("data:image/png,abc");
+    assert_eq!(error_code(not_base64), Some(ImageError::Parameter));
+    let not_uri = factory.open_image_data_uri(&uri["data:".len()..]);
+    assert_eq!(error_code(not_uri), Some(ImageError::Parameter));
+    let corrupted = factory.open_image_data_uri("data:image/png;base64,%%%");
+    assert_eq!(error_code(corrupted), Some(ImageError::Decoding));
+}
+
+#[test]
+fn images_are_saved_to_and_opened_from_files() {
+    let factory = factory();
+    let image = noisy_image(&factory, 9, 7, 40.0);
+    let path = std::env::temp_dir().join(format!("image_sl_fd_{}.png", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    let options = SaveOptions::default();
+    image
+        .save_to_file(&file, ImageFormat::Png, &options)
+        .unwrap();
+    drop(file);
+    // File stays open after image is read from it.
+    let file = std::fs::File::open(&path).unwrap();
+    let opened = factory.open_image_file(&file).unwrap();
+    assert_eq!(opened.differing_pixels(&image, 0).unwrap(), 0);
+    assert!(file.metadata().unwrap().len() > 0);
+    drop(file);
+
+    // Saving to file opened for reading fails.
+    let file = std::fs::File::open(&path).unwrap();
+    let saved = image.save_to_file(&file, ImageFormat::Png, &options);
+    assert_eq!(error_code(saved), Some(ImageError::Io));
+    std::fs::remove_file(&path).unwrap();
+
+    // Pipes are read to their end before decoding.
+    #[cfg(unix)]
+    {
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        let png = image.encode(ImageFormat::Png).unwrap();
+        let writing = std::thread::spawn(move || std::io::Write::write_all(&mut writer, &png));
+        let pipe = std::fs::File::from(std::os::fd::OwnedFd::from(reader));
+        let opened = factory.open_image_file(&pipe).unwrap();
+        writing.join().unwrap().unwrap();
+        assert_eq!(opened.differing_pixels(&image, 0).unwrap(), 0);
+    }
+}
+
+#[test]
+fn eink_rows_are_packed_and_padded() {
+    let factory = factory();
+    let (black, white) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+    // 10 pixels of 1 bit rows take 2 bytes, low 6 bits of the second one are padding.
+    let mut mono = factory.new_image(10, 2, ColorType::Rgb8, black).unwrap();
+    mono.set_pixel(0, 0, white).unwrap();
+    mono.set_pixel(9, 0, white).unwrap();
+    for x in 0..10 {
+        mono.set_pixel(x, 1, white).unwrap();
+    }
+    let packed = mono.pack_eink(EinkFormat::Mono).unwrap();
+    assert_eq!(packed, [0x80, 0x40, 0xff, 0xc0]);
+
+    // 3 pixels of 4 bit rows take 2 bytes, low nibble of the second one is padding.
+    let mut gray = factory.new_image(3, 2, ColorType::L8, [0x30; 4]).unwrap();
+    gray.set_pixel(0, 0, black).unwrap();
+    gray.set_pixel(1, 0, white).unwrap();
+    gray.set_pixel(2, 0, [0x80, 0x80, 0x80, 255]).unwrap();
+    let packed = gray.pack_eink(EinkFormat::Gray4).unwrap();
+    assert_eq!(packed, [0x0f, 0x80, 0x33, 0x30]);
+
+    // Colors are mapped to the nearest of red, white and orange panel colors.
+    let mut color = factory.new_image(3, 1, ColorType::Rgb8, black).unwrap();
+    color.set_pixel(0, 0, [230, 20, 20, 255]).unwrap();
+    color.set_pixel(1, 0, [240, 240, 250, 255]).unwrap();
+    color.set_pixel(2, 0, [250, 120, 10, 255]).unwrap();
+    let packed = color.pack_eink(EinkFormat::Acep7).unwrap();
+    assert_eq!(packed, [0x41, 0x60]);
+}
+
+#[test]
+fn framebuffer_pixels_are_packed_in_row_order() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(2, 2, ColorType::Rgb8, [255, 0, 0, 255])
+        .unwrap();
+    image.set_pixel(1, 0, [0, 255, 0, 255]).unwrap();
+    image.set_pixel(0, 1, [0, 0, 255, 255]).unwrap();
+    image.set_pixel(1, 1, [255, 255, 255, 255]).unwrap();
+    let pack = |layout, order| image.pack_framebuffer(layout, order).unwrap();
+
+    let (top, bottom) = ([0x00, 0xf8, 0xe0, 0x07], [0x1f, 0x00, 0xff, 0xff]);
+    let packed = pack(PixelLayout::Rgb565, RowOrder::TopDown);
+    assert_eq!(packed, [top, bottom].concat());
+    let packed = pack(PixelLayout::Rgb565, RowOrder::BottomUp);
+    assert_eq!(packed, [bottom, top].concat());
+
+    let packed = pack(PixelLayout::Rgb332, RowOrder::TopDown);
+    assert_eq!(packed, [0xe0, 0x1c, 0x03, 0xff]);
+    let packed = pack(PixelLayout::Rgb332, RowOrder::BottomUp);
+    assert_eq!(packed, [0x03, 0xff, 0xe0, 0x1c]);
+
+    let (top, bottom) = ([0, 0, 255, 0, 255, 0], [255, 0, 0, 255, 255, 255]);
+    let packed = pack(PixelLayout::Bgr888, RowOrder::TopDown);
+    assert_eq!(packed, [top, bottom].concat());
+    let packed = pack(PixelLayout::Bgr888, RowOrder::BottomUp);
+    assert_eq!(packed, [bottom, top].concat());
+
+    // Rows of odd width aren't padded.
+    let odd = factory.new_image(3, 2, ColorType::Rgb8, [0; 4]).unwrap();
+    let packed = odd.pack_framebuffer(PixelLayout::Bgr888, RowOrder::TopDown);
+    assert_eq!(packed.unwrap().len(), 18);
+}
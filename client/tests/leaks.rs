@@ -0,0 +1,81 @@
+use image_sl_client::{
+    bindings, capability, ColorType, Exports, FunctionsV2, ImageError, ImageFactory, InitConfig,
+    LayerProps, Version,
+};
+use std::os::raw::c_char;
+
+// Links library statically, so tests don't depend on location of shared library.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn factory() -> ImageFactory {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    unsafe { ImageFactory::from_exports(exports) }.expect("library is linked")
+}
+
+/// Returns table of library functions, for tests leaking images client would destroy.
+fn table() -> FunctionsV2 {
+    let mut table = std::mem::MaybeUninit::<FunctionsV2>::uninit();
+    unsafe {
+        assert_eq!(
+            functions_ex(bindings::ABI_VERSION, table.as_mut_ptr()),
+            ImageError::NoError
+        );
+        table.assume_init()
+    }
+}
+
+// The only test of this file, as it counts images of the whole process.
+#[test]
+fn live_images_are_counted_and_leaks_listed() {
+    let factory = factory();
+    let count = factory.live_handle_count();
+    let image = factory
+        .new_image(2, 2, ColorType::Rgba8, [1, 2, 3, 255])
+        .unwrap();
+    let copy = image.try_clone().unwrap();
+    assert_eq!(factory.live_handle_count(), count + 2);
+    drop(copy);
+    assert_eq!(factory.live_handle_count(), count + 1);
+
+    // Images added to documents are counted as their layers.
+    let mut document = factory.new_document(2, 2).unwrap();
+    document.add_layer(image, &LayerProps::default()).unwrap();
+    assert_eq!(factory.live_handle_count(), count + 1);
+    drop(document);
+    assert_eq!(factory.live_handle_count(), count);
+
+    let f = table().functions;
+    let mut leaked = unsafe { bindings::ImageHandle::new_null() };
+    let color_type = ColorType::Rgba8 as u32;
+    let err = unsafe { (f.new)(2, 2, color_type, 0x0102_03ff, &mut leaked) };
+    assert_eq!(err, ImageError::NoError);
+    assert_eq!(factory.live_handle_count(), count + 1);
+    let leaks = factory.leaks();
+    let (_, origin) = leaks
+        .iter()
+        .find(|(address, _)| *address == leaked.address())
+        .expect("leaked image is listed");
+    let backtrace = factory.capabilities() & capability::BACKTRACE != 0;
+    assert_eq!(!origin.is_empty(), backtrace);
+
+    unsafe { (f.destroy_image)(leaked) };
+    assert_eq!(factory.live_handle_count(), count);
+    let leaks = factory.leaks();
+    assert!(leaks
+        .iter()
+        .all(|(address, _)| *address != leaked.address()));
+}
@@ -0,0 +1,68 @@
+use image_sl_client::{
+    ColorType, Exports, FunctionsV2, ImageError, ImageFactory, InitConfig, Version,
+};
+use std::os::raw::c_char;
+
+// Links library statically, so tests don't depend on location of shared library.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn factory() -> ImageFactory {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    unsafe { ImageFactory::from_exports(exports) }.expect("library is linked")
+}
+
+// The only test of this file, as it changes buffer pool of the whole process.
+#[test]
+fn pool_keeps_buffers_up_to_limit() {
+    let factory = factory();
+    let image = factory
+        .new_image(64, 64, ColorType::Rgba8, [200, 100, 50, 255])
+        .unwrap();
+    let expected = image.blur(2.0).unwrap();
+    assert_eq!(factory.pool_trim(), 0);
+
+    factory.set_pool_limit(1 << 20);
+    assert_eq!(
+        image
+            .blur(2.0)
+            .unwrap()
+            .differing_pixels(&expected, 0)
+            .unwrap(),
+        0
+    );
+    let kept = factory.pool_trim();
+    assert!(kept > 0 && kept <= 1 << 20, "{}", kept);
+    assert_eq!(factory.pool_trim(), 0);
+
+    // Buffers are reused by the next operations, which give them back.
+    for _ in 0..3 {
+        let blurred = image.blur(2.0).unwrap();
+        assert_eq!(blurred.differing_pixels(&expected, 0).unwrap(), 0);
+    }
+    assert_eq!(factory.pool_trim(), kept);
+
+    // Buffers larger than limit are freed.
+    factory.set_pool_limit(16);
+    image.blur(2.0).unwrap();
+    assert_eq!(factory.pool_trim(), 0);
+
+    factory.set_pool_limit(1 << 20);
+    image.resize_with_progress(32, 32, |_| true).unwrap();
+    // Disabling pool frees its buffers.
+    factory.set_pool_limit(0);
+    assert_eq!(factory.pool_trim(), 0);
+}
@@ -0,0 +1,83 @@
+use image_sl_client::{
+    ColorType, Exports, FunctionsV2, ImageError, ImageFactory, InitConfig, Job, JobStatus, Version,
+};
+use std::os::raw::c_char;
+
+// Links library statically, so tests don't depend on location of shared library.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn factory() -> ImageFactory {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    unsafe { ImageFactory::from_exports(exports) }.expect("library is linked")
+}
+
+fn finished(jobs: &[Job]) -> usize {
+    let statuses = jobs.iter().map(Job::status);
+    statuses.filter(|s| *s == JobStatus::Finished).count()
+}
+
+// The only test of this file, as it changes worker threads of the whole process.
+#[test]
+fn queued_jobs_start_by_priority_within_limits() {
+    let factory = factory();
+    assert!(factory.job_queue(0).is_err());
+    // Jobs run one by one on single background thread, so order they start in is observable.
+    factory.set_thread_count(1);
+    let image = factory
+        .new_image(256, 256, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    let blur = r#"{"sigma": 4}"#;
+
+    // Interactive job isn't queued behind batch jobs waiting for their limit.
+    let batch = factory.job_queue(1).unwrap();
+    let interactive = factory.job_queue(1).unwrap();
+    let batch_jobs: Vec<_> = (0..4)
+        .map(|_| batch.submit(&image, "blur", blur, 0).unwrap())
+        .collect();
+    let opened = interactive.submit_open("../data/logo.jpg", 0).unwrap();
+    assert!(opened.result().unwrap().is_some());
+    assert!(finished(&batch_jobs) <= 2);
+    // Destroyed queue still runs its waiting jobs.
+    drop(batch);
+    for job in &batch_jobs {
+        assert!(job.result().unwrap().is_some());
+    }
+
+    // Waiting job of higher priority starts first.
+    let queue = factory.job_queue(1).unwrap();
+    let first = queue.submit(&image, "blur", blur, 0).unwrap();
+    let low = queue.submit(&image, "blur", blur, 0).unwrap();
+    let high = queue.submit(&image, "blur", blur, 5).unwrap();
+    assert!(high.result().unwrap().is_some());
+    assert_eq!(first.status(), JobStatus::Finished);
+    assert_eq!(low.status(), JobStatus::Running);
+    assert!(low.result().unwrap().is_some());
+
+    // Waiting job is cancelled before it starts.
+    let token = factory.cancel_token().unwrap();
+    let running = queue.submit(&image, "blur", blur, 0).unwrap();
+    let waiting = queue
+        .submit_with_token(&image, "blur", blur, 0, &token)
+        .unwrap();
+    token.cancel();
+    match waiting.result() {
+        Err(e) => assert_eq!(e.code(), Some(ImageError::Cancelled)),
+        Ok(_) => panic!("cancelled job succeeded"),
+    }
+    assert!(running.result().unwrap().is_some());
+    factory.set_thread_count(0);
+}
@@ -0,0 +1,48 @@
+use image_sl_client::{ColorType, Error, ImageFactory, LIB_FILE_NAME};
+
+fn factory() -> ImageFactory {
+    // Cargo builds shared library of `image_sl` dev-dependency next to tests.
+    let exe = std::env::current_exe().unwrap();
+    ImageFactory::with_path(exe.with_file_name(LIB_FILE_NAME)).unwrap()
+}
+
+#[test]
+fn images_outlive_reload() {
+    let mut factory = factory();
+    let old = factory
+        .new_image(4, 3, ColorType::Rgb16, [10, 20, 30, 255])
+        .unwrap();
+    factory.reload().unwrap();
+
+    assert_eq!(old.pixel(3, 2).unwrap(), [10, 20, 30, 255]);
+    let mut new = factory.new_image(4, 3, ColorType::Rgba8, [0; 4]).unwrap();
+    match old.blur_into(&mut new, 1.0) {
+        Err(Error::ForeignObject) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let migrated = factory.migrate(&old).unwrap();
+    assert_eq!(migrated.info().unwrap().color_type, Some(ColorType::Rgb16));
+    assert_eq!(migrated.pixel(3, 2).unwrap(), [10, 20, 30, 255]);
+    let mut resized = factory.new_image(2, 2, ColorType::Rgb16, [0; 4]).unwrap();
+    migrated.resize_into(&mut resized).unwrap();
+    factory.migrate(&migrated).unwrap();
+}
+
+#[test]
+fn reload_keeps_library_on_failure() {
+    let dir = std::env::temp_dir().join(format!("image_sl_reload_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(LIB_FILE_NAME);
+    let exe = std::env::current_exe().unwrap();
+    std::fs::copy(exe.with_file_name(LIB_FILE_NAME), &path).unwrap();
+
+    let mut factory = ImageFactory::with_path(&path).unwrap();
+    // Loaded library is replaced, not overwritten, as linkers do.
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, b"not a library").unwrap();
+    assert!(matches!(factory.reload(), Err(Error::Load(_))));
+    std::fs::remove_dir_all(&dir).unwrap();
+    factory.new_image(1, 1, ColorType::L8, [0; 4]).unwrap();
+    assert!(matches!(factory.reload(), Err(Error::Io(_))));
+}
@@ -0,0 +1,80 @@
+use image_sl_client::{
+    ColorType, Error, Exports, FunctionsV2, ImageError, ImageFactory, InitConfig, Version,
+};
+use std::os::raw::c_char;
+
+// Links library statically, so tests don't depend on location of shared library.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn factory() -> ImageFactory {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    unsafe { ImageFactory::from_exports(exports) }.expect("library is linked")
+}
+
+/// Returns code of error of failed `result`, `None` if it succeeded.
+fn error_code<T>(result: Result<T, Error>) -> Option<ImageError> {
+    result.err().and_then(|e| e.code())
+}
+
+// The only test of this file, as it changes handle registry of the whole process.
+#[test]
+fn detached_image_is_attached_by_another_thread() {
+    let factory = factory();
+    factory.set_debug_handles(true);
+    let image = factory
+        .new_image(2, 2, ColorType::Rgba8, [1, 2, 3, 255])
+        .unwrap();
+
+    // Image is owned by thread which created it until it is detached.
+    let image = std::thread::spawn(move || {
+        assert_eq!(
+            error_code(image.pixel(0, 0)),
+            Some(ImageError::InvalidHandle)
+        );
+        assert_eq!(
+            error_code(image.detach_thread()),
+            Some(ImageError::InvalidHandle)
+        );
+        assert_eq!(
+            error_code(image.attach_thread()),
+            Some(ImageError::InvalidHandle)
+        );
+        image
+    })
+    .join()
+    .unwrap();
+    assert_eq!(image.pixel(0, 0).unwrap(), [1, 2, 3, 255]);
+
+    image.detach_thread().unwrap();
+    assert_eq!(
+        error_code(image.pixel(0, 0)),
+        Some(ImageError::InvalidHandle)
+    );
+    let image = std::thread::spawn(move || {
+        image.attach_thread().unwrap();
+        let mut image = image;
+        image.set_pixel(0, 0, [9, 9, 9, 255]).unwrap();
+        image.detach_thread().unwrap();
+        image
+    })
+    .join()
+    .unwrap();
+    image.attach_thread().unwrap();
+    assert_eq!(image.pixel(0, 0).unwrap(), [9, 9, 9, 255]);
+    drop(image);
+    factory.set_debug_handles(false);
+}
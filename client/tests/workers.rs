@@ -0,0 +1,65 @@
+use image_sl_client::{
+    ColorType, Exports, FunctionsV2, ImageError, ImageFactory, InitConfig, Version,
+};
+use std::os::raw::c_char;
+
+// Links library statically, so tests don't depend on location of shared library.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn factory() -> ImageFactory {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    unsafe { ImageFactory::from_exports(exports) }.expect("library is linked")
+}
+
+// The only test of this file, as it changes worker threads of the whole process.
+#[test]
+fn thread_count_limits_worker_threads() {
+    let factory = factory();
+    let mut image = factory
+        .new_image(64, 400, ColorType::Rgba8, [0, 0, 0, 255])
+        .unwrap();
+    for y in 0..400 {
+        image.set_pixel(y % 64, y, [255, 128, 64, 255]).unwrap();
+    }
+    // Returns threads other than calling one, which reported progress of blur, and blurred
+    // image.
+    let blur = || {
+        let mut threads = std::collections::HashSet::new();
+        let blurred = image
+            .blur_with_progress(2.0, |_| {
+                threads.insert(std::thread::current().id());
+                true
+            })
+            .unwrap();
+        threads.remove(&std::thread::current().id());
+        (threads, blurred)
+    };
+
+    factory.set_thread_count(1);
+    let (threads, expected) = blur();
+    assert!(threads.is_empty(), "{:?}", threads);
+
+    factory.set_thread_count(3);
+    let (threads, blurred) = blur();
+    assert!((1..=3).contains(&threads.len()), "{:?}", threads);
+    assert_eq!(blurred.differing_pixels(&expected, 0).unwrap(), 0);
+
+    factory.set_thread_count(0);
+    let (threads, blurred) = blur();
+    assert!(!threads.is_empty());
+    assert_eq!(blurred.differing_pixels(&expected, 0).unwrap(), 0);
+}
@@ -0,0 +1,23 @@
+use image_sl::api::Image;
+use image_sl::ImageFormat;
+use std::error::Error;
+
+// Calls library as ordinary Rust dependency, without loading shared library.
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut image = Image::open("data/logo.jpg")?;
+    let (width, height) = image.dimensions();
+    println!("Opened {}x{} {:?} image", width, height, image.color_type());
+
+    let thumbnail = image.crop(0, 0, width / 2, height / 2)?.resize(64, 64)?;
+    image.mirror()?;
+    let png = image.encode(ImageFormat::Png)?;
+    let decoded = Image::from_memory(&png)?;
+    assert_eq!(decoded.pixel(0, 0)?, image.pixel(0, 0)?);
+
+    decoded.blur(10.)?.save("data/blurred_api.png")?;
+    match thumbnail.blur(-1.) {
+        Err(e) => println!("Negative blur is rejected: {}", e),
+        Ok(_) => unreachable!("negative blur is applied"),
+    }
+    Ok(())
+}
@@ -0,0 +1,12 @@
+/// Compiles C host into static library called by `main.rs`, without its own `main`.
+fn main() {
+    cc::Build::new()
+        .file("use_lib_c.c")
+        .define("USE_LIB_C_NO_MAIN", None)
+        .warnings(true)
+        .compile("use_lib_c");
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux") {
+        println!("cargo:rustc-link-lib=dl");
+    }
+    println!("cargo:rerun-if-changed=use_lib_c.c");
+}
@@ -0,0 +1,39 @@
+//! Runs C host of image_sl compiled from `use_lib_c.c`.
+
+use std::ffi::{CString, NulError};
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+
+extern "C" {
+    fn use_lib_c(
+        lib_path: *const c_char,
+        input: *const c_char,
+        sigma: f32,
+        blurred: *const c_char,
+        mirrored: *const c_char,
+    ) -> c_int;
+}
+
+/// Opens image at `input` with library at `lib_path` from C, saves it blurred with `sigma` to
+/// `blurred` and mirrored to `mirrored`. Returns exit code of C host, `0` on success.
+pub fn run(
+    lib_path: &Path,
+    input: &Path,
+    sigma: f32,
+    blurred: &Path,
+    mirrored: &Path,
+) -> Result<i32, NulError> {
+    let c_path = |path: &Path| CString::new(path.to_string_lossy().into_owned());
+    let (lib_path, input) = (c_path(lib_path)?, c_path(input)?);
+    let (blurred, mirrored) = (c_path(blurred)?, c_path(mirrored)?);
+    let code = unsafe {
+        use_lib_c(
+            lib_path.as_ptr(),
+            input.as_ptr(),
+            sigma,
+            blurred.as_ptr(),
+            mirrored.as_ptr(),
+        )
+    };
+    Ok(code)
+}
@@ -0,0 +1,23 @@
+use std::path::{Path, PathBuf};
+
+/// Loads library built by `cargo build` and processes `data/logo.jpg` with it from C.
+fn main() {
+    let file_name = match std::env::consts::OS {
+        "windows" => "image_sl.dll",
+        "macos" => "libimage_sl.dylib",
+        _ => "libimage_sl.so",
+    };
+    let lib_path = std::env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("target/debug").join(file_name));
+    let code = use_lib_c::run(
+        &lib_path,
+        Path::new("data/logo.jpg"),
+        10.0,
+        Path::new("data/blurred_c.png"),
+        Path::new("data/mirrored_c.png"),
+    )
+    .expect("paths have no null bytes");
+    std::process::exit(code);
+}
@@ -0,0 +1,25 @@
+#[test]
+fn c_host_processes_image() {
+    // Cargo builds shared library of `image_sl` dev-dependency next to tests.
+    let exe = std::env::current_exe().unwrap();
+    let file_name = match std::env::consts::OS {
+        "windows" => "image_sl.dll",
+        "macos" => "libimage_sl.dylib",
+        _ => "libimage_sl.so",
+    };
+    let dir = std::env::temp_dir().join(format!("image_sl_c_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../../data/logo.jpg");
+    let code = use_lib_c::run(
+        &exe.with_file_name(file_name),
+        &input,
+        1.0,
+        &dir.join("blurred.png"),
+        &dir.join("mirrored.png"),
+    )
+    .unwrap();
+    assert_eq!(code, 0);
+    assert!(dir.join("blurred.png").exists());
+    assert!(dir.join("mirrored.png").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,122 @@
+/* C host of image_sl: loads shared library at runtime, gets functions table of ABI version 1
+ * from `functions_ex` and blurs and mirrors image with it. Declarations are written by hand
+ * for the functions used here, so struct layout of the table is checked from C side. Hosts
+ * needing more functions include header generated with `c-header` feature instead.
+ *
+ * Standalone build: cc use_lib_c.c -o use_lib_c -ldl */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#ifdef _WIN32
+#include <windows.h>
+#else
+#include <dlfcn.h>
+#endif
+
+typedef uint32_t ImageError;
+typedef void *ImageHandle;
+
+#define IMAGE_ERROR_NO_ERROR 0
+
+/* Table of functions of ABI version 1, the first 88 fields of `FunctionsBlock`. */
+typedef struct {
+    size_t size;
+    ImageError (*open_image)(const char *path, ImageHandle *handle);
+    ImageError (*save_image)(const char *path, ImageHandle handle);
+    void (*destroy_image)(ImageHandle handle);
+    ImageHandle (*blur_image)(ImageHandle handle, float sigma);
+    void (*mirror_image)(ImageHandle handle);
+    void (*unused_5_46[42])(void);
+    size_t (*last_error_message)(char *buffer, size_t len);
+    void (*unused_48_78[31])(void);
+    ImageError (*blur_image2)(ImageHandle handle, float sigma, ImageHandle *blurred);
+    ImageError (*mirror_image2)(ImageHandle handle);
+    void (*unused_81_87[7])(void);
+} FunctionsV1;
+
+typedef struct {
+    uint32_t abi_version;
+    size_t size;
+    FunctionsV1 functions;
+} FunctionsV2;
+
+typedef ImageError (*FunctionsExFn)(uint32_t requested_abi, FunctionsV2 *out);
+
+static void *load_symbol(const char *lib_path, const char *name) {
+#ifdef _WIN32
+    HMODULE lib = LoadLibraryA(lib_path);
+    return lib ? (void *)GetProcAddress(lib, name) : NULL;
+#else
+    void *lib = dlopen(lib_path, RTLD_NOW | RTLD_LOCAL);
+    return lib ? dlsym(lib, name) : NULL;
+#endif
+}
+
+/* Prints `what` failed with `err` and the last error message of library. */
+static int report(const FunctionsV1 *f, const char *what, ImageError err) {
+    char message[512] = "";
+    f->last_error_message(message, sizeof(message));
+    fprintf(stderr, "%s failed with error %u: %s\n", what, (unsigned)err, message);
+    return 1;
+}
+
+/* Opens image at `input`, saves it blurred with `sigma` to `blurred` and mirrored to `mirrored`
+ * with library at `lib_path`. Returns 0 on success. */
+int use_lib_c(const char *lib_path, const char *input, float sigma, const char *blurred,
+              const char *mirrored) {
+    FunctionsExFn functions_ex;
+    /* Function pointer is copied from object pointer as POSIX suggests for `dlsym`. */
+    void *symbol = load_symbol(lib_path, "functions_ex");
+    memcpy(&functions_ex, &symbol, sizeof(functions_ex));
+    if (!functions_ex) {
+        fprintf(stderr, "can't load functions_ex from %s\n", lib_path);
+        return 1;
+    }
+
+    FunctionsV2 table;
+    memset(&table, 0, sizeof(table));
+    ImageError err = functions_ex(1, &table);
+    if (err != IMAGE_ERROR_NO_ERROR) {
+        fprintf(stderr, "functions_ex failed with error %u\n", (unsigned)err);
+        return 1;
+    }
+    if (table.abi_version != 1 || table.size != sizeof(table) ||
+        table.functions.size != sizeof(table.functions)) {
+        fprintf(stderr, "layout of FunctionsV2 of ABI version 1 doesn't match: %zu != %zu\n",
+                table.size, sizeof(table));
+        return 1;
+    }
+    const FunctionsV1 *f = &table.functions;
+
+    ImageHandle image = NULL;
+    err = f->open_image(input, &image);
+    if (err != IMAGE_ERROR_NO_ERROR)
+        return report(f, "open_image", err);
+
+    ImageHandle blurred_image = NULL;
+    err = f->blur_image2(image, sigma, &blurred_image);
+    if (err == IMAGE_ERROR_NO_ERROR) {
+        err = f->save_image(blurred, blurred_image);
+        f->destroy_image(blurred_image);
+    }
+    if (err == IMAGE_ERROR_NO_ERROR)
+        err = f->mirror_image2(image);
+    if (err == IMAGE_ERROR_NO_ERROR)
+        err = f->save_image(mirrored, image);
+    f->destroy_image(image);
+    if (err != IMAGE_ERROR_NO_ERROR)
+        return report(f, "processing", err);
+    return 0;
+}
+
+#ifndef USE_LIB_C_NO_MAIN
+int main(int argc, char **argv) {
+    if (argc != 5) {
+        fprintf(stderr, "usage: %s <library> <input> <blurred> <mirrored>\n", argv[0]);
+        return 2;
+    }
+    return use_lib_c(argv[1], argv[2], 10.0f, argv[3], argv[4]);
+}
+#endif
@@ -0,0 +1,30 @@
+use image_sl_client::{Exports, FunctionsV2, ImageError, ImageFactory, InitConfig, Version};
+use std::error::Error;
+use std::os::raw::c_char;
+
+// Links library statically, so its exported functions are resolved by linker.
+use image_sl as _;
+
+extern "C" {
+    fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError;
+    fn version() -> Version;
+    fn image_sl_init(config: *const InitConfig) -> ImageError;
+    fn image_sl_shutdown();
+    fn image_sl_dump_trace(path: *const c_char) -> ImageError;
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let exports = Exports {
+        functions_ex,
+        version,
+        init: image_sl_init,
+        shutdown: image_sl_shutdown,
+        dump_trace: image_sl_dump_trace,
+    };
+    let image_factory = unsafe { ImageFactory::from_exports(exports)? };
+    println!("Linked image_sl {}", image_factory.version().build);
+
+    let image = image_factory.open_image("data/logo.jpg")?;
+    image.blur(10.)?.save("data/blurred_static.png")?;
+    Ok(())
+}
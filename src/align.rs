@@ -0,0 +1,428 @@
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+
+/// Maximum side of images alignment is estimated on, larger ones are downscaled.
+const MAX_SIDE: u32 = 512;
+/// Minimum side of the coarsest pyramid level refined by ECC.
+const MIN_SIDE: u32 = 32;
+/// Maximum number of ECC iterations per pyramid level.
+const ITERATIONS: usize = 50;
+/// Change of affine parameters which stops ECC iterations.
+const EPSILON: f64 = 1e-4;
+
+/// Affine transform mapping `(x, y)` to `(m[0] * x + m[1] * y + m[2], m[3] * x + m[4] * y + m[5])`.
+pub type Affine = [f64; 6];
+
+/// Applies `transform` to point `(x, y)`.
+pub fn apply(transform: &Affine, x: f64, y: f64) -> (f64, f64) {
+    let m = transform;
+    (m[0] * x + m[1] * y + m[2], m[3] * x + m[4] * y + m[5])
+}
+
+/// Grayscale pixels in `[0, 1]`.
+struct Plane {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl Plane {
+    fn from_image(image: &DynamicImage) -> Self {
+        let luma = image.to_luma16();
+        Self {
+            width: luma.width(),
+            height: luma.height(),
+            values: luma.iter().map(|&v| v as f32 / u16::MAX as f32).collect(),
+        }
+    }
+
+    fn at(&self, x: u32, y: u32) -> f32 {
+        self.values[(y * self.width + x) as usize]
+    }
+
+    /// Returns plane of half size averaging 2 x 2 blocks.
+    fn half(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut values = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, y0) = (2 * x, 2 * y);
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                let sum = self.at(x0, y0) + self.at(x1, y0) + self.at(x0, y1) + self.at(x1, y1);
+                values.push(sum / 4.0);
+            }
+        }
+        Self {
+            width,
+            height,
+            values,
+        }
+    }
+
+    /// Returns bilinearly interpolated value at `(x, y)`, or `None` outside of plane.
+    fn sample(&self, x: f64, y: f64) -> Option<f32> {
+        let (max_x, max_y) = ((self.width - 1) as f64, (self.height - 1) as f64);
+        if !(0.0..=max_x).contains(&x) || !(0.0..=max_y).contains(&y) {
+            return None;
+        }
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = ((x - x0 as f64) as f32, (y - y0 as f64) as f32);
+        let top = self.at(x0, y0) + (self.at(x1, y0) - self.at(x0, y0)) * fx;
+        let bottom = self.at(x0, y1) + (self.at(x1, y1) - self.at(x0, y1)) * fx;
+        Some(top + (bottom - top) * fy)
+    }
+}
+
+/// Returns number of halvings which make both images fit `MAX_SIDE`.
+fn halvings(a: &DynamicImage, b: &DynamicImage) -> u32 {
+    let side = a.width().max(a.height()).max(b.width()).max(b.height());
+    let mut halvings = 0;
+    while side >> halvings > MAX_SIDE {
+        halvings += 1;
+    }
+    halvings
+}
+
+/// Returns pyramid of plane of `image` halved `halvings` times, from the finest level to the
+/// coarsest one of at most 4 levels.
+fn pyramid(image: &DynamicImage, halvings: u32) -> Vec<Plane> {
+    let mut finest = Plane::from_image(image);
+    for _ in 0..halvings {
+        finest = finest.half();
+    }
+    let mut planes = vec![finest];
+    loop {
+        let last = &planes[planes.len() - 1];
+        if last.width.min(last.height) / 2 < MIN_SIDE || planes.len() == 4 {
+            break;
+        }
+        let half = last.half();
+        planes.push(half);
+    }
+    planes
+}
+
+/// Estimates translation mapping points of `reference` to the same points of `moving` by phase
+/// correlation. Returns transform and height of normalized correlation peak in `[0, 1]`.
+pub fn translation(reference: &DynamicImage, moving: &DynamicImage) -> (Affine, f32) {
+    let halvings = halvings(reference, moving);
+    let scale = 1.0 / (1u32 << halvings) as f64;
+    let reference = &pyramid(reference, halvings)[0];
+    let moving = &pyramid(moving, halvings)[0];
+
+    let width = reference.width.max(moving.width).next_power_of_two() as usize;
+    let height = reference.height.max(moving.height).next_power_of_two() as usize;
+    let mut a = spectrum(reference, width, height);
+    let b = spectrum(moving, width, height);
+
+    // Normalized cross-power spectrum, whose inverse peaks at shift of moving image.
+    for (a, b) in a.iter_mut().zip(&b) {
+        let product = (b.0 * a.0 + b.1 * a.1, b.1 * a.0 - b.0 * a.1);
+        let norm = product.0.hypot(product.1);
+        *a = match norm > 1e-12 {
+            true => (product.0 / norm, product.1 / norm),
+            false => (0.0, 0.0),
+        };
+    }
+    fft_2d(&mut a, width, height, true);
+
+    let (peak, _) =
+        a.iter().enumerate().fold(
+            (0, f64::MIN),
+            |best, (i, v)| if v.0 > best.1 { (i, v.0) } else { best },
+        );
+    let (px, py) = (peak % width, peak / width);
+    let at = |x: usize, y: usize| a[(y % height) * width + x % width].0;
+    // Parabola through peak and its neighbors gives subpixel position.
+    let refine = |left: f64, center: f64, right: f64| {
+        let denominator = left - 2.0 * center + right;
+        match denominator.abs() > 1e-12 {
+            true => (0.5 * (left - right) / denominator).clamp(-0.5, 0.5),
+            false => 0.0,
+        }
+    };
+    let center = at(px, py);
+    let fx = refine(at(px + width - 1, py), center, at(px + 1, py));
+    let fy = refine(at(px, py + height - 1), center, at(px, py + 1));
+    let wrap = |p: usize, size: usize| match p > size / 2 {
+        true => p as f64 - size as f64,
+        false => p as f64,
+    };
+    let dx = (wrap(px, width) + fx) / scale;
+    let dy = (wrap(py, height) + fy) / scale;
+    let transform = [1.0, 0.0, dx, 0.0, 1.0, dy];
+    (transform, center.clamp(0.0, 1.0) as f32)
+}
+
+/// Returns spectrum of `plane` with Hann window, padded with zeros to `width` x `height`.
+fn spectrum(plane: &Plane, width: usize, height: usize) -> Vec<(f64, f64)> {
+    let mut data = vec![(0.0, 0.0); width * height];
+    let hann = |i: u32, size: u32| {
+        let phase = 2.0 * std::f64::consts::PI * i as f64 / (size.max(2) - 1) as f64;
+        0.5 - 0.5 * phase.cos()
+    };
+    let mean = plane.values.iter().map(|&v| v as f64).sum::<f64>() / plane.values.len() as f64;
+    for y in 0..plane.height {
+        for x in 0..plane.width {
+            let window = hann(x, plane.width) * hann(y, plane.height);
+            let value = (plane.at(x, y) as f64 - mean) * window;
+            data[y as usize * width + x as usize] = (value, 0.0);
+        }
+    }
+    fft_2d(&mut data, width, height, false);
+    data
+}
+
+/// Transforms `data` of `width` x `height` power-of-two size in place by rows and columns.
+fn fft_2d(data: &mut [(f64, f64)], width: usize, height: usize, inverse: bool) {
+    for row in data.chunks_mut(width) {
+        fft(row, inverse);
+    }
+    let mut column = vec![(0.0, 0.0); height];
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = data[y * width + x];
+        }
+        fft(&mut column, inverse);
+        for (y, value) in column.iter().enumerate() {
+            data[y * width + x] = *value;
+        }
+    }
+}
+
+/// Iterative radix-2 FFT of power-of-two sized `data`. Inverse transform is scaled by `1 / n`.
+fn fft(data: &mut [(f64, f64)], inverse: bool) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        let step = (angle.cos(), angle.sin());
+        for chunk in data.chunks_mut(len) {
+            let mut w = (1.0, 0.0);
+            let (low, high) = chunk.split_at_mut(len / 2);
+            for (a, b) in low.iter_mut().zip(high) {
+                let t = (b.0 * w.0 - b.1 * w.1, b.0 * w.1 + b.1 * w.0);
+                *b = (a.0 - t.0, a.1 - t.1);
+                *a = (a.0 + t.0, a.1 + t.1);
+                w = (w.0 * step.0 - w.1 * step.1, w.0 * step.1 + w.1 * step.0);
+            }
+        }
+        len <<= 1;
+    }
+    if inverse {
+        data.iter_mut()
+            .for_each(|v| *v = (v.0 / n as f64, v.1 / n as f64));
+    }
+}
+
+/// Estimates affine transform mapping points of `reference` to the same points of `moving` by
+/// maximizing enhanced correlation coefficient coarse to fine, starting from phase correlation
+/// translation. Returns transform and correlation coefficient in `[-1, 1]`, or `None` if
+/// iterations diverged.
+pub fn affine(reference: &DynamicImage, moving: &DynamicImage) -> Option<(Affine, f32)> {
+    let (initial, _) = translation(reference, moving);
+    let halvings = halvings(reference, moving);
+    let scale = 1.0 / (1u32 << halvings) as f64;
+    let references = pyramid(reference, halvings);
+    let movings = pyramid(moving, halvings);
+    let levels = references.len().min(movings.len());
+
+    // Translation is expressed in pixels of the current level.
+    let factor = scale / (1u32 << (levels - 1)) as f64;
+    let mut transform = initial;
+    transform[2] *= factor;
+    transform[5] *= factor;
+    let mut correlation = 0.0;
+    for level in (0..levels).rev() {
+        let (t, c) = ecc(&references[level], &movings[level], transform)?;
+        transform = t;
+        correlation = c;
+        if level != 0 {
+            transform[2] *= 2.0;
+            transform[5] *= 2.0;
+        }
+    }
+    transform[2] /= scale;
+    transform[5] /= scale;
+    Some((transform, correlation))
+}
+
+/// Refines affine `transform` of `reference` to `moving` by forward additive ECC iterations.
+fn ecc(reference: &Plane, moving: &Plane, mut transform: Affine) -> Option<(Affine, f32)> {
+    let gradient = |plane: &Plane, x: f64, y: f64| {
+        let dx = plane.sample(x + 0.5, y)? - plane.sample(x - 0.5, y)?;
+        let dy = plane.sample(x, y + 0.5)? - plane.sample(x, y - 0.5)?;
+        Some((dx as f64, dy as f64))
+    };
+
+    let mut correlation = 0.0;
+    for _ in 0..ITERATIONS {
+        // Pixels of reference whose warped positions lie within moving image.
+        let mut samples = Vec::new();
+        for y in 0..reference.height {
+            for x in 0..reference.width {
+                let (u, v) = apply(&transform, x as f64, y as f64);
+                let (value, gradient) = match (moving.sample(u, v), gradient(moving, u, v)) {
+                    (Some(value), Some(gradient)) => (value, gradient),
+                    _ => continue,
+                };
+                samples.push((
+                    x as f64,
+                    y as f64,
+                    reference.at(x, y) as f64,
+                    value as f64,
+                    gradient,
+                ));
+            }
+        }
+        if samples.len() < 16 {
+            return None;
+        }
+
+        let n = samples.len() as f64;
+        let reference_mean = samples.iter().map(|s| s.2).sum::<f64>() / n;
+        let moving_mean = samples.iter().map(|s| s.3).sum::<f64>() / n;
+        let mut hessian = [[0.0; 6]; 6];
+        let (mut g_reference, mut g_moving) = ([0.0; 6], [0.0; 6]);
+        let (mut reference_norm, mut moving_norm, mut cross) = (0.0, 0.0, 0.0);
+        for &(x, y, r, m, (gx, gy)) in &samples {
+            let (r, m) = (r - reference_mean, m - moving_mean);
+            let jacobian = [gx * x, gx * y, gx, gy * x, gy * y, gy];
+            for i in 0..6 {
+                for j in 0..6 {
+                    hessian[i][j] += jacobian[i] * jacobian[j];
+                }
+                g_reference[i] += jacobian[i] * r;
+                g_moving[i] += jacobian[i] * m;
+            }
+            reference_norm += r * r;
+            moving_norm += m * m;
+            cross += r * m;
+        }
+        correlation = cross / (reference_norm.sqrt() * moving_norm.sqrt()).max(1e-12);
+
+        let inverse = invert(hessian)?;
+        let project = |a: &[f64; 6], b: &[f64; 6]| {
+            let mut sum = 0.0;
+            for i in 0..6 {
+                for j in 0..6 {
+                    sum += a[i] * inverse[i][j] * b[j];
+                }
+            }
+            sum
+        };
+        let numerator = moving_norm - project(&g_moving, &g_moving);
+        let denominator = cross - project(&g_reference, &g_moving);
+        if denominator <= 0.0 {
+            return None;
+        }
+        let lambda = numerator / denominator;
+
+        // Step solves for parameters minimizing error of scaled reference and warped moving.
+        let mut error = [0.0; 6];
+        for i in 0..6 {
+            error[i] = lambda * g_reference[i] - g_moving[i];
+        }
+        let mut change = 0.0;
+        for i in 0..6 {
+            let delta: f64 = (0..6).map(|j| inverse[i][j] * error[j]).sum();
+            transform[i] += delta;
+            change += delta * delta;
+        }
+        if !transform.iter().all(|v| v.is_finite()) {
+            return None;
+        }
+        if change.sqrt() < EPSILON {
+            break;
+        }
+    }
+    Some((transform, correlation as f32))
+}
+
+/// Returns inverse of `matrix` by Gauss-Jordan elimination, or `None` if it is singular.
+fn invert(mut matrix: [[f64; 6]; 6]) -> Option<[[f64; 6]; 6]> {
+    let mut inverse = [[0.0; 6]; 6];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    for column in 0..6 {
+        let pivot = (column..6)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+        let divisor = matrix[column][column];
+        for j in 0..6 {
+            matrix[column][j] /= divisor;
+            inverse[column][j] /= divisor;
+        }
+        for row in 0..6 {
+            if row == column {
+                continue;
+            }
+            let factor = matrix[row][column];
+            for j in 0..6 {
+                matrix[row][j] -= factor * matrix[column][j];
+                inverse[row][j] -= factor * inverse[column][j];
+            }
+        }
+    }
+    Some(inverse)
+}
+
+/// Returns `width` x `height` image whose pixel `(x, y)` is bilinearly interpolated pixel of
+/// `image` at `map(x, y)`, where pixel centers have integer coordinates. Pixels mapped outside of
+/// `image` are transparent. Result has 16-bit channels if `image` has more than 8-bit ones.
+pub fn warp<F>(image: &DynamicImage, width: u32, height: u32, map: F) -> DynamicImage
+where
+    F: Fn(f64, f64) -> Option<(f64, f64)>,
+{
+    let source = image.to_rgba16();
+    let (max_x, max_y) = ((source.width() - 1) as f64, (source.height() - 1) as f64);
+    let at = |x: u32, y: u32| source.get_pixel(x, y).0.map(|v| v as f64);
+    let mut warped = ImageBuffer::<Rgba<u16>, _>::new(width, height);
+    for (x, y, pixel) in warped.enumerate_pixels_mut() {
+        // Pixels cover half a pixel around their centers, so edge pixels extend to image border.
+        let inside = |u: f64, max: f64| (-0.5..=max + 0.5).contains(&u);
+        let (u, v) = match map(x as f64, y as f64) {
+            Some((u, v)) if inside(u, max_x) && inside(v, max_y) => {
+                (u.clamp(0.0, max_x), v.clamp(0.0, max_y))
+            }
+            _ => continue,
+        };
+        let (x0, y0) = (u.floor() as u32, v.floor() as u32);
+        let (x1, y1) = (
+            (x0 + 1).min(source.width() - 1),
+            (y0 + 1).min(source.height() - 1),
+        );
+        let (fx, fy) = (u - x0 as f64, v - y0 as f64);
+        let (a, b, c, d) = (at(x0, y0), at(x1, y0), at(x0, y1), at(x1, y1));
+        for channel in 0..4 {
+            let top = a[channel] + (b[channel] - a[channel]) * fx;
+            let bottom = c[channel] + (d[channel] - c[channel]) * fx;
+            pixel.0[channel] = (top + (bottom - top) * fy).round() as u16;
+        }
+    }
+
+    let warped = DynamicImage::ImageRgba16(warped);
+    match image.color().bytes_per_pixel() / image.color().channel_count() {
+        1 => DynamicImage::ImageRgba8(warped.to_rgba8()),
+        _ => warped,
+    }
+}
@@ -0,0 +1,478 @@
+use crate::Rect;
+use image::{ColorType, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+/// Number of histogram bins of absolute high-pass responses per channel.
+const BINS: usize = 1 << 16;
+/// Responses are divided by this before binning. Largest response is `16 * u16::MAX`.
+const BIN_WIDTH: u32 = 16;
+
+/// Estimates standard deviation of Gaussian noise of each RGBA channel of `image` in `[0, 1]`
+/// units of channel range. Uses median absolute deviation of Laplacian-difference high-pass,
+/// which mostly cancels image structure. Returns `None` if image is smaller than 3x3 pixels.
+pub fn estimate_noise(image: &DynamicImage) -> Option<[f64; 4]> {
+    let rgba = image.to_rgba16();
+    let (width, height) = rgba.dimensions();
+    if width < 3 || height < 3 {
+        return None;
+    }
+
+    let mut histograms = vec![vec![0u32; BINS]; 4];
+    let at = |x: u32, y: u32, c: usize| rgba.get_pixel(x, y).0[c] as i32;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            for (c, histogram) in histograms.iter_mut().enumerate() {
+                // Kernel [1 -2 1; -2 4 -2; 1 -2 1].
+                let corners = at(x - 1, y - 1, c)
+                    + at(x + 1, y - 1, c)
+                    + at(x - 1, y + 1, c)
+                    + at(x + 1, y + 1, c);
+                let edges = at(x, y - 1, c) + at(x - 1, y, c) + at(x + 1, y, c) + at(x, y + 1, c);
+                let response = corners - 2 * edges + 4 * at(x, y, c);
+                let bin = (response.unsigned_abs() / BIN_WIDTH) as usize;
+                histogram[bin.min(BINS - 1)] += 1;
+            }
+        }
+    }
+
+    let count = (width as u64 - 2) * (height as u64 - 2);
+    let mut sigmas = [0.0; 4];
+    for (sigma, histogram) in sigmas.iter_mut().zip(&histograms) {
+        let median = median_bin(histogram, count) * BIN_WIDTH as f64;
+        // Kernel norm is 6 and MAD of normal distribution is 0.6745 sigma.
+        *sigma = median / (0.6745 * 6.0) / u16::MAX as f64;
+    }
+    Some(sigmas)
+}
+
+/// Estimates sharpness of `image` as standard deviation of Laplacian of its luma in `[0, 1]`
+/// units of channel range. Blurry images have lower values. Returns `None` if image is smaller
+/// than 3x3 pixels.
+pub fn estimate_sharpness(image: &DynamicImage) -> Option<f64> {
+    let luma = image.to_luma16();
+    let (width, height) = luma.dimensions();
+    if width < 3 || height < 3 {
+        return None;
+    }
+
+    let at = |x: u32, y: u32| luma.get_pixel(x, y).0[0] as f64 / u16::MAX as f64;
+    let mut sum = 0.0;
+    let mut sum_squares = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian =
+                at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y);
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+        }
+    }
+
+    let count = (width as f64 - 2.0) * (height as f64 - 2.0);
+    let mean = sum / count;
+    Some((sum_squares / count - mean * mean).max(0.0).sqrt())
+}
+
+/// Counts values of `channel` of pixels of `region` of `image` in `bins`, which cover the whole
+/// range of channel whatever bit depth of image is: bin of value `v` scaled to 16 bits is
+/// `v * bins.len() / 65536`. `channel` is less than channel count of image.
+pub fn histogram(image: &DynamicImage, region: Rect, channel: usize, bins: &mut [u32]) {
+    let mut counts = vec![0u64; bins.len()];
+    for_each_pixel(image, region, |pixel| {
+        counts[(pixel[channel] as usize * bins.len()) >> 16] += 1;
+    });
+    for (bin, count) in bins.iter_mut().zip(counts) {
+        *bin = count.min(u32::MAX as u64) as u32;
+    }
+}
+
+/// Statistics of channels of image region in `[0, 1]` units of channel range. Channels are in
+/// order of color type of image, entries past its channel count are zero.
+pub struct Summary {
+    pub min: [f64; 4],
+    pub max: [f64; 4],
+    pub mean: [f64; 4],
+    pub std_dev: [f64; 4],
+    /// Mean Rec. 709 luma of pixels, ignoring alpha.
+    pub luma: f64,
+}
+
+/// Computes minimum, maximum, mean and standard deviation of every channel and mean luma of
+/// pixels of `region` of `image`.
+pub fn summarize(image: &DynamicImage, region: Rect) -> Summary {
+    let luma_weights = match image.color() {
+        ColorType::L8 | ColorType::La8 | ColorType::L16 | ColorType::La16 => [1.0, 0.0, 0.0],
+        ColorType::Bgr8 | ColorType::Bgra8 => [0.0722, 0.7152, 0.2126],
+        _ => [0.2126, 0.7152, 0.0722],
+    };
+    let mut min = [u16::MAX; 4];
+    let mut max = [0; 4];
+    let mut sum = [0.0; 4];
+    let mut sum_squares = [0.0; 4];
+    let mut luma = 0.0;
+    for_each_pixel(image, region, |pixel| {
+        for (c, &v) in pixel.iter().enumerate() {
+            min[c] = min[c].min(v);
+            max[c] = max[c].max(v);
+            let v = v as f64 / u16::MAX as f64;
+            sum[c] += v;
+            sum_squares[c] += v * v;
+        }
+        let weighted = pixel.iter().zip(luma_weights).map(|(&v, w)| v as f64 * w);
+        luma += weighted.sum::<f64>() / u16::MAX as f64;
+    });
+
+    let channels = image.color().channel_count() as usize;
+    let count = region.width as f64 * region.height as f64;
+    let mut summary = Summary {
+        min: [0.0; 4],
+        max: [0.0; 4],
+        mean: [0.0; 4],
+        std_dev: [0.0; 4],
+        luma: 0.0,
+    };
+    if count == 0.0 {
+        return summary;
+    }
+    for c in 0..channels {
+        summary.min[c] = min[c] as f64 / u16::MAX as f64;
+        summary.max[c] = max[c] as f64 / u16::MAX as f64;
+        summary.mean[c] = sum[c] / count;
+        let variance = sum_squares[c] / count - summary.mean[c] * summary.mean[c];
+        summary.std_dev[c] = variance.max(0.0).sqrt();
+    }
+    summary.luma = luma / count;
+    summary
+}
+
+/// Pixels sampled by `dominant_colors` at most.
+const MAX_SAMPLES: u64 = 1 << 16;
+
+/// Finds at most `k` dominant colors of pixels of `region` of `image` by median cut and returns
+/// them as RGBA with fractions of pixels they represent, most common first. Pixels of large
+/// regions are sampled evenly and fully transparent pixels are ignored, so transparent image has
+/// no colors.
+pub fn dominant_colors(image: &DynamicImage, region: Rect, k: usize) -> Vec<([u8; 4], f32)> {
+    let color = image.color();
+    let count = region.width as u64 * region.height as u64;
+    let step = count.div_ceil(MAX_SAMPLES).max(1);
+    let mut samples = Vec::new();
+    let mut i = 0;
+    for_each_pixel(image, region, |pixel| {
+        if i % step == 0 {
+            let rgba = rgba(color, pixel);
+            if rgba[3] != 0 {
+                samples.push(rgba);
+            }
+        }
+        i += 1;
+    });
+
+    let total = samples.len() as f32;
+    let mut boxes = vec![samples];
+    while boxes.len() < k {
+        // Box of the widest channel range is split at median along that channel.
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (channel, range) = (0..3)
+                    .map(|c| {
+                        let values = b.iter().map(|p| p[c]);
+                        (c, values.clone().max().unwrap() - values.min().unwrap())
+                    })
+                    .max_by_key(|&(_, range)| range)
+                    .unwrap();
+                (i, channel, range)
+            })
+            .filter(|&(_, _, range)| range > 0)
+            .max_by_key(|&(_, _, range)| range);
+        let (i, channel, _) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+        let mut pixels = boxes.swap_remove(i);
+        pixels.sort_unstable_by_key(|p| p[channel]);
+        // Pixels of the same value stay in one box, so boxes of one color aren't split.
+        let median = pixels[pixels.len() / 2][channel];
+        let split = match pixels.partition_point(|p| p[channel] < median) {
+            0 => pixels.partition_point(|p| p[channel] <= median),
+            split => split,
+        };
+        let upper = pixels.split_off(split);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    let mut colors: Vec<_> = boxes
+        .into_iter()
+        .filter(|b| !b.is_empty())
+        .map(|b| {
+            let mut sum = [0u64; 4];
+            for p in &b {
+                for (s, &v) in sum.iter_mut().zip(p) {
+                    *s += v as u64;
+                }
+            }
+            let len = b.len() as u64;
+            let mean = sum.map(|s| ((s / len + 128) / 257) as u8);
+            (mean, b.len() as f32 / total)
+        })
+        .collect();
+    colors.sort_by(|a, b| b.1.total_cmp(&a.1));
+    colors
+}
+
+/// Returns average RGBA color of pixels of `region` of `image`. Colors are weighted by alpha,
+/// so transparent pixels don't darken average of partially transparent image, while alpha is
+/// plain mean. Average of fully transparent region is transparent black.
+pub fn average_color(image: &DynamicImage, region: Rect) -> [u8; 4] {
+    let color = image.color();
+    let mut sum = [0u64; 3];
+    let mut alpha_sum = 0u64;
+    for_each_pixel(image, region, |pixel| {
+        let [r, g, b, a] = rgba(color, pixel);
+        for (s, v) in sum.iter_mut().zip([r, g, b]) {
+            *s += v as u64 * a as u64;
+        }
+        alpha_sum += a as u64;
+    });
+
+    let count = region.width as u64 * region.height as u64;
+    if alpha_sum == 0 {
+        return [0; 4];
+    }
+    let [r, g, b] = sum.map(|s| ((s + alpha_sum / 2) / alpha_sum) as u16);
+    let a = ((alpha_sum + count / 2) / count) as u16;
+    [r, g, b, a].map(|v| ((v as u32 + 128) / 257) as u8)
+}
+
+/// Returns peak signal-to-noise ratio in decibels between `region_a` of image `a` and `region_b`
+/// of the same size of image `b`: `10 * log10(1 / mse)` of mean squared error of RGB channels in
+/// `[0, 1]` units. Alpha is ignored. Identical regions have infinite PSNR.
+pub fn psnr(a: &DynamicImage, region_a: Rect, b: &DynamicImage, region_b: Rect) -> f64 {
+    let a = rgba_pixels(a, region_a);
+    let b = rgba_pixels(b, region_b);
+    let mut sum = 0.0;
+    for (a, b) in a.iter().zip(&b) {
+        for c in 0..3 {
+            let difference = (a[c] as f64 - b[c] as f64) / u16::MAX as f64;
+            sum += difference * difference;
+        }
+    }
+    let mse = sum / (3 * a.len()) as f64;
+    -10.0 * mse.log10()
+}
+
+/// Returns image highlighting pixels of `region_a` of image `a` which differ from pixels of
+/// `region_b` of the same size of image `b` in red and number of such pixels. Pixels differ if
+/// any of their RGBA channels differs by more than `tolerance` in 16-bit units. The rest of
+/// pixels show luma of `a` faded towards white, so differences can be found in context.
+pub fn diff(
+    a: &DynamicImage,
+    region_a: Rect,
+    b: &DynamicImage,
+    region_b: Rect,
+    tolerance: u16,
+) -> (RgbaImage, u64) {
+    let a = rgba_pixels(a, region_a);
+    let b = rgba_pixels(b, region_b);
+    let mut count = 0;
+    let mut highlighted = RgbaImage::new(region_a.width, region_a.height);
+    for ((out, a), b) in highlighted.pixels_mut().zip(&a).zip(&b) {
+        let differs = a.iter().zip(b).any(|(a, b)| a.abs_diff(*b) > tolerance);
+        *out = match differs {
+            true => {
+                count += 1;
+                Rgba([255, 0, 0, 255])
+            }
+            false => {
+                let luma = (0.299 * a[0] as f64 + 0.587 * a[1] as f64 + 0.114 * a[2] as f64)
+                    * (a[3] as f64 / u16::MAX as f64);
+                let faded = 255.0 - (255.0 - luma / 257.0) * 0.2;
+                Rgba([faded as u8, faded as u8, faded as u8, 255])
+            }
+        };
+    }
+    (highlighted, count)
+}
+
+/// Returns bounds of content of `region` of `image` relative to `region`: the smallest rectangle
+/// containing all pixels whose RGBA channels differ from its top left pixel by more than
+/// `tolerance` in 16-bit units. Returns `None` if there are no such pixels, i.e. region is
+/// uniform.
+pub fn content_bounds(image: &DynamicImage, region: Rect, tolerance: u16) -> Option<Rect> {
+    let pixels = rgba_pixels(image, region);
+    let background = *pixels.first()?;
+    let differs = |p: &[u16; 4]| {
+        p.iter()
+            .zip(&background)
+            .any(|(a, b)| a.abs_diff(*b) > tolerance)
+    };
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (y, row) in pixels.chunks_exact(region.width as usize).enumerate() {
+        let first = match row.iter().position(differs) {
+            Some(first) => first as u32,
+            None => continue,
+        };
+        let last = row.iter().rposition(differs).unwrap_or_default() as u32;
+        left = left.min(first);
+        right = right.max(last);
+        top = top.min(y as u32);
+        bottom = y as u32;
+    }
+    if top == u32::MAX {
+        return None;
+    }
+    Some(Rect {
+        x: left,
+        y: top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    })
+}
+
+/// Side of Gaussian window SSIM statistics are computed in.
+const SSIM_WINDOW: usize = 11;
+/// Standard deviation of Gaussian window of SSIM.
+const SSIM_SIGMA: f64 = 1.5;
+
+/// Returns mean structural similarity index in `[-1, 1]` between luma of `region_a` of image `a`
+/// and `region_b` of the same size of image `b`, computed in 11x11 Gaussian windows with
+/// standard deviation 1.5 as proposed by Wang et al. Identical regions have SSIM 1. Alpha is
+/// ignored.
+pub fn ssim(a: &DynamicImage, region_a: Rect, b: &DynamicImage, region_b: Rect) -> f64 {
+    let luma = |pixels: Vec<[u16; 4]>| -> Vec<f64> {
+        let weights = [0.299, 0.587, 0.114];
+        let luma = |p: [u16; 4]| (0..3).map(|c| weights[c] * p[c] as f64).sum::<f64>();
+        pixels
+            .into_iter()
+            .map(|p| luma(p) / u16::MAX as f64)
+            .collect()
+    };
+    let x = luma(rgba_pixels(a, region_a));
+    let y = luma(rgba_pixels(b, region_b));
+    let (width, height) = (region_a.width as usize, region_a.height as usize);
+    let product = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).collect::<Vec<_>>();
+    let mu_x = gaussian_blur(&x, width, height);
+    let mu_y = gaussian_blur(&y, width, height);
+    let xx = gaussian_blur(&product(&x, &x), width, height);
+    let yy = gaussian_blur(&product(&y, &y), width, height);
+    let xy = gaussian_blur(&product(&x, &y), width, height);
+
+    // Constants keep ratios stable in flat windows, K1 = 0.01 and K2 = 0.03 of channel range.
+    let (c1, c2) = (0.01f64.powi(2), 0.03f64.powi(2));
+    let mut sum = 0.0;
+    for i in 0..x.len() {
+        let (mx, my) = (mu_x[i], mu_y[i]);
+        let (vx, vy, cov) = (xx[i] - mx * mx, yy[i] - my * my, xy[i] - mx * my);
+        sum +=
+            (2.0 * mx * my + c1) * (2.0 * cov + c2) / ((mx * mx + my * my + c1) * (vx + vy + c2));
+    }
+    sum / x.len() as f64
+}
+
+/// Returns `values` of `width` x `height` image blurred by normalized Gaussian window of SSIM.
+/// Edge values are repeated outside of image.
+fn gaussian_blur(values: &[f64], width: usize, height: usize) -> Vec<f64> {
+    let radius = SSIM_WINDOW as isize / 2;
+    let mut kernel: Vec<f64> = (-radius..=radius)
+        .map(|i| (-(i * i) as f64 / (2.0 * SSIM_SIGMA * SSIM_SIGMA)).exp())
+        .collect();
+    let total: f64 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= total);
+
+    let blur = |at: &dyn Fn(usize) -> f64, i: usize, len: usize| {
+        let taps = kernel.iter().zip(-radius..);
+        let clamp = |j: isize| (i as isize + j).clamp(0, len as isize - 1) as usize;
+        taps.map(|(k, j)| k * at(clamp(j))).sum::<f64>()
+    };
+    let mut rows = vec![0.0; values.len()];
+    for (y, row) in rows.chunks_exact_mut(width).enumerate() {
+        let source = &values[y * width..(y + 1) * width];
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = blur(&|x| source[x], x, width);
+        }
+    }
+    let mut blurred = vec![0.0; values.len()];
+    for (i, value) in blurred.iter_mut().enumerate() {
+        let x = i % width;
+        *value = blur(&|y| rows[y * width + x], i / width, height);
+    }
+    blurred
+}
+
+/// Returns RGBA pixels of `region` of `image` scaled to 16 bits, row by row.
+fn rgba_pixels(image: &DynamicImage, region: Rect) -> Vec<[u16; 4]> {
+    let color = image.color();
+    let mut pixels = Vec::with_capacity(region.width as usize * region.height as usize);
+    for_each_pixel(image, region, |pixel| pixels.push(rgba(color, pixel)));
+    pixels
+}
+
+/// Converts subpixels of pixel of `color` type scaled to 16 bits to RGBA.
+fn rgba(color: ColorType, pixel: &[u16]) -> [u16; 4] {
+    match pixel.len() {
+        1 => [pixel[0], pixel[0], pixel[0], u16::MAX],
+        2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+        _ => {
+            let alpha = pixel.get(3).copied().unwrap_or(u16::MAX);
+            match color {
+                ColorType::Bgr8 | ColorType::Bgra8 => [pixel[2], pixel[1], pixel[0], alpha],
+                _ => [pixel[0], pixel[1], pixel[2], alpha],
+            }
+        }
+    }
+}
+
+/// Calls `f` with subpixels of every pixel of `region` of `image` scaled to 16 bits, in order of
+/// color type of image.
+fn for_each_pixel<F: FnMut(&[u16])>(image: &DynamicImage, region: Rect, mut f: F) {
+    let channels = image.color().channel_count() as usize;
+    let row_len = image.width() as usize * channels;
+    let start = region.x as usize * channels;
+    let end = start + region.width as usize * channels;
+    let mut wide_rows = |subpixels: &[u16]| {
+        for row in region_rows(subpixels, row_len, region) {
+            row[start..end].chunks_exact(channels).for_each(&mut f);
+        }
+    };
+    match image {
+        DynamicImage::ImageLuma16(b) => wide_rows(b),
+        DynamicImage::ImageLumaA16(b) => wide_rows(b),
+        DynamicImage::ImageRgb16(b) => wide_rows(b),
+        DynamicImage::ImageRgba16(b) => wide_rows(b),
+        image => {
+            for row in region_rows(image.as_bytes(), row_len, region) {
+                for pixel in row[start..end].chunks_exact(channels) {
+                    let mut wide = [0; 4];
+                    for (w, &v) in wide.iter_mut().zip(pixel) {
+                        *w = v as u16 * 257;
+                    }
+                    f(&wide[..channels]);
+                }
+            }
+        }
+    }
+}
+
+/// Returns rows of `region` of image whose rows are `row_len` subpixels long.
+fn region_rows<S>(subpixels: &[S], row_len: usize, region: Rect) -> impl Iterator<Item = &[S]> {
+    let rows = subpixels.chunks_exact(row_len).skip(region.y as usize);
+    rows.take(region.height as usize)
+}
+
+/// Returns center of histogram bin containing median of `count` values. Median in the first bin
+/// is treated as zero.
+fn median_bin(histogram: &[u32], count: u64) -> f64 {
+    let mut seen = 0;
+    for (bin, &n) in histogram.iter().enumerate() {
+        seen += n as u64;
+        if seen * 2 >= count {
+            return match bin {
+                0 => 0.0,
+                bin => bin as f64 + 0.5,
+            };
+        }
+    }
+    histogram.len() as f64
+}
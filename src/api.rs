@@ -0,0 +1,217 @@
+//! Safe Rust API for Rust hosts depending on crate as ordinary library, enabled by `rust-api`
+//! feature. `Image` owns handle of image and calls the same functions hosts loading shared
+//! library get in functions table, so both behave the same way.
+
+use crate::{ColorType, ImageError, ImageFormat, ImageHandle, RawPath, Rect};
+use std::ffi::CString;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// Result of operations of `Image`.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Error of operation: its error code and message of the last error describing it, e.g. message
+/// of decoder.
+#[derive(Debug)]
+pub struct Error {
+    code: ImageError,
+    message: Option<String>,
+}
+
+impl Error {
+    /// Returns error code of operation.
+    pub fn code(&self) -> ImageError {
+        self.code
+    }
+
+    /// Returns detailed message of error, if library reported it.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} error", self.code)?;
+        match &self.message {
+            Some(message) => write!(f, ": {}", message),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Calls exported function `f` and turns its error code into `Error` with message of the last
+/// error it reported.
+fn call<F: FnOnce() -> ImageError>(f: F) -> Result<()> {
+    crate::LAST_ERROR.with(|last| *last.borrow_mut() = None);
+    let code = f();
+    if let ImageError::NoError = code {
+        return Ok(());
+    }
+    let last = crate::LAST_ERROR.with(|last| last.borrow_mut().take());
+    let message = last
+        .filter(|last| last.code == code)
+        .map(|last| last.message.to_string_lossy().into_owned());
+    Err(Error { code, message })
+}
+
+/// Returns error of argument which can't be passed to library.
+fn parameter_error(message: String) -> Error {
+    Error {
+        code: ImageError::Parameter,
+        message: Some(message),
+    }
+}
+
+/// Image owned by Rust host. Operations creating new images return them, the rest change image
+/// in place and are recorded in its edit stack, as calls of exported functions are.
+pub struct Image(ImageHandle);
+
+impl Image {
+    /// Calls function `f` writing handle of new image and returns the image.
+    fn create<F: FnOnce(*mut ImageHandle) -> ImageError>(f: F) -> Result<Self> {
+        let mut handle = ImageHandle(std::ptr::null_mut());
+        call(|| f(&mut handle))?;
+        Ok(Self(handle))
+    }
+
+    /// Returns copy of handle of image to pass to exported function.
+    fn handle(&self) -> ImageHandle {
+        ImageHandle(self.0 .0)
+    }
+
+    /// Opens image at `path`, as `img_open` does.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path_to_cstring(path.as_ref())?;
+        Self::create(|handle| unsafe { crate::img_open(RawPath(path.as_ptr()), handle) })
+    }
+
+    /// Decodes image from encoded `data`, guessing its format by content.
+    pub fn from_memory(data: &[u8]) -> Result<Self> {
+        Self::create(|handle| unsafe {
+            crate::img_open_memory(data.as_ptr(), data.len(), std::ptr::null(), handle)
+        })
+    }
+
+    /// Creates image of `width` x `height` pixels of `color_type` filled with color `fill` given
+    /// as RGBA, as `img_new` does.
+    pub fn new(width: u32, height: u32, color_type: ColorType, fill: [u8; 4]) -> Result<Self> {
+        let fill = u32::from_be_bytes(fill);
+        Self::create(|handle| unsafe {
+            crate::img_new(width, height, color_type as u32, fill, handle)
+        })
+    }
+
+    /// Creates copy of image with its own pixels, metadata and edit stack.
+    pub fn try_clone(&self) -> Result<Self> {
+        Self::create(|handle| unsafe { crate::img_clone(self.handle(), handle) })
+    }
+
+    /// Saves image at `path` in format guessed by its extension.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path_to_cstring(path.as_ref())?;
+        call(|| unsafe { crate::img_save(RawPath(path.as_ptr()), self.handle()) })
+    }
+
+    /// Encodes image in `format` into memory.
+    pub fn encode(&self, format: ImageFormat) -> Result<Vec<u8>> {
+        let mut len = 0;
+        let format = format as u32;
+        call(|| unsafe {
+            crate::img_encode(self.handle(), format, std::ptr::null_mut(), &mut len)
+        })?;
+        let mut encoded = vec![0; len];
+        call(|| unsafe {
+            crate::img_encode(self.handle(), format, encoded.as_mut_ptr(), &mut len)
+        })?;
+        Ok(encoded)
+    }
+
+    /// Returns width and height of image.
+    pub fn dimensions(&self) -> (u32, u32) {
+        let (_, region) = unsafe { self.0.as_region() };
+        (region.width, region.height)
+    }
+
+    /// Returns color type of image.
+    pub fn color_type(&self) -> ColorType {
+        ColorType::of(unsafe { self.0.as_image() })
+    }
+
+    /// Returns color of pixel at `x`, `y` as RGBA.
+    pub fn pixel(&self, x: u32, y: u32) -> Result<[u8; 4]> {
+        let mut rgba = 0;
+        call(|| unsafe { crate::img_get_pixel(self.handle(), x, y, &mut rgba) })?;
+        Ok(rgba.to_be_bytes())
+    }
+
+    /// Sets pixel at `x`, `y` to color `rgba` converted to color type of image.
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) -> Result<()> {
+        let rgba = u32::from_be_bytes(rgba);
+        call(|| unsafe { crate::img_set_pixel(self.handle(), x, y, rgba) })
+    }
+
+    /// Returns copy of image blurred with positive `sigma` blur radius.
+    pub fn blur(&self, sigma: f32) -> Result<Self> {
+        Self::create(|handle| unsafe { crate::img_blur2(self.handle(), sigma, handle) })
+    }
+
+    /// Returns copy of image resized to `width` x `height` pixels by Lanczos filter.
+    pub fn resize(&self, width: u32, height: u32) -> Result<Self> {
+        Self::create(|handle| unsafe {
+            let user_data = std::ptr::null_mut();
+            crate::img_resize_with_progress(self.handle(), width, height, None, user_data, handle)
+        })
+    }
+
+    /// Returns copy of region of image at `x`, `y` of `width` x `height` pixels.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<Self> {
+        let rect = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let view = Self::create(|handle| unsafe { crate::img_view(self.handle(), rect, handle) })?;
+        view.try_clone()
+    }
+
+    /// Flips image horizontally.
+    pub fn mirror(&mut self) -> Result<()> {
+        call(|| unsafe { crate::img_mirror2(self.handle()) })
+    }
+
+    /// Returns copy of image converted to `color_type`.
+    pub fn convert(&self, color_type: ColorType) -> Result<Self> {
+        let color_type = color_type as u32;
+        Self::create(|handle| unsafe { crate::img_convert(self.handle(), color_type, handle) })
+    }
+
+    /// Reorders channels of image: channel `i` becomes channel `mapping[i]`, as
+    /// `img_swap_channels` does.
+    pub fn swap_channels(&mut self, mapping: [u8; 4]) -> Result<()> {
+        call(|| unsafe { crate::img_swap_channels(self.handle(), &mapping) })
+    }
+}
+
+impl Clone for Image {
+    fn clone(&self) -> Self {
+        self.try_clone().expect("image is cloned")
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe { crate::img_destroy(self.handle()) }
+    }
+}
+
+/// Converts `path` to null-terminated UTF-8 string library takes.
+fn path_to_cstring(path: &Path) -> Result<CString> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| parameter_error(format!("Path isn't UTF-8: {}", path.display())))?;
+    CString::new(path).map_err(|e| parameter_error(e.to_string()))
+}
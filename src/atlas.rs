@@ -0,0 +1,49 @@
+use crate::Rect;
+
+/// Places rectangles of `sizes` on shelves: rows at most `max_width` pixels wide filled from the
+/// tallest rectangle, with `padding` pixels between rectangles and rows. Returns positions in
+/// order of `sizes` and size of sheet covering them, or `None` if some rectangle is wider than
+/// `max_width` or sheet is taller than `u32::MAX`.
+pub fn pack(sizes: &[(u32, u32)], max_width: u32, padding: u32) -> Option<(Vec<Rect>, u32, u32)> {
+    if sizes.iter().any(|&(w, _)| w > max_width) {
+        return None;
+    }
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse((sizes[i].1, sizes[i].0)));
+
+    let (max_width, padding) = (max_width as u64, padding as u64);
+    // Shelves as `(y, height, x)` of their top, height of their first rectangle and end of
+    // their last rectangle.
+    let mut shelves: Vec<(u64, u64, u64)> = Vec::new();
+    let mut placements = vec![None; sizes.len()];
+    for i in order {
+        let (w, h) = (sizes[i].0 as u64, sizes[i].1 as u64);
+        let fits = |&&mut (_, _, x): &&mut (u64, u64, u64)| x + padding + w <= max_width;
+        let shelf = match shelves.iter_mut().find(fits) {
+            Some(shelf) => {
+                shelf.2 += padding;
+                shelf
+            }
+            None => {
+                let y = shelves.last().map_or(0, |&(y, h, _)| y + h + padding);
+                shelves.push((y, h, 0));
+                shelves.last_mut().expect("shelf is pushed")
+            }
+        };
+        placements[i] = Some((shelf.2, shelf.0, w, h));
+        shelf.2 += w;
+    }
+
+    let width = shelves.iter().map(|s| s.2).max().unwrap_or(0);
+    let height = shelves.last().map_or(0, |&(y, h, _)| y + h);
+    if height > u32::MAX as u64 {
+        return None;
+    }
+    let rects = placements.into_iter().flatten().map(|(x, y, w, h)| Rect {
+        x: x as u32,
+        y: y as u32,
+        width: w as u32,
+        height: h as u32,
+    });
+    Some((rects.collect(), width as u32, height as u32))
+}
@@ -0,0 +1,112 @@
+use crate::{codec, job, parallel, progress, recipe, trace};
+use crate::{ImageData, ImageError, ImageFormat, ImageHandle, LastError, OpenOptions, SaveOptions};
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Edits applied to every file of batch and how their results are written.
+pub struct Batch {
+    /// Edits of edit stack document applied to every image.
+    pub edits: Vec<Value>,
+    pub out_dir: PathBuf,
+    /// Extension of output files replacing extension of sources, which selects format of outputs,
+    /// or `None` to keep extension of each source.
+    pub extension: Option<String>,
+    pub options: SaveOptions,
+}
+
+impl Batch {
+    /// Decodes, edits and encodes files at `paths` on worker threads. Returns outcome of every
+    /// file in order of `paths`. Files skipped as operation was aborted fail with
+    /// `ImageError::Cancelled`.
+    pub fn run(&self, paths: &[PathBuf]) -> Vec<ImageError> {
+        let mut results: Vec<_> = paths.iter().map(|_| ImageError::Cancelled).collect();
+        parallel::for_each(&mut results, |i, result| {
+            // Progress is reported per file, not per row of its edits.
+            *result = progress::detach(|| self.process(&paths[i]));
+        });
+        results
+    }
+
+    /// Converts file at `path`, catching panics of edits, as each file is separate call. Failure
+    /// is logged with path of file.
+    fn process(&self, path: &Path) -> ImageError {
+        let code = crate::guard(|| match unsafe { self.convert(path) } {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        });
+        if let ImageError::NoError = code {
+            return code;
+        }
+        let error = job::take_last_error(code);
+        let message = error.message.to_string_lossy();
+        crate::log::warn(&format!("Batch failed on {}: {}", path.display(), message));
+        code
+    }
+
+    unsafe fn convert(&self, path: &Path) -> Result<(), ImageError> {
+        let _span = trace::span("batch_file");
+        let output = self.output_path(path)?;
+        let data = crate::open_file(path, &OpenOptions::default())?;
+        let handle = ImageHandle::from_data(data);
+        let edited = recipe::apply_edits(&handle, &self.edits);
+        let data = handle.into_data();
+        edited?;
+        save(&data, &output, &self.options)
+    }
+
+    /// Returns path of output for source file at `path`: its name in output directory with
+    /// extension of batch, if any.
+    fn output_path(&self, path: &Path) -> Result<PathBuf, ImageError> {
+        let stem = match path.file_stem() {
+            Some(stem) => stem,
+            None => {
+                let message = format!("Source path {} has no file name", path.display());
+                crate::set_last_error(LastError::new(ImageError::Parameter, message));
+                return Err(ImageError::Parameter);
+            }
+        };
+        let extension = match &self.extension {
+            Some(extension) => Some(OsString::from(extension)),
+            None => path.extension().map(OsString::from),
+        };
+        let mut name = stem.to_os_string();
+        if let Some(extension) = extension {
+            name.push(".");
+            name.push(extension);
+        }
+        Ok(self.out_dir.join(name))
+    }
+}
+
+/// Encodes `data` into file at `path` in format selected by its extension with `options`. Codec
+/// plugins registered for extension take precedence over built-in formats, as in `img_save`.
+fn save(data: &ImageData, path: &Path, options: &SaveOptions) -> Result<(), ImageError> {
+    let create = || match std::fs::File::create(path) {
+        Ok(file) => Ok(BufWriter::new(file)),
+        Err(e) => Err(crate::io_error(e)),
+    };
+    let extension = path.extension().and_then(|e| e.to_str());
+    if let Some(encoder) = extension.and_then(codec::encoder) {
+        let mut writer = create()?;
+        encoder.encode(data, &mut writer)?;
+        return writer.flush().map_err(crate::io_error);
+    }
+
+    let format = image::ImageFormat::from_path(path)
+        .ok()
+        .and_then(|f| ImageFormat::try_from(f).ok());
+    let format = match format {
+        Some(format) => format,
+        None => {
+            let message = format!("Unknown format of output file {}", path.display());
+            crate::set_last_error(LastError::new(ImageError::Unsupported, message));
+            return Err(ImageError::Unsupported);
+        }
+    };
+    let mut writer = create()?;
+    crate::write_encoded(data, format, options, &mut writer)?;
+    writer.flush().map_err(crate::io_error)
+}
@@ -0,0 +1,350 @@
+//! Generates Python `ctypes` module of library from its sources: error codes and other enums,
+//! handles, structs, function types and `FunctionsBlock`, with `load(path)` returning table of
+//! functions of shared library. Built with `python-gen` feature, e.g.
+//! `cargo run --features python-gen --bin gen_python > image_sl.py`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use syn::{Attribute, Expr, Fields, Item, Lit, Type};
+
+/// Sources of library the binary is built with, so module matches the same build.
+const SOURCES: &str = include_str!("../lib.rs");
+
+fn main() {
+    let file = syn::parse_file(SOURCES).expect("sources of library are valid Rust");
+    match generate(&file.items) {
+        Ok(module) => print!("{}", module),
+        Err(e) => {
+            eprintln!("can't generate Python module: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Declarations of sources which Python module mirrors.
+#[derive(Default)]
+struct Declarations<'a> {
+    abi_version: Option<String>,
+    /// Enums with `repr` of integer and their variants with values.
+    enums: Vec<(&'a syn::ItemEnum, Vec<(String, i64)>)>,
+    /// Handles and other types passed as another type, e.g. `ImageHandle` as `*mut c_void`,
+    /// with their variants of `cfg(windows)` if they differ.
+    aliases: Vec<(String, &'a Type, Option<&'a Type>)>,
+    structs: Vec<&'a syn::ItemStruct>,
+    functions: Vec<(String, &'a syn::TypeBareFn)>,
+}
+
+impl<'a> Declarations<'a> {
+    fn collect(items: &'a [Item]) -> Result<Self, String> {
+        let mut declarations = Self::default();
+        for item in items {
+            match item {
+                Item::Const(c) if c.ident == "ABI_VERSION" => {
+                    declarations.abi_version = Some(int_literal(&c.expr)?.to_string());
+                }
+                Item::Enum(e) if repr(&e.attrs).is_some_and(|r| r.starts_with(['u', 'i'])) => {
+                    let mut variants = Vec::new();
+                    let mut next = 0;
+                    for variant in &e.variants {
+                        if let Some((_, value)) = &variant.discriminant {
+                            next = int_literal(value)?;
+                        }
+                        variants.push((variant.ident.to_string(), next));
+                        next += 1;
+                    }
+                    declarations.enums.push((e, variants));
+                }
+                Item::Struct(s) => match (repr(&s.attrs).as_deref(), &s.fields) {
+                    (Some("transparent"), Fields::Unnamed(fields)) => {
+                        let ty = &fields.unnamed[0].ty;
+                        declarations.alias(s.ident.to_string(), ty, &s.attrs);
+                    }
+                    (Some("C"), Fields::Named(_)) => declarations.structs.push(s),
+                    _ => {}
+                },
+                Item::Type(t) => match &*t.ty {
+                    Type::BareFn(f) => declarations.functions.push((t.ident.to_string(), f)),
+                    ty => declarations.alias(t.ident.to_string(), ty, &t.attrs),
+                },
+                _ => {}
+            }
+        }
+        Ok(declarations)
+    }
+
+    /// Records alias `name` of `ty` declared with `attrs`. Variants for other platforms than
+    /// Windows and Unix are skipped.
+    fn alias(&mut self, name: String, ty: &'a Type, attrs: &[Attribute]) {
+        let cfg = attrs
+            .iter()
+            .find(|a| a.path().is_ident("cfg"))
+            .and_then(|a| a.meta.require_list().ok())
+            .map(|list| list.tokens.to_string());
+        match cfg.as_deref() {
+            None | Some("unix") => self.aliases.push((name, ty, None)),
+            Some("windows") => {
+                if let Some(alias) = self.aliases.iter_mut().find(|(n, _, _)| *n == name) {
+                    alias.2 = Some(ty);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Returns Python module mirroring declarations of `items`.
+fn generate(items: &[Item]) -> Result<String, String> {
+    let declarations = Declarations::collect(items)?;
+    let types = PythonTypes::new(&declarations);
+    let mut module = String::new();
+    let out = &mut module;
+
+    writeln!(out, "\"\"\"ctypes bindings of image_sl shared library.").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "Generated by `gen_python` from sources of library. Don't edit."
+    )
+    .unwrap();
+    writeln!(out, "\"\"\"").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "import ctypes").unwrap();
+    writeln!(out, "import enum").unwrap();
+    writeln!(out, "import sys").unwrap();
+    writeln!(out).unwrap();
+    let abi_version = declarations
+        .abi_version
+        .as_deref()
+        .ok_or("no ABI_VERSION")?;
+    writeln!(
+        out,
+        "# ABI version of `FunctionsV2` these bindings request."
+    )
+    .unwrap();
+    writeln!(out, "ABI_VERSION = {}", abi_version).unwrap();
+
+    for (e, variants) in &declarations.enums {
+        writeln!(out, "\n\nclass {}(enum.IntEnum):", e.ident).unwrap();
+        write_doc(out, &e.attrs);
+        for (name, value) in variants {
+            writeln!(out, "    {} = {}", upper_snake_case(name), value).unwrap();
+        }
+    }
+
+    writeln!(out).unwrap();
+    for (name, ty, windows) in &declarations.aliases {
+        let ty = types.of(ty)?;
+        match windows {
+            Some(windows) => {
+                let windows = types.of(windows)?;
+                let platform = "sys.platform == \"win32\"";
+                writeln!(out, "\n{} = {} if {} else {}", name, windows, platform, ty).unwrap();
+            }
+            None => writeln!(out, "\n{} = {}", name, ty).unwrap(),
+        }
+    }
+
+    // Structs and function types refer to each other, so fields are set once all are declared.
+    for s in &declarations.structs {
+        writeln!(out, "\n\nclass {}(ctypes.Structure):", s.ident).unwrap();
+        write_doc(out, &s.attrs);
+        if doc(&s.attrs).is_empty() {
+            writeln!(out, "    pass").unwrap();
+        }
+    }
+    writeln!(out).unwrap();
+    for (name, f) in &declarations.functions {
+        let result = match &f.output {
+            syn::ReturnType::Default => "None".to_string(),
+            syn::ReturnType::Type(_, ty) => types.of(ty)?,
+        };
+        let mut args = vec![result];
+        for arg in &f.inputs {
+            args.push(types.of(&arg.ty)?);
+        }
+        writeln!(out, "\n{} = ctypes.CFUNCTYPE({})", name, args.join(", ")).unwrap();
+    }
+    for s in &declarations.structs {
+        writeln!(out, "\n{}._fields_ = [", s.ident).unwrap();
+        for field in &s.fields {
+            let name = field.ident.as_ref().expect("fields are named");
+            writeln!(out, "    (\"{}\", {}),", name, types.of(&field.ty)?).unwrap();
+        }
+        writeln!(out, "]").unwrap();
+    }
+
+    out.push_str(LOAD);
+    Ok(module)
+}
+
+/// Loader of shared library appended to module.
+const LOAD: &str = r#"
+
+def load(path):
+    """Loads shared library at `path` and returns its `FunctionsBlock` of `ABI_VERSION`.
+
+    Raises `OSError` if library can't be loaded or is older than these bindings.
+    """
+    lib = ctypes.CDLL(path)
+    functions_ex = lib.functions_ex
+    functions_ex.argtypes = [ctypes.c_uint32, ctypes.POINTER(FunctionsV2)]
+    functions_ex.restype = ctypes.c_uint32
+    table = FunctionsV2()
+    err = functions_ex(ABI_VERSION, ctypes.byref(table))
+    if err != ImageError.NO_ERROR:
+        raise OSError("functions_ex failed with {}".format(ImageError(err).name))
+    if table.size != ctypes.sizeof(FunctionsV2):
+        raise OSError("size of functions table of library doesn't match bindings")
+    # Functions of table stay valid while library is loaded.
+    table.functions._lib = lib
+    return table.functions
+"#;
+
+/// Python names of types of declarations.
+struct PythonTypes {
+    /// Names of types declared by module itself: aliases, structs and function types.
+    declared: Vec<String>,
+    /// Integer `ctypes` types of enums by their names.
+    enums: HashMap<String, &'static str>,
+}
+
+impl PythonTypes {
+    fn new(declarations: &Declarations) -> Self {
+        let aliases = declarations.aliases.iter().map(|(name, _, _)| name.clone());
+        let structs = declarations.structs.iter().map(|s| s.ident.to_string());
+        let functions = declarations.functions.iter().map(|(name, _)| name.clone());
+        let enums = declarations.enums.iter().filter_map(|(e, _)| {
+            let ty = primitive(&repr(&e.attrs)?)?;
+            Some((e.ident.to_string(), ty))
+        });
+        Self {
+            declared: aliases.chain(structs).chain(functions).collect(),
+            enums: enums.collect(),
+        }
+    }
+
+    /// Returns `ctypes` expression of Rust type `ty`.
+    fn of(&self, ty: &Type) -> Result<String, String> {
+        let unsupported = || format!("unsupported type `{}`", quote::quote!(#ty));
+        match ty {
+            Type::Path(path) => {
+                let segment = path.path.segments.last().ok_or_else(unsupported)?;
+                let name = segment.ident.to_string();
+                if let Some(ty) = primitive(&name) {
+                    return Ok(ty.to_string());
+                }
+                if let Some(ty) = self.enums.get(&name) {
+                    return Ok(ty.to_string());
+                }
+                if self.declared.contains(&name) {
+                    return Ok(name);
+                }
+                // Null function pointers are passed as `None`.
+                match &segment.arguments {
+                    syn::PathArguments::AngleBracketed(args) if name == "Option" => {
+                        match args.args.first() {
+                            Some(syn::GenericArgument::Type(ty)) => self.of(ty),
+                            _ => Err(unsupported()),
+                        }
+                    }
+                    _ => Err(unsupported()),
+                }
+            }
+            Type::Ptr(ptr) => {
+                let pointee = match &*ptr.elem {
+                    Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+                    _ => None,
+                };
+                match (pointee.as_deref(), ptr.mutability.is_some()) {
+                    (Some("c_void"), _) => Ok("ctypes.c_void_p".to_string()),
+                    (Some("c_char"), false) => Ok("ctypes.c_char_p".to_string()),
+                    _ => Ok(format!("ctypes.POINTER({})", self.of(&ptr.elem)?)),
+                }
+            }
+            Type::Array(array) => {
+                let len = int_literal(&array.len)?;
+                Ok(format!("({} * {})", self.of(&array.elem)?, len))
+            }
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+/// Returns `ctypes` type of primitive Rust type `name`.
+fn primitive(name: &str) -> Option<&'static str> {
+    let ty = match name {
+        "u8" => "ctypes.c_uint8",
+        "u16" => "ctypes.c_uint16",
+        "u32" => "ctypes.c_uint32",
+        "u64" => "ctypes.c_uint64",
+        "i8" => "ctypes.c_int8",
+        "i16" => "ctypes.c_int16",
+        "i32" => "ctypes.c_int32",
+        "i64" => "ctypes.c_int64",
+        "usize" => "ctypes.c_size_t",
+        "isize" => "ctypes.c_ssize_t",
+        "f32" => "ctypes.c_float",
+        "f64" => "ctypes.c_double",
+        "bool" => "ctypes.c_bool",
+        "c_char" => "ctypes.c_char",
+        "c_int" => "ctypes.c_int",
+        _ => return None,
+    };
+    Some(ty)
+}
+
+/// Returns argument of `#[repr(...)]` attribute, e.g. `"C"` or `"u32"`.
+fn repr(attrs: &[Attribute]) -> Option<String> {
+    let attr = attrs.iter().find(|a| a.path().is_ident("repr"))?;
+    let ident = attr.parse_args::<syn::Ident>().ok()?;
+    Some(ident.to_string())
+}
+
+/// Returns value of integer literal `expr`.
+fn int_literal(expr: &Expr) -> Result<i64, String> {
+    match expr {
+        Expr::Lit(syn::ExprLit {
+            lit: Lit::Int(int), ..
+        }) => int.base10_parse().map_err(|e| e.to_string()),
+        expr => Err(format!("`{}` isn't integer literal", quote::quote!(#expr))),
+    }
+}
+
+/// Returns lines of doc comments of `attrs`.
+fn doc(attrs: &[Attribute]) -> Vec<String> {
+    let lines = attrs.iter().filter_map(|attr| match &attr.meta {
+        syn::Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+            Expr::Lit(syn::ExprLit {
+                lit: Lit::Str(s), ..
+            }) => Some(s.value().trim().to_string()),
+            _ => None,
+        },
+        _ => None,
+    });
+    lines.collect()
+}
+
+/// Writes doc comments of `attrs` as docstring of class.
+fn write_doc(out: &mut String, attrs: &[Attribute]) {
+    let lines = doc(attrs);
+    if lines.is_empty() {
+        return;
+    }
+    let text = lines
+        .join("\n    ")
+        .replace('\\', "\\\\")
+        .replace("\"\"\"", "\\\"\"\"");
+    writeln!(out, "    \"\"\"{}\"\"\"", text).unwrap();
+}
+
+/// Converts `CamelCase` name of variant to `UPPER_SNAKE_CASE` of Python constants.
+fn upper_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i != 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_uppercase());
+    }
+    snake
+}
@@ -0,0 +1,164 @@
+use crate::{ImageError, LastError, OperationBudget};
+use std::cell::RefCell;
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+use std::time::{Duration, Instant};
+
+/// Number of reads or writes between checks of deadline by `Stream`.
+const CALLS_PER_CHECK: u32 = 64;
+
+/// Budget of operation running on current thread.
+struct Active {
+    deadline: Option<Instant>,
+    max_millis: u64,
+    max_bytes: u64,
+    /// Bytes of allocations checked so far.
+    used_bytes: u64,
+    /// Message set once budget is exceeded, so failures of nested operations are reported as
+    /// `ImageError::BudgetExceeded` whatever error they return.
+    exceeded: Option<String>,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Active>> = const { RefCell::new(None) };
+}
+
+/// Restores budget of enclosing operation, also if operation panics.
+struct Restore(Option<Active>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        ACTIVE.with(|a| *a.borrow_mut() = previous);
+    }
+}
+
+/// Runs `f` within `budget`. Zero fields of budget mean no limit. Returns
+/// `ImageError::BudgetExceeded` if `f` exceeds budget, also if it finishes nevertheless.
+pub fn run<F: FnOnce() -> ImageError>(budget: &OperationBudget, f: F) -> ImageError {
+    if budget.max_millis != 0 && !crate::trace::HAS_CLOCK {
+        let message = "Time budgets aren't supported on this platform".to_string();
+        crate::set_last_error(LastError::new(ImageError::Unsupported, message));
+        return ImageError::Unsupported;
+    }
+    let active = Active {
+        deadline: match budget.max_millis {
+            0 => None,
+            millis => Instant::now().checked_add(Duration::from_millis(millis)),
+        },
+        max_millis: budget.max_millis,
+        max_bytes: budget.max_bytes,
+        used_bytes: 0,
+        exceeded: None,
+    };
+    let previous = ACTIVE.with(|a| a.borrow_mut().replace(active));
+    let restore = Restore(previous);
+
+    let result = f();
+    let _ = check_time();
+    let exceeded = ACTIVE.with(|a| a.borrow_mut().as_mut().and_then(|a| a.exceeded.take()));
+    drop(restore);
+    match exceeded {
+        Some(message) => {
+            crate::set_last_error(LastError::new(ImageError::BudgetExceeded, message));
+            ImageError::BudgetExceeded
+        }
+        None => result,
+    }
+}
+
+/// Fails with `ImageError::BudgetExceeded` if deadline of current budget has passed.
+pub fn check_time() -> Result<(), ImageError> {
+    ACTIVE.with(|a| match a.borrow_mut().as_mut() {
+        Some(a) if a.deadline.is_some_and(|d| Instant::now() > d) => {
+            let message = format!("Operation exceeded time budget of {} ms", a.max_millis);
+            Err(exceed(a, message))
+        }
+        _ => Ok(()),
+    })
+}
+
+/// Counts allocation of `bytes` against current budget. Fails with `ImageError::BudgetExceeded`
+/// if allocations of operation exceed memory budget or its deadline has passed.
+pub fn charge(bytes: u64) -> Result<(), ImageError> {
+    check_time()?;
+    ACTIVE.with(|a| match a.borrow_mut().as_mut() {
+        Some(a) if a.max_bytes != 0 => {
+            a.used_bytes = a.used_bytes.saturating_add(bytes);
+            if a.used_bytes <= a.max_bytes {
+                return Ok(());
+            }
+            let message = format!(
+                "Operation exceeded memory budget of {} bytes allocating {} bytes",
+                a.max_bytes, bytes
+            );
+            Err(exceed(a, message))
+        }
+        _ => Ok(()),
+    })
+}
+
+/// Marks budget `active` exceeded and records `message` as the last error. The first message is
+/// kept.
+fn exceed(active: &mut Active, message: String) -> ImageError {
+    crate::set_last_error(LastError::new(ImageError::BudgetExceeded, message.clone()));
+    active.exceeded.get_or_insert(message);
+    ImageError::BudgetExceeded
+}
+
+/// Reader or writer failing once deadline of current budget has passed, so long decoding or
+/// encoding is interrupted.
+pub struct Stream<T> {
+    inner: T,
+    calls: u32,
+}
+
+impl<T> Stream<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner, calls: 0 }
+    }
+
+    /// Checks deadline every `CALLS_PER_CHECK` calls.
+    fn check(&mut self) -> std::io::Result<()> {
+        self.calls = self.calls.wrapping_add(1);
+        if !self.calls.is_multiple_of(CALLS_PER_CHECK) {
+            return Ok(());
+        }
+        check_time()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "time budget exceeded"))
+    }
+}
+
+impl<T: Read> Read for Stream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.check()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<T: BufRead> BufRead for Stream<T> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.check()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+impl<T: Seek> Seek for Stream<T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<T: Write> Write for Stream<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.check()?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,360 @@
+use crate::ImageError;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Cached file of encoded result, named by hex digest of its key.
+struct Entry {
+    name: String,
+    bytes: u64,
+}
+
+/// Directory of cached results and size limit of its entries.
+struct Cache {
+    dir: PathBuf,
+    max_bytes: u64,
+    bytes: u64,
+    /// Entries from the least recently used one.
+    entries: Vec<Entry>,
+}
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+/// Number of temporary files written by this process, so concurrent writers don't share them.
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Cache>> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Enables cache of results in `dir`, creating it if needed, with entries of up to `max_bytes`
+/// in total. Entries already in `dir` are kept from the most recently used one while they fit.
+pub fn enable(dir: &Path, max_bytes: u64) -> Result<(), ImageError> {
+    std::fs::create_dir_all(dir).map_err(crate::io_error)?;
+    let mut entries = Vec::new();
+    for file in std::fs::read_dir(dir).map_err(crate::io_error)? {
+        let file = file.map_err(crate::io_error)?;
+        let name = match file.file_name().into_string() {
+            Ok(name) if is_key(&name) => name,
+            _ => continue,
+        };
+        let metadata = match file.metadata() {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => continue,
+        };
+        let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        let bytes = metadata.len();
+        entries.push((used, Entry { name, bytes }));
+    }
+    entries.sort_by_key(|(used, _)| *used);
+
+    let mut cache = Cache {
+        dir: dir.to_path_buf(),
+        max_bytes,
+        bytes: entries.iter().map(|(_, e)| e.bytes).sum(),
+        entries: entries.into_iter().map(|(_, e)| e).collect(),
+    };
+    cache.evict(0);
+    *lock() = Some(cache);
+    Ok(())
+}
+
+/// Disables cache, leaving its files in place.
+pub fn disable() {
+    *lock() = None;
+}
+
+pub fn is_enabled() -> bool {
+    lock().is_some()
+}
+
+/// Returns cache key of result computed from `parts`, e.g. input and description of operations.
+pub fn key(parts: &[&[u8]]) -> String {
+    let mut sha = Sha256::default();
+    for part in parts {
+        // Lengths keep boundaries of parts, so different parts never give the same key.
+        sha.update(&(part.len() as u64).to_le_bytes());
+        sha.update(part);
+    }
+    sha.finish().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn is_key(name: &str) -> bool {
+    name.len() == 64
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Returns result cached under `key`, `None` if cache is disabled or doesn't have it.
+pub fn get(key: &str) -> Option<Vec<u8>> {
+    let mut cache = lock();
+    let cache = cache.as_mut()?;
+    let index = cache.entries.iter().position(|e| e.name == key)?;
+    let entry = cache.entries.remove(index);
+    let path = cache.dir.join(&entry.name);
+    match std::fs::read(&path) {
+        Ok(data) => {
+            // Time of use orders entries found by `enable` of the next process.
+            if let Ok(file) = std::fs::File::options().write(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+            cache.entries.push(entry);
+            Some(data)
+        }
+        Err(e) => {
+            // Entry removed by someone else is forgotten.
+            crate::log::warn(&format!("Failed to read cached {}: {}", path.display(), e));
+            cache.bytes -= entry.bytes;
+            None
+        }
+    }
+}
+
+/// Stores `data` under `key` if cache is enabled, evicting the least recently used entries to
+/// fit it. Results larger than cache are not stored. Failures are logged, as result is
+/// computed anyway.
+pub fn put(key: &str, data: &[u8]) {
+    let mut cache = lock();
+    let cache = match cache.as_mut() {
+        Some(c) if data.len() as u64 <= c.max_bytes => c,
+        _ => return,
+    };
+    if let Some(index) = cache.entries.iter().position(|e| e.name == key) {
+        let entry = cache.entries.remove(index);
+        cache.bytes -= entry.bytes;
+    }
+    cache.evict(data.len() as u64);
+
+    // Written file is renamed, so readers never see partially written entry.
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    let temp = cache
+        .dir
+        .join(format!("{}.{}-{}.tmp", key, std::process::id(), write));
+    let path = cache.dir.join(key);
+    let written = std::fs::write(&temp, data).and_then(|_| std::fs::rename(&temp, &path));
+    match written {
+        Ok(_) => {
+            cache.bytes += data.len() as u64;
+            cache.entries.push(Entry {
+                name: key.to_string(),
+                bytes: data.len() as u64,
+            });
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp);
+            crate::log::warn(&format!("Failed to cache {}: {}", path.display(), e));
+        }
+    }
+}
+
+impl Cache {
+    /// Removes the least recently used entries until `bytes` more fit into cache.
+    fn evict(&mut self, bytes: u64) {
+        let mut evicted = 0;
+        while self.bytes + bytes > self.max_bytes && evicted < self.entries.len() {
+            let entry = &self.entries[evicted];
+            let path = self.dir.join(&entry.name);
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    crate::log::warn(&format!("Failed to evict {}: {}", path.display(), e));
+                }
+            }
+            self.bytes -= entry.bytes;
+            evicted += 1;
+        }
+        self.entries.drain(..evicted);
+    }
+}
+
+/// SHA-256 digest, so keys of different inputs don't collide even for inputs made to.
+struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    /// Number of bytes hashed so far.
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self {
+            state: [
+                0x6a09_e667,
+                0xbb67_ae85,
+                0x3c6e_f372,
+                0xa54f_f53a,
+                0x510e_527f,
+                0x9b05_688c,
+                0x1f83_d9ab,
+                0x5be0_cd19,
+            ],
+            block: [0; 64],
+            len: 0,
+        }
+    }
+}
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a_2f98,
+    0x7137_4491,
+    0xb5c0_fbcf,
+    0xe9b5_dba5,
+    0x3956_c25b,
+    0x59f1_11f1,
+    0x923f_82a4,
+    0xab1c_5ed5,
+    0xd807_aa98,
+    0x1283_5b01,
+    0x2431_85be,
+    0x550c_7dc3,
+    0x72be_5d74,
+    0x80de_b1fe,
+    0x9bdc_06a7,
+    0xc19b_f174,
+    0xe49b_69c1,
+    0xefbe_4786,
+    0x0fc1_9dc6,
+    0x240c_a1cc,
+    0x2de9_2c6f,
+    0x4a74_84aa,
+    0x5cb0_a9dc,
+    0x76f9_88da,
+    0x983e_5152,
+    0xa831_c66d,
+    0xb003_27c8,
+    0xbf59_7fc7,
+    0xc6e0_0bf3,
+    0xd5a7_9147,
+    0x06ca_6351,
+    0x1429_2967,
+    0x27b7_0a85,
+    0x2e1b_2138,
+    0x4d2c_6dfc,
+    0x5338_0d13,
+    0x650a_7354,
+    0x766a_0abb,
+    0x81c2_c92e,
+    0x9272_2c85,
+    0xa2bf_e8a1,
+    0xa81a_664b,
+    0xc24b_8b70,
+    0xc76c_51a3,
+    0xd192_e819,
+    0xd699_0624,
+    0xf40e_3585,
+    0x106a_a070,
+    0x19a4_c116,
+    0x1e37_6c08,
+    0x2748_774c,
+    0x34b0_bcb5,
+    0x391c_0cb3,
+    0x4ed8_aa4a,
+    0x5b9c_ca4f,
+    0x682e_6ff3,
+    0x748f_82ee,
+    0x78a5_636f,
+    0x84c8_7814,
+    0x8cc7_0208,
+    0x90be_fffa,
+    0xa450_6ceb,
+    0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+impl Sha256 {
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let filled = (self.len % 64) as usize;
+            let taken = data.len().min(64 - filled);
+            self.block[filled..filled + taken].copy_from_slice(&data[..taken]);
+            self.len += taken as u64;
+            data = &data[taken..];
+            if filled + taken == 64 {
+                self.compress();
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        let bits = self.len * 8;
+        self.update(&[0x80]);
+        while self.len % 64 != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+        let mut digest = [0; 32];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(&self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(self.block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (k, w) in ROUND_CONSTANTS.iter().zip(&w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(*k)
+                .wrapping_add(*w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(data: &[&[u8]]) -> String {
+        let mut sha = Sha256::default();
+        for part in data {
+            sha.update(part);
+        }
+        sha.finish().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha256_matches_test_vectors() {
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(hex(&[]), empty);
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(hex(&[b"abc"]), abc);
+        // Two blocks, fed in parts crossing their boundary.
+        let message = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        let two_blocks = "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1";
+        assert_eq!(hex(&[&message[..3], &message[3..]]), two_blocks);
+        let million = vec![b'a'; 1_000_000];
+        let million_a = "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0";
+        assert_eq!(hex(&[&million]), million_a);
+    }
+}
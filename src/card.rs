@@ -0,0 +1,188 @@
+use crate::{draw, text, CardLayout, ImageError};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+/// Maximum number of title lines. Longer titles are cut with ellipsis.
+const MAX_TITLE_LINES: usize = 3;
+
+/// Content and look of social media card.
+pub struct Card<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub layout: CardLayout,
+    pub title: &'a str,
+    pub avatar: Option<&'a DynamicImage>,
+    pub logo: Option<&'a DynamicImage>,
+    pub text_color: Rgba<u8>,
+    /// Color of overlay keeping title readable over background.
+    pub scrim_color: Rgba<u8>,
+}
+
+/// Region of card where title is laid out.
+struct TitleBox {
+    x: i64,
+    y: i64,
+    width: u32,
+    height: u32,
+    centered: bool,
+}
+
+/// Composes `card` over `background` scaled to cover it.
+pub fn compose(background: &DynamicImage, card: &Card) -> Result<DynamicImage, ImageError> {
+    let (width, height) = (card.width, card.height);
+    // Resizing allocates intermediate image of target width and source height.
+    let rows = height.max(background.height());
+    crate::check_alloc(crate::image_bytes(width, rows, image::ColorType::Rgba8))?;
+
+    let margin = (height / 12) as i64;
+    let avatar_size = height / 5;
+    let mut canvas = DynamicImage::ImageRgba8(crate::try_image(width, height)?);
+    let title_box = match card.layout {
+        CardLayout::Bottom => {
+            cover(&mut canvas, background, 0, width);
+            bottom_scrim(&mut canvas, card.scrim_color);
+            let text_x = match card.avatar {
+                Some(avatar) => {
+                    let y = height as i64 - margin - avatar_size as i64;
+                    place_avatar(&mut canvas, avatar, margin, y, avatar_size);
+                    margin * 3 / 2 + avatar_size as i64
+                }
+                None => margin,
+            };
+            TitleBox {
+                x: text_x,
+                y: height as i64 / 2,
+                width: (width as i64 - text_x - margin).max(0) as u32,
+                height: (height as i64 / 2 - margin).max(0) as u32,
+                centered: false,
+            }
+        }
+        CardLayout::Center => {
+            cover(&mut canvas, background, 0, width);
+            let (w, h) = (width as i64, height as i64);
+            draw::fill_rect(&mut canvas, 0, 0, w, h, card.scrim_color);
+            let mut top = margin;
+            if let Some(avatar) = card.avatar {
+                let x = (w - avatar_size as i64) / 2;
+                place_avatar(&mut canvas, avatar, x, margin, avatar_size);
+                top += avatar_size as i64 + margin / 2;
+            }
+            TitleBox {
+                x: margin,
+                y: top,
+                width: (w - 2 * margin).max(0) as u32,
+                height: (h - top - margin).max(0) as u32,
+                centered: true,
+            }
+        }
+        CardLayout::Split => {
+            let panel = width / 2;
+            cover(&mut canvas, background, panel, width - panel);
+            let mut scrim = card.scrim_color;
+            scrim.0[3] = u8::MAX;
+            draw::fill_rect(&mut canvas, 0, 0, panel as i64, height as i64, scrim);
+            let mut top = margin;
+            if let Some(avatar) = card.avatar {
+                place_avatar(&mut canvas, avatar, margin, margin, avatar_size);
+                top += avatar_size as i64 + margin / 2;
+            }
+            TitleBox {
+                x: margin,
+                y: top,
+                width: (panel as i64 - 2 * margin).max(0) as u32,
+                height: (height as i64 - top - margin).max(0) as u32,
+                centered: false,
+            }
+        }
+    };
+
+    if let Some(logo) = card.logo {
+        // Logo keeps its aspect ratio within box of avatar size at top right corner.
+        let logo = logo.resize(avatar_size, avatar_size / 2, FilterType::Lanczos3);
+        let x = (width as i64 - margin - logo.width() as i64).max(0);
+        image::imageops::overlay(&mut canvas, &logo, x as u32, margin as u32);
+    }
+    draw_title(&mut canvas, card.title, &title_box, card.text_color);
+    Ok(canvas)
+}
+
+/// Draws `background` scaled to cover `width` x full height stripe of `canvas` starting at `x`.
+fn cover(canvas: &mut DynamicImage, background: &DynamicImage, x: u32, width: u32) {
+    if width == 0 || canvas.height() == 0 {
+        return;
+    }
+    let filled = background.resize_to_fill(width, canvas.height(), FilterType::Lanczos3);
+    image::imageops::overlay(canvas, &filled.to_rgba8(), x, 0);
+}
+
+/// Blends vertical gradient from transparent at the middle of `canvas` to `color` at its bottom.
+fn bottom_scrim(canvas: &mut DynamicImage, color: Rgba<u8>) {
+    let (width, height) = canvas.dimensions();
+    let top = height / 2;
+    for y in top..height {
+        let t = (y - top + 1) as f32 / (height - top) as f32;
+        let mut row = color;
+        row.0[3] = (color.0[3] as f32 * t).round() as u8;
+        draw::fill_rect(canvas, 0, y as i64, width as i64, y as i64 + 1, row);
+    }
+}
+
+/// Draws `avatar` cropped to circle of `size` pixels with top left corner at `(x, y)`.
+fn place_avatar(canvas: &mut DynamicImage, avatar: &DynamicImage, x: i64, y: i64, size: u32) {
+    if size == 0 {
+        return;
+    }
+    let mut circle: RgbaImage = avatar
+        .resize_to_fill(size, size, FilterType::Lanczos3)
+        .to_rgba8();
+    draw::round_corners(&mut circle, size as f32 / 2.0);
+    image::imageops::overlay(canvas, &circle, x.max(0) as u32, y.max(0) as u32);
+}
+
+/// Draws `title` with the largest scale at which it fits `MAX_TITLE_LINES` lines of `area`.
+/// Title is cut with ellipsis if it doesn't fit even at the smallest scale.
+fn draw_title(canvas: &mut DynamicImage, title: &str, area: &TitleBox, color: Rgba<u8>) {
+    if title.trim().is_empty() || area.width == 0 || area.height == 0 {
+        return;
+    }
+
+    // Capital letters are about a tenth of card height at most.
+    let mut scale = (canvas.height() / (10 * text::GLYPH_HEIGHT)).max(1);
+    let mut lines = text::wrap(title, area.width, scale);
+    while scale > 1 {
+        let height = lines.len() as u32 * text::LINE_HEIGHT * scale;
+        if lines.len() <= MAX_TITLE_LINES && height <= area.height {
+            break;
+        }
+        scale -= 1;
+        lines = text::wrap(title, area.width, scale);
+    }
+
+    let max_lines = (area.height / (text::LINE_HEIGHT * scale)).clamp(1, MAX_TITLE_LINES as u32);
+    if lines.len() > max_lines as usize {
+        lines.truncate(max_lines as usize);
+        if let Some(last) = lines.last_mut() {
+            while !last.is_empty() && text::width(&format!("{}...", last), scale) > area.width {
+                last.pop();
+            }
+            last.push_str("...");
+        }
+    }
+
+    // Text block is aligned to the bottom of title box unless it is centered.
+    let line_height = (text::LINE_HEIGHT * scale) as i64;
+    let block = lines.len() as i64 * line_height
+        - ((text::LINE_HEIGHT - text::GLYPH_HEIGHT) * scale) as i64;
+    let mut y = match area.centered {
+        true => area.y + (area.height as i64 - block) / 2,
+        false => area.y + area.height as i64 - block,
+    };
+    for line in &lines {
+        let x = match area.centered {
+            true => area.x + (area.width as i64 - text::width(line, scale) as i64) / 2,
+            false => area.x,
+        };
+        text::draw(canvas, line, x, y, scale, color);
+        y += line_height;
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{DecodeFn, EncodeFn, ImageData, ImageError, ImageHandle, LastError, ProbeFn};
+use image::{DynamicImage, GenericImageView};
+use std::ffi::c_void;
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+/// Number of leading bytes of stream passed to probe callbacks.
+const PROBE_LEN: usize = 256;
+
+/// Host-provided decoder of format.
+#[derive(Copy, Clone)]
+pub struct Decoder {
+    probe: Option<ProbeFn>,
+    decode: DecodeFn,
+    user_data: *mut c_void,
+}
+
+/// Host-provided encoder of format.
+#[derive(Copy, Clone)]
+pub struct Encoder {
+    encode: EncodeFn,
+    user_data: *mut c_void,
+}
+
+/// Registered codecs by lowercase format name in order of registration.
+#[derive(Default)]
+struct Registry {
+    decoders: Vec<(String, Decoder)>,
+    encoders: Vec<(String, Encoder)>,
+}
+
+// Host is responsible for making its callbacks and user data usable from any thread.
+unsafe impl Send for Registry {}
+
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+/// Runs `f` with locked registry.
+fn with_registry<T, F: FnOnce(&mut Registry) -> T>(f: F) -> T {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    f(registry.get_or_insert_with(Default::default))
+}
+
+/// Registers decoder of format `name`, replacing previous one, or removes it if `decode` is
+/// `None`.
+pub fn register_decoder(
+    name: &str,
+    probe: Option<ProbeFn>,
+    decode: Option<DecodeFn>,
+    user_data: *mut c_void,
+) {
+    let name = name.to_lowercase();
+    with_registry(|r| {
+        r.decoders.retain(|(n, _)| *n != name);
+        if let Some(decode) = decode {
+            let decoder = Decoder {
+                probe,
+                decode,
+                user_data,
+            };
+            r.decoders.push((name, decoder));
+        }
+    });
+}
+
+/// Registers encoder of format `name`, replacing previous one, or removes it if `encode` is
+/// `None`.
+pub fn register_encoder(name: &str, encode: Option<EncodeFn>, user_data: *mut c_void) {
+    let name = name.to_lowercase();
+    with_registry(|r| {
+        r.encoders.retain(|(n, _)| *n != name);
+        if let Some(encode) = encode {
+            r.encoders.push((name, Encoder { encode, user_data }));
+        }
+    });
+}
+
+/// Removes all registered codecs.
+pub fn clear() {
+    *REGISTRY.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Returns decoder registered for format `name`, e.g. file extension.
+pub fn decoder(name: &str) -> Option<Decoder> {
+    let name = name.to_lowercase();
+    with_registry(|r| r.decoders.iter().find(|(n, _)| *n == name).map(|(_, d)| *d))
+}
+
+/// Returns encoder registered for format `name`, e.g. file extension.
+pub fn encoder(name: &str) -> Option<Encoder> {
+    let name = name.to_lowercase();
+    with_registry(|r| r.encoders.iter().find(|(n, _)| *n == name).map(|(_, e)| *e))
+}
+
+/// Returns the first registered decoder whose probe callback recognizes stream of `reader`.
+/// Position of `reader` is kept.
+pub fn probe<R: BufRead + Seek>(reader: &mut R) -> std::io::Result<Option<Decoder>> {
+    // Registry isn't locked during callbacks, so they may call library functions.
+    let decoders: Vec<_> = with_registry(|r| r.decoders.iter().map(|(_, d)| *d).collect());
+    if decoders.iter().all(|d| d.probe.is_none()) {
+        return Ok(None);
+    }
+
+    let start = reader.stream_position()?;
+    let mut header = Vec::with_capacity(PROBE_LEN);
+    reader
+        .by_ref()
+        .take(PROBE_LEN as u64)
+        .read_to_end(&mut header)?;
+    reader.seek(SeekFrom::Start(start))?;
+    let found = decoders.into_iter().find(|d| match d.probe {
+        Some(probe) => unsafe { probe(header.as_ptr(), header.len(), d.user_data) },
+        None => false,
+    });
+    Ok(found)
+}
+
+impl Decoder {
+    /// Decodes encoded image `data` by host callback.
+    pub fn decode(&self, data: &[u8]) -> Result<DynamicImage, ImageError> {
+        let mut handle = ImageHandle(std::ptr::null_mut());
+        match unsafe { (self.decode)(data.as_ptr(), data.len(), self.user_data, &mut handle) } {
+            ImageError::NoError => {}
+            e => return Err(e),
+        }
+        if !unsafe { handle.is_valid() } {
+            let message = "Codec plugin returned invalid image handle".to_string();
+            crate::set_last_error(LastError::new(ImageError::Internal, message));
+            return Err(ImageError::Internal);
+        }
+
+        // Image is moved out of handle, which is destroyed then.
+        let mut data = unsafe { handle.into_data() };
+        Ok(std::mem::replace(
+            &mut data.image,
+            DynamicImage::new_rgba8(0, 0),
+        ))
+    }
+}
+
+impl Encoder {
+    /// Encodes RGBA pixels of `data` by host callback writing to `writer`.
+    pub fn encode<W: Write>(&self, data: &ImageData, writer: &mut W) -> Result<(), ImageError> {
+        let pixels = crate::simd::to_rgba8(&data.image)?;
+        let (width, height) = data.image.dimensions();
+        let mut sink = Sink {
+            writer,
+            error: None,
+        };
+        let sink_ptr = &mut sink as *mut Sink<W> as *mut c_void;
+        let result = unsafe {
+            (self.encode)(
+                pixels.as_ptr(),
+                width,
+                height,
+                write_sink::<W>,
+                sink_ptr,
+                self.user_data,
+            )
+        };
+        match (result, sink.error) {
+            (_, Some(e)) => Err(crate::io_error(e)),
+            (ImageError::NoError, None) => Ok(()),
+            (e, None) => Err(e),
+        }
+    }
+}
+
+/// Writer passed to encoder callback, remembering the first write error.
+struct Sink<'a, W> {
+    writer: &'a mut W,
+    error: Option<std::io::Error>,
+}
+
+/// Write callback passed to encoder: writes `len` bytes of `data` to `Sink` at `sink`.
+unsafe extern "C" fn write_sink<W: Write>(sink: *mut c_void, data: *const u8, len: usize) -> isize {
+    let sink = &mut *(sink as *mut Sink<W>);
+    if len == 0 {
+        return 0;
+    }
+    if sink.error.is_some() {
+        return -1;
+    }
+    match sink.writer.write_all(std::slice::from_raw_parts(data, len)) {
+        Ok(_) => len as isize,
+        Err(e) => {
+            sink.error = Some(e);
+            -1
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::ImageError;
+use image::DynamicImage;
+
+/// Returns pixels of `image` converted from color space described by ICC profile `icc` to sRGB.
+/// Images with more than 8 bits per channel are reduced to 8 bits. Grayscale profiles are
+/// applied to luma of image.
+#[cfg(feature = "icc")]
+pub fn convert_to_srgb(image: &DynamicImage, icc: &[u8]) -> Result<DynamicImage, ImageError> {
+    use image::{GenericImageView, ImageBuffer};
+    use qcms::{DataType, Intent, Profile, Transform};
+
+    let input = Profile::new_from_slice(icc, false).ok_or(ImageError::Decoding)?;
+    let mut output = Profile::new_sRGB();
+    output.precache_output_transform();
+
+    let gray = match icc.get(16..20) {
+        Some(b"RGB ") => false,
+        Some(b"GRAY") => true,
+        _ => return Err(ImageError::Unsupported),
+    };
+    let alpha = image.color().has_alpha();
+    let (source, input_type, output_type) = match (gray, alpha) {
+        (false, false) => (image.to_rgb8().into_raw(), DataType::RGB8, DataType::RGB8),
+        (false, true) => (
+            image.to_rgba8().into_raw(),
+            DataType::RGBA8,
+            DataType::RGBA8,
+        ),
+        (true, false) => (image.to_luma8().into_raw(), DataType::Gray8, DataType::RGB8),
+        (true, true) => (
+            image.to_luma_alpha8().into_raw(),
+            DataType::GrayA8,
+            DataType::RGBA8,
+        ),
+    };
+
+    let transform = Transform::new_to(&input, &output, input_type, output_type, Intent::default())
+        .ok_or(ImageError::Unsupported)?;
+
+    let (width, height) = image.dimensions();
+    let pixels = width as usize * height as usize;
+    let mut converted = vec![0; pixels * output_type.bytes_per_pixel()];
+    transform.convert(&source, &mut converted);
+
+    let converted = match alpha {
+        false => ImageBuffer::from_raw(width, height, converted).map(DynamicImage::ImageRgb8),
+        true => ImageBuffer::from_raw(width, height, converted).map(DynamicImage::ImageRgba8),
+    };
+    converted.ok_or(ImageError::Decoding)
+}
+
+/// Color management is available only with `icc` feature.
+#[cfg(not(feature = "icc"))]
+pub fn convert_to_srgb(_image: &DynamicImage, _icc: &[u8]) -> Result<DynamicImage, ImageError> {
+    Err(ImageError::Unsupported)
+}
@@ -0,0 +1,193 @@
+use crate::Rect;
+use image::{DynamicImage, GenericImageView};
+
+/// Size of the longest side of downscaled image used to score crops.
+const ANALYSIS_SIZE: u32 = 256;
+/// Crop sizes tried relative to the largest crop fitting into image.
+const SCALES: [f32; 4] = [1.0, 0.85, 0.7, 0.55];
+/// Number of crop positions tried along each axis.
+const STEPS: u32 = 16;
+/// Crops overlapping a better one more than this are not suggested.
+const MAX_OVERLAP: f32 = 0.6;
+/// Rule-of-thirds and golden-ratio lines in fractions of crop size.
+const POWER_LINES: [f64; 4] = [1.0 / 3.0, 2.0 / 3.0, 0.381_966, 0.618_034];
+/// Weight of energy at composition power points relative to covered energy.
+const FOCUS_WEIGHT: f32 = 0.5;
+
+/// Returns up to `max` crop rectangles of `aspect_w:aspect_h` aspect ratio ranked from best to
+/// worst. Crops are scored by edge energy they cover and by energy at rule-of-thirds and
+/// golden-ratio points of the crop.
+pub fn suggest_crops(image: &DynamicImage, aspect_w: u32, aspect_h: u32, max: usize) -> Vec<Rect> {
+    let (width, height) = image.dimensions();
+    let energy = EnergyMap::new(image);
+
+    let mut candidates = Vec::new();
+    for size in crop_sizes(width, height, aspect_w, aspect_h) {
+        for rect in crop_positions(width, height, size) {
+            candidates.push((energy.score(&rect, width, height), rect));
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut suggestions: Vec<Rect> = Vec::new();
+    for (_, rect) in candidates {
+        if suggestions.len() >= max {
+            break;
+        }
+        if suggestions.iter().all(|s| overlap(s, &rect) <= MAX_OVERLAP) {
+            suggestions.push(rect);
+        }
+    }
+    suggestions
+}
+
+/// Returns the largest crop rectangle of `aspect_w:aspect_h` aspect ratio fitting into image at
+/// position scored best the way `suggest_crops` scores crops, e.g. for thumbnails which keep
+/// the interesting part of photo.
+pub fn smart_crop(image: &DynamicImage, aspect_w: u32, aspect_h: u32) -> Rect {
+    let (width, height) = image.dimensions();
+    let energy = EnergyMap::new(image);
+    let size = crop_sizes(width, height, aspect_w, aspect_h)[0];
+    let crops = crop_positions(width, height, size).into_iter();
+    let scored = crops.map(|rect| (energy.score(&rect, width, height), rect));
+    let best = scored.max_by(|a, b| a.0.total_cmp(&b.0));
+    best.expect("crop fits at least one position").1
+}
+
+/// Returns distinct crop sizes of given aspect ratio fitting into `width` x `height`.
+fn crop_sizes(width: u32, height: u32, aspect_w: u32, aspect_h: u32) -> Vec<(u32, u32)> {
+    let (aspect_w, aspect_h) = (aspect_w as f64, aspect_h as f64);
+    let (max_w, max_h) = match width as f64 * aspect_h <= height as f64 * aspect_w {
+        true => (width as f64, width as f64 * aspect_h / aspect_w),
+        false => (height as f64 * aspect_w / aspect_h, height as f64),
+    };
+
+    let mut sizes: Vec<(u32, u32)> = SCALES
+        .iter()
+        .map(|&s| {
+            let w = (max_w * s as f64).round().clamp(1.0, width as f64) as u32;
+            let h = (max_h * s as f64).round().clamp(1.0, height as f64) as u32;
+            (w, h)
+        })
+        .collect();
+    sizes.dedup();
+    sizes
+}
+
+/// Returns crops of `size` evenly distributed over `width` x `height` image.
+fn crop_positions(width: u32, height: u32, size: (u32, u32)) -> Vec<Rect> {
+    let positions = |free: u32| {
+        let steps = STEPS.min(free);
+        (0..=steps).map(move |i| match steps {
+            0 => 0,
+            _ => (free as u64 * i as u64 / steps as u64) as u32,
+        })
+    };
+
+    let (w, h) = size;
+    let mut rects = Vec::new();
+    for y in positions(height - h) {
+        for x in positions(width - w) {
+            rects.push(Rect {
+                x,
+                y,
+                width: w,
+                height: h,
+            });
+        }
+    }
+    rects
+}
+
+/// Returns intersection area of `a` and `b` relative to area of the smaller one.
+fn overlap(a: &Rect, b: &Rect) -> f32 {
+    let w = (a.x + a.width)
+        .min(b.x + b.width)
+        .saturating_sub(a.x.max(b.x));
+    let h = (a.y + a.height)
+        .min(b.y + b.height)
+        .saturating_sub(a.y.max(b.y));
+    let smaller = (a.width as u64 * a.height as u64).min(b.width as u64 * b.height as u64);
+    (w as u64 * h as u64) as f32 / smaller.max(1) as f32
+}
+
+/// Summed-area table of gradient magnitude of downscaled image.
+struct EnergyMap {
+    width: u32,
+    height: u32,
+    sums: Vec<f64>,
+}
+
+impl EnergyMap {
+    fn new(image: &DynamicImage) -> Self {
+        let luma = image.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).to_luma8();
+        let (width, height) = luma.dimensions();
+        let at = |x: u32, y: u32| luma.get_pixel(x, y).0[0] as f64;
+
+        let stride = width as usize + 1;
+        let mut sums = vec![0.0; stride * (height as usize + 1)];
+        for y in 0..height {
+            let mut row_sum = 0.0;
+            for x in 0..width {
+                let dx = at((x + 1).min(width - 1), y) - at(x.saturating_sub(1), y);
+                let dy = at(x, (y + 1).min(height - 1)) - at(x, y.saturating_sub(1));
+                row_sum += dx.abs() + dy.abs();
+
+                let i = (y as usize + 1) * stride + x as usize + 1;
+                sums[i] = sums[i - stride] + row_sum;
+            }
+        }
+
+        Self {
+            width,
+            height,
+            sums,
+        }
+    }
+
+    /// Returns energy in rectangle given in fractions of image size.
+    fn sum(&self, x0: f64, y0: f64, x1: f64, y1: f64) -> f64 {
+        let col = |x: f64| {
+            (x * self.width as f64)
+                .round()
+                .clamp(0.0, self.width as f64) as usize
+        };
+        let row = |y: f64| {
+            (y * self.height as f64)
+                .round()
+                .clamp(0.0, self.height as f64) as usize
+        };
+        let stride = self.width as usize + 1;
+        let at = |x: usize, y: usize| self.sums[y * stride + x];
+
+        let (x0, x1, y0, y1) = (col(x0), col(x1), row(y0), row(y1));
+        at(x1, y1) - at(x0, y1) - at(x1, y0) + at(x0, y0)
+    }
+
+    /// Scores `rect` of image of `width` x `height` pixels.
+    fn score(&self, rect: &Rect, width: u32, height: u32) -> f32 {
+        let (w, h) = (width as f64, height as f64);
+        let x0 = rect.x as f64 / w;
+        let y0 = rect.y as f64 / h;
+        let cw = rect.width as f64 / w;
+        let ch = rect.height as f64 / h;
+
+        let total = self.sum(0.0, 0.0, 1.0, 1.0).max(f64::EPSILON);
+        let inside = self.sum(x0, y0, x0 + cw, y0 + ch);
+
+        let radius_x = cw / 12.0;
+        let radius_y = ch / 12.0;
+        let focus: f64 = POWER_LINES
+            .iter()
+            .flat_map(|&fx| POWER_LINES.iter().map(move |&fy| (fx, fy)))
+            .map(|(fx, fy)| {
+                let (px, py) = (x0 + fx * cw, y0 + fy * ch);
+                self.sum(px - radius_x, py - radius_y, px + radius_x, py + radius_y)
+            })
+            .sum();
+
+        let coverage = inside / total;
+        let focus = focus / inside.max(f64::EPSILON);
+        (coverage + FOCUS_WEIGHT as f64 * focus.min(1.0)) as f32
+    }
+}
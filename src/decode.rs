@@ -0,0 +1,113 @@
+use crate::{ImageError, OpenOptions};
+use image::codecs::{bmp, dds, farbfeld, gif, hdr, ico, jpeg, png, pnm, tga, tiff, webp};
+use image::{DynamicImage, ImageDecoder, ImageFormat};
+use std::io::{BufRead, BufReader, Seek};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Maximum width of any decoded image. Zero means no limit.
+static MAX_WIDTH: AtomicU32 = AtomicU32::new(0);
+/// Maximum height of any decoded image. Zero means no limit.
+static MAX_HEIGHT: AtomicU32 = AtomicU32::new(0);
+/// Maximum size of pixels of any decoded image in bytes. Zero means no limit.
+static MAX_MEMORY: AtomicU64 = AtomicU64::new(0);
+
+/// Sets limits applied to every decoded image. Zero means no limit.
+pub fn set_limits(max_width: u32, max_height: u32, max_memory: u64) {
+    MAX_WIDTH.store(max_width, Ordering::Relaxed);
+    MAX_HEIGHT.store(max_height, Ordering::Relaxed);
+    MAX_MEMORY.store(max_memory, Ordering::Relaxed);
+}
+
+/// Returns global limit of size of pixels of decoded image in bytes, zero if there is none.
+pub fn max_memory() -> u64 {
+    MAX_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Decodes image in `format` from `reader`. Checks image dimensions and decoded size against
+/// `options` and global limits before pixels are decoded.
+pub fn load<R: BufRead + Seek>(
+    reader: R,
+    format: ImageFormat,
+    options: &OpenOptions,
+) -> Result<DynamicImage, ImageError> {
+    // Decoding is interrupted by reading error once deadline of operation budget has passed.
+    let reader = crate::budget::Stream::new(reader);
+    match format {
+        ImageFormat::Png => load_checked(png::PngDecoder::new(reader)?, options),
+        ImageFormat::Jpeg => load_checked(jpeg::JpegDecoder::new(reader)?, options),
+        ImageFormat::Gif => load_checked(gif::GifDecoder::new(reader)?, options),
+        ImageFormat::WebP => load_checked(webp::WebPDecoder::new(reader)?, options),
+        ImageFormat::Pnm => load_checked(pnm::PnmDecoder::new(BufReader::new(reader))?, options),
+        ImageFormat::Tiff => load_checked(tiff::TiffDecoder::new(reader)?, options),
+        ImageFormat::Tga => load_checked(tga::TgaDecoder::new(reader)?, options),
+        ImageFormat::Dds => load_checked(dds::DdsDecoder::new(reader)?, options),
+        ImageFormat::Bmp => load_checked(bmp::BmpDecoder::new(reader)?, options),
+        ImageFormat::Ico => load_checked(ico::IcoDecoder::new(reader)?, options),
+        ImageFormat::Hdr => load_checked(hdr::HdrAdapter::new(BufReader::new(reader))?, options),
+        ImageFormat::Farbfeld => load_checked(farbfeld::FarbfeldDecoder::new(reader)?, options),
+        _ => Err(ImageError::Unsupported),
+    }
+}
+
+/// Decodes image with `decoder` if it fits into `options` and global limits.
+fn load_checked<'a, D: ImageDecoder<'a>>(
+    decoder: D,
+    options: &OpenOptions,
+) -> Result<DynamicImage, ImageError> {
+    let (width, height) = decoder.dimensions();
+    check_limits(width, height, decoder.total_bytes(), options)?;
+    Ok(DynamicImage::from_decoder(decoder)?)
+}
+
+/// Checks `width` x `height` image of `bytes` decoded pixels against `options` and global
+/// limits. Returns `ImageError::LimitExceeded` if it doesn't fit and `ImageError::OutOfMemory`
+/// if its pixels can't be allocated.
+pub fn check_limits(
+    width: u32,
+    height: u32,
+    bytes: u64,
+    options: &OpenOptions,
+) -> Result<(), ImageError> {
+    let exceeds = |value: u64, limit: u64, global_limit: u64| {
+        (limit != 0 && value > limit) || (global_limit != 0 && value > global_limit)
+    };
+
+    let max_width = MAX_WIDTH.load(Ordering::Relaxed) as u64;
+    let max_height = MAX_HEIGHT.load(Ordering::Relaxed) as u64;
+    let max_memory = MAX_MEMORY.load(Ordering::Relaxed);
+
+    if exceeds(width as u64, options.max_width as u64, max_width)
+        || exceeds(height as u64, options.max_height as u64, max_height)
+        || exceeds(bytes, options.max_memory, max_memory)
+    {
+        return Err(ImageError::LimitExceeded);
+    }
+    crate::check_alloc(bytes)
+}
+
+/// Maximum ratio of sizes of inflated and deflated data.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// Inflates deflate stream `packed`, wrapped in zlib header if `zlib` is set, of at most `size`
+/// bytes. Returns `None` for invalid or larger streams, error if output can't be allocated.
+pub fn inflate(packed: &[u8], size: usize, zlib: bool) -> Result<Option<Vec<u8>>, ImageError> {
+    use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
+    use miniz_oxide::inflate::TINFLStatus;
+
+    let mut flags = inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    if zlib {
+        flags |= inflate_flags::TINFL_FLAG_PARSE_ZLIB_HEADER;
+    }
+    // Output larger than `packed` can expand to would be left unwritten.
+    let size = size.min(packed.len().saturating_mul(MAX_DEFLATE_RATIO));
+    crate::check_alloc(size as u64)?;
+    let mut data = crate::try_alloc(size, 0)?;
+    let mut decompressor = Box::<DecompressorOxide>::default();
+    match decompress(&mut decompressor, packed, &mut data, 0, flags) {
+        (TINFLStatus::Done, _, written) => {
+            data.truncate(written);
+            Ok(Some(data))
+        }
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{simd, BlendMode, ImageData, ImageError};
+use image::{ColorType, DynamicImage, GenericImageView, ImageBuffer};
+
+/// Image placed on canvas of document.
+pub struct Layer {
+    pub data: Box<ImageData>,
+    /// Offset of top left corner of image on canvas. Parts outside of canvas are clipped.
+    pub x: i32,
+    pub y: i32,
+    /// Layer opacity in `[0, 1]` multiplied with alpha of image.
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub visible: bool,
+}
+
+/// Canvas with ordered stack of layers. The first layer is the bottom one.
+pub struct Document {
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<Layer>,
+}
+
+impl Document {
+    /// Composites visible layers bottom to top over transparent canvas. Result has 16 bits per
+    /// channel if some visible layer has more than 8 bits per channel, 8 bits otherwise. Fails
+    /// with `ImageError::OutOfMemory` if canvas or result can't be allocated.
+    pub fn flatten(&self) -> Result<DynamicImage, ImageError> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let area = width.checked_mul(height).ok_or(ImageError::LimitExceeded)?;
+        let mut canvas = crate::try_alloc(area, [0f32; 4])?;
+        let mut deep = false;
+
+        for layer in self.layers.iter().filter(|l| l.visible) {
+            let image = &layer.data.image;
+            let (w, h) = image.dimensions();
+            let source = match image.color() {
+                ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => {
+                    let pixels = simd::to_rgba8(image)?.into_raw();
+                    let mut source = crate::try_alloc(pixels.len(), 0.0)?;
+                    simd::unorm8_to_f32(&pixels, &mut source);
+                    source
+                }
+                _ => {
+                    deep = true;
+                    let pixels = image.to_rgba16().into_raw();
+                    let mut source = crate::try_alloc(pixels.len(), 0.0)?;
+                    for (s, &v) in source.iter_mut().zip(&pixels) {
+                        *s = v as f32 / u16::MAX as f32;
+                    }
+                    source
+                }
+            };
+
+            let (x, y) = (layer.x as i64, layer.y as i64);
+            let x0 = x.max(0);
+            let y0 = y.max(0);
+            let x1 = (x + w as i64).min(width as i64);
+            let y1 = (y + h as i64).min(height as i64);
+
+            if x0 >= x1 {
+                continue;
+            }
+            for cy in y0..y1 {
+                let offset = 4 * ((cy - y) as usize * w as usize + (x0 - x) as usize);
+                let len = (x1 - x0) as usize;
+                let start = cy as usize * width + x0 as usize;
+                let backdrop = &mut canvas[start..start + len];
+                let pixels = &source[offset..offset + 4 * len];
+                simd::composite_row(backdrop, pixels, layer.opacity, layer.blend_mode);
+            }
+        }
+
+        let pixels = canvas.iter().flatten();
+        let len = canvas.len() * 4;
+        match deep {
+            true => {
+                let max = u16::MAX as f32;
+                let mut raw = crate::try_alloc(len, 0)?;
+                for (r, &v) in raw.iter_mut().zip(pixels) {
+                    *r = (v * max).round() as u16;
+                }
+                let buffer = ImageBuffer::from_raw(self.width, self.height, raw);
+                Ok(DynamicImage::ImageRgba16(
+                    buffer.expect("buffer matches canvas size"),
+                ))
+            }
+            false => {
+                let max = u8::MAX as f32;
+                let mut raw = crate::try_alloc(len, 0)?;
+                for (r, &v) in raw.iter_mut().zip(pixels) {
+                    *r = (v * max).round() as u8;
+                }
+                let buffer = ImageBuffer::from_raw(self.width, self.height, raw);
+                Ok(DynamicImage::ImageRgba8(
+                    buffer.expect("buffer matches canvas size"),
+                ))
+            }
+        }
+    }
+}
+
+/// Composites `source` color with layer `opacity` over `backdrop` with `mode`. Colors are
+/// non-premultiplied RGBA in `[0, 1]`.
+pub fn composite(backdrop: [f32; 4], source: [f32; 4], opacity: f32, mode: BlendMode) -> [f32; 4] {
+    let alpha_b = backdrop[3];
+    let alpha_s = source[3] * opacity;
+    let alpha = alpha_s + alpha_b * (1.0 - alpha_s);
+    if alpha <= 0.0 {
+        return [0.0; 4];
+    }
+
+    let mut result = [0.0, 0.0, 0.0, alpha];
+    for c in 0..3 {
+        let (cb, cs) = (backdrop[c], source[c]);
+        // Blended color is used where layer overlaps opaque backdrop.
+        let mixed = (1.0 - alpha_b) * cs + alpha_b * blend(mode, cb, cs);
+        let color = alpha_s * mixed + (1.0 - alpha_s) * alpha_b * cb;
+        result[c] = (color / alpha).clamp(0.0, 1.0);
+    }
+    result
+}
+
+/// Returns `source` channel blended over `backdrop` channel with separable `mode`.
+fn blend(mode: BlendMode, backdrop: f32, source: f32) -> f32 {
+    let screen = |b: f32, s: f32| b + s - b * s;
+    match mode {
+        BlendMode::Normal => source,
+        BlendMode::Multiply => backdrop * source,
+        BlendMode::Screen => screen(backdrop, source),
+        BlendMode::Overlay if backdrop <= 0.5 => 2.0 * backdrop * source,
+        BlendMode::Overlay => screen(2.0 * backdrop - 1.0, source),
+        BlendMode::Darken => backdrop.min(source),
+        BlendMode::Lighten => backdrop.max(source),
+        BlendMode::Add => (backdrop + source).min(1.0),
+        BlendMode::Difference => (backdrop - source).abs(),
+    }
+}
@@ -0,0 +1,300 @@
+use image::{
+    DynamicImage, GenericImage, GenericImageView, ImageBuffer, Pixel, Primitive, Rgba, RgbaImage,
+};
+use num_traits::NumCast;
+
+/// RGBA image of 16-bit channels.
+type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Shapes drawn by `draw_shape` in image coordinates, where `(0, 0)` is top left corner of image
+/// and centers of pixels are at half-integer coordinates.
+pub enum Shape<'a> {
+    /// Segment between two points.
+    Line([f32; 2], [f32; 2]),
+    /// Circle of center and radius.
+    Circle([f32; 2], f32),
+    /// Closed polygon of vertices, which may intersect itself. Its inside is given by non-zero
+    /// winding rule.
+    Polygon(&'a [[f32; 2]]),
+}
+
+impl Shape<'_> {
+    /// Returns `true` if coordinates of shape are finite, radius of circle isn't negative and
+    /// polygon has at least 3 vertices.
+    pub fn is_valid(&self) -> bool {
+        let finite = |p: &[f32; 2]| p[0].is_finite() && p[1].is_finite();
+        match self {
+            Shape::Line(a, b) => finite(a) && finite(b),
+            Shape::Circle(c, r) => finite(c) && r.is_finite() && *r >= 0.0,
+            Shape::Polygon(points) => points.len() >= 3 && points.iter().all(finite),
+        }
+    }
+
+    /// Returns distance from `p` to outline of shape, negative inside of shape. Lines have no
+    /// inside.
+    fn signed_distance(&self, p: [f32; 2]) -> f32 {
+        match self {
+            Shape::Line(a, b) => segment_distance(p, *a, *b),
+            Shape::Circle(center, radius) => (p[0] - center[0]).hypot(p[1] - center[1]) - radius,
+            Shape::Polygon(points) => {
+                let edges = points.iter().zip(points.iter().cycle().skip(1));
+                let mut distance = f32::INFINITY;
+                let mut winding = 0;
+                for (a, b) in edges {
+                    distance = distance.min(segment_distance(p, *a, *b));
+                    // Edges crossing horizontal ray to the right of `p` wind around it.
+                    if (a[1] <= p[1]) != (b[1] <= p[1]) {
+                        let t = (p[1] - a[1]) / (b[1] - a[1]);
+                        if a[0] + t * (b[0] - a[0]) > p[0] {
+                            winding += if b[1] > a[1] { 1 } else { -1 };
+                        }
+                    }
+                }
+                if winding != 0 {
+                    -distance
+                } else {
+                    distance
+                }
+            }
+        }
+    }
+
+    /// Returns bounds `[x0, y0, x1, y1]` of shape.
+    fn bounds(&self) -> [f32; 4] {
+        let points = |points: &mut dyn Iterator<Item = [f32; 2]>| {
+            let init = [
+                f32::INFINITY,
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+                f32::NEG_INFINITY,
+            ];
+            points.fold(init, |b, p| {
+                [
+                    b[0].min(p[0]),
+                    b[1].min(p[1]),
+                    b[2].max(p[0]),
+                    b[3].max(p[1]),
+                ]
+            })
+        };
+        match self {
+            Shape::Line(a, b) => points(&mut [*a, *b].iter().copied()),
+            Shape::Circle(c, r) => [c[0] - r, c[1] - r, c[0] + r, c[1] + r],
+            Shape::Polygon(vertices) => points(&mut vertices.iter().copied()),
+        }
+    }
+}
+
+/// Blends `color` over `shape` drawn on `image`, antialiasing its edges: fills its inside if
+/// `fill` is set, otherwise strokes its outline with line of `thickness` pixels centered on it
+/// with round caps and joins. Lines are always stroked.
+pub fn draw_shape(
+    image: &mut DynamicImage,
+    shape: &Shape,
+    thickness: f32,
+    fill: bool,
+    color: Rgba<u8>,
+) {
+    let fill = fill && !matches!(shape, Shape::Line(..));
+    let margin = if fill { 1.0 } else { thickness / 2.0 + 1.0 };
+    let [x0, y0, x1, y1] = shape.bounds();
+    let (width, height) = image.dimensions();
+    let clamp = |v: f32, max: u32| v.clamp(0.0, max as f32) as u32;
+    let (x0, x1) = (
+        clamp(x0 - margin, width),
+        clamp((x1 + margin).ceil(), width),
+    );
+    let (y0, y1) = (
+        clamp(y0 - margin, height),
+        clamp((y1 + margin).ceil(), height),
+    );
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            // Coverage of pixel is approximated by distance of its center to edge of shape.
+            let distance = shape.signed_distance([x as f32 + 0.5, y as f32 + 0.5]);
+            let coverage = match fill {
+                true => 0.5 - distance,
+                false => thickness / 2.0 + 0.5 - distance.abs(),
+            };
+            if coverage > 0.0 {
+                blend_pixel(image, x, y, color, coverage.min(1.0));
+            }
+        }
+    }
+}
+
+/// Returns distance from `p` to segment between `a` and `b`.
+fn segment_distance(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length = dx * dx + dy * dy;
+    let t = match length > 0.0 {
+        true => (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / length).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    (p[0] - a[0] - t * dx).hypot(p[1] - a[1] - t * dy)
+}
+
+/// Blends `color` with alpha scaled by `coverage` over pixel at `x`, `y` of `image`. 16-bit
+/// images are blended at their precision.
+fn blend_pixel(image: &mut DynamicImage, x: u32, y: u32, color: Rgba<u8>, coverage: f32) {
+    let [r, g, b, a] = color.0;
+    let alpha = a as f32 * coverage;
+    let [wide_r, wide_g, wide_b] = [r, g, b].map(|c| c as u16 * 257);
+    let wide = Rgba([wide_r, wide_g, wide_b, (alpha * 257.0).round() as u16]);
+    let blend = |mut pixel: Rgba<u16>| {
+        pixel.blend(&wide);
+        pixel
+    };
+    match image {
+        DynamicImage::ImageLuma16(i) => {
+            let pixel = blend(i.get_pixel(x, y).to_rgba());
+            i.put_pixel(x, y, pixel.to_luma());
+        }
+        DynamicImage::ImageLumaA16(i) => {
+            let pixel = blend(i.get_pixel(x, y).to_rgba());
+            i.put_pixel(x, y, pixel.to_luma_alpha());
+        }
+        DynamicImage::ImageRgb16(i) => {
+            let pixel = blend(i.get_pixel(x, y).to_rgba());
+            i.put_pixel(x, y, pixel.to_rgb());
+        }
+        DynamicImage::ImageRgba16(i) => {
+            let pixel = blend(*i.get_pixel(x, y));
+            i.put_pixel(x, y, pixel);
+        }
+        image => {
+            let mut pixel = image.get_pixel(x, y);
+            pixel.blend(&Rgba([r, g, b, alpha.round() as u8]));
+            image.put_pixel(x, y, pixel);
+        }
+    }
+}
+
+/// Blends `color` over pixels of rectangle `[x0, x1) x [y0, y1)` clipped by `image` bounds.
+pub fn fill_rect(image: &mut DynamicImage, x0: i64, y0: i64, x1: i64, y1: i64, color: Rgba<u8>) {
+    let (width, height) = image.dimensions();
+    let x0 = x0.clamp(0, width as i64) as u32;
+    let x1 = x1.clamp(0, width as i64) as u32;
+    let y0 = y0.clamp(0, height as i64) as u32;
+    let y1 = y1.clamp(0, height as i64) as u32;
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let mut pixel = image.get_pixel(x, y);
+            pixel.blend(&color);
+            image.put_pixel(x, y, pixel);
+        }
+    }
+}
+
+/// Draws vertical line of `width` pixels centered at `x` across `[y0, y1)`.
+pub fn vertical_line(
+    image: &mut DynamicImage,
+    x: i64,
+    y0: i64,
+    y1: i64,
+    width: u32,
+    color: Rgba<u8>,
+) {
+    let x0 = x - width as i64 / 2;
+    fill_rect(image, x0, y0, x0 + width as i64, y1, color);
+}
+
+/// Draws horizontal line of `width` pixels centered at `y` across `[x0, x1)`.
+pub fn horizontal_line(
+    image: &mut DynamicImage,
+    y: i64,
+    x0: i64,
+    x1: i64,
+    width: u32,
+    color: Rgba<u8>,
+) {
+    let y0 = y - width as i64 / 2;
+    fill_rect(image, x0, y0, x1, y0 + width as i64, color);
+}
+
+/// Draws outline of rectangle `[x0, x1) x [y0, y1)` with lines of `width` pixels inside it.
+pub fn rect_outline(
+    image: &mut DynamicImage,
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+    width: u32,
+    color: Rgba<u8>,
+) {
+    let w = width as i64;
+    fill_rect(image, x0, y0, x1, y0 + w, color);
+    fill_rect(image, x0, y1 - w, x1, y1, color);
+    fill_rect(image, x0, y0 + w, x0 + w, y1 - w, color);
+    fill_rect(image, x1 - w, y0 + w, x1, y1 - w, color);
+}
+
+/// Makes corners of `image` transparent outside arcs of `radius` pixels, antialiasing their
+/// edges. Radius of half the shorter side of square image gives circle.
+pub fn round_corners<S: Primitive + 'static>(
+    image: &mut ImageBuffer<Rgba<S>, Vec<S>>,
+    radius: f32,
+) {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let radius = radius.clamp(0.0, width.min(height) / 2.0);
+    mask_alpha(image, |px, py| {
+        // Distance from pixel center to the nearest point of rectangle inset by radius.
+        let dx = (radius - px).max(px - (width - radius)).max(0.0);
+        let dy = (radius - py).max(py - (height - radius)).max(0.0);
+        (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0)
+    });
+}
+
+/// Makes `image` transparent outside of circle of diameter of its shorter side in its center,
+/// antialiasing its edge.
+pub fn circle_mask<S: Primitive + 'static>(image: &mut ImageBuffer<Rgba<S>, Vec<S>>) {
+    let (cx, cy) = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+    let radius = cx.min(cy);
+    mask_alpha(image, |px, py| {
+        (radius - (px - cx).hypot(py - cy) + 0.5).clamp(0.0, 1.0)
+    });
+}
+
+/// Multiplies alpha of `image` by Rec. 709 luma of `mask` of the same size, or by its
+/// complement if `invert` is set. Alpha of mask is ignored.
+pub fn luma_mask<S>(image: &mut ImageBuffer<Rgba<S>, Vec<S>>, mask: &Rgba16Image, invert: bool)
+where
+    S: Primitive + 'static,
+{
+    mask_alpha(image, |px, py| {
+        let [r, g, b, _] = mask.get_pixel(px as u32, py as u32).0;
+        let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+        let luma = (luma / u16::MAX as f32).min(1.0);
+        match invert {
+            true => 1.0 - luma,
+            false => luma,
+        }
+    });
+}
+
+/// Multiplies alpha of pixels of `image` by `coverage` of their centers in `[0, 1]`.
+fn mask_alpha<S, F>(image: &mut ImageBuffer<Rgba<S>, Vec<S>>, coverage: F)
+where
+    S: Primitive + 'static,
+    F: Fn(f32, f32) -> f32,
+{
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let coverage = coverage(x as f32 + 0.5, y as f32 + 0.5);
+        let alpha = pixel.0[3].to_f32().unwrap_or(0.0) * coverage;
+        pixel.0[3] = NumCast::from(alpha.round()).unwrap_or(pixel.0[3]);
+    }
+}
+
+/// Blends `mark` with its alpha scaled by `opacity` over `image` with top left corner at `x`,
+/// `y`. Parts of mark outside of image are clipped.
+pub fn stamp(image: &mut DynamicImage, mark: &RgbaImage, x: i64, y: i64, opacity: f32) {
+    let (width, height) = image.dimensions();
+    for (mx, my, &color) in mark.enumerate_pixels() {
+        let (px, py) = (x + mx as i64, y + my as i64);
+        if (0..width as i64).contains(&px) && (0..height as i64).contains(&py) {
+            blend_pixel(image, px as u32, py as u32, color, opacity);
+        }
+    }
+}
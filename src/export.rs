@@ -0,0 +1,197 @@
+use crate::{ImageData, ImageError, ImageFormat, LastError, SaveOptions};
+use image::GenericImageView;
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Size of one output relative to source image.
+enum Size {
+    /// Source size multiplied by factor.
+    Scale(f64),
+    /// Source size fitted within box keeping aspect ratio. Missing side doesn't limit size.
+    Fit(Option<u32>, Option<u32>),
+}
+
+/// One file written by profile.
+struct Output {
+    /// File name template, e.g. `"{name}@2x.png"`.
+    name: String,
+    format: ImageFormat,
+    size: Size,
+    options: SaveOptions,
+}
+
+/// Set of outputs written for each exported image, e.g. `@1x`, `@2x` and `@3x` PNG icons.
+pub struct Profile {
+    outputs: Vec<Output>,
+}
+
+impl Profile {
+    /// Parses profile from JSON like
+    /// `{"source_scale": 3, "outputs": [{"name": "{name}@1x.png", "scale": 1}, ...]}`.
+    /// See `img_export_profile_create` for description of fields.
+    pub fn parse(json: &str) -> Result<Self, ImageError> {
+        let profile: Value = serde_json::from_str(json)
+            .map_err(|e| invalid(format!("Invalid export profile: {}", e)))?;
+        let source_scale = match profile.get("source_scale").filter(|v| !v.is_null()) {
+            Some(scale) => positive(scale).ok_or_else(|| invalid_field("source_scale"))?,
+            None => 1.0,
+        };
+        let outputs = match profile["outputs"].as_array() {
+            Some(outputs) if !outputs.is_empty() => outputs,
+            _ => return Err(invalid("Export profile has no outputs".into())),
+        };
+
+        let outputs = outputs
+            .iter()
+            .map(|output| match output.as_object() {
+                Some(output) => Output::parse(output, source_scale),
+                None => Err(invalid(format!("Export output isn't object: {}", output))),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { outputs })
+    }
+
+    /// Writes every output of `data` next to `base_name` path. Template variable `{name}` is
+    /// replaced with file name of `base_name`. Stops at the first failed output.
+    pub fn export(&self, data: &ImageData, base_name: &Path) -> Result<(), ImageError> {
+        let name = match base_name.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => return Err(ImageError::Parameter),
+        };
+        let dir = base_name.parent().unwrap_or_else(|| Path::new(""));
+
+        for output in &self.outputs {
+            let (width, height) = output.dimensions(data.image.dimensions());
+            let resized;
+            let data = if (width, height) == data.image.dimensions() {
+                data
+            } else {
+                // Resizing allocates intermediate image of target width and source height.
+                let rows = height.max(data.image.height());
+                crate::check_alloc(crate::image_bytes(width, rows, data.image.color()))?;
+                let image = crate::parallel::resize_dynamic(&data.image, width, height);
+                resized = ImageData::new(image, data.metadata.clone());
+                &resized
+            };
+
+            let file_name = output
+                .name
+                .replace("{name}", name)
+                .replace("{width}", &width.to_string())
+                .replace("{height}", &height.to_string());
+            let file = std::fs::File::create(dir.join(file_name)).map_err(crate::io_error)?;
+            let mut writer = BufWriter::new(file);
+            crate::write_encoded(data, output.format, &output.options, &mut writer)?;
+            writer.flush().map_err(crate::io_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Output {
+    /// Parses output description. Sizes given by `scale` are divided by `source_scale`.
+    fn parse(output: &Map<String, Value>, source_scale: f64) -> Result<Self, ImageError> {
+        let field = |name: &str| output.get(name).filter(|v| !v.is_null());
+        let name = match field("name").and_then(Value::as_str) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => return Err(invalid_field("name")),
+        };
+
+        // Format is taken from extension of name unless it is given explicitly.
+        let format = match field("format") {
+            Some(format) => format.as_str().ok_or_else(|| invalid_field("format"))?,
+            None => Path::new(&name)
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or(""),
+        };
+        let format = image::ImageFormat::from_extension(format)
+            .and_then(|f| ImageFormat::try_from(f).ok())
+            .ok_or_else(|| invalid(format!("Unknown format of export output `{}`", name)))?;
+        if let image::ImageOutputFormat::Unsupported(_) = image::ImageOutputFormat::from(format) {
+            let message = format!("Format of export output `{}` can't be encoded", name);
+            crate::set_last_error(LastError::new(ImageError::Unsupported, message));
+            return Err(ImageError::Unsupported);
+        }
+
+        let side = |name: &str| match field(name) {
+            Some(side) => side
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .filter(|&v| v != 0)
+                .map(Some)
+                .ok_or_else(|| invalid_field(name)),
+            None => Ok(None),
+        };
+        let size = match (field("scale"), side("width")?, side("height")?) {
+            (Some(_), Some(_), _) | (Some(_), _, Some(_)) => {
+                return Err(invalid(format!(
+                    "Export output `{}` has both scale and size",
+                    name
+                )))
+            }
+            (Some(scale), None, None) => {
+                Size::Scale(positive(scale).ok_or_else(|| invalid_field("scale"))? / source_scale)
+            }
+            (None, None, None) => Size::Scale(1.0 / source_scale),
+            (None, width, height) => Size::Fit(width, height),
+        };
+
+        let quality = match field("jpeg_quality") {
+            Some(quality) => quality
+                .as_u64()
+                .filter(|q| (1..=100).contains(q))
+                .ok_or_else(|| invalid_field("jpeg_quality"))? as u8,
+            None => SaveOptions::default().jpeg_quality,
+        };
+        let flag = |name: &str| match field(name) {
+            Some(flag) => flag.as_bool().ok_or_else(|| invalid_field(name)),
+            None => Ok(false),
+        };
+        let options = SaveOptions {
+            jpeg_quality: quality,
+            keep_metadata: flag("keep_metadata")?,
+            embed_thumbnail: flag("embed_thumbnail")?,
+            ..Default::default()
+        };
+
+        Ok(Self {
+            name,
+            format,
+            size,
+            options,
+        })
+    }
+
+    /// Returns size of output for source image of `width` x `height`. Sides are at least 1.
+    fn dimensions(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let scale = match self.size {
+            Size::Scale(scale) => scale,
+            Size::Fit(fit_width, fit_height) => {
+                let by_width = fit_width.map_or(f64::INFINITY, |w| w as f64 / width as f64);
+                let by_height = fit_height.map_or(f64::INFINITY, |h| h as f64 / height as f64);
+                by_width.min(by_height)
+            }
+        };
+        let side = |v: u32| (v as f64 * scale).round().clamp(1.0, u32::MAX as f64) as u32;
+        (side(width), side(height))
+    }
+}
+
+/// Returns `value` if it is finite positive number.
+fn positive(value: &Value) -> Option<f64> {
+    value.as_f64().filter(|v| v.is_finite() && *v > 0.0)
+}
+
+/// Records error of invalid field `name` of export profile.
+fn invalid_field(name: &str) -> ImageError {
+    invalid(format!("Invalid field `{}` of export profile", name))
+}
+
+/// Records `message` of invalid export profile as the last error.
+fn invalid(message: String) -> ImageError {
+    crate::set_last_error(LastError::new(ImageError::Parameter, message));
+    ImageError::Parameter
+}
@@ -0,0 +1,128 @@
+use crate::filter::{self, Rgba16Image};
+
+/// Look of film stock built from curve, color and grain primitives.
+pub struct Stock {
+    /// Per-channel lift, gamma and gain of `filter::split_tone`.
+    lift: [f32; 3],
+    gamma: [f32; 3],
+    gain: [f32; 3],
+    /// Strength of S-shaped contrast curve in `[-1, 1]`. Negative values flatten tones.
+    contrast: f32,
+    /// Relative saturation change in `[-1, 1]`. `-1` gives black and white film.
+    saturation: f32,
+    /// Standard deviation of grain in midtones in `[0, 1]` units of channel range.
+    grain: f32,
+}
+
+/// Film stocks in `FilmStock` order.
+pub const STOCKS: [Stock; 8] = [
+    // Portra 400: warm skin tones, soft contrast, muted colors.
+    Stock {
+        lift: [0.03, 0.02, 0.0],
+        gamma: [0.03, 0.01, -0.02],
+        gain: [0.02, 0.0, -0.04],
+        contrast: -0.15,
+        saturation: -0.1,
+        grain: 0.015,
+    },
+    // Ektar 100: vivid, fine-grained.
+    Stock {
+        lift: [0.0, 0.0, 0.01],
+        gamma: [0.02, 0.0, -0.01],
+        gain: [0.03, 0.0, -0.02],
+        contrast: 0.25,
+        saturation: 0.3,
+        grain: 0.006,
+    },
+    // Velvia 50: saturated slide film with deep shadows.
+    Stock {
+        lift: [-0.02, -0.02, 0.0],
+        gamma: [0.0, 0.01, 0.02],
+        gain: [0.0, 0.0, 0.02],
+        contrast: 0.35,
+        saturation: 0.35,
+        grain: 0.005,
+    },
+    // Gold 200: golden highlights.
+    Stock {
+        lift: [0.02, 0.01, 0.0],
+        gamma: [0.04, 0.02, -0.03],
+        gain: [0.05, 0.02, -0.06],
+        contrast: 0.1,
+        saturation: 0.1,
+        grain: 0.015,
+    },
+    // Superia 400: cool greens in shadows.
+    Stock {
+        lift: [0.0, 0.03, 0.02],
+        gamma: [-0.01, 0.02, 0.0],
+        gain: [0.0, 0.01, 0.0],
+        contrast: 0.1,
+        saturation: 0.05,
+        grain: 0.02,
+    },
+    // CineStill 800T: tungsten-balanced, cool and grainy.
+    Stock {
+        lift: [0.0, 0.02, 0.05],
+        gamma: [-0.03, 0.0, 0.04],
+        gain: [0.02, 0.0, 0.02],
+        contrast: 0.05,
+        saturation: 0.0,
+        grain: 0.03,
+    },
+    // Tri-X 400: contrasty black and white with visible grain.
+    Stock {
+        lift: [-0.02; 3],
+        gamma: [0.0; 3],
+        gain: [0.02; 3],
+        contrast: 0.4,
+        saturation: -1.0,
+        grain: 0.035,
+    },
+    // HP5 Plus: soft black and white.
+    Stock {
+        lift: [0.02; 3],
+        gamma: [0.01; 3],
+        gain: [0.0; 3],
+        contrast: 0.0,
+        saturation: -1.0,
+        grain: 0.03,
+    },
+];
+
+/// Applies look of `stock` to `image` blended with original by `strength` in `[0, 1]`. Colors
+/// are graded first, then contrast curve and saturation are applied and monochrome grain,
+/// strongest in midtones, is added. Grain is the same for the same image size. Alpha is kept.
+pub fn emulate(image: &Rgba16Image, stock: &Stock, strength: f32) -> Rgba16Image {
+    let max = u16::MAX as f32;
+    let graded = filter::split_tone(image, stock.lift, stock.gamma, stock.gain, 0.0);
+
+    let mut film = image.clone();
+    for (x, y, pixel) in film.enumerate_pixels_mut() {
+        let source = pixel.0.map(|v| v as f32 / max);
+        let rgb = [0, 1, 2].map(|c| graded.get_pixel(x, y).0[c] as f32 / max);
+        let rgb = rgb.map(|v| v + stock.contrast * (v * v * (3.0 - 2.0 * v) - v));
+
+        let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+        let grain = stock.grain * 4.0 * luma * (1.0 - luma) * noise(x, y);
+        let rgb = rgb.map(|v| luma + (v - luma) * (1.0 + stock.saturation) + grain);
+
+        for (c, value) in pixel.0[..3].iter_mut().enumerate() {
+            let blended = source[c] + (rgb[c] - source[c]) * strength;
+            *value = (blended * max).round().clamp(0.0, max) as u16;
+        }
+    }
+    film
+}
+
+/// Returns deterministic noise with zero mean and unit standard deviation for pixel `(x, y)`.
+fn noise(x: u32, y: u32) -> f32 {
+    let mut hash = (x as u64) << 32 | y as u64;
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+
+    // Sum of two uniform values has triangular distribution with variance 1/6.
+    let uniform = |bits: u64| (bits & 0xffff_ffff) as f32 / u32::MAX as f32;
+    (uniform(hash) + uniform(hash >> 32) - 1.0) * 6f32.sqrt()
+}
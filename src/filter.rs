@@ -0,0 +1,631 @@
+use crate::{analysis, hsl};
+use image::{DynamicImage, ImageBuffer, Luma, Rgba};
+
+/// Working image format of filters.
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Noise below this sigma is not worth denoising.
+const MIN_DENOISE_SIGMA: f64 = 0.5 / 255.0;
+/// Spatial sigma of automatic denoising in pixels.
+const DENOISE_SPATIAL_SIGMA: f32 = 1.0;
+/// Range sigma of automatic denoising relative to estimated noise sigma.
+const DENOISE_RANGE_FACTOR: f64 = 2.5;
+/// Sharpness of typical in-focus photo, which gets the weakest automatic sharpening.
+const SHARP_REFERENCE: f64 = 0.05;
+/// Amounts of automatic sharpening of the sharpest and the blurriest images.
+const SHARPEN_AMOUNTS: (f64, f64) = (0.2, 0.8);
+/// Differences below this multiple of noise sigma are not sharpened.
+const SHARPEN_NOISE_FACTOR: f64 = 3.0;
+
+/// Denoises `image` with strength picked from its estimated noise level. Images with
+/// negligible noise are left unchanged.
+pub fn auto_denoise(image: &mut DynamicImage) {
+    let sigma = match analysis::estimate_noise(image) {
+        Some(sigmas) => color_noise(&sigmas),
+        None => return,
+    };
+
+    if sigma < MIN_DENOISE_SIGMA {
+        return;
+    }
+
+    let range_sigma = (sigma * DENOISE_RANGE_FACTOR) as f32;
+    map_rgba16(image, |rgba| {
+        bilateral(rgba, DENOISE_SPATIAL_SIGMA, range_sigma)
+    });
+}
+
+/// Sharpens `image` with parameters picked from its estimated sharpness and noise level:
+/// blurrier images get stronger and wider sharpening, noise is not amplified.
+pub fn auto_sharpen(image: &mut DynamicImage) {
+    let (sharpness, noise) = match (
+        analysis::estimate_sharpness(image),
+        analysis::estimate_noise(image),
+    ) {
+        (Some(sharpness), Some(sigmas)) => (sharpness, color_noise(&sigmas)),
+        _ => return,
+    };
+
+    let sharp = (sharpness / SHARP_REFERENCE).min(1.0);
+    let (min_amount, max_amount) = SHARPEN_AMOUNTS;
+    let amount = (max_amount - (max_amount - min_amount) * sharp) as f32;
+    let sigma = (2.0 - sharp) as f32;
+    let threshold = (noise * SHARPEN_NOISE_FACTOR) as f32;
+    map_rgba16(image, |rgba| unsharp_mask(rgba, sigma, amount, threshold));
+}
+
+/// Radial scales of red and blue channels tried by chromatic aberration estimation.
+const CA_SCALE_RANGE: (f32, f32) = (0.997, 1.003);
+/// Number of scales tried by chromatic aberration estimation.
+const CA_SCALE_STEPS: u32 = 24;
+/// Maximum number of edge pixels compared by chromatic aberration estimation.
+const CA_MAX_SAMPLES: usize = 20000;
+
+/// Corrects lateral chromatic aberration by radially rescaling red and blue channels of
+/// `image` about its center by `red_scale` and `blue_scale`. Scale `0` means it is estimated
+/// from alignment of channel edges with green channel.
+pub fn correct_ca(image: &Rgba16Image, red_scale: f32, blue_scale: f32) -> Rgba16Image {
+    let scale = |channel, scale| {
+        if scale == 0.0 {
+            estimate_ca_scale(image, channel)
+        } else {
+            scale
+        }
+    };
+    let scales = [scale(0, red_scale), 1.0, scale(2, blue_scale)];
+
+    let (width, height) = image.dimensions();
+    let cx = (width as f32 - 1.0) / 2.0;
+    let cy = (height as f32 - 1.0) / 2.0;
+
+    let mut corrected = image.clone();
+    for (x, y, out) in corrected.enumerate_pixels_mut() {
+        for c in [0, 2].iter().cloned() {
+            let sx = cx + (x as f32 - cx) * scales[c];
+            let sy = cy + (y as f32 - cy) * scales[c];
+            out.0[c] = bilinear(image, c, sx, sy).round() as u16;
+        }
+    }
+    corrected
+}
+
+/// Returns radial scale of `channel` best aligning its values at strong edges of green channel
+/// with green channel.
+fn estimate_ca_scale(image: &Rgba16Image, channel: usize) -> f32 {
+    let (width, height) = image.dimensions();
+    if width < 3 || height < 3 {
+        return 1.0;
+    }
+
+    let green = |x: u32, y: u32| image.get_pixel(x, y).0[1] as f32;
+    let mut edges = Vec::new();
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let dx = green(x + 1, y) - green(x - 1, y);
+            let dy = green(x, y + 1) - green(x, y - 1);
+            edges.push((dx * dx + dy * dy, x, y));
+        }
+    }
+    let count = edges.len().min(CA_MAX_SAMPLES);
+    edges.select_nth_unstable_by(count - 1, |a, b| b.0.total_cmp(&a.0));
+    edges.truncate(count);
+
+    // Channels of colored images differ in brightness, so values are compared after scaling
+    // channel to mean of green at compared pixels.
+    let green_mean: f32 = edges.iter().map(|e| green(e.1, e.2)).sum::<f32>() / count as f32;
+    let cx = (width as f32 - 1.0) / 2.0;
+    let cy = (height as f32 - 1.0) / 2.0;
+    let error = |scale: f32| {
+        let samples: Vec<f32> = edges
+            .iter()
+            .map(|&(_, x, y)| {
+                let sx = cx + (x as f32 - cx) * scale;
+                let sy = cy + (y as f32 - cy) * scale;
+                bilinear(image, channel, sx, sy)
+            })
+            .collect();
+        let mean = samples.iter().sum::<f32>() / count as f32;
+        let gain = green_mean / mean.max(1.0);
+        edges
+            .iter()
+            .zip(&samples)
+            .map(|(&(_, x, y), &v)| (v * gain - green(x, y)).powi(2))
+            .sum::<f32>()
+    };
+
+    let (min, max) = CA_SCALE_RANGE;
+    (0..=CA_SCALE_STEPS)
+        .map(|i| min + (max - min) * i as f32 / CA_SCALE_STEPS as f32)
+        .map(|scale| (error(scale), scale))
+        .fold(
+            (f32::INFINITY, 1.0),
+            |best, e| if e.0 < best.0 { e } else { best },
+        )
+        .1
+}
+
+/// Hue of purple fringes in degrees.
+const FRINGE_HUE: f32 = 285.0;
+/// Distance in pixels from high-contrast edge where fringes are removed.
+const FRINGE_RADIUS: usize = 3;
+/// Luma range around pixel, in `[0, 1]` units, which makes pixel part of high-contrast edge.
+const FRINGE_CONTRAST: f32 = 0.25;
+
+/// Removes purple fringes at high-contrast edges of `image` by desaturating pixels with hue
+/// within `hue_range` degrees band around purple. Pixels at band center are desaturated by
+/// `strength` in `[0, 1]`, weight falls to zero at band edges. Alpha is kept.
+pub fn defringe(image: &Rgba16Image, strength: f32, hue_range: f32) -> Rgba16Image {
+    let contrast = local_contrast(image, FRINGE_RADIUS);
+    let half_range = hue_range / 2.0;
+
+    let mut defringed = image.clone();
+    for (pixel, contrast) in defringed.pixels_mut().zip(contrast) {
+        if contrast < FRINGE_CONTRAST * u16::MAX as f32 {
+            continue;
+        }
+
+        let [r, g, b] = [pixel.0[0] as f32, pixel.0[1] as f32, pixel.0[2] as f32];
+        let max = u16::MAX as f32;
+        let [hue, saturation, _] = hsl::rgb_to_hsl([r / max, g / max, b / max]);
+        if saturation <= 0.0 {
+            continue;
+        }
+
+        let distance = (hue - FRINGE_HUE).abs();
+        let distance = distance.min(360.0 - distance);
+        if distance >= half_range {
+            continue;
+        }
+
+        let weight = strength * (1.0 - distance / half_range);
+        let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        for value in pixel.0[..3].iter_mut() {
+            let v = *value as f32;
+            *value = (v + (luma - v) * weight)
+                .round()
+                .clamp(0.0, u16::MAX as f32) as u16;
+        }
+    }
+    defringed
+}
+
+/// Returns difference of the largest and the smallest luma within `radius` pixels square around
+/// each pixel of `image`, in row-major order.
+fn local_contrast(image: &Rgba16Image, radius: usize) -> Vec<f32> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let luma: Vec<f32> = image
+        .pixels()
+        .map(|p| 0.2126 * p.0[0] as f32 + 0.7152 * p.0[1] as f32 + 0.0722 * p.0[2] as f32)
+        .collect();
+
+    // Window minimum and maximum are found separately along rows and then along columns.
+    let window = |at: &dyn Fn(usize) -> (f32, f32), i: usize, len: usize| {
+        let range = i.saturating_sub(radius)..(i + radius + 1).min(len);
+        range
+            .map(at)
+            .fold((f32::MAX, f32::MIN), |(min, max), (lo, hi)| {
+                (min.min(lo), max.max(hi))
+            })
+    };
+
+    let mut rows = vec![(0.0, 0.0); width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |x: usize| (luma[y * width + x], luma[y * width + x]);
+            rows[y * width + x] = window(&at, x, width);
+        }
+    }
+
+    let mut contrast = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |y: usize| rows[y * width + x];
+            let (min, max) = window(&at, y, height);
+            contrast[y * width + x] = max - min;
+        }
+    }
+    contrast
+}
+
+/// Channel values at or above this fraction of channel range are treated as clipped.
+const CLIP_LEVEL: f32 = 0.995;
+/// Radius in pixels of neighbourhood where colors of clipped pixels are taken from.
+const RECOVERY_RADIUS: u32 = 8;
+
+/// Reconstructs clipped channels of pixels where one or two RGB channels are clipped from
+/// unclipped ones, using channel ratios of unclipped pixels within `RECOVERY_RADIUS` pixels or,
+/// if there are none, of the whole image.
+/// Reconstructed pixels are scaled down to fit channel range, which restores their hue and
+/// gradients of highlights. Result is blended with original by `strength` in `[0, 1]`. Alpha is
+/// kept.
+pub fn recover_highlights(image: &Rgba16Image, strength: f32) -> Rgba16Image {
+    let (width, height) = image.dimensions();
+    let clip = CLIP_LEVEL * u16::MAX as f32;
+    let clipped = |p: &Rgba<u16>| [0, 1, 2].map(|c| p.0[c] as f32 >= clip);
+
+    // Summed-area tables of RGB channels and count of pixels without clipped channels.
+    let stride = width as usize + 1;
+    let mut sums = vec![[0.0f64; 4]; stride * (height as usize + 1)];
+    for y in 0..height {
+        let mut row = [0.0; 4];
+        for x in 0..width {
+            let pixel = image.get_pixel(x, y);
+            if !clipped(pixel).contains(&true) {
+                for (row, &value) in row.iter_mut().zip(&pixel.0[..3]) {
+                    *row += value as f64;
+                }
+                row[3] += 1.0;
+            }
+
+            let i = (y as usize + 1) * stride + x as usize + 1;
+            for (c, row) in row.iter().enumerate() {
+                sums[i][c] = sums[i - stride][c] + row;
+            }
+        }
+    }
+    let window = |x: u32, y: u32| {
+        let x0 = x.saturating_sub(RECOVERY_RADIUS) as usize;
+        let y0 = y.saturating_sub(RECOVERY_RADIUS) as usize;
+        let x1 = (x + RECOVERY_RADIUS + 1).min(width) as usize;
+        let y1 = (y + RECOVERY_RADIUS + 1).min(height) as usize;
+        let at = |x: usize, y: usize| sums[y * stride + x];
+        let (a, b, c, d) = (at(x1, y1), at(x0, y1), at(x1, y0), at(x0, y0));
+        [0, 1, 2, 3].map(|k| a[k] - b[k] - c[k] + d[k])
+    };
+
+    let total = sums[sums.len() - 1];
+
+    let mut recovered = image.clone();
+    for (x, y, out) in recovered.enumerate_pixels_mut() {
+        let is_clipped = clipped(out);
+        let count = is_clipped.iter().filter(|&&c| c).count();
+        if count == 0 || count == 3 {
+            continue;
+        }
+
+        // Ratios of the whole image are used if there are no unclipped pixels nearby.
+        let sums = match window(x, y) {
+            sums if sums[3] == 0.0 => total,
+            sums => sums,
+        };
+
+        // Clipped channels are predicted from their ratio to unclipped channels nearby.
+        let unclipped = |values: [f64; 3]| {
+            let channels = (0..3).filter(|&c| !is_clipped[c]);
+            channels.map(|c| values[c]).sum::<f64>() / (3 - count) as f64
+        };
+        let reference = unclipped([sums[0], sums[1], sums[2]]);
+        if reference <= 0.0 {
+            continue;
+        }
+        let current = [out.0[0] as f64, out.0[1] as f64, out.0[2] as f64];
+        let level = unclipped(current);
+
+        let mut values = current;
+        for c in (0..3).filter(|&c| is_clipped[c]) {
+            values[c] = values[c].max(level * sums[c] / reference);
+        }
+        let scale = u16::MAX as f64 / values.iter().cloned().fold(u16::MAX as f64, f64::max);
+
+        for (out, (&value, &current)) in out.0.iter_mut().zip(values.iter().zip(&current)) {
+            let value = current + (value * scale - current) * strength as f64;
+            *out = value.round().clamp(0.0, u16::MAX as f64) as u16;
+        }
+    }
+    recovered
+}
+
+/// Brightens (positive amounts) or darkens (negative amounts) shadows and highlights of `image`
+/// by `shadows` and `highlights` in `[-1, 1]`. Tones are masked by luma blurred with `radius`
+/// sigma, so pixels of one area get the same tone curve and their local contrast is preserved.
+/// Colors are kept by scaling RGB with luma. Alpha is kept.
+pub fn shadows_highlights(
+    image: &Rgba16Image,
+    shadows: f32,
+    highlights: f32,
+    radius: f32,
+) -> Rgba16Image {
+    let max = u16::MAX as f32;
+    let luma_of =
+        |p: &Rgba<u16>| 0.2126 * p.0[0] as f32 + 0.7152 * p.0[1] as f32 + 0.0722 * p.0[2] as f32;
+    let luma = ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+        Luma([luma_of(image.get_pixel(x, y)).round() as u16])
+    });
+    let blurred = crate::parallel::blur(&luma, radius);
+
+    let mut adjusted = image.clone();
+    for (out, area) in adjusted.pixels_mut().zip(blurred.pixels()) {
+        let area = area.0[0] as f32 / max;
+        let luma = luma_of(out) / max;
+        if luma <= 0.0 {
+            continue;
+        }
+
+        // Gamma of tone curve. Masks are squared to keep midtones mostly unchanged.
+        let amount = shadows * (1.0 - area).powi(2) + highlights * area.powi(2);
+        let target = luma.powf(2f32.powf(-amount));
+        let gain = target / luma;
+        for value in out.0[..3].iter_mut() {
+            *value = (*value as f32 * gain).round().clamp(0.0, max) as u16;
+        }
+    }
+    adjusted
+}
+
+/// Colors with at least this HSL saturation get full HSL adjustment.
+const FULL_HSL_SATURATION: f32 = 0.25;
+
+/// Adjusts colors of `image` by hue, saturation and luminance `deltas` of color bands (in
+/// `ColorBand` order). Hue deltas are in degrees, saturation and luminance deltas are in
+/// `[-1, 1]`. Pixels get weighted deltas of bands their hue belongs to, so adjustments blend
+/// smoothly between neighbour bands, and fade out for colors less saturated than
+/// `FULL_HSL_SATURATION`, so grays are kept.
+/// Positive luminance deltas move luminance towards white, negative towards black. Alpha is
+/// kept.
+pub fn hsl_adjust(image: &Rgba16Image, deltas: &[[f32; 3]; 8]) -> Rgba16Image {
+    let max = u16::MAX as f32;
+    let mut adjusted = image.clone();
+    for pixel in adjusted.pixels_mut() {
+        let rgb = [0, 1, 2].map(|c| pixel.0[c] as f32 / max);
+        let [hue, saturation, lightness] = hsl::rgb_to_hsl(rgb);
+        if saturation <= 0.0 {
+            continue;
+        }
+
+        let mut delta = [0.0; 3];
+        for (weight, band) in hsl::band_weights(hue).iter().zip(deltas) {
+            for (delta, band) in delta.iter_mut().zip(band) {
+                *delta += weight * band;
+            }
+        }
+
+        let fade = (saturation / FULL_HSL_SATURATION).min(1.0);
+        let [hue_delta, saturation_delta, luminance_delta] = delta.map(|d| d * fade);
+        let lightness = match luminance_delta {
+            d if d > 0.0 => lightness + (1.0 - lightness) * d,
+            d => lightness + lightness * d,
+        };
+        let saturation = (saturation * (1.0 + saturation_delta)).clamp(0.0, 1.0);
+        let rgb = hsl::hsl_to_rgb([hue + hue_delta, saturation, lightness.clamp(0.0, 1.0)]);
+        for (value, channel) in pixel.0.iter_mut().zip(&rgb) {
+            *value = (channel * max).round().clamp(0.0, max) as u16;
+        }
+    }
+    adjusted
+}
+
+/// Weight of lightness difference in color distance of `replace_color`. Lower than chroma weight,
+/// so shaded parts of matched object also match.
+const REPLACE_LIGHTNESS_WEIGHT: f32 = 0.5;
+/// Match of `replace_color` fades out from `tolerance` to `tolerance * (1 + REPLACE_SOFTNESS)`.
+const REPLACE_SOFTNESS: f32 = 0.5;
+
+/// Replaces colors of `image` close to `from` with `to`. Colors have RGBA channels in `[0, 1]`.
+/// Colors within `tolerance` of `from` are fully replaced, and replacement softly fades out for
+/// farther colors. Distance is measured between hue, saturation and lightness of colors. Colors
+/// are shifted by difference of `to` and `from`, so shading and texture are kept. If
+/// `preserve_luminosity` is set, only hue and saturation are shifted and lightness of pixels is
+/// kept.
+pub fn replace_color(
+    image: &Rgba16Image,
+    from: [f32; 4],
+    to: [f32; 4],
+    tolerance: f32,
+    preserve_luminosity: bool,
+) -> Rgba16Image {
+    let max = u16::MAX as f32;
+    let softness = (tolerance * REPLACE_SOFTNESS).max(f32::EPSILON);
+    let from_rgb = [from[0], from[1], from[2]];
+    let to_rgb = [to[0], to[1], to[2]];
+    let [from_hue, from_saturation, _] = hsl::rgb_to_hsl(from_rgb);
+    let [to_hue, to_saturation, _] = hsl::rgb_to_hsl(to_rgb);
+    let from_coords = color_coords(from_rgb);
+
+    let mut replaced = image.clone();
+    for pixel in replaced.pixels_mut() {
+        let rgba = pixel.0.map(|v| v as f32 / max);
+        let rgb = [rgba[0], rgba[1], rgba[2]];
+        let coords = color_coords(rgb);
+        let distance = coords
+            .iter()
+            .zip(&from_coords)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        let weight = (1.0 - (distance - tolerance) / softness).clamp(0.0, 1.0);
+        if weight <= 0.0 {
+            continue;
+        }
+
+        let target = match preserve_luminosity {
+            true => {
+                let [hue, saturation, lightness] = hsl::rgb_to_hsl(rgb);
+                let hue = match from_saturation > 0.0 && saturation > 0.0 {
+                    true => hue + to_hue - from_hue,
+                    false => to_hue,
+                };
+                let saturation = (saturation + to_saturation - from_saturation).clamp(0.0, 1.0);
+                hsl::hsl_to_rgb([hue, saturation, lightness])
+            }
+            false => [0, 1, 2].map(|c| rgb[c] + to[c] - from[c]),
+        };
+        let alpha = rgba[3] + to[3] - from[3];
+
+        for (c, value) in pixel.0.iter_mut().enumerate() {
+            let target = match c {
+                3 => alpha,
+                c => target[c],
+            };
+            let blended = rgba[c] + (target - rgba[c]) * weight;
+            *value = (blended * max).round().clamp(0.0, max) as u16;
+        }
+    }
+    replaced
+}
+
+/// Returns coordinates of RGB color in HSL hue-saturation plane and weighted lightness, used to
+/// measure distance between colors. Saturation is mostly kept in shades of the same color.
+fn color_coords(rgb: [f32; 3]) -> [f32; 3] {
+    let [hue, saturation, lightness] = hsl::rgb_to_hsl(rgb);
+    let angle = hue.to_radians();
+    [
+        saturation * angle.cos(),
+        saturation * angle.sin(),
+        lightness * REPLACE_LIGHTNESS_WEIGHT,
+    ]
+}
+
+/// Tone splitting shadows and highlights of `split_tone` moves by this for unit balance.
+const BALANCE_RANGE: f32 = 0.4;
+
+/// Grades colors of `image` with per-channel `lift` of shadows, `gamma` of midtones and `gain`
+/// of highlights, all in `[-0.5, 0.5]`. Positive amounts brighten channel. Lift moves black
+/// point keeping white, gain scales channel keeping black, gamma bends curve keeping both.
+/// `balance` in `[-1, 1]` moves tone splitting shadows and highlights from middle gray: positive
+/// balance lets highlights take more of tone range. Alpha is kept.
+pub fn split_tone(
+    image: &Rgba16Image,
+    lift: [f32; 3],
+    gamma: [f32; 3],
+    gain: [f32; 3],
+    balance: f32,
+) -> Rgba16Image {
+    let max = u16::MAX as f32;
+    // Tones are warped so that pivot tone becomes middle gray while grading.
+    let pivot = 0.5 - BALANCE_RANGE * balance;
+    let warp = 0.5f32.ln() / pivot.ln();
+
+    // Curves are the same for all pixels, so they are tabulated per channel.
+    let curves = [0, 1, 2].map(|c| {
+        (0..=u16::MAX)
+            .map(|value| {
+                let tone = (value as f32 / max).powf(warp);
+                let tone = (tone + lift[c] * (1.0 - tone)).max(0.0) * (1.0 + gain[c]);
+                let tone = tone.powf(2f32.powf(-gamma[c])).powf(1.0 / warp);
+                (tone * max).round().clamp(0.0, max) as u16
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let mut graded = image.clone();
+    for pixel in graded.pixels_mut() {
+        for (value, curve) in pixel.0.iter_mut().zip(&curves) {
+            *value = curve[*value as usize];
+        }
+    }
+    graded
+}
+
+/// Returns value of `channel` of `image` at `(x, y)` interpolated from neighbour pixels.
+/// Coordinates are clamped to image bounds.
+pub fn bilinear(image: &Rgba16Image, channel: usize, x: f32, y: f32) -> f32 {
+    let (width, height) = image.dimensions();
+    let x = x.clamp(0.0, width as f32 - 1.0);
+    let y = y.clamp(0.0, height as f32 - 1.0);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let at = |x, y| image.get_pixel(x, y).0[channel] as f32;
+    let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+    let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Returns the largest noise sigma of color channels.
+fn color_noise(sigmas: &[f64; 4]) -> f64 {
+    sigmas[..3].iter().cloned().fold(0.0, f64::max)
+}
+
+/// Applies `filter` to `image` converted to 16-bit RGBA and converts result back to color type
+/// of `image`.
+pub fn map_rgba16<F>(image: &mut DynamicImage, filter: F)
+where
+    F: FnOnce(&Rgba16Image) -> Rgba16Image,
+{
+    let filtered = DynamicImage::ImageRgba16(filter(&image.to_rgba16()));
+    *image = match image {
+        DynamicImage::ImageLuma8(_) => DynamicImage::ImageLuma8(filtered.to_luma8()),
+        DynamicImage::ImageLumaA8(_) => DynamicImage::ImageLumaA8(filtered.to_luma_alpha8()),
+        DynamicImage::ImageRgb8(_) => DynamicImage::ImageRgb8(filtered.to_rgb8()),
+        DynamicImage::ImageRgba8(_) => DynamicImage::ImageRgba8(filtered.to_rgba8()),
+        DynamicImage::ImageBgr8(_) => DynamicImage::ImageBgr8(filtered.to_bgr8()),
+        DynamicImage::ImageBgra8(_) => DynamicImage::ImageBgra8(filtered.to_bgra8()),
+        DynamicImage::ImageLuma16(_) => DynamicImage::ImageLuma16(filtered.to_luma16()),
+        DynamicImage::ImageLumaA16(_) => DynamicImage::ImageLumaA16(filtered.to_luma_alpha16()),
+        DynamicImage::ImageRgb16(_) => DynamicImage::ImageRgb16(filtered.to_rgb16()),
+        DynamicImage::ImageRgba16(_) => filtered,
+    };
+}
+
+/// Edge-preserving bilateral filter. Neighbours within `3 * spatial_sigma` pixels are averaged
+/// with weights falling off with distance and with RGB difference relative to `range_sigma`
+/// (in `[0, 1]` units of channel range). Alpha is kept.
+pub fn bilateral(image: &Rgba16Image, spatial_sigma: f32, range_sigma: f32) -> Rgba16Image {
+    let (width, height) = image.dimensions();
+    let radius = (3.0 * spatial_sigma).ceil() as i64;
+    let range = range_sigma * u16::MAX as f32;
+    let range_factor = -0.5 / (range * range).max(f32::EPSILON);
+    let spatial_factor = -0.5 / (spatial_sigma * spatial_sigma);
+
+    let mut spatial = Vec::new();
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            spatial.push(((dx * dx + dy * dy) as f32 * spatial_factor).exp());
+        }
+    }
+
+    let mut filtered = image.clone();
+    for (x, y, out) in filtered.enumerate_pixels_mut() {
+        let center = image.get_pixel(x, y).0;
+        let mut sum = [0.0f32; 3];
+        let mut total = 0.0f32;
+
+        let mut k = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let weight = spatial[k];
+                k += 1;
+
+                let nx = (x as i64 + dx).clamp(0, width as i64 - 1) as u32;
+                let ny = (y as i64 + dy).clamp(0, height as i64 - 1) as u32;
+                let pixel = image.get_pixel(nx, ny).0;
+
+                let distance: f32 = (0..3)
+                    .map(|c| (pixel[c] as f32 - center[c] as f32).powi(2))
+                    .sum();
+                let weight = weight * (distance * range_factor).exp();
+                for (sum, &value) in sum.iter_mut().zip(&pixel) {
+                    *sum += weight * value as f32;
+                }
+                total += weight;
+            }
+        }
+
+        for (out, sum) in out.0.iter_mut().zip(&sum) {
+            *out = (sum / total).round() as u16;
+        }
+    }
+    filtered
+}
+
+/// Unsharp mask. Adds `amount` of difference between image and its Gaussian blur with `sigma`
+/// to RGB channels where the difference exceeds `threshold` (in `[0, 1]` units of channel
+/// range). Alpha is kept.
+pub fn unsharp_mask(image: &Rgba16Image, sigma: f32, amount: f32, threshold: f32) -> Rgba16Image {
+    let blurred = crate::parallel::blur(image, sigma);
+    let threshold = threshold * u16::MAX as f32;
+
+    let mut sharpened = image.clone();
+    for (out, blurred) in sharpened.pixels_mut().zip(blurred.pixels()) {
+        for c in 0..3 {
+            let value = out.0[c] as f32;
+            let difference = value - blurred.0[c] as f32;
+            if difference.abs() > threshold {
+                let value = value + amount * difference;
+                out.0[c] = value.round().clamp(0.0, u16::MAX as f32) as u16;
+            }
+        }
+    }
+    sharpened
+}
@@ -0,0 +1,255 @@
+use crate::NoiseKind;
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+/// Returns `width` x `height` gradient of color `stops` sorted by position: linear along
+/// direction of `angle` in degrees, clockwise from left to right, or radial from center of
+/// image to its corners if `radial` is set. Positions of stops are fractions of gradient
+/// length; colors before the first stop and after the last one are colors of these stops.
+/// Colors are interpolated with premultiplied alpha, so transparent stops don't darken
+/// gradient.
+pub fn gradient(
+    width: u32,
+    height: u32,
+    stops: &[(f32, Rgba<u8>)],
+    radial: bool,
+    angle: f32,
+) -> RgbaImage {
+    let (w, h) = (width as f32, height as f32);
+    let (sin, cos) = angle.to_radians().sin_cos();
+    // Linear gradient spans projection of image on its direction.
+    let length = match radial {
+        true => (w / 2.0).hypot(h / 2.0),
+        false => (w * cos).abs() + (h * sin).abs(),
+    };
+    let premultiplied: Vec<(f32, [f32; 4])> = stops
+        .iter()
+        .map(|(position, color)| {
+            let alpha = color[3] as f32 / 255.0;
+            let [r, g, b, a] = color.0.map(|c| c as f32);
+            (*position, [r * alpha, g * alpha, b * alpha, a])
+        })
+        .collect();
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f32 + 0.5 - w / 2.0, y as f32 + 0.5 - h / 2.0);
+        let t = match radial {
+            true => dx.hypot(dy) / length.max(f32::EPSILON),
+            false => (dx * cos + dy * sin) / length.max(f32::EPSILON) + 0.5,
+        };
+        color_at(&premultiplied, t)
+    })
+}
+
+/// Returns color of gradient of premultiplied `stops` sorted by position at position `t`.
+fn color_at(stops: &[(f32, [f32; 4])], t: f32) -> Rgba<u8> {
+    let next = stops.partition_point(|(position, _)| *position <= t);
+    let [r, g, b, a] = match (next, stops.get(next)) {
+        (0, _) => stops[0].1,
+        (_, None) => stops[next - 1].1,
+        (_, Some(&(end, to))) => {
+            let (start, from) = stops[next - 1];
+            let f = (t - start) / (end - start);
+            [0, 1, 2, 3].map(|c| from[c] + (to[c] - from[c]) * f)
+        }
+    };
+    let unpremultiply = |c: f32| match a > 0.0 {
+        true => c * 255.0 / a,
+        false => 0.0,
+    };
+    Rgba([unpremultiply(r), unpremultiply(g), unpremultiply(b), a].map(|c| c.round() as u8))
+}
+
+/// Returns `width` x `height` checkerboard of squares of `cell_size` pixels alternating
+/// colors `a` and `b`, starting with `a` at top left corner.
+pub fn checkerboard(
+    width: u32,
+    height: u32,
+    cell_size: u32,
+    a: Rgba<u8>,
+    b: Rgba<u8>,
+) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        match (x / cell_size + y / cell_size) % 2 {
+            0 => a,
+            _ => b,
+        }
+    })
+}
+
+/// Colors of seven bars of 75% intensity of SMPTE color bars: gray, yellow, cyan, green,
+/// magenta, red and blue.
+const BARS: [u32; 7] = [
+    0xc0c0c0, 0xc0c000, 0x00c0c0, 0x00c000, 0xc000c0, 0xc00000, 0x0000c0,
+];
+/// Colors of reversed bars under the main ones, alternating with black.
+const REVERSED_BARS: [u32; 7] = [
+    0x0000c0, 0x131313, 0xc000c0, 0x131313, 0x00c0c0, 0x131313, 0xc0c0c0,
+];
+/// Bottom row of SMPTE color bars as colors with their widths in sevenths of image width
+/// multiplied by 12: -I, white, +Q, black, PLUGE pulses below, at and above black, black.
+const BOTTOM_ROW: [(u32, u32); 8] = [
+    (0x00214c, 15),
+    (0xffffff, 15),
+    (0x32006a, 15),
+    (0x131313, 15),
+    (0x090909, 4),
+    (0x131313, 4),
+    (0x1d1d1d, 4),
+    (0x131313, 12),
+];
+
+/// Returns `width` x `height` SMPTE color bars of RGB values common in digital recreations,
+/// where black is at 7.5% setup level: seven bars in the top two thirds, reversed bars in the
+/// next twelfth and -I, white, +Q and PLUGE in the rest.
+pub fn color_bars(width: u32, height: u32) -> RgbaImage {
+    let rgb = |color: u32| {
+        let [_, r, g, b] = color.to_be_bytes();
+        Rgba([r, g, b, 255])
+    };
+    let (w, h) = (width as u64, height as u64);
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as u64, y as u64);
+        let bar = (x * 7 / w) as usize;
+        if y * 3 < h * 2 {
+            return rgb(BARS[bar]);
+        }
+        if y * 4 < h * 3 {
+            return rgb(REVERSED_BARS[bar]);
+        }
+        let position = x * 84 / w;
+        let mut end = 0;
+        for &(color, span) in &BOTTOM_ROW {
+            end += span as u64;
+            if position < end {
+                return rgb(color);
+            }
+        }
+        rgb(BOTTOM_ROW[BOTTOM_ROW.len() - 1].0)
+    })
+}
+
+/// Directions of gradients of Perlin and simplex noise.
+const GRADIENTS: [[f64; 2]; 8] = [
+    [1.0, 1.0],
+    [-1.0, 1.0],
+    [1.0, -1.0],
+    [-1.0, -1.0],
+    [1.0, 0.0],
+    [-1.0, 0.0],
+    [0.0, 1.0],
+    [0.0, -1.0],
+];
+
+/// Returns `width` x `height` image of fractal `kind` noise: sum of `octaves` layers, each one of
+/// double frequency and half amplitude of previous one. The first layer has `frequency` cycles
+/// per pixel. Noise is the same for the same `seed`.
+pub fn noise(
+    width: u32,
+    height: u32,
+    kind: NoiseKind,
+    frequency: f64,
+    octaves: u32,
+    seed: u32,
+) -> GrayImage {
+    let amplitudes: f64 = (0..octaves).map(|o| 0.5f64.powi(o as i32)).sum();
+    GrayImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as f64 + 0.5, y as f64 + 0.5);
+        let mut sum = 0.0;
+        for octave in 0..octaves {
+            let scale = frequency * 2f64.powi(octave as i32);
+            let seed = seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9));
+            let (x, y) = (x * scale, y * scale);
+            let value = match kind {
+                NoiseKind::Value => value_noise(x, y, seed),
+                NoiseKind::Perlin => perlin_noise(x, y, seed),
+                NoiseKind::Simplex => simplex_noise(x, y, seed),
+            };
+            sum += value * 0.5f64.powi(octave as i32);
+        }
+        let value = (sum / amplitudes).clamp(-1.0, 1.0);
+        Luma([((value + 1.0) * 127.5).round() as u8])
+    })
+}
+
+/// Returns random bits of lattice point `x`, `y` for `seed`.
+fn lattice_hash(x: i64, y: i64, seed: u32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4_eb2d) ^ (y as u32).wrapping_mul(0x1656_67b1);
+    h = (h ^ h >> 15).wrapping_mul(0x2c1b_3c6d);
+    h = (h ^ h >> 12).wrapping_mul(0x297a_2d39);
+    h ^ h >> 15
+}
+
+/// Returns gradient of lattice point `x`, `y` for `seed`.
+fn gradient_at(x: i64, y: i64, seed: u32) -> [f64; 2] {
+    GRADIENTS[(lattice_hash(x, y, seed) >> 29) as usize]
+}
+
+/// Returns quintic fade curve of `t` in `[0, 1]` with zero first and second derivatives at ends.
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// Returns value noise in `[-1, 1]` at `x`, `y`.
+fn value_noise(x: f64, y: f64, seed: u32) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (fade(x - x0), fade(y - y0));
+    let (ix, iy) = (x0 as i64, y0 as i64);
+    let value = |dx, dy| lattice_hash(ix + dx, iy + dy, seed) as f64 / u32::MAX as f64 * 2.0 - 1.0;
+    let top = lerp(value(0, 0), value(1, 0), tx);
+    let bottom = lerp(value(0, 1), value(1, 1), tx);
+    lerp(top, bottom, ty)
+}
+
+/// Returns Perlin noise in `[-1, 1]` at `x`, `y`.
+fn perlin_noise(x: f64, y: f64, seed: u32) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (ix, iy) = (x0 as i64, y0 as i64);
+    let contribution = |dx: i64, dy: i64| {
+        let [gx, gy] = gradient_at(ix + dx, iy + dy, seed);
+        gx * (fx - dx as f64) + gy * (fy - dy as f64)
+    };
+    let (tx, ty) = (fade(fx), fade(fy));
+    let top = lerp(contribution(0, 0), contribution(1, 0), tx);
+    let bottom = lerp(contribution(0, 1), contribution(1, 1), tx);
+    // Extremes of 1 are in the middle of cell with diagonal gradients pointing to it.
+    lerp(top, bottom, ty)
+}
+
+/// Returns simplex noise in about `[-1, 1]` at `x`, `y`.
+fn simplex_noise(x: f64, y: f64, seed: u32) -> f64 {
+    let skew = 0.5 * (3f64.sqrt() - 1.0);
+    let unskew = (3.0 - 3f64.sqrt()) / 6.0;
+    let s = (x + y) * skew;
+    let (i, j) = ((x + s).floor(), (y + s).floor());
+    let t = (i + j) * unskew;
+    // Offsets of point from the first corner of its triangle and the second corner.
+    let (x0, y0) = (x - (i - t), y - (j - t));
+    let (di, dj) = match x0 > y0 {
+        true => (1, 0),
+        false => (0, 1),
+    };
+    let corners = [
+        (0, 0, x0, y0),
+        (di, dj, x0 - di as f64 + unskew, y0 - dj as f64 + unskew),
+        (1, 1, x0 - 1.0 + 2.0 * unskew, y0 - 1.0 + 2.0 * unskew),
+    ];
+    let (i, j) = (i as i64, j as i64);
+    let sum: f64 = corners
+        .iter()
+        .map(|&(di, dj, x, y)| {
+            let falloff = 0.5 - x * x - y * y;
+            if falloff <= 0.0 {
+                return 0.0;
+            }
+            let [gx, gy] = gradient_at(i + di, j + dj, seed);
+            falloff.powi(4) * (gx * x + gy * y)
+        })
+        .sum();
+    70.0 * sum
+}
+
+/// Returns linear interpolation between `a` and `b` at `t`.
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
@@ -0,0 +1,162 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::thread::ThreadId;
+
+/// Description of place where image was created: its backtrace with `backtrace` feature.
+#[cfg(feature = "backtrace")]
+type Origin = std::backtrace::Backtrace;
+#[cfg(not(feature = "backtrace"))]
+type Origin = ();
+
+/// Addresses of live images and, if debug registry is enabled, of destroyed ones with threads
+/// owning live ones.
+#[derive(Default)]
+struct Registry {
+    live: HashMap<usize, Origin>,
+    destroyed: Option<HashSet<usize>>,
+    /// Thread using image, `None` while image is detached to be passed to another thread. Images
+    /// created while debug registry was disabled have no owner and may be used on any thread.
+    owners: HashMap<usize, Option<ThreadId>>,
+}
+
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+/// State of image handle known to debug registry.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    Live,
+    Destroyed,
+    /// Handle wasn't created by library, so it is rejected.
+    Unknown,
+}
+
+/// Runs `f` with locked registry.
+fn with_registry<T, F: FnOnce(&mut Registry) -> T>(f: F) -> T {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    f(registry.get_or_insert_with(Default::default))
+}
+
+/// Enables or disables debug registry of destroyed image handles. Registry starts empty.
+pub fn set_enabled(enabled: bool) {
+    with_registry(|r| {
+        r.destroyed = Some(HashSet::new()).filter(|_| enabled);
+        r.owners.clear();
+    });
+}
+
+/// Records image allocated at `address` as live.
+pub fn register(address: usize) {
+    #[cfg(feature = "backtrace")]
+    let origin = std::backtrace::Backtrace::force_capture();
+    #[cfg(not(feature = "backtrace"))]
+    let origin = ();
+
+    with_registry(|r| {
+        if let Some(destroyed) = &mut r.destroyed {
+            destroyed.remove(&address);
+            r.owners.insert(address, Some(std::thread::current().id()));
+        }
+        r.live.insert(address, origin);
+    });
+}
+
+/// Records destruction of image at `address`.
+pub fn unregister(address: usize) {
+    with_registry(|r| {
+        // Images which were never allocated on heap, e.g. temporary ones, aren't remembered.
+        let live = r.live.remove(&address).is_some();
+        r.owners.remove(&address);
+        if let (true, Some(destroyed)) = (live, &mut r.destroyed) {
+            destroyed.insert(address);
+        }
+    });
+}
+
+/// Returns state of image handle at `address`, or `None` if debug registry is disabled.
+pub fn status(address: usize) -> Option<Status> {
+    with_registry(|r| {
+        let destroyed = r.destroyed.as_ref()?;
+        match (r.live.contains_key(&address), destroyed.contains(&address)) {
+            (true, _) => Some(Status::Live),
+            (_, true) => Some(Status::Destroyed),
+            _ => Some(Status::Unknown),
+        }
+    })
+}
+
+/// Checks that live image at `address` may be used on current thread. Returns description of
+/// violation if image is owned by another thread or detached.
+pub fn check_thread(address: usize) -> Result<(), String> {
+    let current = std::thread::current().id();
+    with_registry(|r| match r.owners.get(&address) {
+        None => Ok(()),
+        Some(Some(owner)) if *owner == current => Ok(()),
+        Some(Some(owner)) => Err(format!(
+            "is used on thread {:?} while it is owned by thread {:?}; \
+             detach it on owner thread and attach on this one",
+            current, owner
+        )),
+        Some(None) => Err(format!(
+            "is used on thread {:?} while it is detached; attach it first",
+            current
+        )),
+    })
+}
+
+/// Detaches live image at `address` from current thread, so another thread may attach it.
+/// Fails if image is used by another thread.
+pub fn detach(address: usize) -> Result<(), String> {
+    check_thread(address)?;
+    with_registry(|r| {
+        if let Some(owner) = r.owners.get_mut(&address) {
+            *owner = None;
+        }
+    });
+    Ok(())
+}
+
+/// Attaches live image at `address` to current thread. Fails if image is owned by another
+/// thread which didn't detach it.
+pub fn attach(address: usize) -> Result<(), String> {
+    let current = std::thread::current().id();
+    with_registry(|r| match r.owners.get_mut(&address) {
+        Some(Some(owner)) if *owner != current => Err(format!(
+            "is attached to thread {:?} while it is owned by thread {:?}; detach it first",
+            current, owner
+        )),
+        Some(owner) => {
+            *owner = Some(current);
+            Ok(())
+        }
+        None => Ok(()),
+    })
+}
+
+/// Lets live image at `address` be used on any thread, e.g. frozen image read concurrently.
+pub fn share(address: usize) {
+    with_registry(|r| {
+        r.owners.remove(&address);
+    });
+}
+
+/// Returns number of live images, including layers of documents.
+pub fn live_count() -> usize {
+    with_registry(|r| r.live.len())
+}
+
+/// Returns addresses of live images with descriptions of places where they were created, empty
+/// without `backtrace` feature.
+pub fn live() -> Vec<(usize, String)> {
+    #[cfg(feature = "backtrace")]
+    let describe = |origin: &Origin| origin.to_string();
+    #[cfg(not(feature = "backtrace"))]
+    let describe = |_: &Origin| String::new();
+
+    let mut live = with_registry(|r| {
+        let live = r.live.iter();
+        live.map(|(&address, origin)| (address, describe(origin)))
+            .collect::<Vec<_>>()
+    });
+    live.sort_by_key(|&(address, _)| address);
+    live
+}
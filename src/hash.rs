@@ -0,0 +1,68 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage};
+
+/// Side of luma thumbnail DCT of perceptual hash is computed of.
+const DCT_SIZE: usize = 32;
+
+/// Returns average hash of `image`: bits of 8x8 luma thumbnail set for pixels brighter than its
+/// mean. Bit 63 is top left pixel, bits follow rows.
+pub fn average_hash(image: &DynamicImage) -> u64 {
+    let thumbnail = luma_thumbnail(image, 8, 8);
+    let pixels = thumbnail.as_raw();
+    let mean = pixels.iter().map(|&v| v as u32).sum::<u32>() as f64 / pixels.len() as f64;
+    bits(pixels.iter().map(|&v| v as f64 > mean))
+}
+
+/// Returns difference hash of `image`: bits of 9x8 luma thumbnail set for pixels brighter than
+/// their right neighbours, so it follows gradients rather than brightness.
+pub fn difference_hash(image: &DynamicImage) -> u64 {
+    let thumbnail = luma_thumbnail(image, 9, 8);
+    let rows = thumbnail.as_raw().chunks_exact(9);
+    bits(rows.flat_map(|row| row.windows(2).map(|pair| pair[0] > pair[1])))
+}
+
+/// Returns perceptual hash of `image`: bits of the lowest 8x8 frequencies of DCT of 32x32 luma
+/// thumbnail set for coefficients above their median, which survive scaling, compression and
+/// small color changes. Median excludes DC coefficient, which only tells average brightness.
+pub fn perceptual_hash(image: &DynamicImage) -> u64 {
+    let thumbnail = luma_thumbnail(image, DCT_SIZE as u32, DCT_SIZE as u32);
+    let pixels: Vec<f64> = thumbnail.as_raw().iter().map(|&v| v as f64).collect();
+    let cosines: Vec<f64> = (0..8 * DCT_SIZE)
+        .map(|i| {
+            let (k, n) = ((i / DCT_SIZE) as f64, (i % DCT_SIZE) as f64);
+            (std::f64::consts::PI * k * (2.0 * n + 1.0) / (2.0 * DCT_SIZE as f64)).cos()
+        })
+        .collect();
+
+    // Rows are transformed first, then 8 lowest frequencies of columns.
+    let mut rows = vec![0.0; DCT_SIZE * 8];
+    for (y, row) in pixels.chunks_exact(DCT_SIZE).enumerate() {
+        for k in 0..8 {
+            let basis = &cosines[k * DCT_SIZE..(k + 1) * DCT_SIZE];
+            rows[y * 8 + k] = row.iter().zip(basis).map(|(v, c)| v * c).sum();
+        }
+    }
+    let mut coefficients = [0.0; 64];
+    for (i, coefficient) in coefficients.iter_mut().enumerate() {
+        let (k, u) = (i / 8, i % 8);
+        let basis = &cosines[k * DCT_SIZE..(k + 1) * DCT_SIZE];
+        *coefficient = (0..DCT_SIZE).map(|y| rows[y * 8 + u] * basis[y]).sum();
+    }
+
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    bits(coefficients.iter().map(|&c| c > median))
+}
+
+/// Returns luma of `image` resized to `width` x `height` pixels, ignoring aspect ratio.
+fn luma_thumbnail(image: &DynamicImage, width: u32, height: u32) -> GrayImage {
+    image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_luma8()
+}
+
+/// Packs 64 `bits` into hash, the first one into the highest bit.
+fn bits<I: Iterator<Item = bool>>(bits: I) -> u64 {
+    bits.fold(0, |hash, bit| hash << 1 | bit as u64)
+}
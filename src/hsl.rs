@@ -0,0 +1,63 @@
+/// Hue centers of color bands in degrees, in `ColorBand` order.
+pub const BAND_HUES: [f32; 8] = [0.0, 30.0, 60.0, 120.0, 180.0, 240.0, 270.0, 300.0];
+
+/// Converts RGB color with channels in `[0, 1]` to hue in degrees in `[0, 360)`, saturation and
+/// lightness in `[0, 1]`.
+pub fn rgb_to_hsl(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta <= 0.0 {
+        return [0.0, 0.0, lightness];
+    }
+
+    let hue = match max {
+        m if m == r => 60.0 * ((g - b) / delta).rem_euclid(6.0),
+        m if m == g => 60.0 * ((b - r) / delta + 2.0),
+        _ => 60.0 * ((r - g) / delta + 4.0),
+    };
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs()).max(f32::EPSILON);
+    [hue, saturation.min(1.0), lightness]
+}
+
+/// Converts color given as hue in degrees, saturation and lightness in `[0, 1]` to RGB with
+/// channels in `[0, 1]`.
+pub fn hsl_to_rgb(hsl: [f32; 3]) -> [f32; 3] {
+    let [hue, saturation, lightness] = hsl;
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    [r + m, g + m, b + m]
+}
+
+/// Returns membership of `hue` in degrees in each color band. Memberships fall linearly from
+/// band center to centers of neighbour bands and add up to `1`.
+pub fn band_weights(hue: f32) -> [f32; 8] {
+    let hue = hue.rem_euclid(360.0);
+    let mut weights = [0.0; 8];
+    for i in 0..BAND_HUES.len() {
+        let next = (i + 1) % BAND_HUES.len();
+        let start = BAND_HUES[i];
+        let end = match next {
+            0 => 360.0,
+            _ => BAND_HUES[next],
+        };
+        if (start..end).contains(&hue) {
+            let t = (hue - start) / (end - start);
+            weights[i] = 1.0 - t;
+            weights[next] = t;
+        }
+    }
+    weights
+}
@@ -0,0 +1,227 @@
+use crate::{IconSetKind, ImageError};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Sizes of images stored in `favicon.ico`.
+const FAVICON_SIZES: [u32; 3] = [16, 32, 48];
+
+/// Square PNG icon of icon set.
+struct Icon {
+    /// Path relative to output directory.
+    file: String,
+    size: u32,
+    /// Transparent pixels are composited over white, as iOS home screen icons can't have alpha.
+    opaque: bool,
+}
+
+impl Icon {
+    fn new(file: String, size: u32) -> Self {
+        Self {
+            file,
+            size,
+            opaque: false,
+        }
+    }
+}
+
+/// Writes icons of `kind` rendered from `image` and their manifest, if platform has one, to
+/// `dir`, creating it if needed. Non-square images are centered on transparent square canvas.
+pub fn generate(image: &DynamicImage, kind: IconSetKind, dir: &Path) -> Result<(), ImageError> {
+    std::fs::create_dir_all(dir).map_err(crate::io_error)?;
+    let (icons, manifest) = match kind {
+        IconSetKind::Favicon => (favicon(image, dir)?, None),
+        IconSetKind::Ios => ios(),
+        IconSetKind::Android => (android(), None),
+        IconSetKind::Pwa => pwa(),
+    };
+
+    for icon in &icons {
+        let path = dir.join(&icon.file);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(crate::io_error)?;
+        }
+        let png = png(&render(image, icon.size, icon.opaque)?)?;
+        std::fs::write(path, png).map_err(crate::io_error)?;
+    }
+    if let Some((file, manifest)) = manifest {
+        let manifest = serde_json::to_string_pretty(&manifest).map_err(|_| ImageError::Internal)?;
+        std::fs::write(dir.join(file), manifest).map_err(crate::io_error)?;
+    }
+    Ok(())
+}
+
+/// Writes `favicon.ico` with `FAVICON_SIZES` images and returns PNG icons linked from HTML.
+fn favicon(image: &DynamicImage, dir: &Path) -> Result<Vec<Icon>, ImageError> {
+    let images = FAVICON_SIZES
+        .iter()
+        .map(|&size| Ok((size, png(&render(image, size, false)?)?)))
+        .collect::<Result<Vec<_>, ImageError>>()?;
+    std::fs::write(dir.join("favicon.ico"), ico(&images)).map_err(crate::io_error)?;
+
+    let mut icons = vec![
+        Icon::new("favicon-16x16.png".into(), 16),
+        Icon::new("favicon-32x32.png".into(), 32),
+    ];
+    icons.push(Icon {
+        opaque: true,
+        ..Icon::new("apple-touch-icon.png".into(), 180)
+    });
+    Ok(icons)
+}
+
+/// Returns icons of iOS app icon set with its `Contents.json`.
+fn ios() -> (Vec<Icon>, Option<(&'static str, Value)>) {
+    // Point size, scales and idiom of every icon slot of asset catalog.
+    let slots: [(f32, &[u32], &str); 10] = [
+        (20.0, &[2, 3], "iphone"),
+        (29.0, &[2, 3], "iphone"),
+        (40.0, &[2, 3], "iphone"),
+        (60.0, &[2, 3], "iphone"),
+        (20.0, &[1, 2], "ipad"),
+        (29.0, &[1, 2], "ipad"),
+        (40.0, &[1, 2], "ipad"),
+        (76.0, &[1, 2], "ipad"),
+        (83.5, &[2], "ipad"),
+        (1024.0, &[1], "ios-marketing"),
+    ];
+
+    let mut icons: Vec<Icon> = Vec::new();
+    let mut images = Vec::new();
+    for &(points, scales, idiom) in &slots {
+        for &scale in scales {
+            let file = format!("Icon-{}@{}x.png", points, scale);
+            // Slots of the same size and scale share file.
+            if !icons.iter().any(|icon| icon.file == file) {
+                icons.push(Icon {
+                    opaque: true,
+                    ..Icon::new(file.clone(), (points * scale as f32) as u32)
+                });
+            }
+            images.push(json!({
+                "size": format!("{}x{}", points, points),
+                "idiom": idiom,
+                "filename": file,
+                "scale": format!("{}x", scale),
+            }));
+        }
+    }
+    let contents = json!({"images": images, "info": {"version": 1, "author": "xcode"}});
+    (icons, Some(("Contents.json", contents)))
+}
+
+/// Returns launcher icons of Android density buckets and Play Store icon.
+fn android() -> Vec<Icon> {
+    let densities = [
+        ("mdpi", 48),
+        ("hdpi", 72),
+        ("xhdpi", 96),
+        ("xxhdpi", 144),
+        ("xxxhdpi", 192),
+    ];
+    let mut icons: Vec<_> = densities
+        .iter()
+        .map(|&(density, size)| Icon::new(format!("mipmap-{}/ic_launcher.png", density), size))
+        .collect();
+    icons.push(Icon::new("playstore-icon.png".into(), 512));
+    icons
+}
+
+/// Returns icons of web app manifest with manifest listing them.
+fn pwa() -> (Vec<Icon>, Option<(&'static str, Value)>) {
+    let mut icons: Vec<_> = [192, 512]
+        .iter()
+        .map(|&size| Icon::new(format!("icon-{0}x{0}.png", size), size))
+        .collect();
+    let entries: Vec<_> = icons
+        .iter()
+        .map(|icon| {
+            json!({
+                "src": icon.file,
+                "sizes": format!("{0}x{0}", icon.size),
+                "type": "image/png",
+            })
+        })
+        .collect();
+    icons.push(Icon {
+        opaque: true,
+        ..Icon::new("apple-touch-icon.png".into(), 180)
+    });
+    (icons, Some(("manifest.json", json!({ "icons": entries }))))
+}
+
+/// Returns `image` fitted into centered `size` x `size` square.
+fn render(image: &DynamicImage, size: u32, opaque: bool) -> Result<DynamicImage, ImageError> {
+    // Resizing allocates intermediate image of target width and source height.
+    let height = size.max(image.height());
+    crate::check_alloc(crate::image_bytes(size, height, image::ColorType::Rgba8))?;
+    let filter = image::imageops::FilterType::Lanczos3;
+    let resized = image.resize(size, size, filter).to_rgba8();
+
+    let background = if opaque { [255; 4] } else { [0; 4] };
+    let mut canvas = RgbaImage::from_pixel(size, size, Rgba(background));
+    let (x, y) = ((size - resized.width()) / 2, (size - resized.height()) / 2);
+    image::imageops::overlay(&mut canvas, &resized, x, y);
+    let canvas = DynamicImage::ImageRgba8(canvas);
+    Ok(match opaque {
+        true => DynamicImage::ImageRgb8(canvas.to_rgb8()),
+        false => canvas,
+    })
+}
+
+/// Encodes `image` as PNG with the best compression, trying every row filter and dropping alpha
+/// channel of opaque images.
+fn png(image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+    let _span = crate::trace::span("encode");
+    let opaque = image.to_rgba8().pixels().all(|p| p.0[3] == u8::MAX);
+    let image = match image {
+        DynamicImage::ImageRgba8(_) if opaque => DynamicImage::ImageRgb8(image.to_rgb8()),
+        image => image.clone(),
+    };
+    let (width, height) = image.dimensions();
+
+    let filters = [
+        FilterType::NoFilter,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Avg,
+        FilterType::Paeth,
+    ];
+    let mut best: Option<Vec<u8>> = None;
+    for &filter in &filters {
+        let mut encoded = Vec::new();
+        let encoder = PngEncoder::new_with_quality(&mut encoded, CompressionType::Best, filter);
+        encoder.encode(image.as_bytes(), width, height, image.color())?;
+        if best.as_ref().is_none_or(|b| encoded.len() < b.len()) {
+            best = Some(encoded);
+        }
+    }
+    Ok(best.unwrap_or_default())
+}
+
+/// Returns ICO file storing PNG `images` of given sizes, supported by all current browsers.
+fn ico(images: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut ico = Vec::new();
+    ico.extend_from_slice(&0u16.to_le_bytes());
+    // Resource type 1 is icon.
+    ico.extend_from_slice(&1u16.to_le_bytes());
+    ico.extend_from_slice(&(images.len() as u16).to_le_bytes());
+
+    let mut offset = 6 + 16 * images.len();
+    for (size, png) in images {
+        // Size of 256 pixels is stored as 0.
+        let side = if *size >= 256 { 0 } else { *size as u8 };
+        ico.extend_from_slice(&[side, side, 0, 0]);
+        // Color planes and bits per pixel.
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        ico.extend_from_slice(&32u16.to_le_bytes());
+        ico.extend_from_slice(&(png.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += png.len();
+    }
+    for (_, png) in images {
+        ico.extend_from_slice(png);
+    }
+    ico
+}
@@ -0,0 +1,271 @@
+use crate::progress::{self, Token};
+use crate::{handles, recipe, trace, ImageData, ImageError, ImageHandle, JobStatus, LastError};
+use crate::{OpenOptions, RawPath};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ffi::CString;
+use std::path::PathBuf;
+use std::ptr::null_mut;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Operation run by job on worker thread. Images are copies owned by job, so host may use
+/// and destroy originals while job runs.
+pub enum Task {
+    /// Decodes file at path with default options, as `img_open` does.
+    Open(PathBuf),
+    /// Encodes image into file at path, as `img_save` does.
+    Save(ImageData, CString),
+    /// Applies operation of edit stacks with parameters to image, as `img_apply` does.
+    Apply(ImageData, String, String),
+}
+
+/// Outcome of job.
+enum State {
+    Running,
+    /// Job succeeded with resulting image, `None` for `Task::Save`.
+    Finished(Option<Box<ImageData>>),
+    Failed(LastError),
+    /// Result of successful job was passed to host.
+    Taken,
+}
+
+/// Operation running on worker thread, shared by it and job handle of host.
+pub struct Job {
+    state: Mutex<State>,
+    finished: Condvar,
+}
+
+impl Job {
+    pub fn status(&self) -> JobStatus {
+        match &*self.state.lock().unwrap_or_else(|e| e.into_inner()) {
+            State::Running => JobStatus::Running,
+            State::Finished(_) | State::Taken => JobStatus::Finished,
+            State::Failed(_) => JobStatus::Failed,
+        }
+    }
+
+    /// Waits until job finishes and takes its resulting image. Returns `Ok(None)` if job
+    /// produces no image. Error of failed job is returned every time.
+    pub fn wait(&self) -> Result<Option<Box<ImageData>>, LastError> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let running = |s: &mut State| matches!(s, State::Running);
+        let mut state = self
+            .finished
+            .wait_while(state, running)
+            .unwrap_or_else(|e| e.into_inner());
+        match std::mem::replace(&mut *state, State::Taken) {
+            State::Finished(image) => Ok(image),
+            State::Failed(error) => {
+                *state = State::Failed(error.clone());
+                Err(error)
+            }
+            _ => Err(LastError::new(
+                ImageError::Parameter,
+                "Result of job was already taken".to_string(),
+            )),
+        }
+    }
+}
+
+impl Job {
+    fn new() -> Arc<Self> {
+        Arc::new(Job {
+            state: Mutex::new(State::Running),
+            finished: Condvar::new(),
+        })
+    }
+
+    fn finish(&self, state: State) {
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = state;
+        self.finished.notify_all();
+    }
+}
+
+/// Jobs limited to `max_parallel` running at once, so jobs of other queues and operations get
+/// worker threads too. Waiting jobs start from the highest priority one, in order of submitting
+/// for equal priorities.
+pub struct Queue {
+    max_parallel: usize,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    waiting: BinaryHeap<Waiting>,
+    /// Number of jobs submitted so far, ordering jobs of equal priority.
+    submitted: u64,
+}
+
+/// Job waiting in queue until fewer than `max_parallel` jobs of queue run.
+struct Waiting {
+    priority: i32,
+    order: u64,
+    job: Arc<Job>,
+    task: Task,
+    token: Option<Arc<Token>>,
+}
+
+impl Ord for Waiting {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Heap pops the greatest one, which is the earliest one of the highest priority.
+        let by_priority = self.priority.cmp(&other.priority);
+        by_priority.then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for Waiting {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiting {
+    fn eq(&self, other: &Self) -> bool {
+        self.order == other.order
+    }
+}
+
+impl Eq for Waiting {}
+
+impl Queue {
+    /// Creates queue running up to `max_parallel` jobs at once, at least one.
+    pub fn new(max_parallel: u32) -> Arc<Self> {
+        Arc::new(Queue {
+            max_parallel: max_parallel.max(1) as usize,
+            state: Default::default(),
+        })
+    }
+
+    /// Starts waiting jobs while fewer than `max_parallel` jobs run.
+    fn start_waiting(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.running < self.max_parallel {
+            let waiting = match state.waiting.pop() {
+                Some(w) => w,
+                None => return,
+            };
+            state.running += 1;
+            let queue = self.clone();
+            let job = waiting.job.clone();
+            let started = start(waiting.job, waiting.task, waiting.token, move || {
+                queue
+                    .state
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .running -= 1;
+                queue.start_waiting();
+            });
+            if let Err(e) = started {
+                // Host learns of it from job, as submitting it may have returned long ago.
+                state.running -= 1;
+                let message = format!("Failed to start job: {}", e);
+                job.finish(State::Failed(LastError::new(
+                    ImageError::Unsupported,
+                    message,
+                )));
+            }
+        }
+    }
+}
+
+/// Queues `task` on worker threads of `parallel::spawn`, aborting it once `token` is cancelled.
+/// Fails with `ImageError::Unsupported` if platform can't spawn threads.
+pub fn submit(task: Task, token: Option<Arc<Token>>) -> Result<Arc<Job>, ImageError> {
+    let job = Job::new();
+    match start(job.clone(), task, token, || {}) {
+        Ok(_) => Ok(job),
+        Err(e) => {
+            let message = format!("Failed to start job: {}", e);
+            crate::set_last_error(LastError::new(ImageError::Unsupported, message));
+            Err(ImageError::Unsupported)
+        }
+    }
+}
+
+/// Adds `task` to `queue` with `priority`, starting it once fewer than limit of queue jobs run.
+/// Job fails with `ImageError::Unsupported` if platform can't spawn threads.
+pub fn submit_on(
+    queue: &Arc<Queue>,
+    priority: i32,
+    task: Task,
+    token: Option<Arc<Token>>,
+) -> Arc<Job> {
+    let job = Job::new();
+    {
+        let mut state = queue.state.lock().unwrap_or_else(|e| e.into_inner());
+        let order = state.submitted;
+        state.submitted += 1;
+        state.waiting.push(Waiting {
+            priority,
+            order,
+            job: job.clone(),
+            task,
+            token,
+        });
+    }
+    queue.start_waiting();
+    job
+}
+
+/// Runs `task` of `job` on worker threads of `parallel::spawn`, calling `finished` after it.
+fn start<F>(
+    job: Arc<Job>,
+    task: Task,
+    token: Option<Arc<Token>>,
+    finished: F,
+) -> std::io::Result<()>
+where
+    F: FnOnce() + Send + 'static,
+{
+    crate::parallel::spawn(Box::new(move || {
+        let mut image = None;
+        let run = || unsafe { run(task, &mut image) };
+        let code = crate::guard(|| progress::run_cancellable(None, null_mut(), token, run));
+        job.finish(match code {
+            ImageError::NoError => State::Finished(image),
+            code => State::Failed(take_last_error(code)),
+        });
+        finished();
+    }))
+}
+
+/// Runs `task` writing its resulting image detached from worker thread to `result`.
+unsafe fn run(task: Task, result: &mut Option<Box<ImageData>>) -> ImageError {
+    let _span = trace::span("job");
+    let data = match task {
+        Task::Open(path) => match crate::open_file(&path, &OpenOptions::default()) {
+            Ok(data) => data.into_box(),
+            Err(e) => return e,
+        },
+        Task::Save(image, path) => {
+            let handle = ImageHandle::from_data(image);
+            let copy = ImageHandle(handle.0);
+            let code = crate::img_save(RawPath(path.as_ptr()), handle);
+            copy.into_data();
+            return code;
+        }
+        Task::Apply(image, op, params) => {
+            let handle = ImageHandle::from_data(image);
+            let applied = recipe::apply_op(&handle, &op, &params);
+            let data = handle.into_data();
+            if let Err(e) = applied {
+                return e;
+            }
+            data
+        }
+    };
+    // Host attaches image to its thread when it takes result.
+    let _ = handles::detach(&*data as *const ImageData as usize);
+    *result = Some(data);
+    ImageError::NoError
+}
+
+/// Takes details of error `code` the last failed call of current thread left.
+pub fn take_last_error(code: ImageError) -> LastError {
+    let last = crate::LAST_ERROR.with(|last| last.borrow_mut().take());
+    match last {
+        Some(error) => LastError { code, ..error },
+        None => LastError::new(code, "Job failed".to_string()),
+    }
+}
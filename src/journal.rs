@@ -0,0 +1,134 @@
+use crate::meta::Metadata;
+use crate::SharedImage;
+use serde_json::Value;
+use std::collections::VecDeque;
+
+/// State of image recorded before edit. Pixels are shared with image until either is changed.
+pub struct Snapshot {
+    image: SharedImage,
+    metadata: Metadata,
+}
+
+impl Snapshot {
+    /// Puts recorded state into `image` and `metadata` and returns their previous state.
+    pub fn swap(self, image: &mut SharedImage, metadata: &mut Metadata) -> Self {
+        Self {
+            image: std::mem::replace(image, self.image),
+            metadata: std::mem::replace(metadata, self.metadata),
+        }
+    }
+}
+
+/// Undo and redo history of edits of one image. Journal with zero depth records no snapshots,
+/// but descriptions of applied edits are always kept.
+#[derive(Default)]
+pub struct Journal {
+    depth: usize,
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Applied edits in order of application, e.g. `{"op": "mirror"}`.
+    edits: Vec<Value>,
+    /// Undone edits, the last undone is the last.
+    undone: Vec<Value>,
+}
+
+impl Journal {
+    /// Sets maximum number of recorded edits. The oldest edits are forgotten first. Zero depth
+    /// disables journal and clears history.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        while self.undo.len() > depth {
+            self.undo.pop_front();
+        }
+        if depth == 0 {
+            self.redo.clear();
+            self.undone.clear();
+        }
+    }
+
+    /// Returns state of image sharing its pixels if journal is enabled.
+    pub fn snapshot(&self, image: &SharedImage, metadata: &Metadata) -> Option<Snapshot> {
+        match self.depth {
+            0 => None,
+            _ => Some(Snapshot {
+                image: image.clone(),
+                metadata: metadata.clone(),
+            }),
+        }
+    }
+
+    /// Records `edit` description and `snapshot` of image taken before it. Redo history is
+    /// cleared.
+    pub fn push(&mut self, snapshot: Option<Snapshot>, edit: Value) {
+        if let Some(snapshot) = snapshot {
+            self.push_undo(snapshot);
+        }
+        self.redo.clear();
+        self.edits.push(edit);
+        self.undone.clear();
+    }
+
+    /// Records `snapshot` for undo, forgetting the oldest one if journal is full.
+    fn push_undo(&mut self, snapshot: Snapshot) {
+        if self.undo.len() == self.depth {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(snapshot);
+    }
+
+    /// Records `edit` description and state of image before it.
+    pub fn record(&mut self, image: &SharedImage, metadata: &Metadata, edit: Value) {
+        let snapshot = self.snapshot(image, metadata);
+        self.push(snapshot, edit);
+    }
+
+    /// Forgets history and applied edits keeping depth, e.g. when image is overwritten with
+    /// result of operation on another one.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.edits.clear();
+        self.undone.clear();
+    }
+
+    /// Replaces descriptions of applied edits, e.g. for image derived from another one.
+    pub fn set_edits(&mut self, edits: Vec<Value>) {
+        self.edits = edits;
+        self.undone.clear();
+    }
+
+    /// Returns descriptions of applied edits in order of application.
+    pub fn edits(&self) -> &[Value] {
+        &self.edits
+    }
+
+    /// Reverts the last edit. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self, image: &mut SharedImage, metadata: &mut Metadata) -> bool {
+        match self.undo.pop_back() {
+            Some(snapshot) => {
+                self.redo.push(snapshot.swap(image, metadata));
+                self.undone.extend(self.edits.pop());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapplies the last undone edit. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self, image: &mut SharedImage, metadata: &mut Metadata) -> bool {
+        match self.redo.pop() {
+            Some(snapshot) => {
+                let snapshot = snapshot.swap(image, metadata);
+                self.push_undo(snapshot);
+                self.edits.extend(self.undone.pop());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns numbers of edits available for undo and redo.
+    pub fn len(&self) -> (usize, usize) {
+        (self.undo.len(), self.redo.len())
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{Keypoint, KeypointMatch};
+use image::{DynamicImage, GrayImage};
+
+/// Offsets of 16 pixels of Bresenham circle of radius 3 tested by FAST in clockwise order.
+const CIRCLE: [(i32, i32); 16] = [
+    (0, -3),
+    (1, -3),
+    (2, -2),
+    (3, -1),
+    (3, 0),
+    (3, 1),
+    (2, 2),
+    (1, 3),
+    (0, 3),
+    (-1, 3),
+    (-2, 2),
+    (-3, 1),
+    (-3, 0),
+    (-3, -1),
+    (-2, -2),
+    (-1, -3),
+];
+/// Number of contiguous circle pixels which must be brighter or darker than the center.
+const ARC: usize = 9;
+/// Difference of intensity from the center which makes circle pixel brighter or darker.
+const THRESHOLD: i32 = 20;
+/// Maximum number of keypoints with the strongest responses kept.
+const MAX_KEYPOINTS: usize = 1000;
+/// Radius of patch used for orientation of ORB keypoints.
+const PATCH_RADIUS: i32 = 15;
+/// Maximum offset of pixels compared by ORB descriptor from keypoint before rotation.
+const PATTERN_RADIUS: i32 = 13;
+/// Distance from image border where ORB patches fit for any rotation.
+const ORB_BORDER: i32 = 20;
+/// Maximum Hamming distance between descriptors of matched keypoints.
+const MAX_DISTANCE: u32 = 64;
+/// Maximum ratio of distances to the best and the second best candidates of accepted match.
+const RATIO: f32 = 0.8;
+/// Maximum difference in pixels of match offset from estimated one to count it as inlier.
+const INLIER_DISTANCE: f32 = 3.0;
+
+/// Detects FAST-9 corners of `image` with the strongest responses. If `orb` is set, keypoints
+/// also get orientation and rotated BRIEF descriptors, and keypoints too close to border for
+/// them are dropped.
+pub fn detect(image: &DynamicImage, orb: bool) -> Vec<Keypoint> {
+    let gray = image.to_luma8();
+    let border = if orb { ORB_BORDER } else { 3 };
+    let mut keypoints = fast(&gray, border);
+    keypoints.sort_by(|a, b| b.response.total_cmp(&a.response));
+    keypoints.truncate(MAX_KEYPOINTS);
+
+    if orb {
+        // Descriptor tests compare smoothed pixels to be robust to noise.
+        let smooth = crate::parallel::blur(&gray, 2.0);
+        let pattern = pattern();
+        for keypoint in &mut keypoints {
+            keypoint.angle = orientation(&gray, keypoint.x as i32, keypoint.y as i32);
+            keypoint.descriptor = describe(&smooth, keypoint, &pattern);
+        }
+    }
+    keypoints
+}
+
+/// Returns corners further than `border` pixels from image edges after non-maximum suppression.
+fn fast(gray: &GrayImage, border: i32) -> Vec<Keypoint> {
+    let (width, height) = (gray.width() as i32, gray.height() as i32);
+    if width <= 2 * border || height <= 2 * border {
+        return Vec::new();
+    }
+
+    let at = |x: i32, y: i32| gray.get_pixel(x as u32, y as u32).0[0] as i32;
+    let mut scores = vec![0.0f32; (width * height) as usize];
+    for y in border..height - border {
+        for x in border..width - border {
+            let center = at(x, y);
+            let ring = CIRCLE.map(|(dx, dy)| at(x + dx, y + dy));
+            let brighter = ring.map(|v| v > center + THRESHOLD);
+            let darker = ring.map(|v| v < center - THRESHOLD);
+            if !has_arc(&brighter) && !has_arc(&darker) {
+                continue;
+            }
+            // Response is the larger of total contrasts of brighter and darker pixels.
+            let excess = |sign: i32| {
+                let contrast = ring.iter().map(move |&v| sign * (v - center) - THRESHOLD);
+                contrast.filter(|&c| c > 0).sum::<i32>()
+            };
+            scores[(y * width + x) as usize] = excess(1).max(excess(-1)) as f32;
+        }
+    }
+
+    let mut keypoints = Vec::new();
+    for y in border..height - border {
+        for x in border..width - border {
+            let score = scores[(y * width + x) as usize];
+            if score == 0.0 {
+                continue;
+            }
+            // Ties are resolved in favor of the first pixel in scan order.
+            let suppressed = (-1..=1).any(|dy| {
+                (-1..=1).any(|dx| {
+                    let other = scores[((y + dy) * width + x + dx) as usize];
+                    other > score || (other == score && (dy, dx) < (0, 0))
+                })
+            });
+            if !suppressed {
+                keypoints.push(Keypoint {
+                    x: x as f32,
+                    y: y as f32,
+                    response: score,
+                    angle: 0.0,
+                    descriptor: [0; 32],
+                });
+            }
+        }
+    }
+    keypoints
+}
+
+/// Returns `true` if circle has `ARC` contiguous flagged pixels.
+fn has_arc(flags: &[bool]) -> bool {
+    let mut run = 0;
+    for &flag in flags.iter().chain(&flags[..ARC - 1]) {
+        run = if flag { run + 1 } else { 0 };
+        if run >= ARC {
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns direction in radians from `(x, y)` to intensity centroid of patch around it.
+fn orientation(gray: &GrayImage, x: i32, y: i32) -> f32 {
+    let (mut m01, mut m10) = (0i64, 0i64);
+    for dy in -PATCH_RADIUS..=PATCH_RADIUS {
+        for dx in -PATCH_RADIUS..=PATCH_RADIUS {
+            if dx * dx + dy * dy > PATCH_RADIUS * PATCH_RADIUS {
+                continue;
+            }
+            let value = gray.get_pixel((x + dx) as u32, (y + dy) as u32).0[0] as i64;
+            m10 += dx as i64 * value;
+            m01 += dy as i64 * value;
+        }
+    }
+    (m01 as f32).atan2(m10 as f32)
+}
+
+/// Returns 256 pairs of offsets compared by descriptor. Offsets are pseudo-random with roughly
+/// Gaussian distribution, but the same in every run, so descriptors are comparable.
+fn pattern() -> Vec<[(f32, f32); 2]> {
+    let mut state = 0x2545_f491_u32;
+    let mut next = move || {
+        // Sum of uniform values approximates Gaussian one.
+        let sum: i32 = (0..4)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                ((state >> 16) % (2 * PATTERN_RADIUS as u32 + 1)) as i32 - PATTERN_RADIUS
+            })
+            .sum();
+        (sum as f32 / 2.0).clamp(-PATTERN_RADIUS as f32, PATTERN_RADIUS as f32)
+    };
+    (0..256)
+        .map(|_| [(next(), next()), (next(), next())])
+        .collect()
+}
+
+/// Returns rotated BRIEF descriptor of `keypoint`: bit is set if the first pixel of pattern pair
+/// rotated by keypoint orientation is darker than the second one.
+fn describe(smooth: &GrayImage, keypoint: &Keypoint, pattern: &[[(f32, f32); 2]]) -> [u8; 32] {
+    let (sin, cos) = keypoint.angle.sin_cos();
+    let at = |(dx, dy): (f32, f32)| {
+        let x = keypoint.x + (dx * cos - dy * sin).round();
+        let y = keypoint.y + (dx * sin + dy * cos).round();
+        smooth.get_pixel(x as u32, y as u32).0[0]
+    };
+    let mut descriptor = [0u8; 32];
+    for (bit, &[first, second]) in pattern.iter().enumerate() {
+        if at(first) < at(second) {
+            descriptor[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+    descriptor
+}
+
+/// Estimated translation between keypoints of two images and matches it is based on.
+pub struct Alignment {
+    pub matches: Vec<KeypointMatch>,
+    pub offset: (f32, f32),
+    pub inliers: usize,
+}
+
+/// Matches ORB keypoints of images `a` and `b` and estimates offset which moves points of `a`
+/// to the same points of `b`.
+pub fn align(a: &DynamicImage, b: &DynamicImage) -> Alignment {
+    let (a, b) = (detect(a, true), detect(b, true));
+    let mut matches = matches(&a, &b);
+
+    // Offset supported by the most matches wins, then it is averaged over them.
+    let delta = |m: &KeypointMatch| (m.bx - m.ax, m.by - m.ay);
+    let supports = |offset: (f32, f32), m: &KeypointMatch| {
+        let (dx, dy) = delta(m);
+        (dx - offset.0).hypot(dy - offset.1) <= INLIER_DISTANCE
+    };
+    let best = matches
+        .iter()
+        .map(|candidate| {
+            let offset = delta(candidate);
+            let support = matches.iter().filter(|m| supports(offset, m)).count();
+            (support, offset)
+        })
+        .fold(
+            (0, (0.0, 0.0)),
+            |best, next| if next.0 > best.0 { next } else { best },
+        );
+
+    let mut offset = (0.0, 0.0);
+    let mut inliers = 0;
+    for m in &mut matches {
+        m.inlier = best.0 > 0 && supports(best.1, m);
+        if m.inlier {
+            let (dx, dy) = delta(m);
+            offset = (offset.0 + dx, offset.1 + dy);
+            inliers += 1;
+        }
+    }
+    if inliers != 0 {
+        offset = (offset.0 / inliers as f32, offset.1 / inliers as f32);
+    }
+    Alignment {
+        matches,
+        offset,
+        inliers,
+    }
+}
+
+/// Returns mutually best matches of descriptors of `a` and `b` passing ratio test.
+fn matches(a: &[Keypoint], b: &[Keypoint]) -> Vec<KeypointMatch> {
+    let distance = |a: &Keypoint, b: &Keypoint| {
+        let pairs = a.descriptor.iter().zip(&b.descriptor);
+        pairs.map(|(a, b)| (a ^ b).count_ones()).sum::<u32>()
+    };
+    // Returns index of the nearest keypoint of `to` and distances to the nearest two.
+    let nearest = |from: &Keypoint, to: &[Keypoint]| {
+        let mut best = (usize::MAX, u32::MAX, u32::MAX);
+        for (index, keypoint) in to.iter().enumerate() {
+            let d = distance(from, keypoint);
+            if d < best.1 {
+                best = (index, d, best.1);
+            } else if d < best.2 {
+                best.2 = d;
+            }
+        }
+        best
+    };
+
+    let mut matches = Vec::new();
+    for (index, keypoint) in a.iter().enumerate() {
+        let (found, best, second) = nearest(keypoint, b);
+        if found == usize::MAX || best > MAX_DISTANCE {
+            continue;
+        }
+        let distinct = second == u32::MAX || (best as f32) < RATIO * second as f32;
+        if distinct && nearest(&b[found], a).0 == index {
+            matches.push(KeypointMatch {
+                ax: keypoint.x,
+                ay: keypoint.y,
+                bx: b[found].x,
+                by: b[found].y,
+                distance: best,
+                inlier: false,
+            });
+        }
+    }
+    matches
+}
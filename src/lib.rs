@@ -1,188 +1,10353 @@
-use image::DynamicImage;
+use base64::Engine;
+use image::{DynamicImage, GenericImage, GenericImageView, Pixel};
+use serde_json::json;
+use std::cell::RefCell;
 use std::convert::{TryFrom, TryInto};
-use std::ffi::{c_void, CStr};
+use std::ffi::{c_void, CStr, CString};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
 use std::os::raw::c_char;
 use std::path::Path;
+use std::sync::Arc;
+
+mod align;
+mod analysis;
+#[cfg(feature = "rust-api")]
+pub mod api;
+mod atlas;
+mod batch;
+mod budget;
+mod cache;
+mod card;
+mod codec;
+mod color;
+mod crop;
+mod decode;
+mod document;
+mod draw;
+mod export;
+mod film;
+mod filter;
+mod generate;
+mod handles;
+mod hash;
+mod hsl;
+mod icons;
+mod job;
+mod journal;
+mod keypoints;
+mod log;
+mod matching;
+mod mesh;
+mod meta;
+mod ora;
+mod pack;
+mod parallel;
+mod pipeline;
+mod placeholder;
+mod pool;
+mod progress;
+mod psd;
+mod recipe;
+mod simd;
+mod text;
+mod tiled;
+mod trace;
+
+/// Tag of live `ImageData` allocations, so garbage and destroyed handles can be told apart.
+const IMAGE_MAGIC: u64 = u64::from_be_bytes(*b"IMAGE_SL");
+
+/// Pixels of image shared by its copy-on-write clones. Pixels are copied once one of sharing
+/// images is borrowed mutably, so only changed images own their pixels.
+#[derive(Clone)]
+struct SharedImage(Arc<DynamicImage>);
+
+impl From<DynamicImage> for SharedImage {
+    fn from(image: DynamicImage) -> Self {
+        Self(Arc::new(image))
+    }
+}
+
+impl std::ops::Deref for SharedImage {
+    type Target = DynamicImage;
+
+    fn deref(&self) -> &DynamicImage {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for SharedImage {
+    fn deref_mut(&mut self) -> &mut DynamicImage {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+/// Image stored behind `ImageHandle`.
+struct ImageData {
+    /// `IMAGE_MAGIC` while data is alive.
+    magic: u64,
+    image: SharedImage,
+    metadata: meta::Metadata,
+    journal: journal::Journal,
+    /// Regions of image with JSON markup attached by host, e.g. review comments.
+    annotations: Vec<(Rect, String)>,
+    /// Frozen image is never changed, so it may be read by several threads at once.
+    frozen: bool,
+    /// Pixels of locked image are written by host through pointer given by `img_lock_pixels`.
+    locked: bool,
+    /// Locked rows padded to alignment requested by `img_lock_pixels_as` and their stride,
+    /// copied to image once it is unlocked.
+    staging: Option<(Vec<u8>, usize)>,
+    /// Region of shared `image` shown by view of `img_view`, `None` for images owning their
+    /// pixels. Views are never changed.
+    view: Option<Rect>,
+}
+
+impl ImageData {
+    fn new(image: impl Into<SharedImage>, metadata: meta::Metadata) -> Self {
+        Self {
+            magic: IMAGE_MAGIC,
+            image: image.into(),
+            metadata,
+            journal: Default::default(),
+            annotations: Vec::new(),
+            frozen: false,
+            locked: false,
+            staging: None,
+            view: None,
+        }
+    }
+
+    /// Returns pixels of image for its copy. They are shared with image, unless it is locked and
+    /// host may change them.
+    fn share_image(&self) -> SharedImage {
+        match self.locked {
+            true => (*self.image).clone().into(),
+            false => self.image.clone(),
+        }
+    }
+
+    /// Returns clone of image sharing its pixels, with metadata, annotations and edit stack of
+    /// image but empty undo history. Clone of view is view of the same region.
+    fn share(&self) -> Self {
+        let mut copy = ImageData::new(self.share_image(), self.metadata.clone());
+        copy.annotations = self.annotations.clone();
+        copy.journal.set_edits(self.journal.edits().to_vec());
+        copy.view = self.view;
+        copy
+    }
+
+    /// Moves image to heap, where handles point to, and records it as live.
+    fn into_box(self) -> Box<Self> {
+        let data = Box::new(self);
+        handles::register(&*data as *const Self as usize);
+        data
+    }
+
+    /// Records current state of image and `step` describing edit in journal before edit.
+    fn checkpoint(&mut self, step: serde_json::Value) {
+        self.journal.record(&self.image, &self.metadata, step);
+    }
+
+    /// Applies fallible `edit` described by `step` recording state of image in journal before
+    /// it. If edit fails, state before it is restored and journal is left unchanged.
+    fn edit<F>(&mut self, step: serde_json::Value, edit: F) -> Result<(), ImageError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), ImageError>,
+    {
+        let snapshot = self.journal.snapshot(&self.image, &self.metadata);
+        let result = edit(self);
+        match (&result, snapshot) {
+            (Ok(_), snapshot) => self.journal.push(snapshot, step),
+            (Err(_), Some(snapshot)) => {
+                snapshot.swap(&mut self.image, &mut self.metadata);
+            }
+            (Err(_), None) => {}
+        }
+        result
+    }
+
+    /// Transforms pixels as EXIF orientation tag prescribes and resets the tag, so image is
+    /// displayed upright without it.
+    fn auto_orient(&mut self) -> Result<(), ImageError> {
+        let exif = match &self.metadata.exif {
+            Some(e) => e,
+            None => return Ok(()),
+        };
+
+        let image = &self.image;
+        let oriented = match meta::exif_orientation(exif) {
+            None | Some(1) => return Ok(()),
+            Some(2) => image.fliph(),
+            Some(3) => image.rotate180(),
+            Some(4) => image.flipv(),
+            Some(5) => image.rotate90().fliph(),
+            Some(6) => image.rotate90(),
+            Some(7) => image.rotate270().fliph(),
+            _ => image.rotate270(),
+        };
+        self.image = oriented.into();
+
+        let exif = meta::set_exif_field(Some(exif), 0x0112, ExifType::Short, "1")?;
+        self.metadata.exif = Some(exif);
+        Ok(())
+    }
+}
+
+impl Drop for ImageData {
+    fn drop(&mut self) {
+        // Handles of destroyed image are rejected while its memory isn't reused.
+        unsafe { std::ptr::write_volatile(&mut self.magic, 0) };
+        handles::unregister(self as *const Self as usize);
+    }
+}
+
+/// Image read by `ImageHandle::read`.
+enum Reading {
+    Image(&'static ImageData),
+    /// Copy of region of view.
+    View(Box<ImageData>),
+}
+
+impl std::ops::Deref for Reading {
+    type Target = ImageData;
+
+    fn deref(&self) -> &ImageData {
+        match self {
+            Reading::Image(data) => data,
+            Reading::View(data) => data,
+        }
+    }
+}
 
 /// Incapsulate raw pointer to image.
 #[repr(transparent)]
+#[derive(Copy, Clone)]
 struct ImageHandle(*mut c_void);
 
 impl ImageHandle {
+    /// Returns `true` if handle points to live image created by this library. Null, misaligned
+    /// and destroyed handles and pointers to other data are rejected in most cases, but reading
+    /// arbitrary addresses may still crash. With debug registry enabled, only handles recorded
+    /// by it are accepted, memory behind handle isn't read and rejected handles are reported.
+    /// Handles of images owned by another thread or detached from threads are rejected too.
+    /// Frozen and locked images and views are rejected too, as they can't be changed; functions
+    /// only reading image check it by `is_readable` instead.
+    pub unsafe fn is_valid(&self) -> bool {
+        if !self.is_readable() {
+            return false;
+        }
+        let data = self.as_data_ref();
+        let state = match (data.frozen, data.locked, data.view) {
+            (true, _, _) => "frozen",
+            (_, true, _) => "locked by img_lock_pixels",
+            (_, _, Some(_)) => "view of img_view",
+            _ => return true,
+        };
+        let message = format!(
+            "Image handle {:p} is {} and can't be changed",
+            self.0, state
+        );
+        set_last_error(LastError::new(ImageError::InvalidHandle, message));
+        false
+    }
+
+    /// Returns `true` if handle may be read by function which doesn't change image: it is either
+    /// valid, locked or frozen one, which any thread may read. Such functions read image by
+    /// `read` or `as_region`, so views keep sharing pixels of their images.
+    pub unsafe fn is_readable(&self) -> bool {
+        if !self.is_live() {
+            return false;
+        }
+        if self.as_data_ref().frozen {
+            return true;
+        }
+        match handles::check_thread(self.0 as usize) {
+            Ok(_) => true,
+            Err(message) => {
+                let message = format!("Image handle {:p} {}", self.0, message);
+                set_last_error(LastError {
+                    sub_code: ErrorSubCode::WrongThread,
+                    ..LastError::new(ImageError::InvalidHandle, message)
+                });
+                false
+            }
+        }
+    }
+
+    /// Returns `true` if handle points to live image created by this library, whatever thread
+    /// owns it. See `is_valid`.
+    pub unsafe fn is_live(&self) -> bool {
+        let ptr = self.0 as *const ImageData;
+        let message = match handles::status(ptr as usize) {
+            Some(handles::Status::Live) => return true,
+            None if ptr.is_null() => return false,
+            None => {
+                return ptr.align_offset(std::mem::align_of::<ImageData>()) == 0
+                    && std::ptr::read_volatile(&(*ptr).magic) == IMAGE_MAGIC
+            }
+            Some(_) if ptr.is_null() => return false,
+            Some(handles::Status::Destroyed) => "is used after it was destroyed",
+            Some(handles::Status::Unknown) => "wasn't created by this library",
+        };
+        let message = format!("Image handle {:p} {}", ptr, message);
+        set_last_error(LastError::new(ImageError::InvalidHandle, message));
+        false
+    }
+
+    /// Returns image for changing it in place. Functions reading image use `read` instead.
     /// # Panics
     /// Panics if `self.0` == null.
-    pub unsafe fn as_image(&self) -> &'static mut DynamicImage {
-        let ptr = self.0 as *mut DynamicImage;
-        ptr.as_mut().unwrap() // Expect null checks before
+    pub unsafe fn as_image(&self) -> &'static DynamicImage {
+        &self.as_data_ref().image
+    }
+
+    /// Returns image for function reading it whole: image itself, or temporary image with copy
+    /// of region of view, while view keeps sharing pixels.
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn read(&self) -> Reading {
+        let data = self.as_data_ref();
+        match data.view {
+            Some(r) => {
+                let pixels = data.image.crop_imm(r.x, r.y, r.width, r.height);
+                Reading::View(Box::new(ImageData::new(pixels, data.metadata.clone())))
+            }
+            None => Reading::Image(data),
+        }
+    }
+
+    /// Returns image and its region shown by handle: whole image, or region of shared image for
+    /// view.
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn as_region(&self) -> (&'static DynamicImage, Rect) {
+        let data = self.as_data_ref();
+        let (width, height) = data.image.dimensions();
+        let whole = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        (&data.image, data.view.unwrap_or(whole))
+    }
+
+    /// Returns image for changing it, copying its pixels first if they are shared.
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn as_image_mut(&self) -> &'static mut DynamicImage {
+        &mut self.as_data().image
+    }
+
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn as_data_ref(&self) -> &'static ImageData {
+        let ptr = self.0 as *const ImageData;
+        ptr.as_ref().unwrap() // Expect validity checks before
+    }
+
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn as_data(&self) -> &'static mut ImageData {
+        let ptr = self.0 as *mut ImageData;
+        ptr.as_mut().unwrap() // Expect validity checks before
     }
 
     /// # Safety
     /// `self.0` != null.
-    pub unsafe fn into_image(self) -> Box<DynamicImage> {
-        let ptr = self.0 as *mut DynamicImage;
+    pub unsafe fn into_data(self) -> Box<ImageData> {
+        let ptr = self.0 as *mut ImageData;
         Box::from_raw(ptr)
     }
 
+    /// Creates handle of image without metadata.
     pub fn from_image(image: DynamicImage) -> Self {
-        let reference = Box::leak(Box::new(image));
-        let ptr = reference as *mut DynamicImage;
+        Self::from_data(ImageData::new(image, Default::default()))
+    }
+
+    pub fn from_data(data: ImageData) -> Self {
+        Self::from_box(data.into_box())
+    }
+
+    /// Creates handle of image keeping its allocation.
+    pub fn from_box(data: Box<ImageData>) -> Self {
+        Self(Box::into_raw(data) as _)
+    }
+}
+
+/// Incapsulate raw pointer to layered document.
+#[repr(transparent)]
+struct DocumentHandle(*mut c_void);
+
+impl DocumentHandle {
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn as_document(&self) -> &'static mut document::Document {
+        let ptr = self.0 as *mut document::Document;
+        ptr.as_mut().unwrap() // Expect null checks before
+    }
+
+    /// # Safety
+    /// `self.0` != null.
+    pub unsafe fn into_document(self) -> Box<document::Document> {
+        let ptr = self.0 as *mut document::Document;
+        Box::from_raw(ptr)
+    }
+
+    pub fn from_document(document: document::Document) -> Self {
+        let reference = Box::leak(Box::new(document));
+        let ptr = reference as *mut document::Document;
+        Self(ptr as _)
+    }
+}
+
+/// Incapsulate raw pointer to export profile.
+#[repr(transparent)]
+struct ExportProfileHandle(*mut c_void);
+
+impl ExportProfileHandle {
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn as_profile(&self) -> &'static export::Profile {
+        let ptr = self.0 as *const export::Profile;
+        ptr.as_ref().unwrap() // Expect null checks before
+    }
+
+    /// # Safety
+    /// `self.0` != null.
+    pub unsafe fn into_profile(self) -> Box<export::Profile> {
+        let ptr = self.0 as *mut export::Profile;
+        Box::from_raw(ptr)
+    }
+
+    pub fn from_profile(profile: export::Profile) -> Self {
+        let reference = Box::leak(Box::new(profile));
+        let ptr = reference as *mut export::Profile;
+        Self(ptr as _)
+    }
+}
+
+/// Incapsulate raw pointer to operation pipeline.
+#[repr(transparent)]
+struct PipelineHandle(*mut c_void);
+
+impl PipelineHandle {
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn as_pipeline(&self) -> &'static mut pipeline::Pipeline {
+        let ptr = self.0 as *mut pipeline::Pipeline;
+        ptr.as_mut().unwrap() // Expect null checks before
+    }
+
+    /// # Safety
+    /// `self.0` != null.
+    pub unsafe fn into_pipeline(self) -> Box<pipeline::Pipeline> {
+        let ptr = self.0 as *mut pipeline::Pipeline;
+        Box::from_raw(ptr)
+    }
+
+    pub fn from_pipeline(pipeline: pipeline::Pipeline) -> Self {
+        let reference = Box::leak(Box::new(pipeline));
+        let ptr = reference as *mut pipeline::Pipeline;
         Self(ptr as _)
     }
 }
 
+/// Incapsulate raw pointer to job shared with its worker thread.
+#[repr(transparent)]
+struct JobHandle(*mut c_void);
+
+impl JobHandle {
+    /// # Panics
+    /// Panics if `self.0` == null.
+    pub unsafe fn as_job(&self) -> &'static job::Job {
+        let ptr = self.0 as *const job::Job;
+        ptr.as_ref().unwrap() // Expect null checks before
+    }
+
+    /// # Safety
+    /// `self.0` != null.
+    pub unsafe fn into_job(self) -> Arc<job::Job> {
+        Arc::from_raw(self.0 as *const job::Job)
+    }
+
+    pub fn from_job(job: Arc<job::Job>) -> Self {
+        Self(Arc::into_raw(job) as _)
+    }
+}
+
+/// Incapsulate raw pointer to cancellation token shared with operations it was passed to.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+struct TokenHandle(*mut c_void);
+
+impl TokenHandle {
+    /// Returns shared token, or `None` if handle is null.
+    pub unsafe fn to_token(self) -> Option<Arc<progress::Token>> {
+        let ptr = self.0 as *const progress::Token;
+        if ptr.is_null() {
+            return None;
+        }
+        Arc::increment_strong_count(ptr);
+        Some(Arc::from_raw(ptr))
+    }
+
+    /// # Safety
+    /// `self.0` != null.
+    pub unsafe fn into_token(self) -> Arc<progress::Token> {
+        Arc::from_raw(self.0 as *const progress::Token)
+    }
+
+    pub fn from_token(token: Arc<progress::Token>) -> Self {
+        Self(Arc::into_raw(token) as _)
+    }
+}
+
+/// Incapsulate raw pointer to job queue shared with its jobs.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+struct QueueHandle(*mut c_void);
+
+impl QueueHandle {
+    /// Returns shared queue, or `None` if handle is null.
+    pub unsafe fn to_queue(self) -> Option<Arc<job::Queue>> {
+        let ptr = self.0 as *const job::Queue;
+        if ptr.is_null() {
+            return None;
+        }
+        Arc::increment_strong_count(ptr);
+        Some(Arc::from_raw(ptr))
+    }
+
+    /// # Safety
+    /// `self.0` != null.
+    pub unsafe fn into_queue(self) -> Arc<job::Queue> {
+        Arc::from_raw(self.0 as *const job::Queue)
+    }
+
+    pub fn from_queue(queue: Arc<job::Queue>) -> Self {
+        Self(Arc::into_raw(queue) as _)
+    }
+}
+
 /// Contain pointer to null-terminated UTF-8 path.
 #[repr(transparent)]
+#[derive(Copy, Clone)]
 struct RawPath(*const c_char);
 
+/// Contain pointer to null-terminated UTF-8 string.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+struct RawStr(*const c_char);
+
 /// Error codes for image oprerations.
 #[repr(u32)]
-enum ImageError {
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageError {
     NoError = 0,
     Io,
     Decoding,
     Encoding,
     Parameter,
     Unsupported,
+    BufferTooSmall,
+    LimitExceeded,
+    NotFound,
+    /// Library failed unexpectedly. Image passed to failed function may be left partially
+    /// modified.
+    Internal,
+    /// Memory for result of operation can't be allocated. Arguments are left unchanged.
+    OutOfMemory,
+    /// Image or document handle is null, destroyed or wasn't created by this library.
+    InvalidHandle,
+    /// Operation ran longer or allocated more than its `OperationBudget` allows and was aborted.
+    BudgetExceeded,
+    /// Operation was aborted by host, e.g. by its progress callback.
+    Cancelled,
 }
 
 impl From<image::ImageError> for ImageError {
     fn from(e: image::ImageError) -> Self {
-        match e {
-            image::ImageError::Decoding(_) => Self::Decoding,
-            image::ImageError::Encoding(_) => Self::Encoding,
-            image::ImageError::Unsupported(_) => Self::Unsupported,
-            image::ImageError::Parameter(_) => Self::Parameter,
-            image::ImageError::IoError(_) => Self::Io,
-            _ => Self::Unsupported,
-        }
-    }
-}
+        use image::error::UnsupportedErrorKind;
+        use image::error::{ImageFormatHint, LimitErrorKind, ParameterErrorKind};
 
-/// Loads image from file function type.
-type OpenImageFn = unsafe extern "C" fn(RawPath, *mut ImageHandle) -> ImageError;
-/// Saves image to file function type.
-type SaveImageFn = unsafe extern "C" fn(RawPath, ImageHandle) -> ImageError;
-/// Destroys image function type.
-type DestroyImageFn = unsafe extern "C" fn(ImageHandle);
+        let e = match e {
+            image::ImageError::IoError(io) => return io_error(io),
+            e => e,
+        };
+        let (code, sub_code, hint) = match &e {
+            image::ImageError::Decoding(d) => (Self::Decoding, ErrorSubCode::None, d.format_hint()),
+            image::ImageError::Encoding(d) => (Self::Encoding, ErrorSubCode::None, d.format_hint()),
+            image::ImageError::Unsupported(u) => {
+                let sub_code = match u.kind() {
+                    UnsupportedErrorKind::Color(_) => ErrorSubCode::UnsupportedColor,
+                    UnsupportedErrorKind::Format(_) => ErrorSubCode::UnsupportedFormat,
+                    _ => ErrorSubCode::UnsupportedFeature,
+                };
+                (Self::Unsupported, sub_code, u.format_hint())
+            }
+            image::ImageError::Parameter(p) => {
+                let sub_code = match p.kind() {
+                    ParameterErrorKind::DimensionMismatch => ErrorSubCode::DimensionMismatch,
+                    _ => ErrorSubCode::None,
+                };
+                (Self::Parameter, sub_code, ImageFormatHint::Unknown)
+            }
+            image::ImageError::Limits(l) => {
+                let sub_code = match l.kind() {
+                    LimitErrorKind::DimensionError => ErrorSubCode::DimensionTooLarge,
+                    LimitErrorKind::InsufficientMemory => ErrorSubCode::InsufficientMemory,
+                    _ => ErrorSubCode::None,
+                };
+                let code = match l.kind() {
+                    LimitErrorKind::InsufficientMemory => Self::OutOfMemory,
+                    _ => Self::LimitExceeded,
+                };
+                (code, sub_code, ImageFormatHint::Unknown)
+            }
+            image::ImageError::IoError(_) => unreachable!(),
+        };
 
-/// Performs a Gaussian blur on the supplied image function type.
-type BlurImageFn = unsafe extern "C" fn(ImageHandle, f32) -> ImageHandle;
-/// Flips image horizontally function type.
-type MirrorImageFn = unsafe extern "C" fn(ImageHandle);
+        let format = match hint {
+            ImageFormatHint::Exact(format) => ImageFormat::try_from(format).ok(),
+            _ => None,
+        };
+        set_last_error(LastError {
+            sub_code,
+            format,
+            ..LastError::new(code, e.to_string())
+        });
+        code
+    }
+}
 
-/// Contains functions provided by library. Allow to import just `functions()` function and get all
-/// functionality of library through this struct.
-/// `size` field contain size of this struct. It helps to avoid versioning and some other errors.
-#[allow(unused)]
+/// Details of the last error reported by `img_last_error_details`. `size` field contain size of
+/// this struct. Fields may be appended in later versions, so callers set `size` to size of
+/// struct they know.
 #[repr(C)]
-pub struct FunctionsBlock {
+struct ErrorDetails {
     size: usize,
-    open_image: OpenImageFn,
-    save_image: SaveImageFn,
-    destroy_image: DestroyImageFn,
-    blur_image: BlurImageFn,
-    mirror_image: MirrorImageFn,
+    /// `ImageError` code of error.
+    code: u32,
+    /// `ErrorSubCode` refining `code`.
+    sub_code: u32,
+    /// `ImageFormat` value of format that caused error, or `u32::MAX` if unknown.
+    format: u32,
+    /// OS error number of I/O errors, or zero.
+    os_error: i32,
+    /// Null-terminated UTF-8 message. Valid until the next error on the same thread.
+    message: *const c_char,
 }
 
-impl Default for FunctionsBlock {
-    fn default() -> Self {
-        Self {
-            size: std::mem::size_of::<Self>(),
-            open_image: img_open,
-            save_image: img_save,
-            destroy_image: img_destroy,
-            blur_image: img_blur,
-            mirror_image: img_mirror,
+/// Refinements of error codes reported by `img_last_error_details`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum ErrorSubCode {
+    None = 0,
+    FileNotFound,
+    PermissionDenied,
+    UnexpectedEof,
+    UnsupportedFormat,
+    UnsupportedColor,
+    UnsupportedFeature,
+    DimensionMismatch,
+    DimensionTooLarge,
+    InsufficientMemory,
+    /// Image handle is used on thread which doesn't own it.
+    WrongThread,
+}
+
+impl From<std::io::ErrorKind> for ErrorSubCode {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        match kind {
+            std::io::ErrorKind::NotFound => Self::FileNotFound,
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            std::io::ErrorKind::UnexpectedEof => Self::UnexpectedEof,
+            std::io::ErrorKind::OutOfMemory => Self::InsufficientMemory,
+            _ => Self::None,
         }
     }
 }
 
-/// Returns all functions of this library.
-#[no_mangle]
-pub extern "C" fn functions() -> FunctionsBlock {
-    FunctionsBlock::default()
+/// Details of error of underlying libraries.
+#[derive(Clone)]
+struct LastError {
+    code: ImageError,
+    sub_code: ErrorSubCode,
+    format: Option<ImageFormat>,
+    os_error: i32,
+    message: CString,
 }
 
-// Exported functions
-
-/// # Safety
-/// - `path` is valid pointer to null-terminated UTF-8 string.
-/// - `handle` is valid pointer to `void*`.
-unsafe extern "C" fn img_open(path: RawPath, handle: *mut ImageHandle) -> ImageError {
-    if handle.is_null() || path.0.is_null() {
-        return ImageError::Parameter;
+impl LastError {
+    /// Creates details of error with `code` and `message` only.
+    fn new(code: ImageError, message: String) -> Self {
+        Self {
+            code,
+            sub_code: ErrorSubCode::None,
+            format: None,
+            os_error: 0,
+            message: CString::new(message.replace('\0', " ")).unwrap_or_default(),
+        }
     }
+}
 
-    let path: &Path = match (&path).try_into() {
-        Ok(p) => p,
-        Err(e) => return e,
-    };
+thread_local! {
+    /// Details of the last error of underlying libraries on this thread.
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
 
-    let img = match image::open(path) {
-        Ok(i) => i,
-        Err(e) => return e.into(),
-    };
+/// Remembers `error` as the last error of this thread.
+fn set_last_error(error: LastError) {
+    log::write(LogLevel::Error, &error.message.to_string_lossy());
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+}
 
-    *handle = ImageHandle::from_image(img);
-    ImageError::NoError
+/// Remembers details of I/O `error` and returns `ImageError::Io`.
+fn io_error(error: std::io::Error) -> ImageError {
+    set_last_error(LastError {
+        sub_code: error.kind().into(),
+        os_error: error.raw_os_error().unwrap_or(0),
+        ..LastError::new(ImageError::Io, format!("I/O error: {}", error))
+    });
+    ImageError::Io
 }
 
-/// # Safety
-/// - `path` is valid pointer to null-terminated UTF-8 string.
-/// - `handle` is valid image handle.
-unsafe extern "C" fn img_save(path: RawPath, handle: ImageHandle) -> ImageError {
-    if handle.0.is_null() || path.0.is_null() {
-        return ImageError::Parameter;
+/// Value returned by exported function if it panics.
+trait PanicResult {
+    fn panicked() -> Self;
+}
+
+impl PanicResult for ImageError {
+    fn panicked() -> Self {
+        ImageError::Internal
     }
+}
 
-    let path: &Path = match (&path).try_into() {
-        Ok(p) => p,
-        Err(e) => return e,
-    };
+impl PanicResult for () {
+    fn panicked() -> Self {}
+}
 
-    let img = handle.as_image();
-    match img.save(path) {
-        Ok(_) => ImageError::NoError,
-        Err(e) => e.into(),
+impl PanicResult for bool {
+    fn panicked() -> Self {
+        false
     }
 }
 
-/// Destroys image created by this library.
-unsafe extern "C" fn img_destroy(handle: ImageHandle) {
-    handle.into_image();
+impl PanicResult for usize {
+    fn panicked() -> Self {
+        0
+    }
 }
 
-/// Blurs image with `sigma` blur radius. Returns new image.
-unsafe extern "C" fn img_blur(handle: ImageHandle, sigma: f32) -> ImageHandle {
-    let image = handle.as_image();
-    let buffer = image::imageops::blur(image, sigma);
-    let blurred = image::DynamicImage::ImageRgba8(buffer);
-    ImageHandle::from_image(blurred)
+impl PanicResult for u64 {
+    fn panicked() -> Self {
+        0
+    }
 }
 
-/// Flip image horizontally in place.
-unsafe extern "C" fn img_mirror(handle: ImageHandle) {
-    let image_ref = handle.as_image();
-    image::imageops::flip_horizontal_in_place(image_ref);
+impl PanicResult for ImageHandle {
+    fn panicked() -> Self {
+        ImageHandle(std::ptr::null_mut())
+    }
 }
 
-// Utils
+impl PanicResult for JobStatus {
+    fn panicked() -> Self {
+        JobStatus::Failed
+    }
+}
 
-impl<'a> TryFrom<&'a RawPath> for &'a Path {
-    type Error = ImageError;
+/// Runs `body` of exported function catching panics, which must not unwind into caller. Panic
+/// is reported as `ImageError::Internal` with panic message as the last error message.
+fn guard<T: PanicResult, F: FnOnce() -> T>(body: F) -> T {
+    // Data touched by panicked function isn't used to continue its work.
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+                (Some(message), _) => message.to_string(),
+                (_, Some(message)) => message.clone(),
+                _ => "unknown panic".to_string(),
+            };
+            let message = format!("Internal error: {}", message);
+            trace::panicked(&message);
+            set_last_error(LastError::new(ImageError::Internal, message));
+            T::panicked()
+        }
+    }
+}
 
-    fn try_from(value: &'a RawPath) -> Result<Self, Self::Error> {
-        if value.0.is_null() {
-            return Err(ImageError::Parameter);
+/// Checks allocation of `bytes`, e.g. for pixels of operation result, against budget of operation
+/// before operation starts. Sizes exceeding address space fail with `ImageError::LimitExceeded`.
+/// Buffers allocated by library itself are allocated by `try_alloc`.
+fn check_alloc(bytes: u64) -> Result<(), ImageError> {
+    budget::charge(bytes)?;
+    match usize::try_from(bytes) {
+        Ok(bytes) if bytes <= isize::MAX as usize => Ok(()),
+        _ => {
+            let message = format!("Allocation of {} bytes exceeds address space", bytes);
+            set_last_error(LastError::new(ImageError::LimitExceeded, message));
+            Err(ImageError::LimitExceeded)
         }
+    }
+}
 
-        let s = unsafe { CStr::from_ptr(value.0) };
-        let utf8_str = match s.to_str() {
-            Ok(s) => s,
-            Err(_) => return Err(ImageError::Parameter),
+/// Allocates buffer of `len` copies of `value`, so that operation fails with
+/// `ImageError::OutOfMemory` instead of aborting host process on allocation failure.
+fn try_alloc<T: Clone>(len: usize, value: T) -> Result<Vec<T>, ImageError> {
+    let mut data = Vec::new();
+    if data.try_reserve_exact(len).is_err() {
+        return Err(out_of_memory(len.saturating_mul(std::mem::size_of::<T>())));
+    }
+    data.resize(len, value);
+    Ok(data)
+}
+
+/// Reports failed allocation of `bytes`.
+fn out_of_memory(bytes: usize) -> ImageError {
+    let message = format!("Out of memory: failed to allocate {} bytes", bytes);
+    set_last_error(LastError {
+        sub_code: ErrorSubCode::InsufficientMemory,
+        ..LastError::new(ImageError::OutOfMemory, message)
+    });
+    ImageError::OutOfMemory
+}
+
+/// Allocates `width` x `height` image of zeroed pixels by `try_alloc`.
+fn try_image<P: image::Pixel + 'static>(
+    width: u32,
+    height: u32,
+) -> Result<image::ImageBuffer<P, Vec<P::Subpixel>>, ImageError> {
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(P::CHANNEL_COUNT as usize))
+        .ok_or(ImageError::LimitExceeded)?;
+    let data = try_alloc(len, num_traits::Zero::zero())?;
+    Ok(image::ImageBuffer::from_raw(width, height, data).expect("buffer of image size"))
+}
+
+/// Returns size of pixels of `width` x `height` image of `color` type.
+fn image_bytes(width: u32, height: u32, color: image::ColorType) -> u64 {
+    let pixels = width as u64 * height as u64;
+    pixels.saturating_mul(color.bytes_per_pixel() as u64)
+}
+
+/// Image formats available for encoding.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+pub enum ImageFormat {
+    Png = 0,
+    Jpeg,
+    Gif,
+    Ico,
+    Bmp,
+    Farbfeld,
+    Tga,
+    Pnm,
+    Tiff,
+    WebP,
+    Hdr,
+    Dds,
+}
+
+impl TryFrom<u32> for ImageFormat {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let format = match value {
+            0 => Self::Png,
+            1 => Self::Jpeg,
+            2 => Self::Gif,
+            3 => Self::Ico,
+            4 => Self::Bmp,
+            5 => Self::Farbfeld,
+            6 => Self::Tga,
+            7 => Self::Pnm,
+            8 => Self::Tiff,
+            9 => Self::WebP,
+            10 => Self::Hdr,
+            11 => Self::Dds,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(format)
+    }
+}
+
+impl From<ImageFormat> for image::ImageOutputFormat {
+    fn from(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Png => Self::Png,
+            ImageFormat::Jpeg => Self::Jpeg(75),
+            ImageFormat::Gif => Self::Gif,
+            ImageFormat::Ico => Self::Ico,
+            ImageFormat::Bmp => Self::Bmp,
+            ImageFormat::Farbfeld => Self::Farbfeld,
+            ImageFormat::Tga => Self::Tga,
+            ImageFormat::Pnm => Self::Pnm(image::codecs::pnm::PNMSubtype::ArbitraryMap),
+            ImageFormat::Tiff | ImageFormat::WebP | ImageFormat::Hdr | ImageFormat::Dds => {
+                Self::Unsupported("format is available only for decoding".into())
+            }
+        }
+    }
+}
+
+impl From<ImageFormat> for image::ImageFormat {
+    fn from(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Png => Self::Png,
+            ImageFormat::Jpeg => Self::Jpeg,
+            ImageFormat::Gif => Self::Gif,
+            ImageFormat::Ico => Self::Ico,
+            ImageFormat::Bmp => Self::Bmp,
+            ImageFormat::Farbfeld => Self::Farbfeld,
+            ImageFormat::Tga => Self::Tga,
+            ImageFormat::Pnm => Self::Pnm,
+            ImageFormat::Tiff => Self::Tiff,
+            ImageFormat::WebP => Self::WebP,
+            ImageFormat::Hdr => Self::Hdr,
+            ImageFormat::Dds => Self::Dds,
+        }
+    }
+}
+
+impl ImageFormat {
+    /// Returns MIME type of format.
+    fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Ico => "image/x-icon",
+            Self::Bmp => "image/bmp",
+            Self::Farbfeld => "image/x-farbfeld",
+            Self::Tga => "image/x-tga",
+            Self::Pnm => "image/x-portable-anymap",
+            Self::Tiff => "image/tiff",
+            Self::WebP => "image/webp",
+            Self::Hdr => "image/vnd.radiance",
+            Self::Dds => "image/vnd-ms.dds",
+        }
+    }
+}
+
+impl TryFrom<image::ImageFormat> for ImageFormat {
+    type Error = ImageError;
+
+    fn try_from(value: image::ImageFormat) -> Result<Self, Self::Error> {
+        let format = match value {
+            image::ImageFormat::Png => Self::Png,
+            image::ImageFormat::Jpeg => Self::Jpeg,
+            image::ImageFormat::Gif => Self::Gif,
+            image::ImageFormat::Ico => Self::Ico,
+            image::ImageFormat::Bmp => Self::Bmp,
+            image::ImageFormat::Farbfeld => Self::Farbfeld,
+            image::ImageFormat::Tga => Self::Tga,
+            image::ImageFormat::Pnm => Self::Pnm,
+            image::ImageFormat::Tiff => Self::Tiff,
+            image::ImageFormat::WebP => Self::WebP,
+            image::ImageFormat::Hdr => Self::Hdr,
+            image::ImageFormat::Dds => Self::Dds,
+            _ => return Err(ImageError::Unsupported),
+        };
+        Ok(format)
+    }
+}
+
+/// Layouts of raw pixel data supplied by host. Rows are tightly packed, 16-bit channels are in
+/// native byte order.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum RawPixelFormat {
+    Rgba8 = 0,
+    Rgb8,
+    L8,
+    Rgba16,
+}
+
+impl TryFrom<u32> for RawPixelFormat {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let format = match value {
+            0 => Self::Rgba8,
+            1 => Self::Rgb8,
+            2 => Self::L8,
+            3 => Self::Rgba16,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(format)
+    }
+}
+
+impl RawPixelFormat {
+    /// Size of one pixel in bytes.
+    fn pixel_size(self) -> usize {
+        match self {
+            Self::Rgba8 => 4,
+            Self::Rgb8 => 3,
+            Self::L8 => 1,
+            Self::Rgba16 => 8,
+        }
+    }
+
+    /// Creates image of `width` x `height` pixels from `data` of exact size.
+    fn create_image(self, width: u32, height: u32, data: &[u8]) -> Option<DynamicImage> {
+        let img = match self {
+            Self::Rgba8 => {
+                DynamicImage::ImageRgba8(image::RgbaImage::from_raw(width, height, data.to_vec())?)
+            }
+            Self::Rgb8 => {
+                DynamicImage::ImageRgb8(image::RgbImage::from_raw(width, height, data.to_vec())?)
+            }
+            Self::L8 => {
+                DynamicImage::ImageLuma8(image::GrayImage::from_raw(width, height, data.to_vec())?)
+            }
+            Self::Rgba16 => {
+                let channels = data
+                    .chunks_exact(2)
+                    .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+                    .collect();
+                DynamicImage::ImageRgba16(image::ImageBuffer::from_raw(width, height, channels)?)
+            }
+        };
+        Some(img)
+    }
+}
+
+/// Color types of images, as `img_info` reports them, and pixel formats of conversions.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorType {
+    L8 = 0,
+    La8,
+    Rgb8,
+    Rgba8,
+    L16,
+    La16,
+    Rgb16,
+    Rgba16,
+    Bgr8,
+    Bgra8,
+    /// RGBA with `float` channels in `[0, 1]`. Images can't have it.
+    Rgba32F,
+}
+
+impl TryFrom<u32> for ColorType {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let color_type = match value {
+            0 => Self::L8,
+            1 => Self::La8,
+            2 => Self::Rgb8,
+            3 => Self::Rgba8,
+            4 => Self::L16,
+            5 => Self::La16,
+            6 => Self::Rgb16,
+            7 => Self::Rgba16,
+            8 => Self::Bgr8,
+            9 => Self::Bgra8,
+            10 => Self::Rgba32F,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(color_type)
+    }
+}
+
+impl ColorType {
+    /// Returns color type of images, `None` for `Rgba32F`.
+    fn to_image(self) -> Option<image::ColorType> {
+        let color = match self {
+            Self::L8 => image::ColorType::L8,
+            Self::La8 => image::ColorType::La8,
+            Self::Rgb8 => image::ColorType::Rgb8,
+            Self::Rgba8 => image::ColorType::Rgba8,
+            Self::L16 => image::ColorType::L16,
+            Self::La16 => image::ColorType::La16,
+            Self::Rgb16 => image::ColorType::Rgb16,
+            Self::Rgba16 => image::ColorType::Rgba16,
+            Self::Bgr8 => image::ColorType::Bgr8,
+            Self::Bgra8 => image::ColorType::Bgra8,
+            Self::Rgba32F => return None,
+        };
+        Some(color)
+    }
+
+    fn bytes_per_pixel(self) -> usize {
+        self.to_image().map_or(4 * std::mem::size_of::<f32>(), |c| {
+            c.bytes_per_pixel() as usize
+        })
+    }
+
+    /// Converts `image` to this color type, `None` for `Rgba32F`. 8-bit channels widened to 16
+    /// bits are scaled to full range, so white stays white.
+    fn convert(self, image: &DynamicImage) -> Option<DynamicImage> {
+        let color = image.color();
+        let widened = color.bytes_per_pixel() == color.channel_count();
+        let (width, height) = image.dimensions();
+        let widen =
+            |narrow: Vec<u8>| -> Vec<u16> { narrow.iter().map(|&c| c as u16 * 257).collect() };
+        let converted = match self {
+            Self::L8 => DynamicImage::ImageLuma8(image.to_luma8()),
+            Self::La8 => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+            Self::Rgb8 => DynamicImage::ImageRgb8(image.to_rgb8()),
+            Self::Rgba8 => DynamicImage::ImageRgba8(image.to_rgba8()),
+            Self::Bgr8 => DynamicImage::ImageBgr8(image.to_bgr8()),
+            Self::Bgra8 => DynamicImage::ImageBgra8(image.to_bgra8()),
+            Self::L16 if widened => {
+                let channels = widen(image.to_luma8().into_raw());
+                DynamicImage::ImageLuma16(image::ImageBuffer::from_raw(width, height, channels)?)
+            }
+            Self::La16 if widened => {
+                let channels = widen(image.to_luma_alpha8().into_raw());
+                DynamicImage::ImageLumaA16(image::ImageBuffer::from_raw(width, height, channels)?)
+            }
+            Self::Rgb16 if widened => {
+                let channels = widen(image.to_rgb8().into_raw());
+                DynamicImage::ImageRgb16(image::ImageBuffer::from_raw(width, height, channels)?)
+            }
+            Self::Rgba16 if widened => {
+                let channels = widen(image.to_rgba8().into_raw());
+                DynamicImage::ImageRgba16(image::ImageBuffer::from_raw(width, height, channels)?)
+            }
+            Self::L16 => DynamicImage::ImageLuma16(image.to_luma16()),
+            Self::La16 => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+            Self::Rgb16 => DynamicImage::ImageRgb16(image.to_rgb16()),
+            Self::Rgba16 => DynamicImage::ImageRgba16(image.to_rgba16()),
+            Self::Rgba32F => return None,
+        };
+        Some(converted)
+    }
+
+    /// Creates image of this color type with zeroed pixels, `None` for `Rgba32F`.
+    fn blank(self, width: u32, height: u32) -> Option<Result<DynamicImage, ImageError>> {
+        let image = match self {
+            Self::L8 => try_image(width, height).map(DynamicImage::ImageLuma8),
+            Self::La8 => try_image(width, height).map(DynamicImage::ImageLumaA8),
+            Self::Rgb8 => try_image(width, height).map(DynamicImage::ImageRgb8),
+            Self::Rgba8 => try_image(width, height).map(DynamicImage::ImageRgba8),
+            Self::L16 => try_image(width, height).map(DynamicImage::ImageLuma16),
+            Self::La16 => try_image(width, height).map(DynamicImage::ImageLumaA16),
+            Self::Rgb16 => try_image(width, height).map(DynamicImage::ImageRgb16),
+            Self::Rgba16 => try_image(width, height).map(DynamicImage::ImageRgba16),
+            Self::Bgr8 => try_image(width, height).map(DynamicImage::ImageBgr8),
+            Self::Bgra8 => try_image(width, height).map(DynamicImage::ImageBgra8),
+            Self::Rgba32F => return None,
+        };
+        Some(image)
+    }
+
+    fn of(image: &DynamicImage) -> Self {
+        match image {
+            DynamicImage::ImageLuma8(_) => Self::L8,
+            DynamicImage::ImageLumaA8(_) => Self::La8,
+            DynamicImage::ImageRgb8(_) => Self::Rgb8,
+            DynamicImage::ImageRgba8(_) => Self::Rgba8,
+            DynamicImage::ImageLuma16(_) => Self::L16,
+            DynamicImage::ImageLumaA16(_) => Self::La16,
+            DynamicImage::ImageRgb16(_) => Self::Rgb16,
+            DynamicImage::ImageRgba16(_) => Self::Rgba16,
+            DynamicImage::ImageBgr8(_) => Self::Bgr8,
+            DynamicImage::ImageBgra8(_) => Self::Bgra8,
+        }
+    }
+}
+
+/// Description of image written by `img_info`. `size` field contain size of this struct known
+/// by caller, fields may be appended in later versions.
+#[repr(C)]
+struct ImageInfo {
+    size: usize,
+    width: u32,
+    height: u32,
+    /// `ColorType` value.
+    color_type: u32,
+    /// Bits per channel, 8 or 16.
+    bit_depth: u32,
+    /// Number of channels including alpha.
+    channels: u32,
+    has_alpha: bool,
+}
+
+/// Statistics of image written by `img_stats`, in `[0, 1]` units of channel range. `size` field
+/// contain size of this struct known by caller, fields may be appended in later versions.
+#[repr(C)]
+struct Stats {
+    size: usize,
+    /// Number of channels including alpha, entries of per-channel arrays past it are zero.
+    channels: u32,
+    /// Minimum, maximum, mean and standard deviation of every channel, in order of color type of
+    /// image.
+    min: [f64; 4],
+    max: [f64; 4],
+    mean: [f64; 4],
+    std_dev: [f64; 4],
+    /// Mean Rec. 709 luma of pixels, ignoring alpha.
+    luminance: f64,
+}
+
+/// Pixels of image written by `img_lock_pixels`.
+#[repr(C)]
+struct PixelLock {
+    /// The first byte of the top row. Rows go from top to bottom without gaps between pixels,
+    /// 16-bit channels are in native byte order.
+    pixels: *mut u8,
+    /// Distance between starts of adjacent rows in bytes.
+    stride: usize,
+    width: u32,
+    height: u32,
+    /// `ColorType` value.
+    format: u32,
+}
+
+/// Types of EXIF tag values.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum ExifType {
+    Ascii = 0,
+    Byte,
+    Short,
+    Long,
+    Rational,
+    SRational,
+    Undefined,
+}
+
+impl TryFrom<u32> for ExifType {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let ty = match value {
+            0 => Self::Ascii,
+            1 => Self::Byte,
+            2 => Self::Short,
+            3 => Self::Long,
+            4 => Self::Rational,
+            5 => Self::SRational,
+            6 => Self::Undefined,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(ty)
+    }
+}
+
+/// Packed framebuffer formats of e-paper displays.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum EinkFormat {
+    /// 1 bit per pixel, set bit is white.
+    Mono = 0,
+    /// 4 bits per pixel gray, `0` is black.
+    Gray4,
+    /// 4 bits per pixel indices of 7-color ACeP panel palette: black, white, green, blue, red,
+    /// yellow, orange.
+    Acep7,
+}
+
+impl TryFrom<u32> for EinkFormat {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let format = match value {
+            0 => Self::Mono,
+            1 => Self::Gray4,
+            2 => Self::Acep7,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(format)
+    }
+}
+
+/// Pixel layouts of packed display framebuffers.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum PixelLayout {
+    /// 16-bit little-endian `RRRRRGGGGGGBBBBB` pixels.
+    Rgb565 = 0,
+    /// 8-bit `RRRGGGBB` pixels.
+    Rgb332,
+    /// 24-bit pixels with blue byte first.
+    Bgr888,
+}
+
+impl TryFrom<u32> for PixelLayout {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let layout = match value {
+            0 => Self::Rgb565,
+            1 => Self::Rgb332,
+            2 => Self::Bgr888,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(layout)
+    }
+}
+
+/// Order of rows in packed framebuffer.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum RowOrder {
+    TopDown = 0,
+    BottomUp,
+}
+
+impl TryFrom<u32> for RowOrder {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::TopDown),
+            1 => Ok(Self::BottomUp),
+            _ => Err(ImageError::Parameter),
+        }
+    }
+}
+
+/// Kinds of transforms estimated by alignment.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum Motion {
+    /// Shift estimated by phase correlation.
+    Translation = 0,
+    /// Affine transform estimated by enhanced correlation coefficient maximization.
+    Affine,
+}
+
+impl TryFrom<u32> for Motion {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Translation),
+            1 => Ok(Self::Affine),
+            _ => Err(ImageError::Parameter),
+        }
+    }
+}
+
+/// Platforms of icon sets written by `img_generate_icon_set`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum IconSetKind {
+    /// `favicon.ico` with PNG favicons and Apple touch icon.
+    Favicon = 0,
+    /// App icon set of iOS asset catalog with its `Contents.json`.
+    Ios,
+    /// Launcher icons of Android density buckets and Play Store icon.
+    Android,
+    /// Icons of web app manifest with `manifest.json` listing them.
+    Pwa,
+}
+
+impl TryFrom<u32> for IconSetKind {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Favicon),
+            1 => Ok(Self::Ios),
+            2 => Ok(Self::Android),
+            3 => Ok(Self::Pwa),
+            _ => Err(ImageError::Parameter),
+        }
+    }
+}
+
+/// Arrangement of social media card composed by `img_compose_card`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum CardLayout {
+    /// Background covers card with gradient scrim at the bottom, title at bottom left next to
+    /// avatar.
+    Bottom = 0,
+    /// Background covers card under uniform scrim, avatar and title centered.
+    Center,
+    /// Left half is solid panel of opaque scrim color with avatar and title, background covers
+    /// right half.
+    Split,
+}
+
+impl TryFrom<u32> for CardLayout {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Bottom),
+            1 => Ok(Self::Center),
+            2 => Ok(Self::Split),
+            _ => Err(ImageError::Parameter),
+        }
+    }
+}
+
+/// Description of social media card composed by `img_compose_card`. `size` field contain size
+/// of this struct.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct CardSpec {
+    size: usize,
+    /// Size of card in pixels. Zero width and height mean 1200 x 630, recommended for Open Graph
+    /// and Twitter cards.
+    width: u32,
+    height: u32,
+    /// One of `CardLayout` values.
+    layout: u32,
+    /// Title drawn with built-in font of printable ASCII characters. May be null.
+    title: RawStr,
+    /// Image drawn cropped to circle, e.g. author photo. May be null.
+    avatar: ImageHandle,
+    /// Image drawn at top right corner keeping its aspect ratio. May be null.
+    logo: ImageHandle,
+    /// RGBA color of title.
+    text_color: [u8; 4],
+    /// RGBA color of overlay keeping title readable over background.
+    scrim_color: [u8; 4],
+}
+
+impl Default for CardSpec {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            width: 0,
+            height: 0,
+            layout: CardLayout::Bottom as u32,
+            title: RawStr(std::ptr::null()),
+            avatar: ImageHandle(std::ptr::null_mut()),
+            logo: ImageHandle(std::ptr::null_mut()),
+            text_color: [255; 4],
+            scrim_color: [0, 0, 0, 180],
+        }
+    }
+}
+
+unsafe impl Versioned for CardSpec {}
+
+/// Affine transform mapping point `(x, y)` of reference image to point
+/// `(m[0] * x + m[1] * y + m[2], m[3] * x + m[4] * y + m[5])` of moving image.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Transform {
+    matrix: [f32; 6],
+    /// Quality of alignment: height of phase correlation peak or correlation coefficient, in
+    /// `[0, 1]` for aligned images.
+    score: f32,
+}
+
+/// Keypoint detectors.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum Detector {
+    /// FAST-9 corners without orientation and descriptors.
+    Fast = 0,
+    /// FAST-9 corners with orientation and rotated BRIEF descriptors.
+    Orb,
+}
+
+impl TryFrom<u32> for Detector {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Fast),
+            1 => Ok(Self::Orb),
+            _ => Err(ImageError::Parameter),
+        }
+    }
+}
+
+/// Corner detected in image.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Keypoint {
+    x: f32,
+    y: f32,
+    /// Strength of corner, larger for more distinct ones.
+    response: f32,
+    /// Orientation in radians of ORB keypoint, zero otherwise.
+    angle: f32,
+    /// 256-bit binary descriptor of ORB keypoint, zeros otherwise.
+    descriptor: [u8; 32],
+}
+
+/// Keypoint of the first image matched to keypoint of the second one.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct KeypointMatch {
+    ax: f32,
+    ay: f32,
+    bx: f32,
+    by: f32,
+    /// Hamming distance between descriptors.
+    distance: u32,
+    /// Whether match agrees with estimated offset.
+    inlier: bool,
+}
+
+/// Keypoint matches of two images with offset which moves points of the first image to the same
+/// points of the second one. `matches` is owned by library and freed by `img_free_matches`.
+#[repr(C)]
+struct Matches {
+    matches: *mut KeypointMatch,
+    count: usize,
+    offset_x: f32,
+    offset_y: f32,
+    /// Number of matches offset is estimated from, zero if images don't overlap.
+    inliers: usize,
+}
+
+/// Ways to search template in image.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum MatchMethod {
+    /// Normalized cross-correlation of template as is.
+    Ncc = 0,
+    /// Template scaled in `[0.5, 2]`.
+    MultiScale,
+    /// Template rotated in 15 degree steps.
+    Rotated,
+    /// Template both scaled and rotated.
+    MultiScaleRotated,
+}
+
+impl TryFrom<u32> for MatchMethod {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Ncc),
+            1 => Ok(Self::MultiScale),
+            2 => Ok(Self::Rotated),
+            3 => Ok(Self::MultiScaleRotated),
+            _ => Err(ImageError::Parameter),
+        }
+    }
+}
+
+/// Placement of template found in image. Rectangle bounds template scaled by `scale` and rotated
+/// clockwise by `angle` degrees around its center.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct MatchResult {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    scale: f32,
+    angle: f32,
+    /// Normalized cross-correlation in `(0, 1]`.
+    score: f32,
+}
+
+/// Kinds of image operations.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum OperationKind {
+    Blur = 0,
+    Mirror,
+    Resize,
+}
+
+impl TryFrom<u32> for OperationKind {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let kind = match value {
+            0 => Self::Blur,
+            1 => Self::Mirror,
+            2 => Self::Resize,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(kind)
+    }
+}
+
+/// Point in image pixel coordinates, where `(0, 0)` is top-left corner of image and
+/// `(width, height)` is bottom-right one.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Point {
+    x: f32,
+    y: f32,
+}
+
+/// Rectangle in image pixel coordinates.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    /// Returns `true` if rectangle isn't empty and lies within `image`.
+    fn is_within(&self, image: &DynamicImage) -> bool {
+        let fits = |start: u32, size: u32, max: u32| {
+            size != 0 && start.checked_add(size).is_some_and(|end| end <= max)
+        };
+        fits(self.x, self.width, image.width()) && fits(self.y, self.height, image.height())
+    }
+
+    /// Draws outline of rectangle over `image` with lines of `line_width` pixels inside it.
+    fn outline(&self, image: &mut DynamicImage, line_width: u32, color: image::Rgba<u8>) {
+        let (x, y) = (self.x as i64, self.y as i64);
+        let (x1, y1) = (x + self.width as i64, y + self.height as i64);
+        draw::rect_outline(image, x, y, x1, y1, line_width, color);
+    }
+}
+
+/// Description of image operation. Fields unused by operation `kind` are ignored.
+#[repr(C)]
+struct Operation {
+    /// One of `OperationKind` values.
+    kind: u32,
+    /// Blur radius for `Blur`.
+    sigma: f32,
+    /// Target width for `Resize`.
+    width: u32,
+    /// Target height for `Resize`.
+    height: u32,
+}
+
+impl Operation {
+    /// Applies operation to `image`.
+    fn apply(&self, image: &mut DynamicImage) -> Result<(), ImageError> {
+        match OperationKind::try_from(self.kind)? {
+            OperationKind::Blur => {
+                check_alloc(image_bytes(
+                    image.width(),
+                    image.height(),
+                    image::ColorType::Rgba8,
+                ))?;
+                let buffer = parallel::blur_dynamic(image, self.sigma)?;
+                *image = DynamicImage::ImageRgba8(buffer);
+            }
+            OperationKind::Mirror => image::imageops::flip_horizontal_in_place(image),
+            OperationKind::Resize => {
+                if self.width == 0 || self.height == 0 {
+                    return Err(ImageError::Parameter);
+                }
+                // Resizing allocates intermediate image of target width and source height.
+                let height = self.height.max(image.height());
+                check_alloc(image_bytes(self.width, height, image.color()))?;
+                *image = parallel::resize_dynamic(image, self.width, self.height);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Host-provided read callback. Reads up to `len` bytes into `buffer`.
+/// Returns number of bytes read, `0` at the end of stream or negative value on error.
+type ReadFn = unsafe extern "C" fn(user_data: *mut c_void, buffer: *mut u8, len: usize) -> isize;
+/// Host-provided seek callback. Moves to `offset` relative to `whence`
+/// (`0` - start, `1` - current position, `2` - end). Returns new position or negative value on error.
+type SeekFn = unsafe extern "C" fn(user_data: *mut c_void, offset: i64, whence: i32) -> i64;
+
+/// Host-provided write callback. Writes `len` bytes from `data`.
+/// Returns number of bytes written or negative value on error.
+type WriteFn = unsafe extern "C" fn(user_data: *mut c_void, data: *const u8, len: usize) -> isize;
+
+/// Host-provided progress callback. Receives percent of work done in `[0, 100]`, growing by
+/// whole percents at least, and returns `false` to abort operation.
+type ProgressFn = unsafe extern "C" fn(percent: f32, user_data: *mut c_void) -> bool;
+
+/// Host-provided log callback. Receives `LogLevel` value and UTF-8 message of `len` bytes,
+/// followed by terminating null. Message is valid only during the call.
+type LogFn =
+    unsafe extern "C" fn(level: u32, message: *const c_char, len: usize, user_data: *mut c_void);
+
+/// Host-provided probe callback of codec plugin. Receives up to 256 leading bytes of stream.
+/// Returns `true` if stream is in format of plugin.
+type ProbeFn = unsafe extern "C" fn(data: *const u8, len: usize, user_data: *mut c_void) -> bool;
+
+/// Host-provided decode callback of codec plugin. Decodes encoded image `data` of `len` bytes and
+/// writes handle of new image, e.g. created by `img_from_raw`, to `handle`. Library takes
+/// ownership of image.
+type DecodeFn = unsafe extern "C" fn(
+    data: *const u8,
+    len: usize,
+    user_data: *mut c_void,
+    handle: *mut ImageHandle,
+) -> ImageError;
+
+/// Host-provided encode callback of codec plugin. Encodes `width` x `height` RGBA pixels with 8
+/// bits per channel, rows top to bottom, and passes encoded bytes to `write_fn` with `writer`.
+type EncodeFn = unsafe extern "C" fn(
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    write_fn: WriteFn,
+    writer: *mut c_void,
+    user_data: *mut c_void,
+) -> ImageError;
+
+/// Host-provided callback receiving live image `handle` and UTF-8 backtrace of its creation of
+/// `len` bytes, followed by terminating null. Backtrace is empty without `backtrace` feature and
+/// valid only during the call.
+type LeakFn = unsafe extern "C" fn(
+    handle: ImageHandle,
+    backtrace: *const c_char,
+    len: usize,
+    user_data: *mut c_void,
+);
+
+/// Host-provided pixel callback of `img_map_pixels`. Receives coordinates of pixel and its color
+/// as `0xRRGGBBAA` at `rgba`, which it may change.
+type PixelFn = unsafe extern "C" fn(x: u32, y: u32, rgba: *mut u32, user_data: *mut c_void);
+
+/// Host-provided row callback of `img_for_each_row`. Receives index of row and its `len` bytes,
+/// valid only during the call, and returns `false` to stop.
+type RowFn =
+    unsafe extern "C" fn(index: u32, row: *const u8, len: usize, user_data: *mut c_void) -> bool;
+
+/// Struct passed to library by pointer whose first field is `size: usize`, set by caller to
+/// size of struct it knows. Fields are only appended to such structs, so hosts built against
+/// older versions keep working.
+/// # Safety
+/// Implementing struct is `#[repr(C)]` with `size: usize` first field, and any bytes of its
+/// fields written by host are valid values of them.
+unsafe trait Versioned: Copy + Default {
+    /// Checks values of fields read by `from_ptr`.
+    fn validate(&self) -> Result<(), ImageError> {
+        Ok(())
+    }
+
+    /// Reads struct passed by caller:
+    /// - null pointer or zero `size`, e.g. of zero-initialized struct, gives defaults;
+    /// - `size` of older version of struct gives fields of that version, the rest are defaults;
+    /// - larger `size`, e.g. of newer version, fails with `ImageError::Parameter`.
+    /// # Safety
+    /// `ptr` is null or valid pointer to `size` bytes of struct.
+    unsafe fn from_ptr(ptr: *const Self) -> Result<Self, ImageError> {
+        let mut value = Self::default();
+        let size = match ptr.is_null() {
+            true => 0,
+            false => *(ptr as *const usize),
+        };
+        let full = std::mem::size_of::<Self>();
+        if size != 0 && (size < std::mem::size_of::<usize>() || size > full) {
+            let name = std::any::type_name::<Self>()
+                .rsplit("::")
+                .next()
+                .unwrap_or_default();
+            let min = std::mem::size_of::<usize>();
+            let message = format!(
+                "Size {} of {} is not in {}..={} range",
+                size, name, min, full
+            );
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return Err(ImageError::Parameter);
+        }
+        std::ptr::copy_nonoverlapping(ptr as *const u8, &mut value as *mut Self as *mut u8, size);
+        // Struct is of this version from now on.
+        *(&mut value as *mut Self as *mut usize) = full;
+        value.validate()?;
+        Ok(value)
+    }
+}
+
+/// Encoding options. `size` field contain size of this struct.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SaveOptions {
+    size: usize,
+    /// JPEG quality in `1..=100` range.
+    jpeg_quality: u8,
+    /// Write metadata of source image (EXIF, PNG text) if output format supports it (JPEG, PNG).
+    keep_metadata: bool,
+    /// Embed JPEG thumbnail of image into EXIF of JPEG output, so galleries can show preview
+    /// without decoding whole image. Ignored for other formats.
+    embed_thumbnail: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            jpeg_quality: 75,
+            keep_metadata: false,
+            embed_thumbnail: false,
+        }
+    }
+}
+
+unsafe impl Versioned for SaveOptions {}
+
+impl SaveOptions {
+    /// Returns `true` if EXIF thumbnail is embedded into image encoded in `format`.
+    fn embeds_thumbnail(&self, format: ImageFormat) -> bool {
+        self.embed_thumbnail && matches!(format, ImageFormat::Jpeg)
+    }
+
+    /// Returns output format for `format` encoded with these options.
+    fn output_format(&self, format: ImageFormat) -> image::ImageOutputFormat {
+        match format {
+            ImageFormat::Jpeg => image::ImageOutputFormat::Jpeg(self.jpeg_quality),
+            format => format.into(),
+        }
+    }
+}
+
+/// Color bands of HSL adjustment.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum ColorBand {
+    Red = 0,
+    Orange,
+    Yellow,
+    Green,
+    Aqua,
+    Blue,
+    Purple,
+    Magenta,
+}
+
+impl TryFrom<u32> for ColorBand {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let band = match value {
+            0 => Self::Red,
+            1 => Self::Orange,
+            2 => Self::Yellow,
+            3 => Self::Green,
+            4 => Self::Aqua,
+            5 => Self::Blue,
+            6 => Self::Purple,
+            7 => Self::Magenta,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(band)
+    }
+}
+
+/// Hue, saturation and luminance adjustment of one color band.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct HslBand {
+    /// One of `ColorBand` values.
+    band: u32,
+    /// Hue shift in degrees in `[-180, 180]`.
+    hue: f32,
+    /// Relative saturation change in `[-1, 1]`.
+    saturation: f32,
+    /// Luminance change in `[-1, 1]`. Positive values move luminance towards white, negative
+    /// towards black.
+    luminance: f32,
+}
+
+/// Color stop of gradient of `img_gradient`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct GradientStop {
+    /// Position of stop along gradient in `[0, 1]`.
+    position: f32,
+    /// Color at stop as `0xRRGGBBAA`.
+    color: u32,
+}
+
+/// Kinds of gradients of `img_gradient`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum GradientKind {
+    /// Gradient along straight line across image at angle.
+    Linear = 0,
+    /// Gradient from center of image to its corners.
+    Radial,
+}
+
+impl TryFrom<u32> for GradientKind {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let kind = match value {
+            0 => Self::Linear,
+            1 => Self::Radial,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(kind)
+    }
+}
+
+/// Kinds of coherent noise generated by `img_noise`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum NoiseKind {
+    /// Random values at lattice points interpolated between them, blocky at low octaves.
+    Value = 0,
+    /// Random gradients at lattice points.
+    Perlin,
+    /// Random gradients at corners of triangular lattice, with fewer axis-aligned artifacts.
+    Simplex,
+}
+
+impl TryFrom<u32> for NoiseKind {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let kind = match value {
+            0 => Self::Value,
+            1 => Self::Perlin,
+            2 => Self::Simplex,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(kind)
+    }
+}
+
+/// Positions of watermark placed by `img_watermark`, in rows from top left to bottom right.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum Anchor {
+    TopLeft = 0,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Returns top left corner of `inner` size placed at anchor in `outer` size `margin` pixels
+    /// away from its edges. Centered axes ignore margin.
+    fn place(self, outer: (u32, u32), inner: (u32, u32), margin: u32) -> (i64, i64) {
+        let index = self as u32;
+        let offset = |outer: u32, inner: u32, column: u32| {
+            let (outer, inner, margin) = (outer as i64, inner as i64, margin as i64);
+            match column {
+                0 => margin,
+                1 => (outer - inner) / 2,
+                _ => outer - inner - margin,
+            }
+        };
+        let x = offset(outer.0, inner.0, index % 3);
+        let y = offset(outer.1, inner.1, index / 3);
+        (x, y)
+    }
+}
+
+impl TryFrom<u32> for Anchor {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let anchor = match value {
+            0 => Self::TopLeft,
+            1 => Self::Top,
+            2 => Self::TopRight,
+            3 => Self::Left,
+            4 => Self::Center,
+            5 => Self::Right,
+            6 => Self::BottomLeft,
+            7 => Self::Bottom,
+            8 => Self::BottomRight,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(anchor)
+    }
+}
+
+/// Film stocks emulated by `img_film_emulation`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum FilmStock {
+    Portra400 = 0,
+    Ektar100,
+    Velvia50,
+    Gold200,
+    Superia400,
+    CineStill800T,
+    TriX400,
+    Hp5Plus,
+}
+
+impl TryFrom<u32> for FilmStock {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let stock = match value {
+            0 => Self::Portra400,
+            1 => Self::Ektar100,
+            2 => Self::Velvia50,
+            3 => Self::Gold200,
+            4 => Self::Superia400,
+            5 => Self::CineStill800T,
+            6 => Self::TriX400,
+            7 => Self::Hp5Plus,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(stock)
+    }
+}
+
+/// Progress of job started by `img_submit`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum JobStatus {
+    Running = 0,
+    /// Job succeeded, its result is taken by `img_job_result`.
+    Finished,
+    /// Job failed, its error is reported by `img_job_result`.
+    Failed,
+}
+
+/// Severity levels of messages passed to log callback.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum LogLevel {
+    /// Error returned by function, with its detailed message.
+    Error = 0,
+    /// Recoverable problem, e.g. malformed metadata ignored on decoding or metadata dropped on
+    /// encoding into format without its support.
+    Warning,
+    Info,
+    Debug,
+}
+
+/// Algorithms of perceptual hashes of `img_phash`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum HashAlgorithm {
+    /// Average hash, bits of 8x8 thumbnail brighter than its mean. The fastest, but changes with
+    /// brightness and contrast.
+    Average = 0,
+    /// Difference hash, gradients between neighbours of 9x8 thumbnail.
+    Difference,
+    /// Perceptual hash, signs of low DCT frequencies of 32x32 thumbnail relative to their median.
+    /// The most robust to scaling, compression and color changes.
+    Perceptual,
+}
+
+impl TryFrom<u32> for HashAlgorithm {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let algorithm = match value {
+            0 => Self::Average,
+            1 => Self::Difference,
+            2 => Self::Perceptual,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(algorithm)
+    }
+}
+
+/// Metrics of similarity of images of `img_compare`.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum CompareMetric {
+    /// Peak signal-to-noise ratio of RGB channels in decibels, infinite for identical images.
+    /// About 30-50 dB for lossy encodings of good quality.
+    Psnr = 0,
+    /// Mean structural similarity index of luma in 11x11 Gaussian windows, 1 for identical
+    /// images. Follows perceived quality more closely than PSNR.
+    Ssim,
+}
+
+impl TryFrom<u32> for CompareMetric {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let metric = match value {
+            0 => Self::Psnr,
+            1 => Self::Ssim,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(metric)
+    }
+}
+
+/// Blend modes of document layers. Modes are separable and applied to non-premultiplied colors.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum BlendMode {
+    Normal = 0,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    Add,
+    Difference,
+}
+
+impl TryFrom<u32> for BlendMode {
+    type Error = ImageError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let mode = match value {
+            0 => Self::Normal,
+            1 => Self::Multiply,
+            2 => Self::Screen,
+            3 => Self::Overlay,
+            4 => Self::Darken,
+            5 => Self::Lighten,
+            6 => Self::Add,
+            7 => Self::Difference,
+            _ => return Err(ImageError::Parameter),
+        };
+        Ok(mode)
+    }
+}
+
+/// Description of compositional guides drawn over image. `size` field contain size of this
+/// struct.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct GuideSpec {
+    size: usize,
+    /// Set of `GuideSpec::THIRDS`, `GuideSpec::CENTER_CROSS` and `GuideSpec::SAFE_AREA` flags.
+    guides: u32,
+    /// RGBA color of guides, blended over image.
+    color: [u8; 4],
+    /// Width of guide lines in pixels.
+    line_width: u32,
+    /// Margin of safe area as fraction of image width and height.
+    safe_margin: f32,
+}
+
+impl Default for GuideSpec {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            guides: Self::THIRDS,
+            color: [255, 255, 255, 160],
+            line_width: 1,
+            safe_margin: 0.1,
+        }
+    }
+}
+
+unsafe impl Versioned for GuideSpec {
+    fn validate(&self) -> Result<(), ImageError> {
+        let known = Self::THIRDS | Self::CENTER_CROSS | Self::SAFE_AREA;
+        if self.guides & !known != 0 || self.line_width == 0 {
+            return Err(ImageError::Parameter);
+        }
+        if !(0.0..0.5).contains(&self.safe_margin) {
+            return Err(ImageError::Parameter);
+        }
+        Ok(())
+    }
+}
+
+impl GuideSpec {
+    /// Rule-of-thirds grid.
+    const THIRDS: u32 = 1;
+    /// Cross at the center of image.
+    const CENTER_CROSS: u32 = 1 << 1;
+    /// Title-safe area frame.
+    const SAFE_AREA: u32 = 1 << 2;
+
+    /// Draws guides over `image`.
+    fn draw(&self, image: &mut DynamicImage) {
+        let (w, h) = (image.width() as i64, image.height() as i64);
+        let color = image::Rgba(self.color);
+        let line = self.line_width;
+
+        if self.guides & Self::THIRDS != 0 {
+            for i in 1..3 {
+                draw::vertical_line(image, w * i / 3, 0, h, line, color);
+                draw::horizontal_line(image, h * i / 3, 0, w, line, color);
+            }
+        }
+
+        if self.guides & Self::CENTER_CROSS != 0 {
+            let arm = (w.min(h) / 20).max(line as i64);
+            draw::vertical_line(image, w / 2, h / 2 - arm, h / 2 + arm, line, color);
+            draw::horizontal_line(image, h / 2, w / 2 - arm, w / 2 + arm, line, color);
+        }
+
+        if self.guides & Self::SAFE_AREA != 0 {
+            let mx = (w as f32 * self.safe_margin).round() as i64;
+            let my = (h as f32 * self.safe_margin).round() as i64;
+            draw::rect_outline(image, mx, my, w - mx, h - my, line, color);
+        }
+    }
+}
+
+/// Placement and compositing properties of document layer. `size` field contain size of this
+/// struct.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct LayerProps {
+    size: usize,
+    /// Offset of top left corner of layer image on canvas.
+    x: i32,
+    y: i32,
+    /// Layer opacity in `[0, 1]`.
+    opacity: f32,
+    /// One of `BlendMode` values.
+    blend_mode: u32,
+    /// Hidden layers are skipped on flattening.
+    visible: bool,
+}
+
+impl Default for LayerProps {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            x: 0,
+            y: 0,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal as u32,
+            visible: true,
+        }
+    }
+}
+
+unsafe impl Versioned for LayerProps {
+    fn validate(&self) -> Result<(), ImageError> {
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err(ImageError::Parameter);
+        }
+        BlendMode::try_from(self.blend_mode)?;
+        Ok(())
+    }
+}
+
+impl LayerProps {
+    /// Applies properties to `layer`. Properties must be validated by `from_ptr`.
+    fn apply(&self, layer: &mut document::Layer) {
+        layer.x = self.x;
+        layer.y = self.y;
+        layer.opacity = self.opacity;
+        layer.blend_mode = BlendMode::try_from(self.blend_mode).unwrap_or(BlendMode::Normal);
+        layer.visible = self.visible;
+    }
+
+    /// Returns properties of `layer`.
+    fn of(layer: &document::Layer) -> Self {
+        Self {
+            x: layer.x,
+            y: layer.y,
+            opacity: layer.opacity,
+            blend_mode: layer.blend_mode as u32,
+            visible: layer.visible,
+            ..Self::default()
+        }
+    }
+}
+
+/// Decoding options. `size` field contain size of this struct.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct OpenOptions {
+    size: usize,
+    /// Maximum width of decoded image. Zero means no limit.
+    max_width: u32,
+    /// Maximum height of decoded image. Zero means no limit.
+    max_height: u32,
+    /// Maximum size of decoded pixels in bytes. Zero means no limit.
+    max_memory: u64,
+    /// Decode image as `format_hint` instead of detecting its format.
+    use_format_hint: bool,
+    /// One of `ImageFormat` values.
+    format_hint: u32,
+    /// Apply EXIF orientation to decoded pixels, as `img_auto_orient` does.
+    auto_orient: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            max_width: 0,
+            max_height: 0,
+            max_memory: 0,
+            use_format_hint: false,
+            format_hint: 0,
+            auto_orient: false,
+        }
+    }
+}
+
+unsafe impl Versioned for OpenOptions {}
+
+impl OpenOptions {
+    /// Returns format hint if it is set.
+    fn format_hint(&self) -> Result<Option<image::ImageFormat>, ImageError> {
+        if !self.use_format_hint {
+            return Ok(None);
+        }
+
+        let format = ImageFormat::try_from(self.format_hint)?;
+        Ok(Some(format.into()))
+    }
+}
+
+/// Limits of one call, e.g. request of multi-tenant server. `size` field contain size of this
+/// struct. Zero means no limit.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct OperationBudget {
+    size: usize,
+    /// Maximum run time of call in milliseconds.
+    max_millis: u64,
+    /// Maximum total size in bytes of pixel buffers allocated by call.
+    max_bytes: u64,
+}
+
+impl Default for OperationBudget {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            max_millis: 0,
+            max_bytes: 0,
+        }
+    }
+}
+
+unsafe impl Versioned for OperationBudget {}
+
+/// Options of `img_batch`. `size` field contain size of this struct.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct BatchOptions {
+    size: usize,
+    /// Extension of output files without dot, e.g. `"webp"`, replacing extensions of sources and
+    /// selecting format of outputs. Null keeps extension and format of each source.
+    extension: RawStr,
+    /// JPEG quality of outputs in `1..=100` range.
+    jpeg_quality: u8,
+    /// Write metadata of sources (EXIF, PNG text) if output format supports it (JPEG, PNG).
+    keep_metadata: bool,
+    /// Progress callback with its user data receiving percent of processed files. Null means no
+    /// callback.
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+    /// Cancellation token aborting batch, null means batch can't be cancelled.
+    token: TokenHandle,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            extension: RawStr(std::ptr::null()),
+            jpeg_quality: SaveOptions::default().jpeg_quality,
+            keep_metadata: false,
+            progress_fn: None,
+            user_data: std::ptr::null_mut(),
+            token: TokenHandle(std::ptr::null_mut()),
+        }
+    }
+}
+
+unsafe impl Versioned for BatchOptions {
+    fn validate(&self) -> Result<(), ImageError> {
+        if !(1..=100).contains(&self.jpeg_quality) {
+            let message = format!("JPEG quality {} is not in 1..=100 range", self.jpeg_quality);
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return Err(ImageError::Parameter);
+        }
+        Ok(())
+    }
+}
+
+/// Operating system file handle: file descriptor on Unix, `HANDLE` on Windows.
+#[cfg(unix)]
+type OsHandle = std::os::raw::c_int;
+/// Operating system file handle: file descriptor on Unix, `HANDLE` on Windows.
+#[cfg(windows)]
+type OsHandle = *mut c_void;
+/// Placeholder of file handle on platforms without them, e.g. WebAssembly, whose functions
+/// taking file handles return `ImageError::Unsupported`.
+#[cfg(not(any(unix, windows)))]
+type OsHandle = std::os::raw::c_int;
+
+/// Loads image from file function type.
+type OpenImageFn = unsafe extern "C" fn(RawPath, *mut ImageHandle) -> ImageError;
+/// Loads image from file with options function type.
+type OpenImageWithOptionsFn =
+    unsafe extern "C" fn(RawPath, *const OpenOptions, *mut ImageHandle) -> ImageError;
+/// Loads image from host-provided reader function type.
+type OpenImageWithReaderFn =
+    unsafe extern "C" fn(ReadFn, Option<SeekFn>, *mut c_void, *mut ImageHandle) -> ImageError;
+/// Saves image to file function type.
+type SaveImageFn = unsafe extern "C" fn(RawPath, ImageHandle) -> ImageError;
+/// Saves image to host-provided writer function type.
+type SaveImageWithWriterFn =
+    unsafe extern "C" fn(WriteFn, *mut c_void, ImageHandle, u32, *const SaveOptions) -> ImageError;
+/// Loads image from OS file handle function type.
+type OpenImageFdFn = unsafe extern "C" fn(OsHandle, *mut ImageHandle) -> ImageError;
+/// Saves image to OS file handle function type.
+type SaveImageFdFn =
+    unsafe extern "C" fn(OsHandle, ImageHandle, u32, *const SaveOptions) -> ImageError;
+/// Loads image from base64 data URI function type.
+type OpenImageDataUriFn = unsafe extern "C" fn(RawStr, *mut ImageHandle) -> ImageError;
+/// Encodes image into base64 data URI function type.
+type EncodeImageDataUriFn =
+    unsafe extern "C" fn(ImageHandle, u32, *mut c_char, *mut usize) -> ImageError;
+/// Loads image from HTTP(S) URL function type.
+type OpenImageUrlFn = unsafe extern "C" fn(RawStr, u32, usize, *mut ImageHandle) -> ImageError;
+/// Sets limits applied to every decoded image function type.
+type SetLimitsFn = unsafe extern "C" fn(u32, u32, u64);
+/// Destroys image function type.
+type DestroyImageFn = unsafe extern "C" fn(ImageHandle);
+
+/// Encodes image into caller-provided buffer function type.
+type EncodeImageFn = unsafe extern "C" fn(ImageHandle, u32, *mut u8, *mut usize) -> ImageError;
+
+/// Decodes, transforms and encodes image from reader to writer function type. Deprecated: use
+/// `TranscodeStream2Fn`, which encodes in given format.
+type TranscodeStreamFn =
+    unsafe extern "C" fn(ReadFn, WriteFn, *const Operation, usize, *mut c_void) -> ImageError;
+
+/// Creates image from raw pixel data function type.
+type ImageFromRawFn =
+    unsafe extern "C" fn(*const u8, u32, u32, u32, *mut ImageHandle) -> ImageError;
+
+/// Packs image into e-paper framebuffer function type.
+type PackEinkFn = unsafe extern "C" fn(ImageHandle, u32, *mut u8, *mut usize) -> ImageError;
+/// Packs image into display framebuffer function type.
+type PackFramebufferFn =
+    unsafe extern "C" fn(ImageHandle, u32, u32, *mut u8, *mut usize) -> ImageError;
+/// Suggests crops of image function type.
+type SuggestCropsFn =
+    unsafe extern "C" fn(ImageHandle, u32, u32, usize, *mut Rect, *mut usize) -> ImageError;
+/// Reads EXIF tag of image function type.
+type ExifGetFn = unsafe extern "C" fn(ImageHandle, u16, *mut c_char, *mut usize) -> ImageError;
+/// Draws compositional guides over image function type.
+type DrawGuidesFn = unsafe extern "C" fn(ImageHandle, *const GuideSpec) -> ImageError;
+/// Estimates noise of image channels function type.
+type EstimateNoiseFn = unsafe extern "C" fn(ImageHandle, *mut f64) -> ImageError;
+/// Writes EXIF tag of image function type.
+type ExifSetFn = unsafe extern "C" fn(ImageHandle, u16, u32, RawStr) -> ImageError;
+/// Applies EXIF orientation to image function type.
+type AutoOrientFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Denoises image with automatically picked strength function type.
+type AutoDenoiseFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Sharpens image with automatically picked strength function type.
+type AutoSharpenFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Corrects lateral chromatic aberration function type.
+type CorrectCaFn = unsafe extern "C" fn(ImageHandle, f32, f32) -> ImageError;
+/// Reads ICC profile of image function type.
+type IccGetFn = unsafe extern "C" fn(ImageHandle, *mut u8, *mut usize) -> ImageError;
+/// Replaces ICC profile of image function type.
+type IccSetFn = unsafe extern "C" fn(ImageHandle, *const u8, usize) -> ImageError;
+/// Converts image from its ICC profile to sRGB function type.
+type ConvertToSrgbFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Removes purple fringes at high-contrast edges function type.
+type DefringeFn = unsafe extern "C" fn(ImageHandle, f32, f32) -> ImageError;
+/// Reads physical resolution of image function type.
+type GetDpiFn = unsafe extern "C" fn(ImageHandle, *mut f64, *mut f64) -> ImageError;
+/// Sets physical resolution of image function type.
+type SetDpiFn = unsafe extern "C" fn(ImageHandle, f64, f64) -> ImageError;
+/// Reconstructs clipped highlights function type.
+type RecoverHighlightsFn = unsafe extern "C" fn(ImageHandle, f32) -> ImageError;
+/// Lists keywords of PNG text of image function type.
+type PngTextKeysFn = unsafe extern "C" fn(ImageHandle, *mut c_char, *mut usize) -> ImageError;
+/// Reads PNG text of image function type.
+type PngTextGetFn =
+    unsafe extern "C" fn(ImageHandle, RawStr, *mut c_char, *mut usize) -> ImageError;
+/// Writes PNG text of image function type.
+type PngTextSetFn = unsafe extern "C" fn(ImageHandle, RawStr, RawStr) -> ImageError;
+/// Adjusts shadows and highlights of image function type.
+type ShadowsHighlightsFn = unsafe extern "C" fn(ImageHandle, f32, f32, f32) -> ImageError;
+/// Adjusts hue, saturation and luminance of color bands of image function type.
+type HslAdjustFn = unsafe extern "C" fn(ImageHandle, *const HslBand, usize) -> ImageError;
+/// Reads XMP packet of image function type.
+type XmpGetFn = unsafe extern "C" fn(ImageHandle, *mut c_char, *mut usize) -> ImageError;
+/// Reads IPTC dataset of image function type.
+type IptcGetFn = unsafe extern "C" fn(ImageHandle, u32, *mut c_char, *mut usize) -> ImageError;
+/// Replaces color of image function type.
+type ReplaceColorFn = unsafe extern "C" fn(ImageHandle, u32, u32, f32, bool) -> ImageError;
+/// Removes all metadata of image function type.
+type StripMetadataFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Loads EXIF thumbnail of image file function type.
+type ExifThumbnailFn = unsafe extern "C" fn(RawPath, *mut ImageHandle) -> ImageError;
+/// Grades shadows, midtones and highlights of image function type.
+type SplitToneFn = unsafe extern "C" fn(ImageHandle, u32, u32, u32, f32) -> ImageError;
+/// Applies film stock look to image function type.
+type FilmEmulationFn = unsafe extern "C" fn(ImageHandle, u32, f32) -> ImageError;
+/// Reads message of the last error function type.
+type LastErrorMessageFn = unsafe extern "C" fn(*mut c_char, usize) -> usize;
+/// Reads details of the last error function type.
+type LastErrorDetailsFn = unsafe extern "C" fn(*mut ErrorDetails) -> ImageError;
+/// Sets depth of edit journal of image function type.
+type SetJournalDepthFn = unsafe extern "C" fn(ImageHandle, u32) -> ImageError;
+/// Reverts the last edit of image function type.
+type UndoFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Reapplies the last undone edit of image function type.
+type RedoFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Reads numbers of undo and redo steps of image function type.
+type HistoryLenFn = unsafe extern "C" fn(ImageHandle, *mut usize, *mut usize) -> ImageError;
+/// Exports edit stack of image function type.
+type ExportEditStackFn = unsafe extern "C" fn(ImageHandle, *mut *mut c_char) -> ImageError;
+/// Applies edit stack to image function type.
+type ApplyEditStackFn = unsafe extern "C" fn(ImageHandle, RawStr) -> ImageError;
+/// Frees string allocated by library function type.
+type FreeStringFn = unsafe extern "C" fn(*mut c_char);
+/// Sets log callback function type.
+type SetLogCallbackFn = unsafe extern "C" fn(Option<LogFn>, *mut c_void);
+/// Attaches annotation to region of image function type.
+type AddAnnotationFn = unsafe extern "C" fn(ImageHandle, Rect, RawStr) -> ImageError;
+/// Reads number of annotations of image function type.
+type AnnotationCountFn = unsafe extern "C" fn(ImageHandle, *mut usize) -> ImageError;
+/// Reads annotation of image function type.
+type GetAnnotationFn =
+    unsafe extern "C" fn(ImageHandle, usize, *mut Rect, *mut c_char, *mut usize) -> ImageError;
+/// Removes annotation of image function type.
+type RemoveAnnotationFn = unsafe extern "C" fn(ImageHandle, usize) -> ImageError;
+/// Draws annotated regions over image function type.
+type BurnAnnotationsFn = unsafe extern "C" fn(ImageHandle, u32, u32) -> ImageError;
+/// Creates empty layered document function type.
+type DocNewFn = unsafe extern "C" fn(u32, u32, *mut DocumentHandle) -> ImageError;
+/// Destroys document function type.
+type DocDestroyFn = unsafe extern "C" fn(DocumentHandle);
+/// Adds layer on top of document function type.
+type DocAddLayerFn =
+    unsafe extern "C" fn(DocumentHandle, ImageHandle, *const LayerProps) -> ImageError;
+/// Reads number of layers of document function type.
+type DocLayerCountFn = unsafe extern "C" fn(DocumentHandle, *mut usize) -> ImageError;
+/// Reads properties of document layer function type.
+type DocGetLayerFn = unsafe extern "C" fn(DocumentHandle, usize, *mut LayerProps) -> ImageError;
+/// Sets properties of document layer function type.
+type DocSetLayerFn = unsafe extern "C" fn(DocumentHandle, usize, *const LayerProps) -> ImageError;
+/// Reads image of document layer function type.
+type DocLayerImageFn = unsafe extern "C" fn(DocumentHandle, usize, *mut ImageHandle) -> ImageError;
+/// Removes layer of document function type.
+type DocRemoveLayerFn = unsafe extern "C" fn(DocumentHandle, usize, *mut ImageHandle) -> ImageError;
+/// Moves layer of document function type.
+type DocMoveLayerFn = unsafe extern "C" fn(DocumentHandle, usize, usize) -> ImageError;
+/// Composites layers of document into image function type.
+type DocFlattenFn = unsafe extern "C" fn(DocumentHandle, *mut ImageHandle) -> ImageError;
+/// Checks image handle function type.
+type IsValidFn = unsafe extern "C" fn(ImageHandle) -> bool;
+/// Opens OpenRaster document function type.
+type DocOpenOraFn = unsafe extern "C" fn(RawPath, *mut DocumentHandle) -> ImageError;
+/// Saves document as OpenRaster function type.
+type DocSaveOraFn = unsafe extern "C" fn(DocumentHandle, RawPath) -> ImageError;
+/// Opens layers of PSD file as document function type.
+type DocOpenPsdFn = unsafe extern "C" fn(RawPath, *mut DocumentHandle) -> ImageError;
+/// Enables debug registry of image handles function type.
+type SetDebugHandlesFn = unsafe extern "C" fn(bool);
+/// Counts live images function type.
+type LiveHandleCountFn = unsafe extern "C" fn() -> usize;
+/// Lists live images function type.
+type DumpLeaksFn = unsafe extern "C" fn(LeakFn, *mut c_void) -> usize;
+
+/// Blurs image into new image function type.
+type BlurImage2Fn = unsafe extern "C" fn(ImageHandle, f32, *mut ImageHandle) -> ImageError;
+/// Flips image horizontally reporting errors function type.
+type MirrorImage2Fn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Finds template in image function type.
+type MatchTemplateFn = unsafe extern "C" fn(
+    ImageHandle,
+    ImageHandle,
+    u32,
+    usize,
+    *mut MatchResult,
+    *mut usize,
+) -> ImageError;
+/// Detects keypoints of image function type.
+type DetectKeypointsFn =
+    unsafe extern "C" fn(ImageHandle, u32, *mut *mut Keypoint, *mut usize) -> ImageError;
+/// Frees keypoints function type.
+type FreeKeypointsFn = unsafe extern "C" fn(*mut Keypoint, usize);
+/// Matches keypoints of two images function type.
+type MatchKeypointsFn = unsafe extern "C" fn(ImageHandle, ImageHandle, *mut Matches) -> ImageError;
+/// Frees keypoint matches function type.
+type FreeMatchesFn = unsafe extern "C" fn(*mut Matches);
+/// Aligns two images function type.
+type AlignFn = unsafe extern "C" fn(
+    ImageHandle,
+    ImageHandle,
+    u32,
+    *mut Transform,
+    *mut ImageHandle,
+) -> ImageError;
+/// Warps image by control grid function type.
+type MeshWarpFn =
+    unsafe extern "C" fn(ImageHandle, *const Point, *const Point, u32, u32) -> ImageError;
+/// Creates export profile function type.
+type ExportProfileCreateFn = unsafe extern "C" fn(RawStr, *mut ExportProfileHandle) -> ImageError;
+/// Destroys export profile function type.
+type ExportProfileDestroyFn = unsafe extern "C" fn(ExportProfileHandle);
+/// Exports image by profile function type.
+type ExportFn = unsafe extern "C" fn(ImageHandle, ExportProfileHandle, RawPath) -> ImageError;
+/// Returns capabilities of build function type.
+type CapabilitiesFn = unsafe extern "C" fn() -> u32;
+/// Checks whether function is available in build function type.
+type HasFnFn = unsafe extern "C" fn(RawStr) -> bool;
+/// Generates icon set of image function type.
+type GenerateIconSetFn = unsafe extern "C" fn(ImageHandle, u32, RawPath) -> ImageError;
+/// Registers decoder plugin function type.
+type RegisterDecoderFn =
+    unsafe extern "C" fn(RawStr, Option<ProbeFn>, Option<DecodeFn>, *mut c_void) -> ImageError;
+/// Registers encoder plugin function type.
+type RegisterEncoderFn = unsafe extern "C" fn(RawStr, Option<EncodeFn>, *mut c_void) -> ImageError;
+/// Composes social media card function type.
+type ComposeCardFn =
+    unsafe extern "C" fn(ImageHandle, *const CardSpec, *mut ImageHandle) -> ImageError;
+/// Loads image from file with options within budget function type.
+type OpenWithBudgetFn = unsafe extern "C" fn(
+    RawPath,
+    *const OpenOptions,
+    *const OperationBudget,
+    *mut ImageHandle,
+) -> ImageError;
+/// Saves image to file within budget function type.
+type SaveWithBudgetFn =
+    unsafe extern "C" fn(RawPath, ImageHandle, *const OperationBudget) -> ImageError;
+/// Transcodes image stream with operations within budget function type.
+type TranscodeStreamWithBudgetFn = unsafe extern "C" fn(
+    ReadFn,
+    WriteFn,
+    *const Operation,
+    usize,
+    *const OperationBudget,
+    *mut c_void,
+) -> ImageError;
+/// Detaches image from current thread function type.
+type DetachThreadFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Attaches image to current thread function type.
+type AttachThreadFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Applies operation by name with parameters to copy of image function type.
+type ApplyFn = unsafe extern "C" fn(ImageHandle, RawStr, RawStr, *mut ImageHandle) -> ImageError;
+/// Loads image from memory buffer with options function type.
+type OpenImageMemoryFn =
+    unsafe extern "C" fn(*const u8, usize, *const OpenOptions, *mut ImageHandle) -> ImageError;
+/// Sets number of worker threads function type.
+type SetThreadCountFn = unsafe extern "C" fn(u32);
+/// Blurs image into new image reporting progress function type.
+type BlurWithProgressFn = unsafe extern "C" fn(
+    ImageHandle,
+    f32,
+    Option<ProgressFn>,
+    *mut c_void,
+    *mut ImageHandle,
+) -> ImageError;
+/// Resizes image into new image reporting progress function type.
+type ResizeWithProgressFn = unsafe extern "C" fn(
+    ImageHandle,
+    u32,
+    u32,
+    Option<ProgressFn>,
+    *mut c_void,
+    *mut ImageHandle,
+) -> ImageError;
+/// Encodes image into caller-provided buffer reporting progress function type.
+type EncodeWithProgressFn = unsafe extern "C" fn(
+    ImageHandle,
+    u32,
+    *mut u8,
+    *mut usize,
+    Option<ProgressFn>,
+    *mut c_void,
+) -> ImageError;
+/// Creates cancellation token function type.
+type TokenCreateFn = unsafe extern "C" fn(*mut TokenHandle) -> ImageError;
+/// Cancels operations of token function type.
+type TokenCancelFn = unsafe extern "C" fn(TokenHandle);
+/// Destroys cancellation token function type.
+type TokenDestroyFn = unsafe extern "C" fn(TokenHandle);
+/// Starts operation on worker thread cancelled by token function type.
+type SubmitWithTokenFn =
+    unsafe extern "C" fn(ImageHandle, RawStr, RawStr, TokenHandle, *mut JobHandle) -> ImageError;
+/// Converts many files with edit stack on worker threads function type.
+type BatchFn = unsafe extern "C" fn(
+    *const RawPath,
+    usize,
+    RawStr,
+    RawPath,
+    *const BatchOptions,
+    *mut ImageError,
+) -> ImageError;
+/// Creates empty operation pipeline function type.
+type PipelineCreateFn = unsafe extern "C" fn(*mut PipelineHandle) -> ImageError;
+/// Destroys operation pipeline function type.
+type PipelineDestroyFn = unsafe extern "C" fn(PipelineHandle);
+/// Appends crop to pipeline function type.
+type PipelineAddCropFn = unsafe extern "C" fn(PipelineHandle, Rect) -> ImageError;
+/// Appends resize to pipeline function type.
+type PipelineAddResizeFn = unsafe extern "C" fn(PipelineHandle, u32, u32) -> ImageError;
+/// Appends blur to pipeline function type.
+type PipelineAddBlurFn = unsafe extern "C" fn(PipelineHandle, f32) -> ImageError;
+/// Appends horizontal flip to pipeline function type.
+type PipelineAddMirrorFn = unsafe extern "C" fn(PipelineHandle) -> ImageError;
+/// Applies operations of pipeline to image function type.
+type PipelineExecuteFn = unsafe extern "C" fn(PipelineHandle, ImageHandle) -> ImageError;
+/// Blurs image into existing image function type.
+type BlurIntoFn = unsafe extern "C" fn(ImageHandle, ImageHandle, f32) -> ImageError;
+/// Resizes image into existing image function type.
+type ResizeIntoFn = unsafe extern "C" fn(ImageHandle, ImageHandle) -> ImageError;
+/// Sets size limit of pool of temporary buffers function type.
+type SetPoolLimitFn = unsafe extern "C" fn(u64);
+/// Frees idle buffers of pool function type.
+type PoolTrimFn = unsafe extern "C" fn() -> u64;
+/// Creates copy-on-write clone of image function type.
+type CloneCowFn = unsafe extern "C" fn(ImageHandle, *mut ImageHandle) -> ImageError;
+/// Creates frozen clone of image readable by many threads at once function type.
+type FreezeFn = unsafe extern "C" fn(ImageHandle, *mut ImageHandle) -> ImageError;
+/// Applies operations of pipeline to TIFF file streaming its strips into TIFF file function type.
+type PipelineExecuteTiledFn = unsafe extern "C" fn(
+    PipelineHandle,
+    RawPath,
+    RawPath,
+    Option<ProgressFn>,
+    *mut c_void,
+) -> ImageError;
+/// Applies operation by name with parameters to downscaled copy of image function type.
+type ApplyPreviewFn =
+    unsafe extern "C" fn(ImageHandle, RawStr, RawStr, u32, *mut ImageHandle) -> ImageError;
+/// Writes size, color type and bit depth of image function type.
+type InfoFn = unsafe extern "C" fn(ImageHandle, *mut ImageInfo) -> ImageError;
+/// Locks pixels of image for direct access function type.
+type LockPixelsFn = unsafe extern "C" fn(ImageHandle, *mut PixelLock) -> ImageError;
+/// Unlocks pixels of image function type.
+type UnlockPixelsFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Reads color of pixel function type.
+type GetPixelFn = unsafe extern "C" fn(ImageHandle, u32, u32, *mut u32) -> ImageError;
+/// Sets color of pixel function type.
+type SetPixelFn = unsafe extern "C" fn(ImageHandle, u32, u32, u32) -> ImageError;
+/// Creates read-only view of region of image sharing its pixels function type.
+type ViewFn = unsafe extern "C" fn(ImageHandle, Rect, *mut ImageHandle) -> ImageError;
+/// Converts image to color type function type.
+type ConvertFn = unsafe extern "C" fn(ImageHandle, u32, *mut ImageHandle) -> ImageError;
+/// Locks pixels of image converted to color type with aligned rows function type.
+type LockPixelsAsFn = unsafe extern "C" fn(ImageHandle, u32, u32, *mut PixelLock) -> ImageError;
+/// Copies pixels of image converted to color type into caller buffer function type.
+type ReadPixelsFn = unsafe extern "C" fn(ImageHandle, *mut u8, usize, u32, usize) -> ImageError;
+/// Passes every pixel of image to host callback which may change it function type.
+type MapPixelsFn =
+    unsafe extern "C" fn(ImageHandle, Option<PixelFn>, *mut c_void, bool) -> ImageError;
+/// Passes rows of image to host callback function type.
+type ForEachRowFn = unsafe extern "C" fn(ImageHandle, Option<RowFn>, *mut c_void) -> ImageError;
+/// Creates copy of image with its own pixels function type.
+type CloneFn = unsafe extern "C" fn(ImageHandle, *mut ImageHandle) -> ImageError;
+/// Creates image filled with color function type.
+type NewFn = unsafe extern "C" fn(u32, u32, u32, u32, *mut ImageHandle) -> ImageError;
+/// Reorders channels of image function type.
+type SwapChannelsFn = unsafe extern "C" fn(ImageHandle, *const [u8; 4]) -> ImageError;
+/// Counts values of channel of image in 256 bins function type.
+type HistogramFn = unsafe extern "C" fn(ImageHandle, u32, *mut u32) -> ImageError;
+/// Counts values of channel of image in 65536 bins function type.
+type Histogram16Fn = unsafe extern "C" fn(ImageHandle, u32, *mut u32) -> ImageError;
+/// Writes statistics of channels and luma of image to `Stats` function type.
+type StatsFn = unsafe extern "C" fn(ImageHandle, *mut Stats) -> ImageError;
+/// Writes `k` dominant colors of image with their weights function type.
+type DominantColorsFn = unsafe extern "C" fn(ImageHandle, u32, *mut u32, *mut f32) -> ImageError;
+/// Writes average color of image or its region as `0xRRGGBBAA` function type.
+type AverageColorFn = unsafe extern "C" fn(ImageHandle, *const Rect, *mut u32) -> ImageError;
+/// Writes 64-bit perceptual hash of image computed by `HashAlgorithm` function type.
+type PhashFn = unsafe extern "C" fn(ImageHandle, u32, *mut u64) -> ImageError;
+/// Returns number of bits differing between two hashes function type.
+type HashDistanceFn = unsafe extern "C" fn(u64, u64) -> u32;
+/// Writes BlurHash of image as null-terminated string function type.
+type BlurhashFn =
+    unsafe extern "C" fn(ImageHandle, u32, u32, *mut c_char, *mut usize) -> ImageError;
+/// Writes ThumbHash of image function type.
+type ThumbhashFn = unsafe extern "C" fn(ImageHandle, *mut u8, *mut usize) -> ImageError;
+/// Decodes ThumbHash into new preview image function type.
+type ThumbhashDecodeFn = unsafe extern "C" fn(*const u8, usize, *mut ImageHandle) -> ImageError;
+/// Compares two images of the same size by metric function type.
+type CompareFn = unsafe extern "C" fn(ImageHandle, ImageHandle, u32, *mut f64) -> ImageError;
+/// Counts pixels differing between two images and highlights them function type.
+type DiffFn =
+    unsafe extern "C" fn(ImageHandle, ImageHandle, u8, *mut ImageHandle, *mut u64) -> ImageError;
+/// Crops away uniform borders of image function type.
+type TrimFn = unsafe extern "C" fn(ImageHandle, u8, *mut ImageHandle) -> ImageError;
+/// Tells whether image is uniform function type.
+type IsBlankFn = unsafe extern "C" fn(ImageHandle, u8, *mut bool) -> ImageError;
+/// Crops image to aspect ratio keeping its most interesting part and resizes it function type.
+type SmartCropFn = unsafe extern "C" fn(ImageHandle, u32, u32, *mut ImageHandle) -> ImageError;
+/// Estimates sharpness of image function type.
+type SharpnessFn = unsafe extern "C" fn(ImageHandle, *mut f64) -> ImageError;
+/// Draws antialiased line over image function type.
+type DrawLineFn = unsafe extern "C" fn(ImageHandle, f32, f32, f32, f32, f32, u32) -> ImageError;
+/// Draws antialiased filled or stroked rectangle over image function type.
+type DrawRectFn =
+    unsafe extern "C" fn(ImageHandle, f32, f32, f32, f32, f32, u32, bool) -> ImageError;
+/// Draws antialiased filled or stroked circle over image function type.
+type DrawCircleFn = unsafe extern "C" fn(ImageHandle, f32, f32, f32, f32, u32, bool) -> ImageError;
+/// Draws antialiased filled or stroked polygon over image function type.
+type DrawPolygonFn =
+    unsafe extern "C" fn(ImageHandle, *const Point, usize, f32, u32, bool) -> ImageError;
+/// Creates image filled with linear or radial gradient function type.
+type GradientFn = unsafe extern "C" fn(
+    u32,
+    u32,
+    *const GradientStop,
+    usize,
+    u32,
+    f32,
+    *mut ImageHandle,
+) -> ImageError;
+/// Creates checkerboard image of two colors function type.
+type CheckerboardFn = unsafe extern "C" fn(u32, u32, u32, u32, u32, *mut ImageHandle) -> ImageError;
+/// Creates image of SMPTE color bars function type.
+type ColorBarsFn = unsafe extern "C" fn(u32, u32, *mut ImageHandle) -> ImageError;
+/// Watermarking function type.
+type WatermarkFn = unsafe extern "C" fn(ImageHandle, ImageHandle, u32, u32, f32, f32) -> ImageError;
+/// Sprite packing function type.
+type PackSpritesFn = unsafe extern "C" fn(
+    *const ImageHandle,
+    usize,
+    u32,
+    u32,
+    *mut ImageHandle,
+    *mut Rect,
+) -> ImageError;
+/// Noise generation function type.
+type NoiseFn = unsafe extern "C" fn(u32, u32, u32, f32, u32, u32, *mut ImageHandle) -> ImageError;
+/// Corner rounding function type.
+type RoundCornersFn = unsafe extern "C" fn(ImageHandle, f32) -> ImageError;
+/// Circle masking function type.
+type CircleMaskFn = unsafe extern "C" fn(ImageHandle) -> ImageError;
+/// Alpha masking function type.
+type ApplyMaskFn = unsafe extern "C" fn(ImageHandle, ImageHandle, bool) -> ImageError;
+/// CMYK TIFF saving function type.
+type SaveCmykTiffFn = unsafe extern "C" fn(RawPath, ImageHandle, *const u8, usize) -> ImageError;
+/// Pipeline execution within budget function type.
+type PipelineExecuteWithBudgetFn =
+    unsafe extern "C" fn(PipelineHandle, ImageHandle, *const OperationBudget) -> ImageError;
+/// Tiled pipeline execution within budget function type.
+type PipelineExecuteTiledWithBudgetFn = unsafe extern "C" fn(
+    PipelineHandle,
+    RawPath,
+    RawPath,
+    *const OperationBudget,
+    Option<ProgressFn>,
+    *mut c_void,
+) -> ImageError;
+/// Decodes, transforms and encodes image in given format from reader to writer function type.
+type TranscodeStream2Fn = unsafe extern "C" fn(
+    ReadFn,
+    WriteFn,
+    *const Operation,
+    usize,
+    u32,
+    u64,
+    *mut c_void,
+) -> ImageError;
+/// Enables cache of pipeline transcoding results function type.
+type EnableCacheFn = unsafe extern "C" fn(*const c_char, u64) -> ImageError;
+/// Transcodes stream applying pipeline function type.
+type PipelineTranscodeFn =
+    unsafe extern "C" fn(PipelineHandle, ReadFn, WriteFn, u32, u64, *mut c_void) -> ImageError;
+/// Creates job queue limiting its running jobs function type.
+type QueueCreateFn = unsafe extern "C" fn(u32, *mut QueueHandle) -> ImageError;
+/// Destroys job queue function type.
+type QueueDestroyFn = unsafe extern "C" fn(QueueHandle);
+/// Starts operation as job of queue with priority function type.
+type SubmitOnFn = unsafe extern "C" fn(
+    QueueHandle,
+    i32,
+    ImageHandle,
+    RawStr,
+    RawStr,
+    TokenHandle,
+    *mut JobHandle,
+) -> ImageError;
+/// Starts operation by name with parameters on worker thread function type.
+type SubmitFn = unsafe extern "C" fn(ImageHandle, RawStr, RawStr, *mut JobHandle) -> ImageError;
+/// Gets progress of job function type.
+type JobStatusFn = unsafe extern "C" fn(JobHandle) -> JobStatus;
+/// Waits for job and takes its result function type.
+type JobResultFn = unsafe extern "C" fn(JobHandle, *mut ImageHandle) -> ImageError;
+/// Destroys job function type.
+type JobDestroyFn = unsafe extern "C" fn(JobHandle);
+
+/// Performs a Gaussian blur on the supplied image function type. Deprecated: use
+/// `BlurImage2Fn`, which reports errors.
+type BlurImageFn = unsafe extern "C" fn(ImageHandle, f32) -> ImageHandle;
+/// Flips image horizontally function type. Deprecated: use `MirrorImage2Fn`, which reports
+/// errors.
+type MirrorImageFn = unsafe extern "C" fn(ImageHandle);
+
+/// Contains functions provided by library. Hosts get prefix of it of their ABI version as
+/// `functions` of `FunctionsV2` written by `functions_ex()`.
+/// `size` field contain size of this struct. It helps to avoid versioning and some other errors.
+#[allow(unused)]
+#[repr(C)]
+pub struct FunctionsBlock {
+    size: usize,
+    open_image: OpenImageFn,
+    save_image: SaveImageFn,
+    destroy_image: DestroyImageFn,
+    blur_image: BlurImageFn,
+    mirror_image: MirrorImageFn,
+    encode_image: EncodeImageFn,
+    open_image_with_reader: OpenImageWithReaderFn,
+    save_image_with_writer: SaveImageWithWriterFn,
+    transcode_stream: TranscodeStreamFn,
+    open_image_fd: OpenImageFdFn,
+    save_image_fd: SaveImageFdFn,
+    open_image_data_uri: OpenImageDataUriFn,
+    encode_image_data_uri: EncodeImageDataUriFn,
+    open_image_url: OpenImageUrlFn,
+    open_image_with_options: OpenImageWithOptionsFn,
+    set_limits: SetLimitsFn,
+    pack_eink: PackEinkFn,
+    pack_framebuffer: PackFramebufferFn,
+    image_from_raw: ImageFromRawFn,
+    suggest_crops: SuggestCropsFn,
+    exif_get: ExifGetFn,
+    draw_guides: DrawGuidesFn,
+    estimate_noise: EstimateNoiseFn,
+    exif_set: ExifSetFn,
+    auto_orient: AutoOrientFn,
+    auto_denoise: AutoDenoiseFn,
+    auto_sharpen: AutoSharpenFn,
+    correct_ca: CorrectCaFn,
+    icc_get: IccGetFn,
+    icc_set: IccSetFn,
+    convert_to_srgb: ConvertToSrgbFn,
+    defringe: DefringeFn,
+    get_dpi: GetDpiFn,
+    set_dpi: SetDpiFn,
+    recover_highlights: RecoverHighlightsFn,
+    png_text_keys: PngTextKeysFn,
+    png_text_get: PngTextGetFn,
+    png_text_set: PngTextSetFn,
+    shadows_highlights: ShadowsHighlightsFn,
+    hsl_adjust: HslAdjustFn,
+    xmp_get: XmpGetFn,
+    iptc_get: IptcGetFn,
+    replace_color: ReplaceColorFn,
+    strip_metadata: StripMetadataFn,
+    exif_thumbnail: ExifThumbnailFn,
+    split_tone: SplitToneFn,
+    film_emulation: FilmEmulationFn,
+    last_error_message: LastErrorMessageFn,
+    set_journal_depth: SetJournalDepthFn,
+    undo: UndoFn,
+    redo: RedoFn,
+    history_len: HistoryLenFn,
+    last_error_details: LastErrorDetailsFn,
+    export_edit_stack: ExportEditStackFn,
+    apply_edit_stack: ApplyEditStackFn,
+    free_string: FreeStringFn,
+    set_log_callback: SetLogCallbackFn,
+    add_annotation: AddAnnotationFn,
+    annotation_count: AnnotationCountFn,
+    get_annotation: GetAnnotationFn,
+    remove_annotation: RemoveAnnotationFn,
+    burn_annotations: BurnAnnotationsFn,
+    doc_new: DocNewFn,
+    doc_destroy: DocDestroyFn,
+    doc_add_layer: DocAddLayerFn,
+    doc_layer_count: DocLayerCountFn,
+    doc_get_layer: DocGetLayerFn,
+    doc_set_layer: DocSetLayerFn,
+    doc_layer_image: DocLayerImageFn,
+    doc_remove_layer: DocRemoveLayerFn,
+    doc_move_layer: DocMoveLayerFn,
+    doc_flatten: DocFlattenFn,
+    is_valid: IsValidFn,
+    doc_open_ora: DocOpenOraFn,
+    doc_save_ora: DocSaveOraFn,
+    doc_open_psd: DocOpenPsdFn,
+    set_debug_handles: SetDebugHandlesFn,
+    live_handle_count: LiveHandleCountFn,
+    dump_leaks: DumpLeaksFn,
+    blur_image2: BlurImage2Fn,
+    mirror_image2: MirrorImage2Fn,
+    match_template: MatchTemplateFn,
+    detect_keypoints: DetectKeypointsFn,
+    free_keypoints: FreeKeypointsFn,
+    match_keypoints: MatchKeypointsFn,
+    free_matches: FreeMatchesFn,
+    align: AlignFn,
+    mesh_warp: MeshWarpFn,
+    export_profile_create: ExportProfileCreateFn,
+    export_profile_destroy: ExportProfileDestroyFn,
+    export: ExportFn,
+    capabilities: CapabilitiesFn,
+    has_fn: HasFnFn,
+    generate_icon_set: GenerateIconSetFn,
+    register_decoder: RegisterDecoderFn,
+    register_encoder: RegisterEncoderFn,
+    compose_card: ComposeCardFn,
+    open_with_budget: OpenWithBudgetFn,
+    save_with_budget: SaveWithBudgetFn,
+    transcode_stream_with_budget: TranscodeStreamWithBudgetFn,
+    detach_thread: DetachThreadFn,
+    attach_thread: AttachThreadFn,
+    apply: ApplyFn,
+    open_image_memory: OpenImageMemoryFn,
+    set_thread_count: SetThreadCountFn,
+    submit: SubmitFn,
+    job_status: JobStatusFn,
+    job_result: JobResultFn,
+    job_destroy: JobDestroyFn,
+    blur_with_progress: BlurWithProgressFn,
+    resize_with_progress: ResizeWithProgressFn,
+    encode_with_progress: EncodeWithProgressFn,
+    token_create: TokenCreateFn,
+    token_cancel: TokenCancelFn,
+    token_destroy: TokenDestroyFn,
+    submit_with_token: SubmitWithTokenFn,
+    batch: BatchFn,
+    pipeline_create: PipelineCreateFn,
+    pipeline_destroy: PipelineDestroyFn,
+    pipeline_add_crop: PipelineAddCropFn,
+    pipeline_add_resize: PipelineAddResizeFn,
+    pipeline_add_blur: PipelineAddBlurFn,
+    pipeline_add_mirror: PipelineAddMirrorFn,
+    pipeline_execute: PipelineExecuteFn,
+    blur_into: BlurIntoFn,
+    resize_into: ResizeIntoFn,
+    set_pool_limit: SetPoolLimitFn,
+    pool_trim: PoolTrimFn,
+    clone_cow: CloneCowFn,
+    freeze: FreezeFn,
+    pipeline_execute_tiled: PipelineExecuteTiledFn,
+    apply_preview: ApplyPreviewFn,
+    info: InfoFn,
+    lock_pixels: LockPixelsFn,
+    unlock_pixels: UnlockPixelsFn,
+    get_pixel: GetPixelFn,
+    set_pixel: SetPixelFn,
+    view: ViewFn,
+    convert: ConvertFn,
+    lock_pixels_as: LockPixelsAsFn,
+    read_pixels: ReadPixelsFn,
+    map_pixels: MapPixelsFn,
+    for_each_row: ForEachRowFn,
+    clone: CloneFn,
+    new: NewFn,
+    swap_channels: SwapChannelsFn,
+    histogram: HistogramFn,
+    histogram16: Histogram16Fn,
+    stats: StatsFn,
+    dominant_colors: DominantColorsFn,
+    average_color: AverageColorFn,
+    phash: PhashFn,
+    hash_distance: HashDistanceFn,
+    blurhash: BlurhashFn,
+    thumbhash: ThumbhashFn,
+    thumbhash_decode: ThumbhashDecodeFn,
+    compare: CompareFn,
+    diff: DiffFn,
+    trim: TrimFn,
+    is_blank: IsBlankFn,
+    smart_crop: SmartCropFn,
+    sharpness: SharpnessFn,
+    draw_line: DrawLineFn,
+    draw_rect: DrawRectFn,
+    draw_circle: DrawCircleFn,
+    draw_polygon: DrawPolygonFn,
+    gradient: GradientFn,
+    checkerboard: CheckerboardFn,
+    color_bars: ColorBarsFn,
+    watermark: WatermarkFn,
+    pack_sprites: PackSpritesFn,
+    noise: NoiseFn,
+    round_corners: RoundCornersFn,
+    circle_mask: CircleMaskFn,
+    apply_mask: ApplyMaskFn,
+    save_cmyk_tiff: SaveCmykTiffFn,
+    pipeline_execute_with_budget: PipelineExecuteWithBudgetFn,
+    pipeline_execute_tiled_with_budget: PipelineExecuteTiledWithBudgetFn,
+    transcode_stream2: TranscodeStream2Fn,
+    enable_cache: EnableCacheFn,
+    pipeline_transcode: PipelineTranscodeFn,
+    queue_create: QueueCreateFn,
+    queue_destroy: QueueDestroyFn,
+    submit_on: SubmitOnFn,
+}
+
+impl Default for FunctionsBlock {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            open_image: img_open,
+            save_image: img_save,
+            destroy_image: img_destroy,
+            blur_image: img_blur,
+            mirror_image: img_mirror,
+            encode_image: img_encode,
+            open_image_with_reader: img_open_with_reader,
+            save_image_with_writer: img_save_with_writer,
+            transcode_stream: img_transcode_stream,
+            open_image_fd: img_open_fd,
+            save_image_fd: img_save_fd,
+            open_image_data_uri: img_open_data_uri,
+            encode_image_data_uri: img_encode_data_uri,
+            open_image_url: img_open_url,
+            open_image_with_options: img_open_with_options,
+            set_limits: img_set_limits,
+            pack_eink: img_pack_eink,
+            pack_framebuffer: img_pack_framebuffer,
+            image_from_raw: img_from_raw,
+            suggest_crops: img_suggest_crops,
+            exif_get: img_exif_get,
+            draw_guides: img_draw_guides,
+            estimate_noise: img_estimate_noise,
+            exif_set: img_exif_set,
+            auto_orient: img_auto_orient,
+            auto_denoise: img_auto_denoise,
+            auto_sharpen: img_auto_sharpen,
+            correct_ca: img_correct_ca,
+            icc_get: img_icc_get,
+            icc_set: img_icc_set,
+            convert_to_srgb: img_convert_to_srgb,
+            defringe: img_defringe,
+            get_dpi: img_get_dpi,
+            set_dpi: img_set_dpi,
+            recover_highlights: img_recover_highlights,
+            png_text_keys: img_png_text_keys,
+            png_text_get: img_png_text_get,
+            png_text_set: img_png_text_set,
+            shadows_highlights: img_shadows_highlights,
+            hsl_adjust: img_hsl_adjust,
+            xmp_get: img_xmp_get,
+            iptc_get: img_iptc_get,
+            replace_color: img_replace_color,
+            strip_metadata: img_strip_metadata,
+            exif_thumbnail: img_exif_thumbnail,
+            split_tone: img_split_tone,
+            film_emulation: img_film_emulation,
+            last_error_message: img_last_error_message,
+            set_journal_depth: img_set_journal_depth,
+            undo: img_undo,
+            redo: img_redo,
+            history_len: img_history_len,
+            last_error_details: img_last_error_details,
+            export_edit_stack: img_export_edit_stack,
+            apply_edit_stack: img_apply_edit_stack,
+            free_string: img_free_string,
+            set_log_callback: img_set_log_callback,
+            add_annotation: img_add_annotation,
+            annotation_count: img_annotation_count,
+            get_annotation: img_get_annotation,
+            remove_annotation: img_remove_annotation,
+            burn_annotations: img_burn_annotations,
+            doc_new: img_doc_new,
+            doc_destroy: img_doc_destroy,
+            doc_add_layer: img_doc_add_layer,
+            doc_layer_count: img_doc_layer_count,
+            doc_get_layer: img_doc_get_layer,
+            doc_set_layer: img_doc_set_layer,
+            doc_layer_image: img_doc_layer_image,
+            doc_remove_layer: img_doc_remove_layer,
+            doc_move_layer: img_doc_move_layer,
+            doc_flatten: img_doc_flatten,
+            is_valid: img_is_valid,
+            doc_open_ora: img_doc_open_ora,
+            doc_save_ora: img_doc_save_ora,
+            doc_open_psd: img_doc_open_psd,
+            set_debug_handles: img_set_debug_handles,
+            live_handle_count: img_live_handle_count,
+            dump_leaks: img_dump_leaks,
+            blur_image2: img_blur2,
+            mirror_image2: img_mirror2,
+            match_template: img_match_template,
+            detect_keypoints: img_detect_keypoints,
+            free_keypoints: img_free_keypoints,
+            match_keypoints: img_match_keypoints,
+            free_matches: img_free_matches,
+            align: img_align,
+            mesh_warp: img_mesh_warp,
+            export_profile_create: img_export_profile_create,
+            export_profile_destroy: img_export_profile_destroy,
+            export: img_export,
+            capabilities: img_capabilities,
+            has_fn: img_has_fn,
+            generate_icon_set: img_generate_icon_set,
+            register_decoder: img_register_decoder,
+            register_encoder: img_register_encoder,
+            compose_card: img_compose_card,
+            open_with_budget: img_open_with_budget,
+            save_with_budget: img_save_with_budget,
+            transcode_stream_with_budget: img_transcode_stream_with_budget,
+            detach_thread: img_detach_thread,
+            attach_thread: img_attach_thread,
+            apply: img_apply,
+            open_image_memory: img_open_memory,
+            set_thread_count: img_set_thread_count,
+            submit: img_submit,
+            job_status: img_job_status,
+            job_result: img_job_result,
+            job_destroy: img_job_destroy,
+            blur_with_progress: img_blur_with_progress,
+            resize_with_progress: img_resize_with_progress,
+            encode_with_progress: img_encode_with_progress,
+            token_create: img_token_create,
+            token_cancel: img_token_cancel,
+            token_destroy: img_token_destroy,
+            submit_with_token: img_submit_with_token,
+            batch: img_batch,
+            pipeline_create: img_pipeline_create,
+            pipeline_destroy: img_pipeline_destroy,
+            pipeline_add_crop: img_pipeline_add_crop,
+            pipeline_add_resize: img_pipeline_add_resize,
+            pipeline_add_blur: img_pipeline_add_blur,
+            pipeline_add_mirror: img_pipeline_add_mirror,
+            pipeline_execute: img_pipeline_execute,
+            blur_into: img_blur_into,
+            resize_into: img_resize_into,
+            set_pool_limit: img_set_pool_limit,
+            pool_trim: img_pool_trim,
+            clone_cow: img_clone_cow,
+            freeze: img_freeze,
+            pipeline_execute_tiled: img_pipeline_execute_tiled,
+            apply_preview: img_apply_preview,
+            info: img_info,
+            lock_pixels: img_lock_pixels,
+            unlock_pixels: img_unlock_pixels,
+            get_pixel: img_get_pixel,
+            set_pixel: img_set_pixel,
+            view: img_view,
+            convert: img_convert,
+            lock_pixels_as: img_lock_pixels_as,
+            read_pixels: img_read_pixels,
+            map_pixels: img_map_pixels,
+            for_each_row: img_for_each_row,
+            clone: img_clone,
+            new: img_new,
+            swap_channels: img_swap_channels,
+            histogram: img_histogram,
+            histogram16: img_histogram16,
+            stats: img_stats,
+            dominant_colors: img_dominant_colors,
+            average_color: img_average_color,
+            phash: img_phash,
+            hash_distance: img_hash_distance,
+            blurhash: img_blurhash,
+            thumbhash: img_thumbhash,
+            thumbhash_decode: img_thumbhash_decode,
+            compare: img_compare,
+            diff: img_diff,
+            trim: img_trim,
+            is_blank: img_is_blank,
+            smart_crop: img_smart_crop,
+            sharpness: img_sharpness,
+            draw_line: img_draw_line,
+            draw_rect: img_draw_rect,
+            draw_circle: img_draw_circle,
+            draw_polygon: img_draw_polygon,
+            gradient: img_gradient,
+            checkerboard: img_checkerboard,
+            color_bars: img_color_bars,
+            watermark: img_watermark,
+            pack_sprites: img_pack_sprites,
+            noise: img_noise,
+            round_corners: img_round_corners,
+            circle_mask: img_circle_mask,
+            apply_mask: img_apply_mask,
+            save_cmyk_tiff: img_save_cmyk_tiff,
+            pipeline_execute_with_budget: img_pipeline_execute_with_budget,
+            pipeline_execute_tiled_with_budget: img_pipeline_execute_tiled_with_budget,
+            transcode_stream2: img_transcode_stream2,
+            enable_cache: image_sl_enable_cache,
+            pipeline_transcode: img_pipeline_transcode,
+            queue_create: img_queue_create,
+            queue_destroy: img_queue_destroy,
+            submit_on: img_submit_on,
+        }
+    }
+}
+
+/// Functions of the first release of library returned by deprecated `functions()`. Hosts built
+/// against it reserve space of this struct for returned value, so its layout is frozen and new
+/// functions are only added to `FunctionsBlock` of `functions_ex()`.
+#[allow(unused)]
+#[repr(C)]
+pub struct LegacyFunctionsBlock {
+    size: usize,
+    open_image: OpenImageFn,
+    save_image: SaveImageFn,
+    destroy_image: DestroyImageFn,
+    blur_image: BlurImageFn,
+    mirror_image: MirrorImageFn,
+}
+
+// Hosts of the first release expect table of 6 pointer-sized fields.
+const _: () =
+    assert!(std::mem::size_of::<LegacyFunctionsBlock>() == 6 * std::mem::size_of::<usize>());
+
+/// Returns functions of the first release of this library. Deprecated: use `functions_ex()`,
+/// which gives all functions of ABI version host was built against.
+#[no_mangle]
+#[deprecated(note = "use functions_ex(), which gives all functions of requested ABI version")]
+pub extern "C" fn functions() -> LegacyFunctionsBlock {
+    LegacyFunctionsBlock {
+        size: std::mem::size_of::<LegacyFunctionsBlock>(),
+        open_image: img_open,
+        save_image: img_save,
+        destroy_image: img_destroy,
+        blur_image: img_blur,
+        mirror_image: img_mirror,
+    }
+}
+
+/// The latest ABI version of `FunctionsV2` table provided by library.
+const ABI_VERSION: u32 = 59;
+
+/// Number of functions in `FunctionsV2` table of each ABI version starting from 1. Table of
+/// every version is prefix of table of the next one: functions are only appended to the end of
+/// `FunctionsBlock`, never reordered or removed, and appending them requires new ABI version.
+const ABI_FUNCTION_COUNTS: [usize; ABI_VERSION as usize] = [
+    88, 91, 93, 94, 96, 97, 100, 102, 103, 104, 105, 109, 112, 116, 117, 124, 126, 128, 129, 130,
+    131, 132, 133, 135, 137, 138, 139, 140, 141, 142, 143, 144, 145, 146, 148, 149, 150, 151, 153,
+    154, 156, 157, 158, 160, 161, 162, 166, 167, 169, 170, 171, 172, 174, 175, 176, 178, 179, 181,
+    184,
+];
+
+// Functions appended without new ABI version fail to compile.
+const _: () = assert!(
+    std::mem::size_of::<FunctionsBlock>()
+        == (1 + ABI_FUNCTION_COUNTS[ABI_VERSION as usize - 1]) * std::mem::size_of::<usize>()
+);
+
+/// Names of fields of `FunctionsBlock` in order, which `img_has_fn` looks functions up by.
+const FUNCTION_NAMES: [&str; ABI_FUNCTION_COUNTS[ABI_VERSION as usize - 1]] = [
+    "open_image",
+    "save_image",
+    "destroy_image",
+    "blur_image",
+    "mirror_image",
+    "encode_image",
+    "open_image_with_reader",
+    "save_image_with_writer",
+    "transcode_stream",
+    "open_image_fd",
+    "save_image_fd",
+    "open_image_data_uri",
+    "encode_image_data_uri",
+    "open_image_url",
+    "open_image_with_options",
+    "set_limits",
+    "pack_eink",
+    "pack_framebuffer",
+    "image_from_raw",
+    "suggest_crops",
+    "exif_get",
+    "draw_guides",
+    "estimate_noise",
+    "exif_set",
+    "auto_orient",
+    "auto_denoise",
+    "auto_sharpen",
+    "correct_ca",
+    "icc_get",
+    "icc_set",
+    "convert_to_srgb",
+    "defringe",
+    "get_dpi",
+    "set_dpi",
+    "recover_highlights",
+    "png_text_keys",
+    "png_text_get",
+    "png_text_set",
+    "shadows_highlights",
+    "hsl_adjust",
+    "xmp_get",
+    "iptc_get",
+    "replace_color",
+    "strip_metadata",
+    "exif_thumbnail",
+    "split_tone",
+    "film_emulation",
+    "last_error_message",
+    "set_journal_depth",
+    "undo",
+    "redo",
+    "history_len",
+    "last_error_details",
+    "export_edit_stack",
+    "apply_edit_stack",
+    "free_string",
+    "set_log_callback",
+    "add_annotation",
+    "annotation_count",
+    "get_annotation",
+    "remove_annotation",
+    "burn_annotations",
+    "doc_new",
+    "doc_destroy",
+    "doc_add_layer",
+    "doc_layer_count",
+    "doc_get_layer",
+    "doc_set_layer",
+    "doc_layer_image",
+    "doc_remove_layer",
+    "doc_move_layer",
+    "doc_flatten",
+    "is_valid",
+    "doc_open_ora",
+    "doc_save_ora",
+    "doc_open_psd",
+    "set_debug_handles",
+    "live_handle_count",
+    "dump_leaks",
+    "blur_image2",
+    "mirror_image2",
+    "match_template",
+    "detect_keypoints",
+    "free_keypoints",
+    "match_keypoints",
+    "free_matches",
+    "align",
+    "mesh_warp",
+    "export_profile_create",
+    "export_profile_destroy",
+    "export",
+    "capabilities",
+    "has_fn",
+    "generate_icon_set",
+    "register_decoder",
+    "register_encoder",
+    "compose_card",
+    "open_with_budget",
+    "save_with_budget",
+    "transcode_stream_with_budget",
+    "detach_thread",
+    "attach_thread",
+    "apply",
+    "open_image_memory",
+    "set_thread_count",
+    "submit",
+    "job_status",
+    "job_result",
+    "job_destroy",
+    "blur_with_progress",
+    "resize_with_progress",
+    "encode_with_progress",
+    "token_create",
+    "token_cancel",
+    "token_destroy",
+    "submit_with_token",
+    "batch",
+    "pipeline_create",
+    "pipeline_destroy",
+    "pipeline_add_crop",
+    "pipeline_add_resize",
+    "pipeline_add_blur",
+    "pipeline_add_mirror",
+    "pipeline_execute",
+    "blur_into",
+    "resize_into",
+    "set_pool_limit",
+    "pool_trim",
+    "clone_cow",
+    "freeze",
+    "pipeline_execute_tiled",
+    "apply_preview",
+    "info",
+    "lock_pixels",
+    "unlock_pixels",
+    "get_pixel",
+    "set_pixel",
+    "view",
+    "convert",
+    "lock_pixels_as",
+    "read_pixels",
+    "map_pixels",
+    "for_each_row",
+    "clone",
+    "new",
+    "swap_channels",
+    "histogram",
+    "histogram16",
+    "stats",
+    "dominant_colors",
+    "average_color",
+    "phash",
+    "hash_distance",
+    "blurhash",
+    "thumbhash",
+    "thumbhash_decode",
+    "compare",
+    "diff",
+    "trim",
+    "is_blank",
+    "smart_crop",
+    "sharpness",
+    "draw_line",
+    "draw_rect",
+    "draw_circle",
+    "draw_polygon",
+    "gradient",
+    "checkerboard",
+    "color_bars",
+    "watermark",
+    "pack_sprites",
+    "noise",
+    "round_corners",
+    "circle_mask",
+    "apply_mask",
+    "save_cmyk_tiff",
+    "pipeline_execute_with_budget",
+    "pipeline_execute_tiled_with_budget",
+    "transcode_stream2",
+    "enable_cache",
+    "pipeline_transcode",
+    "queue_create",
+    "queue_destroy",
+    "submit_on",
+];
+
+/// `img_capabilities` flag: `img_open_url` downloads images (`http` feature).
+const CAPABILITY_HTTP: u32 = 1;
+/// `img_capabilities` flag: `img_convert_to_srgb` applies ICC profiles (`icc` feature).
+const CAPABILITY_ICC: u32 = 1 << 1;
+/// `img_capabilities` flag: files are decoded through memory mapping (`mmap` feature).
+const CAPABILITY_MMAP: u32 = 1 << 2;
+/// `img_capabilities` flag: trace spans are reported to log callback (`tracing` feature).
+const CAPABILITY_TRACING: u32 = 1 << 3;
+/// `img_capabilities` flag: leak reports have backtraces (`backtrace` feature).
+const CAPABILITY_BACKTRACE: u32 = 1 << 4;
+/// `img_capabilities` flag: blur and resize run on worker threads (`parallel` feature).
+const CAPABILITY_PARALLEL: u32 = 1 << 5;
+
+/// Table of library functions of negotiated ABI version written by `functions_ex`.
+#[repr(C)]
+pub struct FunctionsV2 {
+    /// ABI version of table, the same as requested one.
+    abi_version: u32,
+    /// Size of table of `abi_version` in bytes.
+    size: usize,
+    /// Functions of `abi_version`. Its `size` is size of functions of `abi_version` in bytes.
+    functions: FunctionsBlock,
+}
+
+/// Writes table of functions of `requested_abi` version, which host was built against, to `out`.
+/// Only fields of requested version are written, so hosts built against older versions of
+/// library keep working with newer ones. Returns `ImageError::Unsupported` if library is older
+/// than requested version.
+/// # Safety
+/// `out` is valid pointer to writable `FunctionsV2` of `requested_abi` version.
+#[no_mangle]
+pub unsafe extern "C" fn functions_ex(requested_abi: u32, out: *mut FunctionsV2) -> ImageError {
+    guard(|| {
+        if out.is_null() {
+            return ImageError::Parameter;
+        }
+        if requested_abi == 0 || requested_abi > ABI_VERSION {
+            let message = format!(
+                "Requested ABI version {}, library supports versions 1 to {}",
+                requested_abi, ABI_VERSION
+            );
+            set_last_error(LastError::new(ImageError::Unsupported, message));
+            return ImageError::Unsupported;
+        }
+
+        let count = ABI_FUNCTION_COUNTS[requested_abi as usize - 1];
+        let functions_size = (1 + count) * std::mem::size_of::<usize>();
+        let size = std::mem::offset_of!(FunctionsV2, functions) + functions_size;
+        let mut table = FunctionsV2 {
+            abi_version: requested_abi,
+            size,
+            functions: FunctionsBlock::default(),
+        };
+        table.functions.size = functions_size;
+        let table = &table as *const FunctionsV2 as *const u8;
+        std::ptr::copy_nonoverlapping(table, out as *mut u8, size);
+        ImageError::NoError
+    })
+}
+
+/// Version of library, so hosts loading it at runtime can check they got expected build.
+#[repr(C)]
+pub struct Version {
+    major: u32,
+    minor: u32,
+    patch: u32,
+    /// Static null-terminated UTF-8 description of build: version, git commit and enabled Cargo
+    /// features, e.g. `"0.1.0 (git 1a2b3c4, features: http, icc)"`.
+    build: *const c_char,
+}
+
+/// Returns version of this library.
+#[no_mangle]
+pub extern "C" fn version() -> Version {
+    let part = |part: &str| part.parse().unwrap_or(0);
+    Version {
+        major: part(env!("CARGO_PKG_VERSION_MAJOR")),
+        minor: part(env!("CARGO_PKG_VERSION_MINOR")),
+        patch: part(env!("CARGO_PKG_VERSION_PATCH")),
+        build: concat!(env!("IMAGE_SL_BUILD"), "\0").as_ptr() as *const c_char,
+    }
+}
+
+/// Global settings of library applied by `image_sl_init`. `size` field contain size of this
+/// struct.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct InitConfig {
+    size: usize,
+    /// Maximum number of worker threads running blur and resize, `0` means number of CPUs and `1`
+    /// runs them on calling thread.
+    thread_count: u32,
+    /// Limits applied to every decoded image, the same as of `img_set_limits`. Zero means no
+    /// limit.
+    max_width: u32,
+    max_height: u32,
+    max_memory: u64,
+    /// Log callback with its user data, the same as of `img_set_log_callback`. Null means no
+    /// callback.
+    log_callback: Option<LogFn>,
+    log_user_data: *mut c_void,
+    /// Enables debug registry of destroyed image handles, the same as `img_set_debug_handles`.
+    debug_handles: bool,
+    /// File trace of the last operations is written to, as by `image_sl_dump_trace`, when library
+    /// catches panic. Null means no automatic dumps.
+    trace_path: RawPath,
+}
+
+unsafe impl Versioned for InitConfig {}
+
+impl Default for InitConfig {
+    fn default() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            thread_count: 0,
+            max_width: 0,
+            max_height: 0,
+            max_memory: 0,
+            log_callback: None,
+            log_user_data: std::ptr::null_mut(),
+            debug_handles: false,
+            trace_path: RawPath(std::ptr::null()),
+        }
+    }
+}
+
+/// Applies global settings of `config` at once, e.g. when host loads library. Null `config`
+/// or zero `size` means default settings. Calling it again replaces all settings. Returns
+/// `ImageError::Parameter` if `size` of `config` is larger than size of `InitConfig`.
+/// # Safety
+/// - `config` is null or valid pointer to `InitConfig`.
+/// - Log callback of `config` and its user data are usable from any thread until callback is
+///   replaced.
+#[no_mangle]
+pub unsafe extern "C" fn image_sl_init(config: *const InitConfig) -> ImageError {
+    guard(|| {
+        let config = match InitConfig::from_ptr(config) {
+            Ok(c) => c,
+            Err(e) => return e,
+        };
+
+        let trace_path = match config.trace_path.0.is_null() {
+            true => None,
+            false => match <&Path>::try_from(&config.trace_path) {
+                Ok(p) => Some(p.to_path_buf()),
+                Err(e) => return e,
+            },
+        };
+
+        decode::set_limits(config.max_width, config.max_height, config.max_memory);
+        handles::set_enabled(config.debug_handles);
+        trace::set_dump_path(trace_path);
+        parallel::set_thread_count(config.thread_count as usize);
+        log::set_callback(config.log_callback, config.log_user_data);
+        let version = env!("CARGO_PKG_VERSION");
+        log::write(LogLevel::Info, &format!("image_sl {} initialized", version));
+        ImageError::NoError
+    })
+}
+
+/// Releases global resources of library before host unloads it: removes log callback, limits,
+/// codec plugins, debug registry of image handles and trace dump path, disables result cache,
+/// stops worker threads and clears the last error of calling thread. Live images are reported to log as leaks and stay
+/// valid, but should be destroyed before unloading.
+#[no_mangle]
+pub extern "C" fn image_sl_shutdown() {
+    guard(|| {
+        let live = handles::live_count();
+        if live != 0 {
+            log::warn(&format!("image_sl shut down with {} live images", live));
+        }
+        log::set_callback(None, std::ptr::null_mut());
+        decode::set_limits(0, 0, 0);
+        codec::clear();
+        handles::set_enabled(false);
+        trace::set_dump_path(None);
+        parallel::shutdown();
+        pool::set_limit(0);
+        cache::disable();
+        LAST_ERROR.with(|last| *last.borrow_mut() = None);
+    })
+}
+
+/// Writes trace of the last operations of every running thread to file at `path`, one per line
+/// with its thread, start time and duration. Operations which haven't finished are marked as
+/// unfinished, so trace dumped after host crashed inside library shows what it was doing.
+/// Library remembers 64 last operations per thread.
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn image_sl_dump_trace(path: *const c_char) -> ImageError {
+    guard(|| {
+        if path.is_null() {
+            return ImageError::Parameter;
+        }
+        let path = RawPath(path);
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        match trace::dump(path) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => io_error(e),
+        }
+    })
+}
+
+/// Enables cache of results of `img_pipeline_transcode` in directory at `dir`, created if
+/// needed, keeping up to `max_bytes` of encoded results there. Results are keyed by SHA-256
+/// digest of input and description of pipeline and output format, so repeated identical
+/// requests, e.g. of web service, return cached encoding without decoding input. Files of the
+/// least recently used results are removed to fit new ones, and results already in `dir` are
+/// reused. Null `dir` or zero `max_bytes` disables cache, leaving its files in place, which is
+/// the default. Size of cache is tracked by this process only, so other processes shouldn't
+/// write to `dir`.
+/// # Safety
+/// `dir` is null or valid pointer to null-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn image_sl_enable_cache(dir: *const c_char, max_bytes: u64) -> ImageError {
+    guard(|| {
+        if dir.is_null() || max_bytes == 0 {
+            cache::disable();
+            return ImageError::NoError;
+        }
+        let dir = RawPath(dir);
+        let dir: &Path = match (&dir).try_into() {
+            Ok(d) => d,
+            Err(e) => return e,
+        };
+        match cache::enable(dir, max_bytes) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Allocates `len` bytes in memory of WebAssembly module, e.g. for JavaScript host to write
+/// encoded image passed to `img_open_memory` or to receive output of `img_encode`. Returns null if
+/// `len` is zero or memory is exhausted. Memory is freed by `image_sl_free`.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub extern "C" fn image_sl_alloc(len: usize) -> *mut u8 {
+    match std::alloc::Layout::array::<u8>(len) {
+        Ok(layout) if len != 0 => unsafe { std::alloc::alloc(layout) },
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Frees `len` bytes at `ptr` allocated by `image_sl_alloc`. Null `ptr` is ignored.
+/// # Safety
+/// `ptr` is null or pointer returned by `image_sl_alloc(len)` and not freed yet.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub unsafe extern "C" fn image_sl_free(ptr: *mut u8, len: usize) {
+    if let (false, Ok(layout)) = (ptr.is_null(), std::alloc::Layout::array::<u8>(len)) {
+        std::alloc::dealloc(ptr, layout);
+    }
+}
+
+// Exported functions
+
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `handle` is valid pointer to `void*`.
+unsafe extern "C" fn img_open(path: RawPath, handle: *mut ImageHandle) -> ImageError {
+    guard(|| {
+        if handle.is_null() || path.0.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let img = match open_file(path, &OpenOptions::default()) {
+            Ok(i) => i,
+            Err(e) => return e,
+        };
+
+        *handle = ImageHandle::from_data(img);
+        ImageError::NoError
+    })
+}
+
+/// Loads image from file at `path` respecting `options`. If `options` is null, default options
+/// are used. Without format hint, format is deduced from extension or, if it is unknown,
+/// from content.
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `options` is null or valid pointer to `OpenOptions`.
+/// - `handle` is valid pointer to `void*`.
+unsafe extern "C" fn img_open_with_options(
+    path: RawPath,
+    options: *const OpenOptions,
+    handle: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if handle.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let options = match OpenOptions::from_ptr(options) {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+
+        match open_file(path, &options) {
+            Ok(img) => {
+                *handle = ImageHandle::from_data(img);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Sets limits applied to every decoded image in addition to `OpenOptions` limits.
+/// Decoding of images exceeding limits fails with `ImageError::LimitExceeded`.
+/// Zero means no limit. There are no limits by default.
+unsafe extern "C" fn img_set_limits(max_width: u32, max_height: u32, max_memory: u64) {
+    guard(|| {
+        decode::set_limits(max_width, max_height, max_memory);
+    })
+}
+
+/// Sets maximum number of worker threads running blur and resize, the same as `thread_count` of
+/// `InitConfig`: `0` means number of CPUs and `1` runs them on calling thread, e.g. if host
+/// already keeps all cores busy. Running operations keep their threads.
+unsafe extern "C" fn img_set_thread_count(count: u32) {
+    guard(|| parallel::set_thread_count(count as usize))
+}
+
+/// Enables pool of temporary buffers of blur and resize keeping up to `max_bytes` of idle
+/// buffers, so hosts processing many small images reuse them instead of allocating them on
+/// every call. `0` disables pool and frees its buffers, which is the default. Buffers returned
+/// to full pool are freed.
+unsafe extern "C" fn img_set_pool_limit(max_bytes: u64) {
+    guard(|| pool::set_limit(usize::try_from(max_bytes).unwrap_or(usize::MAX)))
+}
+
+/// Frees idle buffers of pool of temporary buffers, e.g. once burst of work is over, keeping
+/// it enabled. Returns number of freed bytes.
+unsafe extern "C" fn img_pool_trim() -> u64 {
+    guard(|| pool::trim() as u64)
+}
+
+/// Loads image from host-provided stream. If `seek_fn` is null, the whole stream is read into
+/// memory before decoding.
+/// # Safety
+/// - `read_fn` and `seek_fn` are safe to call with `user_data`.
+/// - `handle` is valid pointer to `void*`.
+unsafe extern "C" fn img_open_with_reader(
+    read_fn: ReadFn,
+    seek_fn: Option<SeekFn>,
+    user_data: *mut c_void,
+    handle: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if handle.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let mut reader = CallbackReader {
+            read_fn,
+            seek_fn,
+            user_data,
+        };
+
+        let img = match seek_fn {
+            Some(_) => decode(BufReader::new(reader)),
+            None => {
+                let mut data = Vec::new();
+                if let Err(e) = reader.read_to_end(&mut data) {
+                    return io_error(e);
+                }
+                decode(Cursor::new(data))
+            }
+        };
+
+        match img {
+            Ok(img) => {
+                *handle = ImageHandle::from_data(img);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `handle` is valid image handle.
+unsafe extern "C" fn img_save(path: RawPath, handle: ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if path.0.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        // Codec plugins registered for extension take precedence over built-in formats.
+        let data = handle.read();
+        let extension = path.extension().and_then(|e| e.to_str());
+        if let Some(encoder) = extension.and_then(codec::encoder) {
+            let file = match std::fs::File::create(path) {
+                Ok(f) => f,
+                Err(e) => return io_error(e),
+            };
+            let mut writer = BufWriter::new(file);
+            if let Err(e) = encoder.encode(&data, &mut writer) {
+                return e;
+            }
+            return match writer.flush() {
+                Ok(_) => ImageError::NoError,
+                Err(e) => io_error(e),
+            };
+        }
+
+        // Formats are guessed from extension the same way `DynamicImage::save` does it.
+        let format = image::ImageFormat::from_path(path)
+            .ok()
+            .and_then(|f| ImageFormat::try_from(f).ok());
+        let format = match format {
+            Some(format) if data.metadata.needs_embedding(false) => format,
+            _ => {
+                return match data.image.save(path) {
+                    Ok(_) => ImageError::NoError,
+                    Err(e) => e.into(),
+                }
+            }
+        };
+
+        let encoded = match encode_data(&data, format, &SaveOptions::default()) {
+            Ok(e) => e,
+            Err(e) => return e,
+        };
+        match std::fs::write(path, encoded) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => io_error(e),
+        }
+    })
+}
+
+/// Saves image as uncompressed CMYK TIFF file for print workflows, embedding ICC profile of
+/// `icc_len` bytes at `icc` unless it's null, e.g. profile of press the file is sent to. Colors
+/// are separated naively: black is the complement of the brightest channel, the rest of ink is
+/// split between cyan, magenta and yellow, and alpha is composited over white paper.
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `handle` is valid image handle.
+/// - `icc` is null or valid pointer to `icc_len` bytes.
+unsafe extern "C" fn img_save_cmyk_tiff(
+    path: RawPath,
+    handle: ImageHandle,
+    icc: *const u8,
+    icc_len: usize,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("save_cmyk_tiff");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if path.0.is_null() {
+            return ImageError::Parameter;
+        }
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let icc = match icc.is_null() {
+            true => None,
+            false => Some(std::slice::from_raw_parts(icc, icc_len)),
+        };
+        match tiled::save_cmyk(&handle.read().image, icc, path) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Encodes image in `format` (one of `ImageFormat` values) and streams encoded bytes to
+/// `write_fn` chunk by chunk. If `options` is null, default options are used.
+/// # Safety
+/// - `write_fn` is safe to call with `user_data`.
+/// - `handle` is valid image handle.
+/// - `options` is null or valid pointer to `SaveOptions`.
+unsafe extern "C" fn img_save_with_writer(
+    write_fn: WriteFn,
+    user_data: *mut c_void,
+    handle: ImageHandle,
+    format: u32,
+    options: *const SaveOptions,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+
+        let format = match ImageFormat::try_from(format) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+
+        let options = match SaveOptions::from_ptr(options) {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+
+        let writer = CallbackWriter {
+            write_fn,
+            user_data,
+        };
+        let mut writer = BufWriter::new(writer);
+
+        if let Err(e) = write_encoded(&handle.read(), format, &options, &mut writer) {
+            return e;
+        }
+
+        match writer.flush() {
+            Ok(_) => ImageError::NoError,
+            Err(e) => io_error(e),
+        }
+    })
+}
+
+/// Encodes image in `format` (one of `ImageFormat` values) into `buffer` of `*len` bytes.
+/// Writes encoded size to `len`. If `buffer` is null, only the required size is written, so
+/// caller can allocate buffer and call this function again. Returns `ImageError::BufferTooSmall`
+/// if `*len` is less than the required size.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_encode(
+    handle: ImageHandle,
+    format: u32,
+    buffer: *mut u8,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let format = match ImageFormat::try_from(format) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+
+        let encoded = match encode_data(&handle.read(), format, &SaveOptions::default()) {
+            Ok(e) => e,
+            Err(e) => return e,
+        };
+
+        write_to_buffer(&encoded, buffer, len)
+    })
+}
+
+/// Loads image from file descriptor (`HANDLE` on Windows) `fd`. Descriptor stays owned by caller.
+/// Non-seekable descriptors (pipes, sockets) are read into memory before decoding.
+/// # Safety
+/// - `fd` is valid readable file descriptor.
+/// - `handle` is valid pointer to `void*`.
+#[cfg(any(unix, windows))]
+unsafe extern "C" fn img_open_fd(fd: OsHandle, handle: *mut ImageHandle) -> ImageError {
+    guard(|| {
+        if handle.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let mut file = borrow_file(fd);
+        let img = match file.stream_position() {
+            Ok(_) => decode(BufReader::new(&*file)),
+            Err(_) => {
+                let mut data = Vec::new();
+                if let Err(e) = file.read_to_end(&mut data) {
+                    return io_error(e);
+                }
+                decode(Cursor::new(data))
+            }
+        };
+
+        match img {
+            Ok(img) => {
+                *handle = ImageHandle::from_data(img);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Saves image encoded in `format` (one of `ImageFormat` values) to file descriptor
+/// (`HANDLE` on Windows) `fd`. Descriptor stays owned by caller. If `options` is null,
+/// default options are used.
+/// # Safety
+/// - `fd` is valid writable file descriptor.
+/// - `handle` is valid image handle.
+/// - `options` is null or valid pointer to `SaveOptions`.
+#[cfg(any(unix, windows))]
+unsafe extern "C" fn img_save_fd(
+    fd: OsHandle,
+    handle: ImageHandle,
+    format: u32,
+    options: *const SaveOptions,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+
+        let format = match ImageFormat::try_from(format) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+
+        let options = match SaveOptions::from_ptr(options) {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+
+        let file = borrow_file(fd);
+        let mut writer = BufWriter::new(&*file);
+
+        if let Err(e) = write_encoded(&handle.read(), format, &options, &mut writer) {
+            return e;
+        }
+
+        match writer.flush() {
+            Ok(_) => ImageError::NoError,
+            Err(e) => io_error(e),
+        }
+    })
+}
+
+/// File handles are available only on Unix and Windows.
+#[cfg(not(any(unix, windows)))]
+unsafe extern "C" fn img_open_fd(_fd: OsHandle, _handle: *mut ImageHandle) -> ImageError {
+    guard(no_files)
+}
+
+/// File handles are available only on Unix and Windows.
+#[cfg(not(any(unix, windows)))]
+unsafe extern "C" fn img_save_fd(
+    _fd: OsHandle,
+    _handle: ImageHandle,
+    _format: u32,
+    _options: *const SaveOptions,
+) -> ImageError {
+    guard(no_files)
+}
+
+/// Loads image from `data:image/...;base64,` URI.
+/// # Safety
+/// - `uri` is valid pointer to null-terminated UTF-8 string.
+/// - `handle` is valid pointer to `void*`.
+unsafe extern "C" fn img_open_data_uri(uri: RawStr, handle: *mut ImageHandle) -> ImageError {
+    guard(|| {
+        if handle.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let uri: &str = match (&uri).try_into() {
+            Ok(u) => u,
+            Err(e) => return e,
+        };
+
+        let payload = match uri.strip_prefix("data:").and_then(|u| u.split_once(',')) {
+            Some((media_type, payload)) if media_type.ends_with(";base64") => payload,
+            _ => return ImageError::Parameter,
+        };
+
+        let data = match base64::engine::general_purpose::STANDARD.decode(payload) {
+            Ok(d) => d,
+            Err(_) => return ImageError::Decoding,
+        };
+
+        match decode(Cursor::new(data)) {
+            Ok(img) => {
+                *handle = ImageHandle::from_data(img);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Encodes image in `format` (one of `ImageFormat` values) into null-terminated base64 data URI
+/// written to `buffer` of `*len` bytes. Writes URI size including terminating null to `len`.
+/// Size query works the same way as in `img_encode`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_encode_data_uri(
+    handle: ImageHandle,
+    format: u32,
+    buffer: *mut c_char,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let format = match ImageFormat::try_from(format) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+
+        let encoded = match encode_data(&handle.read(), format, &SaveOptions::default()) {
+            Ok(e) => e,
+            Err(e) => return e,
+        };
+
+        let mut uri = format!("data:{};base64,", format.mime_type());
+        base64::engine::general_purpose::STANDARD.encode_string(&encoded, &mut uri);
+        uri.push('\0');
+
+        write_to_buffer(uri.as_bytes(), buffer as *mut u8, len)
+    })
+}
+
+/// Downloads and loads image from HTTP(S) `url`. Request fails if it takes longer than
+/// `timeout_ms` milliseconds or response body is larger than `max_bytes`. Zero `timeout_ms` or
+/// `max_bytes` means no limit. Returns `ImageError::Unsupported` if library is built without
+/// `http` feature.
+/// # Safety
+/// - `url` is valid pointer to null-terminated UTF-8 string.
+/// - `handle` is valid pointer to `void*`.
+unsafe extern "C" fn img_open_url(
+    url: RawStr,
+    timeout_ms: u32,
+    max_bytes: usize,
+    handle: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if handle.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let url: &str = match (&url).try_into() {
+            Ok(u) => u,
+            Err(e) => return e,
+        };
+
+        let data = match fetch(url, timeout_ms, max_bytes) {
+            Ok(d) => d,
+            Err(e) => return e,
+        };
+
+        match decode(Cursor::new(data)) {
+            Ok(img) => {
+                *handle = ImageHandle::from_data(img);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Decodes image from `read_fn`, applies `count` operations from `ops` in order and streams
+/// image encoded in source format to `write_fn`. Input is read as `img_transcode_stream2` reads
+/// it, limited by memory limit of `img_set_limits` only. Deprecated: use
+/// `img_transcode_stream2`, which encodes in given format, as source formats like WebP can't be
+/// encoded.
+/// # Safety
+/// - `read_fn` and `write_fn` are safe to call with `user_data`.
+/// - `ops` is valid pointer to `count` operations. May be null if `count` is zero.
+unsafe extern "C" fn img_transcode_stream(
+    read_fn: ReadFn,
+    write_fn: WriteFn,
+    ops: *const Operation,
+    count: usize,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| transcode(read_fn, write_fn, ops, count, None, 0, user_data))
+}
+
+/// Decodes image from `read_fn`, applies `count` operations from `ops` in order and streams
+/// image encoded in `format` (one of `ImageFormat` values) to `write_fn`. Input is buffered in
+/// memory, because decoders need random access, and read only as fast as decoder can take it:
+/// input larger than `max_input` bytes, or than memory limit of `img_set_limits` if it is zero,
+/// fails with `ImageError::LimitExceeded` once that much is read, and buffered input is
+/// counted against budget of `img_transcode_stream_with_budget`. Output is passed to
+/// `write_fn` through a fixed-size buffer.
+/// # Safety
+/// - `read_fn` and `write_fn` are safe to call with `user_data`.
+/// - `ops` is valid pointer to `count` operations. May be null if `count` is zero.
+unsafe extern "C" fn img_transcode_stream2(
+    read_fn: ReadFn,
+    write_fn: WriteFn,
+    ops: *const Operation,
+    count: usize,
+    format: u32,
+    max_input: u64,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        let format = match ImageFormat::try_from(format) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+        transcode(
+            read_fn,
+            write_fn,
+            ops,
+            count,
+            Some(format),
+            max_input,
+            user_data,
+        )
+    })
+}
+
+/// Transcodes stream as `img_transcode_stream2` does, encoding image in source format if
+/// `format` is `None`.
+/// # Safety
+/// Arguments are valid for `img_transcode_stream2`.
+unsafe fn transcode(
+    read_fn: ReadFn,
+    write_fn: WriteFn,
+    ops: *const Operation,
+    count: usize,
+    format: Option<ImageFormat>,
+    max_input: u64,
+    user_data: *mut c_void,
+) -> ImageError {
+    if ops.is_null() && count != 0 {
+        return ImageError::Parameter;
+    }
+
+    let reader = CallbackReader {
+        read_fn,
+        seek_fn: None,
+        user_data,
+    };
+
+    let data = match read_input(reader, max_input) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+
+    let (mut img, source_format) = match decode_input(data) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+
+    let format = match format.map_or_else(|| ImageFormat::try_from(source_format), Ok) {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+
+    let ops = match count {
+        0 => &[],
+        _ => std::slice::from_raw_parts(ops, count),
+    };
+
+    for op in ops {
+        if let Err(e) = budget::check_time().and_then(|_| op.apply(&mut img)) {
+            return e;
+        }
+    }
+
+    let writer = CallbackWriter {
+        write_fn,
+        user_data,
+    };
+    let mut writer = BufWriter::new(writer);
+
+    if let Err(e) = img.write_to(&mut writer, format) {
+        return e.into();
+    }
+
+    match writer.flush() {
+        Ok(_) => ImageError::NoError,
+        Err(e) => io_error(e),
+    }
+}
+
+/// Decodes image of transcoded input `data` with default options, returning it with its format.
+fn decode_input(data: Vec<u8>) -> Result<(DynamicImage, image::ImageFormat), ImageError> {
+    let reader = image::io::Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(io_error)?;
+    let format = reader.format().ok_or(ImageError::Unsupported)?;
+    let image = decode::load(reader.into_inner(), format, &OpenOptions::default())?;
+    Ok((image, format))
+}
+
+/// Reads whole input of transcoding from `reader`. Input larger than `max_bytes`, or than
+/// memory limit of `img_set_limits` if it is zero, fails with `ImageError::LimitExceeded`, and
+/// every read chunk is counted against budget of operation before it is buffered.
+fn read_input<R: Read>(mut reader: R, max_bytes: u64) -> Result<Vec<u8>, ImageError> {
+    let limit = match max_bytes {
+        0 => decode::max_memory(),
+        max => max,
+    };
+    let mut data = Vec::new();
+    let mut chunk = [0; 16 * 1024];
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => return Ok(data),
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(io_error(e)),
+        };
+        if limit != 0 && (data.len() + read) as u64 > limit {
+            let message = format!("Input exceeds limit of {} bytes", limit);
+            set_last_error(LastError::new(ImageError::LimitExceeded, message));
+            return Err(ImageError::LimitExceeded);
+        }
+        check_alloc(read as u64)?;
+        if data.try_reserve(read).is_err() {
+            return Err(out_of_memory(data.len() + read));
+        }
+        data.extend_from_slice(&chunk[..read]);
+    }
+}
+
+/// Creates image of `width` x `height` pixels by copying raw pixel `data` in `format` (one of
+/// `RawPixelFormat` values). Rows of `data` are tightly packed. Writes handle of created image
+/// to `handle`.
+/// # Safety
+/// - `data` is valid pointer to `width * height * pixel_size` readable bytes.
+/// - `handle` is valid pointer to `ImageHandle`.
+unsafe extern "C" fn img_from_raw(
+    data: *const u8,
+    width: u32,
+    height: u32,
+    format: u32,
+    handle: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if data.is_null() || handle.is_null() || width == 0 || height == 0 {
+            return ImageError::Parameter;
+        }
+
+        let format = match RawPixelFormat::try_from(format) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(format.pixel_size()));
+        let len = match len {
+            Some(l) if l <= isize::MAX as usize => l,
+            _ => return ImageError::Parameter,
+        };
+        if let Err(e) = check_alloc(len as u64) {
+            return e;
+        }
+
+        let data = std::slice::from_raw_parts(data, len);
+        match format.create_image(width, height, data) {
+            Some(img) => {
+                *handle = ImageHandle::from_image(img);
+                ImageError::NoError
+            }
+            None => ImageError::Parameter,
+        }
+    })
+}
+
+/// Creates RGBA8 image of `width` x `height` pixels filled with gradient of `count` color
+/// `stops` and writes its handle to `image`, e.g. for backgrounds and masks. `kind` is one of
+/// `GradientKind` values: linear gradient goes along direction of `angle` in degrees, clockwise
+/// from left to right, so 90 goes from top to bottom, while radial one goes from center of image
+/// to its corners and ignores angle. Stops may be given in any order; colors before the first
+/// stop and after the last one are colors of these stops. Colors are interpolated with
+/// premultiplied alpha.
+/// # Safety
+/// - `stops` is valid pointer to `count` readable `GradientStop`s.
+/// - `image` is valid pointer to `ImageHandle`.
+unsafe extern "C" fn img_gradient(
+    width: u32,
+    height: u32,
+    stops: *const GradientStop,
+    count: usize,
+    kind: u32,
+    angle: f32,
+    image: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("gradient");
+        if image.is_null() || stops.is_null() || count == 0 || width == 0 || height == 0 {
+            return ImageError::Parameter;
+        }
+        let radial = match GradientKind::try_from(kind) {
+            Ok(kind) => matches!(kind, GradientKind::Radial),
+            Err(e) => return e,
+        };
+        let stops = std::slice::from_raw_parts(stops, count);
+        if !angle.is_finite() || !stops.iter().all(|s| s.position.is_finite()) {
+            return ImageError::Parameter;
+        }
+        if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::Rgba8)) {
+            return e;
+        }
+
+        let mut stops: Vec<_> = stops
+            .iter()
+            .map(|s| (s.position, image::Rgba(s.color.to_be_bytes())))
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let gradient = generate::gradient(width, height, &stops, radial, angle);
+        *image = ImageHandle::from_image(DynamicImage::ImageRgba8(gradient));
+        ImageError::NoError
+    })
+}
+
+/// Creates RGBA8 checkerboard of `width` x `height` pixels of squares of `cell_size` pixels
+/// alternating colors `a` and `b` given as `0xRRGGBBAA`, starting with `a` at top left corner,
+/// and writes its handle to `image`, e.g. for test images and transparency backdrops.
+/// # Safety
+/// `image` is valid pointer to `ImageHandle`.
+unsafe extern "C" fn img_checkerboard(
+    width: u32,
+    height: u32,
+    cell_size: u32,
+    a: u32,
+    b: u32,
+    image: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if image.is_null() || width == 0 || height == 0 || cell_size == 0 {
+            return ImageError::Parameter;
+        }
+        if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::Rgba8)) {
+            return e;
+        }
+
+        let (a, b) = (image::Rgba(a.to_be_bytes()), image::Rgba(b.to_be_bytes()));
+        let checkerboard = generate::checkerboard(width, height, cell_size, a, b);
+        *image = ImageHandle::from_image(DynamicImage::ImageRgba8(checkerboard));
+        ImageError::NoError
+    })
+}
+
+/// Creates RGBA8 image of SMPTE color bars of `width` x `height` pixels and writes its handle
+/// to `image`, e.g. for test images of video pipelines: seven 75% bars in the top two thirds,
+/// reversed bars below them and -I, white, +Q and PLUGE pulses in the bottom quarter. Colors are
+/// RGB values common in digital recreations of the pattern, with black at 7.5% setup level.
+/// # Safety
+/// `image` is valid pointer to `ImageHandle`.
+unsafe extern "C" fn img_color_bars(
+    width: u32,
+    height: u32,
+    image: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if image.is_null() || width == 0 || height == 0 {
+            return ImageError::Parameter;
+        }
+        if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::Rgba8)) {
+            return e;
+        }
+
+        let bars = generate::color_bars(width, height);
+        *image = ImageHandle::from_image(DynamicImage::ImageRgba8(bars));
+        ImageError::NoError
+    })
+}
+
+/// Creates L8 image of `width` x `height` pixels of fractal noise of `kind`, one of `NoiseKind`
+/// values, and writes its handle to `image`, e.g. for masks and textures. Noise is sum of
+/// `octaves` layers from 1 to 16, each one of double frequency and half amplitude of previous
+/// one. Positive `frequency` of the first layer is in cycles per pixel, so its features are
+/// about `1 / frequency` pixels large. The same `seed` gives the same noise.
+/// # Safety
+/// `image` is valid pointer to `ImageHandle`.
+unsafe extern "C" fn img_noise(
+    width: u32,
+    height: u32,
+    kind: u32,
+    frequency: f32,
+    octaves: u32,
+    seed: u32,
+    image: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("noise");
+        let kind = match NoiseKind::try_from(kind) {
+            Ok(k) => k,
+            Err(e) => return e,
+        };
+        if image.is_null() || width == 0 || height == 0 || !(1..=16).contains(&octaves) {
+            return ImageError::Parameter;
+        }
+        if !frequency.is_finite() || frequency <= 0.0 {
+            return ImageError::Parameter;
+        }
+        if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::L8)) {
+            return e;
+        }
+
+        let noise = generate::noise(width, height, kind, frequency as f64, octaves, seed);
+        *image = ImageHandle::from_image(DynamicImage::ImageLuma8(noise));
+        ImageError::NoError
+    })
+}
+
+/// Creates image of `width` x `height` pixels of `color_type`, one of `ColorType` values,
+/// filled with color `fill_rgba` given as `0xRRGGBBAA`, e.g. canvas to composite images onto.
+/// Color is converted to color type as `img_set_pixel` does. Writes handle of created image to
+/// `handle`. `ColorType::Rgba32F` isn't supported, as in `img_convert`.
+/// # Safety
+/// `handle` is valid pointer to `ImageHandle`.
+unsafe extern "C" fn img_new(
+    width: u32,
+    height: u32,
+    color_type: u32,
+    fill_rgba: u32,
+    handle: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if handle.is_null() || width == 0 || height == 0 {
+            return ImageError::Parameter;
+        }
+        let color_type = match ColorType::try_from(color_type) {
+            Ok(c) => c,
+            Err(e) => return e,
+        };
+        let color = match color_type.to_image() {
+            Some(c) => c,
+            None => return float_images_unsupported(),
+        };
+        if let Err(e) = check_alloc(image_bytes(width, height, color)) {
+            return e;
+        }
+
+        let blank = |width, height| {
+            color_type
+                .blank(width, height)
+                .expect("color type of images")
+        };
+        let (mut pixel, mut image) = match (blank(1, 1), blank(width, height)) {
+            (Ok(pixel), Ok(image)) => (pixel, image),
+            (Err(e), _) | (_, Err(e)) => return e,
+        };
+        put_pixel(&mut pixel, 0, 0, image::Rgba(fill_rgba.to_be_bytes()));
+        if pixel.as_bytes().iter().any(|&b| b != 0) {
+            for p in pixel_bytes_mut(&mut image).chunks_exact_mut(pixel.as_bytes().len()) {
+                p.copy_from_slice(pixel.as_bytes());
+            }
+        }
+        *handle = ImageHandle::from_image(image);
+        ImageError::NoError
+    })
+}
+
+/// Packs image into framebuffer bytes of e-paper display in `format` (one of `EinkFormat`
+/// values) written to `out` of `*len` bytes. Rows are padded to whole byte. Writes packed size
+/// to `len`. Size query works the same way as in `img_encode`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `out` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_pack_eink(
+    handle: ImageHandle,
+    format: u32,
+    out: *mut u8,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let format = match EinkFormat::try_from(format) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+
+        let data = handle.read();
+        let packed = match format {
+            EinkFormat::Mono => pack::pack_mono(&data.image),
+            EinkFormat::Gray4 => pack::pack_gray4(&data.image),
+            EinkFormat::Acep7 => pack::pack_acep(&data.image),
+        };
+
+        match packed {
+            Ok(packed) => write_to_buffer(&packed, out, len),
+            Err(e) => e,
+        }
+    })
+}
+
+/// Packs image into display framebuffer bytes with `layout` (one of `PixelLayout` values) and
+/// `row_order` (one of `RowOrder` values) written to `out` of `*len` bytes. Rows aren't padded.
+/// Writes packed size to `len`. Size query works the same way as in `img_encode`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `out` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_pack_framebuffer(
+    handle: ImageHandle,
+    layout: u32,
+    row_order: u32,
+    out: *mut u8,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let layout = match PixelLayout::try_from(layout) {
+            Ok(l) => l,
+            Err(e) => return e,
+        };
+
+        let bottom_up = match RowOrder::try_from(row_order) {
+            Ok(order) => matches!(order, RowOrder::BottomUp),
+            Err(e) => return e,
+        };
+
+        let data = handle.read();
+        let packed = match layout {
+            PixelLayout::Rgb565 => pack::pack_rgb565(&data.image, bottom_up),
+            PixelLayout::Rgb332 => pack::pack_rgb332(&data.image, bottom_up),
+            PixelLayout::Bgr888 => pack::pack_bgr888(&data.image, bottom_up),
+        };
+
+        match packed {
+            Ok(packed) => write_to_buffer(&packed, out, len),
+            Err(e) => e,
+        }
+    })
+}
+
+/// Writes up to `max_suggestions` crop rectangles of `aspect_w:aspect_h` aspect ratio to `out`,
+/// ranked from best to worst by composition heuristics and edge energy. Writes number of
+/// suggested crops to `count`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `out` is valid pointer to `max_suggestions` writable `Rect`s.
+/// - `count` is valid pointer to `size_t`.
+unsafe extern "C" fn img_suggest_crops(
+    handle: ImageHandle,
+    aspect_w: u32,
+    aspect_h: u32,
+    max_suggestions: usize,
+    out: *mut Rect,
+    count: *mut usize,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("suggest_crops");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if out.is_null() || count.is_null() || aspect_w == 0 || aspect_h == 0 {
+            return ImageError::Parameter;
+        }
+
+        let crops = crop::suggest_crops(&handle.read().image, aspect_w, aspect_h, max_suggestions);
+        std::ptr::copy_nonoverlapping(crops.as_ptr(), out, crops.len());
+        *count = crops.len();
+        ImageError::NoError
+    })
+}
+
+/// Crops image to aspect ratio of `target_w` x `target_h` keeping its most interesting part,
+/// chosen by edge energy and composition heuristics of `img_suggest_crops`, then resizes crop
+/// to `target_w` x `target_h` pixels by Lanczos filter and writes handle of new image to
+/// `cropped`, e.g. for automated thumbnails. New image inherits metadata of source image.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `cropped` is valid pointer to `void*`.
+unsafe extern "C" fn img_smart_crop(
+    handle: ImageHandle,
+    target_w: u32,
+    target_h: u32,
+    cropped: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("smart_crop");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if cropped.is_null() || target_w == 0 || target_h == 0 {
+            return ImageError::Parameter;
+        }
+
+        let data = handle.read();
+        let rect = crop::smart_crop(&data.image, target_w, target_h);
+        // Crop is copied, then resized through intermediate image of target width.
+        let color = data.image.color();
+        let bytes = image_bytes(rect.width, rect.height, color)
+            + image_bytes(target_w, target_h.max(rect.height), color);
+        if let Err(e) = check_alloc(bytes) {
+            return e;
+        }
+        let mut image = data.image.crop_imm(rect.x, rect.y, rect.width, rect.height);
+        if (rect.width, rect.height) != (target_w, target_h) {
+            image = parallel::resize_dynamic(&image, target_w, target_h);
+        }
+        *cropped = ImageHandle::from_data(ImageData::new(image, data.metadata.clone()));
+        ImageError::NoError
+    })
+}
+
+/// Writes value of EXIF `tag` of primary image as null-terminated UTF-8 string to `buffer` of
+/// `*len` bytes. Integers and rationals are written in decimal (`"6"`, `"72/1"`) and separated by
+/// commas if tag has several values. Writes string size including terminating null to `len`.
+/// Size query works the same way as in `img_encode`. Returns `ImageError::NotFound` if image has
+/// no such tag.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_exif_get(
+    handle: ImageHandle,
+    tag: u16,
+    buffer: *mut c_char,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let exif = match &handle.as_data_ref().metadata.exif {
+            Some(e) => e,
+            None => return ImageError::NotFound,
+        };
+
+        let mut value = match meta::exif_value(exif, tag) {
+            Some(v) => v,
+            None => return ImageError::NotFound,
+        };
+        value.push('\0');
+
+        write_to_buffer(value.as_bytes(), buffer as *mut u8, len)
+    })
+}
+
+/// Sets EXIF `tag` of primary image to `value` parsed as `ty` (one of `ExifType` values),
+/// replacing previous value. Numbers are separated by commas, rationals are written as
+/// `num/denom` or as integer, so values returned by `img_exif_get` can be written back. Whole
+/// `value` is single ASCII string. Undefined values are bytes of `value`. Offset and IFD pointer
+/// tags can't be set. Tag is written with image when `SaveOptions::keep_metadata` is set.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `value` is valid pointer to null-terminated UTF-8 string.
+unsafe extern "C" fn img_exif_set(
+    handle: ImageHandle,
+    tag: u16,
+    ty: u32,
+    value: RawStr,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let ty = match ExifType::try_from(ty) {
+            Ok(t) => t,
+            Err(e) => return e,
+        };
+
+        let value: &str = match (&value).try_into() {
+            Ok(v) => v,
+            Err(e) => return e,
+        };
+
+        let data = handle.as_data();
+        match meta::set_exif_field(data.metadata.exif.as_deref(), tag, ty, value) {
+            Ok(exif) => {
+                data.checkpoint(
+                    json!({"op": "exif_set", "tag": tag, "type": ty as u32, "value": value}),
+                );
+                data.metadata.exif = Some(exif);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Rotates and flips image as its EXIF orientation tag prescribes, then sets the tag to `1`
+/// (upright). Images without orientation tag are left unchanged.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_auto_orient(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        let _span = trace::span("auto_orient");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let step = json!({"op": "auto_orient"});
+        match handle.as_data().edit(step, ImageData::auto_orient) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Denoises image with edge-preserving filter. Strength is picked from estimated noise level of
+/// image, so images without noticeable noise are left unchanged.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_auto_denoise(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        let _span = trace::span("auto_denoise");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        handle.as_data().checkpoint(json!({"op": "auto_denoise"}));
+        filter::auto_denoise(handle.as_image_mut());
+        ImageError::NoError
+    })
+}
+
+/// Sharpens image with unsharp mask. Strength and radius are picked from estimated sharpness of
+/// image and noise isn't amplified.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_auto_sharpen(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        let _span = trace::span("auto_sharpen");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        handle.as_data().checkpoint(json!({"op": "auto_sharpen"}));
+        filter::auto_sharpen(handle.as_image_mut());
+        ImageError::NoError
+    })
+}
+
+/// Corrects lateral chromatic aberration by radially rescaling red and blue channels about
+/// image center by `red_scale` and `blue_scale` (e.g. `1.001` to shrink channel fringe by 0.1%
+/// of distance to center). Scale `0` means it is estimated from alignment of channel edges
+/// with green channel.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_correct_ca(
+    handle: ImageHandle,
+    red_scale: f32,
+    blue_scale: f32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("correct_ca");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let valid = |scale: f32| scale == 0.0 || (0.5..2.0).contains(&scale);
+        if !valid(red_scale) || !valid(blue_scale) {
+            return ImageError::Parameter;
+        }
+
+        let step = json!({"op": "correct_ca", "red_scale": red_scale, "blue_scale": blue_scale});
+        handle.as_data().checkpoint(step);
+        filter::map_rgba16(handle.as_image_mut(), |rgba| {
+            filter::correct_ca(rgba, red_scale, blue_scale)
+        });
+        ImageError::NoError
+    })
+}
+
+/// Writes ICC profile embedded in source file of image to `buffer` of `*len` bytes. Writes
+/// profile size to `len`. Size query works the same way as in `img_encode`. Returns
+/// `ImageError::NotFound` if image has no profile.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_icc_get(
+    handle: ImageHandle,
+    buffer: *mut u8,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        match &handle.as_data_ref().metadata.icc {
+            Some(icc) => write_to_buffer(icc, buffer, len),
+            None => ImageError::NotFound,
+        }
+    })
+}
+
+/// Replaces ICC profile of image with `len` bytes of `data`. Null `data` or zero `len` removes
+/// profile. Pixels are not converted. Profile is embedded into JPEG and PNG images on save and
+/// encoding. Returns `ImageError::Parameter` if `data` is not ICC profile.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `data` is null or valid pointer to `len` readable bytes.
+unsafe extern "C" fn img_icc_set(handle: ImageHandle, data: *const u8, len: usize) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let icc = match data.is_null() || len == 0 {
+            true => None,
+            false => Some(std::slice::from_raw_parts(data, len)),
+        };
+        if matches!(icc, Some(icc) if !meta::is_icc_profile(icc)) {
+            return ImageError::Parameter;
+        }
+
+        let engine = &base64::engine::general_purpose::STANDARD;
+        let step = json!({"op": "icc_set", "icc": icc.map(|icc| engine.encode(icc))});
+        let data = handle.as_data();
+        data.checkpoint(step);
+        data.metadata.icc = icc.map(<[u8]>::to_vec);
+        ImageError::NoError
+    })
+}
+
+/// Converts pixels of image from color space of its ICC profile to sRGB and removes profile, so
+/// encoded image is displayed correctly by viewers assuming sRGB. Images without profile are
+/// treated as sRGB and left unchanged. Converted image has 8 bits per channel. Returns
+/// `ImageError::Unsupported` for non-RGB and non-grayscale profiles or if library is built
+/// without `icc` feature.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_convert_to_srgb(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        let _span = trace::span("convert_to_srgb");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let data = handle.as_data();
+        let icc = match &data.metadata.icc {
+            Some(icc) => icc,
+            None => return ImageError::NoError,
+        };
+
+        let converted = match color::convert_to_srgb(&data.image, icc) {
+            Ok(i) => i,
+            Err(e) => return e,
+        };
+        data.checkpoint(json!({"op": "convert_to_srgb"}));
+        data.image = converted.into();
+        data.metadata.icc = None;
+        ImageError::NoError
+    })
+}
+
+/// Removes purple fringes at high-contrast edges by desaturating pixels with hue within
+/// `hue_range` degrees band (e.g. `60`) around purple. Pixels at band center are desaturated by
+/// `strength` in `[0, 1]`, weight falls to zero at band edges.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_defringe(
+    handle: ImageHandle,
+    strength: f32,
+    hue_range: f32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("defringe");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let valid_range = hue_range > 0.0 && hue_range <= 360.0;
+        if !(0.0..=1.0).contains(&strength) || !valid_range {
+            return ImageError::Parameter;
+        }
+
+        let step = json!({"op": "defringe", "strength": strength, "hue_range": hue_range});
+        handle.as_data().checkpoint(step);
+        filter::map_rgba16(handle.as_image_mut(), |rgba| {
+            filter::defringe(rgba, strength, hue_range)
+        });
+        ImageError::NoError
+    })
+}
+
+/// Writes width, height, color type, bit depth and number of channels of image and whether it
+/// has alpha channel to `info`, whose `size` must be set by caller.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `info` is valid pointer to `ImageInfo`.
+unsafe extern "C" fn img_info(handle: ImageHandle, info: *mut ImageInfo) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let info = match info.as_mut() {
+            Some(i) if i.size >= std::mem::size_of::<ImageInfo>() => i,
+            _ => return ImageError::Parameter,
+        };
+
+        let (image, region) = handle.as_region();
+        let color = image.color();
+        info.width = region.width;
+        info.height = region.height;
+        info.color_type = ColorType::of(image) as u32;
+        info.channels = color.channel_count() as u32;
+        info.bit_depth = color.bytes_per_pixel() as u32 * 8 / info.channels;
+        info.has_alpha = color.has_alpha();
+        ImageError::NoError
+    })
+}
+
+/// Locks pixels of image and writes pointer to them, stride, size and pixel format to `lock`, so
+/// host can blit them to its framebuffer or write them directly. Pointer stays valid until
+/// `img_unlock_pixels` or `img_destroy` of image. Locked image is read as usual, e.g. by
+/// `img_encode` or `img_clone_cow`, whose clone gets copy of pixels, but functions changing
+/// it fail with `ImageError::InvalidHandle` until it is unlocked. Pixels written through pointer
+/// aren't recorded in edit stack. Frozen images can't be locked.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `lock` is valid pointer to `PixelLock`.
+unsafe extern "C" fn img_lock_pixels(handle: ImageHandle, lock: *mut PixelLock) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        let lock = match lock.as_mut() {
+            Some(l) => l,
+            None => return ImageError::Parameter,
+        };
+
+        let image = handle.as_image_mut();
+        let format = ColorType::of(image) as u32;
+        let (width, height) = image.dimensions();
+        let stride = width as usize * image.color().bytes_per_pixel() as usize;
+        *lock = PixelLock {
+            pixels: pixel_bytes_mut(image).as_mut_ptr(),
+            stride,
+            width,
+            height,
+            format,
+        };
+        handle.as_data().locked = true;
+        ImageError::NoError
+    })
+}
+
+/// Returns pixels of `image` as bytes, 16-bit channels are in native byte order.
+fn pixel_bytes_mut(image: &mut DynamicImage) -> &mut [u8] {
+    let len = image.as_bytes().len();
+    let pixels = match image {
+        DynamicImage::ImageLuma8(b) => b.as_mut_ptr(),
+        DynamicImage::ImageLumaA8(b) => b.as_mut_ptr(),
+        DynamicImage::ImageRgb8(b) => b.as_mut_ptr(),
+        DynamicImage::ImageRgba8(b) => b.as_mut_ptr(),
+        DynamicImage::ImageBgr8(b) => b.as_mut_ptr(),
+        DynamicImage::ImageBgra8(b) => b.as_mut_ptr(),
+        DynamicImage::ImageLuma16(b) => b.as_mut_ptr() as *mut u8,
+        DynamicImage::ImageLumaA16(b) => b.as_mut_ptr() as *mut u8,
+        DynamicImage::ImageRgb16(b) => b.as_mut_ptr() as *mut u8,
+        DynamicImage::ImageRgba16(b) => b.as_mut_ptr() as *mut u8,
+    };
+    // Buffer of image holds `len` bytes of its subpixels.
+    unsafe { std::slice::from_raw_parts_mut(pixels, len) }
+}
+
+/// Converts image to `color_type`, one of `ColorType` values, and locks its pixels as
+/// `img_lock_pixels` does, with stride of rows rounded up to multiple of `row_alignment`, a power
+/// of two or `0` for no alignment. E.g. `ColorType::Bgra8` gives pixels for Win32 DIB sections
+/// and `ColorType::Bgr8` with alignment `4` gives DWORD-aligned rows of 24-bit bitmaps; pass
+/// negative height to bitmap, as rows go from top to bottom. Conversion is recorded in edit stack
+/// and kept after unlocking. Rows needing padding are locked in separate buffer, which is copied
+/// to image by `img_unlock_pixels`, so until then functions reading image don't see pixels
+/// written through pointer. `ColorType::Rgba32F` fails with `ImageError::Unsupported`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `lock` is valid pointer to `PixelLock`.
+unsafe extern "C" fn img_lock_pixels_as(
+    handle: ImageHandle,
+    color_type: u32,
+    row_alignment: u32,
+    lock: *mut PixelLock,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        let aligned = row_alignment == 0 || row_alignment.is_power_of_two();
+        let color_type = match ColorType::try_from(color_type) {
+            Ok(c) if aligned && !lock.is_null() => c,
+            _ => return ImageError::Parameter,
+        };
+        let color = match color_type.to_image() {
+            Some(c) => c,
+            None => {
+                let message = "Images with float channels aren't supported".to_string();
+                set_last_error(LastError {
+                    sub_code: ErrorSubCode::UnsupportedColor,
+                    ..LastError::new(ImageError::Unsupported, message)
+                });
+                return ImageError::Unsupported;
+            }
+        };
+
+        let data = handle.as_data();
+        let (width, height) = data.image.dimensions();
+        if data.image.color() != color {
+            if let Err(e) = check_alloc(image_bytes(width, height, color)) {
+                return e;
+            }
+            data.checkpoint(json!({"op": "convert", "color_type": color_type as u32}));
+            let image = color_type
+                .convert(&data.image)
+                .expect("color type of images");
+            data.image = image.into();
+        }
+        let row = width as usize * color.bytes_per_pixel() as usize;
+        let stride = row.next_multiple_of(row_alignment.max(1) as usize);
+        if stride == row {
+            return img_lock_pixels(handle, lock);
+        }
+
+        if let Err(e) = check_alloc((stride as u64).saturating_mul(height as u64)) {
+            return e;
+        }
+        let mut staging = match try_alloc(stride * height as usize, 0) {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+        let rows = data.image.as_bytes().chunks_exact(row);
+        for (padded, row) in staging.chunks_exact_mut(stride).zip(rows) {
+            padded[..row.len()].copy_from_slice(row);
+        }
+        *lock = PixelLock {
+            pixels: staging.as_mut_ptr(),
+            stride,
+            width,
+            height,
+            format: color_type as u32,
+        };
+        data.staging = Some((staging, stride));
+        data.locked = true;
+        ImageError::NoError
+    })
+}
+
+/// Unlocks pixels of image locked by `img_lock_pixels` or `img_lock_pixels_as`, so it can be
+/// changed again. Pointer to pixels must not be used after it. Returns `ImageError::Parameter` if image isn't locked.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_unlock_pixels(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let data = handle.as_data();
+        if !data.locked {
+            let message = format!("Image handle {:p} isn't locked", handle.0);
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return ImageError::Parameter;
+        }
+        if let Some((staging, stride)) = data.staging.take() {
+            let row = data.image.width() as usize * data.image.color().bytes_per_pixel() as usize;
+            let rows = pixel_bytes_mut(&mut data.image).chunks_exact_mut(row);
+            for (row, padded) in rows.zip(staging.chunks_exact(stride)) {
+                row.copy_from_slice(&padded[..row.len()]);
+            }
+        }
+        data.locked = false;
+        ImageError::NoError
+    })
+}
+
+/// Checks that pixel at `x`, `y` is within `image`.
+fn check_pixel((width, height): (u32, u32), x: u32, y: u32) -> Result<(), ImageError> {
+    if x < width && y < height {
+        return Ok(());
+    }
+    let message = format!(
+        "Pixel {}, {} is outside of {}x{} image",
+        x, y, width, height
+    );
+    set_last_error(LastError::new(ImageError::Parameter, message));
+    Err(ImageError::Parameter)
+}
+
+/// Writes color of pixel at `x`, `y` to `rgba` as `0xRRGGBBAA`, e.g. for eyedropper. Channels of
+/// 16-bit images are reduced to 8 bits, gray pixels give equal red, green and blue. Returns
+/// `ImageError::Parameter` if pixel is outside of image.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `rgba` is valid pointer to `uint32_t`.
+unsafe extern "C" fn img_get_pixel(
+    handle: ImageHandle,
+    x: u32,
+    y: u32,
+    rgba: *mut u32,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if rgba.is_null() {
+            return ImageError::Parameter;
+        }
+        let (image, region) = handle.as_region();
+        if let Err(e) = check_pixel((region.width, region.height), x, y) {
+            return e;
+        }
+
+        *rgba = u32::from_be_bytes(image.get_pixel(region.x + x, region.y + y).0);
+        ImageError::NoError
+    })
+}
+
+/// Sets pixel at `x`, `y` to color `rgba` given as `0xRRGGBBAA`, converted to color type of
+/// image, e.g. for small touch-ups. Color replaces pixel without blending. Returns
+/// `ImageError::Parameter` if pixel is outside of image.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_set_pixel(handle: ImageHandle, x: u32, y: u32, rgba: u32) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        if let Err(e) = check_pixel(handle.as_image().dimensions(), x, y) {
+            return e;
+        }
+
+        let step = json!({"op": "set_pixel", "x": x, "y": y, "rgba": rgba});
+        handle.as_data().checkpoint(step);
+        put_pixel(handle.as_image_mut(), x, y, image::Rgba(rgba.to_be_bytes()));
+        ImageError::NoError
+    })
+}
+
+/// Sets pixel of `image` to `color` converted to its color type.
+fn put_pixel(image: &mut DynamicImage, x: u32, y: u32, color: image::Rgba<u8>) {
+    // Channels are scaled to full 16-bit range, so white stays white.
+    let wide = image::Rgba(color.0.map(|c| c as u16 * 257));
+    match image {
+        DynamicImage::ImageLuma16(b) => b.put_pixel(x, y, wide.to_luma()),
+        DynamicImage::ImageLumaA16(b) => b.put_pixel(x, y, wide.to_luma_alpha()),
+        DynamicImage::ImageRgb16(b) => b.put_pixel(x, y, wide.to_rgb()),
+        DynamicImage::ImageRgba16(b) => b.put_pixel(x, y, wide),
+        image => image.put_pixel(x, y, color),
+    }
+}
+
+/// Writes horizontal and vertical physical resolution of image in dots per inch to `x` and `y`.
+/// Resolution is read from JFIF segment of JPEG, `pHYs` chunk of PNG or resolution tags of EXIF.
+/// Returns `ImageError::NotFound` if source file doesn't specify resolution.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `x` and `y` are valid pointers to `double`.
+unsafe extern "C" fn img_get_dpi(handle: ImageHandle, x: *mut f64, y: *mut f64) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if x.is_null() || y.is_null() {
+            return ImageError::Parameter;
+        }
+
+        match handle.as_data_ref().metadata.dpi {
+            Some((dpi_x, dpi_y)) => {
+                *x = dpi_x;
+                *y = dpi_y;
+                ImageError::NoError
+            }
+            None => ImageError::NotFound,
+        }
+    })
+}
+
+/// Sets horizontal and vertical physical resolution of image to `x` and `y` dots per inch.
+/// Resolution is written to JFIF segment of JPEG and `pHYs` chunk of PNG on save and encoding.
+/// Resolution tags of EXIF are updated too.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_set_dpi(handle: ImageHandle, x: f64, y: f64) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let valid = |dpi: f64| dpi.is_finite() && dpi > 0.0;
+        if !valid(x) || !valid(y) {
+            return ImageError::Parameter;
+        }
+
+        let step = json!({"op": "set_dpi", "x": x, "y": y});
+        match handle
+            .as_data()
+            .edit(step, |data| data.metadata.set_dpi(x, y))
+        {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Reconstructs clipped channels of pixels where one or two RGB channels are clipped from
+/// unclipped channels and channel ratios of nearby unclipped pixels, restoring hue and gradients
+/// of highlights. Result is blended with original by `strength` in `[0, 1]`.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_recover_highlights(handle: ImageHandle, strength: f32) -> ImageError {
+    guard(|| {
+        let _span = trace::span("recover_highlights");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        if !(0.0..=1.0).contains(&strength) {
+            return ImageError::Parameter;
+        }
+
+        let step = json!({"op": "recover_highlights", "strength": strength});
+        handle.as_data().checkpoint(step);
+        filter::map_rgba16(handle.as_image_mut(), |rgba| {
+            filter::recover_highlights(rgba, strength)
+        });
+        ImageError::NoError
+    })
+}
+
+/// Writes keywords of PNG text chunks (`tEXt`, `zTXt` and `iTXt`) of image to `buffer` of `*len`
+/// bytes as consecutive null-terminated UTF-8 strings followed by empty string. Writes list size
+/// to `len`. Size query works the same way as in `img_encode`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_png_text_keys(
+    handle: ImageHandle,
+    buffer: *mut c_char,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let mut keys = Vec::new();
+        for (keyword, _) in &handle.as_data_ref().metadata.text {
+            if !keys.contains(&keyword) {
+                keys.push(keyword);
+            }
+        }
+
+        let list = string_list(keys);
+        write_to_buffer(&list, buffer as *mut u8, len)
+    })
+}
+
+/// Writes value of PNG text chunk with `keyword` as null-terminated UTF-8 string to `buffer` of
+/// `*len` bytes. Writes string size including terminating null to `len`. Size query works the
+/// same way as in `img_encode`. Returns `ImageError::NotFound` if image has no such text.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `keyword` is valid pointer to null-terminated UTF-8 string.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_png_text_get(
+    handle: ImageHandle,
+    keyword: RawStr,
+    buffer: *mut c_char,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let keyword: &str = match (&keyword).try_into() {
+            Ok(k) => k,
+            Err(e) => return e,
+        };
+
+        let mut value = match handle.as_data_ref().metadata.text(keyword) {
+            Some(v) => v.to_string(),
+            None => return ImageError::NotFound,
+        };
+        value.push('\0');
+
+        write_to_buffer(value.as_bytes(), buffer as *mut u8, len)
+    })
+}
+
+/// Sets value of PNG text chunk with `keyword` (1 to 79 printable Latin-1 characters) to `value`,
+/// replacing previous values. Null `value` removes text. Text is written to PNG images when
+/// `SaveOptions::keep_metadata` is set.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `keyword` is valid pointer to null-terminated UTF-8 string.
+/// - `value` is null or valid pointer to null-terminated UTF-8 string.
+unsafe extern "C" fn img_png_text_set(
+    handle: ImageHandle,
+    keyword: RawStr,
+    value: RawStr,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let keyword: &str = match (&keyword).try_into() {
+            Ok(k) => k,
+            Err(e) => return e,
+        };
+
+        let value: Option<&str> = match value.0.is_null() {
+            true => None,
+            false => match (&value).try_into() {
+                Ok(v) => Some(v),
+                Err(e) => return e,
+            },
+        };
+
+        let step = json!({"op": "png_text_set", "keyword": keyword, "value": value});
+        match handle
+            .as_data()
+            .edit(step, |data| data.metadata.set_text(keyword, value))
+        {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Brightens (positive amounts) or darkens (negative amounts) shadows and highlights of image
+/// by `shadows` and `highlights` in `[-1, 1]`, e.g. `0.5` and `-0.5` to open up shadows and
+/// recover highlights. Tones are masked by luma blurred with `radius` sigma in pixels: larger
+/// radius preserves more local contrast.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_shadows_highlights(
+    handle: ImageHandle,
+    shadows: f32,
+    highlights: f32,
+    radius: f32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("shadows_highlights");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let valid_amount = |amount: f32| (-1.0..=1.0).contains(&amount);
+        let valid_radius = radius.is_finite() && radius > 0.0;
+        if !valid_amount(shadows) || !valid_amount(highlights) || !valid_radius {
+            return ImageError::Parameter;
+        }
+
+        let step = json!({
+            "op": "shadows_highlights",
+            "shadows": shadows,
+            "highlights": highlights,
+            "radius": radius,
+        });
+        handle.as_data().checkpoint(step);
+        filter::map_rgba16(handle.as_image_mut(), |rgba| {
+            filter::shadows_highlights(rgba, shadows, highlights, radius)
+        });
+        ImageError::NoError
+    })
+}
+
+/// Adjusts hue, saturation and luminance of color bands (reds, oranges, ..., magentas) of image
+/// by `count` `bands`. Adjustments blend smoothly between neighbour bands and fade out for
+/// desaturated colors. Deltas of repeated bands are added.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `bands` is valid pointer to `count` `HslBand` values.
+unsafe extern "C" fn img_hsl_adjust(
+    handle: ImageHandle,
+    bands: *const HslBand,
+    count: usize,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("hsl_adjust");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        if bands.is_null() && count != 0 {
+            return ImageError::Parameter;
+        }
+
+        let bands = match count {
+            0 => &[],
+            _ => std::slice::from_raw_parts(bands, count),
+        };
+
+        let mut deltas = [[0.0; 3]; 8];
+        for band in bands {
+            let color = match ColorBand::try_from(band.band) {
+                Ok(c) => c,
+                Err(e) => return e,
+            };
+
+            let valid_hue = (-180.0..=180.0).contains(&band.hue);
+            let valid = |delta: f32| (-1.0..=1.0).contains(&delta);
+            if !valid_hue || !valid(band.saturation) || !valid(band.luminance) {
+                return ImageError::Parameter;
+            }
+
+            let delta = &mut deltas[color as usize];
+            delta[0] += band.hue;
+            delta[1] += band.saturation;
+            delta[2] += band.luminance;
+        }
+
+        let bands: Vec<_> = bands
+            .iter()
+            .map(|b| {
+                json!({
+                    "band": b.band,
+                    "hue": b.hue,
+                    "saturation": b.saturation,
+                    "luminance": b.luminance,
+                })
+            })
+            .collect();
+        handle
+            .as_data()
+            .checkpoint(json!({"op": "hsl_adjust", "bands": bands}));
+        filter::map_rgba16(handle.as_image_mut(), |rgba| {
+            filter::hsl_adjust(rgba, &deltas)
+        });
+        ImageError::NoError
+    })
+}
+
+/// Writes XMP packet of JPEG or TIFF source file of image as null-terminated UTF-8 string to
+/// `buffer` of `*len` bytes. Writes string size including terminating null to `len`. Size query
+/// works the same way as in `img_encode`. Returns `ImageError::NotFound` if image has no XMP.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_xmp_get(
+    handle: ImageHandle,
+    buffer: *mut c_char,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let xmp = match &handle.as_data_ref().metadata.xmp {
+            Some(x) => x,
+            None => return ImageError::NotFound,
+        };
+
+        // Packets may be padded with nulls.
+        let mut xmp = String::from_utf8_lossy(xmp)
+            .trim_end_matches('\0')
+            .to_string();
+        xmp.push('\0');
+
+        write_to_buffer(xmp.as_bytes(), buffer as *mut u8, len)
+    })
+}
+
+/// Writes values of IPTC `dataset` of application record (e.g. `120` for caption, `25` for
+/// keywords, `116` for copyright notice) of JPEG or TIFF source file of image to `buffer` of
+/// `*len` bytes as consecutive null-terminated UTF-8 strings followed by empty string. Writes
+/// list size to `len`. Size query works the same way as in `img_encode`. Returns
+/// `ImageError::NotFound` if image has no such dataset.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_iptc_get(
+    handle: ImageHandle,
+    dataset: u32,
+    buffer: *mut c_char,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let dataset = match u8::try_from(dataset) {
+            Ok(d) => d,
+            Err(_) => return ImageError::Parameter,
+        };
+
+        let values = handle.as_data_ref().metadata.iptc_values(dataset);
+        if values.is_empty() {
+            return ImageError::NotFound;
+        }
+
+        let list = string_list(values);
+        write_to_buffer(&list, buffer as *mut u8, len)
+    })
+}
+
+/// Replaces colors of image close to `from_rgba` with `to_rgba`, e.g. to make colorway variants
+/// of product from one photo. Colors are given as `0xRRGGBBAA`. Colors within `tolerance` in
+/// `[0, 1]` of `from_rgba` are fully replaced and replacement softly fades out for farther
+/// colors. Shading and texture of matched area are kept. If `preserve_luminosity` is set, only
+/// hue and saturation are changed.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_replace_color(
+    handle: ImageHandle,
+    from_rgba: u32,
+    to_rgba: u32,
+    tolerance: f32,
+    preserve_luminosity: bool,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("replace_color");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        if !(0.0..=1.0).contains(&tolerance) {
+            return ImageError::Parameter;
+        }
+
+        let channels = |rgba: u32| rgba.to_be_bytes().map(|c| c as f32 / u8::MAX as f32);
+        let (from, to) = (channels(from_rgba), channels(to_rgba));
+        handle.as_data().checkpoint(json!({
+            "op": "replace_color",
+            "from": from_rgba,
+            "to": to_rgba,
+            "tolerance": tolerance,
+            "preserve_luminosity": preserve_luminosity,
+        }));
+        filter::map_rgba16(handle.as_image_mut(), |rgba| {
+            filter::replace_color(rgba, from, to, tolerance, preserve_luminosity)
+        });
+        ImageError::NoError
+    })
+}
+
+/// Removes all metadata of image: EXIF (including GPS), XMP, IPTC, ICC profile, DPI and PNG
+/// text, so nothing but pixels is written on save and encoding, even with `keep_metadata`.
+/// Image is rotated as its EXIF orientation prescribes first, so it is still displayed upright.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_strip_metadata(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let strip = |data: &mut ImageData| {
+            data.auto_orient()?;
+            data.metadata = meta::Metadata::default();
+            Ok(())
+        };
+        match handle
+            .as_data()
+            .edit(json!({"op": "strip_metadata"}), strip)
+        {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Loads JPEG thumbnail embedded in EXIF of image file at `path` without decoding the image
+/// itself, which is much faster for previews. Thumbnail is rotated as EXIF orientation of the
+/// image prescribes and has no metadata. Returns `ImageError::NotFound` if file has no EXIF
+/// thumbnail.
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `handle` is valid pointer to `void*`.
+unsafe extern "C" fn img_exif_thumbnail(path: RawPath, handle: *mut ImageHandle) -> ImageError {
+    guard(|| {
+        if handle.is_null() || path.0.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let mut reader = match std::fs::File::open(path) {
+            Ok(f) => BufReader::new(f),
+            Err(e) => return io_error(e),
+        };
+        let (thumbnail, exif) = match meta::read_exif_thumbnail(&mut reader) {
+            Some(t) => t,
+            None => return ImageError::NotFound,
+        };
+
+        let reader = Cursor::new(thumbnail);
+        let image = match decode::load(reader, image::ImageFormat::Jpeg, &OpenOptions::default()) {
+            Ok(i) => i,
+            Err(e) => return e,
+        };
+        let metadata = meta::Metadata {
+            exif: Some(exif),
+            ..Default::default()
+        };
+        let mut data = ImageData::new(image, metadata);
+        if let Err(e) = data.auto_orient() {
+            return e;
+        }
+        data.metadata = meta::Metadata::default();
+
+        *handle = ImageHandle::from_data(data);
+        ImageError::NoError
+    })
+}
+
+/// Grades image with lift of shadows, gamma of midtones and gain of highlights given by
+/// `shadows_rgba`, `midtones_rgba` and `highlights_rgba` colors as `0xRRGGBBAA`. Difference of
+/// color channels from middle gray `0x80` sets direction of channel adjustment and alpha sets its
+/// strength, so `0x80808000` or any color with zero alpha keeps tones. E.g. `0x4080c0ff` shadows
+/// and `0xc08040ff` highlights give teal-and-orange look. `balance` in `[-1, 1]` moves tone
+/// splitting shadows and highlights: positive balance favours highlights.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_split_tone(
+    handle: ImageHandle,
+    shadows_rgba: u32,
+    midtones_rgba: u32,
+    highlights_rgba: u32,
+    balance: f32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("split_tone");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        if !(-1.0..=1.0).contains(&balance) {
+            return ImageError::Parameter;
+        }
+
+        let amounts = |rgba: u32| {
+            let [r, g, b, a] = rgba.to_be_bytes().map(|c| c as f32 / u8::MAX as f32);
+            [r, g, b].map(|c| (c - 0.5) * a)
+        };
+        let (lift, gamma, gain) = (
+            amounts(shadows_rgba),
+            amounts(midtones_rgba),
+            amounts(highlights_rgba),
+        );
+        handle.as_data().checkpoint(json!({
+            "op": "split_tone",
+            "shadows": shadows_rgba,
+            "midtones": midtones_rgba,
+            "highlights": highlights_rgba,
+            "balance": balance,
+        }));
+        filter::map_rgba16(handle.as_image_mut(), |rgba| {
+            filter::split_tone(rgba, lift, gamma, gain, balance)
+        });
+        ImageError::NoError
+    })
+}
+
+/// Applies look of `FilmStock` `stock` to image: color grading, contrast curve, saturation and
+/// grain of the film. `strength` in `[0, 1]` blends look with original image.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_film_emulation(
+    handle: ImageHandle,
+    stock: u32,
+    strength: f32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("film_emulation");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        if !(0.0..=1.0).contains(&strength) {
+            return ImageError::Parameter;
+        }
+
+        let stock = match FilmStock::try_from(stock) {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+
+        let step = json!({"op": "film_emulation", "stock": stock as u32, "strength": strength});
+        let stock = &film::STOCKS[stock as usize];
+        handle.as_data().checkpoint(step);
+        filter::map_rgba16(handle.as_image_mut(), |rgba| {
+            film::emulate(rgba, stock, strength)
+        });
+        ImageError::NoError
+    })
+}
+
+/// Writes detailed message of the last decoding, encoding or I/O error on calling thread, e.g.
+/// `Format error decoding Png: CRC error`, to `buffer` of `len` bytes as null-terminated UTF-8
+/// string. Message is truncated to fit into `buffer`. Returns message size including
+/// terminating null, or zero if no error occurred on this thread yet. Message is kept until the
+/// next error, so it should be read right after function returned error.
+/// # Safety
+/// `buffer` is null or valid pointer to `len` writable bytes.
+unsafe extern "C" fn img_last_error_message(buffer: *mut c_char, len: usize) -> usize {
+    guard(|| {
+        LAST_ERROR.with(|last| {
+            let message = match &*last.borrow() {
+                Some(error) => error.message.to_string_lossy().into_owned(),
+                None => return 0,
+            };
+
+            if !buffer.is_null() && len != 0 {
+                let mut size = message.len().min(len - 1);
+                while !message.is_char_boundary(size) {
+                    size -= 1;
+                }
+                std::ptr::copy_nonoverlapping(message.as_ptr(), buffer as *mut u8, size);
+                *buffer.add(size) = 0;
+            }
+            message.len() + 1
+        })
+    })
+}
+
+/// Enables journal of image edits keeping up to `depth` states of image before edits for
+/// `img_undo` and `img_redo`. Every function modifying pixels or metadata of image records its
+/// state. Recorded states share pixels with image, which are copied once image is changed in
+/// place, so each journal step holds at most one copy of image. Zero `depth` disables journal
+/// and clears history. Journal is disabled by default.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_set_journal_depth(handle: ImageHandle, depth: u32) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        handle.as_data().journal.set_depth(depth as usize);
+        ImageError::NoError
+    })
+}
+
+/// Reverts the last edit of image recorded in its journal. Returns `ImageError::NotFound` if
+/// there is nothing to undo.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_undo(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let data = handle.as_data();
+        match data.journal.undo(&mut data.image, &mut data.metadata) {
+            true => ImageError::NoError,
+            false => ImageError::NotFound,
+        }
+    })
+}
+
+/// Reapplies the last edit reverted by `img_undo`. Any new edit clears redo history. Returns
+/// `ImageError::NotFound` if there is nothing to redo.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_redo(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let data = handle.as_data();
+        match data.journal.redo(&mut data.image, &mut data.metadata) {
+            true => ImageError::NoError,
+            false => ImageError::NotFound,
+        }
+    })
+}
+
+/// Writes numbers of edits of image available for `img_undo` and `img_redo` to `undo` and
+/// `redo`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `undo` and `redo` are valid pointers to `size_t`.
+unsafe extern "C" fn img_history_len(
+    handle: ImageHandle,
+    undo: *mut usize,
+    redo: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if undo.is_null() || redo.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let (undo_len, redo_len) = handle.as_data_ref().journal.len();
+        *undo = undo_len;
+        *redo = redo_len;
+        ImageError::NoError
+    })
+}
+
+/// Writes details of the last decoding, encoding or I/O error on calling thread to `details`:
+/// error code, `ErrorSubCode` refining it, offending image format, OS error number and message.
+/// `details.size` must be set to size of `ErrorDetails` known by caller. Returns
+/// `ImageError::NotFound` if no error occurred on this thread yet.
+/// # Safety
+/// `details` is valid pointer to `ErrorDetails` with `size` field set.
+unsafe extern "C" fn img_last_error_details(details: *mut ErrorDetails) -> ImageError {
+    guard(|| {
+        let details = match details.as_mut() {
+            Some(d) if d.size >= std::mem::size_of::<ErrorDetails>() => d,
+            _ => return ImageError::Parameter,
+        };
+
+        LAST_ERROR.with(|last| {
+            let last = last.borrow();
+            let error = match &*last {
+                Some(e) => e,
+                None => return ImageError::NotFound,
+            };
+
+            details.code = error.code as u32;
+            details.sub_code = error.sub_code as u32;
+            details.format = error.format.map_or(u32::MAX, |f| f as u32);
+            details.os_error = error.os_error;
+            // Message is owned by thread-local storage, so it lives until it is replaced.
+            details.message = error.message.as_ptr();
+            ImageError::NoError
+        })
+    })
+}
+
+/// Writes edit stack of image to `json`: JSON document listing edits applied to image since it was
+/// opened, e.g. `{"version": 1, "edits": [{"op": "hsl_adjust", ...}, {"op": "mirror"}]}`. Undone
+/// edits are not listed. Edit stack can be stored as recipe next to original file and applied to
+/// it again with `img_apply_edit_stack`. Image created by `img_blur` inherits edits of source
+/// image followed by the blur. Returned string must be freed with `img_free_string`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `json` is valid pointer to `char*`.
+unsafe extern "C" fn img_export_edit_stack(
+    handle: ImageHandle,
+    json: *mut *mut c_char,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if json.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let stack = recipe::export(handle.as_data_ref().journal.edits());
+        // JSON escapes control characters, so document has no nulls.
+        *json = CString::new(stack).unwrap_or_default().into_raw();
+        ImageError::NoError
+    })
+}
+
+/// Applies edits of edit stack `json` written by `img_export_edit_stack` to image in order, e.g. to
+/// render edits stored as recipe on original image at full quality. Every edit is recorded in
+/// journal of image as if it was applied by its function. Returns `ImageError::Parameter` if
+/// `json` isn't valid edit stack. If some edit fails, edits before it stay applied.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `json` is valid pointer to null-terminated UTF-8 string.
+unsafe extern "C" fn img_apply_edit_stack(handle: ImageHandle, json: RawStr) -> ImageError {
+    guard(|| {
+        let _span = trace::span("apply_edit_stack");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let json: &str = match (&json).try_into() {
+            Ok(j) => j,
+            Err(e) => return e,
+        };
+
+        match recipe::apply(&handle, json) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Frees `string` returned by library, e.g. by `img_export_edit_stack`. Null `string` is ignored.
+/// # Safety
+/// `string` is null or string returned by library and not freed yet.
+unsafe extern "C" fn img_free_string(string: *mut c_char) {
+    guard(|| {
+        if !string.is_null() {
+            drop(CString::from_raw(string));
+        }
+    })
+}
+
+/// Sets `callback` receiving errors, warnings (e.g. malformed metadata ignored on decoding or
+/// metadata dropped on encoding) and debug messages of library with `user_data`, so they can be
+/// forwarded to logging system of host. Null `callback` removes it. Callback is shared by all
+/// threads and may be called from any thread calling library functions.
+/// # Safety
+/// `callback` and `user_data` are usable from any thread until callback is replaced.
+unsafe extern "C" fn img_set_log_callback(callback: Option<LogFn>, user_data: *mut c_void) {
+    guard(|| {
+        log::set_callback(callback, user_data);
+        let version = env!("CARGO_PKG_VERSION");
+        log::write(
+            LogLevel::Info,
+            &format!("image_sl {} log callback set", version),
+        );
+    })
+}
+
+/// Attaches annotation with JSON markup `json` (e.g. `{"label": "face", "score": 0.93}`) to
+/// region `rect` of image, so machine-readable markup travels with image. Annotations are listed
+/// in order of addition. They are kept by `img_blur`, but aren't recorded in journal and aren't
+/// moved by edits changing geometry. Returns `ImageError::Parameter` if `json` isn't valid JSON
+/// or `rect` is empty or doesn't lie within image.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `json` is valid pointer to null-terminated UTF-8 string.
+unsafe extern "C" fn img_add_annotation(
+    handle: ImageHandle,
+    rect: Rect,
+    json: RawStr,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let json: &str = match (&json).try_into() {
+            Ok(j) => j,
+            Err(e) => return e,
+        };
+        let markup: serde_json::Value = match serde_json::from_str(json) {
+            Ok(m) => m,
+            Err(e) => {
+                let message = format!("Invalid annotation: {}", e);
+                set_last_error(LastError::new(ImageError::Parameter, message));
+                return ImageError::Parameter;
+            }
+        };
+
+        let data = handle.as_data();
+        if !rect.is_within(&data.image) {
+            return ImageError::Parameter;
+        }
+        data.annotations.push((rect, markup.to_string()));
+        ImageError::NoError
+    })
+}
+
+/// Writes number of annotations attached to image to `count`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `count` is valid pointer to `size_t`.
+unsafe extern "C" fn img_annotation_count(handle: ImageHandle, count: *mut usize) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if count.is_null() {
+            return ImageError::Parameter;
+        }
+
+        *count = handle.as_data_ref().annotations.len();
+        ImageError::NoError
+    })
+}
+
+/// Writes region of annotation at `index` to `rect` and its JSON markup as null-terminated UTF-8
+/// string to `buffer` of `*len` bytes. Writes string size including terminating null to `len`.
+/// Size query works the same way as in `img_encode`. Returns `ImageError::NotFound` if `index` is
+/// not less than number of annotations.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `rect` is valid pointer to `Rect`.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_get_annotation(
+    handle: ImageHandle,
+    index: usize,
+    rect: *mut Rect,
+    buffer: *mut c_char,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if rect.is_null() || len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let (region, markup) = match handle.as_data_ref().annotations.get(index) {
+            Some(a) => a,
+            None => return ImageError::NotFound,
+        };
+
+        *rect = *region;
+        let mut markup = markup.clone();
+        markup.push('\0');
+        write_to_buffer(markup.as_bytes(), buffer as *mut u8, len)
+    })
+}
+
+/// Removes annotation at `index` from image. Following annotations move to previous indices.
+/// Returns `ImageError::NotFound` if `index` is not less than number of annotations.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_remove_annotation(handle: ImageHandle, index: usize) -> ImageError {
+    guard(|| {
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let annotations = &mut handle.as_data().annotations;
+        if index >= annotations.len() {
+            return ImageError::NotFound;
+        }
+        annotations.remove(index);
+        ImageError::NoError
+    })
+}
+
+/// Draws outlines of regions of all annotations over image with `color` as `0xRRGGBBAA` and lines
+/// of `line_width` pixels inside regions, e.g. to render reviewed image with its markup.
+/// Annotations stay attached to image.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_burn_annotations(
+    handle: ImageHandle,
+    color: u32,
+    line_width: u32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("burn_annotations");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        if line_width == 0 {
+            return ImageError::Parameter;
+        }
+
+        let data = handle.as_data();
+        let rects: Vec<_> = data.annotations.iter().map(|(rect, _)| *rect).collect();
+        let regions: Vec<_> = rects
+            .iter()
+            .map(|r| json!([r.x, r.y, r.width, r.height]))
+            .collect();
+        data.checkpoint(json!({
+            "op": "burn_annotations",
+            "color": color,
+            "line_width": line_width,
+            "rects": regions,
+        }));
+
+        let color = image::Rgba(color.to_be_bytes());
+        for rect in &rects {
+            rect.outline(&mut data.image, line_width, color);
+        }
+        ImageError::NoError
+    })
+}
+
+/// Creates empty document with `width` x `height` transparent canvas and writes its handle to
+/// `document`. Layers are composited by `img_doc_flatten`. Document must be destroyed with
+/// `img_doc_destroy`.
+/// # Safety
+/// `document` is valid pointer to `void*`.
+unsafe extern "C" fn img_doc_new(
+    width: u32,
+    height: u32,
+    document: *mut DocumentHandle,
+) -> ImageError {
+    guard(|| {
+        if document.is_null() || width == 0 || height == 0 {
+            return ImageError::Parameter;
+        }
+
+        let doc = document::Document {
+            width,
+            height,
+            layers: Vec::new(),
+        };
+        *document = DocumentHandle::from_document(doc);
+        ImageError::NoError
+    })
+}
+
+/// Destroys document with all its layers. Image handles of layers become invalid.
+unsafe extern "C" fn img_doc_destroy(document: DocumentHandle) {
+    guard(|| {
+        if !document.0.is_null() {
+            document.into_document();
+        }
+    })
+}
+
+/// Adds `image` as layer on top of `document` with properties `props`. Null `props` means
+/// default properties: visible opaque normal layer at top left corner. Document takes ownership
+/// of image on success, so `image` must not be destroyed or used afterwards; use
+/// `img_doc_layer_image` to edit it.
+/// # Safety
+/// - `document` is valid document handle.
+/// - `image` is valid image handle not owned by document.
+/// - `props` is null or valid pointer to `LayerProps`.
+unsafe extern "C" fn img_doc_add_layer(
+    document: DocumentHandle,
+    image: ImageHandle,
+    props: *const LayerProps,
+) -> ImageError {
+    guard(|| {
+        if document.0.is_null() || !image.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let props = match LayerProps::from_ptr(props) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let mut layer = document::Layer {
+            data: image.into_data(),
+            x: 0,
+            y: 0,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            visible: true,
+        };
+        props.apply(&mut layer);
+        document.as_document().layers.push(layer);
+        ImageError::NoError
+    })
+}
+
+/// Writes number of layers of `document` to `count`.
+/// # Safety
+/// - `document` is valid document handle.
+/// - `count` is valid pointer to `size_t`.
+unsafe extern "C" fn img_doc_layer_count(
+    document: DocumentHandle,
+    count: *mut usize,
+) -> ImageError {
+    guard(|| {
+        if document.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+        if count.is_null() {
+            return ImageError::Parameter;
+        }
+
+        *count = document.as_document().layers.len();
+        ImageError::NoError
+    })
+}
+
+/// Writes properties of layer at `index` (`0` is the bottom layer) of `document` to `props`,
+/// whose `size` must be set by caller. Returns `ImageError::NotFound` if there is no such layer.
+/// # Safety
+/// - `document` is valid document handle.
+/// - `props` is valid pointer to `LayerProps` with `size` field set.
+unsafe extern "C" fn img_doc_get_layer(
+    document: DocumentHandle,
+    index: usize,
+    props: *mut LayerProps,
+) -> ImageError {
+    guard(|| {
+        let props = match props.as_mut() {
+            Some(p) if p.size >= std::mem::size_of::<LayerProps>() => p,
+            _ => return ImageError::Parameter,
+        };
+        if document.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+
+        match document.as_document().layers.get(index) {
+            Some(layer) => {
+                let size = props.size;
+                *props = LayerProps {
+                    size,
+                    ..LayerProps::of(layer)
+                };
+                ImageError::NoError
+            }
+            None => ImageError::NotFound,
+        }
+    })
+}
+
+/// Sets properties of layer at `index` of `document` to `props`. Returns `ImageError::NotFound`
+/// if there is no such layer.
+/// # Safety
+/// - `document` is valid document handle.
+/// - `props` is valid pointer to `LayerProps`.
+unsafe extern "C" fn img_doc_set_layer(
+    document: DocumentHandle,
+    index: usize,
+    props: *const LayerProps,
+) -> ImageError {
+    guard(|| {
+        if document.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+        if props.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let props = match LayerProps::from_ptr(props) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        match document.as_document().layers.get_mut(index) {
+            Some(layer) => {
+                props.apply(layer);
+                ImageError::NoError
+            }
+            None => ImageError::NotFound,
+        }
+    })
+}
+
+/// Writes handle of image of layer at `index` of `document` to `image`, so layer can be edited
+/// by image functions. Handle is owned by document: it must not be destroyed and becomes invalid
+/// when layer is removed or document is destroyed. Returns `ImageError::NotFound` if there is no
+/// such layer.
+/// # Safety
+/// - `document` is valid document handle.
+/// - `image` is valid pointer to `void*`.
+unsafe extern "C" fn img_doc_layer_image(
+    document: DocumentHandle,
+    index: usize,
+    image: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if document.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+        if image.is_null() {
+            return ImageError::Parameter;
+        }
+
+        match document.as_document().layers.get_mut(index) {
+            Some(layer) => {
+                *image = ImageHandle(&mut *layer.data as *mut ImageData as *mut c_void);
+                ImageError::NoError
+            }
+            None => ImageError::NotFound,
+        }
+    })
+}
+
+/// Removes layer at `index` from `document`. If `image` isn't null, handle of layer image is
+/// written to it and caller takes its ownership, otherwise image is destroyed. Returns
+/// `ImageError::NotFound` if there is no such layer.
+/// # Safety
+/// - `document` is valid document handle.
+/// - `image` is null or valid pointer to `void*`.
+unsafe extern "C" fn img_doc_remove_layer(
+    document: DocumentHandle,
+    index: usize,
+    image: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if document.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+
+        let layers = &mut document.as_document().layers;
+        if index >= layers.len() {
+            return ImageError::NotFound;
+        }
+
+        let layer = layers.remove(index);
+        if !image.is_null() {
+            *image = ImageHandle::from_box(layer.data);
+        }
+        ImageError::NoError
+    })
+}
+
+/// Moves layer at index `from` of `document` to index `to`, shifting layers between them.
+/// Returns `ImageError::NotFound` if there is no layer at either index.
+/// # Safety
+/// `document` is valid document handle.
+unsafe extern "C" fn img_doc_move_layer(
+    document: DocumentHandle,
+    from: usize,
+    to: usize,
+) -> ImageError {
+    guard(|| {
+        if document.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+
+        let layers = &mut document.as_document().layers;
+        if from >= layers.len() || to >= layers.len() {
+            return ImageError::NotFound;
+        }
+
+        let layer = layers.remove(from);
+        layers.insert(to, layer);
+        ImageError::NoError
+    })
+}
+
+/// Composites visible layers of `document` bottom to top with their opacities and blend modes
+/// over transparent canvas and writes handle of resulting RGBA image without metadata to
+/// `image`. Image has 16 bits per channel if some visible layer has more than 8 bits per
+/// channel.
+/// # Safety
+/// - `document` is valid document handle.
+/// - `image` is valid pointer to `void*`.
+unsafe extern "C" fn img_doc_flatten(
+    document: DocumentHandle,
+    image: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("flatten");
+        if document.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+        if image.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let document = document.as_document();
+        // Canvas of flattened document is composited in `f32` channels.
+        let canvas = document.width as u64 * document.height as u64 * 16;
+        if let Err(e) = check_alloc(canvas) {
+            return e;
+        }
+        match document.flatten() {
+            Ok(flattened) => {
+                *image = ImageHandle::from_image(flattened);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Returns `true` if `handle` is live image handle created by this library. Every image
+/// function checks its handle the same way and returns `ImageError::InvalidHandle` for invalid
+/// handles. Null, destroyed and foreign handles are detected as long as memory of destroyed
+/// image isn't reused, but pointers to unmapped memory may still crash.
+/// # Safety
+/// `handle` is null or pointer to readable memory.
+unsafe extern "C" fn img_is_valid(handle: ImageHandle) -> bool {
+    guard(|| handle.is_readable())
+}
+
+/// Opens OpenRaster (`.ora`) document at `path` and writes its handle to `document`. Layers
+/// of nested stacks are added in the same order to one stack, unsupported composite operations
+/// are replaced with normal blending. Returns `ImageError::Decoding` for malformed archives.
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `document` is valid pointer to `void*`.
+unsafe extern "C" fn img_doc_open_ora(path: RawPath, document: *mut DocumentHandle) -> ImageError {
+    guard(|| {
+        if document.is_null() || path.0.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let file = match std::fs::read(path) {
+            Ok(f) => f,
+            Err(e) => return io_error(e),
+        };
+
+        match ora::read(&file) {
+            Ok(doc) => {
+                *document = DocumentHandle::from_document(doc);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Saves `document` at `path` as OpenRaster archive of PNG layers with merged image and
+/// thumbnail, readable by Krita, GIMP and MyPaint.
+/// # Safety
+/// - `document` is valid document handle.
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+unsafe extern "C" fn img_doc_save_ora(document: DocumentHandle, path: RawPath) -> ImageError {
+    guard(|| {
+        if document.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+        if path.0.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let file = match ora::write(document.as_document()) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+        match std::fs::write(path, file) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => io_error(e),
+        }
+    })
+}
+
+/// Opens raster layers of Photoshop (`.psd`) file at `path` as document and writes its handle
+/// to `document`. Groups are flattened into one stack, layer masks, effects and adjustment
+/// layers are ignored and unsupported blend modes are replaced with normal blending. File
+/// without raster layers gives document with its composite image as the only layer. Only
+/// grayscale and RGB files of 8 or 16 bits per channel are supported; composite image of such
+/// files is also opened by `img_open`.
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `document` is valid pointer to `void*`.
+unsafe extern "C" fn img_doc_open_psd(path: RawPath, document: *mut DocumentHandle) -> ImageError {
+    guard(|| {
+        if document.is_null() || path.0.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let path: &Path = match (&path).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let file = match std::fs::read(path) {
+            Ok(f) => f,
+            Err(e) => return io_error(e),
+        };
+
+        match psd::read_document(&file, &OpenOptions::default()) {
+            Ok(doc) => {
+                *document = DocumentHandle::from_document(doc);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Enables or disables debug registry of image handles. While enabled, library remembers
+/// handles it hands out and destroys, so destroying image twice or using destroyed handle is
+/// reported as error to log callback and last error instead of being undefined behavior.
+/// Handles unknown to library are rejected without reading memory behind them. Images created
+/// while registry is enabled are owned by their thread: using them on another thread is reported
+/// as `ImageError::InvalidHandle` with `ErrorSubCode::WrongThread` unless they are passed by
+/// `img_detach_thread` and `img_attach_thread`. Disabling forgets destroyed handles and owners.
+unsafe extern "C" fn img_set_debug_handles(enabled: bool) {
+    guard(|| handles::set_enabled(enabled))
+}
+
+/// Returns number of live images created by library and not destroyed yet, including layers of
+/// documents, so long-running hosts can check they don't leak images.
+unsafe extern "C" fn img_live_handle_count() -> usize {
+    guard(handles::live_count)
+}
+
+/// Passes every live image to `callback` with `user_data`, e.g. at host shutdown when all images
+/// are expected to be destroyed. With `backtrace` feature, backtrace of place where image was
+/// created is passed too. Returns number of live images.
+/// # Safety
+/// `callback` is safe to call with `user_data`.
+unsafe extern "C" fn img_dump_leaks(callback: LeakFn, user_data: *mut c_void) -> usize {
+    guard(|| {
+        // Registry isn't locked during the calls, so callback may call library functions.
+        let live = handles::live();
+        for (address, backtrace) in &live {
+            let backtrace = CString::new(backtrace.replace('\0', " ")).unwrap_or_default();
+            let len = backtrace.as_bytes().len();
+            let handle = ImageHandle(*address as *mut c_void);
+            callback(handle, backtrace.as_ptr(), len, user_data);
+        }
+        live.len()
+    })
+}
+
+/// Finds up to `max_matches` non-overlapping placements of `needle` in `haystack` by normalized
+/// cross-correlation of luma and writes them to `out` ordered from the best one. Transparent
+/// pixels of needle are ignored. `method` is one of `MatchMethod` values and tells whether scaled
+/// and rotated needle is searched too. Writes number of matches to `count`, zero if needle isn't
+/// found.
+/// # Safety
+/// - `haystack` and `needle` are valid image handles.
+/// - `out` is valid pointer to `max_matches` writable `MatchResult`s.
+/// - `count` is valid pointer to `size_t`.
+unsafe extern "C" fn img_match_template(
+    haystack: ImageHandle,
+    needle: ImageHandle,
+    method: u32,
+    max_matches: usize,
+    out: *mut MatchResult,
+    count: *mut usize,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("match_template");
+        if !haystack.is_readable() || !needle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if out.is_null() || count.is_null() {
+            return ImageError::Parameter;
+        }
+        let method = match MatchMethod::try_from(method) {
+            Ok(method) => method,
+            Err(e) => return e,
+        };
+
+        let multi_scale = matches!(
+            method,
+            MatchMethod::MultiScale | MatchMethod::MultiScaleRotated
+        );
+        let rotated = matches!(
+            method,
+            MatchMethod::Rotated | MatchMethod::MultiScaleRotated
+        );
+        let (haystack, needle) = (haystack.read(), needle.read());
+        let found = matching::match_template(
+            &haystack.image,
+            &needle.image,
+            multi_scale,
+            rotated,
+            max_matches,
+        );
+        std::ptr::copy_nonoverlapping(found.as_ptr(), out, found.len());
+        *count = found.len();
+        ImageError::NoError
+    })
+}
+
+/// Detects up to 1000 strongest corners of image with `detector` (one of `Detector` values) and
+/// writes array of them to `out` and its length to `count`. Corners are found by FAST-9 at full
+/// resolution only. ORB keypoints also get orientation and rotated BRIEF descriptors, so they
+/// can be matched across rotated images. Returned array must be freed with
+/// `img_free_keypoints`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `out` is valid pointer to `Keypoint*`.
+/// - `count` is valid pointer to `size_t`.
+unsafe extern "C" fn img_detect_keypoints(
+    handle: ImageHandle,
+    detector: u32,
+    out: *mut *mut Keypoint,
+    count: *mut usize,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("detect_keypoints");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if out.is_null() || count.is_null() {
+            return ImageError::Parameter;
+        }
+        let detector = match Detector::try_from(detector) {
+            Ok(detector) => detector,
+            Err(e) => return e,
+        };
+
+        let orb = matches!(detector, Detector::Orb);
+        let keypoints = keypoints::detect(&handle.read().image, orb).into_boxed_slice();
+        *count = keypoints.len();
+        *out = Box::into_raw(keypoints) as *mut Keypoint;
+        ImageError::NoError
+    })
+}
+
+/// Frees `count` keypoints returned by `img_detect_keypoints`. Null `keypoints` is ignored.
+/// # Safety
+/// `keypoints` is null or array of `count` keypoints returned by library and not freed yet.
+unsafe extern "C" fn img_free_keypoints(keypoints: *mut Keypoint, count: usize) {
+    guard(|| {
+        if !keypoints.is_null() {
+            drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                keypoints, count,
+            )));
+        }
+    })
+}
+
+/// Detects ORB keypoints of images `a` and `b`, matches their descriptors and estimates offset
+/// between images, e.g. to align frames for stacking or stitching. Only translation is
+/// estimated: it is the offset agreed by the most matches, averaged over them. Matches are
+/// mutually nearest by Hamming distance and notably closer than the next candidate. Writes them
+/// to `out`, which must be freed with `img_free_matches`.
+/// # Safety
+/// - `a` and `b` are valid image handles.
+/// - `out` is valid pointer to `Matches`.
+unsafe extern "C" fn img_match_keypoints(
+    a: ImageHandle,
+    b: ImageHandle,
+    out: *mut Matches,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("match_keypoints");
+        if !a.is_readable() || !b.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if out.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let alignment = keypoints::align(&a.read().image, &b.read().image);
+        let matches = alignment.matches.into_boxed_slice();
+        *out = Matches {
+            count: matches.len(),
+            matches: Box::into_raw(matches) as *mut KeypointMatch,
+            offset_x: alignment.offset.0,
+            offset_y: alignment.offset.1,
+            inliers: alignment.inliers,
+        };
+        ImageError::NoError
+    })
+}
+
+/// Frees array of `matches` written by `img_match_keypoints` and resets it to empty. Null
+/// `matches` is ignored.
+/// # Safety
+/// `matches` is null or valid pointer to `Matches` written by library and not freed yet.
+unsafe extern "C" fn img_free_matches(matches: *mut Matches) {
+    guard(|| {
+        let matches = match matches.as_mut() {
+            Some(matches) if !matches.matches.is_null() => matches,
+            _ => return,
+        };
+        let slice = std::ptr::slice_from_raw_parts_mut(matches.matches, matches.count);
+        drop(Box::from_raw(slice));
+        matches.matches = std::ptr::null_mut();
+        matches.count = 0;
+    })
+}
+
+/// Estimates transform of `motion` kind (one of `Motion` values) mapping points of `reference`
+/// image to the same points of `moving` one and writes it to `out`, e.g. to stack frames or
+/// compare before and after shots. If `warped` isn't null, writes there handle of moving image
+/// resampled into frame of reference, so its pixels line up with pixels of reference. Pixels of
+/// warped image not covered by moving one are transparent. Images larger than 512 pixels are
+/// aligned on downscaled copies. Returns `ImageError::NotFound` if affine alignment diverges,
+/// e.g. for images which don't overlap.
+/// # Safety
+/// - `reference` and `moving` are valid image handles.
+/// - `out` is valid pointer to `Transform`.
+/// - `warped` is null or valid pointer to `void*`.
+unsafe extern "C" fn img_align(
+    reference: ImageHandle,
+    moving: ImageHandle,
+    motion: u32,
+    out: *mut Transform,
+    warped: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("align");
+        if !reference.is_readable() || !moving.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if out.is_null() {
+            return ImageError::Parameter;
+        }
+        let motion = match Motion::try_from(motion) {
+            Ok(motion) => motion,
+            Err(e) => return e,
+        };
+
+        let (reference, moving) = (reference.read(), moving.read());
+        let (transform, score) = match motion {
+            Motion::Translation => align::translation(&reference.image, &moving.image),
+            Motion::Affine => match align::affine(&reference.image, &moving.image) {
+                Some(alignment) => alignment,
+                None => {
+                    let message = "Images can't be aligned".to_string();
+                    set_last_error(LastError::new(ImageError::NotFound, message));
+                    return ImageError::NotFound;
+                }
+            },
+        };
+        *out = Transform {
+            matrix: transform.map(|v| v as f32),
+            score,
+        };
+
+        if !warped.is_null() {
+            let (width, height) = reference.image.dimensions();
+            if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::Rgba16)) {
+                return e;
+            }
+            let map = |x, y| Some(align::apply(&transform, x, y));
+            let image = align::warp(&moving.image, width, height, map);
+            *warped = ImageHandle::from_data(ImageData::new(image, moving.metadata.clone()));
+        }
+        ImageError::NoError
+    })
+}
+
+/// Warps image in place so that content at points of `src_grid` moves to the same points of
+/// `dst_grid`, e.g. for liquify-style edits or flattening of photographed pages. Both grids have
+/// `cols` x `rows` points stored row by row, at least 2 x 2. Each grid cell is warped by
+/// perspective transform of its corners, so straight lines within cells stay straight. Size of
+/// image is kept and pixels outside of `dst_grid` become transparent. Returns
+/// `ImageError::Parameter` for degenerate cells, e.g. with 3 corners on one line.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `src_grid` and `dst_grid` are valid pointers to `cols * rows` readable `Point`s.
+unsafe extern "C" fn img_mesh_warp(
+    handle: ImageHandle,
+    src_grid: *const Point,
+    dst_grid: *const Point,
+    cols: u32,
+    rows: u32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("mesh_warp");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        let count = (cols as usize).checked_mul(rows as usize);
+        let count = match count {
+            Some(count) if cols >= 2 && rows >= 2 && !src_grid.is_null() && !dst_grid.is_null() => {
+                count
+            }
+            _ => return ImageError::Parameter,
+        };
+        let (src, dst) = (
+            std::slice::from_raw_parts(src_grid, count),
+            std::slice::from_raw_parts(dst_grid, count),
+        );
+        if !src
+            .iter()
+            .chain(dst)
+            .all(|p| p.x.is_finite() && p.y.is_finite())
+        {
+            return ImageError::Parameter;
+        }
+
+        let data = handle.as_data();
+        let (width, height) = data.image.dimensions();
+        if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::Rgba16)) {
+            return e;
+        }
+        let points = |grid: &[Point]| grid.iter().map(|p| (p.x as f64, p.y as f64)).collect();
+        let (src, dst): (Vec<_>, Vec<_>) = (points(src), points(dst));
+        let (cols, rows) = (cols as usize, rows as usize);
+        let warped = match mesh::warp(&data.image, &src, &dst, cols, rows) {
+            Some(warped) => warped,
+            None => {
+                let message = "Mesh warp grid has degenerate cell".to_string();
+                set_last_error(LastError::new(ImageError::Parameter, message));
+                return ImageError::Parameter;
+            }
+        };
+
+        let flat = |grid: &[(f64, f64)]| grid.iter().flat_map(|p| [p.0, p.1]).collect::<Vec<_>>();
+        let edit = json!({
+            "op": "mesh_warp",
+            "cols": cols,
+            "rows": rows,
+            "src": flat(&src),
+            "dst": flat(&dst),
+        });
+        data.checkpoint(edit);
+        data.image = warped.into();
+        ImageError::NoError
+    })
+}
+
+/// Parses export profile from `json` and writes its handle to `profile`. Profile describes files
+/// written for each image by `img_export`, e.g. `@1x`, `@2x` and `@3x` PNG icons plus JPEG
+/// preview:
+/// `{"source_scale": 3, "outputs": [{"name": "{name}@1x.png", "scale": 1}, {"name":
+/// "{name}@2x.png", "scale": 2}, {"name": "{name}-{width}.jpg", "width": 512, "jpeg_quality":
+/// 85}]}`.
+/// Fields of outputs:
+/// - `name`: file name template, where `{name}` is replaced with file name of base name passed
+///   to `img_export`, `{width}` and `{height}` with size of output.
+/// - `format`: format extension, e.g. `"png"`. Deduced from extension of `name` if missing.
+/// - `scale`: size of output relative to `source_scale` of image, 1 by default. So with
+///   `source_scale` 3, image is treated as `@3x` one and `scale` 1 writes third of its size.
+/// - `width`, `height`: limits of output size keeping aspect ratio of image, instead of `scale`.
+/// - `jpeg_quality`, `keep_metadata`, `embed_thumbnail`: the same as fields of `SaveOptions`.
+///
+/// Returns `ImageError::Parameter` for invalid profile and `ImageError::Unsupported` if some
+/// output format can't be encoded, e.g. WebP. Profile must be destroyed with
+/// `img_export_profile_destroy`.
+/// # Safety
+/// - `json` is valid pointer to null-terminated UTF-8 string.
+/// - `profile` is valid pointer to `void*`.
+unsafe extern "C" fn img_export_profile_create(
+    json: RawStr,
+    profile: *mut ExportProfileHandle,
+) -> ImageError {
+    guard(|| {
+        if profile.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let json: &str = match (&json).try_into() {
+            Ok(j) => j,
+            Err(e) => return e,
+        };
+        match export::Profile::parse(json) {
+            Ok(p) => {
+                *profile = ExportProfileHandle::from_profile(p);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Destroys export profile.
+unsafe extern "C" fn img_export_profile_destroy(profile: ExportProfileHandle) {
+    guard(|| {
+        if !profile.0.is_null() {
+            profile.into_profile();
+        }
+    })
+}
+
+/// Writes image to every output of `profile`. Outputs are written to directory of `base_name`
+/// path and its file name replaces `{name}` in their name templates, so `"icons/app"` base name
+/// and `"{name}@2x.png"` template give `"icons/app@2x.png"`. Image itself isn't changed. Stops
+/// at the first failed output, leaving already written files in place.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `profile` is valid export profile handle.
+/// - `base_name` is valid pointer to null-terminated UTF-8 string.
+unsafe extern "C" fn img_export(
+    handle: ImageHandle,
+    profile: ExportProfileHandle,
+    base_name: RawPath,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("export");
+        if !handle.is_readable() || profile.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+
+        let base_name: &Path = match (&base_name).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        match profile.as_profile().export(&handle.read(), base_name) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Returns set of `CAPABILITY_*` flags of optional features compiled into this build. Functions
+/// of missing features are still present in functions block, but return
+/// `ImageError::Unsupported`.
+unsafe extern "C" fn img_capabilities() -> u32 {
+    let features = [
+        (cfg!(feature = "http"), CAPABILITY_HTTP),
+        (cfg!(feature = "icc"), CAPABILITY_ICC),
+        (cfg!(feature = "mmap"), CAPABILITY_MMAP),
+        (cfg!(feature = "tracing"), CAPABILITY_TRACING),
+        (cfg!(feature = "backtrace"), CAPABILITY_BACKTRACE),
+        (cfg!(feature = "parallel"), CAPABILITY_PARALLEL),
+    ];
+    let enabled = features.iter().filter(|(enabled, _)| *enabled);
+    enabled.fold(0, |capabilities, (_, flag)| capabilities | flag)
+}
+
+/// Returns `true` if functions block of this build has field `name`, e.g. `"open_image_url"`,
+/// and the function does its work rather than returning `ImageError::Unsupported` because its
+/// feature isn't compiled in. So hosts can check for optional operations by name and degrade
+/// gracefully when they are missing, including functions added by newer versions of library.
+/// # Safety
+/// `name` is valid pointer to null-terminated UTF-8 string.
+unsafe extern "C" fn img_has_fn(name: RawStr) -> bool {
+    guard(|| {
+        let name: &str = match (&name).try_into() {
+            Ok(n) => n,
+            Err(_) => return false,
+        };
+        let required = match name {
+            "open_image_url" => CAPABILITY_HTTP,
+            "convert_to_srgb" => CAPABILITY_ICC,
+            _ => 0,
+        };
+        FUNCTION_NAMES.contains(&name) && img_capabilities() & required == required
+    })
+}
+
+/// Writes icon set of `kind` (one of `IconSetKind` values) rendered from image to `out_dir`,
+/// creating it if needed:
+/// - `IconSetKind::Favicon`: `favicon.ico` with 16, 32 and 48 pixel images,
+///   `favicon-16x16.png`, `favicon-32x32.png` and `apple-touch-icon.png`.
+/// - `IconSetKind::Ios`: `Icon-<points>@<scale>x.png` files of every iPhone, iPad and App Store
+///   icon slot with `Contents.json` of asset catalog, so `out_dir` can be `AppIcon.appiconset`.
+/// - `IconSetKind::Android`: `mipmap-<density>/ic_launcher.png` for `mdpi` to `xxxhdpi` and
+///   `playstore-icon.png`.
+/// - `IconSetKind::Pwa`: `icon-192x192.png`, `icon-512x512.png` with `manifest.json` listing
+///   them for web app manifest, and `apple-touch-icon.png`.
+///
+/// Non-square images are centered on transparent square canvas. Icons which platforms require
+/// to be opaque are composited over white. PNG files are compressed with the best of row filters
+/// and without alpha channel if it isn't used. Image itself isn't changed.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `out_dir` is valid pointer to null-terminated UTF-8 string.
+unsafe extern "C" fn img_generate_icon_set(
+    handle: ImageHandle,
+    kind: u32,
+    out_dir: RawPath,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("generate_icon_set");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let kind = match IconSetKind::try_from(kind) {
+            Ok(k) => k,
+            Err(e) => return e,
+        };
+        let out_dir: &Path = match (&out_dir).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        match icons::generate(&handle.read().image, kind, out_dir) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Registers decoder plugin of format `name`, so `img_open` and other loading functions decode
+/// images of proprietary formats by host callbacks. Files with extension `name` (case-insensitive)
+/// are decoded by `decode_fn` unless format hint is given, and streams of unknown format are
+/// passed to `probe_fn` of plugins in order of registration before built-in formats are guessed.
+/// Null `probe_fn` means plugin is selected only by extension. Registering plugin with the same
+/// name replaces it, null `decode_fn` removes it.
+/// # Safety
+/// - `name` is valid pointer to null-terminated UTF-8 string.
+/// - Callbacks are safe to call with `user_data` from any thread until plugin is replaced or
+///   removed.
+unsafe extern "C" fn img_register_decoder(
+    name: RawStr,
+    probe_fn: Option<ProbeFn>,
+    decode_fn: Option<DecodeFn>,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        let name: &str = match (&name).try_into() {
+            Ok(n) => n,
+            Err(e) => return e,
+        };
+        if name.is_empty() {
+            return ImageError::Parameter;
+        }
+        codec::register_decoder(name, probe_fn, decode_fn, user_data);
+        ImageError::NoError
+    })
+}
+
+/// Registers encoder plugin of format `name`, so `img_save` encodes images to files with
+/// extension `name` (case-insensitive) by `encode_fn` instead of built-in encoder. Registering
+/// plugin with the same name replaces it, null `encode_fn` removes it.
+/// # Safety
+/// - `name` is valid pointer to null-terminated UTF-8 string.
+/// - `encode_fn` is safe to call with `user_data` from any thread until plugin is replaced or
+///   removed.
+unsafe extern "C" fn img_register_encoder(
+    name: RawStr,
+    encode_fn: Option<EncodeFn>,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        let name: &str = match (&name).try_into() {
+            Ok(n) => n,
+            Err(e) => return e,
+        };
+        if name.is_empty() {
+            return ImageError::Parameter;
+        }
+        codec::register_encoder(name, encode_fn, user_data);
+        ImageError::NoError
+    })
+}
+
+/// Composes Open Graph or Twitter card of `spec` over image used as background, scaled to cover
+/// card, and writes handle of new RGBA image to `card`. Title is wrapped to at most 3 lines with
+/// the largest font fitting layout and cut with ellipsis if it doesn't fit at all.
+/// # Safety
+/// - `background` is valid image handle.
+/// - `spec` is null or valid pointer to `CardSpec` with null or valid string and image handles.
+///   Null `spec` means default card without title.
+/// - `card` is valid pointer to `void*`.
+unsafe extern "C" fn img_compose_card(
+    background: ImageHandle,
+    spec: *const CardSpec,
+    card: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("compose_card");
+        if !background.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let spec = match CardSpec::from_ptr(spec) {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+        if card.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let layout = match CardLayout::try_from(spec.layout) {
+            Ok(l) => l,
+            Err(e) => return e,
+        };
+        let (width, height) = match (spec.width, spec.height) {
+            (0, 0) => (1200, 630),
+            (0, _) | (_, 0) => return ImageError::Parameter,
+            size => size,
+        };
+        let title: &str = match spec.title.0.is_null() {
+            true => "",
+            false => match (&spec.title).try_into() {
+                Ok(t) => t,
+                Err(e) => return e,
+            },
+        };
+        let optional_image = |handle: &ImageHandle| match handle.0.is_null() {
+            true => Ok(None),
+            false if handle.is_readable() => Ok(Some(handle.read())),
+            false => Err(ImageError::InvalidHandle),
+        };
+        let (avatar, logo) = match (optional_image(&spec.avatar), optional_image(&spec.logo)) {
+            (Ok(avatar), Ok(logo)) => (avatar, logo),
+            (Err(e), _) | (_, Err(e)) => return e,
+        };
+
+        let spec = card::Card {
+            width,
+            height,
+            layout,
+            title,
+            avatar: avatar.as_ref().map(|data| &*data.image),
+            logo: logo.as_ref().map(|data| &*data.image),
+            text_color: image::Rgba(spec.text_color),
+            scrim_color: image::Rgba(spec.scrim_color),
+        };
+        match card::compose(&background.read().image, &spec) {
+            Ok(image) => {
+                *card = ImageHandle::from_image(image);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Loads image as `img_open_with_options` does, aborting with `ImageError::BudgetExceeded` if
+/// decoding takes longer than `budget.max_millis` or allocates more than `budget.max_bytes`.
+/// Null `budget` means no limits. Deadline is checked while file is read and pixels are
+/// allocated, so highly compressed image may overrun it by one decoding step.
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `options` is null or valid pointer to `OpenOptions`.
+/// - `budget` is null or valid pointer to `OperationBudget`.
+/// - `handle` is valid pointer to `void*`.
+unsafe extern "C" fn img_open_with_budget(
+    path: RawPath,
+    options: *const OpenOptions,
+    budget: *const OperationBudget,
+    handle: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let budget = match OperationBudget::from_ptr(budget) {
+            Ok(b) => b,
+            Err(e) => return e,
+        };
+        if handle.is_null() {
+            return ImageError::Parameter;
+        }
+        // Decoding stopped by budget doesn't write handle, so it must not keep value of caller.
+        *handle = ImageHandle(std::ptr::null_mut());
+        let mut opened = ImageError::NoError;
+        let result = budget::run(&budget, || {
+            opened = img_open_with_options(path, options, handle);
+            opened
+        });
+        // Image decoded past deadline is dropped, so host doesn't have to destroy it.
+        if let (ImageError::NoError, ImageError::BudgetExceeded) = (opened, result) {
+            std::ptr::replace(handle, ImageHandle(std::ptr::null_mut())).into_data();
+        }
+        result
+    })
+}
+
+/// Saves image as `img_save` does within `budget`. Null `budget` means no limits. File may be
+/// left partially written if budget is exceeded.
+/// # Safety
+/// - `path` is valid pointer to null-terminated UTF-8 string.
+/// - `handle` is valid image handle.
+/// - `budget` is null or valid pointer to `OperationBudget`.
+unsafe extern "C" fn img_save_with_budget(
+    path: RawPath,
+    handle: ImageHandle,
+    budget: *const OperationBudget,
+) -> ImageError {
+    guard(|| {
+        let budget = match OperationBudget::from_ptr(budget) {
+            Ok(b) => b,
+            Err(e) => return e,
+        };
+        budget::run(&budget, || img_save(path, handle))
+    })
+}
+
+/// Transcodes stream as `img_transcode_stream` does within `budget`: deadline is also checked
+/// between operations. Null `budget` means no limits.
+/// # Safety
+/// - `read_fn` and `write_fn` are safe to call with `user_data`.
+/// - `ops` is valid pointer to `count` operations. May be null if `count` is zero.
+/// - `budget` is null or valid pointer to `OperationBudget`.
+unsafe extern "C" fn img_transcode_stream_with_budget(
+    read_fn: ReadFn,
+    write_fn: WriteFn,
+    ops: *const Operation,
+    count: usize,
+    budget: *const OperationBudget,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        let budget = match OperationBudget::from_ptr(budget) {
+            Ok(b) => b,
+            Err(e) => return e,
+        };
+        budget::run(&budget, || {
+            img_transcode_stream(read_fn, write_fn, ops, count, user_data)
+        })
+    })
+}
+
+/// Applies operations of pipeline to image as `img_pipeline_execute` does within `budget`. Null
+/// `budget` means no limits. Images pipeline allocates are counted before image is changed, so
+/// exceeded memory budget leaves it unchanged, while pipeline finished past deadline keeps its
+/// result and returns `ImageError::BudgetExceeded`.
+/// # Safety
+/// - `pipeline` is valid pipeline handle.
+/// - `handle` is valid image handle.
+/// - `budget` is null or valid pointer to `OperationBudget`.
+unsafe extern "C" fn img_pipeline_execute_with_budget(
+    pipeline: PipelineHandle,
+    handle: ImageHandle,
+    budget: *const OperationBudget,
+) -> ImageError {
+    guard(|| {
+        let budget = match OperationBudget::from_ptr(budget) {
+            Ok(b) => b,
+            Err(e) => return e,
+        };
+        budget::run(&budget, || img_pipeline_execute(pipeline, handle))
+    })
+}
+
+/// Applies operations of pipeline to TIFF file as `img_pipeline_execute_tiled` does within
+/// `budget`: deadline is checked after every strip of source, and rows kept by resize and blur
+/// are counted against memory budget. Null `budget` means no limits. On failure `dst` is removed.
+/// # Safety
+/// - `pipeline` is valid pipeline handle.
+/// - `src` and `dst` are valid paths.
+/// - `budget` is null or valid pointer to `OperationBudget`.
+/// - `progress_fn` is null or safe to call with `user_data`.
+unsafe extern "C" fn img_pipeline_execute_tiled_with_budget(
+    pipeline: PipelineHandle,
+    src: RawPath,
+    dst: RawPath,
+    budget: *const OperationBudget,
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        let budget = match OperationBudget::from_ptr(budget) {
+            Ok(b) => b,
+            Err(e) => return e,
+        };
+        budget::run(&budget, || {
+            img_pipeline_execute_tiled(pipeline, src, dst, progress_fn, user_data)
+        })
+    })
+}
+
+/// Detaches image from current thread, so another thread may attach it by `img_attach_thread`
+/// and use it. Image shouldn't be used until it is attached. Ownership of images is checked only
+/// with debug registry of handles enabled, see `img_set_debug_handles`; otherwise the call only
+/// checks handle.
+/// # Safety
+/// - `handle` is valid image handle used by current thread.
+unsafe extern "C" fn img_detach_thread(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_live() {
+            return ImageError::InvalidHandle;
+        }
+        thread_result(&handle, handles::detach(handle.0 as usize))
+    })
+}
+
+/// Attaches image detached by `img_detach_thread` to current thread, which uses it from now on.
+/// Attaching image owned by current thread does nothing.
+/// # Safety
+/// - `handle` is valid image handle detached from its previous thread.
+unsafe extern "C" fn img_attach_thread(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_live() {
+            return ImageError::InvalidHandle;
+        }
+        thread_result(&handle, handles::attach(handle.0 as usize))
+    })
+}
+
+/// Reports failed detaching or attaching of `handle` as last error.
+fn thread_result(handle: &ImageHandle, result: Result<(), String>) -> ImageError {
+    match result {
+        Ok(_) => ImageError::NoError,
+        Err(message) => {
+            let message = format!("Image handle {:p} {}", handle.0, message);
+            set_last_error(LastError {
+                sub_code: ErrorSubCode::WrongThread,
+                ..LastError::new(ImageError::InvalidHandle, message)
+            });
+            ImageError::InvalidHandle
+        }
+    }
+}
+
+/// Loads image from `len` bytes of encoded image at `data` respecting `options`, e.g. on
+/// platforms without files. If `options` is null, default options are used. Without format
+/// hint, format is deduced from content. Data is only read during the call.
+/// # Safety
+/// - `data` is valid pointer to `len` bytes.
+/// - `options` is null or valid pointer to `OpenOptions`.
+/// - `handle` is valid pointer to `void*`.
+unsafe extern "C" fn img_open_memory(
+    data: *const u8,
+    len: usize,
+    options: *const OpenOptions,
+    handle: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("open");
+        if handle.is_null() || (data.is_null() && len != 0) {
+            return ImageError::Parameter;
+        }
+        let options = match OpenOptions::from_ptr(options) {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+        let format = match options.format_hint() {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+
+        let data = match len {
+            0 => &[],
+            len => std::slice::from_raw_parts(data, len),
+        };
+        match decode_as(Cursor::new(data), format, &options) {
+            Ok(img) => {
+                *handle = ImageHandle::from_data(img);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Applies operation `op` to copy of image and writes handle of result to `result`, so scripting
+/// hosts can call any operation without prototype of its function. Operations are those of edit
+/// stacks, e.g. `"blur"`, `"mirror"` or `"hsl_adjust"`, taking the same parameters as their
+/// edits in `img_export_edit_stack` documents. `params` is JSON object of parameters, e.g.
+/// `{"sigma": 2.5}`, or `key=value` pairs separated by commas or whitespace, e.g. `sigma=2.5`,
+/// whose values are parsed as JSON if they can be and taken as strings otherwise. Null `params`
+/// means no parameters. Unknown operation and missing or invalid parameters fail with
+/// `ImageError::Parameter` described by the last error.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `op` is valid pointer to null-terminated UTF-8 string.
+/// - `params` is null or valid pointer to null-terminated UTF-8 string.
+/// - `result` is valid pointer to `void*`.
+unsafe extern "C" fn img_apply(
+    handle: ImageHandle,
+    op: RawStr,
+    params: RawStr,
+    result: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("apply");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if result.is_null() {
+            return ImageError::Parameter;
+        }
+        let op: &str = match (&op).try_into() {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+        let params: &str = match params.0.is_null() {
+            true => "",
+            false => match (&params).try_into() {
+                Ok(p) => p,
+                Err(e) => return e,
+            },
+        };
+
+        let data = handle.read();
+        let (width, height) = data.image.dimensions();
+        if let Err(e) = check_alloc(image_bytes(width, height, data.image.color())) {
+            return e;
+        }
+        let mut copy = ImageData::new(data.share_image(), data.metadata.clone());
+        copy.annotations = data.annotations.clone();
+        let copy = ImageHandle::from_data(copy);
+        match recipe::apply_op(&copy, op, params) {
+            Ok(_) => {
+                *result = copy;
+                ImageError::NoError
+            }
+            Err(e) => {
+                copy.into_data();
+                e
+            }
+        }
+    })
+}
+
+/// Applies operation `op` as `img_apply` does to copy of image downscaled to fit `max_size` x
+/// `max_size` pixels and writes handle of result to `result`, e.g. for instant feedback of
+/// slider in editor while the same operation runs on full image. Image is downscaled by fast box
+/// filter keeping its aspect ratio, and parameters measured in pixels, e.g. `sigma` of `blur` or
+/// rectangles, are scaled with it, so preview looks as result would scaled down. Images fitting
+/// `max_size` are copied as they are. Result has no annotations and records operation with
+/// scaled parameters.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `op` is valid pointer to null-terminated UTF-8 string.
+/// - `params` is null or valid pointer to null-terminated UTF-8 string.
+/// - `result` is valid pointer to `void*`.
+unsafe extern "C" fn img_apply_preview(
+    handle: ImageHandle,
+    op: RawStr,
+    params: RawStr,
+    max_size: u32,
+    result: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("apply_preview");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if result.is_null() || max_size == 0 {
+            return ImageError::Parameter;
+        }
+        let op: &str = match (&op).try_into() {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+        let params: &str = match params.0.is_null() {
+            true => "",
+            false => match (&params).try_into() {
+                Ok(p) => p,
+                Err(e) => return e,
+            },
+        };
+        let mut params = match recipe::parse_params(op, params) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let data = handle.read();
+        let (width, height) = data.image.dimensions();
+        let size = (width.min(max_size), height.min(max_size));
+        if let Err(e) = check_alloc(image_bytes(size.0, size.1, data.image.color())) {
+            return e;
+        }
+        let proxy = match width.max(height) > max_size {
+            true => data.image.thumbnail(max_size, max_size),
+            false => (*data.image).clone(),
+        };
+        let scale = (
+            proxy.width() as f64 / width.max(1) as f64,
+            proxy.height() as f64 / height.max(1) as f64,
+        );
+        recipe::scale_params(op, &mut params, scale);
+        let copy = ImageHandle::from_data(ImageData::new(proxy, data.metadata.clone()));
+        match recipe::apply_params(&copy, op, params) {
+            Ok(_) => {
+                *result = copy;
+                ImageError::NoError
+            }
+            Err(e) => {
+                copy.into_data();
+                e
+            }
+        }
+    })
+}
+
+/// Starts operation `op` with parameters `params` on worker thread and writes handle of started
+/// job to `job`, so hosts can decode, edit and encode images without blocking their UI threads.
+/// Jobs share worker threads of blur and resize, so at most as many jobs as set by
+/// `img_set_thread_count` run at once while the others wait for them, and with one thread jobs
+/// run one by one on single background thread. Operations are those of `img_apply`, applied to copy of `handle` made on submitting,
+/// and two more with `path` parameter: `"open"` decodes file with default options, as `img_open`
+/// does, ignoring `handle`, and `"save"` encodes copy of `handle` into file, as `img_save` does.
+/// Parameters are written as for `img_apply`, e.g. `{"path": "out.png"}`. Source image may be
+/// used and destroyed while job runs. Returns `ImageError::Unsupported` if platform can't spawn
+/// threads. Job must be destroyed by `img_job_destroy`.
+/// # Safety
+/// - `handle` is valid image handle, or null for `"open"`.
+/// - `op` is valid pointer to null-terminated UTF-8 string.
+/// - `params` is null or valid pointer to null-terminated UTF-8 string.
+/// - `job` is valid pointer to `void*`.
+unsafe extern "C" fn img_submit(
+    handle: ImageHandle,
+    op: RawStr,
+    params: RawStr,
+    job: *mut JobHandle,
+) -> ImageError {
+    let token = TokenHandle(std::ptr::null_mut());
+    img_submit_with_token(handle, op, params, token, job)
+}
+
+/// Creates job queue running at most `max_parallel` of its jobs at once and writes its handle
+/// to `queue`. Jobs of queue are started by `img_submit_on` and still share worker threads of
+/// `img_set_thread_count` with other jobs, so queue of background batch jobs limited to fewer
+/// jobs than there are threads leaves threads to interactive jobs of other queues. Queue must
+/// be destroyed by `img_queue_destroy`.
+/// # Safety
+/// `queue` is valid pointer to `void*`.
+unsafe extern "C" fn img_queue_create(max_parallel: u32, queue: *mut QueueHandle) -> ImageError {
+    guard(|| {
+        if queue.is_null() || max_parallel == 0 {
+            return ImageError::Parameter;
+        }
+        *queue = QueueHandle::from_queue(job::Queue::new(max_parallel));
+        ImageError::NoError
+    })
+}
+
+/// Destroys `queue`. Its jobs, running and waiting ones, still run and may be destroyed later.
+/// Null `queue` is ignored.
+/// # Safety
+/// `queue` is null or valid queue handle not destroyed yet.
+unsafe extern "C" fn img_queue_destroy(queue: QueueHandle) {
+    guard(|| {
+        if !queue.0.is_null() {
+            drop(queue.into_queue());
+        }
+    })
+}
+
+/// Starts job as `img_submit_with_token` does, but as one of jobs of `queue` created by
+/// `img_queue_create`: once limit of queue is reached, job waits until one of its jobs finishes,
+/// and waiting jobs start from the one of the highest `priority`, earlier submitted first of
+/// equal ones. E.g. `"open"` job of priority over those of waiting batch jobs starts next. Null
+/// `queue` starts job as `img_submit_with_token` does, ignoring `priority`. Waiting job is
+/// reported as running and may be cancelled by `token` before it starts. If platform can't
+/// spawn threads, job fails with `ImageError::Unsupported`.
+/// # Safety
+/// - `queue` is null or valid queue handle.
+/// - `handle` is valid image handle, or null for `"open"`.
+/// - `op` is valid pointer to null-terminated UTF-8 string.
+/// - `params` is null or valid pointer to null-terminated UTF-8 string.
+/// - `token` is null or valid token handle.
+/// - `job` is valid pointer to `void*`.
+unsafe extern "C" fn img_submit_on(
+    queue: QueueHandle,
+    priority: i32,
+    handle: ImageHandle,
+    op: RawStr,
+    params: RawStr,
+    token: TokenHandle,
+    job: *mut JobHandle,
+) -> ImageError {
+    submit_job(queue.to_queue(), priority, handle, op, params, token, job)
+}
+
+/// Starts job as `img_submit` does, cancelling it once `token` is cancelled by
+/// `img_token_cancel`: blur and resize stop between rows, other operations stop before they
+/// start. Cancelled job fails with `ImageError::Cancelled`. Null `token` means job can't be
+/// cancelled. Job keeps token alive, so it may be destroyed before job finishes.
+/// # Safety
+/// - `handle` is valid image handle, or null for `"open"`.
+/// - `op` is valid pointer to null-terminated UTF-8 string.
+/// - `params` is null or valid pointer to null-terminated UTF-8 string.
+/// - `token` is null or valid token handle.
+/// - `job` is valid pointer to `void*`.
+unsafe extern "C" fn img_submit_with_token(
+    handle: ImageHandle,
+    op: RawStr,
+    params: RawStr,
+    token: TokenHandle,
+    job: *mut JobHandle,
+) -> ImageError {
+    submit_job(None, 0, handle, op, params, token, job)
+}
+
+/// Starts job of `img_submit_with_token` on `queue` with `priority`, or at once without queue.
+unsafe fn submit_job(
+    queue: Option<Arc<job::Queue>>,
+    priority: i32,
+    handle: ImageHandle,
+    op: RawStr,
+    params: RawStr,
+    token: TokenHandle,
+    job: *mut JobHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("submit");
+        if job.is_null() {
+            return ImageError::Parameter;
+        }
+        let op: &str = match (&op).try_into() {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+        let params: &str = match params.0.is_null() {
+            true => "",
+            false => match (&params).try_into() {
+                Ok(p) => p,
+                Err(e) => return e,
+            },
+        };
+        let path = match op {
+            "open" | "save" => {
+                let parsed = match recipe::parse_params(op, params) {
+                    Ok(p) => p,
+                    Err(e) => return e,
+                };
+                let path = parsed.get("path").and_then(|p| p.as_str());
+                match path.map(CString::new) {
+                    Some(Ok(path)) => Some(path),
+                    _ => {
+                        let message = format!("Invalid parameter `path` of `{}`", op);
+                        set_last_error(LastError::new(ImageError::Parameter, message));
+                        return ImageError::Parameter;
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let task = match (op, path) {
+            ("open", Some(path)) => match <&Path>::try_from(&RawPath(path.as_ptr())) {
+                Ok(p) => job::Task::Open(p.to_path_buf()),
+                Err(e) => return e,
+            },
+            (op, path) => {
+                // Jobs work on copy of image, so frozen and locked images are accepted too.
+                if !handle.is_readable() {
+                    return ImageError::InvalidHandle;
+                }
+                let data = handle.read();
+                let (width, height) = data.image.dimensions();
+                if let Err(e) = check_alloc(image_bytes(width, height, data.image.color())) {
+                    return e;
+                }
+                let mut copy = ImageData::new(data.share_image(), data.metadata.clone());
+                copy.annotations = data.annotations.clone();
+                match path {
+                    Some(path) => job::Task::Save(copy, path),
+                    None => job::Task::Apply(copy, op.to_string(), params.to_string()),
+                }
+            }
+        };
+
+        let submitted = match &queue {
+            Some(queue) => Ok(job::submit_on(queue, priority, task, token.to_token())),
+            None => job::submit(task, token.to_token()),
+        };
+        match submitted {
+            Ok(j) => {
+                *job = JobHandle::from_job(j);
+                ImageError::NoError
+            }
+            Err(e) => e,
+        }
+    })
+}
+
+/// Returns progress of `job`. Null `job` is reported as failed.
+/// # Safety
+/// `job` is null or valid job handle.
+unsafe extern "C" fn img_job_status(job: JobHandle) -> JobStatus {
+    guard(|| match job.0.is_null() {
+        true => JobStatus::Failed,
+        false => job.as_job().status(),
+    })
+}
+
+/// Waits until `job` finishes and writes handle of its resulting image to `result`, or null for
+/// `"save"` jobs. Result can be taken once, later calls fail with `ImageError::Parameter`. If job
+/// failed, returns its error and sets the last error of calling thread to its details every
+/// time.
+/// # Safety
+/// - `job` is valid job handle.
+/// - `result` is valid pointer to `void*`.
+unsafe extern "C" fn img_job_result(job: JobHandle, result: *mut ImageHandle) -> ImageError {
+    guard(|| {
+        if job.0.is_null() || result.is_null() {
+            return ImageError::Parameter;
+        }
+        match job.as_job().wait() {
+            Ok(Some(data)) => {
+                let handle = ImageHandle::from_box(data);
+                let _ = handles::attach(handle.0 as usize);
+                *result = handle;
+                ImageError::NoError
+            }
+            Ok(None) => {
+                *result = ImageHandle(std::ptr::null_mut());
+                ImageError::NoError
+            }
+            Err(error) => {
+                // Error was already logged by worker thread.
+                let code = error.code;
+                LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+                code
+            }
+        }
+    })
+}
+
+/// Destroys `job`. Running job finishes in background and its result is destroyed. Result taken
+/// by `img_job_result` stays valid. Null `job` is ignored. Library must not be unloaded while
+/// jobs run.
+/// # Safety
+/// `job` is null or valid job handle not destroyed yet.
+unsafe extern "C" fn img_job_destroy(job: JobHandle) {
+    guard(|| {
+        if !job.0.is_null() {
+            drop(job.into_job());
+        }
+    })
+}
+
+/// Creates cancellation token and writes its handle to `token`. Host passes token to operations
+/// accepting it and cancels them all at once by `img_token_cancel`, e.g. when user presses
+/// "Cancel". Token must be destroyed by `img_token_destroy`.
+/// # Safety
+/// `token` is valid pointer to `void*`.
+unsafe extern "C" fn img_token_create(token: *mut TokenHandle) -> ImageError {
+    guard(|| {
+        if token.is_null() {
+            return ImageError::Parameter;
+        }
+        *token = TokenHandle::from_token(Default::default());
+        ImageError::NoError
+    })
+}
+
+/// Cancels operations `token` was passed to, including ones started with it later. Cancelled
+/// operations fail with `ImageError::Cancelled` as soon as they notice it. Cancelling token
+/// again does nothing. Null `token` is ignored.
+/// # Safety
+/// `token` is null or valid token handle.
+unsafe extern "C" fn img_token_cancel(token: TokenHandle) {
+    guard(|| {
+        if let Some(token) = token.to_token() {
+            token.cancel();
+        }
+    })
+}
+
+/// Destroys `token`. Operations it was passed to keep running and can't be cancelled anymore,
+/// unless token was already cancelled. Null `token` is ignored.
+/// # Safety
+/// `token` is null or valid token handle not destroyed yet.
+unsafe extern "C" fn img_token_destroy(token: TokenHandle) {
+    guard(|| {
+        if !token.0.is_null() {
+            drop(token.into_token());
+        }
+    })
+}
+
+/// Decodes files at `count` `paths`, applies edits of edit stack `pipeline` to each one and
+/// encodes results into `out_dir`, processing files in parallel on worker threads of library.
+/// `pipeline` is edit stack as written by `img_export_edit_stack`, e.g.
+/// `{"version": 1, "edits": [{"op": "mirror"}]}`. Null `pipeline` means no edits, so files are
+/// only converted. Output has file name of source with extension of `options`, if set, and
+/// replaces existing file. Output directory is created if missing. If `options` is null, default
+/// options are used.
+///
+/// Returns `ImageError::NoError` once every file was processed, also if some of them failed:
+/// outcome of each file is written to `results` array of `count` values unless it is null, and
+/// failed files are logged with their paths. Other errors mean that batch itself failed, e.g.
+/// on invalid `pipeline`. Once progress callback or token of `options` aborts batch, remaining
+/// files are skipped with `ImageError::Cancelled` and it is returned.
+/// # Safety
+/// - `paths` is valid pointer to `count` pointers to null-terminated UTF-8 strings.
+/// - `pipeline` is null or valid pointer to null-terminated UTF-8 string.
+/// - `out_dir` is valid pointer to null-terminated UTF-8 string.
+/// - `options` is null or valid pointer to `BatchOptions`, whose progress callback and user data
+///   are usable from worker threads of library.
+/// - `results` is null or valid pointer to `count` writable `ImageError` values.
+unsafe extern "C" fn img_batch(
+    paths: *const RawPath,
+    count: usize,
+    pipeline: RawStr,
+    out_dir: RawPath,
+    options: *const BatchOptions,
+    results: *mut ImageError,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("batch");
+        if (paths.is_null() && count != 0) || out_dir.0.is_null() {
+            return ImageError::Parameter;
+        }
+        let options = match BatchOptions::from_ptr(options) {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+        let edits = match pipeline.0.is_null() {
+            true => Vec::new(),
+            false => match (&pipeline).try_into().and_then(recipe::parse) {
+                Ok(edits) => edits,
+                Err(e) => return e,
+            },
+        };
+        let extension = match options.extension.0.is_null() {
+            true => None,
+            false => match <&str>::try_from(&options.extension) {
+                Ok(e) => Some(e.trim_start_matches('.').to_string()),
+                Err(e) => return e,
+            },
+        };
+        let out_dir: &Path = match (&out_dir).try_into() {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let paths = match count {
+            0 => &[],
+            count => std::slice::from_raw_parts(paths, count),
+        };
+        let sources = paths
+            .iter()
+            .map(|p| <&Path>::try_from(p).map(Path::to_path_buf));
+        let sources = match sources.collect::<Result<Vec<_>, _>>() {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+        if let Err(e) = std::fs::create_dir_all(out_dir) {
+            return io_error(e);
+        }
+
+        let batch = batch::Batch {
+            edits,
+            out_dir: out_dir.to_path_buf(),
+            extension,
+            options: SaveOptions {
+                jpeg_quality: options.jpeg_quality,
+                keep_metadata: options.keep_metadata,
+                ..Default::default()
+            },
+        };
+        let mut outcomes = Vec::new();
+        let token = options.token.to_token();
+        let code = progress::run_cancellable(options.progress_fn, options.user_data, token, || {
+            outcomes = batch.run(&sources);
+            ImageError::NoError
+        });
+
+        if !results.is_null() {
+            let results = std::slice::from_raw_parts_mut(results, count);
+            for (i, result) in results.iter_mut().enumerate() {
+                *result = outcomes.get(i).copied().unwrap_or(ImageError::Cancelled);
+            }
+        }
+        code
+    })
+}
+
+/// Creates empty operation pipeline. Operations appended to it are applied to image at once by
+/// `img_pipeline_execute`, which reads crops directly from previous image instead of copying
+/// them and reuses intermediate buffers between operations and executions. So pipeline is meant
+/// to be created once and executed for many images. Pipeline must be used by one thread at a
+/// time and destroyed with `img_pipeline_destroy`.
+/// # Safety
+/// `pipeline` is valid pointer to `void*`.
+unsafe extern "C" fn img_pipeline_create(pipeline: *mut PipelineHandle) -> ImageError {
+    guard(|| {
+        if pipeline.is_null() {
+            return ImageError::Parameter;
+        }
+        *pipeline = PipelineHandle::from_pipeline(Default::default());
+        ImageError::NoError
+    })
+}
+
+/// Destroys operation pipeline with its buffers.
+unsafe extern "C" fn img_pipeline_destroy(pipeline: PipelineHandle) {
+    guard(|| {
+        if !pipeline.0.is_null() {
+            pipeline.into_pipeline();
+        }
+    })
+}
+
+/// Appends operation to pipeline, whose parameters are checked against image on execution.
+unsafe fn add_pipeline_step(pipeline: PipelineHandle, step: pipeline::Step) -> ImageError {
+    guard(|| {
+        if pipeline.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+        pipeline.as_pipeline().push(step);
+        ImageError::NoError
+    })
+}
+
+/// Appends crop keeping `rect` of image, in coordinates of image given by previous operations.
+/// # Safety
+/// `pipeline` is valid pipeline handle.
+unsafe extern "C" fn img_pipeline_add_crop(pipeline: PipelineHandle, rect: Rect) -> ImageError {
+    add_pipeline_step(pipeline, pipeline::Step::Crop(rect))
+}
+
+/// Appends resize to `width` x `height` with Lanczos filter, as `img_resize_with_progress`
+/// does. Resize keeps color type of image.
+/// # Safety
+/// `pipeline` is valid pipeline handle.
+unsafe extern "C" fn img_pipeline_add_resize(
+    pipeline: PipelineHandle,
+    width: u32,
+    height: u32,
+) -> ImageError {
+    add_pipeline_step(pipeline, pipeline::Step::Resize(width, height))
+}
+
+/// Appends gaussian blur with standard deviation `sigma`, as `img_blur2` does. Image is converted
+/// to RGBA before the first blur.
+/// # Safety
+/// `pipeline` is valid pipeline handle.
+unsafe extern "C" fn img_pipeline_add_blur(pipeline: PipelineHandle, sigma: f32) -> ImageError {
+    add_pipeline_step(pipeline, pipeline::Step::Blur(sigma))
+}
+
+/// Appends horizontal flip.
+/// # Safety
+/// `pipeline` is valid pipeline handle.
+unsafe extern "C" fn img_pipeline_add_mirror(pipeline: PipelineHandle) -> ImageError {
+    add_pipeline_step(pipeline, pipeline::Step::Mirror)
+}
+
+/// Applies operations of pipeline to image in place, in order they were appended. Pipeline is
+/// recorded as one step of edit stack. Returns `ImageError::Parameter` if crop doesn't fit or
+/// resize gives empty image, and `ImageError::LimitExceeded` or `ImageError::OutOfMemory` if some
+/// image can't be allocated, leaving image unchanged in these cases.
+/// # Safety
+/// - `pipeline` is valid pipeline handle.
+/// - `handle` is valid image handle.
+unsafe extern "C" fn img_pipeline_execute(
+    pipeline: PipelineHandle,
+    handle: ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("pipeline");
+        if !handle.is_valid() || pipeline.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+
+        match pipeline.as_pipeline().apply(handle.as_data()) {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Applies operations of pipeline to 8 or 16 bit gray, RGB or RGBA TIFF image at `src` and saves
+/// result as uncompressed TIFF image at `dst`, for images too large to be held in memory, e.g.
+/// gigapixel panoramas. Source is read strip by strip and only rows the next rows of result need
+/// are kept, e.g. rows under blur kernel, while rows of result are written once they are ready.
+/// Result is the same as of `img_pipeline_execute`. Reports progress to `progress_fn` with
+/// `user_data` unless it's null, and is aborted with `ImageError::Cancelled` once it returns
+/// `false`. Returns `ImageError::Unsupported` for other color types and tiled TIFF files, and
+/// `ImageError::Parameter` if `dst` is `src`. On failure `dst` is removed.
+/// # Safety
+/// - `pipeline` is valid pipeline handle.
+/// - `src` and `dst` are valid paths.
+/// - `progress_fn` is null or safe to call with `user_data`.
+unsafe extern "C" fn img_pipeline_execute_tiled(
+    pipeline: PipelineHandle,
+    src: RawPath,
+    dst: RawPath,
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("pipeline_tiled");
+        if pipeline.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+        if src.0.is_null() || dst.0.is_null() {
+            return ImageError::Parameter;
+        }
+        let (src, dst): (&Path, &Path) = match ((&src).try_into(), (&dst).try_into()) {
+            (Ok(src), Ok(dst)) => (src, dst),
+            (Err(e), _) | (_, Err(e)) => return e,
+        };
+        let pipeline = pipeline.as_pipeline();
+        progress::run(progress_fn, user_data, || {
+            match tiled::execute(pipeline, src, dst) {
+                Ok(_) => ImageError::NoError,
+                Err(e) => e,
+            }
+        })
+    })
+}
+
+/// Decodes image from `read_fn`, applies operations of pipeline as `img_pipeline_execute` does
+/// and streams image encoded in `format` (one of `ImageFormat` values) to `write_fn`. Input is
+/// read as `img_transcode_stream2` reads it, limited by `max_input` bytes or by memory limit of
+/// `img_set_limits` if it is zero. With cache enabled by `image_sl_enable_cache`, result of the
+/// same input, operations and format is written from cache without decoding input.
+/// # Safety
+/// - `pipeline` is valid pipeline handle.
+/// - `read_fn` and `write_fn` are safe to call with `user_data`.
+unsafe extern "C" fn img_pipeline_transcode(
+    pipeline: PipelineHandle,
+    read_fn: ReadFn,
+    write_fn: WriteFn,
+    format: u32,
+    max_input: u64,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("pipeline_transcode");
+        if pipeline.0.is_null() {
+            return ImageError::InvalidHandle;
+        }
+        let format = match ImageFormat::try_from(format) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+        let pipeline = pipeline.as_pipeline();
+        let reader = CallbackReader {
+            read_fn,
+            seek_fn: None,
+            user_data,
+        };
+        let input = match read_input(reader, max_input) {
+            Ok(i) => i,
+            Err(e) => return e,
+        };
+        let mut writer = CallbackWriter {
+            write_fn,
+            user_data,
+        };
+
+        // Results may change with version of library, so it is part of key too.
+        let key = cache::is_enabled().then(|| {
+            let version = env!("CARGO_PKG_VERSION").as_bytes();
+            let steps = pipeline.to_json().to_string();
+            let format = (format as u32).to_le_bytes();
+            cache::key(&[version, steps.as_bytes(), &format, &input])
+        });
+        if let Some(cached) = key.as_deref().and_then(cache::get) {
+            return match writer.write_all(&cached) {
+                Ok(_) => ImageError::NoError,
+                Err(e) => io_error(e),
+            };
+        }
+
+        let mut data = match decode_input(input) {
+            Ok((image, _)) => ImageData::new(image, Default::default()),
+            Err(e) => return e,
+        };
+        if let Err(e) = pipeline.apply(&mut data) {
+            return e;
+        }
+        let options = SaveOptions::default();
+        let written = match key {
+            Some(key) => {
+                let mut encoded = Vec::new();
+                write_encoded(&data, format, &options, &mut encoded).and_then(|_| {
+                    cache::put(&key, &encoded);
+                    writer.write_all(&encoded).map_err(io_error)
+                })
+            }
+            None => {
+                let mut writer = BufWriter::new(writer);
+                write_encoded(&data, format, &options, &mut writer)
+                    .and_then(|_| writer.flush().map_err(io_error))
+            }
+        };
+        match written {
+            Ok(_) => ImageError::NoError,
+            Err(e) => e,
+        }
+    })
+}
+
+/// Draws guides described by `spec` over image. Null `spec` means default guides:
+/// translucent white rule-of-thirds grid.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `spec` is null or valid pointer to `GuideSpec`.
+unsafe extern "C" fn img_draw_guides(handle: ImageHandle, spec: *const GuideSpec) -> ImageError {
+    guard(|| {
+        let _span = trace::span("draw_guides");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        let spec = match GuideSpec::from_ptr(spec) {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+
+        handle.as_data().checkpoint(json!({
+            "op": "draw_guides",
+            "guides": spec.guides,
+            "color": u32::from_be_bytes(spec.color),
+            "line_width": spec.line_width,
+            "safe_margin": spec.safe_margin,
+        }));
+        spec.draw(handle.as_image_mut());
+        ImageError::NoError
+    })
+}
+
+/// Draws antialiased line from `x0`, `y0` to `x1`, `y1` of `thickness` pixels with round caps
+/// in `color` given as `0xRRGGBBAA` over image, e.g. for markers of inspection overlays.
+/// Coordinates are in pixels, where `(0, 0)` is top left corner of image, so centers of pixels
+/// are at half-integers. Color is blended by its alpha.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_draw_line(
+    handle: ImageHandle,
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    thickness: f32,
+    color: u32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("draw_line");
+        let step = json!({
+            "op": "draw_line",
+            "x0": x0,
+            "y0": y0,
+            "x1": x1,
+            "y1": y1,
+            "thickness": thickness,
+            "color": color,
+        });
+        let shape = draw::Shape::Line([x0, y0], [x1, y1]);
+        draw_shape(handle, step, shape, thickness, false, color)
+    })
+}
+
+/// Draws antialiased rectangle at `x`, `y` of `width` x `height` pixels over image as
+/// `img_draw_line` draws lines, e.g. for bounding boxes. Fills rectangle if `fill` is set,
+/// otherwise strokes its outline with line of `thickness` pixels centered on it.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_draw_rect(
+    handle: ImageHandle,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    thickness: f32,
+    color: u32,
+    fill: bool,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("draw_rect");
+        let step = json!({
+            "op": "draw_rect",
+            "x": x,
+            "y": y,
+            "width": width,
+            "height": height,
+            "thickness": thickness,
+            "color": color,
+            "fill": fill,
+        });
+        let corners = [
+            [x, y],
+            [x + width, y],
+            [x + width, y + height],
+            [x, y + height],
+        ];
+        let shape = draw::Shape::Polygon(&corners);
+        draw_shape(handle, step, shape, thickness, fill, color)
+    })
+}
+
+/// Draws antialiased circle of `radius` pixels centered at `cx`, `cy` over image as
+/// `img_draw_rect` draws rectangles.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_draw_circle(
+    handle: ImageHandle,
+    cx: f32,
+    cy: f32,
+    radius: f32,
+    thickness: f32,
+    color: u32,
+    fill: bool,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("draw_circle");
+        let step = json!({
+            "op": "draw_circle",
+            "cx": cx,
+            "cy": cy,
+            "radius": radius,
+            "thickness": thickness,
+            "color": color,
+            "fill": fill,
+        });
+        let shape = draw::Shape::Circle([cx, cy], radius);
+        draw_shape(handle, step, shape, thickness, fill, color)
+    })
+}
+
+/// Draws antialiased closed polygon of `count` `points`, at least 3, over image as
+/// `img_draw_rect` draws rectangles. Polygon may intersect itself; its inside is given by
+/// non-zero winding rule.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `points` is valid pointer to `count` readable `Point`s.
+unsafe extern "C" fn img_draw_polygon(
+    handle: ImageHandle,
+    points: *const Point,
+    count: usize,
+    thickness: f32,
+    color: u32,
+    fill: bool,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("draw_polygon");
+        if points.is_null() {
+            return ImageError::Parameter;
+        }
+        let points = std::slice::from_raw_parts(points, count);
+        let vertices: Vec<_> = points.iter().map(|p| [p.x, p.y]).collect();
+        let step = json!({
+            "op": "draw_polygon",
+            "points": vertices.concat(),
+            "thickness": thickness,
+            "color": color,
+            "fill": fill,
+        });
+        let shape = draw::Shape::Polygon(&vertices);
+        draw_shape(handle, step, shape, thickness, fill, color)
+    })
+}
+
+/// Draws `shape` over image for drawing functions, recording `step` describing it in edit stack
+/// of image. Stroke `thickness` must be positive unless shape is filled.
+unsafe fn draw_shape(
+    handle: ImageHandle,
+    step: serde_json::Value,
+    shape: draw::Shape,
+    thickness: f32,
+    fill: bool,
+    color: u32,
+) -> ImageError {
+    if !handle.is_valid() {
+        return ImageError::InvalidHandle;
+    }
+    let stroked = !fill || matches!(shape, draw::Shape::Line(..));
+    if !shape.is_valid() || !thickness.is_finite() || (stroked && thickness <= 0.0) {
+        return ImageError::Parameter;
+    }
+
+    handle.as_data().checkpoint(step);
+    let color = image::Rgba(color.to_be_bytes());
+    draw::draw_shape(handle.as_image_mut(), &shape, thickness, fill, color);
+    ImageError::NoError
+}
+
+/// Blends `mark` image, e.g. logo, over image at `anchor`, one of `Anchor` values, `margin`
+/// pixels away from edges of image. Alpha of mark is multiplied by `opacity` in `[0, 1]`.
+/// Positive `scale` resizes mark keeping its aspect ratio to `scale` times width of image, zero
+/// keeps its size. Mark may be view or image itself and is clipped by image bounds.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `mark` is valid image handle.
+unsafe extern "C" fn img_watermark(
+    handle: ImageHandle,
+    mark: ImageHandle,
+    anchor: u32,
+    margin: u32,
+    opacity: f32,
+    scale: f32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("watermark");
+        if !handle.is_valid() || !mark.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let anchor = match Anchor::try_from(anchor) {
+            Ok(a) => a,
+            Err(e) => return e,
+        };
+        if !(0.0..=1.0).contains(&opacity) || !scale.is_finite() || scale < 0.0 {
+            return ImageError::Parameter;
+        }
+
+        let (width, height) = handle.as_data_ref().image.dimensions();
+        let (source, region) = mark.as_region();
+        let size = match scale > 0.0 {
+            true => {
+                let w = (width as f64 * scale as f64).round().max(1.0);
+                let h = region.height as f64 * w / region.width.max(1) as f64;
+                (
+                    w.min(u32::MAX as f64) as u32,
+                    h.round().clamp(1.0, u32::MAX as f64) as u32,
+                )
+            }
+            false => (region.width, region.height),
+        };
+        let bytes = image_bytes(region.width, region.height, image::ColorType::Rgba8)
+            + image_bytes(size.0, size.1, image::ColorType::Rgba8);
+        if let Err(e) = check_alloc(bytes) {
+            return e;
+        }
+        // Mark is copied before image changes, as it may share pixels with image.
+        let view = source.view(region.x, region.y, region.width, region.height);
+        let mut stamp = view.to_image();
+        if size != stamp.dimensions() {
+            stamp = parallel::resize(&stamp, size.0, size.1);
+        }
+
+        let (x, y) = anchor.place((width, height), size, margin);
+        handle.as_data().checkpoint(json!({
+            "op": "watermark",
+            "anchor": anchor as u32,
+            "margin": margin,
+            "opacity": opacity,
+            "scale": scale,
+        }));
+        draw::stamp(handle.as_image_mut(), &stamp, x, y, opacity);
+        ImageError::NoError
+    })
+}
+
+/// Makes corners of image transparent outside arcs of `radius` pixels, antialiasing their edges,
+/// e.g. for avatars and cards. Radius is limited to half the shorter side of image. Image is
+/// converted to RGBA of its bit depth, `ColorType::Rgba8` or `ColorType::Rgba16`.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_round_corners(handle: ImageHandle, radius: f32) -> ImageError {
+    guard(|| {
+        let _span = trace::span("round_corners");
+        if !radius.is_finite() || radius < 0.0 {
+            return ImageError::Parameter;
+        }
+        let step = json!({"op": "round_corners", "radius": radius});
+        mask_alpha(handle, step, AlphaMask::Corners(radius))
+    })
+}
+
+/// Makes image transparent outside of circle of diameter of its shorter side in its center,
+/// antialiasing its edge, e.g. for round avatars. Image is converted to RGBA of its bit depth,
+/// `ColorType::Rgba8` or `ColorType::Rgba16`.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe extern "C" fn img_circle_mask(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        let _span = trace::span("circle_mask");
+        mask_alpha(handle, json!({"op": "circle_mask"}), AlphaMask::Circle)
+    })
+}
+
+/// Multiplies alpha of image by luminance of `mask` image of the same size, or by its complement
+/// if `invert` is set, e.g. to cut out shapes drawn, generated or loaded as grayscale masks:
+/// white parts of mask keep image and black ones make it transparent. Alpha of mask is ignored.
+/// Image is converted to RGBA of its bit depth, `ColorType::Rgba8` or `ColorType::Rgba16`. Mask
+/// may be view or image itself. Returns `ImageError::Parameter` with
+/// `ErrorSubCode::DimensionMismatch` if sizes of images differ.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `mask` is valid image handle.
+unsafe extern "C" fn img_apply_mask(
+    handle: ImageHandle,
+    mask: ImageHandle,
+    invert: bool,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("apply_mask");
+        if !handle.is_valid() || !mask.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let (_, target) = handle.as_region();
+        let (source, region) = mask.as_region();
+        if let Err(e) = check_same_size(target, region) {
+            return e;
+        }
+        let bytes = image_bytes(region.width, region.height, source.color())
+            + image_bytes(region.width, region.height, image::ColorType::Rgba16);
+        if let Err(e) = check_alloc(bytes) {
+            return e;
+        }
+
+        // Mask is copied before image changes, as it may share pixels with image.
+        let cropped;
+        let source = match (region.width, region.height) == source.dimensions() {
+            true => source,
+            false => {
+                let (x, y, w, h) = (region.x, region.y, region.width, region.height);
+                cropped = source.crop_imm(x, y, w, h);
+                &cropped
+            }
+        };
+        // Conversion widens 8-bit channels to full range, so white mask keeps image opaque.
+        let wide = ColorType::Rgba16.convert(source);
+        let pixels = wide.expect("color type of images").into_rgba16();
+        let step = json!({"op": "apply_mask", "invert": invert});
+        mask_alpha(handle, step, AlphaMask::Luma(pixels, invert))
+    })
+}
+
+/// Masks of alpha applied by `mask_alpha`.
+enum AlphaMask {
+    /// Corners outside arcs of radius are transparent.
+    Corners(f32),
+    /// Image outside of circle in its center is transparent.
+    Circle,
+    /// Alpha is multiplied by luma of mask of image size, inverted if flag is set.
+    Luma(image::ImageBuffer<image::Rgba<u16>, Vec<u16>>, bool),
+}
+
+/// Converts image to RGBA of its bit depth and applies `mask` to its alpha for masking
+/// functions, recording `step` describing it in edit stack.
+unsafe fn mask_alpha(handle: ImageHandle, step: serde_json::Value, mask: AlphaMask) -> ImageError {
+    if !handle.is_valid() {
+        return ImageError::InvalidHandle;
+    }
+    let data = handle.as_data();
+    let (width, height) = data.image.dimensions();
+    let deep = matches!(
+        data.image.color(),
+        image::ColorType::L16
+            | image::ColorType::La16
+            | image::ColorType::Rgb16
+            | image::ColorType::Rgba16
+    );
+    let color = match deep {
+        true => image::ColorType::Rgba16,
+        false => image::ColorType::Rgba8,
+    };
+    if data.image.color() != color {
+        if let Err(e) = check_alloc(image_bytes(width, height, color)) {
+            return e;
+        }
+    }
+
+    data.checkpoint(step);
+    let image = handle.as_image_mut();
+    match (deep, image) {
+        (true, DynamicImage::ImageRgba16(_)) | (false, DynamicImage::ImageRgba8(_)) => {}
+        (true, image) => *image = DynamicImage::ImageRgba16(image.to_rgba16()),
+        (false, image) => *image = DynamicImage::ImageRgba8(image.to_rgba8()),
+    }
+    match (handle.as_image_mut(), mask) {
+        (DynamicImage::ImageRgba16(image), AlphaMask::Corners(radius)) => {
+            draw::round_corners(image, radius)
+        }
+        (DynamicImage::ImageRgba16(image), AlphaMask::Circle) => draw::circle_mask(image),
+        (DynamicImage::ImageRgba16(image), AlphaMask::Luma(mask, invert)) => {
+            draw::luma_mask(image, &mask, invert)
+        }
+        (DynamicImage::ImageRgba8(image), AlphaMask::Corners(radius)) => {
+            draw::round_corners(image, radius)
+        }
+        (DynamicImage::ImageRgba8(image), AlphaMask::Circle) => draw::circle_mask(image),
+        (DynamicImage::ImageRgba8(image), AlphaMask::Luma(mask, invert)) => {
+            draw::luma_mask(image, &mask, invert)
+        }
+        _ => unreachable!("image is converted to RGBA"),
+    }
+    ImageError::NoError
+}
+
+/// Packs `count` images of `handles` into RGBA8 sprite sheet (atlas) at most `max_width` pixels
+/// wide and writes its handle to `sheet`. Images are placed on rows from the tallest one with
+/// `padding` transparent pixels between them, and region of image `i` in sheet is written to
+/// `placements[i]`. Images may be views. Returns `ImageError::Parameter` if some image is wider
+/// than `max_width`.
+/// # Safety
+/// - `handles` is valid pointer to `count` valid image handles.
+/// - `sheet` is valid pointer to `ImageHandle`.
+/// - `placements` is valid pointer to `count` writable `Rect`s.
+unsafe extern "C" fn img_pack_sprites(
+    handles: *const ImageHandle,
+    count: usize,
+    max_width: u32,
+    padding: u32,
+    sheet: *mut ImageHandle,
+    placements: *mut Rect,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("pack_sprites");
+        if handles.is_null() || count == 0 || sheet.is_null() || placements.is_null() {
+            return ImageError::Parameter;
+        }
+        let handles = std::slice::from_raw_parts(handles, count);
+        if !handles.iter().all(|h| h.is_readable()) {
+            return ImageError::InvalidHandle;
+        }
+
+        let regions: Vec<_> = handles.iter().map(|h| h.as_region()).collect();
+        let sizes: Vec<_> = regions.iter().map(|(_, r)| (r.width, r.height)).collect();
+        let (rects, width, height) = match atlas::pack(&sizes, max_width, padding) {
+            Some(packed) => packed,
+            None => {
+                let message = format!("Sprites don't fit sheet {} pixels wide", max_width);
+                set_last_error(LastError::new(ImageError::Parameter, message));
+                return ImageError::Parameter;
+            }
+        };
+        if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::Rgba8)) {
+            return e;
+        }
+
+        let mut atlas: image::RgbaImage = match try_image(width, height) {
+            Ok(a) => a,
+            Err(e) => return e,
+        };
+        for ((image, region), rect) in regions.iter().zip(&rects) {
+            let view = image.view(region.x, region.y, region.width, region.height);
+            for (x, y, pixel) in view.pixels() {
+                atlas.put_pixel(rect.x + x, rect.y + y, pixel);
+            }
+        }
+        std::slice::from_raw_parts_mut(placements, count).copy_from_slice(&rects);
+        *sheet = ImageHandle::from_image(DynamicImage::ImageRgba8(atlas));
+        ImageError::NoError
+    })
+}
+
+/// Estimates standard deviation of noise of red, green, blue and alpha channels of image and
+/// writes it to `out_sigma`. Sigmas are in `[0, 1]` units of channel range. Returns
+/// `ImageError::Parameter` if image is smaller than 3x3 pixels.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `out_sigma` is valid pointer to 4 writable `double`s.
+unsafe extern "C" fn img_estimate_noise(handle: ImageHandle, out_sigma: *mut f64) -> ImageError {
+    guard(|| {
+        let _span = trace::span("estimate_noise");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if out_sigma.is_null() {
+            return ImageError::Parameter;
+        }
+
+        match analysis::estimate_noise(&handle.read().image) {
+            Some(sigmas) => {
+                std::ptr::copy_nonoverlapping(sigmas.as_ptr(), out_sigma, sigmas.len());
+                ImageError::NoError
+            }
+            None => ImageError::Parameter,
+        }
+    })
+}
+
+/// Estimates sharpness of image as standard deviation of Laplacian of its luma in `[0, 1]` units
+/// of channel range and writes it to `sharpness`, e.g. to flag out-of-focus shots of ingest
+/// pipeline. Blurry images have lower values; threshold depends on content and resolution, so
+/// it's best tuned on sample images. Returns
+/// `ImageError::Parameter` if image is smaller than 3x3 pixels.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `sharpness` is valid pointer to writable `double`.
+unsafe extern "C" fn img_sharpness(handle: ImageHandle, sharpness: *mut f64) -> ImageError {
+    guard(|| {
+        let _span = trace::span("sharpness");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if sharpness.is_null() {
+            return ImageError::Parameter;
+        }
+
+        match analysis::estimate_sharpness(&handle.read().image) {
+            Some(estimate) => {
+                *sharpness = estimate;
+                ImageError::NoError
+            }
+            None => ImageError::Parameter,
+        }
+    })
+}
+
+/// Destroys image created by this library. With debug registry enabled, destroying image twice
+/// is reported to log instead of being undefined behavior. Frozen image may be destroyed on any
+/// thread once no thread reads it.
+unsafe extern "C" fn img_destroy(handle: ImageHandle) {
+    guard(|| {
+        if handles::status(handle.0 as usize) == Some(handles::Status::Destroyed) {
+            let message = format!("Image handle {:p} is destroyed twice", handle.0);
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return;
+        }
+        if handle.is_readable() {
+            handle.into_data();
+        }
+    })
+}
+
+/// Converts image to `color_type`, one of `ColorType` values, and writes handle of new image to
+/// `converted`, e.g. to upload it as GPU texture of known pixel layout. 8-bit channels converted
+/// to 16 bits are scaled to full range, 16-bit ones are reduced to their high bytes and gray is
+/// computed as luminance of color. Images can't have `ColorType::Rgba32F` channels, so
+/// converting to it fails with `ImageError::Unsupported`. New image inherits metadata,
+/// annotations and edits of source image.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `converted` is valid pointer to `void*`.
+unsafe extern "C" fn img_convert(
+    handle: ImageHandle,
+    color_type: u32,
+    converted: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("convert");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let color_type = match ColorType::try_from(color_type) {
+            Ok(c) if !converted.is_null() => c,
+            _ => return ImageError::Parameter,
+        };
+        let color = match color_type.to_image() {
+            Some(c) => c,
+            None => return float_images_unsupported(),
+        };
+
+        let data = handle.read();
+        let (width, height) = data.image.dimensions();
+        if let Err(e) = check_alloc(image_bytes(width, height, color)) {
+            return e;
+        }
+        let image = color_type
+            .convert(&data.image)
+            .expect("color type of images");
+        let mut copy = ImageData::new(image, data.metadata.clone());
+        copy.annotations = data.annotations.clone();
+        let mut edits = data.journal.edits().to_vec();
+        edits.push(json!({"op": "convert", "color_type": color_type as u32}));
+        copy.journal.set_edits(edits);
+        *converted = ImageHandle::from_data(copy);
+        ImageError::NoError
+    })
+}
+
+/// Reorders channels of image in place: channel `i` becomes channel `mapping[i]` of image before
+/// reordering, e.g. `[2, 1, 0, 3]` swaps red and blue to fix RGB read as BGR, and `[0, 0, 2, 3]`
+/// copies red channel of texture map to green one. Channels are numbered in order of color type
+/// of image, e.g. luma and alpha for `ColorType::La8`, and entries past its channel count are
+/// ignored. Returns `ImageError::Parameter` if entry refers to missing channel.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `mapping` is valid pointer to 4 bytes.
+unsafe extern "C" fn img_swap_channels(handle: ImageHandle, mapping: *const [u8; 4]) -> ImageError {
+    guard(|| {
+        let _span = trace::span("swap_channels");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        if mapping.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let mapping = *mapping;
+        let image = handle.as_image();
+        let (width, channels) = (
+            image.width() as usize,
+            image.color().channel_count() as usize,
+        );
+        if let Some(&c) = mapping[..channels]
+            .iter()
+            .find(|&&c| c as usize >= channels)
+        {
+            let message = format!("Image has no channel {}, only {}", c, channels);
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return ImageError::Parameter;
+        }
+
+        let step = json!({"op": "swap_channels", "mapping": mapping});
+        handle.as_data().checkpoint(step);
+        match handle.as_image_mut() {
+            DynamicImage::ImageLuma8(b) => swap_channels(b, width, 1, mapping),
+            DynamicImage::ImageLumaA8(b) => swap_channels(b, width, 2, mapping),
+            DynamicImage::ImageRgb8(b) => swap_channels(b, width, 3, mapping),
+            DynamicImage::ImageRgba8(b) => swap_channels(b, width, 4, mapping),
+            DynamicImage::ImageBgr8(b) => swap_channels(b, width, 3, mapping),
+            DynamicImage::ImageBgra8(b) => swap_channels(b, width, 4, mapping),
+            DynamicImage::ImageLuma16(b) => swap_channels(b, width, 1, mapping),
+            DynamicImage::ImageLumaA16(b) => swap_channels(b, width, 2, mapping),
+            DynamicImage::ImageRgb16(b) => swap_channels(b, width, 3, mapping),
+            DynamicImage::ImageRgba16(b) => swap_channels(b, width, 4, mapping),
+        }
+        ImageError::NoError
+    })
+}
+
+/// Reorders subpixels of every pixel of image `width` pixels wide with `channels` subpixels
+/// each by `mapping` checked by `img_swap_channels`.
+fn swap_channels<S: Copy + Send>(
+    subpixels: &mut [S],
+    width: usize,
+    channels: usize,
+    mapping: [u8; 4],
+) {
+    if mapping[..channels]
+        .iter()
+        .enumerate()
+        .all(|(i, &c)| c as usize == i)
+    {
+        return;
+    }
+    parallel::for_each_row(subpixels, width * channels, |_, row| {
+        for pixel in row.chunks_exact_mut(channels) {
+            let source: [S; 4] = std::array::from_fn(|i| pixel[i.min(channels - 1)]);
+            for (i, subpixel) in pixel.iter_mut().enumerate() {
+                *subpixel = source[mapping[i] as usize];
+            }
+        }
+    });
+}
+
+/// Counts values of `channel` of image in 256 `bins`, e.g. to draw histogram of image or drive
+/// auto-exposure without copying its pixels. Channels are numbered in order of color type of
+/// image, as for `img_swap_channels`, and 16-bit values are counted by their high bytes. Views
+/// count pixels of their region. Returns `ImageError::Parameter` if image has no `channel`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `bins` is valid pointer to 256 writable `uint32_t`s.
+unsafe extern "C" fn img_histogram(
+    handle: ImageHandle,
+    channel: u32,
+    bins: *mut u32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("histogram");
+        histogram(handle, channel, bins, 256)
+    })
+}
+
+/// Counts values of `channel` of image in 65536 `bins`, as `img_histogram` does, so 16-bit
+/// images keep full precision. 8-bit value `v` is counted in bin `v * 257`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `bins` is valid pointer to 65536 writable `uint32_t`s.
+unsafe extern "C" fn img_histogram16(
+    handle: ImageHandle,
+    channel: u32,
+    bins: *mut u32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("histogram16");
+        histogram(handle, channel, bins, 1 << 16)
+    })
+}
+
+/// Writes minimum, maximum, mean and standard deviation of every channel of image and its mean
+/// luma to `stats`, whose `size` must be set by caller, e.g. to detect under- or over-exposed
+/// frames. Views describe pixels of their region.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `stats` is valid pointer to `Stats`.
+unsafe extern "C" fn img_stats(handle: ImageHandle, stats: *mut Stats) -> ImageError {
+    guard(|| {
+        let _span = trace::span("stats");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let stats = match stats.as_mut() {
+            Some(s) if s.size >= std::mem::size_of::<Stats>() => s,
+            _ => return ImageError::Parameter,
+        };
+
+        let (image, region) = handle.as_region();
+        let summary = analysis::summarize(image, region);
+        stats.channels = image.color().channel_count() as u32;
+        stats.min = summary.min;
+        stats.max = summary.max;
+        stats.mean = summary.mean;
+        stats.std_dev = summary.std_dev;
+        stats.luminance = summary.luma;
+        ImageError::NoError
+    })
+}
+
+/// Writes `k` dominant colors of image found by median cut to `colors` as `0xRRGGBBAA`, most
+/// common first, and fractions of pixels they represent to `weights`, which may be null, e.g. for
+/// accent colors of UI or placeholder backgrounds. Fully transparent pixels are ignored and
+/// pixels of large images are sampled. If image has fewer distinct colors than `k`, remaining
+/// entries get zero color and weight. Views describe pixels of their region. Returns
+/// `ImageError::Parameter` if `k` isn't in `[1, 256]`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `colors` is valid pointer to `k` writable `uint32_t`s.
+/// - `weights` is null pointer or valid pointer to `k` writable `float`s.
+unsafe extern "C" fn img_dominant_colors(
+    handle: ImageHandle,
+    k: u32,
+    colors: *mut u32,
+    weights: *mut f32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("dominant_colors");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if colors.is_null() {
+            return ImageError::Parameter;
+        }
+        if !(1..=256).contains(&k) {
+            let message = format!("Number of colors {} isn't in [1, 256]", k);
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return ImageError::Parameter;
+        }
+
+        let (image, region) = handle.as_region();
+        let found = analysis::dominant_colors(image, region, k as usize);
+        let colors = std::slice::from_raw_parts_mut(colors, k as usize);
+        colors.fill(0);
+        for (out, (rgba, _)) in colors.iter_mut().zip(&found) {
+            *out = u32::from_be_bytes(*rgba);
+        }
+        if !weights.is_null() {
+            let weights = std::slice::from_raw_parts_mut(weights, k as usize);
+            weights.fill(0.0);
+            for (out, (_, weight)) in weights.iter_mut().zip(&found) {
+                *out = *weight;
+            }
+        }
+        ImageError::NoError
+    })
+}
+
+/// Writes average color of `rect` of image, or of the whole image if `rect` is null, to `rgba`
+/// as `0xRRGGBBAA`, e.g. for placeholder color of uploaded asset. Colors are weighted by alpha,
+/// so transparent pixels don't affect color, and alpha is their plain mean. `rect` of view is
+/// relative to its region. Returns `ImageError::Parameter` if `rect` doesn't fit image.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `rect` is null pointer or valid pointer to `Rect`.
+/// - `rgba` is valid pointer to writable `uint32_t`.
+unsafe extern "C" fn img_average_color(
+    handle: ImageHandle,
+    rect: *const Rect,
+    rgba: *mut u32,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("average_color");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if rgba.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let (image, mut region) = handle.as_region();
+        if let Some(rect) = rect.as_ref() {
+            let fits = |start: u32, size: u32, max: u32| {
+                size != 0 && start.checked_add(size).is_some_and(|end| end <= max)
+            };
+            if !fits(rect.x, rect.width, region.width) || !fits(rect.y, rect.height, region.height)
+            {
+                let message = format!(
+                    "Rect {}x{} at {}, {} doesn't fit {}x{} image",
+                    rect.width, rect.height, rect.x, rect.y, region.width, region.height
+                );
+                set_last_error(LastError::new(ImageError::Parameter, message));
+                return ImageError::Parameter;
+            }
+            region = Rect {
+                x: region.x + rect.x,
+                y: region.y + rect.y,
+                ..*rect
+            };
+        }
+        *rgba = u32::from_be_bytes(analysis::average_color(image, region));
+        ImageError::NoError
+    })
+}
+
+/// Writes 64-bit perceptual hash of image computed by `algorithm`, one of `HashAlgorithm`
+/// values, to `hash`, e.g. to find duplicates and near-duplicates among thumbnails. Hashes of
+/// similar images differ in few bits, see `img_hash_distance`. Transparency is ignored.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `hash` is valid pointer to writable `uint64_t`.
+unsafe extern "C" fn img_phash(handle: ImageHandle, algorithm: u32, hash: *mut u64) -> ImageError {
+    guard(|| {
+        let _span = trace::span("phash");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if hash.is_null() {
+            return ImageError::Parameter;
+        }
+        let algorithm = match HashAlgorithm::try_from(algorithm) {
+            Ok(a) => a,
+            Err(e) => return e,
+        };
+
+        let data = handle.read();
+        *hash = match algorithm {
+            HashAlgorithm::Average => hash::average_hash(&data.image),
+            HashAlgorithm::Difference => hash::difference_hash(&data.image),
+            HashAlgorithm::Perceptual => hash::perceptual_hash(&data.image),
+        };
+        ImageError::NoError
+    })
+}
+
+/// Returns Hamming distance between hashes `a` and `b` of `img_phash`, number of bits they
+/// differ in. Hashes of the same algorithm within distance of about 10 usually belong to the
+/// same picture.
+unsafe extern "C" fn img_hash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Compares images `a` and `b` of the same size by `metric`, one of `CompareMetric` values, and
+/// writes their similarity to `result`, e.g. to evaluate quality of encodings against their
+/// source. Views are compared by their regions. Alpha is ignored. Returns
+/// `ImageError::Parameter` if images differ in size.
+/// # Safety
+/// - `a` and `b` are valid image handles.
+/// - `result` is valid pointer to writable `double`.
+unsafe extern "C" fn img_compare(
+    a: ImageHandle,
+    b: ImageHandle,
+    metric: u32,
+    result: *mut f64,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("compare");
+        if !a.is_readable() || !b.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if result.is_null() {
+            return ImageError::Parameter;
+        }
+        let metric = match CompareMetric::try_from(metric) {
+            Ok(metric) => metric,
+            Err(e) => return e,
+        };
+
+        let ((a, region_a), (b, region_b)) = (a.as_region(), b.as_region());
+        if let Err(e) = check_same_size(region_a, region_b) {
+            return e;
+        }
+        *result = match metric {
+            CompareMetric::Psnr => analysis::psnr(a, region_a, b, region_b),
+            CompareMetric::Ssim => analysis::ssim(a, region_a, b, region_b),
+        };
+        ImageError::NoError
+    })
+}
+
+/// Compares pixels of images `a` and `b` of the same size for visual regression tests. Pixels
+/// differ if any of their RGBA channels differs by more than `tolerance` in 8-bit units. Writes
+/// number of differing pixels to `count` and, if `diff` isn't null, handle of new RGBA8 image
+/// highlighting differing pixels in red over faded grayscale of `a` to `diff`. Views are
+/// compared by their regions. Returns `ImageError::Parameter` if images differ in size.
+/// # Safety
+/// - `a` and `b` are valid image handles.
+/// - `diff` is null or valid pointer to `void*`.
+/// - `count` is valid pointer to writable `uint64_t`.
+unsafe extern "C" fn img_diff(
+    a: ImageHandle,
+    b: ImageHandle,
+    tolerance: u8,
+    diff: *mut ImageHandle,
+    count: *mut u64,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("diff");
+        if !a.is_readable() || !b.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if count.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let ((a, region_a), (b, region_b)) = (a.as_region(), b.as_region());
+        if let Err(e) = check_same_size(region_a, region_b) {
+            return e;
+        }
+        let rgba = image::ColorType::Rgba8;
+        if let Err(e) = check_alloc(image_bytes(region_a.width, region_a.height, rgba)) {
+            return e;
+        }
+        let tolerance = tolerance as u16 * 257;
+        let (highlighted, differing) = analysis::diff(a, region_a, b, region_b, tolerance);
+        *count = differing;
+        if !diff.is_null() {
+            *diff = ImageHandle::from_image(DynamicImage::ImageRgba8(highlighted));
+        }
+        ImageError::NoError
+    })
+}
+
+/// Crops away uniform borders of image, e.g. white margins of scans, and writes handle of new
+/// image with its metadata to `trimmed`. Border color is color of top left pixel, and pixels
+/// whose RGBA channels differ from it by at most `tolerance` in 8-bit units belong to borders.
+/// Trimmed copy of view gets only pixels of its region. Returns `ImageError::NotFound` if the
+/// whole image is uniform, see `img_is_blank`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `trimmed` is valid pointer to `void*`.
+unsafe extern "C" fn img_trim(
+    handle: ImageHandle,
+    tolerance: u8,
+    trimmed: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("trim");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if trimmed.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let (image, region) = handle.as_region();
+        let bounds = match analysis::content_bounds(image, region, tolerance as u16 * 257) {
+            Some(bounds) => bounds,
+            None => {
+                let message = "Image is blank, nothing is left after trimming".to_string();
+                set_last_error(LastError::new(ImageError::NotFound, message));
+                return ImageError::NotFound;
+            }
+        };
+        if let Err(e) = check_alloc(image_bytes(bounds.width, bounds.height, image.color())) {
+            return e;
+        }
+        let (x, y) = (region.x + bounds.x, region.y + bounds.y);
+        let pixels = image.crop_imm(x, y, bounds.width, bounds.height);
+        let data = handle.as_data_ref();
+        *trimmed = ImageHandle::from_data(ImageData::new(pixels, data.metadata.clone()));
+        ImageError::NoError
+    })
+}
+
+/// Writes to `blank` whether image is uniform: all of its pixels have RGBA channels differing
+/// from its top left pixel by at most `tolerance` in 8-bit units, e.g. blank pages of scans.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `blank` is valid pointer to writable `bool`.
+unsafe extern "C" fn img_is_blank(
+    handle: ImageHandle,
+    tolerance: u8,
+    blank: *mut bool,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("is_blank");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if blank.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let (image, region) = handle.as_region();
+        *blank = analysis::content_bounds(image, region, tolerance as u16 * 257).is_none();
+        ImageError::NoError
+    })
+}
+
+/// Reports `ImageError::Parameter` with `ErrorSubCode::DimensionMismatch` if regions of two
+/// images compared or combined pixel by pixel differ in size.
+fn check_same_size(a: Rect, b: Rect) -> Result<(), ImageError> {
+    if (a.width, a.height) == (b.width, b.height) {
+        return Ok(());
+    }
+    let message = format!(
+        "Images of {}x{} and {}x{} pixels differ in size",
+        a.width, a.height, b.width, b.height
+    );
+    set_last_error(LastError {
+        sub_code: ErrorSubCode::DimensionMismatch,
+        ..LastError::new(ImageError::Parameter, message)
+    });
+    Err(ImageError::Parameter)
+}
+
+/// Writes [BlurHash](https://blurha.sh) of image with `x_components` x `y_components`
+/// components, both in `[1, 9]`, as null-terminated string to `buffer` of `*len` bytes, so web
+/// pages render blurred placeholder until image loads. Writes hash size including terminating
+/// null to `len`; size query works the same way as in `img_encode`. Hash is computed of image
+/// scaled down to at most 64x64 pixels and ignores alpha. Returns `ImageError::Parameter` if
+/// number of components isn't in `[1, 9]`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_blurhash(
+    handle: ImageHandle,
+    x_components: u32,
+    y_components: u32,
+    buffer: *mut c_char,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("blurhash");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+        if !(1..=9).contains(&x_components) || !(1..=9).contains(&y_components) {
+            let message = format!(
+                "Components {}x{} of BlurHash aren't in [1, 9]",
+                x_components, y_components
+            );
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return ImageError::Parameter;
+        }
+
+        let mut hash = placeholder::blurhash(&handle.read().image, x_components, y_components);
+        hash.push('\0');
+        write_to_buffer(hash.as_bytes(), buffer as *mut u8, len)
+    })
+}
+
+/// Writes [ThumbHash](https://evanw.github.io/thumbhash/) of image to `buffer` of `*len` bytes.
+/// Unlike BlurHash, it keeps alpha and approximate aspect ratio of image, and takes at most 25
+/// bytes. Writes hash size to `len`; size query works the same way as in `img_encode`. Hash is
+/// computed of image scaled down to at most 64x64 pixels.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe extern "C" fn img_thumbhash(
+    handle: ImageHandle,
+    buffer: *mut u8,
+    len: *mut usize,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("thumbhash");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if len.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let hash = placeholder::thumbhash(&handle.read().image);
+        write_to_buffer(&hash, buffer, len)
+    })
+}
+
+/// Decodes ThumbHash `hash` of `len` bytes of `img_thumbhash` into new RGBA8 preview image of
+/// about 32 pixels on its longer side with aspect ratio of hashed image and writes its handle
+/// to `image`. Returns `ImageError::Parameter` if hash is truncated.
+/// # Safety
+/// - `hash` is valid pointer to `len` bytes.
+/// - `image` is valid pointer to `ImageHandle`.
+unsafe extern "C" fn img_thumbhash_decode(
+    hash: *const u8,
+    len: usize,
+    image: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("thumbhash_decode");
+        if hash.is_null() || image.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let hash = std::slice::from_raw_parts(hash, len);
+        let preview = match placeholder::decode_thumbhash(hash) {
+            Some(preview) => preview,
+            None => {
+                let message = format!("ThumbHash of {} bytes is truncated", len);
+                set_last_error(LastError::new(ImageError::Parameter, message));
+                return ImageError::Parameter;
+            }
+        };
+        *image = ImageHandle::from_image(DynamicImage::ImageRgba8(preview));
+        ImageError::NoError
+    })
+}
+
+/// Counts values of `channel` of image in `len` `bins` for `img_histogram` and
+/// `img_histogram16`.
+unsafe fn histogram(handle: ImageHandle, channel: u32, bins: *mut u32, len: usize) -> ImageError {
+    if !handle.is_readable() {
+        return ImageError::InvalidHandle;
+    }
+    if bins.is_null() {
+        return ImageError::Parameter;
+    }
+
+    let (image, region) = handle.as_region();
+    let channels = image.color().channel_count() as u32;
+    if channel >= channels {
+        let message = format!("Image has no channel {}, only {}", channel, channels);
+        set_last_error(LastError::new(ImageError::Parameter, message));
+        return ImageError::Parameter;
+    }
+    let bins = std::slice::from_raw_parts_mut(bins, len);
+    analysis::histogram(image, region, channel as usize, bins);
+    ImageError::NoError
+}
+
+/// Reports that images can't have `ColorType::Rgba32F` channels.
+fn float_images_unsupported() -> ImageError {
+    let message = "Images with float channels aren't supported".to_string();
+    set_last_error(LastError {
+        sub_code: ErrorSubCode::UnsupportedColor,
+        ..LastError::new(ImageError::Unsupported, message)
+    });
+    ImageError::Unsupported
+}
+
+/// Pixel callback of `img_map_pixels` with its user data.
+struct PixelCallback {
+    pixel_fn: PixelFn,
+    user_data: *mut c_void,
+}
+
+// Host guarantees that callback and its user data are usable from worker threads of library
+// when it asks for parallel mapping.
+unsafe impl Send for PixelCallback {}
+unsafe impl Sync for PixelCallback {}
+
+/// Passes every pixel of image to `pixel_fn` with `user_data` as `0xRRGGBBAA` color, which
+/// callback may change, e.g. for one-off effects of host. Rows are passed from top to bottom and
+/// pixels from left to right, unless `parallel` is set: then rows are passed from worker threads
+/// of library at once, so callback must be safe to call concurrently. Callback sees 8-bit
+/// channels. Only pixels whose color it changes are set, as `img_set_pixel` does, so the others
+/// keep their precision. Change is recorded in edit
+/// stack as `{"op": "map_pixels"}`, which recipes can't replay, as callback isn't part of it.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `pixel_fn` is safe to call with `user_data` during the call, from any thread if `parallel`
+///   is set.
+unsafe extern "C" fn img_map_pixels(
+    handle: ImageHandle,
+    pixel_fn: Option<PixelFn>,
+    user_data: *mut c_void,
+    parallel: bool,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("map_pixels");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        let callback = match pixel_fn {
+            Some(pixel_fn) => PixelCallback {
+                pixel_fn,
+                user_data,
+            },
+            None => return ImageError::Parameter,
+        };
+
+        let data = handle.as_data();
+        let (width, height) = data.image.dimensions();
+        if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::Rgba8)) {
+            return e;
+        }
+        data.checkpoint(json!({"op": "map_pixels"}));
+        let mut rgba = data.image.to_rgba8();
+        let map_row = |y: usize, row: &mut [u8]| {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let mut color = u32::from_be_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                (callback.pixel_fn)(x as u32, y as u32, &mut color, callback.user_data);
+                pixel.copy_from_slice(&color.to_be_bytes());
+            }
+        };
+        let row_len = width as usize * 4;
+        match parallel {
+            true => parallel::for_each_row(&mut rgba, row_len, map_row),
+            false if row_len == 0 => {}
+            false => {
+                for (y, row) in rgba.chunks_mut(row_len).enumerate() {
+                    map_row(y, row);
+                }
+            }
+        }
+
+        let image = &mut *data.image;
+        for (x, y, &color) in rgba.enumerate_pixels() {
+            if image.get_pixel(x, y) != color {
+                put_pixel(image, x, y, color);
+            }
+        }
+        ImageError::NoError
+    })
+}
+
+/// Passes rows of image from top to bottom to `row_fn` with `user_data`, e.g. for printers and
+/// scanline encoders streaming image without locking or copying it. Rows are in color type of
+/// image reported by `img_info`, 16-bit channels in native byte order. Views are read in place.
+/// Returns `ImageError::Cancelled` once callback returns `false`.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `row_fn` is safe to call with `user_data` during the call.
+unsafe extern "C" fn img_for_each_row(
+    handle: ImageHandle,
+    row_fn: Option<RowFn>,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let row_fn = match row_fn {
+            Some(f) => f,
+            None => return ImageError::Parameter,
+        };
+
+        let (image, region) = handle.as_region();
+        let bytes_per_pixel = image.color().bytes_per_pixel() as usize;
+        let line = image.width() as usize * bytes_per_pixel;
+        let offset = region.x as usize * bytes_per_pixel;
+        let row = region.width as usize * bytes_per_pixel;
+        for y in 0..region.height {
+            let start = (region.y + y) as usize * line + offset;
+            let row = &image.as_bytes()[start..start + row];
+            if !row_fn(y, row.as_ptr(), row.len(), user_data) {
+                return ImageError::Cancelled;
+            }
+        }
+        ImageError::NoError
+    })
+}
+
+/// Copies pixels of image converted to `color_type`, one of `ColorType` values, into `dst` of
+/// `dst_len` bytes, e.g. into staging buffer of texture upload. Rows go from top to bottom and
+/// start `stride` bytes apart, `0` stride means rows without padding. Channels are converted
+/// as `img_convert` does, 16-bit ones are written in native byte order and `ColorType::Rgba32F`
+/// gives native `float` channels in `[0, 1]`. Views are read in place. Returns
+/// `ImageError::BufferTooSmall` if rows don't fit `dst` and `ImageError::Parameter` if `stride`
+/// is less than width of row.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `dst` is valid pointer to `dst_len` bytes.
+unsafe extern "C" fn img_read_pixels(
+    handle: ImageHandle,
+    dst: *mut u8,
+    dst_len: usize,
+    color_type: u32,
+    stride: usize,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("read_pixels");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let color_type = match ColorType::try_from(color_type) {
+            Ok(c) if !dst.is_null() => c,
+            _ => return ImageError::Parameter,
+        };
+        let (image, region) = handle.as_region();
+        let row = region.width as usize * color_type.bytes_per_pixel();
+        let stride = if stride == 0 { row } else { stride };
+        if stride < row {
+            let message = format!("Stride {} is less than {} bytes of row", stride, row);
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return ImageError::Parameter;
+        }
+        let needed = match region.height {
+            0 => 0,
+            height => stride
+                .saturating_mul(height as usize - 1)
+                .saturating_add(row),
+        };
+        if dst_len < needed {
+            return ImageError::BufferTooSmall;
+        }
+        if row == 0 {
+            return ImageError::NoError;
+        }
+
+        let dst = std::slice::from_raw_parts_mut(dst, dst_len);
+        let rows = dst.chunks_mut(stride).take(region.height as usize);
+        if ColorType::of(image) == color_type {
+            let source = image.width() as usize * color_type.bytes_per_pixel();
+            let offset = region.x as usize * color_type.bytes_per_pixel();
+            let lines = image
+                .as_bytes()
+                .chunks_exact(source)
+                .skip(region.y as usize);
+            for (dst, line) in rows.zip(lines) {
+                dst[..row].copy_from_slice(&line[offset..offset + row]);
+            }
+            return ImageError::NoError;
+        }
+        for (y, dst) in rows.enumerate() {
+            let line = image.crop_imm(region.x, region.y + y as u32, region.width, 1);
+            let dst = &mut dst[..row];
+            match color_type.convert(&line) {
+                Some(line) => dst.copy_from_slice(line.as_bytes()),
+                None => {
+                    let line = ColorType::Rgba16
+                        .convert(&line)
+                        .expect("color type of images");
+                    let channels = line.as_rgba16().expect("16-bit RGBA").as_raw().iter();
+                    for (dst, &c) in dst.chunks_exact_mut(4).zip(channels) {
+                        dst.copy_from_slice(&(c as f32 / u16::MAX as f32).to_ne_bytes());
+                    }
+                }
+            }
+        }
+        ImageError::NoError
+    })
+}
+
+/// Creates read-only view of `rect` of image sharing its pixels and writes its handle to `view`,
+/// e.g. for reading image by tiles without copying every tile. View shows pixels image had when
+/// view was created: image changed later copies its pixels first, as clones of `img_clone_cow`
+/// do, so changes of image never show in view. View is separate image destroyed separately, and
+/// it stays valid once image is destroyed, as it keeps shared pixels of whole image alive.
+/// Functions changing view fail with `ImageError::InvalidHandle`, as frozen images do; results
+/// of tiles are written to images of their own, e.g. by `img_blur_into`. `img_clone` and
+/// `img_clone_cow` of view give ordinary image with copy of its region, which can be changed.
+/// Functions reading view by region, e.g. `img_get_pixel`, `img_read_pixels`,
+/// `img_for_each_row`, `img_stats`, `img_compare`, `img_blur_into` and `img_view`, read it in
+/// place, while others, e.g. `img_encode` and `img_blur2`, read temporary copy of region. View has metadata
+/// of image, but neither annotations nor edit stack.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `view` is valid pointer to `void*`.
+unsafe extern "C" fn img_view(
+    handle: ImageHandle,
+    rect: Rect,
+    view: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if view.is_null() {
+            return ImageError::Parameter;
+        }
+        let (_, region) = handle.as_region();
+        let fits = |start: u32, size: u32, max: u32| {
+            size != 0 && start.checked_add(size).is_some_and(|end| end <= max)
+        };
+        if !fits(rect.x, rect.width, region.width) || !fits(rect.y, rect.height, region.height) {
+            let message = format!(
+                "View {}x{} at {}, {} doesn't fit {}x{} image",
+                rect.width, rect.height, rect.x, rect.y, region.width, region.height
+            );
+            set_last_error(LastError::new(ImageError::Parameter, message));
+            return ImageError::Parameter;
+        }
+
+        let data = handle.as_data_ref();
+        let mut copy = ImageData::new(data.share_image(), data.metadata.clone());
+        copy.view = Some(Rect {
+            x: region.x + rect.x,
+            y: region.y + rect.y,
+            ..rect
+        });
+        *view = ImageHandle::from_data(copy);
+        ImageError::NoError
+    })
+}
+
+/// Creates clone of image sharing its pixels and writes its handle to `clone`. Pixels are
+/// copied only once the clone or the original is changed, so hosts keeping many versions of image,
+/// e.g. for undo, don't pay for pixels of versions which are never changed. Clone has metadata,
+/// annotations and edit stack of image but empty undo history, and is destroyed separately.
+/// Clone of read-only view of `img_view` is copy of its region, as `img_clone` gives.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `clone` is valid pointer to `void*`.
+unsafe extern "C" fn img_clone_cow(handle: ImageHandle, clone: *mut ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if clone.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let data = handle.as_data_ref();
+        if data.view.is_some() {
+            return img_clone(handle, clone);
+        }
+        *clone = ImageHandle::from_data(data.share());
+        ImageError::NoError
+    })
+}
+
+/// Creates copy of image with its own pixels and writes its handle to `clone`. Copy has metadata,
+/// annotations and edit stack of image but empty undo history, as clones of `img_clone_cow` do.
+/// Copy of view gets only pixels of its region.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `clone` is valid pointer to `void*`.
+unsafe extern "C" fn img_clone(handle: ImageHandle, clone: *mut ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if clone.is_null() {
+            return ImageError::Parameter;
+        }
+
+        let (image, r) = handle.as_region();
+        if let Err(e) = check_alloc(image_bytes(r.width, r.height, image.color())) {
+            return e;
+        }
+        let data = handle.as_data_ref();
+        let pixels = match data.view {
+            Some(_) => image.crop_imm(r.x, r.y, r.width, r.height),
+            None => image.clone(),
+        };
+        let mut copy = ImageData::new(pixels, data.metadata.clone());
+        copy.annotations = data.annotations.clone();
+        copy.journal.set_edits(data.journal.edits().to_vec());
+        *clone = ImageHandle::from_data(copy);
+        ImageError::NoError
+    })
+}
+
+/// Creates frozen clone of image sharing its pixels, as `img_clone_cow` does, and writes its
+/// handle to `frozen`. Frozen clone of view is view of the same region. Frozen image is never changed, so unlike other images it may be passed to
+/// several threads and read by them at once without attaching it. Functions which only read
+/// image accept it, e.g. `img_save`, `img_encode`, `img_blur2` and metadata getters, while
+/// functions changing image fail with `ImageError::InvalidHandle`. `img_clone_cow` of frozen
+/// image gives ordinary image which can be changed. Original image stays as it was.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `frozen` is valid pointer to `void*`.
+unsafe extern "C" fn img_freeze(handle: ImageHandle, frozen: *mut ImageHandle) -> ImageError {
+    guard(|| {
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if frozen.is_null() {
+            return ImageError::Parameter;
+        }
+        let mut clone = handle.as_data_ref().share();
+        clone.frozen = true;
+        let clone = ImageHandle::from_data(clone);
+        handles::share(clone.0 as usize);
+        *frozen = clone;
+        ImageError::NoError
+    })
+}
+
+/// Blurs image with `sigma` blur radius. Returns new image, or null if `handle` is invalid.
+/// Deprecated: use `img_blur2`, which reports errors.
+unsafe extern "C" fn img_blur(handle: ImageHandle, sigma: f32) -> ImageHandle {
+    let mut blurred = ImageHandle(std::ptr::null_mut());
+    img_blur2(handle, sigma, &mut blurred);
+    blurred
+}
+
+/// Blurs image with positive `sigma` blur radius and writes handle of new image to `blurred`.
+/// New image inherits metadata, annotations and edits of source image.
+/// # Safety
+/// `blurred` is valid pointer to `void*`.
+unsafe extern "C" fn img_blur2(
+    handle: ImageHandle,
+    sigma: f32,
+    blurred: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("blur");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if blurred.is_null() || !(sigma > 0.0 && sigma.is_finite()) {
+            return ImageError::Parameter;
+        }
+
+        let data = handle.read();
+        let (width, height) = data.image.dimensions();
+        if let Err(e) = check_alloc(image_bytes(width, height, image::ColorType::Rgba8)) {
+            return e;
+        }
+        let buffer = match parallel::blur_dynamic(&data.image, sigma) {
+            Ok(buffer) => buffer,
+            Err(e) => return e,
         };
+        let image = image::DynamicImage::ImageRgba8(buffer);
+        let mut data = ImageData::new(image, data.metadata.clone());
+        data.annotations = handle.as_data_ref().annotations.clone();
+        let mut edits = handle.as_data_ref().journal.edits().to_vec();
+        edits.push(json!({"op": "blur", "sigma": sigma}));
+        data.journal.set_edits(edits);
+        *blurred = ImageHandle::from_data(data);
+        ImageError::NoError
+    })
+}
+
+/// Blurs image as `img_blur2` does, passing percent of work done to `progress_fn` with
+/// `user_data`. Blurring stops with `ImageError::Cancelled` once callback returns `false`. Null
+/// `progress_fn` means no progress reports. Callback may be called from worker threads of
+/// library, one call at a time.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `progress_fn` is null or safe to call with `user_data` from any thread during the call.
+/// - `blurred` is valid pointer to `void*`.
+unsafe extern "C" fn img_blur_with_progress(
+    handle: ImageHandle,
+    sigma: f32,
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+    blurred: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        if blurred.is_null() {
+            return ImageError::Parameter;
+        }
+        let mut result = ImageHandle(std::ptr::null_mut());
+        let code = progress::run(progress_fn, user_data, || {
+            img_blur2(ImageHandle(handle.0), sigma, &mut result)
+        });
+        match code {
+            ImageError::NoError => *blurred = result,
+            // Image finished after callback aborted blurring is dropped.
+            _ if !result.0.is_null() => drop(result.into_data()),
+            _ => {}
+        }
+        code
+    })
+}
+
+/// Resizes image to `width` x `height` pixels by Lanczos filter and writes handle of new image
+/// to `resized`, passing percent of work done to `progress_fn` with `user_data`. New image
+/// inherits metadata of source image. Resizing stops with `ImageError::Cancelled` once callback
+/// returns `false`. Null `progress_fn` means no progress reports. Callback may be called from
+/// worker threads of library, one call at a time.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `progress_fn` is null or safe to call with `user_data` from any thread during the call.
+/// - `resized` is valid pointer to `void*`.
+unsafe extern "C" fn img_resize_with_progress(
+    handle: ImageHandle,
+    width: u32,
+    height: u32,
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+    resized: *mut ImageHandle,
+) -> ImageError {
+    guard(|| {
+        let _span = trace::span("resize");
+        if !handle.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        if resized.is_null() || width == 0 || height == 0 {
+            return ImageError::Parameter;
+        }
+
+        let data = handle.read();
+        // Resizing allocates intermediate image of target width and source height.
+        let rows = height.max(data.image.height());
+        if let Err(e) = check_alloc(image_bytes(width, rows, data.image.color())) {
+            return e;
+        }
+        let mut image = None;
+        let code = progress::run(progress_fn, user_data, || {
+            image = Some(parallel::resize_dynamic(&data.image, width, height));
+            ImageError::NoError
+        });
+        match (code, image) {
+            (ImageError::NoError, Some(image)) => {
+                *resized = ImageHandle::from_data(ImageData::new(image, data.metadata.clone()));
+                ImageError::NoError
+            }
+            (code, _) => code,
+        }
+    })
+}
+
+/// Checks that `src` and `dst` are different images, `src` readable and `dst` valid one, and
+/// `dst` is `width` x `height` image of `color` type, as results of operations written into `dst`
+/// must be.
+unsafe fn check_destination(
+    src: &ImageHandle,
+    dst: &ImageHandle,
+    (width, height): (u32, u32),
+    color: image::ColorType,
+) -> Result<(), ImageError> {
+    if !src.is_readable() || !dst.is_valid() {
+        return Err(ImageError::InvalidHandle);
+    }
+    if src.0 == dst.0 {
+        let message = "Destination image is the source one".to_string();
+        set_last_error(LastError::new(ImageError::Parameter, message));
+        return Err(ImageError::Parameter);
+    }
+    let image = &dst.as_data().image;
+    if image.dimensions() != (width, height) || image.color() != color {
+        let message = format!(
+            "Destination is {}x{} {:?} image instead of {}x{} {:?} one",
+            image.width(),
+            image.height(),
+            image.color(),
+            width,
+            height,
+            color
+        );
+        set_last_error(LastError::new(ImageError::Parameter, message));
+        return Err(ImageError::Parameter);
+    }
+    Ok(())
+}
+
+/// Blurs `src` image as `img_blur2` does, writing result into pixels of `dst` instead of new
+/// image, so hosts processing frames reuse one destination image instead of creating and
+/// destroying image per frame. `dst` must be RGBA image of `src` size, e.g. result of previous
+/// `img_blur2`, and differ from `src`. Metadata, annotations and edit stack of `dst` are replaced
+/// with ones `img_blur2` would give, and its undo history is cleared.
+/// # Safety
+/// `src` and `dst` are valid image handles.
+unsafe extern "C" fn img_blur_into(src: ImageHandle, dst: ImageHandle, sigma: f32) -> ImageError {
+    guard(|| {
+        let _span = trace::span("blur");
+        if !src.is_readable() {
+            return ImageError::InvalidHandle;
+        }
+        let (_, region) = src.as_region();
+        let size = (region.width, region.height);
+        if let Err(e) = check_destination(&src, &dst, size, image::ColorType::Rgba8) {
+            return e;
+        }
+        if !(sigma > 0.0 && sigma.is_finite()) {
+            return ImageError::Parameter;
+        }
+        // Intermediate rows of blur are allocated per call.
+        if let Err(e) = check_alloc(image_bytes(size.0, size.1, image::ColorType::Rgba8)) {
+            return e;
+        }
+
+        let (data, target) = (src.read(), dst.as_data());
+        if let image::DynamicImage::ImageRgba8(buffer) = &mut *target.image {
+            if let Err(e) = parallel::blur_dynamic_into(&data.image, sigma, buffer) {
+                return e;
+            }
+        }
+        target.metadata = data.metadata.clone();
+        target.annotations = data.annotations.clone();
+        let mut edits = data.journal.edits().to_vec();
+        edits.push(json!({"op": "blur", "sigma": sigma}));
+        target.journal.clear();
+        target.journal.set_edits(edits);
+        ImageError::NoError
+    })
+}
+
+/// Resizes `src` image to size of `dst` image as `img_resize_with_progress` does, writing
+/// result into pixels of `dst` instead of new image, so hosts processing frames reuse one
+/// destination image instead of creating and destroying image per frame. `dst` must have color
+/// type of `src` and differ from it. Metadata of `dst` is replaced with one of `src`, and its
+/// annotations, edit stack and undo history are cleared, as new image would have them.
+/// # Safety
+/// `src` and `dst` are valid image handles.
+unsafe extern "C" fn img_resize_into(src: ImageHandle, dst: ImageHandle) -> ImageError {
+    guard(|| {
+        let _span = trace::span("resize");
+        if !src.is_readable() || !dst.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+        let (width, height) = dst.as_data().image.dimensions();
+        let (source, region) = src.as_region();
+        let color = source.color();
+        if let Err(e) = check_destination(&src, &dst, (width, height), color) {
+            return e;
+        }
+        if width == 0 || height == 0 {
+            return ImageError::Parameter;
+        }
+        // Intermediate rows of resize are allocated per call.
+        let rows = height.max(region.height);
+        if let Err(e) = check_alloc(image_bytes(width, rows, color)) {
+            return e;
+        }
+
+        let (data, target) = (src.read(), dst.as_data());
+        parallel::resize_dynamic_into(&data.image, &mut target.image);
+        target.metadata = data.metadata.clone();
+        target.annotations.clear();
+        target.journal.clear();
+        ImageError::NoError
+    })
+}
+
+/// Encodes image as `img_encode` does, passing percent of work done to `progress_fn` with
+/// `user_data`: 0 before encoding starts and 100 once it finishes, as encoders don't report
+/// their progress. If callback returns `false` before encoding starts, call fails with
+/// `ImageError::Cancelled`. Null `progress_fn` means no progress reports.
+/// # Safety
+/// - `handle` is valid image handle.
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+/// - `progress_fn` is null or safe to call with `user_data` during the call.
+unsafe extern "C" fn img_encode_with_progress(
+    handle: ImageHandle,
+    format: u32,
+    buffer: *mut u8,
+    len: *mut usize,
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+) -> ImageError {
+    guard(|| {
+        progress::run(progress_fn, user_data, || {
+            img_encode(ImageHandle(handle.0), format, buffer, len)
+        })
+    })
+}
+
+/// Flip image horizontally in place. Deprecated: use `img_mirror2`, which reports errors.
+unsafe extern "C" fn img_mirror(handle: ImageHandle) {
+    img_mirror2(handle);
+}
 
+/// Flips image horizontally in place.
+unsafe extern "C" fn img_mirror2(handle: ImageHandle) -> ImageError {
+    guard(|| {
+        let _span = trace::span("mirror");
+        if !handle.is_valid() {
+            return ImageError::InvalidHandle;
+        }
+
+        handle.as_data().checkpoint(json!({"op": "mirror"}));
+        let image_ref = handle.as_image_mut();
+        image::imageops::flip_horizontal_in_place(image_ref);
+        ImageError::NoError
+    })
+}
+
+// Utils
+
+/// `false` on platforms without file system, e.g. `wasm32-unknown-unknown`, where functions
+/// taking paths fail with `ImageError::Unsupported` and hosts pass images in memory buffers.
+const HAS_FILES: bool = !cfg!(all(target_arch = "wasm32", target_os = "unknown"));
+
+/// Reports that files aren't available on this platform.
+fn no_files() -> ImageError {
+    let message = "Files aren't supported on this platform, use img_open_memory and img_encode";
+    set_last_error(LastError::new(ImageError::Unsupported, message.to_string()));
+    ImageError::Unsupported
+}
+
+impl<'a> TryFrom<&'a RawPath> for &'a Path {
+    type Error = ImageError;
+
+    fn try_from(value: &'a RawPath) -> Result<Self, Self::Error> {
+        if !HAS_FILES {
+            return Err(no_files());
+        }
+        let utf8_str = unsafe { utf8_str(value.0) }?;
         let path: &Path = Path::new(utf8_str);
         Ok(path)
     }
 }
+
+impl<'a> TryFrom<&'a RawStr> for &'a str {
+    type Error = ImageError;
+
+    fn try_from(value: &'a RawStr) -> Result<Self, Self::Error> {
+        unsafe { utf8_str(value.0) }
+    }
+}
+
+/// Returns `strings` as consecutive null-terminated strings followed by empty string.
+fn string_list<I, S>(strings: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut list = Vec::new();
+    for string in strings {
+        list.extend_from_slice(string.as_ref().as_bytes());
+        list.push(0);
+    }
+    list.push(0);
+    list
+}
+
+/// Converts null-terminated string to `&str`. Returns `ImageError::Parameter` if `ptr` is null
+/// or string isn't valid UTF-8.
+/// # Safety
+/// `ptr` is null or valid pointer to null-terminated string.
+unsafe fn utf8_str<'a>(ptr: *const c_char) -> Result<&'a str, ImageError> {
+    if ptr.is_null() {
+        return Err(ImageError::Parameter);
+    }
+
+    let s = CStr::from_ptr(ptr);
+    match s.to_str() {
+        Ok(s) => Ok(s),
+        Err(_) => Err(ImageError::Parameter),
+    }
+}
+
+/// Decodes image file at `path` respecting `options`. Without format hint, format is deduced
+/// from extension or, if it is unknown, from content.
+#[cfg(not(feature = "mmap"))]
+fn open_file(path: &Path, options: &OpenOptions) -> Result<ImageData, ImageError> {
+    let _span = trace::span("open");
+    if let Some(decoder) = plugin_decoder(path, options)? {
+        let file = std::fs::read(path).map_err(io_error)?;
+        return decode_plugin(&decoder, &file, options);
+    }
+    let file = std::fs::File::open(path).map_err(io_error)?;
+    let format = options.format_hint()?;
+    let format = format.or_else(|| image::ImageFormat::from_path(path).ok());
+    decode_as(BufReader::new(file), format, options)
+}
+
+/// Decodes memory-mapped image file at `path` respecting `options`, so file content isn't
+/// buffered on heap before decoding. Without format hint, format is deduced from extension or,
+/// if it is unknown, from content.
+#[cfg(feature = "mmap")]
+fn open_file(path: &Path, options: &OpenOptions) -> Result<ImageData, ImageError> {
+    let _span = trace::span("open");
+    let file = std::fs::File::open(path).map_err(io_error)?;
+    // File is expected to stay unmodified while it is mapped.
+    let map = unsafe { memmap2::Mmap::map(&file) }.map_err(io_error)?;
+    if let Some(decoder) = plugin_decoder(path, options)? {
+        return decode_plugin(&decoder, &map, options);
+    }
+    let format = options.format_hint()?;
+    let format = format.or_else(|| image::ImageFormat::from_path(path).ok());
+    decode_as(Cursor::new(&map[..]), format, options)
+}
+
+/// Returns codec plugin registered for extension of `path`, unless `options` have format hint.
+fn plugin_decoder(
+    path: &Path,
+    options: &OpenOptions,
+) -> Result<Option<codec::Decoder>, ImageError> {
+    if options.format_hint()?.is_some() {
+        return Ok(None);
+    }
+    let extension = path.extension().and_then(|e| e.to_str());
+    Ok(extension.and_then(codec::decoder))
+}
+
+/// Decodes `file` by codec plugin `decoder` and checks decoded image against `options` limits.
+fn decode_plugin(
+    decoder: &codec::Decoder,
+    file: &[u8],
+    options: &OpenOptions,
+) -> Result<ImageData, ImageError> {
+    let image = decoder.decode(file)?;
+    let (width, height) = image.dimensions();
+    decode::check_limits(width, height, image.as_bytes().len() as u64, options)?;
+    let message = format!("Decoded image {}x{} by codec plugin", width, height);
+    log::write(LogLevel::Debug, &message);
+    Ok(ImageData::new(image, Default::default()))
+}
+
+/// Wraps OS file handle owned by caller into `File`, which doesn't close it on drop.
+/// # Safety
+/// `fd` is valid file descriptor.
+#[cfg(unix)]
+unsafe fn borrow_file(fd: OsHandle) -> std::mem::ManuallyDrop<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+    std::mem::ManuallyDrop::new(std::fs::File::from_raw_fd(fd))
+}
+
+/// Wraps OS file handle owned by caller into `File`, which doesn't close it on drop.
+/// # Safety
+/// `fd` is valid file `HANDLE`.
+#[cfg(windows)]
+unsafe fn borrow_file(fd: OsHandle) -> std::mem::ManuallyDrop<std::fs::File> {
+    use std::os::windows::io::FromRawHandle;
+    std::mem::ManuallyDrop::new(std::fs::File::from_raw_handle(fd))
+}
+
+/// Downloads response body from `url`. Zero `timeout_ms` or `max_bytes` means no limit.
+#[cfg(feature = "http")]
+fn fetch(url: &str, timeout_ms: u32, max_bytes: usize) -> Result<Vec<u8>, ImageError> {
+    let mut agent = ureq::AgentBuilder::new();
+    if timeout_ms != 0 {
+        agent = agent.timeout(std::time::Duration::from_millis(timeout_ms as u64));
+    }
+
+    let response = agent.build().get(url).call().map_err(|e| {
+        set_last_error(LastError::new(ImageError::Io, format!("HTTP error: {}", e)));
+        ImageError::Io
+    })?;
+    let limit = match max_bytes {
+        0 => u64::MAX,
+        max => max as u64 + 1,
+    };
+
+    let mut data = Vec::new();
+    let mut body = response.into_reader().take(limit);
+    body.read_to_end(&mut data).map_err(io_error)?;
+
+    if max_bytes != 0 && data.len() > max_bytes {
+        return Err(ImageError::LimitExceeded);
+    }
+    Ok(data)
+}
+
+/// Downloading is available only with `http` feature.
+#[cfg(not(feature = "http"))]
+fn fetch(_url: &str, _timeout_ms: u32, _max_bytes: usize) -> Result<Vec<u8>, ImageError> {
+    Err(ImageError::Unsupported)
+}
+
+/// Decodes image from `reader` guessing its format by content.
+fn decode<R: BufRead + Seek>(reader: R) -> Result<ImageData, ImageError> {
+    decode_as(reader, None, &OpenOptions::default())
+}
+
+/// Decodes image from `reader` respecting `options`. If `format` is `None`, it is guessed by
+/// content.
+fn decode_as<R: BufRead + Seek>(
+    reader: R,
+    format: Option<image::ImageFormat>,
+    options: &OpenOptions,
+) -> Result<ImageData, ImageError> {
+    let _span = trace::span("decode");
+    let mut reader = reader;
+    if format.is_none() {
+        if let Some(decoder) = codec::probe(&mut reader).map_err(io_error)? {
+            let mut file = Vec::new();
+            reader.read_to_end(&mut file).map_err(io_error)?;
+            return decode_plugin(&decoder, &file, options);
+        }
+    }
+    if format.is_none() && psd::detect(&mut reader).map_err(io_error)? {
+        let mut file = Vec::new();
+        reader.read_to_end(&mut file).map_err(io_error)?;
+        let data = psd::read_composite(&file, options)?;
+        let (width, height) = data.image.dimensions();
+        log::write(
+            LogLevel::Debug,
+            &format!("Decoded PSD image {}x{}", width, height),
+        );
+        return Ok(data);
+    }
+
+    let reader = match format {
+        Some(format) => image::io::Reader::with_format(reader, format),
+        None => image::io::Reader::new(reader)
+            .with_guessed_format()
+            .map_err(io_error)?,
+    };
+
+    let format = reader.format().ok_or_else(|| {
+        set_last_error(LastError {
+            sub_code: ErrorSubCode::UnsupportedFormat,
+            ..LastError::new(ImageError::Unsupported, "Unknown image format".into())
+        });
+        ImageError::Unsupported
+    })?;
+    let mut reader = reader.into_inner();
+    let start = reader.stream_position().map_err(io_error)?;
+    let metadata = meta::Metadata::read(&mut reader, format);
+    reader.seek(SeekFrom::Start(start)).map_err(io_error)?;
+
+    let image = decode::load(reader, format, options)?;
+    let (width, height) = image.dimensions();
+    let message = format!("Decoded {:?} image {}x{}", format, width, height);
+    log::write(LogLevel::Debug, &message);
+    let mut data = ImageData::new(image, metadata);
+    if options.auto_orient {
+        data.auto_orient()?;
+    }
+    Ok(data)
+}
+
+/// `Read + Seek` adapter over host-provided callbacks.
+struct CallbackReader {
+    read_fn: ReadFn,
+    seek_fn: Option<SeekFn>,
+    user_data: *mut c_void,
+}
+
+impl Read for CallbackReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = unsafe { (self.read_fn)(self.user_data, buf.as_mut_ptr(), buf.len()) };
+        if read < 0 {
+            return Err(std::io::Error::other("read callback failed"));
+        }
+        if read as usize > buf.len() {
+            let message = "read callback returned more bytes than buffer holds";
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ));
+        }
+        Ok(read as usize)
+    }
+}
+
+impl Seek for CallbackReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let seek_fn = match self.seek_fn {
+            Some(f) => f,
+            None => return Err(std::io::Error::other("seek callback is not provided")),
+        };
+
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (offset as i64, 0),
+            SeekFrom::Current(offset) => (offset, 1),
+            SeekFrom::End(offset) => (offset, 2),
+        };
+
+        let position = unsafe { seek_fn(self.user_data, offset, whence) };
+        if position < 0 {
+            return Err(std::io::Error::other("seek callback failed"));
+        }
+        Ok(position as u64)
+    }
+}
+
+/// Encodes `image` into memory.
+fn encode(image: &DynamicImage, format: image::ImageOutputFormat) -> Result<Vec<u8>, ImageError> {
+    let _span = trace::span("encode");
+    let mut encoded = Vec::new();
+    image.write_to(&mut budget::Stream::new(&mut encoded), format)?;
+    Ok(encoded)
+}
+
+/// Maximum width and height of thumbnail embedded into EXIF, as recommended by EXIF standard.
+const EXIF_THUMBNAIL_SIZE: (u32, u32) = (160, 120);
+
+/// Encodes image of `data` in `format` with `options` and writes it to `writer`.
+fn write_encoded<W: Write>(
+    data: &ImageData,
+    format: ImageFormat,
+    options: &SaveOptions,
+    writer: &mut W,
+) -> Result<(), ImageError> {
+    if !data.metadata.needs_embedding(options.keep_metadata) && !options.embeds_thumbnail(format) {
+        let _span = trace::span("encode");
+        let format = options.output_format(format);
+        return Ok(data
+            .image
+            .write_to(&mut budget::Stream::new(writer), format)?);
+    }
+
+    let encoded = encode_data(data, format, options)?;
+    writer.write_all(&encoded).map_err(io_error)
+}
+
+/// Encodes image of `data` in `format` with `options` into memory. ICC profile is always
+/// embedded, EXIF only if `options` ask to keep metadata or to embed thumbnail.
+fn encode_data(
+    data: &ImageData,
+    format: ImageFormat,
+    options: &SaveOptions,
+) -> Result<Vec<u8>, ImageError> {
+    let encoded = encode(&data.image, options.output_format(format))?;
+    if !options.embeds_thumbnail(format) {
+        return data
+            .metadata
+            .embed(encoded, format.into(), options.keep_metadata);
+    }
+
+    let (width, height) = EXIF_THUMBNAIL_SIZE;
+    let thumbnail = DynamicImage::ImageRgb8(data.image.thumbnail(width, height).to_rgb8());
+    let thumbnail = encode(&thumbnail, image::ImageOutputFormat::Jpeg(75))?;
+    let exif = data
+        .metadata
+        .exif
+        .as_deref()
+        .filter(|_| options.keep_metadata);
+    let metadata = meta::Metadata {
+        exif: Some(meta::set_exif_thumbnail(exif, &thumbnail)?),
+        ..data.metadata.clone()
+    };
+    metadata.embed(encoded, format.into(), true)
+}
+
+/// `Write` adapter over host-provided callback.
+struct CallbackWriter {
+    write_fn: WriteFn,
+    user_data: *mut c_void,
+}
+
+impl Write for CallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = unsafe { (self.write_fn)(self.user_data, buf.as_ptr(), buf.len()) };
+        if written < 0 {
+            return Err(std::io::Error::other("write callback failed"));
+        }
+        if written as usize > buf.len() {
+            let message = "write callback returned more bytes than buffer holds";
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ));
+        }
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Copies `data` into caller-provided `buffer` of `*len` bytes and writes `data` size to `len`.
+/// If `buffer` is null, only writes the size.
+/// # Safety
+/// - `buffer` is null or valid pointer to `*len` writable bytes.
+/// - `len` is valid pointer to `size_t`.
+unsafe fn write_to_buffer(data: &[u8], buffer: *mut u8, len: *mut usize) -> ImageError {
+    let available = *len;
+    *len = data.len();
+
+    if buffer.is_null() {
+        return ImageError::NoError;
+    }
+
+    if available < data.len() {
+        return ImageError::BufferTooSmall;
+    }
+
+    std::ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len());
+    ImageError::NoError
+}
@@ -0,0 +1,42 @@
+use crate::{LogFn, LogLevel};
+use std::ffi::{c_void, CString};
+use std::sync::Mutex;
+
+/// Host-provided log callback with its user data.
+#[derive(Copy, Clone)]
+struct Logger {
+    callback: LogFn,
+    user_data: *mut c_void,
+}
+
+// Host is responsible for making its callback and user data usable from any thread.
+unsafe impl Send for Logger {}
+
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+/// Sets `callback` receiving messages of library, or removes it if `callback` is `None`.
+pub fn set_callback(callback: Option<LogFn>, user_data: *mut c_void) {
+    let logger = callback.map(|callback| Logger {
+        callback,
+        user_data,
+    });
+    *LOGGER.lock().unwrap_or_else(|e| e.into_inner()) = logger;
+}
+
+/// Passes `message` to host log callback, if it is set.
+pub fn write(level: LogLevel, message: &str) {
+    // Lock isn't held during the call, so callback may call library functions.
+    let logger = match *LOGGER.lock().unwrap_or_else(|e| e.into_inner()) {
+        Some(l) => l,
+        None => return,
+    };
+
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    let len = message.as_bytes().len();
+    unsafe { (logger.callback)(level as u32, message.as_ptr(), len, logger.user_data) };
+}
+
+/// Reports recoverable problem, e.g. ignored malformed metadata.
+pub fn warn(message: &str) {
+    write(LogLevel::Warning, message);
+}
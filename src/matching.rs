@@ -0,0 +1,342 @@
+use crate::MatchResult;
+use image::DynamicImage;
+
+/// Number of pyramid levels above full resolution searched coarse to fine at most.
+const MAX_LEVEL: u32 = 3;
+/// Minimum width and height of template at the coarsest searched level.
+const MIN_TEMPLATE_SIZE: u32 = 8;
+/// Scales of template tried by multi-scale matching.
+const SCALES: [f32; 7] = [0.512, 0.64, 0.8, 1.0, 1.25, 1.5625, 1.953125];
+/// Step of template rotations in degrees tried by rotation-invariant matching.
+const ANGLE_STEP: f32 = 15.0;
+
+/// Grayscale pixels with weights, where zero weight marks pixels ignored by matching.
+struct Plane {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+    weights: Vec<f32>,
+}
+
+impl Plane {
+    /// Returns luma of `image` in `[0, 1]` weighted by its alpha.
+    fn from_image(image: &DynamicImage) -> Self {
+        let rgba = image.to_rgba8();
+        let pixels = rgba.pixels();
+        let (values, weights) = pixels
+            .map(|p| {
+                let [r, g, b, a] = p.0.map(|v| v as f32 / u8::MAX as f32);
+                (0.2126 * r + 0.7152 * g + 0.0722 * b, a)
+            })
+            .unzip();
+        Self {
+            width: rgba.width(),
+            height: rgba.height(),
+            values,
+            weights,
+        }
+    }
+
+    /// Returns plane of half size averaging 2 x 2 blocks by weight.
+    fn half(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut half = Self {
+            width,
+            height,
+            values: vec![0.0; (width * height) as usize],
+            weights: vec![0.0; (width * height) as usize],
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let (mut sum, mut weight) = (0.0, 0.0);
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (sx, sy) = (
+                        (2 * x + dx).min(self.width - 1),
+                        (2 * y + dy).min(self.height - 1),
+                    );
+                    let index = (sy * self.width + sx) as usize;
+                    sum += self.values[index] * self.weights[index];
+                    weight += self.weights[index];
+                }
+                let index = (y * width + x) as usize;
+                half.values[index] = if weight > 0.0 { sum / weight } else { 0.0 };
+                half.weights[index] = weight / 4.0;
+            }
+        }
+        half
+    }
+
+    /// Returns bilinearly interpolated value and weight at `(x, y)`, zero weight outside.
+    fn sample(&self, x: f32, y: f32) -> (f32, f32) {
+        if x < 0.0 || y < 0.0 || x > (self.width - 1) as f32 || y > (self.height - 1) as f32 {
+            return (0.0, 0.0);
+        }
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let at = |x: u32, y: u32| {
+            let index = (y * self.width + x) as usize;
+            (self.values[index], self.weights[index])
+        };
+        let mix =
+            |a: (f32, f32), b: (f32, f32), f: f32| (a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f);
+        mix(
+            mix(at(x0, y0), at(x1, y0), fx),
+            mix(at(x0, y1), at(x1, y1), fx),
+            fy,
+        )
+    }
+
+    /// Returns plane scaled by `scale` and rotated by `angle` degrees around its center. Its size
+    /// is bounding box of transformed plane, uncovered pixels have zero weight.
+    fn transform(&self, scale: f32, angle: f32) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (w, h) = (self.width as f32 * scale, self.height as f32 * scale);
+        let width = (w * cos.abs() + h * sin.abs()).round().max(1.0) as u32;
+        let height = (w * sin.abs() + h * cos.abs()).round().max(1.0) as u32;
+
+        let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+        let (sx, sy) = (self.width as f32 / 2.0, self.height as f32 / 2.0);
+        let mut plane = Self {
+            width,
+            height,
+            values: Vec::with_capacity((width * height) as usize),
+            weights: Vec::with_capacity((width * height) as usize),
+        };
+        for y in 0..height {
+            for x in 0..width {
+                // Inverse mapping of pixel center of result into source.
+                let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+                let u = (dx * cos + dy * sin) / scale + sx - 0.5;
+                let v = (-dx * sin + dy * cos) / scale + sy - 0.5;
+                let (value, weight) = self.sample(u, v);
+                plane.values.push(value);
+                plane.weights.push(weight);
+            }
+        }
+        plane
+    }
+}
+
+/// Template prepared for normalized cross-correlation: pixels of enough weight with values
+/// relative to their mean.
+struct Template {
+    width: u32,
+    height: u32,
+    /// Offsets of used pixels in template and their centered values.
+    pixels: Vec<(u32, u32, f32)>,
+    norm: f32,
+}
+
+impl Template {
+    fn new(plane: &Plane) -> Option<Self> {
+        let mut pixels = Vec::new();
+        for y in 0..plane.height {
+            for x in 0..plane.width {
+                let index = (y * plane.width + x) as usize;
+                if plane.weights[index] >= 0.5 {
+                    pixels.push((x, y, plane.values[index]));
+                }
+            }
+        }
+        let mean = pixels.iter().map(|p| p.2).sum::<f32>() / pixels.len().max(1) as f32;
+        pixels.iter_mut().for_each(|p| p.2 -= mean);
+        let norm = pixels.iter().map(|p| p.2 * p.2).sum::<f32>().sqrt();
+        // Flat templates match anything equally well.
+        match norm > 1e-4 {
+            true => Some(Self {
+                width: plane.width,
+                height: plane.height,
+                pixels,
+                norm,
+            }),
+            false => None,
+        }
+    }
+
+    /// Returns normalized cross-correlation in `[-1, 1]` of template placed at `(x, y)` of
+    /// `haystack`.
+    fn score(&self, haystack: &Plane, x: u32, y: u32) -> f32 {
+        let (mut sum, mut sum_sq, mut cross) = (0.0, 0.0, 0.0);
+        for &(tx, ty, t) in &self.pixels {
+            let h = haystack.values[((y + ty) * haystack.width + x + tx) as usize];
+            sum += h;
+            sum_sq += h * h;
+            cross += t * h;
+        }
+        let n = self.pixels.len() as f32;
+        let deviation = (sum_sq - sum * sum / n).max(0.0).sqrt();
+        match deviation > 1e-4 {
+            true => cross / (self.norm * deviation),
+            false => 0.0,
+        }
+    }
+
+    /// Returns position and score of the best match in `[x0, x1] x [y0, y1]` of `haystack`.
+    fn best(
+        &self,
+        haystack: &Plane,
+        (x0, y0): (u32, u32),
+        (x1, y1): (u32, u32),
+    ) -> (u32, u32, f32) {
+        let mut best = (x0, y0, f32::MIN);
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let score = self.score(haystack, x, y);
+                if score > best.2 {
+                    best = (x, y, score);
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Finds up to `max_matches` best non-overlapping placements of `needle` in `haystack` by
+/// normalized cross-correlation of luma. Transparent pixels of needle are ignored. Scales in
+/// `[0.5, 2]` are tried if `multi_scale` is set, rotations in 15 degree steps if `rotated` is
+/// set. Matches are ordered from the best one and have positive scores.
+pub fn match_template(
+    haystack: &DynamicImage,
+    needle: &DynamicImage,
+    multi_scale: bool,
+    rotated: bool,
+    max_matches: usize,
+) -> Vec<MatchResult> {
+    let scales: &[f32] = if multi_scale { &SCALES } else { &[1.0] };
+    let angles = if rotated {
+        (360.0 / ANGLE_STEP) as u32
+    } else {
+        1
+    };
+
+    let haystack = Plane::from_image(haystack);
+    let needle = Plane::from_image(needle);
+    let mut pyramid = vec![haystack];
+    for _ in 0..MAX_LEVEL {
+        let half = pyramid[pyramid.len() - 1].half();
+        pyramid.push(half);
+    }
+
+    let mut matches = Vec::new();
+    for &scale in scales {
+        for step in 0..angles {
+            let angle = step as f32 * ANGLE_STEP;
+            let variant = needle.transform(scale, angle);
+            search(
+                &pyramid,
+                variant,
+                max_matches,
+                &mut |x, y, width, height, score| {
+                    matches.push(MatchResult {
+                        x,
+                        y,
+                        width,
+                        height,
+                        scale,
+                        angle,
+                        score,
+                    })
+                },
+            );
+        }
+    }
+
+    // The best matches suppress overlapping worse ones, e.g. of neighbor scales.
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<MatchResult> = Vec::new();
+    for m in matches {
+        if kept.len() == max_matches {
+            break;
+        }
+        if m.score > 0.0 && !kept.iter().any(|k| overlap(k, &m) > 0.5) {
+            kept.push(m);
+        }
+    }
+    kept
+}
+
+/// Searches `template` in image of `pyramid` coarse to fine and passes up to `count` candidate
+/// placements in full resolution with their scores to `found`.
+fn search<F>(pyramid: &[Plane], template: Plane, count: usize, found: &mut F)
+where
+    F: FnMut(u32, u32, u32, u32, f32),
+{
+    let fits = |plane: &Plane, t: &Plane| t.width <= plane.width && t.height <= plane.height;
+    if !fits(&pyramid[0], &template) {
+        return;
+    }
+
+    let mut templates = vec![template];
+    while templates.len() < pyramid.len() {
+        let last = &templates[templates.len() - 1];
+        let level = templates.len();
+        let half = last.half();
+        let small = half.width.min(half.height) < MIN_TEMPLATE_SIZE;
+        if small || !fits(&pyramid[level], &half) {
+            break;
+        }
+        templates.push(half);
+    }
+    let templates: Vec<_> = templates.iter().map(Template::new).collect();
+    let top = templates.len() - 1;
+    let coarse = match &templates[top] {
+        Some(t) => t,
+        None => return,
+    };
+
+    // Local maxima of the coarsest level are refined by searching few pixels around them.
+    let plane = &pyramid[top];
+    let (w, h) = (
+        plane.width - coarse.width + 1,
+        plane.height - coarse.height + 1,
+    );
+    let scores: Vec<f32> = (0..w * h)
+        .map(|i| coarse.score(plane, i % w, i / w))
+        .collect();
+    let mut candidates: Vec<(u32, u32, f32)> = (0..w * h)
+        .filter(|&i| {
+            let (x, y) = (i % w, i / w);
+            let neighbor = |dx: i64, dy: i64| {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                let inside = nx >= 0 && ny >= 0 && nx < w as i64 && ny < h as i64;
+                inside && scores[(ny * w as i64 + nx) as usize] > scores[i as usize]
+            };
+            !(-1..=1).any(|dy| (-1..=1).any(|dx| neighbor(dx, dy)))
+        })
+        .map(|i| (i % w, i / w, scores[i as usize]))
+        .collect();
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+    candidates.truncate(count.saturating_mul(4).max(8));
+
+    for (mut x, mut y, mut score) in candidates {
+        for level in (0..top).rev() {
+            let template = match &templates[level] {
+                Some(t) => t,
+                None => return,
+            };
+            let plane = &pyramid[level];
+            let (max_x, max_y) = (plane.width - template.width, plane.height - template.height);
+            let (cx, cy) = ((2 * x).min(max_x), (2 * y).min(max_y));
+            let from = (cx.saturating_sub(2), cy.saturating_sub(2));
+            let to = ((cx + 2).min(max_x), (cy + 2).min(max_y));
+            let best = template.best(plane, from, to);
+            x = best.0;
+            y = best.1;
+            score = best.2;
+        }
+        let size = &templates[0];
+        let (width, height) = size.as_ref().map_or((0, 0), |t| (t.width, t.height));
+        found(x, y, width, height, score);
+    }
+}
+
+/// Returns area of intersection of rectangles of `a` and `b` relative to the smaller one.
+fn overlap(a: &MatchResult, b: &MatchResult) -> f32 {
+    let span =
+        |a0: u32, a1: u32, b0: u32, b1: u32| (a1.min(b1) as f32 - a0.max(b0) as f32).max(0.0);
+    let width = span(a.x, a.x + a.width, b.x, b.x + b.width);
+    let height = span(a.y, a.y + a.height, b.y, b.y + b.height);
+    let smaller = (a.width * a.height).min(b.width * b.height).max(1) as f32;
+    width * height / smaller
+}
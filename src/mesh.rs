@@ -0,0 +1,115 @@
+use crate::align;
+use image::{DynamicImage, GenericImageView};
+
+/// Projective transform mapping `(x, y)` to `((h[0] x + h[1] y + h[2]) / w, (h[3] x + h[4] y +
+/// h[5]) / w)` where `w = h[6] x + h[7] y + 1`.
+type Homography = [f64; 8];
+
+/// Warps `image` so that content at points of `src` grid of `cols` x `rows` points moves to the
+/// same points of `dst` grid. Each cell of grid is warped by perspective transform of its
+/// corners. Size of image is kept, pixels outside of `dst` grid become transparent. Returns
+/// `None` if some cell is degenerate.
+pub fn warp(
+    image: &DynamicImage,
+    src: &[(f64, f64)],
+    dst: &[(f64, f64)],
+    cols: usize,
+    rows: usize,
+) -> Option<DynamicImage> {
+    let (width, height) = image.dimensions();
+    let mut cells = Vec::new();
+    // Index of cell covering each pixel, the last one wins where folded cells overlap.
+    let mut coverage = vec![u32::MAX; width as usize * height as usize];
+    for row in 0..rows - 1 {
+        for col in 0..cols - 1 {
+            let corners = [
+                row * cols + col,
+                row * cols + col + 1,
+                (row + 1) * cols + col + 1,
+                (row + 1) * cols + col,
+            ];
+            let to = corners.map(|i| dst[i]);
+            let from = corners.map(|i| src[i]);
+            cells.push(homography(&to, &from)?);
+            rasterize(&to, width, height, |x, y| {
+                coverage[(y * width + x) as usize] = (cells.len() - 1) as u32;
+            });
+        }
+    }
+
+    let map = |x: f64, y: f64| {
+        let cell = coverage[(y as u32 * width + x as u32) as usize];
+        let h = cells.get(cell as usize)?;
+        // Pixel centers are mapped, so that identity grid keeps image unchanged.
+        let (x, y) = (x + 0.5, y + 0.5);
+        let w = h[6] * x + h[7] * y + 1.0;
+        let u = (h[0] * x + h[1] * y + h[2]) / w;
+        let v = (h[3] * x + h[4] * y + h[5]) / w;
+        Some((u - 0.5, v - 0.5))
+    };
+    Some(align::warp(image, width, height, map))
+}
+
+/// Calls `f` for pixels of `width` x `height` image whose centers lie within quad of `corners`.
+fn rasterize<F: FnMut(u32, u32)>(corners: &[(f64, f64); 4], width: u32, height: u32, mut f: F) {
+    let (xs, ys) = (corners.map(|c| c.0), corners.map(|c| c.1));
+    let min = |v: [f64; 4]| v.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = |v: [f64; 4]| v.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let clamp = |v: f64, size: u32| v.clamp(0.0, size as f64) as u32;
+    let (x0, x1) = (clamp(min(xs).floor(), width), clamp(max(xs).ceil(), width));
+    let (y0, y1) = (
+        clamp(min(ys).floor(), height),
+        clamp(max(ys).ceil(), height),
+    );
+
+    // Quad is split into two triangles, so that concave quads are covered too.
+    let [a, b, c, d] = *corners;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let p = (x as f64 + 0.5, y as f64 + 0.5);
+            if in_triangle(p, a, b, c) || in_triangle(p, a, c, d) {
+                f(x, y);
+            }
+        }
+    }
+}
+
+/// Returns `true` if `p` lies within triangle `abc` of any orientation, including its edges.
+fn in_triangle(p: (f64, f64), a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> bool {
+    let side = |a: (f64, f64), b: (f64, f64)| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+    let (ab, bc, ca) = (side(a, b), side(b, c), side(c, a));
+    (ab >= 0.0 && bc >= 0.0 && ca >= 0.0) || (ab <= 0.0 && bc <= 0.0 && ca <= 0.0)
+}
+
+/// Returns homography mapping 4 `from` points to `to` points, or `None` if points are
+/// degenerate, e.g. 3 of them lie on one line.
+fn homography(from: &[(f64, f64); 4], to: &[(f64, f64); 4]) -> Option<Homography> {
+    let mut system = [[0.0; 9]; 8];
+    for (i, (&(x, y), &(u, v))) in from.iter().zip(to).enumerate() {
+        system[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+        system[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+    }
+
+    // Gaussian elimination with partial pivoting.
+    for column in 0..8 {
+        let pivot = (column..8)
+            .max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))?;
+        if system[pivot][column].abs() < 1e-9 {
+            return None;
+        }
+        system.swap(column, pivot);
+        let pivot = system[column];
+        for row in system.iter_mut().skip(column + 1) {
+            let factor = row[column] / pivot[column];
+            for (value, pivot) in row.iter_mut().zip(&pivot).skip(column) {
+                *value -= factor * pivot;
+            }
+        }
+    }
+    let mut h = [0.0; 8];
+    for row in (0..8).rev() {
+        let known: f64 = (row + 1..8).map(|j| system[row][j] * h[j]).sum();
+        h[row] = (system[row][8] - known) / system[row][row];
+    }
+    h.iter().all(|v| v.is_finite()).then_some(h)
+}
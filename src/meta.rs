@@ -0,0 +1,873 @@
+use crate::{ExifType, ImageError};
+use exif::experimental::Writer;
+use exif::{Context, Field, In, Rational, SRational, Tag, Value};
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom};
+
+/// Tags of offsets and IFD pointers, which are maintained by EXIF writer.
+const STRUCTURAL_TAGS: [u16; 9] = [
+    0x0111, 0x0117, 0x0144, 0x0145, 0x0201, 0x0202, 0x8769, 0x8825, 0xa005,
+];
+/// Tags of TIFF file describing its pixel layout, which doesn't apply to re-encoded image, or
+/// holding metadata kept apart from EXIF.
+const TIFF_SKIPPED_TAGS: [u16; 14] = [
+    0x0100, 0x0101, 0x0102, 0x0103, 0x0106, 0x0115, 0x0116, 0x011c, 0x013d, 0x0152, 0x0153,
+    ICC_TAG, XMP_TAG, IPTC_TAG,
+];
+/// Tag of ICC profile in TIFF files.
+pub const ICC_TAG: u16 = 0x8773;
+/// Tag of XMP packet in TIFF files.
+const XMP_TAG: u16 = 0x02bc;
+/// Tag of IPTC records in TIFF files.
+const IPTC_TAG: u16 = 0x83bb;
+/// Header of EXIF APP1 segment of JPEG.
+const JPEG_EXIF_HEADER: &[u8] = b"Exif\0\0";
+/// Header of XMP APP1 segment of JPEG.
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Header of Photoshop APP13 segments of JPEG.
+const JPEG_PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+/// Header of ICC profile APP2 segments of JPEG.
+const JPEG_ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+/// Length of inch in meters.
+const INCH_METERS: f64 = 0.0254;
+/// Maximum payload size of JPEG segment.
+const JPEG_SEGMENT_MAX: usize = u16::MAX as usize - 2;
+
+/// Metadata of source image file kept along with its pixels.
+#[derive(Default, Clone)]
+pub struct Metadata {
+    /// Raw EXIF payload in TIFF layout, without container framing.
+    pub exif: Option<Vec<u8>>,
+    /// Embedded ICC profile.
+    pub icc: Option<Vec<u8>>,
+    /// Horizontal and vertical physical resolution in dots per inch.
+    pub dpi: Option<(f64, f64)>,
+    /// Keywords and values of PNG text chunks in file order.
+    pub text: Vec<(String, String)>,
+    /// XMP packet.
+    pub xmp: Option<Vec<u8>>,
+    /// IPTC-IIM records.
+    pub iptc: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Reads metadata of image in `format` from `reader`. Missing or malformed metadata is
+    /// ignored. Position of `reader` is left unspecified.
+    pub fn read<R: BufRead + Seek>(reader: &mut R, format: image::ImageFormat) -> Self {
+        use image::ImageFormat::*;
+
+        let start = reader.stream_position().ok();
+        let exif = match format {
+            Jpeg | Png | Tiff | WebP => match exif::Reader::new().read_from_container(reader) {
+                Ok(exif) => Some(exif),
+                Err(exif::Error::NotFound(_)) => None,
+                Err(e) => {
+                    crate::log::warn(&format!("Malformed EXIF ignored: {}", e));
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let mut metadata = Self::default();
+        let rewound = match start {
+            Some(start) => reader.seek(SeekFrom::Start(start)).is_ok(),
+            None => false,
+        };
+        match format {
+            Jpeg if rewound => {
+                let segments = read_jpeg_segments(reader, &[0xe0, 0xe1, 0xe2, 0xed]);
+                metadata.icc = jpeg_icc(&segments);
+                metadata.dpi = jfif_dpi(&segments);
+                metadata.xmp = jpeg_xmp(&segments);
+                metadata.iptc = jpeg_iptc(&segments);
+            }
+            Png if rewound => {
+                let types = [b"iCCP", b"pHYs", b"tEXt", b"zTXt", b"iTXt"];
+                let chunks = read_png_chunks(reader, &types);
+                metadata.icc = png_icc(&chunks);
+                if metadata.icc.is_none() && chunks.iter().any(|(ty, _)| ty == b"iCCP") {
+                    crate::log::warn("Malformed PNG iCCP chunk ignored");
+                }
+                metadata.dpi = png_dpi(&chunks);
+                metadata.text = chunks.iter().filter_map(png_text).collect();
+                let texts = chunks
+                    .iter()
+                    .filter(|(ty, _)| ty != b"iCCP" && ty != b"pHYs");
+                let skipped = texts.count() - metadata.text.len();
+                if skipped != 0 {
+                    crate::log::warn(&format!("{} malformed PNG text chunks skipped", skipped));
+                }
+            }
+            WebP if rewound => metadata.icc = read_webp_icc(reader),
+            Tiff => {
+                if let Some(exif) = &exif {
+                    metadata.icc = tiff_blob(exif, ICC_TAG);
+                    metadata.xmp = tiff_blob(exif, XMP_TAG);
+                    metadata.iptc = tiff_blob(exif, IPTC_TAG);
+                }
+            }
+            _ => {}
+        }
+        if metadata.dpi.is_none() {
+            metadata.dpi = exif.as_ref().and_then(exif_dpi);
+        }
+
+        // TIFF file is parsed as a whole, so only its descriptive tags are kept.
+        metadata.exif = match (format, exif) {
+            (Tiff, Some(exif)) => rewrite_exif(&exif, None, |f| {
+                f.ifd_num == In::PRIMARY && !TIFF_SKIPPED_TAGS.contains(&f.tag.number())
+            })
+            .ok(),
+            (_, exif) => exif.map(|e| e.buf().to_vec()),
+        };
+        metadata
+    }
+
+    /// Returns values of IPTC `dataset` of application record (e.g. `25` for keywords).
+    pub fn iptc_values(&self, dataset: u8) -> Vec<String> {
+        let records = match &self.iptc {
+            Some(iptc) => iptc_records(iptc),
+            None => return Vec::new(),
+        };
+
+        // Coded character set of envelope record, UTF-8 is `ESC % G`.
+        let utf8 = records
+            .iter()
+            .any(|&(record, dataset, data)| (record, dataset, data) == (1, 90, &b"\x1b%G"[..]));
+        records
+            .into_iter()
+            .filter(|&(record, number, _)| record == 2 && number == dataset)
+            .map(|(_, _, data)| match std::str::from_utf8(data) {
+                Ok(value) if utf8 || value.is_ascii() => value.to_string(),
+                // Legacy records are mostly Latin-1.
+                _ => data.iter().map(|&b| b as char).collect(),
+            })
+            .collect()
+    }
+
+    /// Returns `true` if encoded image needs metadata embedded by `embed` with
+    /// `keep_metadata`.
+    pub fn needs_embedding(&self, keep_metadata: bool) -> bool {
+        let descriptive = self.exif.is_some() || !self.text.is_empty();
+        (keep_metadata && descriptive) || self.icc.is_some() || self.dpi.is_some()
+    }
+
+    /// Embeds ICC profile, resolution and, if `keep_metadata` is set, EXIF and PNG text into
+    /// `encoded` image in `format`. Formats without metadata support are returned unchanged.
+    pub fn embed(
+        &self,
+        encoded: Vec<u8>,
+        format: image::ImageFormat,
+        keep_metadata: bool,
+    ) -> Result<Vec<u8>, ImageError> {
+        let exif = self.exif.as_ref().filter(|_| keep_metadata);
+        let text = if keep_metadata { &self.text[..] } else { &[] };
+        match format {
+            image::ImageFormat::Jpeg => embed_jpeg(encoded, exif, self.icc.as_ref(), self.dpi),
+            image::ImageFormat::Png => {
+                Ok(embed_png(encoded, exif, self.icc.as_ref(), self.dpi, text))
+            }
+            _ => {
+                if self.needs_embedding(keep_metadata) {
+                    let message = format!("Metadata dropped: {:?} doesn't support it", format);
+                    crate::log::warn(&message);
+                }
+                Ok(encoded)
+            }
+        }
+    }
+
+    /// Returns value of the first PNG text chunk with `keyword`.
+    pub fn text(&self, keyword: &str) -> Option<&str> {
+        let (_, value) = self.text.iter().find(|(k, _)| k == keyword)?;
+        Some(value)
+    }
+
+    /// Replaces values of PNG text chunks with `keyword` by `value`, or removes them if `value`
+    /// is `None`. Returns `ImageError::Parameter` if `keyword` isn't 1 to 79 printable Latin-1
+    /// characters.
+    pub fn set_text(&mut self, keyword: &str, value: Option<&str>) -> Result<(), ImageError> {
+        let printable = |c: char| (' '..='~').contains(&c) || ('\u{a1}'..='\u{ff}').contains(&c);
+        if !(1..=79).contains(&keyword.chars().count()) || !keyword.chars().all(printable) {
+            return Err(ImageError::Parameter);
+        }
+
+        let position = self.text.iter().position(|(k, _)| k == keyword);
+        self.text.retain(|(k, _)| k != keyword);
+        if let Some(value) = value {
+            let entry = (keyword.to_string(), value.to_string());
+            self.text.insert(position.unwrap_or(self.text.len()), entry);
+        }
+        Ok(())
+    }
+
+    /// Sets physical resolution to `x` and `y` dots per inch. Resolution tags of EXIF, if there
+    /// is one, are updated too.
+    pub fn set_dpi(&mut self, x: f64, y: f64) -> Result<(), ImageError> {
+        if let Some(exif) = &self.exif {
+            // Resolution is stored with two decimal places.
+            let rational = |dpi: f64| format!("{}/100", (dpi * 100.0).round() as u32);
+            let exif = set_exif_field(Some(exif), 0x011a, ExifType::Rational, &rational(x))?;
+            let exif = set_exif_field(Some(&exif), 0x011b, ExifType::Rational, &rational(y))?;
+            let exif = set_exif_field(Some(&exif), 0x0128, ExifType::Short, "2")?;
+            self.exif = Some(exif);
+        }
+        self.dpi = Some((x, y));
+        Ok(())
+    }
+}
+
+/// Inserts EXIF APP1 and ICC profile APP2 segments after SOI and JFIF APP0 segments of JPEG
+/// image. Resolution is written to JFIF APP0 segment.
+fn embed_jpeg(
+    mut encoded: Vec<u8>,
+    exif: Option<&Vec<u8>>,
+    icc: Option<&Vec<u8>>,
+    dpi: Option<(f64, f64)>,
+) -> Result<Vec<u8>, ImageError> {
+    let mut segments = Vec::new();
+    if let Some(exif) = exif {
+        if JPEG_EXIF_HEADER.len() + exif.len() > JPEG_SEGMENT_MAX {
+            return Err(ImageError::LimitExceeded);
+        }
+        segments.push((0xe1, [JPEG_EXIF_HEADER, exif].concat()));
+    }
+
+    if let Some(icc) = icc {
+        let chunks: Vec<_> = icc
+            .chunks(JPEG_SEGMENT_MAX - JPEG_ICC_HEADER.len() - 2)
+            .collect();
+        let count = u8::try_from(chunks.len()).map_err(|_| ImageError::LimitExceeded)?;
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let sequence = [i as u8 + 1, count];
+            segments.push((0xe2, [JPEG_ICC_HEADER, &sequence, chunk].concat()));
+        }
+    }
+
+    let mut position = 2;
+    let has_jfif =
+        encoded.get(2..4) == Some(&[0xff, 0xe0]) && encoded.get(6..11) == Some(b"JFIF\0");
+    if has_jfif {
+        let app0_len = u16::from_be_bytes([encoded[4], encoded[5]]);
+        position += 2 + app0_len as usize;
+    }
+
+    if let Some((x, y)) = dpi {
+        // Units, horizontal and vertical density follow identifier and version.
+        let density = |dpi: f64| (dpi.round().clamp(1.0, u16::MAX as f64) as u16).to_be_bytes();
+        let fields = [&[1][..], &density(x), &density(y)].concat();
+        match has_jfif {
+            true => encoded[13..18].copy_from_slice(&fields),
+            false => segments.insert(
+                0,
+                (0xe0, [&b"JFIF\0\x01\x02"[..], &fields, &[0, 0]].concat()),
+            ),
+        }
+    }
+
+    let mut embedded = encoded[..position].to_vec();
+    for (marker, payload) in segments {
+        embedded.extend_from_slice(&[0xff, marker]);
+        embedded.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        embedded.extend_from_slice(&payload);
+    }
+    embedded.extend_from_slice(&encoded[position..]);
+    Ok(embedded)
+}
+
+/// Inserts `iCCP`, `pHYs`, `eXIf` and text chunks after IHDR chunk of PNG image. Latin-1 text
+/// is written to `tEXt` chunks, other text to `iTXt` chunks.
+fn embed_png(
+    encoded: Vec<u8>,
+    exif: Option<&Vec<u8>>,
+    icc: Option<&Vec<u8>>,
+    dpi: Option<(f64, f64)>,
+    text: &[(String, String)],
+) -> Vec<u8> {
+    // Signature, then IHDR chunk of length, type, 13 bytes of data and CRC.
+    const POSITION: usize = 8 + 4 + 4 + 13 + 4;
+
+    let mut chunks = Vec::new();
+    if let Some(icc) = icc {
+        // Profile name, compression method and zlib stream.
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(icc, 6);
+        chunks.push([b"iCCP", &b"ICC Profile\0\0"[..], &compressed].concat());
+    }
+    if let Some((x, y)) = dpi {
+        // Pixels per meter and unit.
+        let density = |dpi: f64| ((dpi / INCH_METERS).round() as u32).to_be_bytes();
+        chunks.push([b"pHYs", &density(x)[..], &density(y), &[1]].concat());
+    }
+    if let Some(exif) = exif {
+        chunks.push([b"eXIf", &exif[..]].concat());
+    }
+    for (keyword, value) in text {
+        let keyword = latin1(keyword).unwrap_or_default();
+        chunks.push(match latin1(value) {
+            Some(value) => [&b"tEXt"[..], &keyword, &[0], &value].concat(),
+            // Uncompressed, without language tag and translated keyword.
+            None => [&b"iTXt"[..], &keyword, &[0, 0, 0, 0, 0], value.as_bytes()].concat(),
+        });
+    }
+
+    let mut embedded = encoded[..POSITION].to_vec();
+    for chunk in chunks {
+        embedded.extend_from_slice(&(chunk.len() as u32 - 4).to_be_bytes());
+        embedded.extend_from_slice(&chunk);
+        embedded.extend_from_slice(&crc32fast::hash(&chunk).to_be_bytes());
+    }
+    embedded.extend_from_slice(&encoded[POSITION..]);
+    embedded
+}
+
+/// Reads payloads of JPEG segments with `markers` preceding image data.
+fn read_jpeg_segments<R: Read + Seek>(reader: &mut R, markers: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut segments = Vec::new();
+    let mut soi = [0; 2];
+    if reader.read_exact(&mut soi).is_err() || soi != [0xff, 0xd8] {
+        return segments;
+    }
+
+    loop {
+        let mut header = [0; 4];
+        if reader.read_exact(&mut header).is_err() {
+            break;
+        }
+        // Metadata segments precede start of scan.
+        if header[0] != 0xff || header[1] == 0xda || header[1] == 0xd9 {
+            break;
+        }
+
+        let len = match (u16::from_be_bytes([header[2], header[3]]) as usize).checked_sub(2) {
+            Some(len) => len,
+            None => break,
+        };
+        if !markers.contains(&header[1]) {
+            if reader.seek(SeekFrom::Current(len as i64)).is_err() {
+                break;
+            }
+            continue;
+        }
+
+        let mut payload = vec![0; len];
+        if reader.read_exact(&mut payload).is_err() {
+            break;
+        }
+        segments.push((header[1], payload));
+    }
+    segments
+}
+
+/// Returns ICC profile assembled from APP2 `segments` of JPEG image.
+fn jpeg_icc(segments: &[(u8, Vec<u8>)]) -> Option<Vec<u8>> {
+    let mut chunks: Vec<_> = segments
+        .iter()
+        .filter(|(marker, payload)| {
+            *marker == 0xe2
+                && payload.len() > JPEG_ICC_HEADER.len() + 2
+                && payload.starts_with(JPEG_ICC_HEADER)
+        })
+        .map(|(_, payload)| {
+            let sequence = payload[JPEG_ICC_HEADER.len()];
+            (sequence, &payload[JPEG_ICC_HEADER.len() + 2..])
+        })
+        .collect();
+
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|c| c.0);
+    Some(
+        chunks
+            .into_iter()
+            .flat_map(|c| c.1.iter().cloned())
+            .collect(),
+    )
+}
+
+/// Returns resolution stored in JFIF APP0 segment of JPEG image.
+fn jfif_dpi(segments: &[(u8, Vec<u8>)]) -> Option<(f64, f64)> {
+    let (_, payload) = segments
+        .iter()
+        .find(|(marker, payload)| *marker == 0xe0 && payload.starts_with(b"JFIF\0"))?;
+    // Units, horizontal and vertical density follow identifier and version.
+    let fields = payload.get(7..12)?;
+    let x = u16::from_be_bytes([fields[1], fields[2]]) as f64;
+    let y = u16::from_be_bytes([fields[3], fields[4]]) as f64;
+    match fields[0] {
+        1 => Some((x, y)),
+        2 => Some((x * 2.54, y * 2.54)),
+        // Aspect ratio only.
+        _ => None,
+    }
+}
+
+/// Reads payloads of PNG chunks of `types`.
+fn read_png_chunks<R: Read + Seek>(reader: &mut R, types: &[&[u8; 4]]) -> Vec<([u8; 4], Vec<u8>)> {
+    let mut chunks = Vec::new();
+    if reader.seek(SeekFrom::Current(8)).is_err() {
+        return chunks;
+    }
+
+    loop {
+        let mut header = [0; 8];
+        if reader.read_exact(&mut header).is_err() {
+            break;
+        }
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let ty = [header[4], header[5], header[6], header[7]];
+        if &ty == b"IEND" {
+            break;
+        }
+
+        if !types.contains(&&ty) {
+            if reader.seek(SeekFrom::Current(len as i64 + 4)).is_err() {
+                break;
+            }
+            continue;
+        }
+
+        let data = match read_chunk(reader, len) {
+            Some(data) if reader.seek(SeekFrom::Current(4)).is_ok() => data,
+            _ => break,
+        };
+        chunks.push((ty, data));
+    }
+    chunks
+}
+
+/// Reads `len` bytes of chunk payload. Buffer grows with data actually read, so lengths of
+/// truncated or corrupted files don't allocate more than file holds.
+fn read_chunk<R: Read>(reader: &mut R, len: u32) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len as u64).read_to_end(&mut data).ok()?;
+    match data.len() == len as usize {
+        true => Some(data),
+        false => None,
+    }
+}
+
+/// Returns ICC profile stored in `iCCP` chunk of PNG image.
+fn png_icc(chunks: &[([u8; 4], Vec<u8>)]) -> Option<Vec<u8>> {
+    let (_, data) = chunks.iter().find(|(ty, _)| ty == b"iCCP")?;
+    // Profile name, compression method and zlib stream.
+    let name_end = data.iter().position(|&b| b == 0)?;
+    let compressed = data.get(name_end + 2..)?;
+    miniz_oxide::inflate::decompress_to_vec_zlib(compressed).ok()
+}
+
+/// Returns keyword and value of PNG text `chunk`.
+fn png_text((ty, data): &([u8; 4], Vec<u8>)) -> Option<(String, String)> {
+    let keyword_end = data.iter().position(|&b| b == 0)?;
+    let keyword = data[..keyword_end].iter().map(|&b| b as char).collect();
+    let rest = &data[keyword_end + 1..];
+
+    let value = match ty {
+        b"tEXt" => rest.iter().map(|&b| b as char).collect(),
+        b"zTXt" => {
+            // Compression method and zlib stream.
+            let text = miniz_oxide::inflate::decompress_to_vec_zlib(rest.get(1..)?).ok()?;
+            text.iter().map(|&b| b as char).collect()
+        }
+        b"iTXt" => {
+            // Compression flag and method, language tag and translated keyword.
+            let (compressed, rest) = (*rest.first()? == 1, rest.get(2..)?);
+            let language_end = rest.iter().position(|&b| b == 0)?;
+            let rest = &rest[language_end + 1..];
+            let translated_end = rest.iter().position(|&b| b == 0)?;
+            let text = &rest[translated_end + 1..];
+            let text = match compressed {
+                true => miniz_oxide::inflate::decompress_to_vec_zlib(text).ok()?,
+                false => text.to_vec(),
+            };
+            String::from_utf8(text).ok()?
+        }
+        _ => return None,
+    };
+    Some((keyword, value))
+}
+
+/// Returns `text` encoded in Latin-1, or `None` if it has other characters.
+fn latin1(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| u8::try_from(c as u32).ok()).collect()
+}
+
+/// Returns resolution stored in `pHYs` chunk of PNG image.
+fn png_dpi(chunks: &[([u8; 4], Vec<u8>)]) -> Option<(f64, f64)> {
+    let (_, data) = chunks.iter().find(|(ty, _)| ty == b"pHYs")?;
+    if data.len() != 9 || data[8] != 1 {
+        return None;
+    }
+    let x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f64;
+    let y = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as f64;
+    Some((x * INCH_METERS, y * INCH_METERS))
+}
+
+/// Returns resolution stored in resolution tags of primary image in `exif`. Missing vertical
+/// resolution is taken equal to horizontal one.
+fn exif_dpi(exif: &exif::Exif) -> Option<(f64, f64)> {
+    let rational = |tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(v) if !v.is_empty() && v[0].denom != 0 => Some(v[0].to_f64()),
+        _ => None,
+    };
+    let x = rational(Tag::XResolution)?;
+    let y = rational(Tag::YResolution).unwrap_or(x);
+    let unit = exif
+        .get_field(Tag::ResolutionUnit, In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .unwrap_or(2);
+    match unit {
+        2 => Some((x, y)),
+        3 => Some((x * 2.54, y * 2.54)),
+        _ => None,
+    }
+}
+
+/// Reads ICC profile from `ICCP` chunk of WebP image.
+fn read_webp_icc<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    reader.seek(SeekFrom::Current(12)).ok()?;
+    loop {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header).ok()?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if &header[..4] == b"ICCP" {
+            return read_chunk(reader, len);
+        }
+        // Chunks are padded to even size.
+        reader
+            .seek(SeekFrom::Current((len + len % 2) as i64))
+            .ok()?;
+    }
+}
+
+/// Returns bytes of `tag` of primary IFD of TIFF file. Tags of other types than bytes (IPTC
+/// records are often typed as longs) are converted back to bytes in file byte order.
+fn tiff_blob(exif: &exif::Exif, tag: u16) -> Option<Vec<u8>> {
+    let field = exif
+        .fields()
+        .find(|f| f.ifd_num == In::PRIMARY && f.tag.number() == tag)?;
+    match &field.value {
+        Value::Undefined(data, _) | Value::Byte(data) => Some(data.clone()),
+        Value::Ascii(strings) => Some(strings.concat()),
+        Value::Long(values) if exif.little_endian() => {
+            Some(values.iter().flat_map(|v| v.to_le_bytes()).collect())
+        }
+        Value::Long(values) => Some(values.iter().flat_map(|v| v.to_be_bytes()).collect()),
+        _ => None,
+    }
+}
+
+/// Returns XMP packet stored in APP1 `segments` of JPEG image.
+fn jpeg_xmp(segments: &[(u8, Vec<u8>)]) -> Option<Vec<u8>> {
+    let (_, payload) = segments
+        .iter()
+        .find(|(marker, payload)| *marker == 0xe1 && payload.starts_with(JPEG_XMP_HEADER))?;
+    Some(payload[JPEG_XMP_HEADER.len()..].to_vec())
+}
+
+/// Returns IPTC records stored in Photoshop image resource of APP13 `segments` of JPEG image.
+fn jpeg_iptc(segments: &[(u8, Vec<u8>)]) -> Option<Vec<u8>> {
+    let resources: Vec<u8> = segments
+        .iter()
+        .filter(|(marker, payload)| *marker == 0xed && payload.starts_with(JPEG_PHOTOSHOP_HEADER))
+        .flat_map(|(_, payload)| payload[JPEG_PHOTOSHOP_HEADER.len()..].iter().cloned())
+        .collect();
+
+    // Resources are signature, ID, padded Pascal name, size and data padded to even size.
+    let mut rest = &resources[..];
+    while rest.len() >= 12 && rest.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([rest[4], rest[5]]);
+        let name_len = (1 + rest[6] as usize + 1) & !1;
+        let header_len = 6 + name_len + 4;
+        let size = rest.get(header_len - 4..header_len)?;
+        let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        let data = rest.get(header_len..header_len.checked_add(size)?)?;
+        if id == 0x0404 {
+            return Some(data.to_vec());
+        }
+        rest = rest
+            .get(header_len + ((size + 1) & !1)..)
+            .unwrap_or_default();
+    }
+    None
+}
+
+/// Returns record number, dataset number and data of IPTC-IIM datasets in `iptc`.
+fn iptc_records(iptc: &[u8]) -> Vec<(u8, u8, &[u8])> {
+    let mut records = Vec::new();
+    let mut rest = iptc;
+    while rest.len() >= 5 && rest[0] == 0x1c {
+        let (record, dataset) = (rest[1], rest[2]);
+        let size = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        let (size, start) = match size & 0x8000 {
+            // Extended size is stored in following bytes.
+            0 => (size, 5),
+            _ => {
+                let len = size & 0x7fff;
+                let bytes = match rest.get(5..5 + len) {
+                    Some(b) if len <= 8 => b,
+                    _ => break,
+                };
+                let size = bytes.iter().fold(0usize, |size, &b| size << 8 | b as usize);
+                (size, 5 + len)
+            }
+        };
+        let data = match rest.get(start..start.saturating_add(size)) {
+            Some(d) => d,
+            None => break,
+        };
+        records.push((record, dataset, data));
+        rest = &rest[start + size..];
+    }
+    records
+}
+
+/// Returns `true` if `icc` looks like ICC profile: it has profile signature and its size
+/// matches size in header.
+pub fn is_icc_profile(icc: &[u8]) -> bool {
+    icc.len() >= 128
+        && u32::from_be_bytes([icc[0], icc[1], icc[2], icc[3]]) as usize == icc.len()
+        && &icc[36..40] == b"acsp"
+}
+
+/// Returns raw EXIF payload `exif` (or new payload, if `exif` is `None`) with `tag` of primary
+/// image set to `value` parsed as `ty`. Numbers are separated by commas, rationals are written
+/// as `num/denom` or as integer. Whole `value` is single ASCII string. Undefined values are
+/// bytes of `value`.
+pub fn set_exif_field(
+    exif: Option<&[u8]>,
+    tag: u16,
+    ty: ExifType,
+    value: &str,
+) -> Result<Vec<u8>, ImageError> {
+    if STRUCTURAL_TAGS.contains(&tag) {
+        return Err(ImageError::Parameter);
+    }
+
+    let field = Field {
+        tag: known_tag(tag),
+        ifd_num: In::PRIMARY,
+        value: parse_value(ty, value).ok_or(ImageError::Parameter)?,
+    };
+
+    let source = match exif {
+        Some(e) => exif::Reader::new()
+            .read_raw(e.to_vec())
+            .map_err(|_| ImageError::Decoding)?,
+        None => return write_exif(vec![&field], None, false),
+    };
+
+    let replaced = |f: &Field| f.ifd_num == In::PRIMARY && f.tag.number() == tag;
+    rewrite_exif(&source, Some(&field), |f| !replaced(f))
+}
+
+/// Writes EXIF payload with fields of `source` accepted by `keep` and `extra` field. JPEG
+/// thumbnail is kept if its fields are kept.
+fn rewrite_exif<F>(
+    source: &exif::Exif,
+    extra: Option<&Field>,
+    keep: F,
+) -> Result<Vec<u8>, ImageError>
+where
+    F: Fn(&Field) -> bool,
+{
+    let thumbnail = jpeg_thumbnail(source);
+    let mut fields: Vec<&Field> = source
+        .fields()
+        .filter(|f| !matches!(f.value, Value::Unknown(..)))
+        .filter(|f| f.ifd_num == In::PRIMARY || thumbnail.is_some())
+        .filter(|f| keep(f))
+        .collect();
+    fields.extend(extra);
+
+    let thumbnail = thumbnail.filter(|_| fields.iter().any(|f| f.ifd_num == In::THUMBNAIL));
+    write_exif(fields, thumbnail, source.little_endian())
+}
+
+/// Writes EXIF payload with `fields` and JPEG `thumbnail`.
+fn write_exif(
+    fields: Vec<&Field>,
+    thumbnail: Option<&[u8]>,
+    little_endian: bool,
+) -> Result<Vec<u8>, ImageError> {
+    let mut writer = Writer::new();
+    for f in fields {
+        writer.push_field(f);
+    }
+    if let Some(thumbnail) = thumbnail {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+
+    let mut written = Cursor::new(Vec::new());
+    writer
+        .write(&mut written, little_endian)
+        .map_err(|_| ImageError::Encoding)?;
+    Ok(written.into_inner())
+}
+
+/// Returns raw EXIF payload with fields of primary image of `exif` (or new payload, if `exif` is
+/// `None`) and JPEG `thumbnail` replacing embedded one.
+pub fn set_exif_thumbnail(exif: Option<&[u8]>, thumbnail: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let source = match exif {
+        Some(e) => Some(
+            exif::Reader::new()
+                .read_raw(e.to_vec())
+                .map_err(|_| ImageError::Decoding)?,
+        ),
+        None => None,
+    };
+
+    // Resolution fields are required in both IFDs, thumbnail is JPEG-compressed.
+    let resolution = |tag, ifd_num| Field {
+        tag,
+        ifd_num,
+        value: Value::Rational(vec![Rational { num: 72, denom: 1 }]),
+    };
+    let unit = |ifd_num| Field {
+        tag: Tag::ResolutionUnit,
+        ifd_num,
+        value: Value::Short(vec![2]),
+    };
+    let required = [
+        resolution(Tag::XResolution, In::PRIMARY),
+        resolution(Tag::YResolution, In::PRIMARY),
+        unit(In::PRIMARY),
+        resolution(Tag::XResolution, In::THUMBNAIL),
+        resolution(Tag::YResolution, In::THUMBNAIL),
+        unit(In::THUMBNAIL),
+        Field {
+            tag: Tag::Compression,
+            ifd_num: In::THUMBNAIL,
+            value: Value::Short(vec![6]),
+        },
+    ];
+
+    let mut fields: Vec<&Field> = source
+        .iter()
+        .flat_map(|s| s.fields())
+        .filter(|f| f.ifd_num == In::PRIMARY && !matches!(f.value, Value::Unknown(..)))
+        .collect();
+    for field in &required {
+        let present = |f: &&Field| f.ifd_num == field.ifd_num && f.tag == field.tag;
+        if !fields.iter().any(present) {
+            fields.push(field);
+        }
+    }
+    let little_endian = source.as_ref().is_some_and(|s| s.little_endian());
+    write_exif(fields, Some(thumbnail), little_endian)
+}
+
+/// Returns tag with `number` in context where it is defined. Unknown tags are placed in TIFF
+/// context.
+fn known_tag(number: u16) -> Tag {
+    [Context::Tiff, Context::Exif, Context::Gps]
+        .iter()
+        .map(|&context| Tag(context, number))
+        .find(|tag| tag.description().is_some())
+        .unwrap_or(Tag(Context::Tiff, number))
+}
+
+/// Reads only EXIF of image file from `reader` and returns JPEG thumbnail embedded in it with
+/// raw EXIF.
+pub fn read_exif_thumbnail<R: BufRead + Seek>(reader: &mut R) -> Option<(Vec<u8>, Vec<u8>)> {
+    let exif = exif::Reader::new().read_from_container(reader).ok()?;
+    let thumbnail = jpeg_thumbnail(&exif)?.to_vec();
+    Some((thumbnail, exif.buf().to_vec()))
+}
+
+/// Returns JPEG thumbnail embedded in EXIF.
+fn jpeg_thumbnail(exif: &exif::Exif) -> Option<&[u8]> {
+    let offset = exif.get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?;
+    let len = exif.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?;
+    let offset = offset.value.get_uint(0)? as usize;
+    let len = len.value.get_uint(0)? as usize;
+    exif.buf().get(offset..offset.checked_add(len)?)
+}
+
+/// Parses `value` written as described in `set_exif_field`.
+fn parse_value(ty: ExifType, value: &str) -> Option<Value> {
+    fn list<T: std::str::FromStr>(value: &str) -> Option<Vec<T>> {
+        value.split(',').map(|v| v.trim().parse().ok()).collect()
+    }
+
+    fn ratio<T: std::str::FromStr + From<u8>>(value: &str) -> Option<(T, T)> {
+        match value.split_once('/') {
+            Some((num, denom)) => Some((num.trim().parse().ok()?, denom.trim().parse().ok()?)),
+            None => Some((value.trim().parse().ok()?, T::from(1))),
+        }
+    }
+
+    let value = match ty {
+        ExifType::Ascii => Value::Ascii(vec![value.as_bytes().to_vec()]),
+        ExifType::Undefined => Value::Undefined(value.as_bytes().to_vec(), 0),
+        ExifType::Byte => Value::Byte(list(value)?),
+        ExifType::Short => Value::Short(list(value)?),
+        ExifType::Long => Value::Long(list(value)?),
+        ExifType::Rational => Value::Rational(
+            value
+                .split(',')
+                .map(|v| ratio(v).map(|(num, denom)| Rational { num, denom }))
+                .collect::<Option<_>>()?,
+        ),
+        ExifType::SRational => Value::SRational(
+            value
+                .split(',')
+                .map(|v| ratio(v).map(|(num, denom)| SRational { num, denom }))
+                .collect::<Option<_>>()?,
+        ),
+    };
+    Some(value)
+}
+
+/// Returns value of `tag` of primary image in raw EXIF payload formatted as string, or `None`
+/// if there is no such tag. Integers and rationals are written in decimal and separated by
+/// commas if there are several.
+pub fn exif_value(exif: &[u8], tag: u16) -> Option<String> {
+    let exif = exif::Reader::new().read_raw(exif.to_vec()).ok()?;
+    let field = exif
+        .fields()
+        .find(|f| f.ifd_num == In::PRIMARY && f.tag.number() == tag)?;
+
+    let value = match &field.value {
+        Value::Byte(v) => join(v),
+        Value::Short(v) => join(v),
+        Value::Long(v) => join(v),
+        Value::SByte(v) => join(v),
+        Value::SShort(v) => join(v),
+        Value::SLong(v) => join(v),
+        Value::Float(v) => join(v),
+        Value::Double(v) => join(v),
+        Value::Rational(v) => join(
+            &v.iter()
+                .map(|r| format!("{}/{}", r.num, r.denom))
+                .collect::<Vec<_>>(),
+        ),
+        Value::SRational(v) => join(
+            &v.iter()
+                .map(|r| format!("{}/{}", r.num, r.denom))
+                .collect::<Vec<_>>(),
+        ),
+        Value::Ascii(v) => v
+            .iter()
+            .map(|s| String::from_utf8_lossy(s))
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => field.display_value().to_string(),
+    };
+    Some(value.replace('\0', ""))
+}
+
+/// Returns EXIF orientation of primary image in `1..=8` range.
+pub fn exif_orientation(exif: &[u8]) -> Option<u32> {
+    let exif = exif::Reader::new().read_raw(exif.to_vec()).ok()?;
+    let field = exif.get_field(Tag::Orientation, In::PRIMARY)?;
+    field.value.get_uint(0).filter(|o| (1..=8).contains(o))
+}
+
+/// Joins `values` with commas.
+fn join<T: Display>(values: &[T]) -> String {
+    let values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+    values.join(", ")
+}
@@ -0,0 +1,290 @@
+use crate::document::{Document, Layer};
+use crate::{BlendMode, ImageError, ImageFormat, LastError, OpenOptions, SaveOptions};
+use image::{DynamicImage, GenericImageView};
+use std::convert::TryFrom;
+use std::io::Cursor;
+
+/// MIME type stored as the first file of OpenRaster archive.
+const MIMETYPE: &[u8] = b"image/openraster";
+/// Maximum width and height of thumbnail.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// `composite-op` attribute values of blend modes in `BlendMode` order.
+const COMPOSITE_OPS: [&str; 8] = [
+    "svg:src-over",
+    "svg:multiply",
+    "svg:screen",
+    "svg:overlay",
+    "svg:darken",
+    "svg:lighten",
+    "svg:plus",
+    "svg:difference",
+];
+
+/// Reads OpenRaster document from `file`: canvas size and PNG layers listed in `stack.xml`.
+/// Nested stacks are flattened into one list of layers, their own attributes are ignored.
+pub fn read(file: &[u8]) -> Result<Document, ImageError> {
+    let stack = read_entry(file, "stack.xml")?;
+    let stack = String::from_utf8_lossy(&stack);
+
+    let image = match tags(&stack, "image").next() {
+        Some(i) => i,
+        None => return Err(malformed("stack.xml has no image element")),
+    };
+    let size = |name| attribute(image, name).and_then(|v| v.parse::<u32>().ok());
+    let (width, height) = match (size("w"), size("h")) {
+        (Some(w), Some(h)) if w != 0 && h != 0 => (w, h),
+        _ => return Err(malformed("invalid canvas size")),
+    };
+
+    let mut layers = Vec::new();
+    for tag in tags(&stack, "layer") {
+        let src = match attribute(tag, "src") {
+            Some(s) => s,
+            None => return Err(malformed("layer without src")),
+        };
+        let png = read_entry(file, &src)?;
+        let data = crate::decode_as(
+            Cursor::new(png),
+            Some(image::ImageFormat::Png),
+            &OpenOptions::default(),
+        )?;
+
+        let offset = |name| attribute(tag, name).map_or(Some(0), |v| v.parse::<i32>().ok());
+        let (x, y) = match (offset("x"), offset("y")) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return Err(malformed("invalid layer offset")),
+        };
+        let opacity = attribute(tag, "opacity").map_or(Some(1.0), |v| v.parse::<f32>().ok());
+        let opacity = match opacity {
+            Some(o) if o.is_finite() => o.clamp(0.0, 1.0),
+            _ => return Err(malformed("invalid layer opacity")),
+        };
+
+        let op = attribute(tag, "composite-op");
+        let blend_mode = match COMPOSITE_OPS.iter().position(|&o| Some(o) == op.as_deref()) {
+            Some(mode) => BlendMode::try_from(mode as u32)?,
+            None => {
+                if let Some(op) = op {
+                    let message = format!("Unsupported composite-op {} replaced with normal", op);
+                    crate::log::warn(&message);
+                }
+                BlendMode::Normal
+            }
+        };
+
+        // Layers are listed from the top one.
+        layers.insert(
+            0,
+            Layer {
+                data: data.into_box(),
+                x,
+                y,
+                opacity,
+                blend_mode,
+                visible: attribute(tag, "visibility").as_deref() != Some("hidden"),
+            },
+        );
+    }
+
+    Ok(Document {
+        width,
+        height,
+        layers,
+    })
+}
+
+/// Writes `document` as OpenRaster archive with layer PNGs, merged image and thumbnail.
+pub fn write(document: &Document) -> Result<Vec<u8>, ImageError> {
+    let mut stack = format!(
+        "<?xml version='1.0' encoding='UTF-8'?>\n<image version=\"0.0.3\" w=\"{}\" h=\"{}\">\n<stack>\n",
+        document.width, document.height
+    );
+    let mut entries = vec![("mimetype".to_string(), MIMETYPE.to_vec())];
+    for (index, layer) in document.layers.iter().enumerate().rev() {
+        let src = format!("data/layer{}.png", index);
+        stack += &format!(
+            "<layer name=\"Layer {}\" src=\"{}\" x=\"{}\" y=\"{}\" opacity=\"{}\" visibility=\"{}\" composite-op=\"{}\"/>\n",
+            index + 1,
+            src,
+            layer.x,
+            layer.y,
+            layer.opacity,
+            if layer.visible { "visible" } else { "hidden" },
+            COMPOSITE_OPS[layer.blend_mode as usize],
+        );
+        let png = crate::encode_data(&layer.data, ImageFormat::Png, &SaveOptions::default())?;
+        entries.push((src, png));
+    }
+    stack += "</stack>\n</image>\n";
+    entries.insert(1, ("stack.xml".to_string(), stack.into_bytes()));
+
+    let merged = document.flatten()?;
+    let (width, height) = merged.dimensions();
+    let thumbnail = match width > THUMBNAIL_SIZE || height > THUMBNAIL_SIZE {
+        true => merged.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE),
+        false => merged.clone(),
+    };
+    entries.push(("mergedimage.png".to_string(), png(&merged)?));
+    entries.push(("Thumbnails/thumbnail.png".to_string(), png(&thumbnail)?));
+    Ok(zip(&entries))
+}
+
+/// Encodes `image` as PNG.
+fn png(image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+    crate::encode(image, image::ImageOutputFormat::Png)
+}
+
+/// Returns uncompressed content of file `name` of zip archive `file`.
+fn read_entry(file: &[u8], name: &str) -> Result<Vec<u8>, ImageError> {
+    let u16_at = |pos: usize| {
+        file.get(pos..pos + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+    };
+    let u32_at = |pos: usize| {
+        let b = file.get(pos..pos + 4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+
+    // End of central directory record is followed by comment of up to 64 KiB.
+    let search_start = file.len().saturating_sub(22 + u16::MAX as usize);
+    let end = (search_start..file.len().saturating_sub(21))
+        .rev()
+        .find(|&pos| u32_at(pos) == Some(0x0605_4b50))
+        .ok_or_else(|| malformed("not a zip archive"))?;
+    let count = u16_at(end + 10).ok_or_else(|| malformed("truncated archive"))?;
+    let mut pos = u32_at(end + 16).ok_or_else(|| malformed("truncated archive"))?;
+
+    for _ in 0..count {
+        if u32_at(pos) != Some(0x0201_4b50) {
+            return Err(malformed("invalid central directory"));
+        }
+        let field = |offset| u16_at(pos + offset).ok_or_else(|| malformed("truncated archive"));
+        let (method, name_len, extra_len, comment_len) =
+            (field(10)?, field(28)?, field(30)?, field(32)?);
+        let entry_name = file.get(pos + 46..pos + 46 + name_len);
+        if entry_name != Some(name.as_bytes()) {
+            pos += 46 + name_len + extra_len + comment_len;
+            continue;
+        }
+
+        let sizes = (
+            u32_at(pos + 16),
+            u32_at(pos + 20),
+            u32_at(pos + 24),
+            u32_at(pos + 42),
+        );
+        let (crc, compressed_size, size, local) = match sizes {
+            (Some(c), Some(cs), Some(s), Some(l)) => (c as u32, cs, s, l),
+            _ => return Err(malformed("truncated archive")),
+        };
+        if u32_at(local) != Some(0x0403_4b50) {
+            return Err(malformed("invalid local file header"));
+        }
+        let local_len = match (u16_at(local + 26), u16_at(local + 28)) {
+            (Some(n), Some(e)) => 30 + n + e,
+            _ => return Err(malformed("truncated archive")),
+        };
+        let data = file
+            .get(local + local_len..local + local_len + compressed_size)
+            .ok_or_else(|| malformed("truncated archive"))?;
+
+        let data = match method {
+            0 => data.to_vec(),
+            8 => miniz_oxide::inflate::decompress_to_vec_with_limit(data, size)
+                .map_err(|_| malformed("invalid deflate stream"))?,
+            _ => return Err(ImageError::Unsupported),
+        };
+        if data.len() != size || crc32fast::hash(&data) != crc {
+            return Err(malformed("checksum mismatch"));
+        }
+        return Ok(data);
+    }
+    Err(malformed(&format!("no {} in archive", name)))
+}
+
+/// Returns uncompressed zip archive of files named `entries`.
+fn zip(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (name, data) in entries {
+        let offset = archive.len() as u32;
+        // Version 2.0, no flags, stored, 1980-01-01 00:00.
+        let mut header = Vec::new();
+        header.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);
+        header.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&[0, 0]);
+
+        archive.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(data);
+
+        // Made by version 2.0, no comment, disk and attributes.
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&[20, 0]);
+        directory.extend_from_slice(&header);
+        directory.extend_from_slice(&[0; 10]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    archive.extend_from_slice(&directory);
+    archive.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    archive.extend_from_slice(&[0; 4]);
+    archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&directory_offset.to_le_bytes());
+    archive.extend_from_slice(&[0, 0]);
+    archive
+}
+
+/// Returns start tags of elements `name` in `xml`, e.g. `<layer src="a.png"/>`.
+fn tags<'a>(xml: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> {
+    xml.match_indices('<').filter_map(move |(start, _)| {
+        let rest = &xml[start + 1..];
+        let after = rest.strip_prefix(name)?;
+        if !after.starts_with(|c: char| c.is_whitespace() || c == '/' || c == '>') {
+            return None;
+        }
+        let end = rest.find('>')?;
+        Some(&rest[name.len()..end])
+    })
+}
+
+/// Returns unescaped value of attribute `name` of start `tag`.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    loop {
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim();
+        let value = rest[eq + 1..].trim_start();
+        let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let end = value[1..].find(quote)? + 1;
+        if key == name {
+            return Some(unescape(&value[1..end]));
+        }
+        rest = &value[end + 1..];
+    }
+}
+
+/// Replaces predefined XML entities of `text`.
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Records `message` about malformed OpenRaster file as the last error.
+fn malformed(message: &str) -> ImageError {
+    let message = format!("Malformed OpenRaster file: {}", message);
+    crate::set_last_error(LastError::new(ImageError::Decoding, message));
+    ImageError::Decoding
+}
@@ -0,0 +1,135 @@
+use crate::ImageError;
+use image::{DynamicImage, ImageBuffer, Luma, Pixel, Rgb};
+
+/// Colors of 7-color ACeP e-paper panels in order of their palette indices.
+const ACEP_PALETTE: [[u8; 3]; 7] = [
+    [0, 0, 0],       // black
+    [255, 255, 255], // white
+    [0, 255, 0],     // green
+    [0, 0, 255],     // blue
+    [255, 0, 0],     // red
+    [255, 255, 0],   // yellow
+    [255, 128, 0],   // orange
+];
+
+/// Packs `image` into 1 bit per pixel rows, most significant bit first. Set bit is white.
+/// Each row is padded to whole byte.
+pub fn pack_mono(image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+    let gray = image.to_luma8();
+    pack_rows(&gray, 1, |Luma([l])| (*l >= 128) as u8)
+}
+
+/// Packs `image` into 4 bits per pixel gray rows, high nibble first. `0` is black, `15` is white.
+/// Each row is padded to whole byte.
+pub fn pack_gray4(image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+    let gray = image.to_luma8();
+    pack_rows(&gray, 4, |Luma([l])| l >> 4)
+}
+
+/// Packs `image` into 4 bits per pixel rows of ACeP palette indices, high nibble first.
+/// Each pixel is mapped to the nearest panel color. Each row is padded to whole byte.
+pub fn pack_acep(image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+    let rgb = image.to_rgb8();
+    pack_rows(&rgb, 4, |Rgb(color)| nearest_acep_color(*color))
+}
+
+/// Packs `image` into rows of 16-bit little-endian RGB565 pixels.
+pub fn pack_rgb565(image: &DynamicImage, bottom_up: bool) -> Result<Vec<u8>, ImageError> {
+    pack_rgb(image, bottom_up, 2, |[r, g, b], out| {
+        let value = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+        out.copy_from_slice(&value.to_le_bytes());
+    })
+}
+
+/// Packs `image` into rows of 8-bit RGB332 pixels.
+pub fn pack_rgb332(image: &DynamicImage, bottom_up: bool) -> Result<Vec<u8>, ImageError> {
+    pack_rgb(image, bottom_up, 1, |[r, g, b], out| {
+        out[0] = (r >> 5) << 5 | (g >> 5) << 2 | b >> 6;
+    })
+}
+
+/// Packs `image` into rows of 24-bit BGR888 pixels.
+pub fn pack_bgr888(image: &DynamicImage, bottom_up: bool) -> Result<Vec<u8>, ImageError> {
+    pack_rgb(image, bottom_up, 3, |[r, g, b], out| {
+        out.copy_from_slice(&[b, g, r]);
+    })
+}
+
+/// Packs RGB pixels of `image` into `pixel_size`-byte values written by `write` in top-down or
+/// bottom-up row order. Rows aren't padded.
+fn pack_rgb<F>(
+    image: &DynamicImage,
+    bottom_up: bool,
+    pixel_size: usize,
+    write: F,
+) -> Result<Vec<u8>, ImageError>
+where
+    F: Fn([u8; 3], &mut [u8]),
+{
+    let rgb = image.to_rgb8();
+    let row_len = rgb.width() as usize * pixel_size;
+    let mut packed = alloc(row_len, rgb.height())?;
+
+    for (y, row) in rgb.rows().enumerate() {
+        let y = match bottom_up {
+            true => rgb.height() as usize - 1 - y,
+            false => y,
+        };
+
+        let out_row = &mut packed[y * row_len..(y + 1) * row_len];
+        for (pixel, out) in row.zip(out_row.chunks_exact_mut(pixel_size)) {
+            write(pixel.0, out);
+        }
+    }
+    Ok(packed)
+}
+
+/// Returns index of ACeP palette color nearest to `color`.
+fn nearest_acep_color(color: [u8; 3]) -> u8 {
+    let distance = |palette_color: &[u8; 3]| -> u32 {
+        color
+            .iter()
+            .zip(palette_color)
+            .map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32)
+            .sum()
+    };
+
+    let nearest = ACEP_PALETTE
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, palette_color)| distance(palette_color));
+    nearest.map(|(i, _)| i as u8).unwrap_or(0)
+}
+
+/// Packs pixels of `image` mapped by `value` into rows of `bits`-wide values, most significant
+/// bits first. Each row is padded to whole byte.
+fn pack_rows<P, F>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    bits: u32,
+    value: F,
+) -> Result<Vec<u8>, ImageError>
+where
+    P: Pixel + 'static,
+    F: Fn(&P) -> u8,
+{
+    let per_byte = 8 / bits;
+    let row_len = image.width().div_ceil(per_byte);
+    let mut packed = alloc(row_len as usize, image.height())?;
+
+    for (y, row) in packed.chunks_exact_mut(row_len as usize).enumerate() {
+        for x in 0..image.width() {
+            let pixel = image.get_pixel(x, y as u32);
+            let shift = 8 - bits * (x % per_byte + 1);
+            row[(x / per_byte) as usize] |= value(pixel) << shift;
+        }
+    }
+    Ok(packed)
+}
+
+/// Allocates zeroed `height` rows of `row_len` bytes by `try_alloc`.
+fn alloc(row_len: usize, height: u32) -> Result<Vec<u8>, ImageError> {
+    let len = row_len
+        .checked_mul(height as usize)
+        .ok_or(ImageError::LimitExceeded)?;
+    crate::try_alloc(len, 0)
+}
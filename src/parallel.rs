@@ -0,0 +1,451 @@
+use crate::pool::{self, Pooled};
+use crate::{progress, simd, ImageError};
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive};
+use num_traits::NumCast;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+
+/// Requested number of worker threads, `0` means number of CPUs.
+static THREAD_COUNT: Mutex<usize> = Mutex::new(0);
+
+#[cfg(feature = "parallel")]
+type Pool = std::sync::Arc<rayon::ThreadPool>;
+
+/// Worker threads with their count, created on first parallel operation.
+#[cfg(feature = "parallel")]
+static POOL: Mutex<Option<(usize, Pool)>> = Mutex::new(None);
+
+/// Task run by `spawn`.
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Sender of tasks to single thread running them in order when worker threads of parallel
+/// operations aren't available, started by the first such `spawn`.
+static TASKS: Mutex<Option<mpsc::Sender<Task>>> = Mutex::new(None);
+
+/// Sets maximum number of worker threads of parallel operations, `0` means number of CPUs and
+/// `1` runs operations on calling thread. Running operations keep their threads.
+pub fn set_thread_count(count: usize) {
+    *THREAD_COUNT.lock().unwrap_or_else(|e| e.into_inner()) = count;
+}
+
+/// Stops worker threads once running operations finish, e.g. before host unloads library.
+/// Parallel operations start them again.
+pub fn shutdown() {
+    #[cfg(feature = "parallel")]
+    {
+        *POOL.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+    *TASKS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Runs `task` in background on worker threads of parallel operations, so at most as many tasks
+/// as set by `set_thread_count` run at once and the others wait in queue. If worker threads
+/// aren't available, e.g. with thread count `1`, tasks run one by one on single thread. Fails if
+/// platform can't spawn threads.
+pub fn spawn(task: Task) -> std::io::Result<()> {
+    #[cfg(feature = "parallel")]
+    if let Some(pool) = pool() {
+        pool.spawn(task);
+        return Ok(());
+    }
+    let mut tasks = TASKS.lock().unwrap_or_else(|e| e.into_inner());
+    let task = match &*tasks {
+        Some(sender) => match sender.send(task) {
+            Ok(()) => return Ok(()),
+            Err(mpsc::SendError(task)) => task,
+        },
+        None => task,
+    };
+    let (sender, receiver) = mpsc::channel::<Task>();
+    std::thread::Builder::new()
+        .name("image_sl-job".into())
+        .spawn(move || receiver.into_iter().for_each(|task| task()))?;
+    sender.send(task).expect("thread receives tasks");
+    *tasks = Some(sender);
+    Ok(())
+}
+
+/// Returns worker threads, or `None` if operations run on calling thread, e.g. if platform
+/// can't spawn threads.
+#[cfg(feature = "parallel")]
+fn pool() -> Option<Pool> {
+    let count = *THREAD_COUNT.lock().unwrap_or_else(|e| e.into_inner());
+    // Threads can't be spawned on `wasm32-unknown-unknown`.
+    if count == 1 || cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        return None;
+    }
+    let mut pool = POOL.lock().unwrap_or_else(|e| e.into_inner());
+    match &*pool {
+        Some((c, p)) if *c == count => return Some(p.clone()),
+        _ => {}
+    }
+    let builder = rayon::ThreadPoolBuilder::new()
+        .num_threads(count)
+        .thread_name(|i| format!("image_sl-{}", i));
+    match builder.build() {
+        Ok(p) => {
+            let p = std::sync::Arc::new(p);
+            *pool = Some((count, p.clone()));
+            Some(p)
+        }
+        Err(e) => {
+            crate::log::warn(&format!("Failed to start worker threads: {}", e));
+            None
+        }
+    }
+}
+
+/// Calls `f` with index and content of every `row_len` long row of `buffer`, in parallel if
+/// worker threads are available. Finished rows are reported as progress of current step; once
+/// operation is aborted, the remaining rows are skipped.
+pub fn for_each_row<T, F>(buffer: &mut [T], row_len: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Send + Sync,
+{
+    if row_len == 0 {
+        return;
+    }
+    let progress = progress::current();
+    let rows = buffer.len() / row_len;
+    let done = AtomicUsize::new(0);
+    let f = |y: usize, row: &mut [T]| match &progress {
+        Some(p) if p.is_aborted() => {}
+        Some(p) => {
+            f(y, row);
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            p.report(done as f32 / rows as f32);
+        }
+        None => f(y, row),
+    };
+    #[cfg(feature = "parallel")]
+    if let Some(pool) = pool() {
+        use rayon::prelude::*;
+        return pool.install(|| {
+            let rows = buffer.par_chunks_mut(row_len).enumerate();
+            rows.for_each(|(y, row)| f(y, row))
+        });
+    }
+    for (y, row) in buffer.chunks_mut(row_len).enumerate() {
+        f(y, row);
+    }
+}
+
+/// Calls `f` with index and every item of `items`, in parallel if worker threads are available.
+/// Finished items are reported as progress of current step, as `for_each_row` does with rows.
+pub fn for_each<T, F>(items: &mut [T], f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut T) + Send + Sync,
+{
+    for_each_row(items, 1, |i, item| f(i, &mut item[0]))
+}
+
+/// Samples contributing to one output pixel along axis: index of the first one and their
+/// normalized weights.
+pub struct Window {
+    pub start: usize,
+    pub weights: Vec<f32>,
+}
+
+/// Returns windows of samples of `len` input pixels contributing to each of `new_len` output
+/// pixels for `kernel` spanning `support` input pixels around pixel center at scale 1, as
+/// `image::imageops` sampling does.
+pub fn windows(len: u32, new_len: u32, support: f32, kernel: &dyn Fn(f32) -> f32) -> Vec<Window> {
+    let ratio = len as f32 / new_len as f32;
+    let scale = ratio.max(1.0);
+    let support = support * scale;
+    (0..new_len)
+        .map(|out| {
+            let center = (out as f32 + 0.5) * ratio;
+            let left = ((center - support).floor() as i64).clamp(0, len as i64 - 1);
+            let right = ((center + support).ceil() as i64).clamp(left + 1, len as i64);
+            let weights: Vec<f32> = (left..right)
+                .map(|i| kernel((i as f32 - (center - 0.5)) / scale))
+                .collect();
+            let sum: f32 = weights.iter().sum();
+            Window {
+                start: left as usize,
+                weights: weights.into_iter().map(|w| w / sum).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Rectangle of image read by operation, so image doesn't need to be cropped before it.
+pub struct View<'a, S> {
+    /// Subpixels of whole image.
+    pub data: &'a [S],
+    /// Length of image row in subpixels.
+    pub stride: usize,
+    pub channels: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<'a, S: Primitive + 'static> View<'a, S> {
+    /// Returns view of whole `image`.
+    pub fn of<P: Pixel<Subpixel = S> + 'static>(image: &'a ImageBuffer<P, Vec<S>>) -> Self {
+        let channels = P::CHANNEL_COUNT as usize;
+        Self {
+            data: image.as_raw(),
+            stride: image.width() as usize * channels,
+            channels,
+            x: 0,
+            y: 0,
+            width: image.width(),
+            height: image.height(),
+        }
+    }
+
+    /// Returns subpixels of row `y` of view.
+    pub fn row(&self, y: u32) -> &'a [S] {
+        let start = (self.y + y) as usize * self.stride + self.x as usize * self.channels;
+        &self.data[start..start + self.width as usize * self.channels]
+    }
+}
+
+/// Resamples `view` to `new_width` x `new_height` by separable `kernel`, vertically into
+/// `vertical` and then horizontally into `output`, processing rows in parallel. Buffers are
+/// resized keeping their allocations, so callers reuse them.
+fn resample_into<S>(
+    view: &View<S>,
+    (new_width, new_height): (u32, u32),
+    support: f32,
+    kernel: &dyn Fn(f32) -> f32,
+    vertical: &mut Vec<S>,
+    output: &mut Vec<S>,
+) where
+    S: Primitive + Pooled + Sync + 'static,
+{
+    let (width, height) = (view.width, view.height);
+    let channels = view.channels;
+    let new_row_len = new_width as usize * channels;
+    output.clear();
+    output.resize(new_row_len * new_height as usize, S::min_value());
+    if width == 0 || height == 0 || new_width == 0 || new_height == 0 {
+        return;
+    }
+    let max: f32 = NumCast::from(S::max_value()).unwrap_or(f32::MAX);
+    let to_subpixel = |value: f32| -> S {
+        NumCast::from(value.clamp(0.0, max).round()).unwrap_or_else(S::max_value)
+    };
+
+    // Intermediate image has the same subpixels as source, as in `image::imageops`.
+    let columns = windows(height, new_height, support, kernel);
+    let row_len = width as usize * channels;
+    vertical.clear();
+    vertical.resize(row_len * new_height as usize, S::min_value());
+    let first_pass = |y: usize, out: &mut [S]| {
+        let window = &columns[y];
+        let mut sums = pool::take::<f32>(row_len);
+        sums.resize(row_len, 0.0);
+        for (i, w) in window.weights.iter().enumerate() {
+            let row = view.row((window.start + i) as u32);
+            for (sum, v) in sums.iter_mut().zip(row) {
+                *sum += w * NumCast::from(*v).unwrap_or(0.0);
+            }
+        }
+        for (out, sum) in out.iter_mut().zip(sums.iter()) {
+            *out = to_subpixel(*sum);
+        }
+    };
+    progress::step(0.0, 0.5, || for_each_row(vertical, row_len, first_pass));
+
+    let rows = windows(width, new_width, support, kernel);
+    let vertical = &*vertical;
+    let second_pass = |y: usize, out: &mut [S]| {
+        let row = &vertical[y * row_len..(y + 1) * row_len];
+        for (x, window) in rows.iter().enumerate() {
+            for c in 0..channels {
+                let mut sum = 0.0;
+                for (i, w) in window.weights.iter().enumerate() {
+                    let v: f32 =
+                        NumCast::from(row[(window.start + i) * channels + c]).unwrap_or(0.0);
+                    sum += w * v;
+                }
+                out[x * channels + c] = to_subpixel(sum);
+            }
+        }
+    };
+    progress::step(0.5, 0.5, || for_each_row(output, new_row_len, second_pass));
+}
+
+/// Resamples `image` to `new_width` x `new_height` by separable `kernel` into new image.
+fn resample<P, S>(
+    image: &ImageBuffer<P, Vec<S>>,
+    new_width: u32,
+    new_height: u32,
+    support: f32,
+    kernel: &dyn Fn(f32) -> f32,
+) -> ImageBuffer<P, Vec<S>>
+where
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + Pooled + Sync + 'static,
+{
+    let view = View::of(image);
+    let mut vertical = vertical_buffer(&view, new_height);
+    let mut output = Vec::new();
+    let size = (new_width, new_height);
+    resample_into(&view, size, support, kernel, &mut vertical, &mut output);
+    ImageBuffer::from_raw(new_width, new_height, output).expect("buffer fits image")
+}
+
+/// Returns buffer of intermediate rows of resampling `view` to `new_height` rows from pool.
+fn vertical_buffer<S: Pooled>(view: &View<S>, new_height: u32) -> pool::Scratch<S> {
+    pool::take(view.width as usize * view.channels * new_height as usize)
+}
+
+/// Returns Gaussian kernel with standard deviation `sigma` and its support, as
+/// `image::imageops::blur` samples it.
+pub fn gaussian(sigma: f32) -> (f32, impl Fn(f32) -> f32) {
+    let sigma = if sigma <= 0.0 { 1.0 } else { sigma };
+    let kernel =
+        move |x: f32| ((2.0 * PI).sqrt() * sigma).recip() * (-x * x / (2.0 * sigma * sigma)).exp();
+    (2.0 * sigma, kernel)
+}
+
+/// Lanczos kernel with window of 3.
+pub fn lanczos3(x: f32) -> f32 {
+    let sinc = |x: f32| match x == 0.0 {
+        true => 1.0,
+        false => (PI * x).sin() / (PI * x),
+    };
+    match x.abs() < 3.0 {
+        true => sinc(x) * sinc(x / 3.0),
+        false => 0.0,
+    }
+}
+
+/// Gaussian blur with standard deviation `sigma`, matching `image::imageops::blur`. Rows are
+/// processed in parallel.
+pub fn blur<P, S>(image: &ImageBuffer<P, Vec<S>>, sigma: f32) -> ImageBuffer<P, Vec<S>>
+where
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + Pooled + Sync + 'static,
+{
+    let (support, kernel) = gaussian(sigma);
+    let (width, height) = image.dimensions();
+    resample(image, width, height, support, &kernel)
+}
+
+/// Blurs `view` as `blur` does into `output` of view size, using `vertical` for intermediate
+/// rows. Buffers keep their allocations.
+pub fn blur_into<S>(view: &View<S>, sigma: f32, vertical: &mut Vec<S>, output: &mut Vec<S>)
+where
+    S: Primitive + Pooled + Sync + 'static,
+{
+    let (support, kernel) = gaussian(sigma);
+    let size = (view.width, view.height);
+    resample_into(view, size, support, &kernel, vertical, output)
+}
+
+/// Resizes `image` to `width` x `height` by Lanczos filter with window of 3, matching
+/// `image::imageops::resize` with `FilterType::Lanczos3`. Rows are processed in parallel.
+pub fn resize<P, S>(
+    image: &ImageBuffer<P, Vec<S>>,
+    width: u32,
+    height: u32,
+) -> ImageBuffer<P, Vec<S>>
+where
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + Pooled + Sync + 'static,
+{
+    resample(image, width, height, 3.0, &lanczos3)
+}
+
+/// Resizes `view` to `width` x `height` as `resize` does into `output`, using `vertical` for
+/// intermediate rows. Buffers keep their allocations.
+pub fn resize_into<S>(
+    view: &View<S>,
+    (width, height): (u32, u32),
+    vertical: &mut Vec<S>,
+    output: &mut Vec<S>,
+) where
+    S: Primitive + Pooled + Sync + 'static,
+{
+    resample_into(view, (width, height), 3.0, &lanczos3, vertical, output)
+}
+
+/// Blurs `image` as `image::imageops::blur` does, giving RGBA image.
+pub fn blur_dynamic(image: &DynamicImage, sigma: f32) -> Result<image::RgbaImage, ImageError> {
+    match image {
+        DynamicImage::ImageRgba8(buffer) => Ok(blur(buffer, sigma)),
+        image => Ok(blur(&simd::to_rgba8(image)?, sigma)),
+    }
+}
+
+/// Blurs `image` as `blur_dynamic` does into `output` of its size, reusing buffer of `output`.
+pub fn blur_dynamic_into(
+    image: &DynamicImage,
+    sigma: f32,
+    output: &mut image::RgbaImage,
+) -> Result<(), ImageError> {
+    match image {
+        DynamicImage::ImageRgba8(buffer) => blur_buffer_into(buffer, sigma, output),
+        image => blur_buffer_into(&simd::to_rgba8(image)?, sigma, output),
+    }
+    Ok(())
+}
+
+fn blur_buffer_into(image: &image::RgbaImage, sigma: f32, output: &mut image::RgbaImage) {
+    let (width, height) = output.dimensions();
+    let mut data = std::mem::replace(output, ImageBuffer::new(0, 0)).into_raw();
+    let view = View::of(image);
+    let mut vertical = vertical_buffer(&view, height);
+    blur_into(&view, sigma, &mut vertical, &mut data);
+    *output = ImageBuffer::from_raw(width, height, data).expect("buffer fits image");
+}
+
+/// Resizes `image` to `width` x `height` by Lanczos filter keeping its color type, as
+/// `DynamicImage::resize_exact` does.
+pub fn resize_dynamic(image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    match image {
+        DynamicImage::ImageLuma8(b) => DynamicImage::ImageLuma8(resize(b, width, height)),
+        DynamicImage::ImageLumaA8(b) => DynamicImage::ImageLumaA8(resize(b, width, height)),
+        DynamicImage::ImageRgb8(b) => DynamicImage::ImageRgb8(resize(b, width, height)),
+        DynamicImage::ImageRgba8(b) => DynamicImage::ImageRgba8(resize(b, width, height)),
+        DynamicImage::ImageBgr8(b) => DynamicImage::ImageBgr8(resize(b, width, height)),
+        DynamicImage::ImageBgra8(b) => DynamicImage::ImageBgra8(resize(b, width, height)),
+        DynamicImage::ImageLuma16(b) => DynamicImage::ImageLuma16(resize(b, width, height)),
+        DynamicImage::ImageLumaA16(b) => DynamicImage::ImageLumaA16(resize(b, width, height)),
+        DynamicImage::ImageRgb16(b) => DynamicImage::ImageRgb16(resize(b, width, height)),
+        DynamicImage::ImageRgba16(b) => DynamicImage::ImageRgba16(resize(b, width, height)),
+    }
+}
+
+/// Resizes `image` as `resize_dynamic` does into `output`, to size of `output` and reusing its
+/// buffer.
+/// # Panics
+/// Panics if images have different color types.
+pub fn resize_dynamic_into(image: &DynamicImage, output: &mut DynamicImage) {
+    match (image, output) {
+        (DynamicImage::ImageLuma8(b), DynamicImage::ImageLuma8(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageLumaA8(b), DynamicImage::ImageLumaA8(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageRgb8(b), DynamicImage::ImageRgb8(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageRgba8(b), DynamicImage::ImageRgba8(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageBgr8(b), DynamicImage::ImageBgr8(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageBgra8(b), DynamicImage::ImageBgra8(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageLuma16(b), DynamicImage::ImageLuma16(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageLumaA16(b), DynamicImage::ImageLumaA16(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageRgb16(b), DynamicImage::ImageRgb16(o)) => resize_buffer_into(b, o),
+        (DynamicImage::ImageRgba16(b), DynamicImage::ImageRgba16(o)) => resize_buffer_into(b, o),
+        _ => panic!("Resized images have different color types"),
+    }
+}
+
+fn resize_buffer_into<P, S>(image: &ImageBuffer<P, Vec<S>>, output: &mut ImageBuffer<P, Vec<S>>)
+where
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + Pooled + Sync + 'static,
+{
+    let size = output.dimensions();
+    let mut data = std::mem::replace(output, ImageBuffer::new(0, 0)).into_raw();
+    let view = View::of(image);
+    let mut vertical = vertical_buffer(&view, size.1);
+    resize_into(&view, size, &mut vertical, &mut data);
+    *output = ImageBuffer::from_raw(size.0, size.1, data).expect("buffer fits image");
+}
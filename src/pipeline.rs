@@ -0,0 +1,325 @@
+use crate::parallel::{self, View};
+use crate::{simd, ImageData, ImageError, LastError, Rect};
+use image::{ColorType, DynamicImage, GenericImageView, ImageBuffer, Pixel, Primitive};
+use serde_json::{json, Value};
+
+/// Operation of pipeline, the same as function applying it to image.
+#[derive(Copy, Clone)]
+pub enum Step {
+    /// Keeps rectangle of image.
+    Crop(Rect),
+    /// Resizes image by Lanczos filter keeping its color type, as `img_resize_with_progress`
+    /// does.
+    Resize(u32, u32),
+    /// Gaussian blur with standard deviation giving RGBA image, as `img_blur2` does.
+    Blur(f32),
+    /// Flips image horizontally.
+    Mirror,
+}
+
+impl Step {
+    /// Returns description of step in edit stack, e.g. `{"op": "blur", "sigma": 2.5}`.
+    fn to_json(self) -> Value {
+        match self {
+            Step::Crop(r) => json!({
+                "op": "crop", "x": r.x, "y": r.y, "width": r.width, "height": r.height
+            }),
+            Step::Resize(width, height) => {
+                json!({"op": "resize", "width": width, "height": height})
+            }
+            Step::Blur(sigma) => json!({"op": "blur", "sigma": sigma}),
+            Step::Mirror => json!({"op": "mirror"}),
+        }
+    }
+}
+
+/// Buffers of one subpixel type kept between executions of pipeline.
+#[derive(Default)]
+struct Spare<S> {
+    /// The other of two images steps write to in turn.
+    image: Vec<S>,
+    /// Intermediate rows of resampling.
+    vertical: Vec<S>,
+}
+
+#[derive(Default)]
+struct Buffers {
+    u8: Spare<u8>,
+    u16: Spare<u16>,
+}
+
+/// Subpixel of image pipeline keeps buffers for.
+trait Subpixel: Primitive + crate::pool::Pooled + Sync {
+    fn spare(buffers: &mut Buffers) -> &mut Spare<Self>;
+}
+
+impl Subpixel for u8 {
+    fn spare(buffers: &mut Buffers) -> &mut Spare<Self> {
+        &mut buffers.u8
+    }
+}
+
+impl Subpixel for u16 {
+    fn spare(buffers: &mut Buffers) -> &mut Spare<Self> {
+        &mut buffers.u16
+    }
+}
+
+/// Sequence of operations applied to image at once. Crops are fused into the next step reading
+/// rectangle of image instead of copying it, mirroring is done in place, and the other steps
+/// write into buffers of pipeline reused by the next steps and executions.
+#[derive(Default)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+    buffers: Buffers,
+}
+
+impl Pipeline {
+    pub fn new(steps: Vec<Step>) -> Self {
+        Self {
+            steps,
+            buffers: Buffers::default(),
+        }
+    }
+
+    pub fn push(&mut self, step: Step) {
+        self.steps.push(step);
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Returns description of pipeline in edit stack, e.g.
+    /// `{"op": "pipeline", "steps": [{"op": "mirror"}]}`.
+    pub fn to_json(&self) -> Value {
+        let steps: Vec<_> = self.steps.iter().map(|s| s.to_json()).collect();
+        json!({"op": "pipeline", "steps": steps})
+    }
+
+    /// Applies steps to image of `data` in order, recording pipeline in its journal. Steps are
+    /// checked before image is changed, so failed pipeline leaves it as is.
+    pub fn apply(&mut self, data: &mut ImageData) -> Result<(), ImageError> {
+        let (size, color) = (data.image.dimensions(), data.image.color());
+        self.check(size, color, true)?;
+        // Buffer RGB image is expanded into at the first blur is allocated before steps run.
+        let blur = self.steps.iter().position(|s| matches!(s, Step::Blur(_)));
+        let rgba = match (color, blur) {
+            (ColorType::Rgb8, Some(blur)) => {
+                let before = Pipeline::new(self.steps[..blur].to_vec());
+                let (width, height) = before.check(size, color, false)?.0;
+                crate::try_alloc(width as usize * height as usize * 4, 0)?
+            }
+            _ => Vec::new(),
+        };
+        data.checkpoint(self.to_json());
+        let image = std::mem::replace(&mut *data.image, DynamicImage::new_rgba8(0, 0));
+        data.image = self.execute(image, rgba).into();
+        Ok(())
+    }
+
+    /// Checks that every step applies to image of `width` x `height` with `color` and, if
+    /// `allocated`, that images it gives can be allocated. Returns size and color of result.
+    pub fn check(
+        &self,
+        (mut width, mut height): (u32, u32),
+        mut color: ColorType,
+        allocated: bool,
+    ) -> Result<((u32, u32), ColorType), ImageError> {
+        for (i, step) in self.steps.iter().enumerate() {
+            match *step {
+                Step::Crop(r) => {
+                    let fits = |start: u32, size: u32, max: u32| {
+                        size != 0 && start.checked_add(size).is_some_and(|end| end <= max)
+                    };
+                    if !fits(r.x, r.width, width) || !fits(r.y, r.height, height) {
+                        let message = format!(
+                            "Crop {}x{} at {}, {} of pipeline step {} doesn't fit {}x{} image",
+                            r.width, r.height, r.x, r.y, i, width, height
+                        );
+                        crate::set_last_error(LastError::new(ImageError::Parameter, message));
+                        return Err(ImageError::Parameter);
+                    }
+                    width = r.width;
+                    height = r.height;
+                }
+                Step::Resize(new_width, new_height) => {
+                    if new_width == 0 || new_height == 0 {
+                        let message = format!("Resize of pipeline step {} to empty image", i);
+                        crate::set_last_error(LastError::new(ImageError::Parameter, message));
+                        return Err(ImageError::Parameter);
+                    }
+                    // Resizing allocates intermediate image of target width and source height.
+                    let rows = new_height.max(height);
+                    if allocated {
+                        crate::check_alloc(crate::image_bytes(new_width, rows, color))?;
+                    }
+                    width = new_width;
+                    height = new_height;
+                }
+                Step::Blur(sigma) => {
+                    if !(sigma > 0.0 && sigma.is_finite()) {
+                        let message = format!("Invalid sigma {} of pipeline step {}", sigma, i);
+                        crate::set_last_error(LastError::new(ImageError::Parameter, message));
+                        return Err(ImageError::Parameter);
+                    }
+                    color = ColorType::Rgba8;
+                    if allocated {
+                        crate::check_alloc(crate::image_bytes(width, height, color))?;
+                    }
+                }
+                Step::Mirror => {}
+            }
+        }
+        Ok(((width, height), color))
+    }
+
+    /// Applies steps checked by `check` to `image`. Image keeps its color type until the first
+    /// blur, which converts it to RGBA expanding RGB pixels into `rgba`.
+    fn execute(&mut self, image: DynamicImage, rgba: Vec<u8>) -> DynamicImage {
+        let steps = std::mem::take(&mut self.steps);
+        let blur = steps.iter().position(|s| matches!(s, Step::Blur(_)));
+        let image = match (image, blur) {
+            (DynamicImage::ImageRgba8(b), _) => DynamicImage::ImageRgba8(self.run(b, &steps)),
+            (image, Some(blur)) => {
+                let image = self.run_dynamic(image, &steps[..blur]);
+                let image = simd::to_rgba8_in(&image, rgba);
+                DynamicImage::ImageRgba8(self.run(image, &steps[blur..]))
+            }
+            (image, None) => self.run_dynamic(image, &steps),
+        };
+        self.steps = steps;
+        image
+    }
+
+    /// Applies `steps` to `image` keeping its color type.
+    fn run_dynamic(&mut self, image: DynamicImage, steps: &[Step]) -> DynamicImage {
+        match image {
+            DynamicImage::ImageLuma8(b) => DynamicImage::ImageLuma8(self.run(b, steps)),
+            DynamicImage::ImageLumaA8(b) => DynamicImage::ImageLumaA8(self.run(b, steps)),
+            DynamicImage::ImageRgb8(b) => DynamicImage::ImageRgb8(self.run(b, steps)),
+            DynamicImage::ImageRgba8(b) => DynamicImage::ImageRgba8(self.run(b, steps)),
+            DynamicImage::ImageBgr8(b) => DynamicImage::ImageBgr8(self.run(b, steps)),
+            DynamicImage::ImageBgra8(b) => DynamicImage::ImageBgra8(self.run(b, steps)),
+            DynamicImage::ImageLuma16(b) => DynamicImage::ImageLuma16(self.run(b, steps)),
+            DynamicImage::ImageLumaA16(b) => DynamicImage::ImageLumaA16(self.run(b, steps)),
+            DynamicImage::ImageRgb16(b) => DynamicImage::ImageRgb16(self.run(b, steps)),
+            DynamicImage::ImageRgba16(b) => DynamicImage::ImageRgba16(self.run(b, steps)),
+        }
+    }
+
+    /// Applies `steps` to `image`, swapping its buffer with spare one of pipeline after every
+    /// step writing new image.
+    fn run<P, S>(&mut self, image: ImageBuffer<P, Vec<S>>, steps: &[Step]) -> ImageBuffer<P, Vec<S>>
+    where
+        P: Pixel<Subpixel = S> + 'static,
+        S: Subpixel,
+    {
+        let channels = P::CHANNEL_COUNT as usize;
+        let (mut width, mut height) = image.dimensions();
+        let mut data = image.into_raw();
+        let spare = S::spare(&mut self.buffers);
+        // Part of image the next step reads.
+        let mut region = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+
+        for step in steps {
+            let view = View {
+                data: &data,
+                stride: width as usize * channels,
+                channels,
+                x: region.x,
+                y: region.y,
+                width: region.width,
+                height: region.height,
+            };
+            match *step {
+                Step::Crop(r) => {
+                    region.x += r.x;
+                    region.y += r.y;
+                    region.width = r.width;
+                    region.height = r.height;
+                    continue;
+                }
+                Step::Resize(new_width, new_height) => {
+                    let size = (new_width, new_height);
+                    parallel::resize_into(&view, size, &mut spare.vertical, &mut spare.image);
+                    width = new_width;
+                    height = new_height;
+                }
+                Step::Blur(sigma) => {
+                    parallel::blur_into(&view, sigma, &mut spare.vertical, &mut spare.image);
+                    width = region.width;
+                    height = region.height;
+                }
+                Step::Mirror => {
+                    if (region.width, region.height) != (width, height) {
+                        copy_view(&view, &mut spare.image);
+                        std::mem::swap(&mut data, &mut spare.image);
+                        width = region.width;
+                        height = region.height;
+                    }
+                    mirror(&mut data, width as usize, channels);
+                    region = Rect {
+                        x: 0,
+                        y: 0,
+                        width,
+                        height,
+                    };
+                    continue;
+                }
+            }
+            std::mem::swap(&mut data, &mut spare.image);
+            region = Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            };
+        }
+
+        if (region.width, region.height) != (width, height) {
+            let view = View {
+                data: &data,
+                stride: width as usize * channels,
+                channels,
+                x: region.x,
+                y: region.y,
+                width: region.width,
+                height: region.height,
+            };
+            copy_view(&view, &mut spare.image);
+            std::mem::swap(&mut data, &mut spare.image);
+            width = region.width;
+            height = region.height;
+        }
+        ImageBuffer::from_raw(width, height, data).expect("buffer fits image")
+    }
+}
+
+/// Copies pixels of `view` into `output` keeping its allocation.
+fn copy_view<S: Primitive + 'static>(view: &View<S>, output: &mut Vec<S>) {
+    output.clear();
+    for y in 0..view.height {
+        output.extend_from_slice(view.row(y));
+    }
+}
+
+/// Flips image of `width` pixels with `channels` subpixels each horizontally in place.
+pub fn mirror<S>(data: &mut [S], width: usize, channels: usize) {
+    if width == 0 {
+        return;
+    }
+    for row in data.chunks_exact_mut(width * channels) {
+        for x in 0..width / 2 {
+            let mirrored = width - 1 - x;
+            for c in 0..channels {
+                row.swap(x * channels + c, mirrored * channels + c);
+            }
+        }
+    }
+}
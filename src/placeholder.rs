@@ -0,0 +1,327 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::f64::consts::PI;
+
+/// Images are scaled to fit this size before computing placeholders of, which keeps them fast
+/// and hardly changes them, as placeholders only keep the lowest frequencies.
+const MAX_SIZE: u32 = 64;
+
+/// Characters of base 83 encoding of BlurHash.
+const BASE83: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Returns BlurHash of `image` with `x_components` x `y_components` components of cosine
+/// transform, both in `[1, 9]`. Alpha is ignored.
+pub fn blurhash(image: &DynamicImage, x_components: u32, y_components: u32) -> String {
+    let rgba = thumbnail(image);
+    let (width, height) = rgba.dimensions();
+    let linear: Vec<[f64; 3]> = rgba
+        .pixels()
+        .map(|p| [p[0], p[1], p[2]].map(srgb_to_linear))
+        .collect();
+
+    let mut factors = Vec::with_capacity((x_components * y_components) as usize);
+    for j in 0..y_components {
+        for i in 0..x_components {
+            let normalization = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0; 3];
+            for (index, pixel) in linear.iter().enumerate() {
+                let (x, y) = (index as u32 % width, index as u32 / width);
+                let basis = (PI * (i * x) as f64 / width as f64).cos()
+                    * (PI * (j * y) as f64 / height as f64).cos();
+                for (f, v) in factor.iter_mut().zip(pixel) {
+                    *f += basis * v;
+                }
+            }
+            let scale = normalization / (width * height) as f64;
+            factors.push(factor.map(|f| f * scale));
+        }
+    }
+
+    let mut hash = String::new();
+    push_base83(&mut hash, (x_components - 1) + (y_components - 1) * 9, 1);
+    let (dc, ac) = factors.split_first().expect("at least one component");
+    let maximum = match ac.iter().flatten().map(|f| f.abs()).reduce(f64::max) {
+        Some(actual) => {
+            let quantized = (actual * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+            push_base83(&mut hash, quantized, 1);
+            (quantized + 1) as f64 / 166.0
+        }
+        None => {
+            push_base83(&mut hash, 0, 1);
+            1.0
+        }
+    };
+    let [r, g, b] = dc.map(linear_to_srgb);
+    push_base83(&mut hash, (r << 16) + (g << 8) + b, 4);
+    for factor in ac {
+        let [r, g, b] = factor.map(|f| {
+            let value = (f / maximum).abs().sqrt().copysign(f);
+            (value * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+        });
+        push_base83(&mut hash, r * 19 * 19 + g * 19 + b, 2);
+    }
+    hash
+}
+
+/// Appends `value` encoded in `digits` characters of base 83 to `hash`.
+fn push_base83(hash: &mut String, value: u32, digits: u32) {
+    for digit in (0..digits).rev() {
+        let index = value / 83u32.pow(digit) % 83;
+        hash.push(BASE83[index as usize] as char);
+    }
+}
+
+/// Returns [ThumbHash](https://evanw.github.io/thumbhash/) of `image`, which unlike BlurHash
+/// keeps alpha and approximate aspect ratio of image.
+pub fn thumbhash(image: &DynamicImage) -> Vec<u8> {
+    let rgba = thumbnail(image);
+    let (w, h) = rgba.dimensions();
+    let pixels: Vec<[f64; 4]> = rgba
+        .pixels()
+        .map(|p| p.0.map(|v| v as f64 / 255.0))
+        .collect();
+
+    // Transparent pixels are composited atop average color.
+    let mut average = [0.0; 4];
+    for &[r, g, b, a] in &pixels {
+        for (s, v) in average.iter_mut().zip([a * r, a * g, a * b, a]) {
+            *s += v;
+        }
+    }
+    if average[3] > 0.0 {
+        for c in 0..3 {
+            average[c] /= average[3];
+        }
+    }
+    let count = (w * h) as f64;
+    let has_alpha = average[3] < count;
+    let l_limit = if has_alpha { 5.0 } else { 7.0 };
+    let longer = w.max(h) as f64;
+    let lx = ((l_limit * w as f64 / longer).round() as usize).max(1);
+    let ly = ((l_limit * h as f64 / longer).round() as usize).max(1);
+
+    let mut channels = [vec![], vec![], vec![], vec![]];
+    for &[r, g, b, a] in &pixels {
+        let [r, g, b] = [0, 1, 2].map(|c| average[c] * (1.0 - a) + a * [r, g, b][c]);
+        channels[0].push((r + g + b) / 3.0);
+        channels[1].push((r + g) / 2.0 - b);
+        channels[2].push(r - g);
+        channels[3].push(a);
+    }
+    let [l, p, q, a] = channels;
+    let (w, h) = (w as usize, h as usize);
+    let (l_dc, l_ac, l_scale) = encode_channel(&l, w, h, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = encode_channel(&p, w, h, 3, 3);
+    let (q_dc, q_ac, q_scale) = encode_channel(&q, w, h, 3, 3);
+
+    let round = |v: f64| v.round() as u32;
+    let landscape = w > h;
+    let header24 = round(63.0 * l_dc)
+        | round(31.5 + 31.5 * p_dc) << 6
+        | round(31.5 + 31.5 * q_dc) << 12
+        | round(31.0 * l_scale) << 18
+        | (has_alpha as u32) << 23;
+    let header16 = (if landscape { ly } else { lx }) as u32
+        | round(63.0 * p_scale) << 3
+        | round(63.0 * q_scale) << 9
+        | (landscape as u32) << 15;
+    let mut hash = vec![
+        header24 as u8,
+        (header24 >> 8) as u8,
+        (header24 >> 16) as u8,
+        header16 as u8,
+        (header16 >> 8) as u8,
+    ];
+    let mut factors = vec![l_ac, p_ac, q_ac];
+    if has_alpha {
+        let (a_dc, a_ac, a_scale) = encode_channel(&a, w, h, 5, 5);
+        hash.push((round(15.0 * a_dc) | round(15.0 * a_scale) << 4) as u8);
+        factors.push(a_ac);
+    }
+    for (i, f) in factors.concat().into_iter().enumerate() {
+        if i % 2 == 0 {
+            hash.push(0);
+        }
+        *hash.last_mut().unwrap() |= (round(15.0 * f) << ((i & 1) * 4)) as u8;
+    }
+    hash
+}
+
+/// Returns DC, AC factors normalized to `[0, 1]` and their scale of cosine transform of `channel`
+/// of `w` x `h` image with `nx` x `ny` triangle of components.
+fn encode_channel(
+    channel: &[f64],
+    w: usize,
+    h: usize,
+    nx: usize,
+    ny: usize,
+) -> (f64, Vec<f64>, f64) {
+    let mut dc = 0.0;
+    let mut ac = Vec::new();
+    let mut scale = 0.0f64;
+    for cy in 0..ny {
+        let mut cx = 0;
+        while cx * ny < nx * (ny - cy) {
+            let fx: Vec<f64> = (0..w)
+                .map(|x| (PI / w as f64 * cx as f64 * (x as f64 + 0.5)).cos())
+                .collect();
+            let mut f = 0.0;
+            for (y, row) in channel.chunks_exact(w).enumerate() {
+                let fy = (PI / h as f64 * cy as f64 * (y as f64 + 0.5)).cos();
+                f += row.iter().zip(&fx).map(|(v, fx)| v * fx * fy).sum::<f64>();
+            }
+            f /= (w * h) as f64;
+            if cx > 0 || cy > 0 {
+                ac.push(f);
+                scale = scale.max(f.abs());
+            } else {
+                dc = f;
+            }
+            cx += 1;
+        }
+    }
+    if scale > 0.0 {
+        for f in &mut ac {
+            *f = 0.5 + 0.5 / scale * *f;
+        }
+    }
+    (dc, ac, scale)
+}
+
+/// Decodes ThumbHash `hash` into RGBA image of about 32 pixels on longer side with aspect ratio
+/// of hashed image. Returns `None` if `hash` is too short.
+pub fn decode_thumbhash(hash: &[u8]) -> Option<RgbaImage> {
+    let header = hash.get(..5)?;
+    let header24 = header[0] as u32 | (header[1] as u32) << 8 | (header[2] as u32) << 16;
+    let header16 = header[3] as u32 | (header[4] as u32) << 8;
+    let l_dc = (header24 & 63) as f64 / 63.0;
+    let p_dc = ((header24 >> 6) & 63) as f64 / 31.5 - 1.0;
+    let q_dc = ((header24 >> 12) & 63) as f64 / 31.5 - 1.0;
+    let l_scale = ((header24 >> 18) & 31) as f64 / 31.0;
+    let has_alpha = header24 >> 23 != 0;
+    let p_scale = ((header16 >> 3) & 63) as f64 / 63.0;
+    let q_scale = ((header16 >> 9) & 63) as f64 / 63.0;
+    let landscape = header16 >> 15 != 0;
+    let l_limit = if has_alpha { 5 } else { 7 };
+    let (lx, ly) = match landscape {
+        true => (l_limit, (header16 & 7) as usize),
+        false => ((header16 & 7) as usize, l_limit),
+    };
+    let (lx, ly) = (lx.max(3), ly.max(3));
+    let (a_dc, a_scale) = match has_alpha {
+        true => {
+            let a = *hash.get(5)?;
+            ((a & 15) as f64 / 15.0, (a >> 4) as f64 / 15.0)
+        }
+        false => (1.0, 0.0),
+    };
+
+    let mut nibbles = hash[if has_alpha { 6 } else { 5 }..]
+        .iter()
+        .flat_map(|&b| [b & 15, b >> 4]);
+    let mut decode_channel = |nx: usize, ny: usize, scale: f64| {
+        let mut ac = Vec::new();
+        for cy in 0..ny {
+            let mut cx = if cy > 0 { 0 } else { 1 };
+            while cx * ny < nx * (ny - cy) {
+                ac.push((nibbles.next()? as f64 / 7.5 - 1.0) * scale);
+                cx += 1;
+            }
+        }
+        Some(ac)
+    };
+    let l_ac = decode_channel(lx, ly, l_scale)?;
+    // Saturation is boosted by 1.25 to compensate for quantization.
+    let p_ac = decode_channel(3, 3, p_scale * 1.25)?;
+    let q_ac = decode_channel(3, 3, q_scale * 1.25)?;
+    let a_ac = match has_alpha {
+        true => decode_channel(5, 5, a_scale)?,
+        false => Vec::new(),
+    };
+
+    let ratio = thumbhash_aspect_ratio(hash);
+    let (w, h) = match ratio > 1.0 {
+        true => (32, (32.0 / ratio).round() as u32),
+        false => ((32.0 * ratio).round() as u32, 32),
+    };
+    let n = lx.max(ly).max(if has_alpha { 5 } else { 3 });
+    let decoded = RgbaImage::from_fn(w, h, |x, y| {
+        let fx: Vec<f64> = (0..n)
+            .map(|cx| (PI / w as f64 * (x as f64 + 0.5) * cx as f64).cos())
+            .collect();
+        let fy: Vec<f64> = (0..n)
+            .map(|cy| (PI / h as f64 * (y as f64 + 0.5) * cy as f64).cos())
+            .collect();
+        let sum = |ac: &[f64], nx: usize, ny: usize| {
+            let mut value = 0.0;
+            let mut j = 0;
+            for (cy, fy) in fy.iter().enumerate().take(ny) {
+                let mut cx = if cy > 0 { 0 } else { 1 };
+                while cx * ny < nx * (ny - cy) {
+                    value += ac[j] * fx[cx] * fy * 2.0;
+                    j += 1;
+                    cx += 1;
+                }
+            }
+            value
+        };
+        let l = l_dc + sum(&l_ac, lx, ly);
+        let p = p_dc + sum(&p_ac, 3, 3);
+        let q = q_dc + sum(&q_ac, 3, 3);
+        let a = if has_alpha {
+            a_dc + sum(&a_ac, 5, 5)
+        } else {
+            a_dc
+        };
+        let b = l - 2.0 / 3.0 * p;
+        let r = (3.0 * l - b + q) / 2.0;
+        let g = r - q;
+        image::Rgba([r, g, b, a].map(|v| (255.0 * v.clamp(0.0, 1.0)) as u8))
+    });
+    Some(decoded)
+}
+
+/// Returns approximate ratio of width to height of image of ThumbHash `hash` of at least 5 bytes.
+fn thumbhash_aspect_ratio(hash: &[u8]) -> f64 {
+    let has_alpha = hash[2] & 0x80 != 0;
+    let landscape = hash[4] & 0x80 != 0;
+    let l_limit = if has_alpha { 5.0 } else { 7.0 };
+    let other = (hash[3] & 7) as f64;
+    match landscape {
+        true => l_limit / other,
+        false => other / l_limit,
+    }
+}
+
+/// Returns RGBA pixels of `image` scaled to fit `MAX_SIZE`, keeping aspect ratio.
+fn thumbnail(image: &DynamicImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if width <= MAX_SIZE && height <= MAX_SIZE {
+        return image.to_rgba8();
+    }
+    image
+        .resize(MAX_SIZE, MAX_SIZE, FilterType::Triangle)
+        .to_rgba8()
+}
+
+/// Converts 8-bit sRGB value to linear light in `[0, 1]`.
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = value as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts linear light to 8-bit sRGB value, clamping it to `[0, 1]`.
+fn linear_to_srgb(value: f64) -> u32 {
+    let v = value.clamp(0.0, 1.0);
+    let srgb = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0 + 0.5) as u32
+}
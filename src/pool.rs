@@ -0,0 +1,141 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Maximum size of idle buffers kept by pool in bytes, `0` disables pool.
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+static POOL: Mutex<Pool> = Mutex::new(Pool {
+    bytes: 0,
+    u8: Vec::new(),
+    u16: Vec::new(),
+    f32: Vec::new(),
+});
+
+/// Idle buffers of every element type.
+pub struct Pool {
+    /// Size of idle buffers in bytes.
+    bytes: usize,
+    u8: Vec<Vec<u8>>,
+    u16: Vec<Vec<u16>>,
+    f32: Vec<Vec<f32>>,
+}
+
+impl Pool {
+    /// Frees all idle buffers and returns their size in bytes.
+    fn clear(&mut self) -> usize {
+        self.u8 = Vec::new();
+        self.u16 = Vec::new();
+        self.f32 = Vec::new();
+        std::mem::take(&mut self.bytes)
+    }
+}
+
+/// Element of buffers pool keeps.
+pub trait Pooled: Sized + Send + 'static {
+    fn shelf(pool: &mut Pool) -> &mut Vec<Vec<Self>>;
+}
+
+impl Pooled for u8 {
+    fn shelf(pool: &mut Pool) -> &mut Vec<Vec<Self>> {
+        &mut pool.u8
+    }
+}
+
+impl Pooled for u16 {
+    fn shelf(pool: &mut Pool) -> &mut Vec<Vec<Self>> {
+        &mut pool.u16
+    }
+}
+
+impl Pooled for f32 {
+    fn shelf(pool: &mut Pool) -> &mut Vec<Vec<Self>> {
+        &mut pool.f32
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Pool> {
+    POOL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Sets maximum size of idle buffers kept by pool in bytes. `0` disables pool, which is the
+/// default, and frees buffers it keeps.
+pub fn set_limit(bytes: usize) {
+    LIMIT.store(bytes, Ordering::Relaxed);
+    let mut pool = lock();
+    if pool.bytes > bytes {
+        pool.clear();
+    }
+}
+
+/// Frees idle buffers kept by pool, e.g. once burst of work is over. Returns their size in
+/// bytes.
+pub fn trim() -> usize {
+    lock().clear()
+}
+
+/// Empty temporary buffer with capacity of at least `capacity` elements, taken from pool if it
+/// keeps one. Buffer is returned to pool once dropped.
+pub fn take<T: Pooled>(capacity: usize) -> Scratch<T> {
+    if LIMIT.load(Ordering::Relaxed) == 0 {
+        return Scratch(Vec::with_capacity(capacity));
+    }
+    let mut pool = lock();
+    let shelf = T::shelf(&mut pool);
+    // The smallest fitting buffer, so large ones are left for large requests.
+    let best = shelf
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| b.capacity() >= capacity)
+        .min_by_key(|(_, b)| b.capacity())
+        .map(|(i, _)| i);
+    match best {
+        Some(i) => {
+            let buffer = shelf.swap_remove(i);
+            pool.bytes -= buffer.capacity() * std::mem::size_of::<T>();
+            Scratch(buffer)
+        }
+        None => {
+            drop(pool);
+            Scratch(Vec::with_capacity(capacity))
+        }
+    }
+}
+
+/// Returns `buffer` to pool, or frees it if pool is disabled or full.
+fn give<T: Pooled>(mut buffer: Vec<T>) {
+    let bytes = buffer.capacity() * std::mem::size_of::<T>();
+    let limit = LIMIT.load(Ordering::Relaxed);
+    if bytes == 0 || bytes > limit {
+        return;
+    }
+    buffer.clear();
+    let mut pool = lock();
+    if pool.bytes + bytes <= limit {
+        pool.bytes += bytes;
+        T::shelf(&mut pool).push(buffer);
+    }
+}
+
+/// Temporary buffer returned to pool once dropped.
+pub struct Scratch<T: Pooled>(Vec<T>);
+
+impl<T: Pooled> Deref for Scratch<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T: Pooled> DerefMut for Scratch<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
+
+impl<T: Pooled> Drop for Scratch<T> {
+    fn drop(&mut self) {
+        give(std::mem::take(&mut self.0));
+    }
+}
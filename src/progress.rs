@@ -0,0 +1,181 @@
+use crate::{ImageError, LastError, ProgressFn};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Flag set by host to cancel operations it was passed to, e.g. when user presses "Cancel".
+#[derive(Default)]
+pub struct Token {
+    cancelled: AtomicBool,
+}
+
+impl Token {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress callback of running operation with its user data and cancellation token.
+struct Callback {
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+    token: Option<Arc<Token>>,
+    /// The last whole percent passed to callback.
+    reported: Mutex<Option<u32>>,
+    /// Set once callback returns `false`.
+    aborted: AtomicBool,
+}
+
+// Host guarantees that callback and its user data are usable from worker threads of library.
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+/// Part of progress of running operation covered by one of its steps, e.g. pass of blur.
+#[derive(Clone)]
+pub struct Scope {
+    callback: Arc<Callback>,
+    /// Percent of operation done when step starts.
+    start: f32,
+    /// Percents of operation step covers.
+    len: f32,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Scope>> = const { RefCell::new(None) };
+}
+
+/// Restores scope of enclosing step, also if step panics.
+struct Restore(Option<Scope>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        ACTIVE.with(|a| *a.borrow_mut() = previous);
+    }
+}
+
+impl Scope {
+    /// Reports that `fraction` in `[0, 1]` of step is done. Callback is called once percent of
+    /// operation grows by whole percent, one call at a time. Returns `false` if operation is
+    /// aborted.
+    pub fn report(&self, fraction: f32) -> bool {
+        let callback = &self.callback;
+        let progress_fn = match callback.progress_fn {
+            Some(p) => p,
+            None => return !self.is_aborted(),
+        };
+        let mut reported = callback.reported.lock().unwrap_or_else(|e| e.into_inner());
+        if self.is_aborted() {
+            return false;
+        }
+        let percent = self.start + self.len * fraction.clamp(0.0, 1.0);
+        if reported.is_some_and(|r| percent as u32 <= r) {
+            return true;
+        }
+        *reported = Some(percent as u32);
+        let proceed = unsafe { progress_fn(percent, callback.user_data) };
+        if !proceed {
+            callback.aborted.store(true, Ordering::Relaxed);
+        }
+        proceed
+    }
+
+    /// Returns `true` once callback asked to abort operation or its token is cancelled, so its
+    /// remaining work is skipped.
+    pub fn is_aborted(&self) -> bool {
+        let callback = &self.callback;
+        callback.aborted.load(Ordering::Relaxed)
+            || callback.token.as_ref().is_some_and(|t| t.is_cancelled())
+    }
+}
+
+/// Returns scope of step running on current thread, or `None` if operation has no progress
+/// callback. Scope is passed to worker threads processing parts of step.
+pub fn current() -> Option<Scope> {
+    ACTIVE.with(|a| a.borrow().clone())
+}
+
+/// Runs `f` reporting its progress to `progress_fn` with `user_data`, from 0 before it starts to
+/// 100 once it succeeds. Null `progress_fn` runs `f` as is. Returns `ImageError::Cancelled` if
+/// callback aborted `f`, also if it finishes nevertheless.
+pub fn run<F: FnOnce() -> ImageError>(
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+    f: F,
+) -> ImageError {
+    run_cancellable(progress_fn, user_data, None, f)
+}
+
+/// Runs `f` as `run` does, also aborting it once `token` is cancelled. Operation cancelled
+/// before it starts isn't run.
+pub fn run_cancellable<F: FnOnce() -> ImageError>(
+    progress_fn: Option<ProgressFn>,
+    user_data: *mut c_void,
+    token: Option<Arc<Token>>,
+    f: F,
+) -> ImageError {
+    if progress_fn.is_none() && token.is_none() {
+        return f();
+    }
+    let scope = Scope {
+        callback: Arc::new(Callback {
+            progress_fn,
+            user_data,
+            token,
+            reported: Mutex::new(None),
+            aborted: AtomicBool::new(false),
+        }),
+        start: 0.0,
+        len: 100.0,
+    };
+    let result = match scope.report(0.0) {
+        true => {
+            let previous = ACTIVE.with(|a| a.borrow_mut().replace(scope.clone()));
+            let _restore = Restore(previous);
+            f()
+        }
+        false => ImageError::NoError,
+    };
+    if scope.is_aborted() {
+        let message = match scope.callback.aborted.load(Ordering::Relaxed) {
+            true => "Operation was aborted by progress callback".to_string(),
+            false => "Operation was cancelled by its token".to_string(),
+        };
+        crate::set_last_error(LastError::new(ImageError::Cancelled, message));
+        return ImageError::Cancelled;
+    }
+    if let ImageError::NoError = result {
+        scope.report(1.0);
+    }
+    result
+}
+
+/// Runs step `f` of operation covering `len` fraction of progress of enclosing step after
+/// `start` fraction of it, e.g. the second of two equal passes starts at `0.5` with `0.5` length.
+pub fn step<T, F: FnOnce() -> T>(start: f32, len: f32, f: F) -> T {
+    let scope = current().map(|s| Scope {
+        start: s.start + s.len * start,
+        len: s.len * len,
+        callback: s.callback,
+    });
+    let scope = match scope {
+        Some(s) => s,
+        None => return f(),
+    };
+    let previous = ACTIVE.with(|a| a.borrow_mut().replace(scope));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Runs `f` outside of step running on current thread, so progress of operations it runs isn't
+/// reported, e.g. for one of many items whose count is reported instead.
+pub fn detach<T, F: FnOnce() -> T>(f: F) -> T {
+    let previous = ACTIVE.with(|a| a.borrow_mut().take());
+    let _restore = Restore(previous);
+    f()
+}
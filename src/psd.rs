@@ -0,0 +1,579 @@
+use crate::document::{Document, Layer};
+use crate::meta::Metadata;
+use crate::{decode, BlendMode, ImageData, ImageError, LastError, OpenOptions};
+use image::{DynamicImage, ImageBuffer};
+use std::convert::TryFrom;
+use std::io::{BufRead, Seek, SeekFrom};
+
+/// Layer blend mode keys in `BlendMode` order.
+const BLEND_KEYS: [&[u8; 4]; 8] = [
+    b"norm", b"mul ", b"scrn", b"over", b"dark", b"lite", b"lddg", b"diff",
+];
+
+/// Returns `true` if `reader` is positioned at start of PSD file. Position is kept.
+pub fn detect<R: BufRead + Seek>(reader: &mut R) -> std::io::Result<bool> {
+    let start = reader.stream_position()?;
+    let mut signature = [0; 4];
+    let detected = match reader.read_exact(&mut signature) {
+        Ok(_) => &signature == b"8BPS",
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => false,
+        Err(e) => return Err(e),
+    };
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(detected)
+}
+
+/// Reads composite image of PSD `file` with its ICC profile and resolution. Composite alpha is
+/// read only if file marks its first extra channel as transparency of merged image.
+pub fn read_composite(file: &[u8], options: &OpenOptions) -> Result<ImageData, ImageError> {
+    let psd = Psd::parse(file)?;
+    let header = &psd.header;
+    let color_channels = header.color_channels();
+    let alpha = psd.merged_alpha && header.channels > color_channels;
+    let count = color_channels + alpha as usize;
+    decode::check_limits(
+        header.width,
+        header.height,
+        psd.size(header.width, header.height, count),
+        options,
+    )?;
+
+    let mut reader = Reader::new(psd.image_data);
+    let compression = reader.u16()?;
+    let length = reader.remaining();
+    let mut planes = header.planes(
+        &mut reader,
+        compression,
+        header.channels,
+        header.width,
+        header.height,
+        length,
+    )?;
+    planes.truncate(count);
+    let image = header.image(planes, header.width, header.height)?;
+    Ok(ImageData::new(image, psd.metadata()))
+}
+
+/// Reads raster layers of PSD `file` into document of canvas size. Group dividers, adjustment
+/// layers without pixels and layer masks are skipped, unsupported blend modes are replaced with
+/// normal blending. File without raster layers gives document with composite image as the only
+/// layer.
+pub fn read_document(file: &[u8], options: &OpenOptions) -> Result<Document, ImageError> {
+    let psd = Psd::parse(file)?;
+    let header = &psd.header;
+    let mut layers = Vec::new();
+    if let Some(info) = psd.layer_info {
+        let mut reader = Reader::new(info);
+        let count = reader.i16()?.unsigned_abs() as usize;
+        let records = (0..count)
+            .map(|_| Record::read(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for record in records {
+            let (width, height) = record.size();
+            let mut color = vec![Vec::new(); header.color_channels()];
+            let mut alpha = None;
+            // Limits are checked before planes are decompressed.
+            if !record.divider && width != 0 && height != 0 {
+                let has_alpha = record.channels.iter().any(|&(id, _)| id == -1);
+                let count = color.len() + has_alpha as usize;
+                decode::check_limits(width, height, psd.size(width, height, count), options)?;
+            }
+            for &(id, length) in &record.channels {
+                let data = reader.take(length)?;
+                // Masks have their own bounds, so only color and transparency planes are read.
+                let plane = match id {
+                    0..=2 if (id as usize) < color.len() => &mut color[id as usize],
+                    -1 => alpha.get_or_insert_with(Vec::new),
+                    _ => continue,
+                };
+                if width == 0 || height == 0 {
+                    continue;
+                }
+                let mut reader = Reader::new(data);
+                let compression = reader.u16()?;
+                let length = reader.remaining();
+                *plane = header
+                    .planes(&mut reader, compression, 1, width, height, length)?
+                    .remove(0);
+            }
+            if record.divider || width == 0 || height == 0 {
+                continue;
+            }
+            if color.iter().any(Vec::is_empty) {
+                return Err(malformed("layer without color channel"));
+            }
+
+            color.extend(alpha);
+            let image = header.image(color, width, height)?;
+
+            let blend_mode = match BLEND_KEYS.iter().position(|&k| k == &record.blend_key) {
+                Some(mode) => BlendMode::try_from(mode as u32)?,
+                None => {
+                    let key = String::from_utf8_lossy(&record.blend_key);
+                    crate::log::warn(&format!(
+                        "Unsupported blend mode {} replaced with normal",
+                        key
+                    ));
+                    BlendMode::Normal
+                }
+            };
+            let data = ImageData::new(image, psd.metadata());
+            layers.push(Layer {
+                data: data.into_box(),
+                x: record.left,
+                y: record.top,
+                opacity: record.opacity as f32 / u8::MAX as f32,
+                blend_mode,
+                // Bit 1 of flags is set for hidden layers.
+                visible: record.flags & 2 == 0,
+            });
+        }
+    }
+
+    if layers.is_empty() {
+        layers.push(Layer {
+            data: read_composite(file, options)?.into_box(),
+            x: 0,
+            y: 0,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            visible: true,
+        });
+    }
+    Ok(Document {
+        width: header.width,
+        height: header.height,
+        layers,
+    })
+}
+
+/// File header of PSD.
+struct Header {
+    channels: usize,
+    width: u32,
+    height: u32,
+    depth: u16,
+    color_mode: u16,
+}
+
+impl Header {
+    /// Returns number of color channels of image, 1 for grayscale or 3 for RGB.
+    fn color_channels(&self) -> usize {
+        match self.color_mode {
+            1 => 1,
+            _ => 3,
+        }
+    }
+
+    /// Decodes `count` planes of `width` x `height` pixels compressed with `compression` from
+    /// `reader`. Zip-compressed planes take `length` bytes.
+    fn planes(
+        &self,
+        reader: &mut Reader,
+        compression: u16,
+        count: usize,
+        width: u32,
+        height: u32,
+        length: usize,
+    ) -> Result<Vec<Vec<u8>>, ImageError> {
+        let row = (width as usize)
+            .checked_mul((self.depth / 8) as usize)
+            .ok_or_else(|| malformed("too large plane"))?;
+        let plane = row
+            .checked_mul(height as usize)
+            .ok_or_else(|| malformed("too large plane"))?;
+        let size = plane
+            .checked_mul(count)
+            .ok_or_else(|| malformed("too large plane"))?;
+        match compression {
+            0 => (0..count)
+                .map(|_| Ok(reader.take(plane)?.to_vec()))
+                .collect(),
+            1 => {
+                let rows = count
+                    .checked_mul(height as usize)
+                    .ok_or_else(|| malformed("too large plane"))?;
+                let lengths = (0..rows)
+                    .map(|_| reader.u16())
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut planes = vec![Vec::with_capacity(plane); count];
+                for (index, &length) in lengths.iter().enumerate() {
+                    let packed = reader.take(length as usize)?;
+                    unpack_bits(packed, row, &mut planes[index / height as usize])?;
+                }
+                Ok(planes)
+            }
+            2 | 3 => {
+                let packed = reader.take(length)?;
+                let mut data = decode::inflate(packed, size, true)?
+                    .ok_or_else(|| malformed("invalid zip stream"))?;
+                if data.len() < size {
+                    return Err(malformed("truncated zip stream"));
+                }
+                if compression == 3 && row != 0 {
+                    for line in data.chunks_exact_mut(row) {
+                        predict(line, self.depth);
+                    }
+                }
+                Ok(data
+                    .chunks_exact(plane.max(1))
+                    .take(count)
+                    .map(<[u8]>::to_vec)
+                    .collect())
+            }
+            _ => Err(malformed("unknown compression")),
+        }
+    }
+
+    /// Interleaves color `planes`, optionally followed by alpha plane, into image.
+    fn image(
+        &self,
+        planes: Vec<Vec<u8>>,
+        width: u32,
+        height: u32,
+    ) -> Result<DynamicImage, ImageError> {
+        let bytes = (self.depth / 8) as usize;
+        let pixels = width as usize * height as usize;
+        let mut raw = Vec::with_capacity(pixels * planes.len() * bytes);
+        for pixel in 0..pixels {
+            for plane in &planes {
+                raw.extend_from_slice(&plane[pixel * bytes..(pixel + 1) * bytes]);
+            }
+        }
+
+        let wide = || {
+            raw.chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect::<Vec<_>>()
+        };
+        let image = match (self.depth, planes.len()) {
+            (8, 1) => ImageBuffer::from_raw(width, height, raw).map(DynamicImage::ImageLuma8),
+            (8, 2) => ImageBuffer::from_raw(width, height, raw).map(DynamicImage::ImageLumaA8),
+            (8, 3) => ImageBuffer::from_raw(width, height, raw).map(DynamicImage::ImageRgb8),
+            (8, _) => ImageBuffer::from_raw(width, height, raw).map(DynamicImage::ImageRgba8),
+            (_, 1) => ImageBuffer::from_raw(width, height, wide()).map(DynamicImage::ImageLuma16),
+            (_, 2) => ImageBuffer::from_raw(width, height, wide()).map(DynamicImage::ImageLumaA16),
+            (_, 3) => ImageBuffer::from_raw(width, height, wide()).map(DynamicImage::ImageRgb16),
+            _ => ImageBuffer::from_raw(width, height, wide()).map(DynamicImage::ImageRgba16),
+        };
+        image.ok_or_else(|| malformed("invalid channel data"))
+    }
+}
+
+/// Sections of PSD file.
+struct Psd<'a> {
+    header: Header,
+    /// Image resources as `(id, data)` pairs.
+    resources: Vec<(u16, &'a [u8])>,
+    /// Content of layer info: layer count, layer records and channel image data.
+    layer_info: Option<&'a [u8]>,
+    /// First extra channel of composite image is its transparency.
+    merged_alpha: bool,
+    image_data: &'a [u8],
+}
+
+impl<'a> Psd<'a> {
+    /// Splits `file` into sections. Only 8 and 16 bits per channel grayscale and RGB files are
+    /// supported.
+    fn parse(file: &'a [u8]) -> Result<Self, ImageError> {
+        let mut reader = Reader::new(file);
+        if reader.take(4)? != b"8BPS" {
+            return Err(malformed("invalid signature"));
+        }
+        let version = reader.u16()?;
+        reader.take(6)?;
+        let header = Header {
+            channels: reader.u16()? as usize,
+            height: reader.u32()?,
+            width: reader.u32()?,
+            depth: reader.u16()?,
+            color_mode: reader.u16()?,
+        };
+        if version != 1 || !matches!(header.depth, 8 | 16) || !matches!(header.color_mode, 1 | 3) {
+            let message = format!(
+                "PSD version {}, {} bits per channel, color mode {} isn't supported",
+                version, header.depth, header.color_mode
+            );
+            crate::set_last_error(LastError::new(ImageError::Unsupported, message));
+            return Err(ImageError::Unsupported);
+        }
+        if header.width == 0 || header.height == 0 || header.channels < header.color_channels() {
+            return Err(malformed("invalid header"));
+        }
+
+        let length = reader.u32()? as usize;
+        reader.take(length)?;
+        let length = reader.u32()? as usize;
+        let mut resources = Vec::new();
+        let mut section = Reader::new(reader.take(length)?);
+        while section.remaining() > 0 {
+            if section.take(4)? != b"8BIM" {
+                return Err(malformed("invalid image resource"));
+            }
+            let id = section.u16()?;
+            // Pascal string name is padded to even size.
+            let name = section.u8()? as usize;
+            section.take(name + (name + 1) % 2)?;
+            let size = section.u32()? as usize;
+            resources.push((id, section.take(size)?));
+            section.take(size % 2)?;
+        }
+
+        let length = reader.u32()? as usize;
+        let (layer_info, merged_alpha) = Self::layers(reader.take(length)?, header.depth)?;
+        Ok(Self {
+            header,
+            resources,
+            layer_info,
+            merged_alpha,
+            image_data: reader.take(reader.remaining())?,
+        })
+    }
+
+    /// Finds layer info in layer and mask information `section`. Layers of 16 bits per channel
+    /// files are stored in `Lr16` block after global layer mask.
+    fn layers(section: &'a [u8], depth: u16) -> Result<(Option<&'a [u8]>, bool), ImageError> {
+        if section.is_empty() {
+            return Ok((None, false));
+        }
+        let mut reader = Reader::new(section);
+        let length = reader.u32()? as usize;
+        let mut info = reader.take(length)?;
+        if info.is_empty() && depth == 16 && reader.remaining() > 0 {
+            let length = reader.u32()? as usize;
+            reader.take(length)?;
+            while reader.remaining() >= 12 {
+                reader.take(4)?;
+                let key = reader.take(4)?;
+                let length = reader.u32()? as usize;
+                let data = reader.take(length)?;
+                if key == b"Lr16" {
+                    info = data;
+                    break;
+                }
+            }
+        }
+
+        match info.len() {
+            0 | 1 => Ok((None, false)),
+            _ => Ok((Some(info), (info[0] as i8) < 0)),
+        }
+    }
+
+    /// Returns size in bytes of `count` planes of `width` x `height` pixels.
+    fn size(&self, width: u32, height: u32, count: usize) -> u64 {
+        width as u64 * height as u64 * count as u64 * (self.header.depth / 8) as u64
+    }
+
+    /// Returns metadata of image resources: ICC profile and resolution.
+    fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::default();
+        for &(id, data) in &self.resources {
+            match id {
+                1039 => metadata.icc = Some(data.to_vec()),
+                1005 => {
+                    let mut reader = Reader::new(data);
+                    let mut resolution = || -> Result<f64, ImageError> {
+                        // Fixed point 16.16 value, its unit (2 means pixels per cm) and unit
+                        // of size.
+                        let value = reader.u32()? as f64 / 65536.0;
+                        let unit = reader.u16()?;
+                        reader.u16()?;
+                        Ok(if unit == 2 { value * 2.54 } else { value })
+                    };
+                    if let (Ok(x), Ok(y)) = (resolution(), resolution()) {
+                        metadata.dpi = Some((x, y)).filter(|_| x > 0.0 && y > 0.0);
+                    }
+                }
+                _ => {}
+            }
+        }
+        metadata
+    }
+}
+
+/// Layer record of PSD.
+struct Record {
+    top: i32,
+    left: i32,
+    bottom: i32,
+    right: i32,
+    /// Channel ids and lengths of their data.
+    channels: Vec<(i16, usize)>,
+    blend_key: [u8; 4],
+    opacity: u8,
+    flags: u8,
+    /// Layer marks start or end of layer group.
+    divider: bool,
+}
+
+impl Record {
+    fn read(reader: &mut Reader) -> Result<Self, ImageError> {
+        let (top, left, bottom, right) =
+            (reader.i32()?, reader.i32()?, reader.i32()?, reader.i32()?);
+        let count = reader.u16()?;
+        let channels = (0..count)
+            .map(|_| Ok((reader.i16()?, reader.u32()? as usize)))
+            .collect::<Result<Vec<_>, ImageError>>()?;
+        if reader.take(4)? != b"8BIM" {
+            return Err(malformed("invalid layer record"));
+        }
+        let mut blend_key = [0; 4];
+        blend_key.copy_from_slice(reader.take(4)?);
+        let opacity = reader.u8()?;
+        reader.u8()?;
+        let flags = reader.u8()?;
+        reader.u8()?;
+
+        let length = reader.u32()? as usize;
+        let mut extra = Reader::new(reader.take(length)?);
+        for _ in 0..2 {
+            // Layer mask and blending ranges.
+            let length = extra.u32()? as usize;
+            extra.take(length)?;
+        }
+        // Pascal string name is padded to multiple of 4 bytes.
+        let name = extra.u8()? as usize;
+        extra.take((name + 4) / 4 * 4 - 1)?;
+
+        let mut divider = false;
+        while extra.remaining() >= 12 {
+            extra.take(4)?;
+            let key = extra.take(4)?;
+            let length = extra.u32()? as usize;
+            let data = extra.take(length.min(extra.remaining()))?;
+            if key == b"lsct" || key == b"lsdk" {
+                divider |= data.get(..4).is_some_and(|t| t != [0; 4]);
+            }
+        }
+
+        Ok(Self {
+            top,
+            left,
+            bottom,
+            right,
+            channels,
+            blend_key,
+            opacity,
+            flags,
+            divider,
+        })
+    }
+
+    /// Returns width and height of layer bounds.
+    fn size(&self) -> (u32, u32) {
+        let width = (self.right as i64 - self.left as i64).max(0);
+        let height = (self.bottom as i64 - self.top as i64).max(0);
+        (
+            width.min(u32::MAX as i64) as u32,
+            height.min(u32::MAX as i64) as u32,
+        )
+    }
+}
+
+/// Big-endian reader over bytes of PSD file.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ImageError> {
+        match self.data.get(self.pos..self.pos.saturating_add(len)) {
+            Some(bytes) => {
+                self.pos += len;
+                Ok(bytes)
+            }
+            None => Err(malformed("unexpected end of file")),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, ImageError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ImageError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, ImageError> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, ImageError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, ImageError> {
+        Ok(self.u32()? as i32)
+    }
+}
+
+/// Appends `row` bytes unpacked from PackBits-compressed `packed` to `plane`.
+fn unpack_bits(packed: &[u8], row: usize, plane: &mut Vec<u8>) -> Result<(), ImageError> {
+    let end = plane.len() + row;
+    let mut pos = 0;
+    while pos < packed.len() && plane.len() < end {
+        let header = packed[pos] as i8;
+        pos += 1;
+        match header {
+            -128 => {}
+            0..=127 => {
+                let len = header as usize + 1;
+                let literal = packed
+                    .get(pos..pos + len)
+                    .ok_or_else(|| malformed("invalid RLE data"))?;
+                plane.extend_from_slice(literal);
+                pos += len;
+            }
+            _ => {
+                let value = *packed
+                    .get(pos)
+                    .ok_or_else(|| malformed("invalid RLE data"))?;
+                plane.resize(plane.len() + (1 - header as isize) as usize, value);
+                pos += 1;
+            }
+        }
+    }
+    if plane.len() < end {
+        return Err(malformed("invalid RLE data"));
+    }
+    plane.truncate(end);
+    Ok(())
+}
+
+/// Reverts delta encoding of zip-with-prediction compressed `row` of `depth` bits samples.
+fn predict(row: &mut [u8], depth: u16) {
+    match depth {
+        8 => {
+            for i in 1..row.len() {
+                row[i] = row[i].wrapping_add(row[i - 1]);
+            }
+        }
+        _ => {
+            for i in (2..row.len().saturating_sub(1)).step_by(2) {
+                let previous = u16::from_be_bytes([row[i - 2], row[i - 1]]);
+                let value = u16::from_be_bytes([row[i], row[i + 1]]).wrapping_add(previous);
+                row[i..i + 2].copy_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+}
+
+/// Records `message` about malformed PSD file as the last error.
+fn malformed(message: &str) -> ImageError {
+    let message = format!("Malformed PSD file: {}", message);
+    crate::set_last_error(LastError::new(ImageError::Decoding, message));
+    ImageError::Decoding
+}
@@ -0,0 +1,547 @@
+use crate::pipeline::{Pipeline, Step};
+use crate::{GuideSpec, HslBand, ImageError, ImageHandle, LastError, Point, RawStr, Rect};
+use image::DynamicImage;
+use serde_json::{json, Map, Value};
+use std::convert::TryFrom;
+use std::ffi::CString;
+
+/// Version of edit stack documents written by `export`.
+const VERSION: u64 = 1;
+
+/// Returns edit stack document with `edits` descriptions, e.g.
+/// `{"version": 1, "edits": [{"op": "mirror"}]}`.
+pub fn export(edits: &[Value]) -> String {
+    json!({"version": VERSION, "edits": edits}).to_string()
+}
+
+/// Applies edits of edit stack document `json` to image in order. Each edit is applied by the
+/// exported function that recorded it, so it is recorded in journal of image again.
+/// # Safety
+/// `handle` is valid image handle.
+pub unsafe fn apply(handle: &ImageHandle, json: &str) -> Result<(), ImageError> {
+    apply_edits(handle, &parse(json)?)
+}
+
+/// Returns edits of edit stack document `json`.
+pub fn parse(json: &str) -> Result<Vec<Value>, ImageError> {
+    let stack: Value =
+        serde_json::from_str(json).map_err(|e| invalid(format!("Invalid edit stack: {}", e)))?;
+    if stack["version"] != VERSION {
+        return Err(invalid(format!(
+            "Unsupported edit stack version: {}",
+            stack["version"]
+        )));
+    }
+
+    match stack {
+        Value::Object(mut stack) => match stack.remove("edits") {
+            Some(Value::Array(edits)) => Ok(edits),
+            _ => Err(invalid("Edit stack has no edits array".into())),
+        },
+        _ => Err(invalid("Edit stack isn't object".into())),
+    }
+}
+
+/// Applies `edits` parsed by `parse` to image in order, as `apply` does.
+/// # Safety
+/// `handle` is valid image handle.
+pub unsafe fn apply_edits(handle: &ImageHandle, edits: &[Value]) -> Result<(), ImageError> {
+    for edit in edits {
+        apply_edit(handle, edit)?;
+    }
+    Ok(())
+}
+
+/// Applies edit `op` with parameters `params` to image. Parameters are JSON object, e.g.
+/// `{"sigma": 2.5}`, or `key=value` pairs separated by commas or whitespace, e.g. `sigma=2.5`.
+/// # Safety
+/// `handle` is valid image handle.
+pub unsafe fn apply_op(handle: &ImageHandle, op: &str, params: &str) -> Result<(), ImageError> {
+    apply_params(handle, op, parse_params(op, params)?)
+}
+
+/// Applies edit `op` with parameters `params` parsed by `parse_params` to image.
+/// # Safety
+/// `handle` is valid image handle.
+pub unsafe fn apply_params(
+    handle: &ImageHandle,
+    op: &str,
+    mut params: Map<String, Value>,
+) -> Result<(), ImageError> {
+    params.insert("op".into(), Value::String(op.into()));
+    apply_edit(handle, &Value::Object(params))
+}
+
+/// Scales parameters of edit `op` measured in pixels, e.g. blur sigma or rectangles, by
+/// `scale_x` horizontally and `scale_y` vertically, so that edit of image resized by these
+/// factors looks as edit of original image. Positions and sizes are rounded down, so rectangles
+/// of original image stay within resized one. Parameters which aren't numbers are left as is.
+pub fn scale_params(op: &str, params: &mut Map<String, Value>, (scale_x, scale_y): (f64, f64)) {
+    let scale = (scale_x + scale_y) / 2.0;
+    match op {
+        "blur" => scale_f64(params.get_mut("sigma"), scale),
+        "shadows_highlights" | "round_corners" => scale_f64(params.get_mut("radius"), scale),
+        "draw_guides" => scale_u32(params.get_mut("line_width"), scale, 1),
+        "set_pixel" => {
+            scale_u32(params.get_mut("x"), scale_x, 0);
+            scale_u32(params.get_mut("y"), scale_y, 0);
+        }
+        "burn_annotations" => {
+            scale_u32(params.get_mut("line_width"), scale, 1);
+            let rects = params.get_mut("rects").and_then(Value::as_array_mut);
+            // Rectangles are `[x, y, width, height]` arrays.
+            for rect in rects.into_iter().flatten().filter_map(Value::as_array_mut) {
+                for (i, value) in rect.iter_mut().enumerate() {
+                    let scale = if i % 2 == 0 { scale_x } else { scale_y };
+                    scale_u32(Some(value), scale, (i / 2) as u32);
+                }
+            }
+        }
+        "draw_line" | "draw_rect" | "draw_circle" => {
+            for name in ["x0", "x1", "x", "width", "cx"] {
+                scale_f64(params.get_mut(name), scale_x);
+            }
+            for name in ["y0", "y1", "y", "height", "cy"] {
+                scale_f64(params.get_mut(name), scale_y);
+            }
+            scale_f64(params.get_mut("radius"), scale);
+            scale_f64(params.get_mut("thickness"), scale);
+        }
+        "draw_polygon" => {
+            let coords = params.get_mut("points").and_then(Value::as_array_mut);
+            for (i, c) in coords.into_iter().flatten().enumerate() {
+                scale_f64(Some(c), if i % 2 == 0 { scale_x } else { scale_y });
+            }
+            scale_f64(params.get_mut("thickness"), scale);
+        }
+        "mesh_warp" => {
+            for name in ["src", "dst"] {
+                let coords = params.get_mut(name).and_then(Value::as_array_mut);
+                for (i, c) in coords.into_iter().flatten().enumerate() {
+                    scale_f64(Some(c), if i % 2 == 0 { scale_x } else { scale_y });
+                }
+            }
+        }
+        "pipeline" => {
+            let steps = params.get_mut("steps").and_then(Value::as_array_mut);
+            let (mut scale_x, mut scale_y) = (scale_x, scale_y);
+            for step in steps.into_iter().flatten() {
+                let op = step.get("op").and_then(Value::as_str).unwrap_or_default();
+                match op {
+                    "crop" => {
+                        scale_u32(step.get_mut("x"), scale_x, 0);
+                        scale_u32(step.get_mut("y"), scale_y, 0);
+                        scale_u32(step.get_mut("width"), scale_x, 1);
+                        scale_u32(step.get_mut("height"), scale_y, 1);
+                    }
+                    // Later steps are scaled as resized image is, so crops stay within it.
+                    "resize" => {
+                        for (name, scale) in [("width", &mut scale_x), ("height", &mut scale_y)] {
+                            if let Some(size) = step.get(name).and_then(as_u32) {
+                                let scaled = ((size as f64 * *scale).round() as u32).max(1);
+                                step[name] = scaled.into();
+                                *scale = scaled as f64 / size as f64;
+                            }
+                        }
+                    }
+                    "blur" => scale_f64(step.get_mut("sigma"), (scale_x + scale_y) / 2.0),
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn scale_f64(value: Option<&mut Value>, scale: f64) {
+    if let Some(value) = value {
+        if let Some(v) = value.as_f64() {
+            *value = (v * scale).into();
+        }
+    }
+}
+
+/// Scales `value` fitting `u32` rounding it down, but not below `min`.
+fn scale_u32(value: Option<&mut Value>, scale: f64, min: u32) {
+    if let Some(value) = value {
+        if let Some(v) = as_u32(value) {
+            *value = ((v as f64 * scale).floor() as u32).max(min).into();
+        }
+    }
+}
+
+/// Parses parameters `params` of operation `op` written as JSON object or `key=value` pairs, as
+/// `apply_op` takes them.
+pub fn parse_params(op: &str, params: &str) -> Result<Map<String, Value>, ImageError> {
+    match params.trim_start().starts_with('{') {
+        true => match serde_json::from_str(params) {
+            Ok(Value::Object(p)) => Ok(p),
+            _ => Err(invalid(format!(
+                "Invalid parameters of `{}`: {}",
+                op, params
+            ))),
+        },
+        false => key_values(op, params),
+    }
+}
+
+/// Parses `key=value` pairs of `params`. Values are parsed as JSON if they can be, e.g. numbers
+/// and booleans, and taken as strings otherwise.
+fn key_values(op: &str, params: &str) -> Result<Map<String, Value>, ImageError> {
+    let mut map = Map::new();
+    let pairs = params.split(|c: char| c == ',' || c.is_whitespace());
+    for pair in pairs.filter(|p| !p.is_empty()) {
+        let (key, value) = match pair.split_once('=') {
+            Some((k, v)) if !k.is_empty() => (k, v),
+            _ => return Err(invalid(format!("Invalid parameter of `{}`: {}", op, pair))),
+        };
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.into()));
+        map.insert(key.into(), value);
+    }
+    Ok(map)
+}
+
+/// Applies one `edit` to image.
+/// # Safety
+/// `handle` is valid image handle.
+unsafe fn apply_edit(handle: &ImageHandle, edit: &Value) -> Result<(), ImageError> {
+    let params = match edit.as_object() {
+        Some(p) => Params(p),
+        None => return Err(invalid(format!("Edit isn't object: {}", edit))),
+    };
+    let handle = || ImageHandle(handle.0);
+
+    let op = params.str("op")?;
+    let result = match op {
+        "exif_set" => {
+            let value = params.c_string("value")?;
+            let tag = u16::try_from(params.u32("tag")?).map_err(|_| params.error("tag"))?;
+            let ty = params.u32("type")?;
+            crate::img_exif_set(handle(), tag, ty, RawStr(value.as_ptr()))
+        }
+        "auto_orient" => crate::img_auto_orient(handle()),
+        "auto_denoise" => crate::img_auto_denoise(handle()),
+        "auto_sharpen" => crate::img_auto_sharpen(handle()),
+        "correct_ca" => {
+            let (red, blue) = (params.f32("red_scale")?, params.f32("blue_scale")?);
+            crate::img_correct_ca(handle(), red, blue)
+        }
+        "icc_set" => {
+            let icc = match params.optional("icc") {
+                Some(_) => {
+                    let engine = &base64::engine::general_purpose::STANDARD;
+                    let icc = params.str("icc")?;
+                    base64::Engine::decode(engine, icc).map_err(|_| params.error("icc"))?
+                }
+                None => Vec::new(),
+            };
+            crate::img_icc_set(handle(), icc.as_ptr(), icc.len())
+        }
+        "convert_to_srgb" => crate::img_convert_to_srgb(handle()),
+        "defringe" => {
+            let (strength, range) = (params.f32("strength")?, params.f32("hue_range")?);
+            crate::img_defringe(handle(), strength, range)
+        }
+        "set_dpi" => crate::img_set_dpi(handle(), params.f64("x")?, params.f64("y")?),
+        "recover_highlights" => crate::img_recover_highlights(handle(), params.f32("strength")?),
+        "png_text_set" => {
+            let keyword = params.c_string("keyword")?;
+            let value = match params.optional("value") {
+                Some(_) => Some(params.c_string("value")?),
+                None => None,
+            };
+            let value = value.as_ref().map_or(std::ptr::null(), |v| v.as_ptr());
+            crate::img_png_text_set(handle(), RawStr(keyword.as_ptr()), RawStr(value))
+        }
+        "shadows_highlights" => {
+            let shadows = params.f32("shadows")?;
+            let highlights = params.f32("highlights")?;
+            let radius = params.f32("radius")?;
+            crate::img_shadows_highlights(handle(), shadows, highlights, radius)
+        }
+        "hsl_adjust" => {
+            let bands = match params.optional("bands").and_then(Value::as_array) {
+                Some(b) => b,
+                None => return Err(params.error("bands")),
+            };
+            let mut adjustments = Vec::with_capacity(bands.len());
+            for band in bands {
+                let band = match band.as_object() {
+                    Some(b) => Params(b),
+                    None => return Err(params.error("bands")),
+                };
+                adjustments.push(HslBand {
+                    band: band.u32("band")?,
+                    hue: band.f32("hue")?,
+                    saturation: band.f32("saturation")?,
+                    luminance: band.f32("luminance")?,
+                });
+            }
+            crate::img_hsl_adjust(handle(), adjustments.as_ptr(), adjustments.len())
+        }
+        "replace_color" => {
+            let (from, to) = (params.u32("from")?, params.u32("to")?);
+            let tolerance = params.f32("tolerance")?;
+            let preserve_luminosity = params.bool("preserve_luminosity")?;
+            crate::img_replace_color(handle(), from, to, tolerance, preserve_luminosity)
+        }
+        "strip_metadata" => crate::img_strip_metadata(handle()),
+        "split_tone" => {
+            let shadows = params.u32("shadows")?;
+            let midtones = params.u32("midtones")?;
+            let highlights = params.u32("highlights")?;
+            let balance = params.f32("balance")?;
+            crate::img_split_tone(handle(), shadows, midtones, highlights, balance)
+        }
+        "film_emulation" => {
+            let (stock, strength) = (params.u32("stock")?, params.f32("strength")?);
+            crate::img_film_emulation(handle(), stock, strength)
+        }
+        "draw_guides" => {
+            let spec = GuideSpec {
+                guides: params.u32("guides")?,
+                color: params.u32("color")?.to_be_bytes(),
+                line_width: params.u32("line_width")?,
+                safe_margin: params.f32("safe_margin")?,
+                ..GuideSpec::default()
+            };
+            crate::img_draw_guides(handle(), &spec)
+        }
+        "burn_annotations" => {
+            let regions = match params.optional("rects").and_then(Value::as_array) {
+                Some(r) => r,
+                None => return Err(params.error("rects")),
+            };
+            let mut rects = Vec::with_capacity(regions.len());
+            for region in regions {
+                let coords: Option<Option<Vec<_>>> =
+                    region.as_array().map(|c| c.iter().map(as_u32).collect());
+                match coords.flatten().as_deref() {
+                    Some(&[x, y, width, height]) => rects.push(Rect {
+                        x,
+                        y,
+                        width,
+                        height,
+                    }),
+                    _ => return Err(params.error("rects")),
+                }
+            }
+
+            let color = image::Rgba(params.u32("color")?.to_be_bytes());
+            let line_width = params.u32("line_width")?;
+            if line_width == 0 {
+                return Err(params.error("line_width"));
+            }
+
+            let data = handle().as_data();
+            data.checkpoint(edit.clone());
+            for rect in &rects {
+                rect.outline(&mut data.image, line_width, color);
+            }
+            ImageError::NoError
+        }
+        "blur" => {
+            // Blur creates new image, but it is applied in place to render edit stack.
+            let sigma = params.f32("sigma")?;
+            let data = handle().as_data();
+            let image =
+                DynamicImage::ImageRgba8(crate::parallel::blur_dynamic(&data.image, sigma)?);
+            data.checkpoint(edit.clone());
+            data.image = image.into();
+            ImageError::NoError
+        }
+        "convert" => {
+            // Conversion creates new image, but it is applied in place to render edit stack.
+            let color_type = crate::ColorType::try_from(params.u32("color_type")?)
+                .map_err(|_| params.error("color_type"))?;
+            let data = handle().as_data();
+            let image = match color_type.convert(&data.image) {
+                Some(i) => i,
+                None => return Err(params.error("color_type")),
+            };
+            data.checkpoint(edit.clone());
+            data.image = image.into();
+            ImageError::NoError
+        }
+        "mirror" => crate::img_mirror2(handle()),
+        "swap_channels" => {
+            let mapping: Option<Option<Vec<_>>> = params
+                .optional("mapping")
+                .and_then(Value::as_array)
+                .map(|m| {
+                    m.iter()
+                        .map(|c| as_u32(c).and_then(|c| u8::try_from(c).ok()))
+                        .collect()
+                });
+            let mapping = match mapping.flatten().as_deref() {
+                Some(&[r, g, b, a]) => [r, g, b, a],
+                _ => return Err(params.error("mapping")),
+            };
+            crate::img_swap_channels(handle(), &mapping)
+        }
+        "set_pixel" => {
+            let (x, y) = (params.u32("x")?, params.u32("y")?);
+            crate::img_set_pixel(handle(), x, y, params.u32("rgba")?)
+        }
+        "pipeline" => {
+            let steps = match params.optional("steps").and_then(Value::as_array) {
+                Some(s) => s,
+                None => return Err(params.error("steps")),
+            };
+            let steps: Result<Vec<_>, _> = steps.iter().map(pipeline_step).collect();
+            let mut pipeline = Pipeline::new(steps?);
+            match pipeline.apply(handle().as_data()) {
+                Ok(_) => ImageError::NoError,
+                Err(e) => e,
+            }
+        }
+        "mesh_warp" => {
+            let (cols, rows) = (params.u32("cols")?, params.u32("rows")?);
+            let grid = |name: &str| {
+                let coords: Option<Option<Vec<_>>> = params
+                    .optional(name)
+                    .and_then(Value::as_array)
+                    .map(|c| c.iter().map(|v| v.as_f64().map(|v| v as f32)).collect());
+                let coords = coords.flatten().ok_or_else(|| params.error(name))?;
+                let points: Vec<_> = coords
+                    .chunks(2)
+                    .map(|p| Point { x: p[0], y: p[1] })
+                    .collect();
+                match (coords.len() % 2, points.len()) {
+                    (0, count) if count as u64 == cols as u64 * rows as u64 => Ok(points),
+                    _ => Err(params.error(name)),
+                }
+            };
+            let (src, dst) = (grid("src")?, grid("dst")?);
+            crate::img_mesh_warp(handle(), src.as_ptr(), dst.as_ptr(), cols, rows)
+        }
+        "draw_line" => {
+            let (x0, y0) = (params.f32("x0")?, params.f32("y0")?);
+            let (x1, y1) = (params.f32("x1")?, params.f32("y1")?);
+            let (thickness, color) = (params.f32("thickness")?, params.u32("color")?);
+            crate::img_draw_line(handle(), x0, y0, x1, y1, thickness, color)
+        }
+        "draw_rect" => {
+            let (x, y) = (params.f32("x")?, params.f32("y")?);
+            let (width, height) = (params.f32("width")?, params.f32("height")?);
+            let (thickness, color) = (params.f32("thickness")?, params.u32("color")?);
+            let fill = params.bool("fill")?;
+            crate::img_draw_rect(handle(), x, y, width, height, thickness, color, fill)
+        }
+        "draw_circle" => {
+            let (cx, cy, radius) = (params.f32("cx")?, params.f32("cy")?, params.f32("radius")?);
+            let (thickness, color) = (params.f32("thickness")?, params.u32("color")?);
+            let fill = params.bool("fill")?;
+            crate::img_draw_circle(handle(), cx, cy, radius, thickness, color, fill)
+        }
+        "draw_polygon" => {
+            let coords: Option<Option<Vec<_>>> = params
+                .optional("points")
+                .and_then(Value::as_array)
+                .map(|c| c.iter().map(|v| v.as_f64().map(|v| v as f32)).collect());
+            let coords = match coords.flatten() {
+                Some(coords) if coords.len() % 2 == 0 => coords,
+                _ => return Err(params.error("points")),
+            };
+            let points: Vec<_> = coords
+                .chunks(2)
+                .map(|p| Point { x: p[0], y: p[1] })
+                .collect();
+            let (thickness, color) = (params.f32("thickness")?, params.u32("color")?);
+            let fill = params.bool("fill")?;
+            crate::img_draw_polygon(
+                handle(),
+                points.as_ptr(),
+                points.len(),
+                thickness,
+                color,
+                fill,
+            )
+        }
+        "round_corners" => crate::img_round_corners(handle(), params.f32("radius")?),
+        "circle_mask" => crate::img_circle_mask(handle()),
+        _ => return Err(invalid(format!("Unknown edit: {}", op))),
+    };
+
+    match result {
+        ImageError::NoError => Ok(()),
+        err => Err(err),
+    }
+}
+
+/// Parses one of `steps` of `pipeline` edit, which are described as edits, e.g.
+/// `{"op": "resize", "width": 64, "height": 64}`.
+fn pipeline_step(step: &Value) -> Result<Step, ImageError> {
+    let params = match step.as_object() {
+        Some(p) => Params(p),
+        None => return Err(invalid(format!("Pipeline step isn't object: {}", step))),
+    };
+    let step = match params.str("op")? {
+        "crop" => Step::Crop(Rect {
+            x: params.u32("x")?,
+            y: params.u32("y")?,
+            width: params.u32("width")?,
+            height: params.u32("height")?,
+        }),
+        "resize" => Step::Resize(params.u32("width")?, params.u32("height")?),
+        "blur" => Step::Blur(params.f32("sigma")?),
+        "mirror" => Step::Mirror,
+        op => return Err(invalid(format!("Unknown pipeline step: {}", op))),
+    };
+    Ok(step)
+}
+
+/// Parameters of one edit.
+struct Params<'a>(&'a Map<String, Value>);
+
+impl<'a> Params<'a> {
+    /// Returns parameter `name` unless it is missing or null.
+    fn optional(&self, name: &str) -> Option<&'a Value> {
+        self.0.get(name).filter(|v| !v.is_null())
+    }
+
+    fn str(&self, name: &str) -> Result<&'a str, ImageError> {
+        let value = self.optional(name).and_then(Value::as_str);
+        value.ok_or_else(|| self.error(name))
+    }
+
+    fn c_string(&self, name: &str) -> Result<CString, ImageError> {
+        CString::new(self.str(name)?).map_err(|_| self.error(name))
+    }
+
+    fn u32(&self, name: &str) -> Result<u32, ImageError> {
+        let value = self.optional(name).and_then(as_u32);
+        value.ok_or_else(|| self.error(name))
+    }
+
+    fn f64(&self, name: &str) -> Result<f64, ImageError> {
+        let value = self.optional(name).and_then(Value::as_f64);
+        value.ok_or_else(|| self.error(name))
+    }
+
+    fn f32(&self, name: &str) -> Result<f32, ImageError> {
+        Ok(self.f64(name)? as f32)
+    }
+
+    fn bool(&self, name: &str) -> Result<bool, ImageError> {
+        let value = self.optional(name).and_then(Value::as_bool);
+        value.ok_or_else(|| self.error(name))
+    }
+
+    /// Records error of missing or invalid parameter `name`.
+    fn error(&self, name: &str) -> ImageError {
+        let op = self.0.get("op").and_then(Value::as_str).unwrap_or("?");
+        invalid(format!("Invalid parameter `{}` of edit `{}`", name, op))
+    }
+}
+
+/// Returns `value` if it is number fitting `u32`.
+fn as_u32(value: &Value) -> Option<u32> {
+    value.as_u64().and_then(|v| u32::try_from(v).ok())
+}
+
+/// Records `message` of invalid edit stack as the last error.
+fn invalid(message: String) -> ImageError {
+    crate::set_last_error(LastError::new(ImageError::Parameter, message));
+    ImageError::Parameter
+}
@@ -0,0 +1,488 @@
+use crate::{document, BlendMode, ImageError};
+use image::{DynamicImage, RgbaImage};
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+// Vector paths do the same floating point operations in the same order as scalar ones, so
+// results don't depend on instructions CPU supports.
+
+/// Converts `image` to RGBA with 8 bits per channel, as `DynamicImage::to_rgba8` does. RGB
+/// pixels are expanded by vector instructions where CPU supports them into buffer allocated by
+/// `try_alloc`.
+pub fn to_rgba8(image: &DynamicImage) -> Result<RgbaImage, ImageError> {
+    let raw = match image {
+        DynamicImage::ImageRgb8(buffer) => {
+            let (width, height) = buffer.dimensions();
+            crate::try_alloc(width as usize * height as usize * 4, 0)?
+        }
+        _ => Vec::new(),
+    };
+    Ok(to_rgba8_in(image, raw))
+}
+
+/// Converts `image` as `to_rgba8` does, expanding RGB pixels into `raw` of 4 bytes per pixel.
+/// `raw` is ignored for images of other color types.
+pub fn to_rgba8_in(image: &DynamicImage, mut raw: Vec<u8>) -> RgbaImage {
+    match image {
+        DynamicImage::ImageRgb8(buffer) => {
+            let (width, height) = buffer.dimensions();
+            raw.resize(width as usize * height as usize * 4, 0);
+            rgb_to_rgba(buffer.as_raw(), &mut raw);
+            RgbaImage::from_raw(width, height, raw).expect("buffer fits image")
+        }
+        image => image.to_rgba8(),
+    }
+}
+
+/// Expands RGB pixels of `src` to opaque RGBA pixels of `dst`.
+pub fn rgb_to_rgba(src: &[u8], dst: &mut [u8]) {
+    let pixels = (src.len() / 3).min(dst.len() / 4);
+    let (src, dst) = (&src[..pixels * 3], &mut dst[..pixels * 4]);
+    let done = fast_rgb_to_rgba(src, dst);
+    for (d, s) in dst[done * 4..]
+        .chunks_exact_mut(4)
+        .zip(src[done * 3..].chunks_exact(3))
+    {
+        d.copy_from_slice(&[s[0], s[1], s[2], u8::MAX]);
+    }
+}
+
+/// Converts 8-bit channels of `src` to `[0, 1]` floats of `dst`.
+pub fn unorm8_to_f32(src: &[u8], dst: &mut [f32]) {
+    let len = src.len().min(dst.len());
+    let (src, dst) = (&src[..len], &mut dst[..len]);
+    let done = fast_unorm8_to_f32(src, dst);
+    for (d, &s) in dst[done..].iter_mut().zip(&src[done..]) {
+        *d = s as f32 / u8::MAX as f32;
+    }
+}
+
+/// Composites pixels of `source` row, 4 channels each, with layer `opacity` over `backdrop`
+/// pixels with `mode`, as `document::composite` does.
+pub fn composite_row(backdrop: &mut [[f32; 4]], source: &[f32], opacity: f32, mode: BlendMode) {
+    let pixels = backdrop.len().min(source.len() / 4);
+    let (backdrop, source) = (&mut backdrop[..pixels], &source[..pixels * 4]);
+    let done = fast_composite_row(backdrop, source, opacity, mode);
+    let rest = backdrop[done..].iter_mut();
+    for (b, s) in rest.zip(source[done * 4..].chunks_exact(4)) {
+        *b = document::composite(*b, [s[0], s[1], s[2], s[3]], opacity, mode);
+    }
+}
+
+// Fast paths return number of pixels or channels they processed, the rest is left to scalar
+// code.
+
+#[cfg(target_arch = "x86_64")]
+fn fast_rgb_to_rgba(src: &[u8], dst: &mut [u8]) -> usize {
+    match is_x86_feature_detected!("ssse3") {
+        true => unsafe { rgb_to_rgba_ssse3(src, dst) },
+        false => 0,
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn fast_rgb_to_rgba(src: &[u8], dst: &mut [u8]) -> usize {
+    unsafe { rgb_to_rgba_neon(src, dst) }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn fast_rgb_to_rgba(_: &[u8], _: &mut [u8]) -> usize {
+    0
+}
+
+#[cfg(target_arch = "x86_64")]
+fn fast_unorm8_to_f32(src: &[u8], dst: &mut [f32]) -> usize {
+    match is_x86_feature_detected!("avx2") {
+        true => unsafe { unorm8_to_f32_avx2(src, dst) },
+        false => unsafe { unorm8_to_f32_sse2(src, dst) },
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn fast_unorm8_to_f32(src: &[u8], dst: &mut [f32]) -> usize {
+    unsafe { unorm8_to_f32_neon(src, dst) }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn fast_unorm8_to_f32(_: &[u8], _: &mut [f32]) -> usize {
+    0
+}
+
+#[cfg(target_arch = "x86_64")]
+fn fast_composite_row(
+    backdrop: &mut [[f32; 4]],
+    source: &[f32],
+    opacity: f32,
+    mode: BlendMode,
+) -> usize {
+    let done = match is_x86_feature_detected!("avx") {
+        true => unsafe { composite_avx(backdrop, source, opacity, mode) },
+        false => 0,
+    };
+    let (backdrop, source) = (&mut backdrop[done..], &source[done * 4..]);
+    done + unsafe { composite_sse2(backdrop, source, opacity, mode) }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn fast_composite_row(
+    backdrop: &mut [[f32; 4]],
+    source: &[f32],
+    opacity: f32,
+    mode: BlendMode,
+) -> usize {
+    unsafe { composite_neon(backdrop, source, opacity, mode) }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn fast_composite_row(_: &mut [[f32; 4]], _: &[f32], _: f32, _: BlendMode) -> usize {
+    0
+}
+
+/// Expands 4 pixels at a time, reading 16 bytes for every 12 of them.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn rgb_to_rgba_ssse3(src: &[u8], dst: &mut [u8]) -> usize {
+    let shuffle = _mm_setr_epi8(0, 1, 2, -1, 3, 4, 5, -1, 6, 7, 8, -1, 9, 10, 11, -1);
+    let alpha = _mm_set1_epi32(0xFF00_0000u32 as i32);
+    let mut pixels = 0;
+    while pixels * 3 + 16 <= src.len() {
+        let rgb = _mm_loadu_si128(src.as_ptr().add(pixels * 3) as *const __m128i);
+        let rgba = _mm_or_si128(_mm_shuffle_epi8(rgb, shuffle), alpha);
+        _mm_storeu_si128(dst.as_mut_ptr().add(pixels * 4) as *mut __m128i, rgba);
+        pixels += 4;
+    }
+    pixels
+}
+
+/// Expands 16 pixels at a time by interleaving loads and stores.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn rgb_to_rgba_neon(src: &[u8], dst: &mut [u8]) -> usize {
+    let alpha = vdupq_n_u8(u8::MAX);
+    let mut pixels = 0;
+    while (pixels + 16) * 3 <= src.len() {
+        let rgb = vld3q_u8(src.as_ptr().add(pixels * 3));
+        let rgba = uint8x16x4_t(rgb.0, rgb.1, rgb.2, alpha);
+        vst4q_u8(dst.as_mut_ptr().add(pixels * 4), rgba);
+        pixels += 16;
+    }
+    pixels
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn unorm8_to_f32_sse2(src: &[u8], dst: &mut [f32]) -> usize {
+    let zero = _mm_setzero_si128();
+    let max = _mm_set1_ps(u8::MAX as f32);
+    let mut i = 0;
+    while i + 16 <= src.len() {
+        let bytes = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+        let words = [
+            _mm_unpacklo_epi8(bytes, zero),
+            _mm_unpackhi_epi8(bytes, zero),
+        ];
+        for (j, w) in words.iter().enumerate() {
+            let ints = [_mm_unpacklo_epi16(*w, zero), _mm_unpackhi_epi16(*w, zero)];
+            for (k, v) in ints.iter().enumerate() {
+                let floats = _mm_div_ps(_mm_cvtepi32_ps(*v), max);
+                _mm_storeu_ps(dst.as_mut_ptr().add(i + j * 8 + k * 4), floats);
+            }
+        }
+        i += 16;
+    }
+    i
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn unorm8_to_f32_avx2(src: &[u8], dst: &mut [f32]) -> usize {
+    let max = _mm256_set1_ps(u8::MAX as f32);
+    let mut i = 0;
+    while i + 8 <= src.len() {
+        let bytes = _mm_loadl_epi64(src.as_ptr().add(i) as *const __m128i);
+        let floats = _mm256_cvtepi32_ps(_mm256_cvtepu8_epi32(bytes));
+        _mm256_storeu_ps(dst.as_mut_ptr().add(i), _mm256_div_ps(floats, max));
+        i += 8;
+    }
+    i
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn unorm8_to_f32_neon(src: &[u8], dst: &mut [f32]) -> usize {
+    let max = vdupq_n_f32(u8::MAX as f32);
+    let mut i = 0;
+    while i + 16 <= src.len() {
+        let bytes = vld1q_u8(src.as_ptr().add(i));
+        let words = [vmovl_u8(vget_low_u8(bytes)), vmovl_high_u8(bytes)];
+        for (j, w) in words.iter().enumerate() {
+            let ints = [vmovl_u16(vget_low_u16(*w)), vmovl_high_u16(*w)];
+            for (k, v) in ints.iter().enumerate() {
+                let floats = vdivq_f32(vcvtq_f32_u32(*v), max);
+                vst1q_f32(dst.as_mut_ptr().add(i + j * 8 + k * 4), floats);
+            }
+        }
+        i += 16;
+    }
+    i
+}
+
+/// Composites one pixel per vector, its color channels and alpha in the same lanes.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn composite_sse2(
+    backdrop: &mut [[f32; 4]],
+    source: &[f32],
+    opacity: f32,
+    mode: BlendMode,
+) -> usize {
+    let one = _mm_set1_ps(1.0);
+    let zero = _mm_setzero_ps();
+    let opacity = _mm_set1_ps(opacity);
+    let colors = _mm_castsi128_ps(_mm_setr_epi32(-1, -1, -1, 0));
+    for (b, s) in backdrop.iter_mut().zip(source.chunks_exact(4)) {
+        let cb = _mm_loadu_ps(b.as_ptr());
+        let cs = _mm_loadu_ps(s.as_ptr());
+        let alpha_b = _mm_shuffle_ps(cb, cb, 0xFF);
+        let alpha_s = _mm_mul_ps(_mm_shuffle_ps(cs, cs, 0xFF), opacity);
+        let alpha = _mm_add_ps(alpha_s, _mm_mul_ps(alpha_b, _mm_sub_ps(one, alpha_s)));
+
+        let blended = blend_sse2(mode, cb, cs);
+        let mixed = _mm_add_ps(
+            _mm_mul_ps(_mm_sub_ps(one, alpha_b), cs),
+            _mm_mul_ps(alpha_b, blended),
+        );
+        let color = _mm_add_ps(
+            _mm_mul_ps(alpha_s, mixed),
+            _mm_mul_ps(_mm_mul_ps(_mm_sub_ps(one, alpha_s), alpha_b), cb),
+        );
+        // Operand order keeps NaN and signed zero as `f32::clamp` does.
+        let color = _mm_min_ps(one, _mm_max_ps(zero, _mm_div_ps(color, alpha)));
+        let result = _mm_or_ps(_mm_and_ps(colors, color), _mm_andnot_ps(colors, alpha));
+        let visible = _mm_cmpnle_ps(alpha, zero);
+        _mm_storeu_ps(b.as_mut_ptr(), _mm_and_ps(result, visible));
+    }
+    backdrop.len()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn blend_sse2(mode: BlendMode, b: __m128, s: __m128) -> __m128 {
+    let screen = |b, s| _mm_sub_ps(_mm_add_ps(b, s), _mm_mul_ps(b, s));
+    match mode {
+        BlendMode::Normal => s,
+        BlendMode::Multiply => _mm_mul_ps(b, s),
+        BlendMode::Screen => screen(b, s),
+        BlendMode::Overlay => {
+            let two = _mm_set1_ps(2.0);
+            let dark = _mm_cmple_ps(b, _mm_set1_ps(0.5));
+            let low = _mm_mul_ps(_mm_mul_ps(two, b), s);
+            let high = screen(_mm_sub_ps(_mm_mul_ps(two, b), _mm_set1_ps(1.0)), s);
+            _mm_or_ps(_mm_and_ps(dark, low), _mm_andnot_ps(dark, high))
+        }
+        BlendMode::Darken => _mm_min_ps(b, s),
+        BlendMode::Lighten => _mm_max_ps(b, s),
+        BlendMode::Add => _mm_min_ps(_mm_add_ps(b, s), _mm_set1_ps(1.0)),
+        BlendMode::Difference => _mm_andnot_ps(_mm_set1_ps(-0.0), _mm_sub_ps(b, s)),
+    }
+}
+
+/// Composites two pixels per vector, as `composite_sse2` does.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn composite_avx(
+    backdrop: &mut [[f32; 4]],
+    source: &[f32],
+    opacity: f32,
+    mode: BlendMode,
+) -> usize {
+    let one = _mm256_set1_ps(1.0);
+    let zero = _mm256_setzero_ps();
+    let opacity = _mm256_set1_ps(opacity);
+    let mut pixels = 0;
+    for (b, s) in backdrop.chunks_exact_mut(2).zip(source.chunks_exact(8)) {
+        let cb = _mm256_loadu_ps(b.as_ptr() as *const f32);
+        let cs = _mm256_loadu_ps(s.as_ptr());
+        let alpha_b = _mm256_permute_ps(cb, 0xFF);
+        let alpha_s = _mm256_mul_ps(_mm256_permute_ps(cs, 0xFF), opacity);
+        let alpha = _mm256_add_ps(alpha_s, _mm256_mul_ps(alpha_b, _mm256_sub_ps(one, alpha_s)));
+
+        let blended = blend_avx(mode, cb, cs);
+        let mixed = _mm256_add_ps(
+            _mm256_mul_ps(_mm256_sub_ps(one, alpha_b), cs),
+            _mm256_mul_ps(alpha_b, blended),
+        );
+        let color = _mm256_add_ps(
+            _mm256_mul_ps(alpha_s, mixed),
+            _mm256_mul_ps(_mm256_mul_ps(_mm256_sub_ps(one, alpha_s), alpha_b), cb),
+        );
+        let color = _mm256_min_ps(one, _mm256_max_ps(zero, _mm256_div_ps(color, alpha)));
+        let result = _mm256_blend_ps(color, alpha, 0x88);
+        let visible = _mm256_cmp_ps(alpha, zero, _CMP_NLE_UQ);
+        _mm256_storeu_ps(b.as_mut_ptr() as *mut f32, _mm256_and_ps(result, visible));
+        pixels += 2;
+    }
+    pixels
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn blend_avx(mode: BlendMode, b: __m256, s: __m256) -> __m256 {
+    let screen = |b, s| _mm256_sub_ps(_mm256_add_ps(b, s), _mm256_mul_ps(b, s));
+    match mode {
+        BlendMode::Normal => s,
+        BlendMode::Multiply => _mm256_mul_ps(b, s),
+        BlendMode::Screen => screen(b, s),
+        BlendMode::Overlay => {
+            let two = _mm256_set1_ps(2.0);
+            let dark = _mm256_cmp_ps(b, _mm256_set1_ps(0.5), _CMP_LE_OQ);
+            let low = _mm256_mul_ps(_mm256_mul_ps(two, b), s);
+            let high = screen(_mm256_sub_ps(_mm256_mul_ps(two, b), _mm256_set1_ps(1.0)), s);
+            _mm256_blendv_ps(high, low, dark)
+        }
+        BlendMode::Darken => _mm256_min_ps(b, s),
+        BlendMode::Lighten => _mm256_max_ps(b, s),
+        BlendMode::Add => _mm256_min_ps(_mm256_add_ps(b, s), _mm256_set1_ps(1.0)),
+        BlendMode::Difference => _mm256_andnot_ps(_mm256_set1_ps(-0.0), _mm256_sub_ps(b, s)),
+    }
+}
+
+/// Composites one pixel per vector, as `composite_sse2` does.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn composite_neon(
+    backdrop: &mut [[f32; 4]],
+    source: &[f32],
+    opacity: f32,
+    mode: BlendMode,
+) -> usize {
+    let one = vdupq_n_f32(1.0);
+    let zero = vdupq_n_f32(0.0);
+    for (b, s) in backdrop.iter_mut().zip(source.chunks_exact(4)) {
+        let cb = vld1q_f32(b.as_ptr());
+        let cs = vld1q_f32(s.as_ptr());
+        let alpha_b = vdupq_laneq_f32::<3>(cb);
+        let alpha_s = vmulq_n_f32(vdupq_laneq_f32::<3>(cs), opacity);
+        let alpha = vaddq_f32(alpha_s, vmulq_f32(alpha_b, vsubq_f32(one, alpha_s)));
+
+        let blended = blend_neon(mode, cb, cs);
+        let mixed = vaddq_f32(
+            vmulq_f32(vsubq_f32(one, alpha_b), cs),
+            vmulq_f32(alpha_b, blended),
+        );
+        let color = vaddq_f32(
+            vmulq_f32(alpha_s, mixed),
+            vmulq_f32(vmulq_f32(vsubq_f32(one, alpha_s), alpha_b), cb),
+        );
+        let color = vminq_f32(vmaxq_f32(vdivq_f32(color, alpha), zero), one);
+        let result = vsetq_lane_f32::<3>(vgetq_lane_f32::<3>(alpha), color);
+        let hidden = vcleq_f32(alpha, zero);
+        vst1q_f32(b.as_mut_ptr(), vbslq_f32(hidden, zero, result));
+    }
+    backdrop.len()
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn blend_neon(mode: BlendMode, b: float32x4_t, s: float32x4_t) -> float32x4_t {
+    let screen = |b, s| vsubq_f32(vaddq_f32(b, s), vmulq_f32(b, s));
+    match mode {
+        BlendMode::Normal => s,
+        BlendMode::Multiply => vmulq_f32(b, s),
+        BlendMode::Screen => screen(b, s),
+        BlendMode::Overlay => {
+            let two = vdupq_n_f32(2.0);
+            let dark = vcleq_f32(b, vdupq_n_f32(0.5));
+            let low = vmulq_f32(vmulq_f32(two, b), s);
+            let high = screen(vsubq_f32(vmulq_f32(two, b), vdupq_n_f32(1.0)), s);
+            vbslq_f32(dark, low, high)
+        }
+        BlendMode::Darken => vminq_f32(b, s),
+        BlendMode::Lighten => vmaxq_f32(b, s),
+        BlendMode::Add => vminq_f32(vaddq_f32(b, s), vdupq_n_f32(1.0)),
+        BlendMode::Difference => vabsq_f32(vsubq_f32(b, s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    /// Lengths covering empty input, every remainder of vector widths and several full vectors.
+    const LENGTHS: std::ops::Range<usize> = 0..37;
+
+    /// Returns `len` pseudo-random bytes, the same for every run.
+    fn bytes(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rgb_to_rgba_matches_scalar() {
+        for pixels in LENGTHS {
+            let src = bytes(pixels * 3);
+            let mut dst = vec![0; pixels * 4];
+            rgb_to_rgba(&src, &mut dst);
+            let expected: Vec<u8> = src
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], u8::MAX])
+                .collect();
+            assert_eq!(dst, expected, "{} pixels", pixels);
+        }
+    }
+
+    #[test]
+    fn unorm8_to_f32_matches_scalar() {
+        for len in LENGTHS.chain([255, 256, 257]) {
+            let src = bytes(len);
+            let mut dst = vec![0.0; len];
+            unorm8_to_f32(&src, &mut dst);
+            let expected: Vec<f32> = src.iter().map(|&v| v as f32 / u8::MAX as f32).collect();
+            assert_eq!(dst, expected, "{} channels", len);
+        }
+    }
+
+    #[test]
+    fn composite_row_matches_scalar() {
+        let to_f32 = |v: &u8| *v as f32 / u8::MAX as f32;
+        for mode in (0..).map_while(|m| BlendMode::try_from(m).ok()) {
+            for pixels in LENGTHS {
+                let mut source: Vec<f32> = bytes(pixels * 4).iter().map(to_f32).collect();
+                let backdrop: Vec<f32> = bytes(pixels * 4 + 1)[1..].iter().map(to_f32).collect();
+                // Transparent and opaque pixels take special paths of compositing.
+                if pixels > 2 {
+                    source[3] = 0.0;
+                    source[7] = 1.0;
+                }
+                let backdrop: Vec<[f32; 4]> = backdrop
+                    .chunks_exact(4)
+                    .map(|p| [p[0], p[1], p[2], p[3]])
+                    .collect();
+
+                for opacity in [0.0, 0.4, 1.0] {
+                    let mut row = backdrop.clone();
+                    composite_row(&mut row, &source, opacity, mode);
+                    let expected: Vec<[f32; 4]> = backdrop
+                        .iter()
+                        .zip(source.chunks_exact(4))
+                        .map(|(&b, s)| {
+                            let s = [s[0], s[1], s[2], s[3]];
+                            document::composite(b, s, opacity, mode)
+                        })
+                        .collect();
+                    let message = format!("mode {}, {} pixels", mode as u32, pixels);
+                    assert_eq!(row, expected, "{}, opacity {}", message, opacity);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,531 @@
+use crate::parallel::{self, Window};
+use crate::pipeline::{self, Pipeline, Step};
+use crate::{budget, progress, simd, ImageError, LastError, Rect};
+use image::{ColorType, DynamicImage, ImageBuffer, Primitive};
+use num_traits::NumCast;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, Write};
+use std::path::Path;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::{colortype, TiffEncoder, TiffValue};
+use tiff::TiffError;
+
+/// Receiver of rows of image streamed top to bottom.
+type Emit<'a, S> = dyn FnMut(&[S]) -> Result<(), ImageError> + 'a;
+
+/// Source of rows of image passing all of them to its argument.
+type Rows<'a, S> = dyn FnMut(&mut Emit<S>) -> Result<(), ImageError> + 'a;
+
+/// Step of pipeline applied to rows of image one at a time.
+trait Stage<S> {
+    /// Takes the next row of image and passes rows of result it completes to `emit`.
+    fn push(&mut self, row: &[S], emit: &mut Emit<S>) -> Result<(), ImageError>;
+}
+
+/// Passes `row` through `stages` in order, the last of them emitting rows to `sink`.
+fn feed<S>(
+    stages: &mut [Box<dyn Stage<S>>],
+    row: &[S],
+    sink: &mut Emit<S>,
+) -> Result<(), ImageError> {
+    match stages.split_first_mut() {
+        Some((stage, rest)) => stage.push(row, &mut |r| feed(rest, r, sink)),
+        None => sink(row),
+    }
+}
+
+struct Crop {
+    rect: Rect,
+    channels: usize,
+    /// Index of the next row.
+    y: u32,
+}
+
+impl<S> Stage<S> for Crop {
+    fn push(&mut self, row: &[S], emit: &mut Emit<S>) -> Result<(), ImageError> {
+        let y = self.y;
+        self.y += 1;
+        if y < self.rect.y || y - self.rect.y >= self.rect.height {
+            return Ok(());
+        }
+        let start = self.rect.x as usize * self.channels;
+        emit(&row[start..start + self.rect.width as usize * self.channels])
+    }
+}
+
+struct Mirror<S> {
+    channels: usize,
+    row: Vec<S>,
+}
+
+impl<S: Copy> Stage<S> for Mirror<S> {
+    fn push(&mut self, row: &[S], emit: &mut Emit<S>) -> Result<(), ImageError> {
+        self.row.clear();
+        self.row.extend_from_slice(row);
+        pipeline::mirror(&mut self.row, row.len() / self.channels, self.channels);
+        emit(&self.row)
+    }
+}
+
+/// Separable resampling of `parallel` keeping only input rows the next output rows need, so
+/// results are the same.
+struct Resample<S> {
+    channels: usize,
+    /// Windows of input rows of every output row.
+    columns: Vec<Window>,
+    /// Windows of input pixels of every output pixel of row.
+    rows: Vec<Window>,
+    /// Input rows from `first` on, not yet used by every output row needing them.
+    kept: VecDeque<Vec<S>>,
+    first: usize,
+    /// Index of the next input row.
+    received: usize,
+    /// Index of the next output row.
+    next: usize,
+    /// Rows dropped from `kept`, reused for the next input rows.
+    spare: Vec<Vec<S>>,
+    sums: Vec<f32>,
+    vertical: Vec<S>,
+    output: Vec<S>,
+}
+
+impl<S: Primitive> Resample<S> {
+    fn new(
+        (width, height): (u32, u32),
+        (new_width, new_height): (u32, u32),
+        channels: usize,
+        support: f32,
+        kernel: &dyn Fn(f32) -> f32,
+    ) -> Self {
+        Self {
+            channels,
+            columns: parallel::windows(height, new_height, support, kernel),
+            rows: parallel::windows(width, new_width, support, kernel),
+            kept: VecDeque::new(),
+            first: 0,
+            received: 0,
+            next: 0,
+            spare: Vec::new(),
+            sums: Vec::new(),
+            vertical: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Computes output row `self.next` from kept input rows.
+    fn resample(&mut self) {
+        let max: f32 = NumCast::from(S::max_value()).unwrap_or(f32::MAX);
+        let to_subpixel = |value: f32| -> S {
+            NumCast::from(value.clamp(0.0, max).round()).unwrap_or_else(S::max_value)
+        };
+
+        let window = &self.columns[self.next];
+        let row_len = self.kept[0].len();
+        self.sums.clear();
+        self.sums.resize(row_len, 0.0);
+        for (i, w) in window.weights.iter().enumerate() {
+            let row = &self.kept[window.start + i - self.first];
+            for (sum, v) in self.sums.iter_mut().zip(row) {
+                *sum += w * NumCast::from(*v).unwrap_or(0.0);
+            }
+        }
+        self.vertical.clear();
+        self.vertical
+            .extend(self.sums.iter().map(|s| to_subpixel(*s)));
+
+        let channels = self.channels;
+        self.output.clear();
+        for window in &self.rows {
+            for c in 0..channels {
+                let mut sum = 0.0;
+                for (i, w) in window.weights.iter().enumerate() {
+                    let v: f32 = NumCast::from(self.vertical[(window.start + i) * channels + c])
+                        .unwrap_or(0.0);
+                    sum += w * v;
+                }
+                self.output.push(to_subpixel(sum));
+            }
+        }
+    }
+}
+
+impl<S: Primitive> Stage<S> for Resample<S> {
+    fn push(&mut self, row: &[S], emit: &mut Emit<S>) -> Result<(), ImageError> {
+        let y = self.received;
+        self.received += 1;
+        match self.columns.get(self.next) {
+            Some(window) if y >= window.start => {}
+            _ => return Ok(()),
+        }
+        if self.kept.is_empty() {
+            self.first = y;
+        }
+        let mut kept = match self.spare.pop() {
+            Some(kept) => kept,
+            None => {
+                crate::check_alloc(std::mem::size_of_val(row) as u64)?;
+                Vec::new()
+            }
+        };
+        kept.clear();
+        kept.extend_from_slice(row);
+        self.kept.push_back(kept);
+
+        while let Some(window) = self.columns.get(self.next) {
+            if window.start + window.weights.len() > self.received {
+                break;
+            }
+            self.resample();
+            emit(&self.output)?;
+            self.next += 1;
+            let start = self.columns.get(self.next).map_or(usize::MAX, |w| w.start);
+            while self.first < start && !self.kept.is_empty() {
+                self.spare.extend(self.kept.pop_front());
+                self.first += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns stages applying `steps` to rows of image of `size` with `channels` subpixels per
+/// pixel.
+fn stages<S: Primitive + 'static>(
+    steps: &[Step],
+    (mut width, mut height): (u32, u32),
+    channels: usize,
+) -> Vec<Box<dyn Stage<S>>> {
+    let mut stages: Vec<Box<dyn Stage<S>>> = Vec::new();
+    for step in steps {
+        match *step {
+            Step::Crop(rect) => {
+                stages.push(Box::new(Crop {
+                    rect,
+                    channels,
+                    y: 0,
+                }));
+                width = rect.width;
+                height = rect.height;
+            }
+            Step::Resize(new_width, new_height) => {
+                let (size, new_size) = ((width, height), (new_width, new_height));
+                let resample = Resample::new(size, new_size, channels, 3.0, &parallel::lanczos3);
+                stages.push(Box::new(resample));
+                width = new_width;
+                height = new_height;
+            }
+            Step::Blur(sigma) => {
+                let (support, kernel) = parallel::gaussian(sigma);
+                let size = (width, height);
+                stages.push(Box::new(Resample::new(
+                    size, size, channels, support, &kernel,
+                )));
+            }
+            Step::Mirror => stages.push(Box::new(Mirror {
+                channels,
+                row: Vec::new(),
+            })),
+        }
+    }
+    stages
+}
+
+/// Subpixel of TIFF images streamed by pipeline.
+trait Sample: Primitive + 'static {
+    /// Takes subpixels of decoded strip.
+    fn from_strip(strip: DecodingResult) -> Option<Vec<Self>>;
+
+    /// Returns image of `width` x `1` pixels of `color` made of `row`.
+    fn row_image(row: &[Self], width: u32, color: ColorType) -> Option<DynamicImage>;
+
+    /// Writes TIFF image of `size` with `color` whose rows `rows` passes to its argument.
+    fn encode<W: Write + Seek>(
+        encoder: &mut TiffEncoder<W>,
+        size: (u32, u32),
+        color: ColorType,
+        rows: &mut Rows<Self>,
+    ) -> Result<(), ImageError>;
+}
+
+impl Sample for u8 {
+    fn from_strip(strip: DecodingResult) -> Option<Vec<Self>> {
+        match strip {
+            DecodingResult::U8(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    fn row_image(row: &[Self], width: u32, color: ColorType) -> Option<DynamicImage> {
+        let row = row.to_vec();
+        Some(match color {
+            ColorType::L8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, 1, row)?),
+            ColorType::Rgb8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, 1, row)?),
+            ColorType::Rgba8 => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, 1, row)?),
+            _ => return None,
+        })
+    }
+
+    fn encode<W: Write + Seek>(
+        encoder: &mut TiffEncoder<W>,
+        size: (u32, u32),
+        color: ColorType,
+        rows: &mut Rows<Self>,
+    ) -> Result<(), ImageError> {
+        match color {
+            ColorType::L8 => write_strips::<colortype::Gray8, W>(encoder, size, rows),
+            ColorType::Rgb8 => write_strips::<colortype::RGB8, W>(encoder, size, rows),
+            ColorType::Rgba8 => write_strips::<colortype::RGBA8, W>(encoder, size, rows),
+            _ => Err(ImageError::Unsupported),
+        }
+    }
+}
+
+impl Sample for u16 {
+    fn from_strip(strip: DecodingResult) -> Option<Vec<Self>> {
+        match strip {
+            DecodingResult::U16(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    fn row_image(row: &[Self], width: u32, color: ColorType) -> Option<DynamicImage> {
+        let row = row.to_vec();
+        Some(match color {
+            ColorType::L16 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, 1, row)?),
+            ColorType::Rgb16 => DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, 1, row)?),
+            ColorType::Rgba16 => DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, 1, row)?),
+            _ => return None,
+        })
+    }
+
+    fn encode<W: Write + Seek>(
+        encoder: &mut TiffEncoder<W>,
+        size: (u32, u32),
+        color: ColorType,
+        rows: &mut Rows<Self>,
+    ) -> Result<(), ImageError> {
+        match color {
+            ColorType::L16 => write_strips::<colortype::Gray16, W>(encoder, size, rows),
+            ColorType::Rgb16 => write_strips::<colortype::RGB16, W>(encoder, size, rows),
+            ColorType::Rgba16 => write_strips::<colortype::RGBA16, W>(encoder, size, rows),
+            _ => Err(ImageError::Unsupported),
+        }
+    }
+}
+
+/// Writes image of `C` color type and `width` x `height` pixels, collecting rows `rows` passes
+/// to its argument into strips.
+fn write_strips<C, W>(
+    encoder: &mut TiffEncoder<W>,
+    (width, height): (u32, u32),
+    rows: &mut Rows<C::Inner>,
+) -> Result<(), ImageError>
+where
+    C: colortype::ColorType,
+    C::Inner: Copy,
+    [C::Inner]: TiffValue,
+    W: Write + Seek,
+{
+    let mut image = encoder
+        .new_image::<C>(width, height)
+        .map_err(|e| tiff_error(e, ImageError::Encoding))?;
+    let mut strip = Vec::new();
+    rows(&mut |row| {
+        strip.extend_from_slice(row);
+        if strip.len() as u64 == image.next_strip_sample_count() {
+            image
+                .write_strip(&strip)
+                .map_err(|e| tiff_error(e, ImageError::Encoding))?;
+            strip.clear();
+        }
+        Ok(())
+    })?;
+    if image.next_strip_sample_count() != 0 {
+        return Err(ImageError::Internal);
+    }
+    image
+        .finish()
+        .map_err(|e| tiff_error(e, ImageError::Encoding))
+}
+
+/// Converts error of TIFF codec, reporting malformed files as `kind`.
+fn tiff_error(e: TiffError, kind: ImageError) -> ImageError {
+    match e {
+        TiffError::IoError(e) => crate::io_error(e),
+        TiffError::UnsupportedError(_) => ImageError::Unsupported,
+        TiffError::LimitsExceeded => ImageError::LimitExceeded,
+        _ => kind,
+    }
+}
+
+/// Passes rows of image decoded by `decoder` strip after strip to `f`, reporting progress after
+/// every strip.
+fn read_rows<S: Sample>(
+    decoder: &mut Decoder<BufReader<File>>,
+    row_len: usize,
+    f: &mut Emit<S>,
+) -> Result<(), ImageError> {
+    let count = decoder
+        .strip_count()
+        .map_err(|e| tiff_error(e, ImageError::Decoding))?;
+    for i in 0..count {
+        let strip = decoder
+            .read_strip()
+            .map_err(|e| tiff_error(e, ImageError::Decoding))?;
+        let strip = S::from_strip(strip).ok_or(ImageError::Decoding)?;
+        for row in strip.chunks_exact(row_len) {
+            f(row)?;
+        }
+        budget::check_time()?;
+        let done = (i + 1) as f32 / count as f32;
+        if progress::current().is_some_and(|s| !s.report(done)) {
+            return Err(ImageError::Cancelled);
+        }
+    }
+    Ok(())
+}
+
+/// Applies steps of `pipeline` to image of `size` with `color` decoded by `decoder`, writing
+/// result to `encoder` as soon as its rows are ready.
+fn stream<S: Sample, W: Write + Seek>(
+    pipeline: &Pipeline,
+    decoder: &mut Decoder<BufReader<File>>,
+    (size, color): ((u32, u32), ColorType),
+    encoder: &mut TiffEncoder<W>,
+) -> Result<(), ImageError> {
+    let steps = pipeline.steps();
+    let (output, output_color) = pipeline.check(size, color, false)?;
+    let channels = color.channel_count() as usize;
+    let row_len = size.0 as usize * channels;
+    let blur = steps.iter().position(|s| matches!(s, Step::Blur(_)));
+    match blur {
+        // Image is converted to RGBA before the first blur, as in `Pipeline::execute`.
+        Some(blur) if color != ColorType::Rgba8 => {
+            let (before, after) = steps.split_at(blur);
+            let converted = Pipeline::new(before.to_vec()).check(size, color, false)?.0;
+            let mut before = stages::<S>(before, size, channels);
+            let mut after = stages::<u8>(after, converted, 4);
+            u8::encode(encoder, output, output_color, &mut |sink| {
+                read_rows::<S>(decoder, row_len, &mut |row| {
+                    feed(&mut before, row, &mut |row| {
+                        let image = S::row_image(row, converted.0, color);
+                        let rgba = simd::to_rgba8(&image.ok_or(ImageError::Internal)?)?;
+                        feed(&mut after, rgba.as_raw(), sink)
+                    })
+                })
+            })
+        }
+        _ => {
+            let mut stages = stages::<S>(steps, size, channels);
+            S::encode(encoder, output, output_color, &mut |sink| {
+                read_rows::<S>(decoder, row_len, &mut |row| feed(&mut stages, row, sink))
+            })
+        }
+    }
+}
+
+/// Applies steps of `pipeline` to TIFF image at `src` and saves result as TIFF image at `dst`,
+/// holding only rows the next rows of result need instead of whole images. Source is read strip
+/// by strip and result is written strip by strip. On failure `dst` is removed.
+pub fn execute(pipeline: &Pipeline, src: &Path, dst: &Path) -> Result<(), ImageError> {
+    let same = match (std::fs::canonicalize(src), std::fs::canonicalize(dst)) {
+        (Ok(src), Ok(dst)) => src == dst,
+        _ => false,
+    };
+    if same {
+        let message = format!(
+            "Tiled pipeline can't overwrite its source {}",
+            src.display()
+        );
+        crate::set_last_error(LastError::new(ImageError::Parameter, message));
+        return Err(ImageError::Parameter);
+    }
+
+    let file = File::open(src).map_err(crate::io_error)?;
+    let mut decoder =
+        Decoder::new(BufReader::new(file)).map_err(|e| tiff_error(e, ImageError::Decoding))?;
+    let size = decoder
+        .dimensions()
+        .map_err(|e| tiff_error(e, ImageError::Decoding))?;
+    let tiff_color = decoder
+        .colortype()
+        .map_err(|e| tiff_error(e, ImageError::Decoding))?;
+    let color = match tiff_color {
+        tiff::ColorType::Gray(8) => ColorType::L8,
+        tiff::ColorType::RGB(8) => ColorType::Rgb8,
+        tiff::ColorType::RGBA(8) => ColorType::Rgba8,
+        tiff::ColorType::Gray(16) => ColorType::L16,
+        tiff::ColorType::RGB(16) => ColorType::Rgb16,
+        tiff::ColorType::RGBA(16) => ColorType::Rgba16,
+        other => {
+            let message = format!(
+                "Tiled pipeline supports 8 and 16 bit gray, RGB and RGBA TIFF images, not {:?}",
+                other
+            );
+            crate::set_last_error(LastError::new(ImageError::Unsupported, message));
+            return Err(ImageError::Unsupported);
+        }
+    };
+    // Steps are checked before destination is created.
+    pipeline.check(size, color, false)?;
+
+    let file = File::create(dst).map_err(crate::io_error)?;
+    let mut writer = BufWriter::new(file);
+    let result = TiffEncoder::new(&mut writer)
+        .map_err(|e| tiff_error(e, ImageError::Encoding))
+        .and_then(
+            |mut encoder| match color.bytes_per_pixel() / color.channel_count() {
+                1 => stream::<u8, _>(pipeline, &mut decoder, (size, color), &mut encoder),
+                _ => stream::<u16, _>(pipeline, &mut decoder, (size, color), &mut encoder),
+            },
+        )
+        .and_then(|_| writer.flush().map_err(crate::io_error));
+    if result.is_err() {
+        drop(writer);
+        let _ = std::fs::remove_file(dst);
+    }
+    result
+}
+
+/// Saves `image` as uncompressed CMYK TIFF image at `path` for print workflows, embedding `icc`
+/// profile if given. Colors are separated naively: black is the complement of the brightest
+/// channel and the rest of ink is split between cyan, magenta and yellow, with alpha composited
+/// over white paper. On failure `path` is removed.
+pub fn save_cmyk(image: &DynamicImage, icc: Option<&[u8]>, path: &Path) -> Result<(), ImageError> {
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    crate::check_alloc(width as u64 * height as u64 * 4)?;
+    let mut cmyk = crate::try_alloc(rgba.as_raw().len(), 0u8)?;
+    for (ink, pixel) in cmyk.chunks_exact_mut(4).zip(rgba.pixels()) {
+        let [r, g, b, a] = pixel.0;
+        // Transparent parts show paper, which is white.
+        let over_white = |c: u8| 255 - (255 - c as u32) * a as u32 / 255;
+        let [r, g, b] = [over_white(r), over_white(g), over_white(b)];
+        let max = r.max(g).max(b);
+        ink[3] = (255 - max) as u8;
+        // Black pixels are printed with black ink only.
+        for (ink, c) in ink.iter_mut().zip(&[r, g, b]) {
+            *ink = ((max - c) * 255).checked_div(max).unwrap_or(0) as u8;
+        }
+    }
+
+    let file = File::create(path).map_err(crate::io_error)?;
+    let mut writer = BufWriter::new(file);
+    let result = TiffEncoder::new(&mut writer)
+        .and_then(|mut encoder| {
+            let mut tiff = encoder.new_image::<colortype::CMYK8>(width, height)?;
+            if let Some(icc) = icc {
+                let tag = tiff::tags::Tag::Unknown(crate::meta::ICC_TAG);
+                tiff.encoder().write_tag(tag, icc)?;
+            }
+            tiff.write_data(&cmyk)
+        })
+        .map_err(|e| tiff_error(e, ImageError::Encoding))
+        .and_then(|_| writer.flush().map_err(crate::io_error));
+    if result.is_err() {
+        drop(writer);
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Number of the last operations remembered per thread.
+const TRACE_LEN: usize = 64;
+
+/// `false` on platforms without clock, e.g. `wasm32-unknown-unknown`, where traced operations
+/// have zero start time and duration.
+pub const HAS_CLOCK: bool = !cfg!(all(target_arch = "wasm32", target_os = "unknown"));
+
+/// State of remembered operation.
+enum Outcome {
+    Running,
+    Finished(Duration),
+    /// Operation was unwound by panic after running that long.
+    Unwound(Duration),
+    /// Panic caught by library with its message.
+    Panic(String),
+}
+
+/// Operation remembered in trace of thread.
+struct Entry {
+    id: u64,
+    name: &'static str,
+    /// Seconds since Unix epoch.
+    started: f64,
+    outcome: Outcome,
+}
+
+/// The last operations of one thread, the oldest first.
+struct Ring {
+    thread: String,
+    next_id: u64,
+    entries: VecDeque<Entry>,
+}
+
+/// Traces of threads which used library and are still running.
+static THREADS: Mutex<Vec<Weak<Mutex<Ring>>>> = Mutex::new(Vec::new());
+
+/// File trace is dumped to when library catches panic.
+static DUMP_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+thread_local! {
+    static RING: Arc<Mutex<Ring>> = register();
+}
+
+/// Creates trace of current thread and adds it to traces dumped by `dump`.
+fn register() -> Arc<Mutex<Ring>> {
+    let current = std::thread::current();
+    let thread = match current.name() {
+        Some(name) => format!("{:?} {}", current.id(), name),
+        None => format!("{:?}", current.id()),
+    };
+    let ring = Arc::new(Mutex::new(Ring {
+        thread,
+        next_id: 0,
+        entries: VecDeque::with_capacity(TRACE_LEN),
+    }));
+    let mut threads = THREADS.lock().unwrap_or_else(|e| e.into_inner());
+    threads.retain(|t| t.strong_count() != 0);
+    threads.push(Arc::downgrade(&ring));
+    ring
+}
+
+/// Runs `f` with trace of current thread. Does nothing while thread is being destroyed.
+fn with_ring<T, F: FnOnce(&mut Ring) -> T>(f: F) -> Option<T> {
+    RING.try_with(|r| f(&mut r.lock().unwrap_or_else(|e| e.into_inner())))
+        .ok()
+}
+
+/// Remembers `outcome` of operation `name` in trace of current thread, forgetting the oldest
+/// operation if trace is full. Returns id of remembered operation.
+fn push(name: &'static str, outcome: Outcome) -> Option<u64> {
+    let started = match HAS_CLOCK {
+        true => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |d| d.as_secs_f64()),
+        false => 0.0,
+    };
+    with_ring(|r| {
+        if r.entries.len() == TRACE_LEN {
+            r.entries.pop_front();
+        }
+        let id = r.next_id;
+        r.next_id += 1;
+        r.entries.push_back(Entry {
+            id,
+            name,
+            started,
+            outcome,
+        });
+        id
+    })
+}
+
+/// Sets file trace is dumped to when library catches panic. `None` disables dumping.
+pub fn set_dump_path(path: Option<PathBuf>) {
+    *DUMP_PATH.lock().unwrap_or_else(|e| e.into_inner()) = path;
+}
+
+/// Remembers panic caught by library with its `message` and dumps trace to file set by
+/// `set_dump_path`, if any.
+pub fn panicked(message: &str) {
+    push("panic", Outcome::Panic(message.to_string()));
+    let path = DUMP_PATH.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(path) = path {
+        if let Err(e) = dump(&path) {
+            crate::log::warn(&format!(
+                "Failed to dump trace to {}: {}",
+                path.display(),
+                e
+            ));
+        }
+    }
+}
+
+/// Writes the last operations of all running threads to file at `path`, one per line in order
+/// of start. Operations which didn't finish, e.g. running when host crashed, are marked so.
+pub fn dump(path: &Path) -> std::io::Result<()> {
+    let threads: Vec<_> = THREADS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    let mut lines = Vec::new();
+    for ring in threads {
+        let ring = ring.lock().unwrap_or_else(|e| e.into_inner());
+        for entry in &ring.entries {
+            let outcome = match &entry.outcome {
+                Outcome::Running => "unfinished".to_string(),
+                Outcome::Finished(d) => format!("took {:.3} ms", d.as_secs_f64() * 1000.0),
+                Outcome::Unwound(d) => format!("unwound after {:.3} ms", d.as_secs_f64() * 1000.0),
+                Outcome::Panic(message) => format!("caught: {}", message),
+            };
+            let line = format!(
+                "{:.3} [{}] {} {}",
+                entry.started, ring.thread, entry.name, outcome
+            );
+            lines.push((entry.started, line));
+        }
+    }
+    lines.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let version = env!("CARGO_PKG_VERSION");
+    writeln!(
+        file,
+        "image_sl {} trace of {} operations",
+        version,
+        lines.len()
+    )?;
+    for (_, line) in lines {
+        writeln!(file, "{}", line)?;
+    }
+    file.flush()
+}
+
+/// Traced operation, remembered in trace of thread and finished when dropped. With `tracing`
+/// feature it is also reported to subscribers and log callback.
+pub struct Span {
+    id: Option<u64>,
+    start: Option<Instant>,
+    #[cfg(feature = "tracing")]
+    name: &'static str,
+    #[cfg(feature = "tracing")]
+    _entered: tracing::span::EnteredSpan,
+}
+
+/// Starts span of operation `name`, e.g. `"decode"`, lasting until returned value is dropped.
+pub fn span(name: &'static str) -> Span {
+    Span {
+        id: push(name, Outcome::Running),
+        start: match HAS_CLOCK {
+            true => Some(Instant::now()),
+            false => None,
+        },
+        #[cfg(feature = "tracing")]
+        name,
+        #[cfg(feature = "tracing")]
+        _entered: tracing::info_span!("image_sl", op = name).entered(),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let elapsed = self.start.map_or(Duration::ZERO, |s| s.elapsed());
+        if let Some(id) = self.id {
+            let outcome = match std::thread::panicking() {
+                true => Outcome::Unwound(elapsed),
+                false => Outcome::Finished(elapsed),
+            };
+            // Entry is gone if more than `TRACE_LEN` operations were nested in this one.
+            with_ring(|r| {
+                if let Some(entry) = r.entries.iter_mut().rev().find(|e| e.id == id) {
+                    entry.outcome = outcome;
+                }
+            });
+        }
+        #[cfg(feature = "tracing")]
+        self.report(elapsed);
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Span {
+    fn report(&self, elapsed: Duration) {
+        tracing::debug!(
+            op = self.name,
+            elapsed_us = elapsed.as_micros() as u64,
+            "finished"
+        );
+
+        // Subscriber of host can't observe spans of shared library, so timings are also passed
+        // to log callback.
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        let message = format!("{} took {:.3} ms", self.name, millis);
+        crate::log::write(crate::LogLevel::Debug, &message);
+    }
+}